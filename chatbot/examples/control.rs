@@ -0,0 +1,40 @@
+//! Sends each line of stdin to the bot's control socket and prints the answers, e.g.
+//! `echo '{"command":"status"}' | cargo run --example control -- /run/user/1000/chatbot.sock`
+#[cfg(unix)]
+fn main() -> std::io::Result<()> {
+    use std::{
+        env,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        process,
+    };
+
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: control <socket>");
+        process::exit(2);
+    };
+    let mut stream = UnixStream::connect(&path).unwrap_or_else(|error| {
+        eprintln!("Could not connect to {}: {}", path, error);
+        process::exit(1);
+    });
+    let mut answers = BufReader::new(stream.try_clone()?);
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(stream, "{}", line)?;
+        let mut answer = String::new();
+        // the bot hung up, e.g. after an answer that was too long
+        if answers.read_line(&mut answer)? == 0 {
+            break;
+        }
+        print!("{}", answer);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The control socket is a Unix socket");
+}
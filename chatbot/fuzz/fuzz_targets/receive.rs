@@ -0,0 +1,16 @@
+//! Run with `cargo +nightly fuzz run receive` from the chatbot directory.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/config.rs"]
+mod config;
+#[allow(dead_code)]
+#[path = "../../src/connect/mod.rs"]
+mod connect;
+#[allow(dead_code)]
+#[path = "../../src/prometheus.rs"]
+mod prometheus;
+
+fuzz_target!(|data: &[u8]| connect::fuzz_receive(data));
@@ -0,0 +1,352 @@
+//! The HTTP API under `/api/channels/{channel}/`, for a dashboard changing the bot without chat.
+//! Requests need `Authorization: Bearer {http.api_token}`, bodies are JSON and so are the answers.
+//! The bot answers the calls between chat events, see [crate::core::ChatBot].
+use crate::connect::{ApiAction, ApiAnswer, ApiCall, ApiRequest, ChatBotEvent};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{
+    sync::{mpsc::RecvTimeoutError, Arc},
+    time::Duration,
+};
+use tiny_http::Method;
+
+// how long a request waits for the bot, which is busy with chat meanwhile
+const TIMEOUT: Duration = Duration::from_secs(5);
+const LEADERBOARD: usize = 10;
+
+/// The token the requests need and how a call gets to the bot.
+#[derive(Clone)]
+pub struct Api {
+    token: String,
+    forward: Arc<dyn Fn(ChatBotEvent) + Send + Sync>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewCommand {
+    name: String,
+    response: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Response {
+    response: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Text {
+    text: String,
+}
+
+fn show_value() -> String {
+    "$(value)".to_owned()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewCounter {
+    name: String,
+    #[serde(default = "show_value")]
+    response: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CounterValue {
+    value: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Timer {
+    // in seconds
+    interval: u64,
+    text: String,
+}
+
+pub fn error(status: u16, message: impl Into<String>) -> ApiAnswer {
+    ApiAnswer {
+        status,
+        body: json!({ "error": message.into() }),
+    }
+}
+
+impl Api {
+    pub fn new(token: String, forward: impl Fn(ChatBotEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            token,
+            forward: Arc::new(forward),
+        }
+    }
+
+    /// Hands the request to the bot and waits for its answer.
+    pub fn call(&self, request: ApiRequest) -> ApiAnswer {
+        let (call, answer) = ApiCall::new(request);
+        (self.forward)(ChatBotEvent::Api(call));
+        match answer.recv_timeout(TIMEOUT) {
+            Ok(answer) => answer,
+            Err(RecvTimeoutError::Timeout) => error(504, "the bot did not answer in time"),
+            Err(RecvTimeoutError::Disconnected) => error(503, "the bot is stopping"),
+        }
+    }
+
+    // the same time for every wrong token of the same length
+    fn authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = authorization.and_then(|header| header.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        let (given, expected) = (token.trim().as_bytes(), self.token.as_bytes());
+        given.len() == expected.len()
+            && given
+                .iter()
+                .zip(expected)
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, ApiAnswer> {
+    serde_json::from_str(body).map_err(|invalid| error(400, format!("invalid body: {}", invalid)))
+}
+
+// what the method and the path after the channel ask for
+fn action(
+    method: &Method,
+    route: &[&str],
+    query: &str,
+    body: &str,
+) -> Result<ApiAction, ApiAnswer> {
+    let not_found = || error(404, "there is no such endpoint");
+    let id = |id: &str| id.parse().map_err(|_| error(404, "quotes are numbered"));
+    let action = match (route, method) {
+        (["commands"], Method::Get) => ApiAction::Commands,
+        (["commands"], Method::Post) => {
+            let NewCommand { name, response } = parse(body)?;
+            ApiAction::AddCommand { name, response }
+        }
+        (["commands", name], Method::Put) => ApiAction::EditCommand {
+            name: name.to_lowercase(),
+            response: parse::<Response>(body)?.response,
+        },
+        (["commands", name], Method::Delete) => ApiAction::RemoveCommand {
+            name: name.to_lowercase(),
+        },
+        (["commands", name, "stats"], Method::Get) => ApiAction::CommandStats {
+            name: Some(name.to_lowercase()),
+        },
+        (["stats", "commands"], Method::Get) => ApiAction::CommandStats { name: None },
+        (["quotes"], Method::Get) => ApiAction::Quotes,
+        (["quotes"], Method::Post) => ApiAction::AddQuote {
+            text: parse::<Text>(body)?.text,
+        },
+        (["quotes", quote], Method::Put) => ApiAction::EditQuote {
+            id: id(quote)?,
+            text: parse::<Text>(body)?.text,
+        },
+        (["quotes", quote], Method::Delete) => ApiAction::RemoveQuote { id: id(quote)? },
+        (["counters"], Method::Get) => ApiAction::Counters,
+        (["counters"], Method::Post) => {
+            let NewCounter { name, response } = parse(body)?;
+            ApiAction::AddCounter { name, response }
+        }
+        (["counters", name], Method::Put) => ApiAction::SetCounter {
+            name: name.to_lowercase(),
+            value: parse::<CounterValue>(body)?.value,
+        },
+        (["counters", name], Method::Delete) => ApiAction::RemoveCounter {
+            name: name.to_lowercase(),
+        },
+        (["timers"], Method::Get) => ApiAction::Timers,
+        (["timers", name], Method::Put) => {
+            let Timer { interval, text } = parse(body)?;
+            ApiAction::SetTimer {
+                name: name.to_string(),
+                interval,
+                text,
+            }
+        }
+        (["timers", name], Method::Delete) => ApiAction::RemoveTimer {
+            name: name.to_lowercase(),
+        },
+        (["points"], Method::Get) => {
+            let count = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("count="))
+                .map(|count| count.parse().map_err(|_| error(400, "count is a number")))
+                .transpose()?;
+            ApiAction::Leaderboard {
+                count: count.unwrap_or(LEADERBOARD),
+            }
+        }
+        (["points", login], Method::Get) => ApiAction::Balance {
+            login: login.to_lowercase(),
+        },
+        (["messages"], Method::Post) => ApiAction::SendMessage {
+            text: parse::<Text>(body)?.text,
+        },
+        (
+            ["commands" | "quotes" | "counters" | "timers" | "points" | "messages" | "stats", ..],
+            _,
+        ) if route.len() <= 2 => return Err(error(405, "the method is not allowed here")),
+        _ => return Err(not_found()),
+    };
+    Ok(action)
+}
+
+/// Answers a request for a path under `/api/`, `call` asks the bot.
+pub fn handle(
+    api: &Api,
+    method: &Method,
+    url: &str,
+    authorization: Option<&str>,
+    body: &str,
+    call: impl FnOnce(ApiRequest) -> ApiAnswer,
+) -> ApiAnswer {
+    if !api.authorized(authorization) {
+        return error(401, "a valid bearer token is needed");
+    }
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    let (channel, route) = match segments.as_slice() {
+        ["", "api", "channels", channel, route @ ..] if !route.is_empty() => (channel, route),
+        _ => return error(404, "there is no such endpoint"),
+    };
+    match action(method, route, query, body) {
+        Ok(action) => call(ApiRequest {
+            channel: channel.trim_start_matches('#').to_lowercase(),
+            action,
+        }),
+        Err(answer) => answer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, core::ChatBot, storage::Storage};
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn api() -> Api {
+        Api::new(TOKEN.to_owned(), |_| {})
+    }
+
+    fn request(bot: &mut ChatBot, method: Method, url: &str, body: &str) -> ApiAnswer {
+        let authorization = format!("Bearer {}", TOKEN);
+        handle(
+            &api(),
+            &method,
+            url,
+            Some(&authorization),
+            body,
+            |request| {
+                let (call, answer) = ApiCall::new(request);
+                bot.handle_event(ChatBotEvent::Api(call));
+                answer.try_recv().unwrap()
+            },
+        )
+    }
+
+    #[test]
+    fn only_the_token_is_let_in() {
+        let unreachable = |_| panic!("the bot is never asked");
+        let url = "/api/channels/captaincallback/commands";
+        for authorization in [
+            None,
+            Some("0123456789abcdef"),
+            Some("Bearer 0123456789abcdeF"),
+            Some("Bearer 0123456789abcde"),
+            Some("Basic 0123456789abcdef"),
+        ] {
+            let answer = handle(&api(), &Method::Get, url, authorization, "", unreachable);
+            assert_eq!(answer.status, 401, "{:?}", authorization);
+        }
+        let answer = handle(
+            &api(),
+            &Method::Get,
+            url,
+            Some("Bearer 0123456789abcdef"),
+            "",
+            |request| {
+                assert_eq!(request.action, ApiAction::Commands);
+                ApiAnswer {
+                    status: 200,
+                    body: json!({}),
+                }
+            },
+        );
+        assert_eq!(answer.status, 200);
+    }
+
+    #[test]
+    fn bodies_and_routes_are_checked() {
+        let mut bot = ChatBot::load(&Config::default(), Storage::memory()).unwrap();
+        let commands = "/api/channels/captaincallback/commands";
+        let answer = request(&mut bot, Method::Post, commands, "{\"name\":\"hello\"}");
+        assert_eq!(answer.status, 400);
+        assert!(answer.body["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid body: missing field `response`"));
+        let body = "{\"name\":\"hello\",\"response\":\"Hi!\",\"level\":\"mod\"}";
+        let answer = request(&mut bot, Method::Post, commands, body);
+        assert_eq!(answer.status, 400);
+        let answer = request(&mut bot, Method::Patch, commands, "");
+        assert_eq!(answer.status, 405);
+        let answer = request(
+            &mut bot,
+            Method::Get,
+            "/api/channels/captaincallback/songs",
+            "",
+        );
+        assert_eq!(answer.status, 404);
+        let answer = request(
+            &mut bot,
+            Method::Get,
+            "/api/channels/captaincallback/quotes/x",
+            "",
+        );
+        assert_eq!(answer.status, 405);
+        let answer = request(
+            &mut bot,
+            Method::Delete,
+            "/api/channels/captaincallback/quotes/x",
+            "",
+        );
+        assert_eq!(answer.status, 404);
+    }
+
+    #[test]
+    fn changes_reach_the_bot() {
+        let mut bot = ChatBot::load(&Config::default(), Storage::memory()).unwrap();
+        let commands = "/api/channels/CaptainCallback/commands";
+        let body = "{\"name\":\"hello\",\"response\":\"Hi $(user)!\"}";
+        let answer = request(&mut bot, Method::Post, commands, body);
+        assert_eq!(answer.status, 201);
+        let answer = request(
+            &mut bot,
+            Method::Put,
+            "/api/channels/captaincallback/commands/Hello",
+            "{\"response\":\"Hello $(user)!\"}",
+        );
+        assert_eq!(answer.status, 200);
+        let answer = request(&mut bot, Method::Get, commands, "");
+        assert_eq!(answer.body["commands"][0]["response"], "Hello $(user)!");
+        let answer = request(
+            &mut bot,
+            Method::Get,
+            "/api/channels/captaincallback/points?count=3",
+            "",
+        );
+        assert_eq!(answer.body, json!({ "leaderboard": [] }));
+        let answer = request(
+            &mut bot,
+            Method::Get,
+            "/api/channels/captaincallback/commands/hello/stats",
+            "",
+        );
+        assert_eq!(answer.body, json!({ "name": "hello", "days": [] }));
+    }
+}
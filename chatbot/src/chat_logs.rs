@@ -0,0 +1,484 @@
+//! Chat kept in a file for each channel and day, e.g. `captaincallback-2026-10-14.log`.
+//!
+//! A thread of its own writes the files, so a slow disk never holds up the bot. When it
+//! falls behind, the lines that don't fit into the queue are dropped and counted.
+use crate::{
+    config::{ChatLogFormat, ChatLogsConfig},
+    connect::{to_json, Badge, ChatBotEvent, Command},
+    core::{timestamp, Date},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// lines waiting for the writer, more are dropped
+const QUEUE: usize = 4096;
+
+#[derive(Debug)]
+struct Entry {
+    channel: String,
+    time: SystemTime,
+    line: String,
+}
+
+/// Hands the chat to the writer thread.
+#[derive(Debug)]
+pub struct ChatLogs {
+    format: ChatLogFormat,
+    sender: SyncSender<Entry>,
+    dropped: Arc<AtomicU64>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl ChatLogs {
+    pub fn new(config: &ChatLogsConfig) -> Self {
+        let (mut logs, receiver) = Self::queue(config.format, QUEUE);
+        let mut files = Rotation::new(config);
+        logs.writer = Some(thread::spawn(move || files.run(receiver)));
+        logs
+    }
+
+    // without a writer until one takes the entries
+    fn queue(format: ChatLogFormat, queue: usize) -> (Self, Receiver<Entry>) {
+        let (sender, receiver) = mpsc::sync_channel(queue);
+        let logs = Self {
+            format,
+            sender,
+            dropped: Arc::default(),
+            writer: None,
+        };
+        (logs, receiver)
+    }
+
+    /// Queues the event if it belongs in the chat logs, without waiting for the disk.
+    pub fn log(&self, event: &ChatBotEvent) {
+        let time = SystemTime::now();
+        let Some((channel, line)) = format_event(event, self.format, time) else {
+            return;
+        };
+        let entry = Entry {
+            channel,
+            time,
+            line,
+        };
+        match self.sender.try_send(entry) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// The lines dropped so far because the writer fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Waits until the queued lines are written, e.g. before the bot stops.
+    pub fn finish(self) {
+        let dropped = self.dropped();
+        drop(self.sender);
+        if self.writer.is_some_and(|writer| writer.join().is_err()) {
+            tracing::error!("the chat logs stopped early");
+        }
+        if dropped > 0 {
+            tracing::warn!(dropped, "lines were dropped from the chat logs");
+        }
+    }
+}
+
+// the file of a channel written to
+#[derive(Debug)]
+struct Open {
+    date: Date,
+    index: u32,
+    size: u64,
+    file: BufWriter<File>,
+}
+
+/// The files of each channel, by day and by size within the day.
+#[derive(Debug)]
+struct Rotation {
+    directory: PathBuf,
+    extension: &'static str,
+    max_size: u64,
+    keep_days: u64,
+    open: HashMap<String, Open>,
+    // the day old files were deleted last
+    pruned: Option<Date>,
+}
+
+impl Rotation {
+    fn new(config: &ChatLogsConfig) -> Self {
+        Self {
+            directory: config.directory.clone(),
+            extension: match config.format {
+                ChatLogFormat::Text => "log",
+                ChatLogFormat::Jsonl => "jsonl",
+            },
+            max_size: config.max_kilobytes * 1024,
+            keep_days: config.keep_days,
+            open: HashMap::new(),
+            pruned: None,
+        }
+    }
+
+    // written in batches, whatever waits when the queue runs empty is flushed
+    fn run(&mut self, receiver: Receiver<Entry>) {
+        while let Ok(entry) = receiver.recv() {
+            self.write_logged(&entry);
+            while let Ok(entry) = receiver.try_recv() {
+                self.write_logged(&entry);
+            }
+            if let Err(error) = self.flush() {
+                tracing::warn!(%error, "could not write the chat logs");
+            }
+        }
+    }
+
+    fn write_logged(&mut self, entry: &Entry) {
+        if let Err(error) = self.write(&entry.channel, entry.time, &entry.line) {
+            tracing::warn!(channel = %entry.channel, %error, "could not write the chat log");
+        }
+    }
+
+    fn path(&self, channel: &str, date: Date, index: u32) -> PathBuf {
+        let name = match index {
+            0 => format!("{}-{}.{}", channel, date, self.extension),
+            index => format!("{}-{}.{}.{}", channel, date, index, self.extension),
+        };
+        self.directory.join(name)
+    }
+
+    // a line never goes alone into a file of its own, however long it is
+    fn is_full(&self, size: u64, line: u64) -> bool {
+        self.max_size > 0 && size > 0 && size + line > self.max_size
+    }
+
+    fn write(&mut self, channel: &str, time: SystemTime, line: &str) -> io::Result<()> {
+        let date = Date::of(time);
+        let length = line.len() as u64 + 1;
+        let next = match self.open.get(channel) {
+            Some(open) if open.date == date && !self.is_full(open.size, length) => None,
+            Some(open) if open.date == date => Some(open.index + 1),
+            _ => Some(0),
+        };
+        if let Some(index) = next {
+            if self.pruned != Some(date) {
+                self.pruned = Some(date);
+                self.prune(time);
+            }
+            if let Some(mut open) = self.open.remove(channel) {
+                open.file.flush()?;
+            }
+            let open = self.open_file(channel, date, index, length)?;
+            self.open.insert(channel.to_owned(), open);
+        }
+        let open = self.open.get_mut(channel).expect("opened above");
+        writeln!(open.file, "{}", line)?;
+        open.size += length;
+        Ok(())
+    }
+
+    // continues the day's last file written before a restart
+    fn open_file(&self, channel: &str, date: Date, mut index: u32, line: u64) -> io::Result<Open> {
+        fs::create_dir_all(&self.directory)?;
+        while self.path(channel, date, index + 1).exists() {
+            index += 1;
+        }
+        let mut size = fs::metadata(self.path(channel, date, index))
+            .map(|file| file.len())
+            .unwrap_or(0);
+        if self.is_full(size, line) {
+            index += 1;
+            size = 0;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(channel, date, index))?;
+        Ok(Open {
+            date,
+            index,
+            size,
+            file: BufWriter::new(file),
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for open in self.open.values_mut() {
+            open.file.flush()?;
+        }
+        Ok(())
+    }
+
+    // deletes the files of days before the last ones kept
+    fn prune(&self, now: SystemTime) {
+        if self.keep_days == 0 {
+            return;
+        }
+        let oldest = Date::of(now - Duration::from_secs((self.keep_days - 1) * 86400));
+        let Ok(entries) = fs::read_dir(&self.directory) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(date) = name.to_str().and_then(|name| self.date(name)) else {
+                continue;
+            };
+            if date < oldest {
+                if let Err(error) = fs::remove_file(entry.path()) {
+                    tracing::warn!(file = ?name, %error, "could not delete an old chat log");
+                }
+            }
+        }
+    }
+
+    // "carkhy-2026-10-14.log" or "carkhy-2026-10-14.2.log", None for other files
+    fn date(&self, name: &str) -> Option<Date> {
+        let stem = name.strip_suffix(self.extension)?.strip_suffix('.')?;
+        // the index follows the date
+        let stem = stem.split('.').next()?;
+        let written = stem.get(stem.len().checked_sub(10)?..)?;
+        let mut parts = written.split('-').map(|part| part.parse().ok());
+        let date = Date {
+            year: parts.next()??,
+            month: parts.next()??,
+            day: parts.next()??,
+        };
+        (date.to_string() == written).then_some(date)
+    }
+}
+
+// "[sub/27,vip] "
+fn badges(badges: &[Badge]) -> String {
+    let names: Vec<_> = badges
+        .iter()
+        .filter_map(|badge| match badge {
+            Badge::Broadcaster => Some("broadcaster".to_owned()),
+            Badge::Moderator => Some("mod".to_owned()),
+            Badge::Vip => Some("vip".to_owned()),
+            Badge::Subscriber { months, .. } => Some(format!("sub/{}", months)),
+            Badge::Bits { amount } => Some(format!("bits/{}", amount)),
+            Badge::Unknown(..) => None,
+        })
+        .collect();
+    match names.is_empty() {
+        true => String::new(),
+        false => format!("[{}] ", names.join(",")),
+    }
+}
+
+/// The channel and the line of the events kept in the chat logs, chat messages and what
+/// happened to them.
+fn format_event(
+    event: &ChatBotEvent,
+    format: ChatLogFormat,
+    now: SystemTime,
+) -> Option<(String, String)> {
+    let (channel, time, json, text) = match event {
+        ChatBotEvent::TextMessage(message) | ChatBotEvent::Command(Command { message, .. }) => {
+            let user = message.user.display_name();
+            let text = match message.is_action {
+                true => format!("* {} {}", user, message.text),
+                false => format!("{}: {}", user, message.text),
+            };
+            let text = format!("{}{}", badges(&message.user.badges), text);
+            let time = message.timestamp.unwrap_or(now);
+            let mut json = to_json(event)?;
+            // for the CSV export, the chat export has no bits
+            if let Some(bits) = message.bits {
+                json["bits"] = bits.into();
+            }
+            (&message.channel, time, json, text)
+        }
+        ChatBotEvent::UserNotice(notice) => {
+            let text = match &notice.text {
+                Some(text) => format!(
+                    "usernotice: {} {}: {}",
+                    notice.system_message,
+                    notice.user.display_name(),
+                    text
+                ),
+                None => format!("usernotice: {}", notice.system_message),
+            };
+            (&notice.channel, now, to_json(event)?, text)
+        }
+        ChatBotEvent::ClearChat(clear) => {
+            let text = match (&clear.target_user, clear.duration) {
+                (None, _) => "clearchat: the chat was cleared".to_owned(),
+                (Some(user), None) => format!("clearchat: {} was banned", user),
+                (Some(user), Some(duration)) => format!(
+                    "clearchat: {} was timed out for {}s",
+                    user,
+                    duration.as_secs()
+                ),
+            };
+            let json = json!({
+                "type": "clearchat",
+                "channel": clear.channel,
+                "user": clear.target_user,
+                "duration": clear.duration.map(|duration| duration.as_secs()),
+            });
+            (&clear.channel, now, json, text)
+        }
+        ChatBotEvent::ClearMessage(clear) => {
+            let text = format!(
+                "clearmsg: a message of {} was deleted: {}",
+                clear.login, clear.text
+            );
+            let json = json!({
+                "type": "clearmsg",
+                "channel": clear.channel,
+                "user": clear.login,
+                "text": clear.text,
+            });
+            (&clear.channel, now, json, text)
+        }
+        ChatBotEvent::Notice(notice) => {
+            let channel = notice.channel.as_ref()?;
+            let json = json!({
+                "type": "notice",
+                "channel": channel,
+                "text": notice.text,
+            });
+            (channel, now, json, format!("notice: {}", notice.text))
+        }
+        _ => return None,
+    };
+    let line = match format {
+        ChatLogFormat::Text => format!("[{}] {}", timestamp(time), text),
+        ChatLogFormat::Jsonl => with_time(json, time).to_string(),
+    };
+    Some((channel.clone(), line))
+}
+
+// the time twitch sent, else when the bot received it
+fn with_time(mut json: Value, time: SystemTime) -> Value {
+    if json["timestamp"].is_null() {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+        json["timestamp"] = millis.into();
+    }
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect::{InternalEvent, SubTier, TextMessage, UserInfo},
+        storage::testing::TempDir,
+    };
+    use std::path::Path;
+
+    fn message(text: &str) -> ChatBotEvent {
+        ChatBotEvent::TextMessage(TextMessage {
+            text: text.to_owned(),
+            channel: "carkhy".to_owned(),
+            user: UserInfo {
+                name: "tenaciousbyte".to_owned(),
+                badges: vec![
+                    Badge::Subscriber {
+                        months: 27,
+                        tier: SubTier::Tier1,
+                    },
+                    Badge::Vip,
+                ],
+                ..Default::default()
+            },
+            // 2026-10-14 23:59:59
+            timestamp: Some(UNIX_EPOCH + Duration::from_secs(1_792_022_399)),
+            ..Default::default()
+        })
+    }
+
+    fn names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn files_rotate_by_day_and_size() {
+        let directory = TempDir::new("chatbot-chat-logs");
+        let config = ChatLogsConfig {
+            directory: directory.to_path_buf(),
+            max_kilobytes: 1,
+            keep_days: 2,
+            ..Default::default()
+        };
+        let mut files = Rotation::new(&config);
+        let day = UNIX_EPOCH + Duration::from_secs(1_792_022_399);
+        let (_, line) = format_event(&message("hi"), ChatLogFormat::Text, day).unwrap();
+        assert_eq!(line, "[2026-10-14 23:59:59] [sub/27,vip] tenaciousbyte: hi");
+        // an old file, gone once the bot writes the days after
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("carkhy-2026-10-12.log"), "old\n").unwrap();
+        fs::write(directory.join("notes.txt"), "kept\n").unwrap();
+
+        files.write("carkhy", day, &"a".repeat(1000)).unwrap();
+        // a line never fits twice into a kilobyte
+        files.write("carkhy", day, &"b".repeat(1000)).unwrap();
+        files.write("carkhy", day, "c").unwrap();
+        files
+            .write("carkhy", day + Duration::from_secs(1), "the next day")
+            .unwrap();
+        files.flush().unwrap();
+        assert_eq!(
+            names(&directory),
+            [
+                "carkhy-2026-10-14.1.log",
+                "carkhy-2026-10-14.log",
+                "carkhy-2026-10-15.log",
+                "notes.txt",
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(directory.join("carkhy-2026-10-14.1.log")).unwrap(),
+            format!("{}\nc\n", "b".repeat(1000))
+        );
+
+        // a restart continues the day's last file
+        let mut restarted = Rotation::new(&config);
+        restarted.write("carkhy", day, "d").unwrap();
+        restarted.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("carkhy-2026-10-14.1.log")).unwrap(),
+            format!("{}\nc\nd\n", "b".repeat(1000))
+        );
+    }
+
+    #[test]
+    fn lines_are_dropped_and_counted_when_the_writer_falls_behind() {
+        let (logs, receiver) = ChatLogs::queue(ChatLogFormat::Jsonl, 2);
+        for text in ["one", "two", "three", "four"] {
+            logs.log(&message(text));
+        }
+        // not for the logs
+        logs.log(&ChatBotEvent::Internal(InternalEvent::TimerTick));
+        assert_eq!(logs.dropped(), 2);
+        let entry = receiver.try_recv().unwrap();
+        let json: Value = serde_json::from_str(&entry.line).unwrap();
+        assert_eq!(json["text"], "one");
+        assert_eq!(json["timestamp"], 1_792_022_399_000u64);
+        assert!(receiver.try_recv().is_ok() && receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! The subcommands of the binary, `run` when none is given. `--config <path>` goes with
+//! any of them.
+use crate::{
+    connect::{Identity, ReplayTiming},
+    core::Date,
+    csv_export::Range,
+};
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub const USAGE: &str = "\
+Usage: chatbot [--config <path>] [<command>]
+
+Commands:
+  run [--replay <log> [--original-timing]]  Chat, or answer a raw IRC log instead (default)
+  validate-config                           Check the config and print it, secrets redacted
+  send <channel> <message>                  Send one message and leave again
+  token validate|refresh [--broadcaster]    Check or refresh the stored access token
+  export [--channel <name>] [--since <date>] [--until <date>]
+                                            Write CSV files of the chat logs and stats
+  --example-config                          Print an example config with every key";
+
+/// What the binary is asked to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Replay {
+        path: String,
+        timing: ReplayTiming,
+    },
+    ValidateConfig,
+    Send {
+        channel: String,
+        message: String,
+    },
+    Token(TokenAction, Identity),
+    Export {
+        channel: Option<String>,
+        range: Range,
+    },
+    ExampleConfig,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenAction {
+    Validate,
+    Refresh,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    pub config: Option<PathBuf>,
+    pub command: Command,
+}
+
+/// Arguments that don't make a command, shown with the usage.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum UsageError {
+    #[error("{0} is no command")]
+    UnknownCommand(String),
+    #[error("{0} is not an option of {1}")]
+    UnknownOption(String, &'static str),
+    #[error("{0} needs {1}")]
+    Missing(&'static str, &'static str),
+    #[error("{command} doesn't take {argument}")]
+    Unexpected {
+        command: &'static str,
+        argument: String,
+    },
+}
+
+/// The arguments after the binary's name.
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Cli, UsageError> {
+    let mut config = None;
+    let mut words = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                config = Some(PathBuf::from(
+                    args.next()
+                        .ok_or(UsageError::Missing("--config", "a path"))?,
+                ))
+            }
+            _ => words.push(arg),
+        }
+    }
+    let mut words = words.into_iter();
+    let command = match words.next().as_deref() {
+        None => Command::Run,
+        Some("run") => run(words)?,
+        // the options of run work without it, like before the subcommands
+        Some(option @ ("--replay" | "--original-timing")) => {
+            run(std::iter::once(option.to_owned()).chain(words))?
+        }
+        Some("--example-config") => Command::ExampleConfig,
+        Some("help" | "--help" | "-h") => Command::Help,
+        Some("validate-config") => match words.next() {
+            None => Command::ValidateConfig,
+            Some(argument) => {
+                return Err(UsageError::Unexpected {
+                    command: "validate-config",
+                    argument,
+                })
+            }
+        },
+        Some("send") => {
+            let channel = words
+                .next()
+                .ok_or(UsageError::Missing("send", "a channel"))?;
+            // the words of an unquoted message are joined again
+            let message = words.collect::<Vec<_>>().join(" ");
+            if message.trim().is_empty() {
+                return Err(UsageError::Missing("send", "a message"));
+            }
+            Command::Send { channel, message }
+        }
+        Some("token") => {
+            let action = match words.next().as_deref() {
+                Some("validate") => TokenAction::Validate,
+                Some("refresh") => TokenAction::Refresh,
+                _ => return Err(UsageError::Missing("token", "validate or refresh")),
+            };
+            let identity = match words.next() {
+                None => Identity::Bot,
+                Some(flag) if flag == "--broadcaster" => Identity::Broadcaster,
+                Some(argument) => {
+                    return Err(UsageError::Unexpected {
+                        command: "token",
+                        argument,
+                    })
+                }
+            };
+            if let Some(argument) = words.next() {
+                return Err(UsageError::Unexpected {
+                    command: "token",
+                    argument,
+                });
+            }
+            Command::Token(action, identity)
+        }
+        Some("export") => export(words)?,
+        Some(other) => return Err(UsageError::UnknownCommand(other.to_owned())),
+    };
+    Ok(Cli { config, command })
+}
+
+// --replay <log> [--original-timing]
+fn run(mut args: impl Iterator<Item = String>) -> Result<Command, UsageError> {
+    let mut path = None;
+    let mut timing = ReplayTiming::Fast;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => {
+                path = Some(
+                    args.next()
+                        .ok_or(UsageError::Missing("--replay", "a log"))?,
+                )
+            }
+            "--original-timing" => timing = ReplayTiming::Original,
+            _ => return Err(UsageError::UnknownOption(arg, "run")),
+        }
+    }
+    Ok(match path {
+        Some(path) => Command::Replay { path, timing },
+        None if timing == ReplayTiming::Original => {
+            return Err(UsageError::Missing("--original-timing", "--replay <log>"))
+        }
+        None => Command::Run,
+    })
+}
+
+// [--channel <name>] [--since <date>] [--until <date>], the dates like 2026-10-14
+fn export(mut args: impl Iterator<Item = String>) -> Result<Command, UsageError> {
+    let mut channel = None;
+    let mut range = Range::default();
+    while let Some(arg) = args.next() {
+        let mut date = |option| {
+            args.next()
+                .as_deref()
+                .and_then(Date::parse)
+                .ok_or(UsageError::Missing(option, "a date like 2026-10-14"))
+        };
+        match arg.as_str() {
+            "--since" => range.since = Some(date("--since")?),
+            "--until" => range.until = Some(date("--until")?),
+            "--channel" => {
+                channel = Some(
+                    args.next()
+                        .ok_or(UsageError::Missing("--channel", "a channel"))?,
+                )
+            }
+            _ => return Err(UsageError::UnknownOption(arg, "export")),
+        }
+    }
+    Ok(Command::Export { channel, range })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, UsageError> {
+        super::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn command(args: &[&str]) -> Result<Command, UsageError> {
+        parse(args).map(|cli| cli.command)
+    }
+
+    #[test]
+    fn replay_needs_a_log() {
+        assert_eq!(
+            command(&["--original-timing", "--replay", "stream.log"]),
+            Ok(Command::Replay {
+                path: "stream.log".to_owned(),
+                timing: ReplayTiming::Original
+            })
+        );
+        assert_eq!(
+            command(&["run", "--replay", "stream.log"]),
+            Ok(Command::Replay {
+                path: "stream.log".to_owned(),
+                timing: ReplayTiming::Fast
+            })
+        );
+        assert_eq!(
+            command(&["--original-timing"]),
+            Err(UsageError::Missing("--original-timing", "--replay <log>"))
+        );
+        assert_eq!(
+            command(&["run", "--replay"]),
+            Err(UsageError::Missing("--replay", "a log"))
+        );
+    }
+
+    #[test]
+    fn config_path_goes_with_every_command() {
+        let config = |args: &[&str]| parse(args).map(|cli| cli.config);
+        assert_eq!(
+            config(&["--config", "bot.toml"]),
+            Ok(Some(PathBuf::from("bot.toml")))
+        );
+        assert_eq!(
+            parse(&["token", "refresh", "--config", "bot.toml"]),
+            Ok(Cli {
+                config: Some(PathBuf::from("bot.toml")),
+                command: Command::Token(TokenAction::Refresh, Identity::Bot)
+            })
+        );
+        assert_eq!(
+            config(&["--config"]),
+            Err(UsageError::Missing("--config", "a path"))
+        );
+        assert_eq!(config(&[]), Ok(None));
+        assert_eq!(command(&[]), Ok(Command::Run));
+    }
+
+    #[test]
+    fn commands_take_their_arguments() {
+        assert_eq!(
+            command(&["send", "#captaincallback", "Stream", "starts", "soon!"]),
+            Ok(Command::Send {
+                channel: "#captaincallback".to_owned(),
+                message: "Stream starts soon!".to_owned()
+            })
+        );
+        assert_eq!(
+            command(&["send", "captaincallback"]),
+            Err(UsageError::Missing("send", "a message"))
+        );
+        assert_eq!(
+            command(&["token", "validate", "--broadcaster"]),
+            Ok(Command::Token(TokenAction::Validate, Identity::Broadcaster))
+        );
+        assert_eq!(
+            command(&["token", "show"]),
+            Err(UsageError::Missing("token", "validate or refresh"))
+        );
+        assert_eq!(
+            command(&["validate-config", "now"]),
+            Err(UsageError::Unexpected {
+                command: "validate-config",
+                argument: "now".to_owned()
+            })
+        );
+        assert_eq!(
+            command(&["export", "--until", "2026-10-14", "--channel", "carkhy"]),
+            Ok(Command::Export {
+                channel: Some("carkhy".to_owned()),
+                range: Range {
+                    since: None,
+                    until: Date::parse("2026-10-14"),
+                }
+            })
+        );
+        assert_eq!(
+            command(&["export", "--since", "yesterday"]),
+            Err(UsageError::Missing("--since", "a date like 2026-10-14"))
+        );
+        assert_eq!(
+            command(&["start"]),
+            Err(UsageError::UnknownCommand("start".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,3822 @@
+use crate::core::{Cron, TimeZone};
+use dotenv::dotenv;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+
+/// How the connection to twitch chat is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionSecurity {
+    // only meant for testing against a local fake server, the token is sent unencrypted
+    Plain,
+    #[default]
+    Tls,
+}
+
+/// How IRC lines are carried to twitch chat, the websocket endpoint only needs ports 80 and 443.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatTransport {
+    Tcp,
+    #[default]
+    WebSocket,
+}
+
+/// What happens to a chat message identical to the one sent to the channel just before.
+/// Twitch drops it within 30 seconds unless the bot is moderator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateMessages {
+    // wait until twitch takes it again
+    #[default]
+    Delay,
+    // send it right away with an invisible character added or removed
+    Vary,
+}
+
+// TWITCHBOT_CHAT__MESSAGE_TTL sets message_ttl in the [chat] table
+const ENV_PREFIX: &str = "TWITCHBOT_";
+const ENV_SEPARATOR: &str = "__";
+
+// read when no --config is given, the defaults apply without it
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// comparing each message with more would slow down a busy chat
+const MAX_SIMILARITY_MESSAGES: usize = 100;
+
+// membership is needed to keep track of the chatters
+const DEFAULT_CAPABILITIES: [&str; 3] = [
+    "twitch.tv/tags",
+    "twitch.tv/commands",
+    "twitch.tv/membership",
+];
+
+/// Everything the bot is configured with, read from the config file and the environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub twitch: TwitchConfig,
+    pub broadcaster: BroadcasterConfig,
+    pub connection: ConnectionConfig,
+    pub chat: ChatConfig,
+    pub commands: CommandsConfig,
+    pub events: EventsConfig,
+    pub moderation: ModerationConfig,
+    pub points: PointsConfig,
+    pub raffle: RaffleConfig,
+    pub trivia: TriviaConfig,
+    pub songs: SongsConfig,
+    pub queue: QueueConfig,
+    pub reminders: RemindersConfig,
+    pub lurk: LurkConfig,
+    pub watch_time: WatchTimeConfig,
+    pub emotes: EmotesConfig,
+    pub imitate: ImitateConfig,
+    pub poll: PollConfig,
+    pub redemptions: RedemptionsConfig,
+    pub output: OutputConfig,
+    pub chat_logs: ChatLogsConfig,
+    pub export: ExportConfig,
+    pub logging: LoggingConfig,
+    pub http: HttpConfig,
+    pub webhooks: WebhooksConfig,
+    pub discord: DiscordConfig,
+    pub obs: ObsConfig,
+    pub tts: TtsConfig,
+    pub control: ControlConfig,
+    pub storage: StorageConfig,
+    pub timers: TimersConfig,
+    pub schedule: ScheduleConfig,
+    // by channel, lowercase and without the leading '#', see `for_channel`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub channels: BTreeMap<String, ChannelOverrides>,
+}
+
+/// Who the bot is and where it chats.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TwitchConfig {
+    pub channels: Vec<String>,
+    pub user: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub anonymous: bool,
+    pub capabilities: Vec<String>,
+}
+
+// the client secret must not end up in a log
+impl fmt::Debug for TwitchConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TwitchConfig")
+            .field("channels", &self.channels)
+            .field("user", &self.user)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("anonymous", &self.anonymous)
+            .field("capabilities", &self.capabilities)
+            .finish()
+    }
+}
+
+impl Default for TwitchConfig {
+    fn default() -> Self {
+        Self {
+            channels: vec!["#captaincallback".to_owned()],
+            user: String::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            anonymous: false,
+            capabilities: DEFAULT_CAPABILITIES.map(String::from).to_vec(),
+        }
+    }
+}
+
+/// The broadcaster's own token, for what only the broadcaster may do like starting polls,
+/// next to the bot's token that chats.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BroadcasterConfig {
+    // the broadcaster who authorizes the token, the bot's token does everything without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login: Option<String>,
+    // those of `twitch` when empty
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+// the client secret must not end up in a log
+impl fmt::Debug for BroadcasterConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BroadcasterConfig")
+            .field("login", &self.login)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// How the bot connects to twitch chat.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionConfig {
+    pub transport: ChatTransport,
+    pub security: ConnectionSecurity,
+    pub verify_certificates: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    // seconds
+    pub keepalive: u64,
+    pub forward_pings: bool,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            transport: ChatTransport::default(),
+            security: ConnectionSecurity::default(),
+            verify_certificates: true,
+            server: None,
+            keepalive: 4 * 60,
+            forward_pings: false,
+        }
+    }
+}
+
+/// What happens to the messages the bot sends.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    pub duplicates: DuplicateMessages,
+    // seconds
+    pub message_ttl: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub goodbye_message: Option<String>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            duplicates: DuplicateMessages::default(),
+            message_ttl: 60,
+            goodbye_message: None,
+        }
+    }
+}
+
+/// What the bot says when something happens in a channel, like a first message.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    // a template like the custom commands' responses, empty greets nobody
+    pub greeting: String,
+    pub greet_only_live: bool,
+    // a template with $(from) and $(viewers), empty sends nothing
+    pub raid_message: String,
+    pub raid_shoutout: bool,
+    // seconds slow mode is off after a raid, 0 leaves it alone
+    pub raid_slow_pause: u64,
+    // smaller raids are only logged, e.g. of viewbots
+    pub raid_min_viewers: u32,
+    // follows and the stream going live come over EventSub, chat doesn't tell about them
+    pub eventsub: bool,
+    // a template, empty thanks nobody. Only sent with eventsub
+    pub follow_message: String,
+    // templates, empty sends nothing. The offline message has $(duration), $(top_chatter) and
+    // $(top_messages)
+    pub live_message: String,
+    pub offline_message: String,
+    // both are twitch announcements of the color then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub announce_stream: Option<AnnouncementColor>,
+    // templates with $(level), the end with $(contributors) as well. Empty sends nothing
+    pub hype_train_begin: String,
+    pub hype_train_level: String,
+    pub hype_train_end: String,
+    // the levels in between aren't announced
+    pub hype_train_only_ends: bool,
+    // templates, empty thanks nobody
+    pub sub_message: String,
+    // with $(months) and $(tier) like sub_message
+    pub resub_message: String,
+    // with $(recipient)
+    pub gift_message: String,
+    // with $(gifts), the single gifts of the bomb aren't thanked
+    pub gift_bomb_message: String,
+    pub quiet_in_subs_only: bool,
+    // the highest tier a cheer reaches is thanked, smaller cheers aren't
+    pub bits_tiers: Vec<BitsTier>,
+    // channels without the leading '#' with tiers of their own
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub channel_bits_tiers: HashMap<String, Vec<BitsTier>>,
+}
+
+/// A cheer of at least the bits is thanked with the message.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitsTier {
+    pub bits: u32,
+    // a template with $(bits) and $(message), the cheer without its cheermotes
+    pub message: String,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            greeting: "Welcome to the chat, $(user)! Write '!help' to see what I can do."
+                .to_owned(),
+            greet_only_live: false,
+            raid_message: "$(from) is raiding with $(viewers) viewers!".to_owned(),
+            raid_shoutout: false,
+            raid_slow_pause: 0,
+            raid_min_viewers: 2,
+            eventsub: false,
+            follow_message: "Thank you for the follow, $(user)!".to_owned(),
+            live_message: String::new(),
+            offline_message: String::new(),
+            announce_stream: None,
+            hype_train_begin: "Hype Train level $(level) started!".to_owned(),
+            hype_train_level: "The Hype Train reached level $(level)!".to_owned(),
+            hype_train_end: "The Hype Train ended at level $(level)! Thanks to $(contributors)"
+                .to_owned(),
+            hype_train_only_ends: false,
+            sub_message: "Thank you for subscribing, $(user)!".to_owned(),
+            resub_message: "Thank you for $(months) months, $(user)!".to_owned(),
+            gift_message: "Thank you for gifting a sub to $(recipient), $(user)!".to_owned(),
+            gift_bomb_message: "Thank you for gifting $(gifts) subs, $(user)!".to_owned(),
+            quiet_in_subs_only: false,
+            bits_tiers: vec![
+                BitsTier {
+                    bits: 100,
+                    message: "Thank you for the $(bits) bits, $(user)!".to_owned(),
+                },
+                BitsTier {
+                    bits: 1000,
+                    message: "WOW, $(bits) bits! Thank you so much, $(user)!".to_owned(),
+                },
+            ],
+            channel_bits_tiers: HashMap::new(),
+        }
+    }
+}
+
+/// What a user hears who isn't allowed to call a command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Denial {
+    #[default]
+    Silent,
+    // answers in a thread when tags were granted
+    Reply,
+}
+
+/// How chat commands are called.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandsConfig {
+    pub prefix: String,
+    // channels without the leading '#' that use another prefix
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub channel_prefixes: HashMap<String, String>,
+    pub denial: Denial,
+    // a bare !commercial runs one of 30 seconds, instead of asking for the length
+    pub commercial_default: bool,
+    // answers per minute to the commands of a user below moderator, 0 for no limit
+    pub responses_per_user: usize,
+    // templates, one is picked at random for each question
+    pub eightball_answers: Vec<String>,
+    // logins whose whispers are commands, at a level above the broadcaster
+    pub admins: Vec<String>,
+}
+
+// the answers of the classic toy
+const EIGHTBALL_ANSWERS: [&str; 20] = [
+    "it is certain.",
+    "it is decidedly so.",
+    "without a doubt.",
+    "yes, definitely.",
+    "you may rely on it.",
+    "as I see it, yes.",
+    "most likely.",
+    "outlook good.",
+    "yes.",
+    "signs point to yes.",
+    "reply hazy, try again.",
+    "ask again later.",
+    "better not tell you now.",
+    "cannot predict now.",
+    "concentrate and ask again.",
+    "don't count on it.",
+    "my reply is no.",
+    "my sources say no.",
+    "outlook not so good.",
+    "very doubtful.",
+];
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "!".to_owned(),
+            channel_prefixes: HashMap::new(),
+            denial: Denial::default(),
+            commercial_default: false,
+            responses_per_user: 5,
+            eightball_answers: EIGHTBALL_ANSWERS
+                .iter()
+                .map(|answer| format!("$(user), {}", answer))
+                .collect(),
+            admins: Vec::new(),
+        }
+    }
+}
+
+/// Where a user stands in a channel, from everyone up to the broadcaster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+/// What happens to a user whose message breaks the rules, the message is removed in any case.
+/// Written as "delete", "timeout <seconds>" or "ban".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Punishment {
+    Delete,
+    // seconds
+    Timeout(u64),
+    Ban,
+}
+
+impl TryFrom<String> for Punishment {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        match value.split_whitespace().collect::<Vec<_>>()[..] {
+            ["delete"] => Ok(Punishment::Delete),
+            ["ban"] => Ok(Punishment::Ban),
+            ["timeout", seconds] => seconds
+                .parse()
+                .map(Punishment::Timeout)
+                .map_err(|_| format!("{:?} is not a number of seconds", seconds)),
+            _ => Err(format!(
+                "{:?} is not \"delete\", \"timeout <seconds>\" or \"ban\"",
+                value
+            )),
+        }
+    }
+}
+
+impl From<Punishment> for String {
+    fn from(punishment: Punishment) -> Self {
+        match punishment {
+            Punishment::Delete => "delete".to_owned(),
+            Punishment::Timeout(seconds) => format!("timeout {}", seconds),
+            Punishment::Ban => "ban".to_owned(),
+        }
+    }
+}
+
+/// A word or phrase not allowed in chat.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BannedTermConfig {
+    // matched ignoring case and simple leetspeak, or a regex ignoring case
+    pub term: String,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default = "delete")]
+    pub punishment: Punishment,
+}
+
+fn delete() -> Punishment {
+    Punishment::Delete
+}
+
+/// How the bot keeps order in chat.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModerationConfig {
+    // logins, changed at runtime with !ignore
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignored_users: Vec<String>,
+    // links of users below link_level are deleted
+    pub link_filter: bool,
+    pub link_level: Level,
+    // links to them and their subdomains are fine from anyone
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_domains: Vec<String>,
+    // changed at runtime with !banword
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub banned_terms: Vec<BannedTermConfig>,
+    // told the user whose message had a banned term
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banned_term_warning: Option<String>,
+    // users below caps_level are warned once, then messages with too many capitals are deleted
+    pub caps_filter: bool,
+    pub caps_level: Level,
+    // letters with a case, shorter messages are never shouting
+    pub caps_min_length: usize,
+    pub caps_max_percent: u8,
+    // seconds a warning is remembered
+    pub caps_window: u64,
+    // messages of users below emote_level with too many emotes are deleted
+    pub emote_filter: bool,
+    pub emote_level: Level,
+    pub max_emotes: usize,
+    // of the words, from 6 emotes on
+    pub emote_max_percent: u8,
+    // messages of users below symbol_level that are mostly symbols, zalgo or walls are deleted
+    pub symbol_filter: bool,
+    pub symbol_level: Level,
+    // characters besides whitespace, shorter messages may be only symbols
+    pub symbol_min_length: usize,
+    pub symbol_max_percent: u8,
+    // on a single character
+    pub max_combining_marks: usize,
+    pub max_repeated_chars: usize,
+    // messages of users below similarity_level like their own recent ones or like spam are deleted
+    pub similarity_filter: bool,
+    pub similarity_level: Level,
+    // of the longer message that stays the same
+    pub similarity_percent: u8,
+    // letters and digits, shorter messages are never compared
+    pub similarity_min_length: usize,
+    // seconds and messages per channel a message is compared with
+    pub similarity_window: u64,
+    pub similarity_messages: usize,
+    // seconds until a strike expires
+    pub strike_decay: u64,
+    // the punishment by the number of strikes, the last one for any more
+    pub strike_ladder: Vec<Punishment>,
+    // seconds a phrase nuked before anyone said it stays filtered
+    pub nuke_filter: u64,
+    // said when shield mode turns on and off, empty for nothing
+    pub shield_message: String,
+    pub shield_off_message: String,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            ignored_users: Vec::new(),
+            link_filter: false,
+            link_level: Level::Vip,
+            allowed_domains: Vec::new(),
+            banned_terms: Vec::new(),
+            banned_term_warning: None,
+            caps_filter: false,
+            caps_level: Level::Vip,
+            caps_min_length: 10,
+            caps_max_percent: 70,
+            caps_window: 300,
+            emote_filter: false,
+            emote_level: Level::Vip,
+            max_emotes: 15,
+            emote_max_percent: 80,
+            symbol_filter: false,
+            symbol_level: Level::Vip,
+            symbol_min_length: 10,
+            symbol_max_percent: 50,
+            max_combining_marks: 2,
+            max_repeated_chars: 20,
+            similarity_filter: false,
+            similarity_level: Level::Vip,
+            similarity_percent: 80,
+            similarity_min_length: 10,
+            similarity_window: 60,
+            similarity_messages: 50,
+            strike_decay: 24 * 60 * 60,
+            strike_ladder: vec![
+                Punishment::Delete,
+                Punishment::Timeout(60),
+                Punishment::Timeout(600),
+                Punishment::Ban,
+            ],
+            nuke_filter: 5 * 60,
+            shield_message: "Shield mode is on, links are removed for everyone below moderator."
+                .to_owned(),
+            shield_off_message: "Shield mode is off.".to_owned(),
+        }
+    }
+}
+
+/// The loyalty points viewers earn by watching and chatting.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PointsConfig {
+    pub enabled: bool,
+    // for each 5 minutes in chat
+    pub interval_points: u64,
+    pub message_points: u64,
+    // seconds until a user's next message earns points again
+    pub message_cooldown: u64,
+    // of the points viewers earn, subscribers earn this share
+    pub sub_percent: u64,
+    // viewers pass on their own points with !give, moderators always make new ones
+    pub peer_give: bool,
+    // whether !gamble and !slots answer
+    pub gambling: bool,
+    // seconds between two games of a user
+    pub gamble_cooldown: u64,
+    pub gamble_win_percent: u8,
+    // of the wager, paid back on a win
+    pub gamble_payout_percent: u64,
+    // each equally likely on each of the three reels
+    pub slots_symbols: Vec<SlotsSymbol>,
+    // of the wager, paid back for two of the same symbol
+    pub slots_pair_percent: u64,
+}
+
+/// A symbol on the reels of `!slots`, three of it pay back `payout_percent` of the wager.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlotsSymbol {
+    pub symbol: String,
+    pub payout_percent: u64,
+}
+
+fn slots_symbol(symbol: &str, payout_percent: u64) -> SlotsSymbol {
+    SlotsSymbol {
+        symbol: symbol.to_owned(),
+        payout_percent,
+    }
+}
+
+impl Default for PointsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_points: 10,
+            message_points: 1,
+            message_cooldown: 60,
+            sub_percent: 150,
+            peer_give: false,
+            gambling: true,
+            gamble_cooldown: 30,
+            gamble_win_percent: 45,
+            gamble_payout_percent: 200,
+            slots_symbols: vec![
+                slots_symbol("🍒", 500),
+                slots_symbol("🍋", 500),
+                slots_symbol("🍇", 1000),
+                slots_symbol("💎", 2000),
+            ],
+            slots_pair_percent: 50,
+        }
+    }
+}
+
+/// The color of a twitch announcement, primary is the channel's accent color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementColor {
+    #[default]
+    Primary,
+    Blue,
+    Green,
+    Orange,
+    Purple,
+}
+
+impl AnnouncementColor {
+    /// How helix names it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnnouncementColor::Primary => "primary",
+            AnnouncementColor::Blue => "blue",
+            AnnouncementColor::Green => "green",
+            AnnouncementColor::Orange => "orange",
+            AnnouncementColor::Purple => "purple",
+        }
+    }
+}
+
+/// Where in a message the keyword of a raffle enters the user.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeywordMatch {
+    // the message is nothing but the keyword
+    Message,
+    #[default]
+    Word,
+}
+
+/// Who may enter the raffles started with `!raffle`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaffleConfig {
+    pub keyword_match: KeywordMatch,
+    // asks twitch for each entrant, the token needs moderator:read:followers
+    pub followers_only: bool,
+    pub subs_only: bool,
+    // in chat since the bot saw the user join or write
+    pub min_watch_minutes: u64,
+    // entries of a subscriber, 1 gives everyone the same chance
+    pub sub_tickets: u32,
+    // tier 2 subscribers get twice the sub_tickets, tier 3 three times
+    pub tier_scaled: bool,
+    // counted by the watch time, checked when drawing like the rules below
+    pub min_total_watch_minutes: u64,
+    pub min_points: u64,
+    // winners of the channel's raffles can't win again for this long, 0 for no limit
+    pub exclude_winners_days: u32,
+    // each win within the days takes this share of a user's chance
+    pub luck_decay_days: u32,
+    pub luck_decay_percent: u8,
+    // the winners are whispered as well, the token needs user:manage:whispers
+    pub whisper_winners: bool,
+}
+
+impl Default for RaffleConfig {
+    fn default() -> Self {
+        Self {
+            keyword_match: KeywordMatch::Word,
+            followers_only: false,
+            subs_only: false,
+            min_watch_minutes: 0,
+            sub_tickets: 1,
+            tier_scaled: false,
+            min_total_watch_minutes: 0,
+            min_points: 0,
+            exclude_winners_days: 0,
+            luck_decay_days: 0,
+            luck_decay_percent: 0,
+            whisper_winners: false,
+        }
+    }
+}
+
+/// The questions of `!trivia` and how long chat has for each.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TriviaConfig {
+    // read as JSON with the extension .json, as TOML otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub questions: Option<String>,
+    pub answer_time: u64,
+    pub hint: bool,
+    // only paid with the points enabled
+    pub points: u64,
+}
+
+impl Default for TriviaConfig {
+    fn default() -> Self {
+        Self {
+            questions: None,
+            answer_time: 30,
+            hint: true,
+            points: 50,
+        }
+    }
+}
+
+/// The song requests of `!sr`, a player outside the bot plays them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SongsConfig {
+    pub enabled: bool,
+    // songs of one user waiting after the one playing
+    pub per_user: usize,
+}
+
+impl Default for SongsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            per_user: 2,
+        }
+    }
+}
+
+/// The viewer queue of `!join`, e.g. for playing with viewers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    // subscribers join ahead of everyone else, behind the other subscribers
+    pub sub_priority: bool,
+    // users who part the channel lose their spot unless they come back in time
+    pub drop_parted: bool,
+    pub part_grace_minutes: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            sub_priority: false,
+            drop_parted: false,
+            part_grace_minutes: 5,
+        }
+    }
+}
+
+/// The reminders of `!remindme` and `!remind`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemindersConfig {
+    // reminders waiting for one user
+    pub per_user: usize,
+    // delivered as whispers, in chat only when the user doesn't allow them
+    pub whisper: bool,
+}
+
+impl Default for RemindersConfig {
+    fn default() -> Self {
+        Self {
+            per_user: 3,
+            whisper: false,
+        }
+    }
+}
+
+/// The answers of `!lurk` and how lurks end.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LurkConfig {
+    pub message: String,
+    // the next chat message of a lurker ends the lurk, not only `!unlurk`
+    pub end_on_message: bool,
+    // empty says nothing when a lurk ends
+    pub welcome_back: String,
+}
+
+impl Default for LurkConfig {
+    fn default() -> Self {
+        Self {
+            message: "$(user) is lurking now, enjoy the stream!".to_owned(),
+            end_on_message: true,
+            welcome_back: "Welcome back $(user), you lurked for $(duration).".to_owned(),
+        }
+    }
+}
+
+/// How long viewers watched the streams, for `!watchtime` and `!top watchtime`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchTimeConfig {
+    pub enabled: bool,
+    // a join without a part or a message since counts this long at most
+    pub max_presence_minutes: u64,
+}
+
+impl Default for WatchTimeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_presence_minutes: 4 * 60,
+        }
+    }
+}
+
+/// The emotes counted for `!topemotes` and `!emotecount`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmotesConfig {
+    // third-party emotes like BTTV's, counted as whole words since twitch doesn't mark them
+    pub extra_names: Vec<String>,
+}
+
+/// The chat models `!imitate` makes up sentences with.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImitateConfig {
+    pub enabled: bool,
+    // the words a next word is picked by
+    pub order: usize,
+    pub max_words: usize,
+    // a user's model imitates them once it learned this many messages
+    pub min_user_messages: u64,
+    // of all models together, the least recently used are forgotten beyond it
+    pub max_ngrams: usize,
+}
+
+impl Default for ImitateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            order: 2,
+            max_words: 30,
+            min_user_messages: 20,
+            max_ngrams: 100_000,
+        }
+    }
+}
+
+/// How long a poll of `!poll` runs and whose votes count more.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollConfig {
+    // seconds
+    pub duration: u64,
+    // what a subscriber's vote counts, 1 counts everyone the same
+    pub sub_weight: u32,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        Self {
+            duration: 120,
+            sub_weight: 1,
+        }
+    }
+}
+
+/// What the bot does when a viewer redeems a reward of channel points.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedemptionsConfig {
+    // twitch only lets the bot fulfill rewards created with its client id
+    pub fulfill: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rewards: Vec<RewardConfig>,
+}
+
+/// The actions of a reward, the empty ones are skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewardConfig {
+    // the title, ignoring case, or the id
+    pub reward: String,
+    // sent to chat
+    #[serde(default)]
+    pub message: String,
+    // run as if the broadcaster sent it, e.g. "!so $(input)"
+    #[serde(default)]
+    pub command: String,
+    // the redemption is POSTed here as JSON
+    #[serde(default)]
+    pub webhook: String,
+}
+
+/// Where received chat is written to, besides the bot.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chat_export: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chat_log: Option<String>,
+}
+
+/// Chat written to a file for each channel and day, kept for good unlike the chat log.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatLogsConfig {
+    pub enabled: bool,
+    pub directory: PathBuf,
+    pub format: ChatLogFormat,
+    // a day's file is continued in another one past this size, 0 only starts one each day
+    pub max_kilobytes: u64,
+    // older files are deleted, 0 keeps them all
+    pub keep_days: u64,
+}
+
+impl Default for ChatLogsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: PathBuf::from("logs"),
+            format: ChatLogFormat::Text,
+            max_kilobytes: 10240,
+            keep_days: 0,
+        }
+    }
+}
+
+/// Where `export` and `!export` write the CSV files of the chat logs and the stats.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    pub directory: PathBuf,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("exports"),
+        }
+    }
+}
+
+/// How the chat logs are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatLogFormat {
+    // a line for people to read, e.g. "[2026-10-14 20:46:42] [sub/27] Carkhy: hello"
+    #[default]
+    Text,
+    // a JSON object per line, like the chat export
+    Jsonl,
+}
+
+/// What the bot tells about itself, e.g. about the connection and the helix requests.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    // "info,chatbot::helix=debug", the level of everything and of the modules after it
+    pub filter: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Pretty,
+            filter: "info".to_owned(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// The level of each module in the filter, "" for the rest, in the order written.
+    pub fn directives(&self) -> Result<Vec<(String, LevelFilter)>, String> {
+        self.filter
+            .split(',')
+            .map(str::trim)
+            .filter(|directive| !directive.is_empty())
+            .map(|directive| {
+                let (target, level) = match directive.split_once('=') {
+                    Some((target, level)) => (target.trim(), level.trim()),
+                    None => ("", directive),
+                };
+                match level.parse() {
+                    Ok(level) => Ok((target.to_owned(), level)),
+                    Err(_) => Err(format!(
+                        "{:?} is no level, use off, error, warn, info, debug or trace",
+                        level
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+/// How the log is written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // a line for people to read, with the spans it happened in
+    #[default]
+    Pretty,
+    // a JSON object per line, for collecting the logs
+    Json,
+}
+
+/// Where the bot answers HTTP requests, e.g. Prometheus asking for the metrics.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    // "127.0.0.1:9100", nothing is listened to without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    // seconds without a read from twitch before `/healthz` fails
+    pub stale_after: u64,
+    // lines waiting to be sent before `/readyz` fails
+    pub max_queue: usize,
+    // the bearer token of `/api/`, which is only answered with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+}
+
+// the token must not end up in a log
+impl fmt::Debug for HttpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpConfig")
+            .field("listen", &self.listen)
+            .field("stale_after", &self.stale_after)
+            .field("max_queue", &self.max_queue)
+            .field("api_token", &self.api_token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            stale_after: 5 * 60,
+            max_queue: 50,
+            api_token: None,
+        }
+    }
+}
+
+/// Where chat events are POSTed to as JSON, e.g. for a dashboard or an automation.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    // the key of the signature, needed once any URL is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    // after the first attempt, for server errors and timeouts
+    pub retries: u32,
+    // seconds a delivery may take
+    pub timeout: u64,
+    // the URLs of each kind of event
+    pub follow: Vec<String>,
+    pub sub: Vec<String>,
+    pub raid: Vec<String>,
+    pub first_chat: Vec<String>,
+    pub command: Vec<String>,
+    pub moderation: Vec<String>,
+}
+
+// the secret must not end up in a log
+impl fmt::Debug for WebhooksConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhooksConfig")
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .field("retries", &self.retries)
+            .field("timeout", &self.timeout)
+            .field("follow", &self.follow)
+            .field("sub", &self.sub)
+            .field("raid", &self.raid)
+            .field("first_chat", &self.first_chat)
+            .field("command", &self.command)
+            .field("moderation", &self.moderation)
+            .finish()
+    }
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            retries: 3,
+            timeout: 10,
+            follow: Vec::new(),
+            sub: Vec::new(),
+            raid: Vec::new(),
+            first_chat: Vec::new(),
+            command: Vec::new(),
+            moderation: Vec::new(),
+        }
+    }
+}
+
+impl WebhooksConfig {
+    /// The URLs by the kind of event, in the order of the config.
+    pub fn urls(&self) -> [(&'static str, &[String]); 6] {
+        [
+            ("follow", &self.follow),
+            ("sub", &self.sub),
+            ("raid", &self.raid),
+            ("first_chat", &self.first_chat),
+            ("command", &self.command),
+            ("moderation", &self.moderation),
+        ]
+    }
+}
+
+/// What the bot tells a Discord server, with a webhook or as a Discord bot relaying chat.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    // where the announcements go, else to the relay's channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    // the relay posts chat to the channel as this bot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bot_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    // lines of each user relayed a minute, the others are left out
+    pub relay_per_minute: u32,
+    // seconds between the moderation summaries, 0 for none
+    pub summary_interval: u64,
+}
+
+// the token must not end up in a log
+impl fmt::Debug for DiscordConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiscordConfig")
+            .field(
+                "webhook_url",
+                &self.webhook_url.as_ref().map(|_| "<redacted>"),
+            )
+            .field("bot_token", &self.bot_token.as_ref().map(|_| "<redacted>"))
+            .field("channel_id", &self.channel_id)
+            .field("relay_per_minute", &self.relay_per_minute)
+            .field("summary_interval", &self.summary_interval)
+            .finish()
+    }
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            bot_token: None,
+            channel_id: None,
+            relay_per_minute: 5,
+            summary_interval: 5 * 60,
+        }
+    }
+}
+
+/// The OBS the scene, mute and show commands control, over obs-websocket.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObsConfig {
+    // e.g. "ws://127.0.0.1:4455", the commands are off without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    // how long !show shows a source without a number of seconds
+    pub show_seconds: u64,
+}
+
+// the password must not end up in a log
+impl fmt::Debug for ObsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObsConfig")
+            .field("url", &self.url)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("show_seconds", &self.show_seconds)
+            .finish()
+    }
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            password: None,
+            show_seconds: 10,
+        }
+    }
+}
+
+/// What a local text to speech program reads out, and where the bot writes it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtsConfig {
+    // "unix:/path", "tcp:host:port" or "pipe:/path", nothing is read out without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sink: Option<String>,
+    // titles or ids of the rewards whose input is read
+    pub rewards: Vec<String>,
+    // logins whose messages are read
+    pub users: Vec<String>,
+    // a regex, the messages matching it are read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    // the voice hint by reward or login, else `voice`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    pub voices: HashMap<String, String>,
+    // emotes after these are left out
+    pub max_emotes: usize,
+    // entries waiting for a slow program, the oldest is dropped for a new one
+    pub queue: usize,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            sink: None,
+            rewards: Vec::new(),
+            users: Vec::new(),
+            pattern: None,
+            voice: None,
+            voices: HashMap::new(),
+            max_emotes: 3,
+            queue: 20,
+        }
+    }
+}
+
+/// The local socket scripts on the same host control the bot with.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlConfig {
+    // the path of the Unix socket, nothing is listened to without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    // clients connected at once, more are turned away
+    pub max_clients: usize,
+    // seconds a client may take for a request
+    pub timeout: u64,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            max_clients: 4,
+            timeout: 30,
+        }
+    }
+}
+
+/// Where the bot keeps what is changed from chat, like custom commands.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub directory: PathBuf,
+    // seconds a change may wait before it is written, changes meanwhile are written together
+    pub write_delay: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Json,
+            directory: PathBuf::from("data"),
+            write_delay: 2,
+        }
+    }
+}
+
+/// How the bot keeps what is changed from chat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    // a JSON file for each kind of data, which may be edited while the bot is stopped
+    #[default]
+    Json,
+    // nothing is kept after the bot stops, e.g. to try it out
+    Memory,
+    // one SQLite database in the directory, needs the sqlite feature
+    Sqlite,
+}
+
+/// A message sent again and again to a channel, as long as chat is active.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimerConfig {
+    // with the leading '#'
+    pub channel: String,
+    pub name: String,
+    pub text: String,
+    // seconds between two messages at least
+    pub interval: u64,
+    // chat lines received since the last message, the bot's own don't count
+    #[serde(default)]
+    pub min_messages: usize,
+    // sent as a twitch announcement of the color instead of a chat line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub announce: Option<AnnouncementColor>,
+}
+
+/// Messages like the rules or socials, sent by the bot on its own.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimersConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<TimerConfig>,
+    // the timers of offline channels wait
+    pub only_live: bool,
+}
+
+/// A message or a command at the times of a cron expression.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledConfig {
+    pub name: String,
+    // "minute hour day month weekday" in schedule.timezone
+    pub cron: String,
+    // with the leading '#'
+    pub channel: String,
+    // one of text and command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    // skipped while the channel is offline
+    #[serde(default)]
+    pub only_live: bool,
+    // the last time missed while the bot was stopped is made up at the start
+    #[serde(default)]
+    pub catch_up: bool,
+}
+
+/// What the bot sends at fixed times, like a weekly game night.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    // a name of the tz database like "Europe/Berlin", or a POSIX rule
+    pub timezone: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<ScheduledConfig>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_owned(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// The tables of a channel's `[channels.<name>]`, merged into the global ones for it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct ChannelOverrides(pub toml::value::Table);
+
+// a config has no NaN, the only value not equal to itself
+impl Eq for ChannelOverrides {}
+
+// the keys a channel may set for itself, each read through `for_channel`
+const CHANNEL_KEYS: &[(&str, &[&str])] = &[
+    ("commands", &["prefix", "denial"]),
+    ("moderation", &["banned_terms", "banned_term_warning"]),
+    (
+        "points",
+        &[
+            "enabled",
+            "interval_points",
+            "message_points",
+            "message_cooldown",
+            "sub_percent",
+        ],
+    ),
+    ("timers", &["messages"]),
+];
+
+// of them, the ones that can be appended to
+const CHANNEL_LISTS: &[&str] = &["moderation.banned_terms", "timers.messages"];
+
+// the timers in a channel's overrides, by the key of their list
+fn own_timers(overrides: &toml::value::Table) -> impl Iterator<Item = (&str, &toml::value::Table)> {
+    let timers = overrides.get("timers").and_then(toml::Value::as_table);
+    ["messages", "+messages"].into_iter().flat_map(move |key| {
+        let list = timers.and_then(|timers| timers.get(key));
+        let list = list.and_then(toml::Value::as_array).map(Vec::as_slice);
+        list.unwrap_or_default()
+            .iter()
+            .filter_map(toml::Value::as_table)
+            .map(move |timer| (key, timer))
+    })
+}
+
+// the overrides into the values: tables key by key, anything else replaced, and the list of a
+// key written with a leading '+' appended to
+fn merge(values: &mut toml::value::Table, overrides: &toml::value::Table) {
+    for (key, value) in overrides {
+        let appended = key.strip_prefix('+');
+        let key = appended.unwrap_or(key);
+        match (values.get_mut(key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(overrides)) => {
+                merge(table, overrides)
+            }
+            (Some(toml::Value::Array(list)), toml::Value::Array(more)) if appended.is_some() => {
+                list.extend(more.iter().cloned())
+            }
+            _ => {
+                values.insert(key.to_owned(), value.clone());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read config file {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Invalid config file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Invalid value for {field}: {reason}")]
+    InvalidValue { field: String, reason: String },
+}
+
+fn invalid(field: impl Into<String>, reason: impl Into<String>) -> ConfigError {
+    ConfigError::InvalidValue {
+        field: field.into(),
+        reason: reason.into(),
+    }
+}
+
+// "#CaptainCallback, carkhy" names the channels #captaincallback and #carkhy
+fn parse_channel_names(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|name| name.trim().trim_start_matches('#').to_lowercase())
+        .filter(|name| !name.is_empty())
+        .map(|name| format!("#{}", name))
+        .collect()
+}
+
+// "#captaincallback", with the leading '#' like in the config file
+fn check_channel(field: String, channel: &str) -> Result<(), ConfigError> {
+    let Some(name) = channel.strip_prefix('#') else {
+        return Err(invalid(
+            field,
+            format!("{:?} must start with '#', e.g. \"#{}\"", channel, channel),
+        ));
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(invalid(
+            field,
+            format!("{:?} is not a twitch channel name", channel),
+        ));
+    }
+    Ok(())
+}
+
+// "127.0.0.1:6667", None without a port
+fn parse_server(value: &str) -> Option<(String, u16)> {
+    let (host, port) = value.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    (!host.is_empty()).then(|| (host.to_owned(), port))
+}
+
+// lowercase names like "websocket" for the enums, just like in the config file
+fn parse_variant<T: DeserializeOwned>(name: &str, value: String) -> Result<T, ConfigError> {
+    toml::Value::String(value)
+        .try_into()
+        .map_err(|error| invalid(name, error.to_string()))
+}
+
+fn parse_seconds(name: &str, value: &str) -> Result<u64, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| invalid(name, format!("{:?} is not a number of seconds", value)))
+}
+
+fn env_name(table: &str, key: &str) -> String {
+    format!("{}{}{}{}", ENV_PREFIX, table, ENV_SEPARATOR, key).to_uppercase()
+}
+
+// the value has the type of the current one, keys without a value yet are strings
+fn env_value(
+    name: &str,
+    current: Option<&toml::Value>,
+    value: String,
+) -> Result<toml::Value, ConfigError> {
+    use toml::Value;
+    match current {
+        Some(Value::Boolean(_)) => match value.as_str() {
+            "true" | "1" => Ok(Value::Boolean(true)),
+            "false" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(invalid(name, format!("{:?} is not true or false", value))),
+        },
+        Some(Value::Integer(_)) => value
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| invalid(name, format!("{:?} is not a number", value))),
+        Some(Value::Array(items)) => {
+            let (mut list, added) = match value.strip_prefix('+') {
+                Some(added) => (items.clone(), added),
+                None => (Vec::new(), value.as_str()),
+            };
+            list.extend(
+                added
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_owned())),
+            );
+            Ok(Value::Array(list))
+        }
+        _ => Ok(Value::String(value)),
+    }
+}
+
+// left out of `validate-config`, like they are of the debug output
+const SECRETS: [(&str, &str); 7] = [
+    ("twitch", "client_secret"),
+    ("broadcaster", "client_secret"),
+    ("http", "api_token"),
+    ("webhooks", "secret"),
+    ("discord", "webhook_url"),
+    ("discord", "bot_token"),
+    ("obs", "password"),
+];
+
+// every key of the config file with its description, optional keys with an example value
+const FIELDS: &[(&str, &str, &str, Option<&str>)] = &[
+    (
+        "twitch",
+        "channels",
+        "The channels to join, each starting with '#'.",
+        None,
+    ),
+    (
+        "twitch",
+        "user",
+        "The name of the user the bot chats as.",
+        None,
+    ),
+    (
+        "twitch",
+        "client_id",
+        "The client ID of the bot's application.",
+        None,
+    ),
+    (
+        "twitch",
+        "client_secret",
+        "The client secret of the bot's application.",
+        None,
+    ),
+    (
+        "twitch",
+        "anonymous",
+        "Read chat anonymously, nothing can be sent and no credentials are needed.",
+        None,
+    ),
+    (
+        "twitch",
+        "capabilities",
+        "Requested at login, refused capabilities are only reported as a warning.",
+        None,
+    ),
+    (
+        "broadcaster",
+        "login",
+        "The broadcaster who authorizes a token of their own for !settitle, !setgame, !marker, !commercial, !twitchpoll, !prediction, !vip and !mod, while the bot chats with its own token. Without it these use the bot's token, which only works in the bot's own channel.",
+        Some("\"captaincallback\""),
+    ),
+    (
+        "broadcaster",
+        "client_id",
+        "The client ID of the application the broadcaster authorizes, `twitch.client_id` when empty.",
+        None,
+    ),
+    (
+        "broadcaster",
+        "client_secret",
+        "The client secret of that application, `twitch.client_secret` when empty.",
+        None,
+    ),
+    (
+        "connection",
+        "transport",
+        "\"websocket\" (port 443) or \"tcp\" (port 6697).",
+        None,
+    ),
+    (
+        "connection",
+        "security",
+        "\"tls\" or \"plain\", plain sends the access token unencrypted.",
+        None,
+    ),
+    (
+        "connection",
+        "verify_certificates",
+        "Turn off for a test harness with a self-signed certificate.",
+        None,
+    ),
+    (
+        "connection",
+        "server",
+        "host:port to connect to instead of twitch chat, e.g. a local test server.",
+        Some("\"127.0.0.1:6667\""),
+    ),
+    (
+        "connection",
+        "keepalive",
+        "Seconds without anything received before the bot pings twitch.",
+        None,
+    ),
+    (
+        "connection",
+        "forward_pings",
+        "Pass PINGs on to the bot, e.g. to see them in the chat log.",
+        None,
+    ),
+    (
+        "chat",
+        "duplicates",
+        "\"delay\" or \"vary\" messages identical to the one sent just before.",
+        None,
+    ),
+    (
+        "chat",
+        "message_ttl",
+        "Seconds a response or repeating message may wait for the rate limit.",
+        None,
+    ),
+    (
+        "chat",
+        "goodbye_message",
+        "Sent to every joined channel when the bot is stopped.",
+        Some("\"See you next stream!\""),
+    ),
+    (
+        "commands",
+        "prefix",
+        "Commands like !info and the custom ones start with it.",
+        None,
+    ),
+    (
+        "commands",
+        "channel_prefixes",
+        "Another prefix for some channels, named without '#'.",
+        Some("{ carkhy = \"?\" }"),
+    ),
+    (
+        "commands",
+        "denial",
+        "\"silent\" or \"reply\" to users not allowed to call a command.",
+        None,
+    ),
+    (
+        "commands",
+        "commercial_default",
+        "Whether !commercial without a length runs a 30 second ad.",
+        None,
+    ),
+    (
+        "commands",
+        "responses_per_user",
+        "Answers a minute to the commands of one user, moderators are not limited. 0 for no limit.",
+        None,
+    ),
+    (
+        "commands",
+        "eightball_answers",
+        "The answers of `!8ball`, picked at random. $(user) is the name of the asker.",
+        None,
+    ),
+    (
+        "commands",
+        "admins",
+        "Logins that control the bot by whispering it commands, e.g. !shutdown. Answers are whispered.",
+        Some("[\"captaincallback\"]"),
+    ),
+    (
+        "events",
+        "greeting",
+        "Sent to a user's first message in the channel ever, $(user) is their name. Empty greets nobody.",
+        None,
+    ),
+    (
+        "events",
+        "greet_only_live",
+        "Greet new chatters only while the stream is live.",
+        None,
+    ),
+    (
+        "events",
+        "raid_message",
+        "Sent when a channel raids, $(from) is its name and $(viewers) how many came along. Empty sends nothing.",
+        None,
+    ),
+    (
+        "events",
+        "raid_shoutout",
+        "Shout out the raiding channel like `!so`.",
+        None,
+    ),
+    (
+        "events",
+        "raid_slow_pause",
+        "Seconds slow mode is turned off after a raid, then it is set back. 0 leaves slow mode alone.",
+        None,
+    ),
+    (
+        "events",
+        "raid_min_viewers",
+        "Raids with fewer viewers are ignored, e.g. of bots.",
+        None,
+    ),
+    (
+        "events",
+        "eventsub",
+        "Listen to twitch's EventSub for follows, subs and the stream going live or offline, which needs the broadcaster's token.",
+        None,
+    ),
+    (
+        "events",
+        "follow_message",
+        "Thanks for a follow with eventsub, each follower once while the bot runs. Empty thanks nobody.",
+        None,
+    ),
+    (
+        "events",
+        "live_message",
+        "Sent when the stream goes live. Empty sends nothing.",
+        Some("\"$(channel) is live!\""),
+    ),
+    (
+        "events",
+        "offline_message",
+        "Sent when the stream went offline, with $(duration), $(top_chatter) and $(top_messages). Empty sends nothing.",
+        Some("\"Thanks for watching! We streamed $(duration), $(top_chatter) wrote the most\""),
+    ),
+    (
+        "events",
+        "announce_stream",
+        "Sends the live and offline messages as twitch announcements of the color: primary, blue, green, orange or purple. The token needs the scope moderator:manage:announcements.",
+        Some("\"purple\""),
+    ),
+    (
+        "events",
+        "hype_train_begin",
+        "Sent when a hype train starts, with $(level). Only with eventsub, empty sends nothing.",
+        None,
+    ),
+    (
+        "events",
+        "hype_train_level",
+        "Sent once for each level a hype train reaches, with $(level). Empty sends nothing.",
+        None,
+    ),
+    (
+        "events",
+        "hype_train_end",
+        "Sent when a hype train ends, with $(level) and $(contributors), who gave the most. Empty sends nothing.",
+        None,
+    ),
+    (
+        "events",
+        "hype_train_only_ends",
+        "Announces only the start and the end of hype trains, not the levels in between.",
+        None,
+    ),
+    (
+        "events",
+        "sub_message",
+        "Thanks for a new sub, $(tier) is its tier. Empty thanks nobody, like the other messages.",
+        None,
+    ),
+    (
+        "events",
+        "resub_message",
+        "Thanks for a resub, $(months) is how many months the user subscribed in total.",
+        None,
+    ),
+    (
+        "events",
+        "gift_message",
+        "Thanks for a gifted sub, $(recipient) got it.",
+        None,
+    ),
+    (
+        "events",
+        "gift_bomb_message",
+        "Thanks for $(gifts) gifted subs at once, the single gifts aren't thanked then.",
+        None,
+    ),
+    (
+        "events",
+        "quiet_in_subs_only",
+        "Thank nobody while chat is in subs-only mode, e.g. for a celebration.",
+        None,
+    ),
+    (
+        "events",
+        "bits_tiers",
+        "Cheers of at least the bits are thanked with the message of the highest tier reached, $(bits) and $(message) are filled in.",
+        None,
+    ),
+    (
+        "events",
+        "channel_bits_tiers",
+        "Other tiers for some channels, named without '#'.",
+        Some("{ carkhy = [{ bits = 500, message = \"$(user) cheered $(bits)!\" }] }"),
+    ),
+    (
+        "moderation",
+        "ignored_users",
+        "Logins whose messages the bot doesn't react to, like other bots. Its own are always ignored.",
+        Some("[\"nightbot\", \"streamelements\"]"),
+    ),
+    (
+        "moderation",
+        "link_filter",
+        "Whether links of users below link_level are deleted, the bot has to moderate the channel.",
+        None,
+    ),
+    (
+        "moderation",
+        "link_level",
+        "\"everyone\", \"subscriber\", \"vip\", \"moderator\" or \"broadcaster\": who may post links.",
+        None,
+    ),
+    (
+        "moderation",
+        "allowed_domains",
+        "Links to these domains and their subdomains are fine from anyone.",
+        Some("[\"clips.twitch.tv\", \"youtube.com\"]"),
+    ),
+    (
+        "moderation",
+        "banned_terms",
+        "Messages with them are removed, punishment is \"delete\", \"timeout <seconds>\" or \"ban\".",
+        Some("[{ term = \"buy followers\", punishment = \"timeout 600\" }, { term = \"f[o0]llow ?b[o0]ts\", regex = true, punishment = \"ban\" }]"),
+    ),
+    (
+        "moderation",
+        "banned_term_warning",
+        "Told the user whose message with a banned term was removed.",
+        Some("\"please keep it friendly.\""),
+    ),
+    (
+        "moderation",
+        "caps_filter",
+        "Whether users below caps_level are warned about too many capitals, and then their messages deleted.",
+        None,
+    ),
+    (
+        "moderation",
+        "caps_level",
+        "Who may write in capitals, like link_level.",
+        None,
+    ),
+    (
+        "moderation",
+        "caps_min_length",
+        "Messages with fewer letters are never too loud, emotes, digits and letters without case don't count.",
+        None,
+    ),
+    (
+        "moderation",
+        "caps_max_percent",
+        "The share of capitals among the letters a message may have.",
+        None,
+    ),
+    (
+        "moderation",
+        "caps_window",
+        "Seconds after a warning in which another loud message is deleted.",
+        None,
+    ),
+    (
+        "moderation",
+        "emote_filter",
+        "Whether messages of users below emote_level with too many emotes are deleted.",
+        None,
+    ),
+    (
+        "moderation",
+        "emote_level",
+        "Who may spam emotes, like link_level.",
+        None,
+    ),
+    (
+        "moderation",
+        "max_emotes",
+        "Emotes a message may have, a word repeated 3 times counts as emote of BTTV or FFZ.",
+        None,
+    ),
+    (
+        "moderation",
+        "emote_max_percent",
+        "The share of the words that may be emotes once there are 6, messages of only emotes just count.",
+        None,
+    ),
+    (
+        "moderation",
+        "symbol_filter",
+        "Whether messages of users below symbol_level that are mostly symbols, zalgo or walls are deleted.",
+        None,
+    ),
+    (
+        "moderation",
+        "symbol_level",
+        "Who may spam symbols, like link_level.",
+        None,
+    ),
+    (
+        "moderation",
+        "symbol_min_length",
+        "Shorter messages may be only symbols, whitespace doesn't count.",
+        None,
+    ),
+    (
+        "moderation",
+        "symbol_max_percent",
+        "The share of the characters that may be neither letters nor digits, of any script.",
+        None,
+    ),
+    (
+        "moderation",
+        "max_combining_marks",
+        "Combining marks a character may have, zalgo stacks many of them.",
+        None,
+    ),
+    (
+        "moderation",
+        "max_repeated_chars",
+        "How often a character may be repeated in a row, like the blocks of ASCII art.",
+        None,
+    ),
+    (
+        "moderation",
+        "similarity_filter",
+        "Whether messages of users below similarity_level are deleted when they are like their own recent messages, or like spam of another user.",
+        None,
+    ),
+    (
+        "moderation",
+        "similarity_level",
+        "Who may repeat messages, like link_level.",
+        None,
+    ),
+    (
+        "moderation",
+        "similarity_percent",
+        "How much of the longer of two messages has to be the same for them to be alike, ignoring case, emotes and punctuation.",
+        None,
+    ),
+    (
+        "moderation",
+        "similarity_min_length",
+        "Shorter messages are never compared, counting letters and digits.",
+        None,
+    ),
+    (
+        "moderation",
+        "similarity_window",
+        "Seconds a message is compared with the messages after it.",
+        None,
+    ),
+    (
+        "moderation",
+        "similarity_messages",
+        "The most recent messages of a channel compared with, at most 100.",
+        None,
+    ),
+    (
+        "moderation",
+        "strike_decay",
+        "Seconds until a strike of a filter expires, each one on its own.",
+        None,
+    ),
+    (
+        "moderation",
+        "strike_ladder",
+        "The punishment for the first, second, ... strike, the last one for any more.",
+        None,
+    ),
+    (
+        "moderation",
+        "nuke_filter",
+        "Seconds a phrase stays filtered when `!nuke` found no message with it.",
+        None,
+    ),
+    (
+        "moderation",
+        "shield_message",
+        "Said when shield mode turns on, links are filtered then. Empty for nothing.",
+        None,
+    ),
+    (
+        "moderation",
+        "shield_off_message",
+        "Said when shield mode turns off, empty for nothing.",
+        None,
+    ),
+    (
+        "points",
+        "enabled",
+        "Whether viewers earn points and the points commands answer.",
+        None,
+    ),
+    (
+        "points",
+        "interval_points",
+        "Points for every 5 minutes a viewer is in chat, joined or writing.",
+        None,
+    ),
+    (
+        "points",
+        "message_points",
+        "Points for a chat message, at most once per message_cooldown.",
+        None,
+    ),
+    (
+        "points",
+        "message_cooldown",
+        "Seconds until a viewer's next message earns points again.",
+        None,
+    ),
+    (
+        "points",
+        "sub_percent",
+        "The share of the points subscribers earn, 150 is half again as many as other viewers.",
+        None,
+    ),
+    (
+        "points",
+        "peer_give",
+        "Whether viewers may give their own points to others with `!give`, moderators always may.",
+        None,
+    ),
+    (
+        "points",
+        "gambling",
+        "Whether `!gamble` and `!slots` answer, false stops every game right away.",
+        None,
+    ),
+    (
+        "points",
+        "gamble_cooldown",
+        "Seconds a user waits between two games, further ones are ignored.",
+        None,
+    ),
+    (
+        "points",
+        "gamble_win_percent",
+        "The chance to win `!gamble` in percent.",
+        None,
+    ),
+    (
+        "points",
+        "gamble_payout_percent",
+        "A win of `!gamble` pays back this share of the wager, 200 doubles it.",
+        None,
+    ),
+    (
+        "points",
+        "slots_symbols",
+        "The symbols on each reel of `!slots`, three of one pay back payout_percent of the wager.",
+        None,
+    ),
+    (
+        "points",
+        "slots_pair_percent",
+        "Two of the same symbol pay back this share of the wager.",
+        None,
+    ),
+    (
+        "raffle",
+        "keyword_match",
+        "\"word\" enters users with the keyword anywhere in their message, \"message\" only with the keyword alone.",
+        None,
+    ),
+    (
+        "raffle",
+        "followers_only",
+        "Whether only followers of the channel are entered, the token needs the scope moderator:read:followers.",
+        None,
+    ),
+    (
+        "raffle",
+        "subs_only",
+        "Whether only subscribers are entered.",
+        None,
+    ),
+    (
+        "raffle",
+        "min_watch_minutes",
+        "Minutes a user has to be in chat before entering, since the bot saw them join or write.",
+        None,
+    ),
+    (
+        "raffle",
+        "sub_tickets",
+        "How many entries a subscriber gets, more give them a better chance.",
+        None,
+    ),
+    (
+        "raffle",
+        "tier_scaled",
+        "Whether tier 2 subscribers get twice the sub_tickets and tier 3 subscribers three times.",
+        None,
+    ),
+    (
+        "raffle",
+        "min_total_watch_minutes",
+        "Minutes of watch time an entrant needs to win, counted with watch_time.enabled.",
+        None,
+    ),
+    (
+        "raffle",
+        "min_points",
+        "Points an entrant needs to win, they aren't spent.",
+        None,
+    ),
+    (
+        "raffle",
+        "exclude_winners_days",
+        "Days in which a winner of the channel can't win again, 0 for no limit.",
+        None,
+    ),
+    (
+        "raffle",
+        "luck_decay_days",
+        "Days in which a win lowers the chance of the winner, by luck_decay_percent for each win.",
+        None,
+    ),
+    (
+        "raffle",
+        "luck_decay_percent",
+        "The share of the chance each recent win takes, 0 for no luck decay.",
+        None,
+    ),
+    (
+        "raffle",
+        "whisper_winners",
+        "Whether the winners are whispered as well, the token needs the scope user:manage:whispers.",
+        None,
+    ),
+    (
+        "trivia",
+        "questions",
+        "The file with the questions of `!trivia`, JSON if it ends with .json and TOML otherwise.",
+        Some("\"trivia.toml\""),
+    ),
+    (
+        "trivia",
+        "answer_time",
+        "Seconds chat has to answer a question.",
+        None,
+    ),
+    (
+        "trivia",
+        "hint",
+        "Whether the first letters of the answer are shown halfway through the time.",
+        None,
+    ),
+    (
+        "trivia",
+        "points",
+        "Points for the first right answer, paid with the points enabled.",
+        None,
+    ),
+    (
+        "songs",
+        "enabled",
+        "Whether users request songs with `!sr`, the queue is kept in songs.json in the storage directory for a player.",
+        None,
+    ),
+    (
+        "songs",
+        "per_user",
+        "How many songs of one user may wait in the queue.",
+        None,
+    ),
+    (
+        "queue",
+        "sub_priority",
+        "Whether subscribers join the viewer queue ahead of everyone else, behind the other subscribers.",
+        None,
+    ),
+    (
+        "queue",
+        "drop_parted",
+        "Whether users who part the channel lose their spot in the viewer queue.",
+        None,
+    ),
+    (
+        "queue",
+        "part_grace_minutes",
+        "Minutes a user who parted has to come back before losing their spot.",
+        None,
+    ),
+    (
+        "reminders",
+        "per_user",
+        "How many reminders may wait for one user.",
+        None,
+    ),
+    (
+        "reminders",
+        "whisper",
+        "Whether reminders are whispered, in chat only for users who don't allow whispers. The token needs the scope user:manage:whispers.",
+        None,
+    ),
+    (
+        "lurk",
+        "message",
+        "The answer to `!lurk`, $(user) is the name of the lurker.",
+        None,
+    ),
+    (
+        "lurk",
+        "end_on_message",
+        "Whether the next chat message of a lurker ends the lurk, not only `!unlurk`.",
+        None,
+    ),
+    (
+        "lurk",
+        "welcome_back",
+        "Sent when a lurk ends, $(user) is the name of the lurker and $(duration) how long they lurked. Empty says nothing.",
+        None,
+    ),
+    (
+        "watch_time",
+        "enabled",
+        "Whether the bot counts how long viewers watch while the stream is live, kept in watch_time.json in the storage directory.",
+        None,
+    ),
+    (
+        "watch_time",
+        "max_presence_minutes",
+        "Minutes a viewer who joined counts as watching at most without writing or parting, in case twitch misses the part.",
+        None,
+    ),
+    (
+        "emotes",
+        "extra_names",
+        "Third-party emotes like BTTV's, FFZ's or 7TV's, counted where they are written as a whole word. Twitch's own emotes are always counted.",
+        Some("[\"catJAM\", \"KEKW\"]"),
+    ),
+    (
+        "imitate",
+        "enabled",
+        "Whether the bot learns from chat for `!imitate`, the models are kept in imitate.json in the storage directory.",
+        None,
+    ),
+    (
+        "imitate",
+        "order",
+        "How many words before the next one pick it, more sound more like chat but repeat it more.",
+        None,
+    ),
+    (
+        "imitate",
+        "max_words",
+        "The most words of a sentence made up.",
+        None,
+    ),
+    (
+        "imitate",
+        "min_user_messages",
+        "How many messages of a user the bot learns before it imitates them.",
+        None,
+    ),
+    (
+        "imitate",
+        "max_ngrams",
+        "How many word sequences the bot keeps of all channels and users, the least recently used are forgotten.",
+        None,
+    ),
+    (
+        "poll",
+        "duration",
+        "Seconds a poll of `!poll start` takes votes before the results are announced, and a poll of `!twitchpoll` without its seconds.",
+        None,
+    ),
+    (
+        "poll",
+        "sub_weight",
+        "How many votes the vote of a subscriber counts as, 1 counts everyone the same.",
+        None,
+    ),
+    (
+        "redemptions",
+        "fulfill",
+        "Whether redemptions are marked fulfilled after their actions, only works for rewards created with the bot's client id.",
+        None,
+    ),
+    (
+        "redemptions",
+        "rewards",
+        "What the bot does when a reward is redeemed, by its title or id. The message and command may use $(user), $(channel), $(input), $(reward) and $(cost).",
+        Some("[{ reward = \"Hydrate\", message = \"Drink some water, $(channel)! Says $(user)\", command = \"\", webhook = \"\" }]"),
+    ),
+    (
+        "output",
+        "chat_export",
+        "Export chat messages as JSON lines to \"stdout\" or a file.",
+        Some("\"stdout\""),
+    ),
+    (
+        "output",
+        "chat_log",
+        "Log every received event as IRC line to \"stdout\" or a file.",
+        Some("\"chat.log\""),
+    ),
+    (
+        "chat_logs",
+        "enabled",
+        "Write chat to a file for each channel and day in the directory.",
+        None,
+    ),
+    (
+        "chat_logs",
+        "directory",
+        "Where the chat logs are written, it is created when needed.",
+        None,
+    ),
+    (
+        "chat_logs",
+        "format",
+        "`text` for lines people read, `jsonl` for a JSON object per line like the chat export.",
+        None,
+    ),
+    (
+        "chat_logs",
+        "max_kilobytes",
+        "A day's log is continued in another file past this size, 0 starts a new file only each day.",
+        None,
+    ),
+    (
+        "chat_logs",
+        "keep_days",
+        "Logs older than this many days are deleted, 0 keeps them all.",
+        None,
+    ),
+    (
+        "export",
+        "directory",
+        "Where `export` and `!export` write the CSV files of the chat logs, it is created when needed.",
+        None,
+    ),
+    (
+        "logging",
+        "format",
+        "`pretty` for lines people read, `json` for a JSON object per line.",
+        None,
+    ),
+    (
+        "logging",
+        "filter",
+        "The level of the log, and of the modules after it, e.g. \"info,chatbot::helix=debug\". Levels are off, error, warn, info, debug and trace.",
+        None,
+    ),
+    (
+        "http",
+        "listen",
+        "The address the bot answers HTTP requests on, `/metrics` for Prometheus, `/healthz` and `/readyz`. Nothing is listened to without it.",
+        Some("\"127.0.0.1:9100\""),
+    ),
+    (
+        "http",
+        "stale_after",
+        "Seconds without anything read from twitch before `/healthz` fails, longer than `connection.keepalive`.",
+        None,
+    ),
+    (
+        "http",
+        "max_queue",
+        "Lines waiting to be sent to chat before `/readyz` fails.",
+        None,
+    ),
+    (
+        "http",
+        "api_token",
+        "The bearer token for the API under `/api/`, at least 16 characters. The API is off without it.",
+        Some("\"a long random secret\""),
+    ),
+    ("webhooks", "secret", "The HMAC-SHA256 key of the `X-Chatbot-Signature` header, at least 16 characters, needed for any webhook.", Some("\"another long random secret\"")),
+    ("webhooks", "retries", "How often a delivery is tried again after a server error or a timeout, waiting twice as long each time.", None),
+    ("webhooks", "timeout", "Seconds each attempt may take.", None),
+    ("webhooks", "follow", "URLs told about new followers, from EventSub.", Some("[\"https://example.com/hooks/follow\"]")),
+    ("webhooks", "sub", "URLs told about subs, resubs and gifted subs.", None),
+    ("webhooks", "raid", "URLs told about raids.", None),
+    ("webhooks", "first_chat", "URLs told about the first message of a user in the channel.", None),
+    ("webhooks", "command", "URLs told about the commands the bot answered.", None),
+    ("webhooks", "moderation", "URLs told about timeouts, bans, cleared chats and deleted messages.", None),
+    ("discord", "webhook_url", "The Discord webhook go-live announcements, raids, subs and moderation summaries are posted to. Needs the `discord` feature.", Some("\"https://discord.com/api/webhooks/123/abc\"")),
+    ("discord", "bot_token", "The token of a Discord bot relaying chat to `channel_id`, the announcements go there too without a webhook.", None),
+    ("discord", "channel_id", "The id of the Discord channel chat is relayed to.", Some("\"123456789012345678\"")),
+    ("discord", "relay_per_minute", "Lines of each chatter relayed a minute, the others are left out.", None),
+    ("discord", "summary_interval", "Seconds between the summaries of the timeouts, bans and deleted messages, 0 for none.", None),
+    ("obs", "url", "The obs-websocket server of OBS (Tools, WebSocket Server Settings), the `scene`, `mute` and `show` commands need it.", Some("\"ws://127.0.0.1:4455\"")),
+    ("obs", "password", "The server's password, if authentication is on.", None),
+    ("obs", "show_seconds", "How long `show` shows a source when no number of seconds is given.", None),
+    ("tts", "sink", "Where the text to speech entries are written as JSON lines: `unix:` and the path of a Unix socket, `tcp:` and a host and port, or `pipe:` and the path of a named pipe.", Some("\"tcp:127.0.0.1:5555\"")),
+    ("tts", "rewards", "The titles or ids of the rewards whose input is read out.", Some("[\"Read my message\"]")),
+    ("tts", "users", "The logins whose chat messages are read out.", None),
+    ("tts", "pattern", "A regex, the chat messages matching it are read out. Commands never are.", Some("\"(?i)^tts \"")),
+    ("tts", "voice", "The voice hint of the entries, unless `voices` has one for the reward or the user.", None),
+    ("tts", "voices", "Voice hints by reward title or login.", Some("{ carkhy = \"robot\" }")),
+    ("tts", "max_emotes", "Emotes after this many are left out of the text.", None),
+    ("tts", "queue", "Entries waiting for a slow program, the oldest is dropped for a new one.", None),
+    ("control", "socket", "The path of a Unix socket taking newline-delimited JSON commands, e.g. from a stream deck or a shell script. Only the bot's user may connect.", Some("\"/run/user/1000/chatbot.sock\"")),
+    ("control", "max_clients", "Clients connected to the socket at once, more are turned away.", None),
+    ("control", "timeout", "Seconds a client may take to send a request, and the bot to answer it, before it is disconnected.", None),
+    (
+        "storage",
+        "backend",
+        "`json` keeps the data in JSON files in the directory, `sqlite` in the database chatbot.sqlite3 there, `memory` keeps nothing after a stop.",
+        None,
+    ),
+    (
+        "storage",
+        "directory",
+        "Custom commands and quotes are kept here, it is created when needed.",
+        None,
+    ),
+    (
+        "storage",
+        "write_delay",
+        "Seconds a change to a JSON file may wait before it is written, the changes meanwhile are written at once.",
+        None,
+    ),
+    (
+        "timers",
+        "messages",
+        "Sent every interval seconds, but only after min_messages chat lines since the last time. With announce = \"blue\" or another color a timer is a twitch announcement.",
+        Some("[{ channel = \"#captaincallback\", name = \"socials\", text = \"Follow me on ...\", interval = 900, min_messages = 5, announce = \"purple\" }]"),
+    ),
+    (
+        "timers",
+        "only_live",
+        "Timers only fire while the channel is live.",
+        None,
+    ),
+    (
+        "schedule",
+        "timezone",
+        "The time zone of the cron expressions: a name like \"Europe/Berlin\" from the system's zoneinfo, or a POSIX rule like \"CET-1CEST,M3.5.0,M10.5.0/3\".",
+        None,
+    ),
+    (
+        "schedule",
+        "entries",
+        "Sent at the times of cron, minute hour day month weekday, either a text with $(date) and $(time) or a command run as an admin. Skipped while the bot hasn't joined the channel, and with only_live while it is offline. Times missed while the bot was stopped are left out, with catch_up = true the last one is made up at the start.",
+        Some("[{ name = \"game night\", cron = \"0 20 * * sat\", channel = \"#captaincallback\", text = \"Community game night starts now!\", only_live = false, catch_up = false }]"),
+    ),    (
+        "channels",
+        "carkhy",
+        "What differs for a channel the bot joins: commands.prefix and denial, moderation.banned_terms and banned_term_warning, points.enabled, interval_points, message_points, message_cooldown and sub_percent, and timers.messages, whose channel may be left out. Values replace the global ones, a list under a key with a leading '+' is appended to. validate-config shows what each channel ends up with.",
+        Some("{ commands = { prefix = \"?\" }, points = { enabled = false }, moderation = { \"+banned_terms\" = [{ term = \"spoiler\" }] } }"),
+    ),
+];
+
+fn has_table(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Table(_) => true,
+        toml::Value::Array(items) => items.iter().any(has_table),
+        _ => false,
+    }
+}
+
+// `[{ bits = 100, message = "..." }]` on a single line
+fn inline(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) => {
+            let entries: Vec<_> = table
+                .iter()
+                .map(|(key, value)| format!("{} = {}", key, inline(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        toml::Value::Array(items) => {
+            let items: Vec<_> = items.iter().map(inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+impl Config {
+    /// Read the config file, then let environment variables and `.env` override it.
+    /// Without a path the default file is read if there is one.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        dotenv().ok();
+        let mut config = match path {
+            Some(path) => Self::read(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        config.apply_prefixed_env(env::vars())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    // the environment variables from before there was a config file
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(value) = var("TWITCH_CHANNEL") {
+            self.twitch.channels = parse_channel_names(&value);
+        }
+        if let Some(value) = var("TWITCH_CHAT_USER") {
+            self.twitch.user = value;
+        }
+        if let Some(value) = var("TWITCH_AUTH_CLIENT_ID") {
+            self.twitch.client_id = value;
+        }
+        if let Some(value) = var("TWITCH_AUTH_CLIENT_SECRET") {
+            self.twitch.client_secret = value;
+        }
+        if let Some(value) = var("TWITCH_CHAT_ANONYMOUS") {
+            self.twitch.anonymous = value == "1";
+        }
+        if let Some(value) = var("TWITCH_CHAT_CAPABILITIES") {
+            self.twitch.capabilities = value.split_whitespace().map(String::from).collect();
+        }
+        if let Some(value) = var("TWITCH_CHAT_TRANSPORT") {
+            self.connection.transport = parse_variant("TWITCH_CHAT_TRANSPORT", value)?;
+        }
+        if let Some(value) = var("TWITCH_CHAT_SECURITY") {
+            self.connection.security = parse_variant("TWITCH_CHAT_SECURITY", value)?;
+        }
+        if let Some(value) = var("TWITCH_CHAT_TLS_VERIFY") {
+            self.connection.verify_certificates = value != "0";
+        }
+        if let Some(value) = var("TWITCH_CHAT_SERVER") {
+            self.connection.server = Some(value);
+        }
+        if let Some(value) = var("TWITCH_CHAT_KEEPALIVE") {
+            self.connection.keepalive = parse_seconds("TWITCH_CHAT_KEEPALIVE", &value)?;
+        }
+        if let Some(value) = var("FORWARD_PINGS") {
+            self.connection.forward_pings = value == "1";
+        }
+        if let Some(value) = var("TWITCH_CHAT_DUPLICATES") {
+            self.chat.duplicates = parse_variant("TWITCH_CHAT_DUPLICATES", value)?;
+        }
+        if let Some(value) = var("TWITCH_CHAT_MESSAGE_TTL") {
+            self.chat.message_ttl = parse_seconds("TWITCH_CHAT_MESSAGE_TTL", &value)?;
+        }
+        if let Some(value) = var("GOODBYE_MESSAGE") {
+            self.chat.goodbye_message = Some(value);
+        }
+        if let Some(value) = var("CHAT_EXPORT") {
+            self.output.chat_export = Some(value);
+        }
+        if let Some(value) = var("CHAT_LOG") {
+            self.output.chat_log = Some(value);
+        }
+        Ok(())
+    }
+
+    // TWITCHBOT_<TABLE>__<KEY> for every key of the config file, applied after the others.
+    // Lists are separated by commas, a leading '+' appends to the list instead of replacing it
+    fn apply_prefixed_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        let mut vars: Vec<_> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        vars.sort();
+        for (name, value) in vars {
+            // the tables of channels aren't set from the environment
+            let (table, key) = FIELDS
+                .iter()
+                .map(|&(table, key, _, _)| (table, key))
+                .filter(|&(table, _)| table != "channels")
+                .find(|(table, key)| name == env_name(table, key))
+                .ok_or_else(|| invalid(&name, "is not a config key"))?;
+            let mut values = toml::Value::try_from(&*self).expect("Config is a table");
+            let section = values
+                .get_mut(table)
+                .and_then(toml::Value::as_table_mut)
+                .expect("every table is serialized");
+            let parsed = env_value(&name, section.get(key), value)?;
+            section.insert(key.to_owned(), parsed);
+            *self = values
+                .try_into()
+                .map_err(|error: toml::de::Error| invalid(&name, error.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Checks what serde can't, the first problem found is returned.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (index, channel) in self.twitch.channels.iter().enumerate() {
+            check_channel(format!("twitch.channels[{}]", index), channel)?;
+        }
+        if !self.twitch.anonymous {
+            let credentials = [
+                ("twitch.user", &self.twitch.user),
+                ("twitch.client_id", &self.twitch.client_id),
+                ("twitch.client_secret", &self.twitch.client_secret),
+            ];
+            for (field, value) in credentials {
+                if value.is_empty() {
+                    return Err(invalid(field, "is required unless twitch.anonymous is set"));
+                }
+            }
+        }
+        if let Some(login) = &self.broadcaster.login {
+            if self.twitch.anonymous {
+                return Err(invalid("broadcaster.login", "needs the bot to log in"));
+            }
+            if !self.channel_names().contains(&login.to_lowercase()) {
+                return Err(invalid(
+                    "broadcaster.login",
+                    "must be one of twitch.channels",
+                ));
+            }
+        }
+        if let Some(server) = &self.connection.server {
+            if parse_server(server).is_none() {
+                return Err(invalid(
+                    "connection.server",
+                    format!("{:?} is not host:port", server),
+                ));
+            }
+        }
+        let mut prefixes: Vec<_> = self
+            .commands
+            .channel_prefixes
+            .iter()
+            .map(|(channel, prefix)| (format!("commands.channel_prefixes.{}", channel), prefix))
+            .collect();
+        prefixes.sort();
+        prefixes.insert(0, ("commands.prefix".to_owned(), &self.commands.prefix));
+        for (field, prefix) in prefixes {
+            if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+                return Err(invalid(
+                    field,
+                    format!("{:?} must be a prefix without spaces, e.g. \"!\"", prefix),
+                ));
+            }
+        }
+        if self.commands.eightball_answers.is_empty() {
+            return Err(invalid(
+                "commands.eightball_answers",
+                "needs at least one answer",
+            ));
+        }
+        if self.connection.keepalive == 0 {
+            return Err(invalid("connection.keepalive", "must be at least 1 second"));
+        }
+        if self.chat.message_ttl == 0 {
+            return Err(invalid("chat.message_ttl", "must be at least 1 second"));
+        }
+        let mut tiers: Vec<_> = self
+            .events
+            .channel_bits_tiers
+            .iter()
+            .map(|(channel, tiers)| (format!("events.channel_bits_tiers.{}", channel), tiers))
+            .collect();
+        tiers.sort_by(|(a, _), (b, _)| a.cmp(b));
+        tiers.insert(0, ("events.bits_tiers".to_owned(), &self.events.bits_tiers));
+        for (field, tiers) in tiers {
+            if let Some(index) = tiers.iter().position(|tier| tier.bits == 0) {
+                return Err(invalid(
+                    format!("{}[{}].bits", field, index),
+                    "must be at least 1 bit",
+                ));
+            }
+        }
+        if self.imitate.order == 0 {
+            return Err(invalid("imitate.order", "must be at least 1 word"));
+        }
+        if self.imitate.max_words == 0 {
+            return Err(invalid("imitate.max_words", "must be at least 1 word"));
+        }
+        if self.imitate.max_ngrams == 0 {
+            return Err(invalid(
+                "imitate.max_ngrams",
+                "must be at least 1 word sequence",
+            ));
+        }
+        if let Some(listen) = &self.http.listen {
+            if listen.parse::<std::net::SocketAddr>().is_err() {
+                return Err(invalid(
+                    "http.listen",
+                    format!(
+                        "{:?} must be an address and a port, e.g. \"127.0.0.1:9100\"",
+                        listen
+                    ),
+                ));
+            }
+        }
+        if self
+            .http
+            .api_token
+            .as_ref()
+            .is_some_and(|token| token.len() < 16)
+        {
+            return Err(invalid("http.api_token", "must be at least 16 characters"));
+        }
+        for (kind, urls) in self.webhooks.urls() {
+            for (index, url) in urls.iter().enumerate() {
+                if !url.starts_with("https://") && !url.starts_with("http://") {
+                    return Err(invalid(
+                        format!("webhooks.{}[{}]", kind, index),
+                        "must be an http:// or https:// url",
+                    ));
+                }
+            }
+        }
+        let hooked = self
+            .webhooks
+            .urls()
+            .iter()
+            .any(|(_, urls)| !urls.is_empty());
+        match &self.webhooks.secret {
+            Some(secret) if secret.len() < 16 => {
+                return Err(invalid("webhooks.secret", "must be at least 16 characters"))
+            }
+            None if hooked => {
+                return Err(invalid("webhooks.secret", "is needed to sign the webhooks"))
+            }
+            _ => {}
+        }
+        if self.webhooks.timeout == 0 {
+            return Err(invalid("webhooks.timeout", "must be at least 1 second"));
+        }
+        if let Some(url) = &self.discord.webhook_url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(invalid(
+                    "discord.webhook_url",
+                    "must be an http:// or https:// url",
+                ));
+            }
+        }
+        match (&self.discord.bot_token, &self.discord.channel_id) {
+            (Some(_), None) => {
+                return Err(invalid("discord.channel_id", "is needed for the relay"))
+            }
+            (None, Some(_)) => return Err(invalid("discord.bot_token", "is needed for the relay")),
+            (_, Some(id)) if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) => {
+                return Err(invalid(
+                    "discord.channel_id",
+                    "must be the channel's number, e.g. \"123456789012345678\"",
+                ))
+            }
+            _ => {}
+        }
+        if let Some(url) = &self.obs.url {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(invalid("obs.url", "must be a ws:// or wss:// url"));
+            }
+        }
+        if self.obs.show_seconds == 0 {
+            return Err(invalid("obs.show_seconds", "must be at least 1 second"));
+        }
+        if let Some(sink) = &self.tts.sink {
+            let address = ["unix:", "tcp:", "pipe:"]
+                .iter()
+                .find_map(|kind| sink.strip_prefix(kind));
+            if address.is_none_or(str::is_empty) {
+                return Err(invalid(
+                    "tts.sink",
+                    "must be unix:<path>, tcp:<host>:<port> or pipe:<path>",
+                ));
+            }
+        }
+        if let Some(pattern) = &self.tts.pattern {
+            if let Err(error) = Regex::new(pattern) {
+                return Err(invalid("tts.pattern", error.to_string()));
+            }
+        }
+        if self.tts.queue == 0 {
+            return Err(invalid("tts.queue", "must be at least 1"));
+        }
+        if self.control.max_clients == 0 {
+            return Err(invalid("control.max_clients", "must be at least 1"));
+        }
+        if self.control.timeout == 0 {
+            return Err(invalid("control.timeout", "must be at least 1 second"));
+        }
+        // an idle chat is read from only after the keepalive's PING
+        if self.http.stale_after <= self.connection.keepalive {
+            return Err(invalid(
+                "http.stale_after",
+                "must be longer than connection.keepalive",
+            ));
+        }
+        if let Err(reason) = self.logging.directives() {
+            return Err(invalid("logging.filter", reason));
+        }
+        if self.poll.duration == 0 {
+            return Err(invalid("poll.duration", "must be at least 1 second"));
+        }
+        if self.poll.sub_weight == 0 {
+            return Err(invalid("poll.sub_weight", "must be at least 1 vote"));
+        }
+        for (index, reward) in self.redemptions.rewards.iter().enumerate() {
+            let field = format!("redemptions.rewards[{}]", index);
+            if reward.reward.trim().is_empty() {
+                return Err(invalid(
+                    format!("{}.reward", field),
+                    "needs the title or id of the reward",
+                ));
+            }
+            if reward.message.is_empty() && reward.command.is_empty() && reward.webhook.is_empty() {
+                return Err(invalid(field, "needs a message, a command or a webhook"));
+            }
+            if !reward.webhook.is_empty()
+                && !reward.webhook.starts_with("https://")
+                && !reward.webhook.starts_with("http://")
+            {
+                return Err(invalid(
+                    format!("{}.webhook", field),
+                    "must be an http:// or https:// url",
+                ));
+            }
+        }
+        if self.points.gamble_win_percent > 100 {
+            return Err(invalid(
+                "points.gamble_win_percent",
+                "must be a percentage from 0 to 100",
+            ));
+        }
+        if self.points.slots_symbols.is_empty() {
+            return Err(invalid("points.slots_symbols", "needs at least one symbol"));
+        }
+        if let Some(index) = self
+            .points
+            .slots_symbols
+            .iter()
+            .position(|symbol| symbol.symbol.trim().is_empty())
+        {
+            return Err(invalid(
+                format!("points.slots_symbols[{}].symbol", index),
+                "must not be empty",
+            ));
+        }
+        if self.raffle.sub_tickets == 0 {
+            return Err(invalid("raffle.sub_tickets", "must be at least 1 entry"));
+        }
+        if self.raffle.min_total_watch_minutes > 0 && !self.watch_time.enabled {
+            return Err(invalid(
+                "raffle.min_total_watch_minutes",
+                "needs watch_time.enabled",
+            ));
+        }
+        if self.reminders.per_user == 0 {
+            return Err(invalid("reminders.per_user", "must be at least 1 reminder"));
+        }
+        if self.songs.per_user == 0 {
+            return Err(invalid("songs.per_user", "must be at least 1 song"));
+        }
+        if self.trivia.answer_time < 2 {
+            return Err(invalid("trivia.answer_time", "must be at least 2 seconds"));
+        }
+        if self.watch_time.max_presence_minutes == 0 {
+            return Err(invalid(
+                "watch_time.max_presence_minutes",
+                "must be at least 1 minute",
+            ));
+        }
+        for (index, timer) in self.timers.messages.iter().enumerate() {
+            let field = format!("timers.messages[{}]", index);
+            check_channel(format!("{}.channel", field), &timer.channel)?;
+            if timer.interval == 0 {
+                return Err(invalid(
+                    format!("{}.interval", field),
+                    "must be at least 1 second",
+                ));
+            }
+            let same_name = |other: &TimerConfig| {
+                other.channel.eq_ignore_ascii_case(&timer.channel) && other.name == timer.name
+            };
+            if self.timers.messages[..index].iter().any(same_name) {
+                return Err(invalid(
+                    format!("{}.name", field),
+                    format!("{:?} is used twice in {}", timer.name, timer.channel),
+                ));
+            }
+        }
+        if let Err(error) = TimeZone::load(&self.schedule.timezone) {
+            return Err(invalid("schedule.timezone", error.to_string()));
+        }
+        for (index, entry) in self.schedule.entries.iter().enumerate() {
+            let field = format!("schedule.entries[{}]", index);
+            check_channel(format!("{}.channel", field), &entry.channel)?;
+            if let Err(error) = Cron::parse(&entry.cron) {
+                return Err(invalid(format!("{}.cron", field), error.to_string()));
+            }
+            if entry.text.is_some() == entry.command.is_some() {
+                return Err(invalid(field, "needs either text or command"));
+            }
+            let same_name = |other: &ScheduledConfig| other.name == entry.name;
+            if self.schedule.entries[..index].iter().any(same_name) {
+                return Err(invalid(
+                    format!("{}.name", field),
+                    format!("{:?} is used twice", entry.name),
+                ));
+            }
+        }
+        let percentages = [
+            (
+                "moderation.caps_max_percent",
+                self.moderation.caps_max_percent,
+            ),
+            (
+                "moderation.emote_max_percent",
+                self.moderation.emote_max_percent,
+            ),
+            (
+                "moderation.symbol_max_percent",
+                self.moderation.symbol_max_percent,
+            ),
+            (
+                "moderation.similarity_percent",
+                self.moderation.similarity_percent,
+            ),
+            ("raffle.luck_decay_percent", self.raffle.luck_decay_percent),
+        ];
+        for (field, percent) in percentages {
+            if percent > 100 {
+                return Err(invalid(field, "must be a percentage from 0 to 100"));
+            }
+        }
+        if self.moderation.similarity_messages > MAX_SIMILARITY_MESSAGES {
+            return Err(invalid(
+                "moderation.similarity_messages",
+                format!("must be at most {} messages", MAX_SIMILARITY_MESSAGES),
+            ));
+        }
+        if self.moderation.strike_ladder.is_empty() {
+            return Err(invalid(
+                "moderation.strike_ladder",
+                "needs a punishment for the first strike",
+            ));
+        }
+        for (index, punishment) in self.moderation.strike_ladder.iter().enumerate() {
+            if *punishment == Punishment::Timeout(0) {
+                return Err(invalid(
+                    format!("moderation.strike_ladder[{}]", index),
+                    "a timeout must be at least 1 second",
+                ));
+            }
+        }
+        for (index, banned) in self.moderation.banned_terms.iter().enumerate() {
+            let field = format!("moderation.banned_terms[{}]", index);
+            if banned.term.trim().is_empty() {
+                return Err(invalid(format!("{}.term", field), "must not be empty"));
+            }
+            if banned.regex {
+                if let Err(error) = Regex::new(&banned.term) {
+                    return Err(invalid(format!("{}.term", field), error.to_string()));
+                }
+            }
+            if banned.punishment == Punishment::Timeout(0) {
+                return Err(invalid(
+                    format!("{}.punishment", field),
+                    "a timeout must be at least 1 second",
+                ));
+            }
+        }
+        self.validate_channels()
+    }
+
+    // overrides only for channels the bot joins, of the keys that may differ, which have to
+    // make a valid config merged into the global one
+    fn validate_channels(&self) -> Result<(), ConfigError> {
+        let names = self.channel_names();
+        for (channel, overrides) in &self.channels {
+            let field = format!("channels.{}", channel);
+            if !names.contains(channel) {
+                return Err(invalid(
+                    field,
+                    "is not in twitch.channels, the name is lowercase without the '#'",
+                ));
+            }
+            for (table, values) in &overrides.0 {
+                let field = format!("{}.{}", field, table);
+                let Some(&(_, keys)) = CHANNEL_KEYS.iter().find(|(name, _)| name == table) else {
+                    return Err(invalid(field, "can't differ between channels"));
+                };
+                let Some(values) = values.as_table() else {
+                    return Err(invalid(field, "must be a table"));
+                };
+                for key in values.keys() {
+                    let field = format!("{}.{}", field, key);
+                    let appended = key.strip_prefix('+');
+                    if !keys.contains(&appended.unwrap_or(key)) {
+                        return Err(invalid(field, "can't differ between channels"));
+                    }
+                    let list = format!("{}.{}", table, appended.unwrap_or(key));
+                    if appended.is_some() && !CHANNEL_LISTS.contains(&list.as_str()) {
+                        return Err(invalid(field, "only lists can be appended to"));
+                    }
+                }
+            }
+            for (key, timer) in own_timers(&overrides.0) {
+                let other = timer.get("channel").and_then(toml::Value::as_str);
+                if other.is_some_and(|other| other.trim_start_matches('#') != channel) {
+                    return Err(invalid(
+                        format!("{}.timers.{}", field, key),
+                        "the timers of a channel are sent to it, leave out the channel",
+                    ));
+                }
+            }
+            let config = self.merged(channel)?;
+            config.validate().map_err(|error| match error {
+                ConfigError::InvalidValue { field: key, reason } => {
+                    invalid(format!("{}.{}", field, key), reason)
+                }
+                error => error,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The config a channel runs with, its `[channels.<name>]` merged into the global tables.
+    pub fn for_channel(&self, channel: &str) -> Cow<'_, Config> {
+        let channel = channel.trim_start_matches('#').to_lowercase();
+        let prefix = self.commands.channel_prefixes.contains_key(&channel);
+        if !prefix && !self.channels.contains_key(&channel) {
+            return Cow::Borrowed(self);
+        }
+        // validated when loaded, so this only fails for a config built in code
+        match self.merged(&channel) {
+            Ok(config) => Cow::Owned(config),
+            Err(_) => Cow::Borrowed(self),
+        }
+    }
+
+    fn merged(&self, channel: &str) -> Result<Config, ConfigError> {
+        let mut config = self.clone();
+        config.channels.clear();
+        // an override's prefix wins over the shorthand
+        if let Some(prefix) = config.commands.channel_prefixes.get(channel) {
+            config.commands.prefix = prefix.clone();
+        }
+        let Some(overrides) = self.channels.get(channel) else {
+            return Ok(config);
+        };
+        let mut overrides = overrides.0.clone();
+        let timers = overrides
+            .get_mut("timers")
+            .and_then(toml::Value::as_table_mut);
+        for list in timers
+            .into_iter()
+            .flat_map(|timers| timers.iter_mut().map(|(_, list)| list))
+        {
+            let timers = list
+                .as_array_mut()
+                .map(Vec::as_mut_slice)
+                .unwrap_or_default();
+            for timer in timers.iter_mut().filter_map(toml::Value::as_table_mut) {
+                timer
+                    .entry("channel")
+                    .or_insert_with(|| toml::Value::String(format!("#{}", channel)));
+            }
+        }
+        let mut values = toml::Value::try_from(&config).expect("Config is a table");
+        if let toml::Value::Table(values) = &mut values {
+            merge(values, &overrides);
+        }
+        values.try_into().map_err(|error: toml::de::Error| {
+            invalid(format!("channels.{}", channel), error.to_string())
+        })
+    }
+
+    /// The timers of every channel, a channel that sets its own has only those.
+    pub fn timer_messages(&self) -> Vec<TimerConfig> {
+        let own = |channel: &str| {
+            let overrides = self.channels.get(channel.trim_start_matches('#'));
+            overrides.is_some_and(|overrides| own_timers(&overrides.0).next().is_some())
+        };
+        let mut timers: Vec<TimerConfig> = self
+            .timers
+            .messages
+            .iter()
+            .filter(|timer| !own(&timer.channel.to_lowercase()))
+            .cloned()
+            .collect();
+        for channel in self.channels.keys().filter(|channel| own(channel)) {
+            let config = self.for_channel(channel);
+            let name = format!("#{}", channel);
+            timers.extend(
+                config
+                    .timers
+                    .messages
+                    .iter()
+                    .filter(|timer| timer.channel.to_lowercase() == name)
+                    .cloned(),
+            );
+        }
+        timers
+    }
+
+    /// What each channel with overrides ends up with for the keys that may differ, as comments.
+    pub fn effective_channels(&self) -> String {
+        let mut report = String::new();
+        for channel in self.channels.keys() {
+            let config =
+                toml::Value::try_from(&*self.for_channel(channel)).expect("Config is a table");
+            report.push_str(&format!("# effective for channel {}\n", channel));
+            for &(table, keys) in CHANNEL_KEYS {
+                for &key in keys {
+                    let Some(value) = config.get(table).and_then(|values| values.get(key)) else {
+                        continue;
+                    };
+                    report.push_str(&format!("# {}.{} = {}\n", table, key, inline(value)));
+                }
+            }
+        }
+        report
+    }
+
+    /// The annotated config file with every default, optional keys commented out.
+    /// The config as TOML, with the secrets that are set replaced.
+    pub fn redacted(&self) -> String {
+        let mut config = toml::Value::try_from(self).expect("Config is a table");
+        for (table, key) in SECRETS {
+            if let Some(value) = config.get_mut(table).and_then(|values| values.get_mut(key)) {
+                if value.as_str().is_some_and(|secret| !secret.is_empty()) {
+                    *value = toml::Value::String("<redacted>".to_owned());
+                }
+            }
+        }
+        toml::to_string(&config).expect("values are serializable")
+    }
+
+    pub fn example() -> String {
+        let defaults = toml::Value::try_from(Config::default()).expect("Config is a table");
+        let mut example = String::from(
+            "# Configuration of the chat bot, environment variables override these values.\n",
+        );
+        let mut section = "";
+        for &(table, key, description, unset) in FIELDS {
+            if table != section {
+                example.push_str(&format!("\n[{}]\n", table));
+                section = table;
+            }
+            example.push_str(&format!("# {}\n", description));
+            match defaults.get(table).and_then(|values| values.get(key)) {
+                // toml writes tables in a list as sections of their own
+                Some(value) if has_table(value) => {
+                    example.push_str(&format!("{} = {}\n", key, inline(value)));
+                }
+                Some(value) => {
+                    let mut line = toml::value::Table::new();
+                    line.insert(key.to_owned(), value.clone());
+                    example.push_str(&toml::to_string(&line).expect("values are serializable"));
+                }
+                None => example.push_str(&format!("# {} = {}\n", key, unset.unwrap_or("\"\""))),
+            }
+        }
+        example
+    }
+
+    /// The names of the channels to join, lowercase and without the leading '#'.
+    pub fn channel_names(&self) -> Vec<String> {
+        self.twitch
+            .channels
+            .iter()
+            .map(|channel| channel.trim_start_matches('#').to_lowercase())
+            .collect()
+    }
+
+    /// The application the broadcaster's token is for, the bot's unless another is set.
+    pub fn broadcaster_client(&self) -> (&str, &str) {
+        match self.broadcaster.client_id.is_empty() {
+            true => (&self.twitch.client_id, &self.twitch.client_secret),
+            false => (&self.broadcaster.client_id, &self.broadcaster.client_secret),
+        }
+    }
+
+    /// The host and port to connect to instead of twitch chat, only for test harnesses.
+    pub fn chat_server(&self) -> Option<(String, u16)> {
+        self.connection.server.as_deref().and_then(parse_server)
+    }
+
+    pub fn keepalive(&self) -> Duration {
+        Duration::from_secs(self.connection.keepalive)
+    }
+
+    pub fn message_ttl(&self) -> Duration {
+        Duration::from_secs(self.chat.message_ttl)
+    }
+}
+
+// the only section applied while running, the others are read once at startup
+const LIVE_TABLE: &str = "chat";
+
+/// The active config, replaced as a whole on reload so that readers never see half of one.
+#[derive(Debug, Clone)]
+pub struct SharedConfig(Arc<RwLock<Arc<Config>>>);
+
+/// The keys a reload changed, the ones that need a restart still have their old value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reload {
+    pub applied: Vec<String>,
+    pub requires_restart: Vec<String>,
+}
+
+impl fmt::Display for Reload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.applied.is_empty() && self.requires_restart.is_empty() {
+            return write!(f, "Reloaded the config, nothing changed");
+        }
+        write!(
+            f,
+            "Reloaded the config, applied: [{}]",
+            self.applied.join(", ")
+        )?;
+        if !self.requires_restart.is_empty() {
+            write!(
+                f,
+                ", requires restart: [{}]",
+                self.requires_restart.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(config))))
+    }
+
+    /// The config as it is right now, a reload doesn't change what was loaded before.
+    pub fn load(&self) -> Arc<Config> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Read the config file again, an invalid one leaves the active config as it is.
+    pub fn reload(&self, path: Option<&Path>) -> Result<Reload, ConfigError> {
+        Ok(self.apply(Config::load(path)?))
+    }
+
+    fn apply(&self, next: Config) -> Reload {
+        let mut active = self.0.write().unwrap();
+        let old = toml::Value::try_from(&**active).expect("Config is a table");
+        let new = toml::Value::try_from(&next).expect("Config is a table");
+        let mut reload = Reload::default();
+        for &(table, key, _, _) in FIELDS {
+            let value = |config: &toml::Value| config.get(table)?.get(key).cloned();
+            if value(&old) == value(&new) {
+                continue;
+            }
+            let field = format!("{}.{}", table, key);
+            if table == LIVE_TABLE {
+                reload.applied.push(field);
+            } else {
+                reload.requires_restart.push(field);
+            }
+        }
+        let mut config = (**active).clone();
+        config.chat = next.chat;
+        *active = Arc::new(config);
+        reload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, process};
+
+    const EXAMPLE: &str = include_str!("../config.example.toml");
+
+    fn config(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    fn error(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn channel_names_are_normalized() {
+        assert_eq!(
+            parse_channel_names("#CaptainCallback, carkhy,,"),
+            vec!["#captaincallback", "#carkhy"]
+        );
+    }
+
+    #[test]
+    fn server_needs_host_and_port() {
+        assert_eq!(
+            parse_server("127.0.0.1:6667"),
+            Some(("127.0.0.1".to_owned(), 6667))
+        );
+        assert_eq!(parse_server("127.0.0.1"), None);
+        assert_eq!(parse_server(":6667"), None);
+    }
+
+    #[test]
+    fn example_is_up_to_date() {
+        // run `cargo run -- --example-config > config.example.toml` after changing the config
+        assert_eq!(EXAMPLE, Config::example());
+        assert_eq!(config(EXAMPLE), Config::default());
+    }
+
+    #[test]
+    fn every_key_is_in_the_example() {
+        let mut config = Config::default();
+        config.connection.server = Some(String::new());
+        config.chat.goodbye_message = Some(String::new());
+        config.output.chat_export = Some(String::new());
+        config.output.chat_log = Some(String::new());
+        let values = toml::Value::try_from(config).unwrap();
+        for (table, keys) in values.as_table().unwrap() {
+            for key in keys.as_table().unwrap().keys() {
+                assert!(
+                    FIELDS
+                        .iter()
+                        .any(|field| field.0 == table && field.1 == key),
+                    "{}.{} is missing from FIELDS",
+                    table,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn missing_fields_get_defaults() {
+        let config =
+            config("[twitch]\nchannels = [\"#carkhy\"]\n\n[connection]\ntransport = \"tcp\"\n");
+        assert_eq!(config.channel_names(), vec!["carkhy"]);
+        assert_eq!(config.connection.transport, ChatTransport::Tcp);
+        assert_eq!(config.keepalive(), Duration::from_secs(240));
+        assert_eq!(config.twitch.capabilities.len(), 3);
+        assert_eq!(config.chat, ChatConfig::default());
+    }
+
+    #[test]
+    fn parse_errors_name_the_field() {
+        let error = toml::from_str::<Config>("[chat]\nmessage_ttl = -5\n").unwrap_err();
+        assert!(error.to_string().contains("chat.message_ttl"), "{}", error);
+        let error = toml::from_str::<Config>("[connection]\ntransport = \"irc\"\n").unwrap_err();
+        assert!(
+            error.to_string().contains("connection.transport"),
+            "{}",
+            error
+        );
+        let error = toml::from_str::<Config>("[chat]\ncooldown = 5\n").unwrap_err();
+        assert!(error.to_string().contains("cooldown"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_values_are_explained() {
+        let mut config =
+            config("[twitch]\nchannels = [\"#carkhy\", \"captaincallback\"]\nanonymous = true\n");
+        assert_eq!(
+            error(&config),
+            "Invalid value for twitch.channels[1]: \"captaincallback\" must start with '#', e.g. \"#captaincallback\""
+        );
+        config.twitch.channels.clear();
+        config.connection.keepalive = 0;
+        assert_eq!(
+            error(&config),
+            "Invalid value for connection.keepalive: must be at least 1 second"
+        );
+        config.twitch.anonymous = false;
+        assert_eq!(
+            error(&config),
+            "Invalid value for twitch.user: is required unless twitch.anonymous is set"
+        );
+        config.twitch.anonymous = true;
+        config.connection.keepalive = 30;
+        config.broadcaster.login = Some("carkhy".to_owned());
+        assert_eq!(
+            error(&config),
+            "Invalid value for broadcaster.login: needs the bot to log in"
+        );
+        let mut logged_in = Config::default();
+        logged_in.twitch.user = "carkhybot".to_owned();
+        logged_in.twitch.client_id = "id".to_owned();
+        logged_in.twitch.client_secret = "secret".to_owned();
+        logged_in.broadcaster.login = Some("carkhy".to_owned());
+        assert_eq!(
+            error(&logged_in),
+            "Invalid value for broadcaster.login: must be one of twitch.channels"
+        );
+        logged_in.broadcaster.login = Some("CaptainCallback".to_owned());
+        assert!(logged_in.validate().is_ok());
+        config.broadcaster.login = None;
+        config.logging.filter = "info,chatbot::helix=loud".to_owned();
+        assert_eq!(
+            error(&config),
+            "Invalid value for logging.filter: \"loud\" is no level, use off, error, warn, info, debug or trace"
+        );
+        config.logging.filter = "info".to_owned();
+        config.http.stale_after = 30;
+        assert_eq!(
+            error(&config),
+            "Invalid value for http.stale_after: must be longer than connection.keepalive"
+        );
+        config.http.api_token = Some("hunter2".to_owned());
+        assert_eq!(
+            error(&config),
+            "Invalid value for http.api_token: must be at least 16 characters"
+        );
+        config.http.api_token = None;
+        config.webhooks.raid = vec!["localhost:8080".to_owned()];
+        assert_eq!(
+            error(&config),
+            "Invalid value for webhooks.raid[0]: must be an http:// or https:// url"
+        );
+        config.webhooks.raid = vec!["http://localhost:8080".to_owned()];
+        assert_eq!(
+            error(&config),
+            "Invalid value for webhooks.secret: is needed to sign the webhooks"
+        );
+        config.webhooks.raid.clear();
+        config.discord.bot_token = Some("token".to_owned());
+        config.discord.channel_id = Some("#general".to_owned());
+        assert_eq!(
+            error(&config),
+            "Invalid value for discord.channel_id: must be the channel's number, e.g. \"123456789012345678\""
+        );
+        config.discord.bot_token = None;
+        config.discord.channel_id = None;
+        config.obs.url = Some("127.0.0.1:4455".to_owned());
+        assert_eq!(
+            error(&config),
+            "Invalid value for obs.url: must be a ws:// or wss:// url"
+        );
+        config.obs.url = None;
+        config.tts.sink = Some("udp:127.0.0.1:5555".to_owned());
+        assert_eq!(
+            error(&config),
+            "Invalid value for tts.sink: must be unix:<path>, tcp:<host>:<port> or pipe:<path>"
+        );
+        let mut config = self::config("[twitch]\nanonymous = true\n");
+        config.schedule.timezone = "Mars/Olympus_Mons".to_owned();
+        assert_eq!(
+            error(&config),
+            "Invalid value for schedule.timezone: no time zone \"Mars/Olympus_Mons\" in /usr/share/zoneinfo"
+        );
+        config.schedule.timezone = "CET-1CEST,M3.5.0,M10.5.0/3".to_owned();
+        config.schedule.entries = vec![ScheduledConfig {
+            name: "game night".to_owned(),
+            cron: "0 20 * * saturday".to_owned(),
+            channel: "#captaincallback".to_owned(),
+            text: Some("Game night!".to_owned()),
+            command: Some("!settitle Game night".to_owned()),
+            only_live: false,
+            catch_up: false,
+        }];
+        assert_eq!(
+            error(&config),
+            "Invalid value for schedule.entries[0].cron: \"saturday\" is no weekday, it goes from 0 to 7"
+        );
+        config.schedule.entries[0].cron = "0 20 * * sat".to_owned();
+        assert_eq!(
+            error(&config),
+            "Invalid value for schedule.entries[0]: needs either text or command"
+        );
+        config.schedule.entries.clear();
+        config.raffle.min_total_watch_minutes = 60;
+        assert_eq!(
+            error(&config),
+            "Invalid value for raffle.min_total_watch_minutes: needs watch_time.enabled"
+        );
+    }
+
+    #[test]
+    fn rewards_need_an_action() {
+        let mut config = config(
+            "[twitch]\nanonymous = true\n[redemptions]\nrewards = [\n\
+             { reward = \"Hydrate\", message = \"Drink some water!\" },\n\
+             { reward = \"Song\", webhook = \"localhost:8080\" },\n]\n",
+        );
+        assert_eq!(
+            error(&config),
+            "Invalid value for redemptions.rewards[1].webhook: must be an http:// or https:// url"
+        );
+        config.redemptions.rewards[1].webhook.clear();
+        assert_eq!(
+            error(&config),
+            "Invalid value for redemptions.rewards[1]: needs a message, a command or a webhook"
+        );
+    }
+
+    #[test]
+    fn timers_need_distinct_names() {
+        let mut config = config(
+            "[twitch]\nanonymous = true\n[timers]\nmessages = [\n\
+             { channel = \"#carkhy\", name = \"rules\", text = \"Be nice\", interval = 600 },\n\
+             { channel = \"#Carkhy\", name = \"rules\", text = \"Be kind\", interval = 0 },\n]\n",
+        );
+        assert_eq!(config.timers.messages[0].min_messages, 0);
+        assert_eq!(
+            error(&config),
+            "Invalid value for timers.messages[1].interval: must be at least 1 second"
+        );
+        config.timers.messages[1].interval = 900;
+        assert_eq!(
+            error(&config),
+            "Invalid value for timers.messages[1].name: \"rules\" is used twice in #Carkhy"
+        );
+    }
+
+    #[test]
+    fn banned_terms_need_a_valid_regex() {
+        let mut config = config(
+            "[twitch]\nanonymous = true\n[moderation]\nbanned_terms = [\n\
+             { term = \"spam\" },\n\
+             { term = \"buy\", punishment = \"timeout 0\" },\n\
+             { term = \"b[o0\", regex = true, punishment = \"ban\" },\n]\n",
+        );
+        assert_eq!(
+            config.moderation.banned_terms[0].punishment,
+            Punishment::Delete
+        );
+        assert_eq!(
+            error(&config),
+            "Invalid value for moderation.banned_terms[1].punishment: a timeout must be at least 1 second"
+        );
+        config.moderation.banned_terms[1].punishment = Punishment::Timeout(600);
+        assert!(error(&config)
+            .starts_with("Invalid value for moderation.banned_terms[2].term: regex parse error"));
+    }
+
+    const CHANNELS: &str = "[twitch]\nanonymous = true\n\
+                            channels = [\"#captaincallback\", \"#carkhy\", \"#rustlang\"]\n\
+                            [commands]\nchannel_prefixes = { carkhy = \"$\", rustlang = \"$\" }\n\
+                            [moderation]\nbanned_terms = [{ term = \"spam\" }]\n\
+                            [timers]\nmessages = [\n\
+                            { channel = \"#captaincallback\", name = \"rules\", text = \"Be nice\", interval = 600 },\n\
+                            { channel = \"#carkhy\", name = \"rules\", text = \"Be nice\", interval = 600 },\n]\n";
+
+    #[test]
+    fn channels_override_the_global_tables() {
+        let config = config(&format!(
+            "{}[channels.carkhy]\ncommands = {{ prefix = \"?\" }}\n\
+             points = {{ enabled = false }}\n\
+             moderation = {{ \"+banned_terms\" = [{{ term = \"spoiler\" }}] }}\n\
+             timers = {{ messages = [{{ name = \"socials\", text = \"Follow!\", interval = 900 }}] }}\n\
+             [channels.rustlang]\nmoderation = {{ banned_terms = [{{ term = \"crab\" }}] }}\n",
+            CHANNELS
+        ));
+        config.validate().unwrap();
+        let carkhy = config.for_channel("#Carkhy");
+        // the override wins over channel_prefixes, which wins over the global prefix
+        assert_eq!(carkhy.commands.prefix, "?");
+        assert_eq!(config.for_channel("rustlang").commands.prefix, "$");
+        assert!(matches!(
+            config.for_channel("captaincallback"),
+            Cow::Borrowed(_)
+        ));
+        assert!(!carkhy.points.enabled);
+        let terms = |config: &Config| -> Vec<String> {
+            let terms = config.moderation.banned_terms.iter();
+            terms.map(|banned| banned.term.clone()).collect()
+        };
+        assert_eq!(terms(&carkhy), ["spam", "spoiler"]);
+        assert_eq!(terms(&config.for_channel("rustlang")), ["crab"]);
+        assert_eq!(terms(&config), ["spam"]);
+        // a channel's own timers replace the global ones of it
+        let timers: Vec<_> = config
+            .timer_messages()
+            .into_iter()
+            .map(|timer| format!("{} {}", timer.channel, timer.name))
+            .collect();
+        assert_eq!(timers, ["#captaincallback rules", "#carkhy socials"]);
+        let report = config.effective_channels();
+        assert!(report.contains("# effective for channel carkhy\n# commands.prefix = \"?\"\n"));
+        assert!(report.contains("# points.enabled = false\n"));
+    }
+
+    #[test]
+    fn channel_overrides_are_validated() {
+        let invalid =
+            |overrides: &str| error(&config(&format!("{}[channels]\n{}\n", CHANNELS, overrides)));
+        assert_eq!(
+            invalid("Carkhy = { points = { enabled = false } }"),
+            "Invalid value for channels.Carkhy: is not in twitch.channels, the name is lowercase without the '#'"
+        );
+        assert_eq!(
+            invalid("carkhy = { storage = { backend = \"memory\" } }"),
+            "Invalid value for channels.carkhy.storage: can't differ between channels"
+        );
+        assert_eq!(
+            invalid("carkhy = { points = { gamble_max = 5 } }"),
+            "Invalid value for channels.carkhy.points.gamble_max: can't differ between channels"
+        );
+        assert_eq!(
+            invalid("carkhy = { commands = { \"+prefix\" = \"?\" } }"),
+            "Invalid value for channels.carkhy.commands.+prefix: only lists can be appended to"
+        );
+        assert_eq!(
+            invalid("carkhy = { points = { message_points = -1 } }"),
+            "Invalid value for channels.carkhy: invalid value: integer `-1`, expected u64 for key `points.message_points`"
+        );
+        assert_eq!(
+            invalid("carkhy = { timers = { messages = [{ channel = \"#rustlang\", name = \"a\", text = \"b\", interval = 60 }] } }"),
+            "Invalid value for channels.carkhy.timers.messages: the timers of a channel are sent to it, leave out the channel"
+        );
+        assert_eq!(
+            invalid("carkhy = { moderation = { banned_terms = [{ term = \"\" }] } }"),
+            "Invalid value for channels.carkhy.moderation.banned_terms[0].term: must not be empty"
+        );
+    }
+
+    #[test]
+    fn reload_applies_the_chat_settings() {
+        let shared = SharedConfig::new(Config::default());
+        let mut next = Config::default();
+        next.chat.message_ttl = 30;
+        next.chat.goodbye_message = Some("Bye!".to_owned());
+        next.twitch.channels.push("#carkhy".to_owned());
+        let reload = shared.apply(next);
+        assert_eq!(
+            reload.to_string(),
+            "Reloaded the config, applied: [chat.message_ttl, chat.goodbye_message], \
+             requires restart: [twitch.channels]"
+        );
+        let config = shared.load();
+        assert_eq!(config.message_ttl(), Duration::from_secs(30));
+        assert_eq!(config.chat.goodbye_message.as_deref(), Some("Bye!"));
+        assert_eq!(config.channel_names(), vec!["captaincallback"]);
+    }
+
+    #[test]
+    fn invalid_reload_keeps_the_config() {
+        let path = env::temp_dir().join(format!("chatbot-config-{}.toml", process::id()));
+        fs::write(
+            &path,
+            "[twitch]\nanonymous = true\n[chat]\nmessage_ttl = 0\n",
+        )
+        .unwrap();
+        let shared = SharedConfig::new(Config::default());
+        let before = shared.load();
+        let error = shared.reload(Some(&path)).unwrap_err();
+        assert!(error.to_string().contains("chat.message_ttl"), "{}", error);
+        fs::write(&path, "[chat\nmessage_ttl = 30\n").unwrap();
+        assert!(matches!(
+            shared.reload(Some(&path)),
+            Err(ConfigError::Parse { .. })
+        ));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(shared.load(), before);
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn prefixed_variables_override_config_keys() {
+        let mut config = config("[twitch]\nclient_secret = \"from the file\"\n");
+        config
+            .apply_prefixed_env(vars(&[
+                ("TWITCHBOT_TWITCH__CLIENT_SECRET", "from the environment"),
+                ("TWITCHBOT_CONNECTION__KEEPALIVE", "30"),
+                ("TWITCHBOT_CONNECTION__VERIFY_CERTIFICATES", "false"),
+                ("TWITCHBOT_CHAT__GOODBYE_MESSAGE", "Bye!"),
+                ("TWITCHBOT_CHAT__DUPLICATES", "vary"),
+                ("TWITCH_CHANNEL", "carkhy"),
+            ]))
+            .unwrap();
+        assert_eq!(config.twitch.client_secret, "from the environment");
+        assert_eq!(config.keepalive(), Duration::from_secs(30));
+        assert!(!config.connection.verify_certificates);
+        assert_eq!(config.chat.goodbye_message.as_deref(), Some("Bye!"));
+        assert_eq!(config.chat.duplicates, DuplicateMessages::Vary);
+        assert_eq!(config.twitch.channels, vec!["#captaincallback"]);
+    }
+
+    #[test]
+    fn prefixed_lists_are_replaced_or_appended() {
+        let mut config = Config::default();
+        config
+            .apply_prefixed_env(vars(&[(
+                "TWITCHBOT_TWITCH__CHANNELS",
+                "+#carkhy, #tutorial",
+            )]))
+            .unwrap();
+        assert_eq!(
+            config.twitch.channels,
+            vec!["#captaincallback", "#carkhy", "#tutorial"]
+        );
+        config
+            .apply_prefixed_env(vars(&[("TWITCHBOT_TWITCH__CHANNELS", "#carkhy")]))
+            .unwrap();
+        assert_eq!(config.twitch.channels, vec!["#carkhy"]);
+    }
+
+    #[test]
+    fn bad_prefixed_values_name_the_variable() {
+        let error = |name, value| {
+            Config::default()
+                .apply_prefixed_env(vars(&[(name, value)]))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("TWITCHBOT_CHAT__MESSAGE_TTL", "soon"),
+            "Invalid value for TWITCHBOT_CHAT__MESSAGE_TTL: \"soon\" is not a number"
+        );
+        assert!(error("TWITCHBOT_CHAT__MESSAGE_TTL", "-5").starts_with(
+            "Invalid value for TWITCHBOT_CHAT__MESSAGE_TTL: invalid value: integer `-5`"
+        ));
+        assert!(error("TWITCHBOT_CONNECTION__TRANSPORT", "irc").starts_with(
+            "Invalid value for TWITCHBOT_CONNECTION__TRANSPORT: unknown variant `irc`"
+        ));
+        assert_eq!(
+            error("TWITCHBOT_TWITCH__ANONYMOUS", "yes"),
+            "Invalid value for TWITCHBOT_TWITCH__ANONYMOUS: \"yes\" is not true or false"
+        );
+        assert_eq!(
+            error("TWITCHBOT_CHAT__COOLDOWN", "5"),
+            "Invalid value for TWITCHBOT_CHAT__COOLDOWN: is not a config key"
+        );
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let mut config = Config::default();
+        config.twitch.client_secret = "hunter2".to_owned();
+        config.obs.password = Some("open sesame".to_owned());
+        let redacted = config.redacted();
+        assert!(!redacted.contains("hunter2"), "{}", redacted);
+        assert!(!redacted.contains("open sesame"), "{}", redacted);
+        assert!(redacted.contains("client_secret = \"<redacted>\""));
+        let parsed: Config = toml::from_str(&redacted).unwrap();
+        assert_eq!(parsed.twitch.user, config.twitch.user);
+    }
+
+    #[test]
+    fn client_secret_is_not_debug_printed() {
+        let mut config = Config::default();
+        config.twitch.client_secret = "hunter2".to_owned();
+        config.http.api_token = Some("correct horse battery staple".to_owned());
+        config.webhooks.secret = Some("open sesame, open up".to_owned());
+        config.discord.bot_token = Some("discord bot token".to_owned());
+        config.obs.password = Some("obs password".to_owned());
+        config.broadcaster.client_secret = "broadcaster secret".to_owned();
+        let printed = format!("{:?}", config);
+        assert!(!printed.contains("hunter2"), "{}", printed);
+        assert!(!printed.contains("battery"), "{}", printed);
+        assert!(!printed.contains("sesame"), "{}", printed);
+        assert!(!printed.contains("discord bot"), "{}", printed);
+        assert!(!printed.contains("obs password"), "{}", printed);
+        assert!(!printed.contains("broadcaster secret"), "{}", printed);
+        assert!(
+            printed.contains("client_secret: \"<redacted>\""),
+            "{}",
+            printed
+        );
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let vars = HashMap::from([
+            ("TWITCH_CHANNEL", "CaptainCallback,carkhy"),
+            ("TWITCH_CHAT_TRANSPORT", "tcp"),
+            ("TWITCH_CHAT_KEEPALIVE", "30"),
+        ]);
+        let mut config = config("[connection]\nkeepalive = 60\nverify_certificates = false\n");
+        config
+            .apply_env(|name| vars.get(name).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.twitch.channels, vec!["#captaincallback", "#carkhy"]);
+        assert_eq!(config.connection.transport, ChatTransport::Tcp);
+        assert_eq!(config.keepalive(), Duration::from_secs(30));
+        assert!(!config.connection.verify_certificates);
+
+        let error = config
+            .apply_env(|name| (name == "TWITCH_CHAT_SECURITY").then(|| "ssl".to_owned()))
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid value for TWITCH_CHAT_SECURITY: unknown variant `ssl`"),
+            "{}",
+            error
+        );
+    }
+}
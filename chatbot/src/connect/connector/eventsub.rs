@@ -0,0 +1,550 @@
+use super::twitch_chat::{
+    retry_manager::{random_jitter, Backoff},
+    stream::ChatStream,
+};
+use crate::{
+    config::ConnectionSecurity,
+    connect::{error::ConnectorError, ChatBotEvent, HypeTrainStage, Redemption, SubTier},
+};
+use serde_json::Value;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use websocket::{sync::Client, url::Url, ClientBuilder, OwnedMessage, WebSocketError};
+
+pub const EVENTSUB_URL: &str = "wss://eventsub.wss.twitch.tv/ws";
+// twitch may deliver an event twice, the ids of this many are remembered
+const RECENT_IDS: usize = 1000;
+// how often the watchdog looks, and how late a keepalive may be
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(1);
+
+// what the bot understands of twitch's messages, the rest is ignored
+#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
+enum Message {
+    // twitch sends a keepalive when it had nothing else for this long
+    Welcome {
+        session_id: String,
+        keepalive: Option<Duration>,
+    },
+    // the session moves to the url, its subscriptions with it
+    Reconnect {
+        url: String,
+    },
+    // the id of the message, twitch sends it again when it isn't sure it arrived
+    Notification {
+        id: String,
+        event: ChatBotEvent,
+    },
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_owned()
+}
+
+// the top contributors are the one who gave the most bits and the one who gave the most subs
+fn hype_train(channel: String, stage: HypeTrainStage, event: &Value) -> ChatBotEvent {
+    let mut contributors = Vec::new();
+    for contribution in event["top_contributions"].as_array().into_iter().flatten() {
+        let name = text(&contribution["user_name"]);
+        if !name.is_empty() && !contributors.contains(&name) {
+            contributors.push(name);
+        }
+    }
+    ChatBotEvent::HypeTrain {
+        channel,
+        stage,
+        level: event["level"].as_u64().unwrap_or(1) as u32,
+        contributors,
+    }
+}
+
+// https://dev.twitch.tv/docs/eventsub/eventsub-subscription-types/
+fn event(kind: &str, event: &Value) -> Option<ChatBotEvent> {
+    let channel = text(&event["broadcaster_user_login"]);
+    let (login, name) = (text(&event["user_login"]), text(&event["user_name"]));
+    match kind {
+        "channel.channel_points_custom_reward_redemption.add" => {
+            Some(ChatBotEvent::Redemption(Redemption {
+                id: text(&event["id"]),
+                channel,
+                login,
+                display_name: name,
+                input: text(&event["user_input"]),
+                reward_id: text(&event["reward"]["id"]),
+                reward: text(&event["reward"]["title"]),
+                cost: event["reward"]["cost"].as_u64().unwrap_or_default(),
+            }))
+        }
+        "channel.follow" => Some(ChatBotEvent::Follow {
+            channel,
+            login,
+            name,
+        }),
+        // prime subs are tier 1000 as well
+        "channel.subscribe" => Some(ChatBotEvent::Subscribed {
+            channel,
+            login,
+            name,
+            tier: match event["tier"].as_str() {
+                Some("2000") => SubTier::Tier2,
+                Some("3000") => SubTier::Tier3,
+                _ => SubTier::Tier1,
+            },
+            gift: event["is_gift"].as_bool().unwrap_or_default(),
+        }),
+        "channel.hype_train.begin" => Some(hype_train(channel, HypeTrainStage::Begin, event)),
+        "channel.hype_train.progress" => Some(hype_train(channel, HypeTrainStage::Progress, event)),
+        "channel.hype_train.end" => Some(hype_train(channel, HypeTrainStage::End, event)),
+        "channel.shield_mode.begin" => Some(ChatBotEvent::ShieldMode {
+            channel,
+            active: true,
+            asked: false,
+        }),
+        "channel.shield_mode.end" => Some(ChatBotEvent::ShieldMode {
+            channel,
+            active: false,
+            asked: false,
+        }),
+        "stream.online" => Some(ChatBotEvent::LiveStatus {
+            channel,
+            live: true,
+        }),
+        "stream.offline" => Some(ChatBotEvent::LiveStatus {
+            channel,
+            live: false,
+        }),
+        _ => None,
+    }
+}
+
+// https://dev.twitch.tv/docs/eventsub/handling-websocket-events/
+fn parse(json: &str) -> Option<Message> {
+    let message: Value = serde_json::from_str(json).ok()?;
+    let payload = &message["payload"];
+    match message["metadata"]["message_type"].as_str()? {
+        "session_welcome" => Some(Message::Welcome {
+            session_id: payload["session"]["id"].as_str()?.to_owned(),
+            keepalive: payload["session"]["keepalive_timeout_seconds"]
+                .as_u64()
+                .map(Duration::from_secs),
+        }),
+        "session_reconnect" => Some(Message::Reconnect {
+            url: payload["session"]["reconnect_url"].as_str()?.to_owned(),
+        }),
+        "notification" => Some(Message::Notification {
+            id: message["metadata"]["message_id"].as_str()?.to_owned(),
+            event: event(payload["subscription"]["type"].as_str()?, &payload["event"])?,
+        }),
+        // keepalives only keep the connection busy, revocations are logged by twitch's answer
+        // to the next subscription
+        _ => None,
+    }
+}
+
+/// A websocket to a ws:// or wss:// url, also used for OBS.
+pub fn connect_websocket(url: &str) -> Result<Client<ChatStream>, ConnectorError> {
+    let invalid = |reason: String| {
+        ConnectorError::ExternalServerError(format!("Invalid url {}: {}", url, reason))
+    };
+    let parsed = Url::parse(url).map_err(|err| invalid(err.to_string()))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| invalid("no host".to_owned()))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| invalid("no port".to_owned()))?;
+    let security = match parsed.scheme() {
+        "ws" => ConnectionSecurity::Plain,
+        _ => ConnectionSecurity::Tls,
+    };
+    let stream = ChatStream::connect(host, port, security, true)?;
+    Ok(ClientBuilder::from_url(&parsed).connect_on(stream)?)
+}
+
+#[derive(Debug)]
+struct Watch {
+    last: Instant,
+    // None until the welcome told it
+    timeout: Option<Duration>,
+    stopped: bool,
+}
+
+// twitch revokes the subscriptions of a session that went quiet, the bot starts a new one then.
+// The read blocks, so the watchdog shuts the connection down from a thread of its own
+struct Watchdog(Arc<Mutex<Watch>>);
+
+impl Watchdog {
+    fn start(stream: ChatStream) -> Self {
+        let watch = Arc::new(Mutex::new(Watch {
+            last: Instant::now(),
+            timeout: None,
+            stopped: false,
+        }));
+        let watched = watch.clone();
+        thread::spawn(move || loop {
+            thread::sleep(WATCHDOG_INTERVAL);
+            let watch = watched.lock().unwrap();
+            if watch.stopped {
+                return;
+            }
+            if let Some(timeout) = watch.timeout {
+                if watch.last.elapsed() > timeout + KEEPALIVE_GRACE {
+                    tracing::warn!(?timeout, "EventSub went quiet, reconnecting");
+                    stream.shutdown();
+                    return;
+                }
+            }
+        });
+        Self(watch)
+    }
+
+    fn received(&self) {
+        self.0.lock().unwrap().last = Instant::now();
+    }
+
+    fn set_timeout(&self, timeout: Duration) {
+        self.0.lock().unwrap().timeout = Some(timeout);
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().stopped = true;
+    }
+}
+
+/// The ids of the latest events, oldest first.
+#[derive(Debug, Default)]
+struct RecentIds {
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+}
+
+impl RecentIds {
+    // false for an id seen before
+    fn first(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_owned()) {
+            return false;
+        }
+        self.order.push_back(id.to_owned());
+        if self.order.len() > RECENT_IDS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// One session of EventSub after the other, a moved session keeps its subscriptions.
+struct Listener {
+    url: String,
+    // where twitch moved the session to
+    moved: Option<String>,
+    seen: RecentIds,
+}
+
+impl Listener {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            moved: None,
+            seen: RecentIds::default(),
+        }
+    }
+
+    // delivers the events of the next session until it ends, Ok when twitch moved it.
+    // The welcome of a moved session starts no new one
+    fn session(&mut self, deliver: &mut impl FnMut(ChatBotEvent)) -> Result<(), ConnectorError> {
+        let moved = self.moved.take();
+        let mut client = connect_websocket(moved.as_deref().unwrap_or(&self.url))?;
+        let stream = client.stream_ref().try_clone().map_err(|err| {
+            ConnectorError::ExternalServerError(format!("Could not watch EventSub: {:?}", err))
+        })?;
+        let watchdog = Watchdog::start(stream);
+        loop {
+            let received = client.recv_message();
+            watchdog.received();
+            let text = match received {
+                Ok(OwnedMessage::Text(text)) => text,
+                Ok(OwnedMessage::Ping(data)) => {
+                    client.send_message(&OwnedMessage::Pong(data))?;
+                    continue;
+                }
+                Ok(OwnedMessage::Close(_)) => {
+                    return Err(ConnectorError::MessageReceiveFailed(
+                        "EventSub closed the session".to_owned(),
+                    ))
+                }
+                Ok(_) => continue,
+                // the connection is gone, e.g. shut down by the watchdog
+                Err(WebSocketError::NoDataAvailable) => {
+                    return Err(ConnectorError::MessageReceiveFailed(
+                        "EventSub connection ended".to_owned(),
+                    ))
+                }
+                Err(err) => {
+                    return Err(ConnectorError::MessageReceiveFailed(format!(
+                        "Could not receive from EventSub: {:?}",
+                        err
+                    )))
+                }
+            };
+            match parse(&text) {
+                Some(Message::Welcome {
+                    session_id,
+                    keepalive,
+                }) => {
+                    if let Some(keepalive) = keepalive {
+                        watchdog.set_timeout(keepalive);
+                    }
+                    if moved.is_none() {
+                        deliver(ChatBotEvent::EventSubWelcome { session_id });
+                    }
+                }
+                // twitch keeps the new session open a few seconds, only events of the old are lost
+                Some(Message::Reconnect { url }) => {
+                    client.stream_ref().shutdown();
+                    self.moved = Some(url);
+                    return Ok(());
+                }
+                Some(Message::Notification { id, event }) if self.seen.first(&id) => deliver(event),
+                Some(Message::Notification { .. }) | None => {}
+            }
+        }
+    }
+}
+
+/// Listens to EventSub until the bot stops, each new session is welcomed so that the bot
+/// subscribes again. Reading blocks, so it has a thread of its own.
+pub fn spawn_eventsub(url: &str, mut deliver: impl FnMut(ChatBotEvent) + Send + 'static) {
+    let mut listener = Listener::new(url);
+    thread::spawn(move || {
+        let backoff = Backoff::default();
+        let mut attempt = 0;
+        loop {
+            match listener.session(&mut deliver) {
+                Ok(()) => attempt = 0,
+                Err(error) => {
+                    attempt += 1;
+                    let delay = backoff.delay(attempt, random_jitter());
+                    tracing::warn!(attempt, ?delay, %error, "EventSub failed, reconnecting");
+                    thread::sleep(delay);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::connect::ChatBotEvent;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+    use websocket::{sync::Server, OwnedMessage};
+
+    /// What the bot is told by the session at the url, until it ends.
+    pub fn session_events(url: &str) -> Vec<ChatBotEvent> {
+        let mut events = Vec::new();
+        let _ = super::Listener::new(url).session(&mut |event| events.push(event));
+        events
+    }
+
+    fn serve(messages: Vec<String>, close: bool) -> String {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().unwrap();
+            for message in messages {
+                client.send_message(&OwnedMessage::Text(message)).unwrap();
+            }
+            if close {
+                let _ = client.send_message(&OwnedMessage::Close(None));
+            }
+            while client.recv_message().is_ok() {}
+        });
+        format!("ws://127.0.0.1:{}/ws", port)
+    }
+
+    /// A session of EventSub at the returned url, sending the messages then closing.
+    pub fn eventsub_server(messages: Vec<String>) -> String {
+        serve(messages, true)
+    }
+
+    /// A session sending the messages then nothing, until the bot leaves.
+    pub fn quiet_eventsub_server(messages: Vec<String>) -> String {
+        serve(messages, false)
+    }
+
+    pub fn welcome(session_id: &str, keepalive: u64) -> String {
+        format!(
+            r#"{{"metadata":{{"message_id":"1","message_type":"session_welcome","message_timestamp":"2026-10-14T10:11:12.123Z"}},
+                "payload":{{"session":{{"id":"{}","status":"connected","keepalive_timeout_seconds":{},"reconnect_url":null}}}}}}"#,
+            session_id, keepalive
+        )
+    }
+
+    pub fn reconnect(url: &str) -> String {
+        format!(
+            r#"{{"metadata":{{"message_id":"2","message_type":"session_reconnect"}},
+                "payload":{{"session":{{"id":"AQoQ","status":"reconnecting","reconnect_url":"{}"}}}}}}"#,
+            url
+        )
+    }
+
+    /// A notification of the subscription type with the message id.
+    pub fn notification(id: &str, kind: &str, event: &str) -> String {
+        format!(
+            r#"{{"metadata":{{"message_id":"{}","message_type":"notification","subscription_type":"{}"}},
+                "payload":{{"subscription":{{"type":"{}","version":"1"}},"event":{}}}}}"#,
+            id, kind, kind, event
+        )
+    }
+
+    // each redemption is a message of its own
+    pub fn redemption(reward: &str, input: &str) -> String {
+        static ID: AtomicUsize = AtomicUsize::new(0);
+        let event = format!(
+            r#"{{"id":"17fa2df1","broadcaster_user_id":"1","broadcaster_user_login":"captaincallback","broadcaster_user_name":"CaptainCallback",
+                "user_id":"2","user_login":"carkhy","user_name":"Carkhy","user_input":"{}","status":"unfulfilled",
+                "reward":{{"id":"92af127c","title":"{}","cost":100,"prompt":""}},"redeemed_at":"2026-10-14T10:11:13.123Z"}}"#,
+            input, reward
+        );
+        let id = format!("redemption-{}", ID.fetch_add(1, Ordering::Relaxed));
+        notification(
+            &id,
+            "channel.channel_points_custom_reward_redemption.add",
+            &event,
+        )
+    }
+
+    pub fn follow(id: &str, login: &str) -> String {
+        let event = format!(
+            r#"{{"user_id":"3","user_login":"{}","user_name":"{}","broadcaster_user_id":"1","broadcaster_user_login":"captaincallback",
+                "broadcaster_user_name":"CaptainCallback","followed_at":"2026-10-14T10:11:14.123Z"}}"#,
+            login, login
+        );
+        notification(id, "channel.follow", &event)
+    }
+
+    /// An event of a hype train of the stage, "begin", "progress" or "end".
+    pub fn hype_train(id: &str, stage: &str, level: u32, contributors: &[&str]) -> String {
+        let contributions: Vec<_> = contributors
+            .iter()
+            .map(|name| {
+                format!(
+                    r#"{{"user_id":"4","user_login":"{}","user_name":"{}","type":"bits","total":500}}"#,
+                    name.to_lowercase(),
+                    name
+                )
+            })
+            .collect();
+        let event = format!(
+            r#"{{"id":"1b0AsbInCHZW2SQFQkCzqN07Ib2","broadcaster_user_id":"1","broadcaster_user_login":"captaincallback",
+                "broadcaster_user_name":"CaptainCallback","level":{},"total":700,"top_contributions":[{}]}}"#,
+            level,
+            contributions.join(",")
+        );
+        notification(id, &format!("channel.hype_train.{}", stage), &event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::*, *};
+
+    #[test]
+    fn sessions_deliver_the_welcome_and_events() {
+        let keepalive = r#"{"metadata":{"message_type":"session_keepalive"},"payload":{}}"#;
+        let subscribed = r#"{"user_login":"tenaciousbyte","user_name":"TenaciousByte","broadcaster_user_login":"captaincallback",
+            "tier":"2000","is_gift":true}"#;
+        let url = eventsub_server(vec![
+            welcome("AQoQ", 10),
+            keepalive.to_owned(),
+            redemption("Hydrate", "now"),
+            notification("3", "channel.subscribe", subscribed),
+            notification(
+                "4",
+                "stream.offline",
+                r#"{"broadcaster_user_login":"captaincallback"}"#,
+            ),
+        ]);
+        let events = session_events(&url);
+        assert_eq!(events.len(), 4, "{:?}", events);
+        assert_eq!(
+            events[0],
+            ChatBotEvent::EventSubWelcome {
+                session_id: "AQoQ".to_owned()
+            }
+        );
+        match &events[1] {
+            ChatBotEvent::Redemption(redemption) => {
+                assert_eq!(redemption.channel, "captaincallback");
+                assert_eq!(redemption.display_name, "Carkhy");
+                assert_eq!((&*redemption.reward, redemption.cost), ("Hydrate", 100));
+                assert_eq!(redemption.input, "now");
+            }
+            event => panic!("{:?}", event),
+        }
+        assert_eq!(
+            events[2],
+            ChatBotEvent::Subscribed {
+                channel: "captaincallback".to_owned(),
+                login: "tenaciousbyte".to_owned(),
+                name: "TenaciousByte".to_owned(),
+                tier: SubTier::Tier2,
+                gift: true,
+            }
+        );
+        assert_eq!(
+            events[3],
+            ChatBotEvent::LiveStatus {
+                channel: "captaincallback".to_owned(),
+                live: false
+            }
+        );
+    }
+
+    #[test]
+    fn moved_sessions_keep_their_subscriptions_and_skip_redeliveries() {
+        let moved_to = eventsub_server(vec![
+            welcome("AQoQ", 10),
+            follow("5", "carkhy"),
+            follow("6", "tenaciousbyte"),
+        ]);
+        let url = quiet_eventsub_server(vec![
+            welcome("AQoQ", 10),
+            follow("5", "carkhy"),
+            reconnect(&moved_to),
+        ]);
+        let mut listener = Listener::new(&url);
+        let mut events = Vec::new();
+        assert!(listener.session(&mut |event| events.push(event)).is_ok());
+        assert!(listener.session(&mut |event| events.push(event)).is_err());
+        let followers: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ChatBotEvent::Follow { login, .. } => Some(&**login),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(followers, ["carkhy", "tenaciousbyte"]);
+        // only the first session is welcomed
+        assert_eq!(events.len(), 3, "{:?}", events);
+    }
+
+    #[test]
+    fn quiet_sessions_end_after_the_keepalive_timeout() {
+        let url = quiet_eventsub_server(vec![welcome("AQoQ", 1)]);
+        let start = Instant::now();
+        assert_eq!(session_events(&url).len(), 1);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
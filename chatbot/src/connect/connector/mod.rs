@@ -1,3 +1,19 @@
+mod eventsub;
 pub(crate) mod twitch_chat;
 
-pub use twitch_chat::TwitchChatConnector;
+#[cfg(test)]
+pub use eventsub::testing as eventsub_testing;
+pub use eventsub::{connect_websocket, spawn_eventsub, EVENTSUB_URL};
+#[cfg(fuzzing)]
+pub use twitch_chat::fuzz_receive;
+#[cfg(test)]
+pub use twitch_chat::testing;
+pub use twitch_chat::{
+    fit_message, required_scopes, AccessTokenDispenser, Connection, EventHandler, Health,
+    HealthLimits, Identity, Overflow, Priority, ReplaySource, ReplayTiming, Report, SharedTokens,
+    TokenInfo, TwitchChatConnector, MAX_MESSAGE_CHARS,
+};
+pub use twitch_chat::{
+    retry_manager::{random_jitter, Backoff},
+    stream::ChatStream,
+};
@@ -1,31 +1,161 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use super::retry_manager::ExponentialRetryManager;
-use crate::{app_config::AppConfig, connect::error::ConnectorError};
+use crate::{config::Config, connect::error::ConnectorError};
 use futures_retry::FutureRetry;
 use kv::*;
 use reqwest::Response;
 use serde_json::{from_str, Value};
 
 const VALIDATION_URL: &str = "https://id.twitch.tv/oauth2/validate";
-const REDIRECT_URI: &str = "https://localhost:3030";
+const TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+const DEVICE_URL: &str = "https://id.twitch.tv/oauth2/device";
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+// added to the polling interval whenever twitch asks to slow down
+const SLOW_DOWN_INCREMENT: Duration = Duration::from_secs(5);
+const STORE_OPEN_ATTEMPTS: u32 = 10;
+const STORE_OPEN_DELAY: Duration = Duration::from_millis(20);
 const AUTH_CONFIG_FILE: &str = "./auth_store";
+// the broadcaster's tokens are kept apart from the bot's
+const BROADCASTER_AUTH_CONFIG_FILE: &str = "./auth_store_broadcaster";
 const AUTH_BUCKET_NAME: &str = "auth_config";
 const ACCESS_TOKEN_PERSISTENCE_KEY: &str = "access_token";
 const REFRESH_TOKEN_PERSISTENCE_KEY: &str = "refresh_token";
 
+// twitch's by default, tests use a local server
+struct AuthEndpoints {
+    validation: String,
+    token: String,
+    device: String,
+}
+
+impl Default for AuthEndpoints {
+    fn default() -> Self {
+        Self {
+            validation: VALIDATION_URL.to_owned(),
+            token: TOKEN_URL.to_owned(),
+            device: DEVICE_URL.to_owned(),
+        }
+    }
+}
+
+/// Whose token a request goes out with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Identity {
+    // the user the bot chats as
+    Bot,
+    // `broadcaster.login`, for what only the broadcaster may do
+    Broadcaster,
+}
+
+impl Identity {
+    /// Where the token is kept.
+    pub fn store(self) -> PathBuf {
+        PathBuf::from(match self {
+            Identity::Bot => AUTH_CONFIG_FILE,
+            Identity::Broadcaster => BROADCASTER_AUTH_CONFIG_FILE,
+        })
+    }
+}
+
+/// The scopes twitch grants only to the broadcaster of a channel, asked of the broadcaster's
+/// token once there is one instead of the bot's.
+pub const BROADCASTER_SCOPES: [&str; 6] = [
+    "channel:manage:broadcast",
+    "channel:edit:commercial",
+    "channel:manage:polls",
+    "channel:manage:predictions",
+    "channel:manage:vips",
+    "channel:manage:moderators",
+];
+
+/// The scopes the token of the identity needs with the given config.
+pub fn required_scopes(config: &Config, identity: Identity) -> Vec<&'static str> {
+    let separate = config.broadcaster.login.is_some();
+    let scopes = all_scopes(config).into_iter();
+    match identity {
+        Identity::Bot if separate => scopes
+            .filter(|scope| !BROADCASTER_SCOPES.contains(scope))
+            .collect(),
+        Identity::Bot => scopes.collect(),
+        Identity::Broadcaster => BROADCASTER_SCOPES.to_vec(),
+    }
+}
+
+// what the bot asks for when a single token does everything
+fn all_scopes(config: &Config) -> Vec<&'static str> {
+    // !followage asks who follows the channel, !so sends twitch's shoutout as well,
+    // !settitle and !setgame change the channel when the bot is the broadcaster, !clip clips it
+    // and !commercial runs ads, the moderation deletes messages and times users out,
+    // a raid may pause slow mode, !twitchpoll starts twitch's polls and !prediction its
+    // predictions
+    let mut scopes = vec![
+        "chat:read",
+        "chat:edit",
+        "moderator:read:followers",
+        "moderator:manage:shoutouts",
+        "channel:manage:broadcast",
+        "clips:edit",
+        "channel:edit:commercial",
+        "moderator:manage:chat_messages",
+        "moderator:manage:banned_users",
+        "moderator:manage:chat_settings",
+        "moderator:manage:shield_mode",
+        "channel:manage:vips",
+        "channel:manage:moderators",
+        "channel:manage:polls",
+        "channel:manage:predictions",
+    ];
+    // twitch only delivers whispers over IRC with this scope
+    if config
+        .twitch
+        .capabilities
+        .iter()
+        .any(|capability| capability == "twitch.tv/commands")
+    {
+        scopes.push("whispers:read");
+    }
+    // announcements fall back to chat lines without this one
+    if config.events.announce_stream.is_some()
+        || config
+            .timers
+            .messages
+            .iter()
+            .any(|timer| timer.announce.is_some())
+    {
+        scopes.push("moderator:manage:announcements");
+    }
+    // the bot whispers with this one, IRC whispers don't reach anyone anymore
+    if config.raffle.whisper_winners
+        || config.reminders.whisper
+        || !config.commands.admins.is_empty()
+    {
+        scopes.push("user:manage:whispers");
+    }
+    // EventSub tells about redemptions with this scope, fulfilling them needs it as well
+    if !config.redemptions.rewards.is_empty() {
+        scopes.push("channel:manage:redemptions");
+    }
+    // and about subs and hype trains with these, follows need moderator:read:followers
+    if config.events.eventsub {
+        scopes.push("channel:read:subscriptions");
+        scopes.push("channel:read:hype_train");
+    }
+    scopes
+}
+
 async fn get_json_from_response(response: Response) -> Result<Value, ConnectorError> {
     let response_text = response.text().await?;
     let val: Value = from_str(&response_text)?;
     Ok(val)
 }
 
-fn extract_code_from_url(request_url: &str) -> &str {
-    let code_start = request_url.find("code=").unwrap() + 5;
-    let code_end = code_start + 30;
-    &request_url[code_start..code_end]
-}
-
 fn create_url_with_query_params(base: &str, query_params: &HashMap<&str, &str>) -> String {
     let query_params_options_strings: Vec<String> = query_params
         .iter()
@@ -34,16 +164,50 @@ fn create_url_with_query_params(base: &str, query_params: &HashMap<&str, &str>)
     format!("{}?{}", base, query_params_options_strings.join("&"))
 }
 
-async fn access_token_is_valid(access_token: &str) -> Result<bool, ConnectorError> {
+/// What twitch tells about a valid access token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub login: String,
+    pub scopes: Vec<String>,
+    pub expires_in: Duration,
+}
+
+impl TokenInfo {
+    /// The scopes the config needs that the token wasn't granted.
+    pub fn missing_scopes(&self, config: &Config, identity: Identity) -> Vec<&'static str> {
+        required_scopes(config, identity)
+            .into_iter()
+            .filter(|scope| !self.scopes.iter().any(|granted| granted == scope))
+            .collect()
+    }
+}
+
+// https://dev.twitch.tv/docs/authentication/validate-tokens/, None for an invalid token
+async fn validate_access_token(
+    endpoints: &AuthEndpoints,
+    access_token: &str,
+) -> Result<Option<TokenInfo>, ConnectorError> {
     let client = reqwest::Client::new();
     let validation_response = client
-        .get(VALIDATION_URL)
+        .get(&endpoints.validation)
         .bearer_auth(access_token)
         .send()
         .await?;
     match validation_response.status().as_u16() {
-        401 => Ok(false),
-        200 => Ok(true),
+        401 => Ok(None),
+        200 => {
+            let json = get_json_from_response(validation_response).await?;
+            Ok(Some(TokenInfo {
+                login: json["login"].as_str().unwrap_or_default().to_owned(),
+                scopes: json["scopes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|scope| scope.as_str().map(str::to_owned))
+                    .collect(),
+                expires_in: Duration::from_secs(json["expires_in"].as_u64().unwrap_or_default()),
+            }))
+        }
         status_code => Err(ConnectorError::ExternalServerError(format!(
             "Access token validation server sent bad response with http status code {}",
             status_code
@@ -51,9 +215,21 @@ async fn access_token_is_valid(access_token: &str) -> Result<bool, ConnectorErro
     }
 }
 
-async fn access_token_is_valid_retrying(access_token: &str) -> Result<bool, ConnectorError> {
+async fn access_token_is_valid(
+    endpoints: &AuthEndpoints,
+    access_token: &str,
+) -> Result<bool, ConnectorError> {
+    Ok(validate_access_token(endpoints, access_token)
+        .await?
+        .is_some())
+}
+
+async fn access_token_is_valid_retrying(
+    endpoints: &AuthEndpoints,
+    access_token: &str,
+) -> Result<bool, ConnectorError> {
     FutureRetry::new(
-        || access_token_is_valid(access_token),
+        || access_token_is_valid(endpoints, access_token),
         ExponentialRetryManager::new(Some(1), Some(3)),
     )
     .await
@@ -61,45 +237,87 @@ async fn access_token_is_valid_retrying(access_token: &str) -> Result<bool, Conn
     .map_err(|err| err.0)
 }
 
-fn retrieve_code(client_id: &str) -> Result<String, ConnectorError> {
-    let ssl_config = tiny_http::SslConfig {
-        certificate: include_bytes!("../../../../certificates/cert.crt").to_vec(),
-        // TODO: security: must not include private keys in a binary !
-        private_key: include_bytes!("../../../../certificates/cert.key").to_vec(),
-    };
-    let server = tiny_http::Server::https("0.0.0.0:3030", ssl_config).map_err(|err| {
-        ConnectorError::MessageReceiveFailed(format!(
-            "Could not start server to get code: {:?}",
-            err
-        ))
-    })?;
-    println!(
-            "Open link https://id.twitch.tv/oauth2/authorize?client_id={}&redirect_uri=https://localhost:3030&response_type=code&scope=chat:read%20chat:edit",
-            client_id,
-        );
-    let request: tiny_http::Request = server.recv().map_err(|err| {
-        ConnectorError::MessageReceiveFailed(format!(
-            "Could not receive request with code: {:?}",
-            err
-        ))
-    })?;
-    let request_url = request.url();
-    let code = extract_code_from_url(request_url);
-    Ok(code.to_owned())
+/// The tokens of an identity in their sled store, kept open while the dispenser lives. Sled
+/// allows one open store per path, and only releases the lock of a dropped one once its
+/// flusher is done.
+#[derive(Clone)]
+struct TokenStore {
+    path: PathBuf,
+    store: Store,
 }
 
-fn load_saved_access_token() -> Result<(String, String), ConnectorError> {
-    let cfg = Config::new(AUTH_CONFIG_FILE);
-    let store = Store::new(cfg)?;
-    let bucket = store.bucket::<String, String>(Some(AUTH_BUCKET_NAME))?;
-    let access_token = bucket.get("access_token")?;
-    let refresh_token = bucket.get("refresh_token")?;
-    if access_token.is_some() && refresh_token.is_some() {
-        Ok((access_token.unwrap(), refresh_token.unwrap()))
-    } else {
-        Err(ConnectorError::StoredValueNotAvailable(
-            "access_token or refresh_token".to_owned(),
-        ))
+// another store of the path that is still being released
+fn is_locked(error: &kv::Error) -> bool {
+    matches!(error, kv::Error::Sled(error) if error.to_string().contains("could not acquire lock"))
+}
+
+impl TokenStore {
+    /// Opened in a blocking task, it may wait for the lock.
+    async fn open(path: PathBuf) -> Result<Self, ConnectorError> {
+        tokio::task::spawn_blocking(move || Self::open_blocking(path))
+            .await
+            .expect("opening the token store doesn't panic")
+    }
+
+    fn open_blocking(path: PathBuf) -> Result<Self, ConnectorError> {
+        let mut attempts = 1;
+        loop {
+            match Store::new(kv::Config::new(&path)) {
+                Ok(store) => return Ok(Self { path, store }),
+                Err(error) if is_locked(&error) && attempts < STORE_OPEN_ATTEMPTS => {
+                    attempts += 1;
+                    std::thread::sleep(STORE_OPEN_DELAY);
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    fn load(&self) -> Result<(String, String), ConnectorError> {
+        let bucket = self
+            .store
+            .bucket::<String, String>(Some(AUTH_BUCKET_NAME))?;
+        let access_token = bucket.get(ACCESS_TOKEN_PERSISTENCE_KEY)?;
+        let refresh_token = bucket.get(REFRESH_TOKEN_PERSISTENCE_KEY)?;
+        match (access_token, refresh_token) {
+            (Some(access_token), Some(refresh_token)) => Ok((access_token, refresh_token)),
+            _ => Err(ConnectorError::StoredValueNotAvailable(
+                "access_token or refresh_token".to_owned(),
+            )),
+        }
+    }
+
+    /// Written and flushed in a blocking task, only the bot's user may read the store.
+    async fn save(&self, access_token: &str, refresh_token: &str) {
+        let store = self.clone();
+        let tokens = (access_token.to_owned(), refresh_token.to_owned());
+        tokio::task::spawn_blocking(move || store.save_blocking(&tokens.0, &tokens.1))
+            .await
+            .expect("saving the tokens doesn't panic")
+    }
+
+    fn save_blocking(&self, access_token: &str, refresh_token: &str) {
+        if self
+            .store
+            .bucket::<String, String>(Some(AUTH_BUCKET_NAME))
+            .and_then(|bucket| {
+                let access_token_saving = bucket.set(ACCESS_TOKEN_PERSISTENCE_KEY, access_token);
+                let refresh_token_saving = bucket.set(REFRESH_TOKEN_PERSISTENCE_KEY, refresh_token);
+                access_token_saving.and(refresh_token_saving)?;
+                bucket.flush()?;
+                Ok(())
+            })
+            .is_err()
+        {
+            tracing::error!(path = ?self.path, "could not store the access and refresh token");
+        }
+        if let Err(error) = restrict_permissions(&self.path) {
+            tracing::warn!(
+                path = ?self.path,
+                %error,
+                "could not restrict access to the token store"
+            );
+        }
     }
 }
 
@@ -109,17 +327,16 @@ async fn request_access_token(uri: &str) -> Result<(String, String), ConnectorEr
     match response.status().as_u16() {
         200 => {
             let json = get_json_from_response(response).await?;
-            let access_token = json["access_token"].as_str();
-            let refresh_token = json["refresh_token"].as_str();
-            if access_token.is_some() && refresh_token.is_some() {
-                Ok((
-                    access_token.unwrap().to_owned(),
-                    refresh_token.unwrap().to_owned(),
-                ))
-            } else {
-                Err(ConnectorError::ExternalServerError(
+            match (
+                json["access_token"].as_str(),
+                json["refresh_token"].as_str(),
+            ) {
+                (Some(access_token), Some(refresh_token)) => {
+                    Ok((access_token.to_owned(), refresh_token.to_owned()))
+                }
+                _ => Err(ConnectorError::ExternalServerError(
                     "Server did not provide access token or refresh token in response".to_owned(),
-                ))
+                )),
             }
         }
         403 => {
@@ -129,6 +346,14 @@ async fn request_access_token(uri: &str) -> Result<(String, String), ConnectorEr
                 error_message.unwrap_or_default().to_owned(),
             ))
         }
+        // e.g. an invalid refresh token
+        400 => {
+            let json = get_json_from_response(response).await?;
+            let error_message = json["message"].as_str();
+            Err(ConnectorError::TokenRequestRejected(
+                error_message.unwrap_or_default().to_owned(),
+            ))
+        }
         404 => Err(ConnectorError::HTTP404),
         status_code => Err(ConnectorError::ExternalServerError(format!(
             "Access token request server sent bad response with http status code {}",
@@ -137,39 +362,125 @@ async fn request_access_token(uri: &str) -> Result<(String, String), ConnectorEr
     }
 }
 
-// https://dev.twitch.tv/docs/authentication/getting-tokens-oauth/#oauth-authorization-code-flow
-async fn request_new_access_token(
+// what the user is shown to authorize the bot
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: Duration,
+    interval: Duration,
+}
+
+// https://dev.twitch.tv/docs/authentication/getting-tokens-oauth/#device-code-grant-flow
+async fn request_device_code(
+    endpoints: &AuthEndpoints,
     client_id: &str,
-    client_secret: &str,
-) -> Result<(String, String), ConnectorError> {
-    // TODO: don't include the code retrieval in retrying
-    let code = retrieve_code(client_id)?;
+    scopes: &[&str],
+) -> Result<DeviceCode, ConnectorError> {
+    let scopes = scopes.join("%20");
+    let query_params: HashMap<&str, &str> =
+        HashMap::from([("client_id", client_id), ("scopes", &scopes)]);
+    let uri = create_url_with_query_params(&endpoints.device, &query_params);
+    let response = reqwest::Client::new().post(uri).send().await?;
+    let status = response.status().as_u16();
+    let json = get_json_from_response(response).await?;
+    let seconds = |name: &str| json[name].as_u64().map(Duration::from_secs);
+    match (
+        status,
+        json["device_code"].as_str(),
+        json["user_code"].as_str(),
+        json["verification_uri"].as_str(),
+        seconds("expires_in"),
+        seconds("interval"),
+    ) {
+        (
+            200,
+            Some(device_code),
+            Some(user_code),
+            Some(verification_uri),
+            Some(expires_in),
+            Some(interval),
+        ) => Ok(DeviceCode {
+            device_code: device_code.to_owned(),
+            user_code: user_code.to_owned(),
+            verification_uri: verification_uri.to_owned(),
+            expires_in,
+            interval,
+        }),
+        (200, ..) => Err(ConnectorError::ExternalServerError(
+            "Server did not provide a device code in response".to_owned(),
+        )),
+        (status_code, ..) => Err(ConnectorError::ExternalServerError(format!(
+            "Device code request server sent bad response with http status code {}: {}",
+            status_code,
+            json["message"].as_str().unwrap_or_default()
+        ))),
+    }
+}
+
+// asks until the user authorized the bot, waiting between the requests as twitch asks to
+async fn poll_device_token<S, F>(
+    endpoints: &AuthEndpoints,
+    client_id: &str,
+    scopes: &[&str],
+    device_code: &DeviceCode,
+    mut sleep: S,
+) -> Result<(String, String), ConnectorError>
+where
+    S: FnMut(Duration) -> F,
+    F: Future<Output = ()>,
+{
+    let scopes = scopes.join("%20");
     let query_params: HashMap<&str, &str> = HashMap::from([
         ("client_id", client_id),
-        ("client_secret", client_secret),
-        ("code", &code),
-        ("grant_type", "authorization_code"),
-        ("redirect_uri", REDIRECT_URI),
+        ("scopes", &scopes),
+        ("device_code", &device_code.device_code),
+        ("grant_type", DEVICE_GRANT_TYPE),
     ]);
-    let uri = create_url_with_query_params("https://id.twitch.tv/oauth2/token", &query_params);
-    request_access_token(&uri).await
+    let uri = create_url_with_query_params(&endpoints.token, &query_params);
+    let deadline = Instant::now() + device_code.expires_in;
+    let mut interval = device_code.interval;
+    loop {
+        sleep(interval).await;
+        match request_access_token(&uri).await {
+            Err(ConnectorError::TokenRequestRejected(reason)) => match reason.as_str() {
+                "authorization_pending" => {}
+                "slow_down" => interval += SLOW_DOWN_INCREMENT,
+                "expired_token" => return Err(ConnectorError::AuthorizationExpired),
+                _ => return Err(ConnectorError::TokenRequestRejected(reason)),
+            },
+            result => return result,
+        }
+        if Instant::now() >= deadline {
+            return Err(ConnectorError::AuthorizationExpired);
+        }
+    }
 }
 
-async fn request_new_access_token_retrying(
+// the user authorizes the bot on twitch's site, no token has to be pasted anywhere
+async fn request_new_access_token(
+    endpoints: &AuthEndpoints,
     client_id: &str,
-    client_secret: &str,
+    scopes: &[&str],
 ) -> Result<(String, String), ConnectorError> {
-    FutureRetry::new(
-        || request_new_access_token(client_id, client_secret),
-        ExponentialRetryManager::new(Some(1), Some(3)),
+    let device_code = request_device_code(endpoints, client_id, scopes).await?;
+    println!(
+        "Open {} and enter the code {} to authorize the chat bot",
+        device_code.verification_uri, device_code.user_code
+    );
+    poll_device_token(
+        endpoints,
+        client_id,
+        scopes,
+        &device_code,
+        tokio::time::sleep,
     )
     .await
-    .map(|val| val.0)
-    .map_err(|err| err.0)
 }
 
 // https://dev.twitch.tv/docs/authentication#refreshing-access-tokens
 async fn refresh_access_token(
+    endpoints: &AuthEndpoints,
     client_id: &str,
     client_secret: &str,
     refresh_token: &str,
@@ -177,20 +488,21 @@ async fn refresh_access_token(
     let query_params: HashMap<&str, &str> = HashMap::from([
         ("client_id", client_id),
         ("client_secret", client_secret),
-        ("refresh_token", &refresh_token),
+        ("refresh_token", refresh_token),
         ("grant_type", "refresh_token"),
     ]);
-    let uri = create_url_with_query_params("https://id.twitch.tv/oauth2/token", &query_params);
+    let uri = create_url_with_query_params(&endpoints.token, &query_params);
     request_access_token(&uri).await
 }
 
 async fn refresh_access_token_retrying(
+    endpoints: &AuthEndpoints,
     client_id: &str,
     client_secret: &str,
     refresh_token: &str,
 ) -> Result<(String, String), ConnectorError> {
     FutureRetry::new(
-        || refresh_access_token(client_id, client_secret, refresh_token),
+        || refresh_access_token(endpoints, client_id, client_secret, refresh_token),
         ExponentialRetryManager::new(Some(1), Some(3)),
     )
     .await
@@ -198,81 +510,173 @@ async fn refresh_access_token_retrying(
     .map_err(|err| err.0)
 }
 
-fn store_tokens(access_token: &str, refresh_token: &str) {
-    let cfg = Config::new(AUTH_CONFIG_FILE);
-    if let Err(_) = Store::new(cfg)
-        .and_then(|store| store.bucket::<String, String>(Some(AUTH_BUCKET_NAME)))
-        .and_then(|bucket| {
-            let access_token_saving = bucket.set(ACCESS_TOKEN_PERSISTENCE_KEY, access_token);
-            let refresh_token_saving = bucket.set(REFRESH_TOKEN_PERSISTENCE_KEY, refresh_token);
-            access_token_saving.and(refresh_token_saving)
-        })
-    {
-        println!("Could not store access token or refresh token");
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700))?;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let mode = if entry.file_type()?.is_dir() {
+            0o700
+        } else {
+            0o600
+        };
+        fs::set_permissions(entry.path(), fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// the application each identity's tokens are for
+fn client(config: &Config, identity: Identity) -> (&str, &str) {
+    match identity {
+        Identity::Bot => (&config.twitch.client_id, &config.twitch.client_secret),
+        Identity::Broadcaster => config.broadcaster_client(),
     }
 }
 
-pub struct AccessTokenDispenser<'a> {
-    app_config: &'a AppConfig,
+/// Hands out a valid user access token, refreshed with the refresh token when it expired.
+pub struct AccessTokenDispenser {
+    client_id: String,
+    client_secret: String,
     access_token: String,
     refresh_token: String,
+    endpoints: AuthEndpoints,
+    // None for a fixed token
+    store: Option<TokenStore>,
 }
 
-impl<'a> AccessTokenDispenser<'a> {
+impl AccessTokenDispenser {
+    /// The stored tokens of the identity, else those the user authorizes now on twitch's site.
     pub async fn new(
-        app_config: &'a AppConfig,
-    ) -> Result<AccessTokenDispenser<'a>, ConnectorError> {
-        let (access_token, refresh_token) = match load_saved_access_token() {
-            Ok(val) => val,
-            Err(_) => {
-                let (access_token, refresh_token) = request_new_access_token_retrying(
-                    app_config.twitch_client_id(),
-                    app_config.twitch_client_secret(),
-                )
-                .await?;
-                store_tokens(&access_token, &refresh_token);
-                (access_token, refresh_token)
+        config: &Config,
+        identity: Identity,
+    ) -> Result<AccessTokenDispenser, ConnectorError> {
+        let endpoints = AuthEndpoints::default();
+        let store = TokenStore::open(identity.store()).await?;
+        if store.load().is_err() {
+            let (client_id, _) = client(config, identity);
+            if let (Identity::Broadcaster, Some(login)) = (identity, &config.broadcaster.login) {
+                tracing::info!(%login, "the broadcaster has to authorize the next code");
             }
-        };
+            let (access_token, refresh_token) =
+                request_new_access_token(&endpoints, client_id, &required_scopes(config, identity))
+                    .await?;
+            store.save(&access_token, &refresh_token).await;
+        }
+        Self::load(config, identity, endpoints, store)
+    }
+
+    /// The tokens the bot stored before, an error when there are none. Unlike [Self::new],
+    /// nobody is asked to authorize the bot.
+    pub async fn stored(
+        config: &Config,
+        identity: Identity,
+    ) -> Result<AccessTokenDispenser, ConnectorError> {
+        let store = TokenStore::open(identity.store()).await?;
+        Self::load(config, identity, AuthEndpoints::default(), store)
+    }
+
+    fn load(
+        config: &Config,
+        identity: Identity,
+        endpoints: AuthEndpoints,
+        store: TokenStore,
+    ) -> Result<AccessTokenDispenser, ConnectorError> {
+        let (access_token, refresh_token) = store.load()?;
+        let (client_id, client_secret) = client(config, identity);
         Ok(Self {
-            app_config,
+            client_id: client_id.to_owned(),
+            client_secret: client_secret.to_owned(),
             access_token,
             refresh_token,
+            endpoints,
+            store: Some(store),
         })
     }
 
+    /// What twitch tells about the access token, None when it expired or was revoked.
+    pub async fn validate(&self) -> Result<Option<TokenInfo>, ConnectorError> {
+        validate_access_token(&self.endpoints, &self.access_token).await
+    }
+
+    /// The access token after validating it with twitch, refreshed when it is invalid.
     pub async fn get(&mut self) -> Result<&str, ConnectorError> {
-        let access_token_valid = access_token_is_valid_retrying(&self.access_token).await?;
-        let (access_token, refresh_token) = match access_token_valid {
-            true => (self.access_token.to_owned(), self.refresh_token.to_owned()),
-            false => {
-                refresh_access_token_retrying(
-                    &self.app_config.twitch_client_id(),
-                    &self.app_config.twitch_client_secret(),
-                    &self.refresh_token,
-                )
-                .await?
-            }
-        };
-        store_tokens(&access_token, &refresh_token);
+        if access_token_is_valid_retrying(&self.endpoints, &self.access_token).await? {
+            return Ok(self.access_token.as_ref());
+        }
+        self.refresh().await
+    }
+
+    /// A new access token, e.g. after twitch rejected the login with the current one.
+    pub async fn refresh(&mut self) -> Result<&str, ConnectorError> {
+        let (access_token, refresh_token) = refresh_access_token_retrying(
+            &self.endpoints,
+            &self.client_id,
+            &self.client_secret,
+            &self.refresh_token,
+        )
+        .await?;
+        if let Some(store) = &self.store {
+            store.save(&access_token, &refresh_token).await;
+        }
         self.access_token = access_token;
         self.refresh_token = refresh_token;
         Ok(self.access_token.as_ref())
     }
 }
 
+/// The dispenser of a token, the chat login's is shared with the Helix API so both use the
+/// same token.
+#[derive(Clone)]
+pub struct SharedTokens(Arc<tokio::sync::Mutex<AccessTokenDispenser>>);
+
+impl SharedTokens {
+    pub fn new(dispenser: AccessTokenDispenser) -> Self {
+        Self(Arc::new(tokio::sync::Mutex::new(dispenser)))
+    }
+
+    /// The token handed out last, without asking twitch whether it is still valid.
+    pub async fn current(&self) -> String {
+        self.0.lock().await.access_token.clone()
+    }
+
+    pub async fn refresh(&self) -> Result<String, ConnectorError> {
+        Ok(self.0.lock().await.refresh().await?.to_owned())
+    }
+
+    /// What twitch tells about the token, None when it expired or was revoked.
+    pub async fn validate(&self) -> Result<Option<TokenInfo>, ConnectorError> {
+        self.0.lock().await.validate().await
+    }
+
+    /// A token that can't be refreshed, for tests against a local server.
+    #[cfg(test)]
+    pub fn fixed(access_token: &str) -> Self {
+        let nowhere = "http://127.0.0.1:0".to_owned();
+        Self::new(AccessTokenDispenser {
+            client_id: "client".to_owned(),
+            client_secret: String::new(),
+            access_token: access_token.to_owned(),
+            refresh_token: String::new(),
+            endpoints: AuthEndpoints {
+                validation: nowhere.clone(),
+                token: nowhere.clone(),
+                device: nowhere,
+            },
+            store: None,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    #[test]
-    fn getting_code_out_of_url() {
-        let url1 = "https://localhost:3030/?code=y6gdw1g6vfi07y1otcpn5abfzb94n9&scope=chat%3Aread+chat%3Aedit";
-        let url2 = "/?code=y6gdw1g6vfi07y1otcpn5abfzb94n9&scope=chat%3Aread+chat%3Aedit";
-        let code = "y6gdw1g6vfi07y1otcpn5abfzb94n9";
-        assert_eq!(extract_code_from_url(url1), code);
-        assert_eq!(extract_code_from_url(url2), code);
-    }
+    use crate::storage::testing::TempDir;
 
     #[test]
     fn creating_url_with_query_params() {
@@ -286,4 +690,264 @@ mod tests {
         assert!(generated_uri.contains("param2=secondvalue"));
         assert_eq!(generated_uri.len(), base.len() + 37);
     }
+
+    // answers the requests in order with status and body, each to the expected path
+    fn auth_server(answers: Vec<(&'static str, u16, &'static str)>) -> String {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for ((path, status, body), stream) in answers.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+                let request = lines.next().unwrap().unwrap();
+                for line in lines.by_ref() {
+                    if line.unwrap().is_empty() {
+                        break;
+                    }
+                }
+                assert!(request.contains(path), "{} instead of {}", request, path);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    fn dispenser(server: &str, store: Option<TokenStore>) -> AccessTokenDispenser {
+        AccessTokenDispenser {
+            client_id: "client".to_owned(),
+            client_secret: "secret".to_owned(),
+            access_token: "expired".to_owned(),
+            refresh_token: "refresh".to_owned(),
+            endpoints: AuthEndpoints {
+                validation: format!("{}/validate", server),
+                token: format!("{}/token", server),
+                device: format!("{}/device", server),
+            },
+            store,
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_refreshed_and_stored() {
+        let server = auth_server(vec![
+            ("/validate", 401, "{}"),
+            (
+                "/token",
+                200,
+                r#"{"access_token":"renewed","refresh_token":"next"}"#,
+            ),
+        ]);
+        let path = TempDir::new("auth_store_refreshed");
+        let store = TokenStore::open(path.to_path_buf()).await.unwrap();
+        let mut dispenser = dispenser(&server, Some(store));
+        assert_eq!(dispenser.get().await.unwrap(), "renewed");
+        assert_eq!(
+            dispenser.store.as_ref().unwrap().load().unwrap(),
+            ("renewed".to_owned(), "next".to_owned())
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+    }
+
+    #[tokio::test]
+    async fn stored_tokens_are_validated_and_refreshed() {
+        let server = auth_server(vec![
+            (
+                "/validate",
+                200,
+                r#"{"client_id":"client","login":"carkhybot","scopes":["chat:read","chat:edit"],"user_id":"1","expires_in":5400}"#,
+            ),
+            (
+                "/token",
+                200,
+                r#"{"access_token":"renewed","refresh_token":"next"}"#,
+            ),
+            (
+                "/validate",
+                401,
+                r#"{"status":401,"message":"invalid access token"}"#,
+            ),
+        ]);
+        let path = TempDir::new("auth_store_stored");
+        let endpoints = || dispenser(&server, None).endpoints;
+        let config = Config::default();
+        let store = TokenStore::open(path.to_path_buf()).await.unwrap();
+        assert!(matches!(
+            AccessTokenDispenser::load(&config, Identity::Bot, endpoints(), store.clone()),
+            Err(ConnectorError::StoredValueNotAvailable(_))
+        ));
+        store.save("stored", "refresh").await;
+        let mut tokens =
+            AccessTokenDispenser::load(&config, Identity::Bot, endpoints(), store).unwrap();
+        let info = tokens.validate().await.unwrap().unwrap();
+        assert_eq!(info.login, "carkhybot");
+        assert_eq!(info.expires_in, Duration::from_secs(5400));
+        assert!(info
+            .missing_scopes(&config, Identity::Bot)
+            .contains(&"moderator:read:followers"));
+        assert!(!info
+            .missing_scopes(&config, Identity::Bot)
+            .contains(&"chat:read"));
+        assert_eq!(tokens.refresh().await.unwrap(), "renewed");
+        // opened again like after a restart, once sled released the lock
+        drop(tokens);
+        let store = TokenStore::open(path.to_path_buf()).await.unwrap();
+        let tokens =
+            AccessTokenDispenser::load(&config, Identity::Bot, endpoints(), store).unwrap();
+        assert_eq!(tokens.access_token, "renewed");
+        assert_eq!(tokens.validate().await.unwrap(), None);
+    }
+
+    #[test]
+    fn only_the_lock_is_waited_for() {
+        let path = TempDir::new("auth_store_locked");
+        let store = TokenStore::open_blocking(path.to_path_buf()).unwrap();
+        match TokenStore::open_blocking(path.to_path_buf()) {
+            Err(ConnectorError::KVError(error)) => assert!(is_locked(&error), "{}", error),
+            _ => panic!("the store is open already"),
+        }
+        drop(store);
+        // a file where the store belongs fails right away
+        let directory = TempDir::new("auth_store_file");
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("auth_store");
+        std::fs::write(&file, "").unwrap();
+        match TokenStore::open_blocking(file.clone()) {
+            Err(ConnectorError::KVError(error)) => assert!(!is_locked(&error), "{}", error),
+            _ => panic!("a file is no store"),
+        }
+    }
+
+    #[test]
+    fn broadcaster_scopes_move_to_their_token() {
+        let mut config = Config::default();
+        let single = required_scopes(&config, Identity::Bot);
+        assert!(single.contains(&"chat:edit"));
+        assert!(single.contains(&"channel:manage:polls"));
+        config.broadcaster.login = Some("captaincallback".to_owned());
+        let bot = required_scopes(&config, Identity::Bot);
+        assert!(bot.contains(&"chat:edit"));
+        assert!(bot.contains(&"moderator:manage:shoutouts"));
+        assert!(!bot.contains(&"channel:manage:polls"));
+        assert_eq!(
+            required_scopes(&config, Identity::Broadcaster),
+            BROADCASTER_SCOPES
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_refresh_token_is_not_retried() {
+        let server = auth_server(vec![(
+            "/token",
+            400,
+            r#"{"status":400,"message":"Invalid refresh token"}"#,
+        )]);
+        let mut dispenser = dispenser(&server, None);
+        let error = dispenser.refresh().await.unwrap_err();
+        assert!(
+            matches!(error, ConnectorError::TokenRequestRejected(ref message) if message == "Invalid refresh token"),
+            "{:?}",
+            error
+        );
+        assert_eq!(dispenser.access_token, "expired");
+    }
+
+    #[tokio::test]
+    async fn unreachable_token_endpoint_is_a_request_error() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let dispenser = dispenser(&format!("http://127.0.0.1:{}", port), None);
+        let result = refresh_access_token(
+            &dispenser.endpoints,
+            &dispenser.client_id,
+            &dispenser.client_secret,
+            &dispenser.refresh_token,
+        )
+        .await;
+        assert!(
+            matches!(result, Err(ConnectorError::ReqwestError(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    const DEVICE_CODE: &str = r#"{"device_code":"device","expires_in":1800,"interval":5,"user_code":"ABCDEFGH","verification_uri":"https://www.twitch.tv/activate?public=true&device-code=ABCDEFGH"}"#;
+
+    fn pending(reason: &'static str) -> (&'static str, u16, &'static str) {
+        let body = match reason {
+            "authorization_pending" => r#"{"status":400,"message":"authorization_pending"}"#,
+            "slow_down" => r#"{"status":400,"message":"slow_down"}"#,
+            _ => r#"{"status":400,"message":"expired_token"}"#,
+        };
+        ("/token", 400, body)
+    }
+
+    #[tokio::test]
+    async fn device_code_is_polled_until_authorized() {
+        let server = auth_server(vec![
+            ("/device", 200, DEVICE_CODE),
+            pending("authorization_pending"),
+            pending("slow_down"),
+            (
+                "/token",
+                200,
+                r#"{"access_token":"granted","refresh_token":"refresh"}"#,
+            ),
+        ]);
+        let endpoints = dispenser(&server, None).endpoints;
+        let scopes = ["chat:read", "chat:edit"];
+        let device_code = request_device_code(&endpoints, "client", &scopes)
+            .await
+            .unwrap();
+        assert_eq!(device_code.user_code, "ABCDEFGH");
+        let mut sleeps = Vec::new();
+        let tokens = poll_device_token(&endpoints, "client", &scopes, &device_code, |delay| {
+            sleeps.push(delay.as_secs());
+            std::future::ready(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(tokens, ("granted".to_owned(), "refresh".to_owned()));
+        // twitch asked to slow down after the second request
+        assert_eq!(sleeps, vec![5, 5, 10]);
+    }
+
+    #[tokio::test]
+    async fn expired_device_code_ends_polling() {
+        let server = auth_server(vec![
+            ("/device", 200, DEVICE_CODE),
+            pending("authorization_pending"),
+            pending("expired_token"),
+        ]);
+        let endpoints = dispenser(&server, None).endpoints;
+        let device_code = request_device_code(&endpoints, "client", &["chat:read"])
+            .await
+            .unwrap();
+        let result = poll_device_token(&endpoints, "client", &["chat:read"], &device_code, |_| {
+            std::future::ready(())
+        })
+        .await;
+        assert!(
+            matches!(result, Err(ConnectorError::AuthorizationExpired)),
+            "{:?}",
+            result
+        );
+    }
 }
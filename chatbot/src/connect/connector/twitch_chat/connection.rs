@@ -0,0 +1,77 @@
+use super::{priority::SendCount, split::Overflow, Priority};
+use crate::connect::{error::ConnectorError, ChatBotEvent};
+use std::{error::Error, ops::ControlFlow, time::Duration};
+
+/// What the bot needs from twitch chat: events coming in and messages going out.
+/// Bot features are written against this trait, so that they can be tested with
+/// [MockConnection](super::testing::MockConnection) instead of a real connection.
+pub trait Connection {
+    /// The next event for the chat bot, None once the connection is gone for good.
+    async fn next_event(&mut self) -> Option<ChatBotEvent>;
+
+    /// Messages starting with "/me " are sent as action. Long messages are split or cut off.
+    fn send_message(
+        &self,
+        channel: &str,
+        message: &str,
+        overflow: Overflow,
+        priority: Priority,
+    ) -> Result<(), ConnectorError>;
+
+    /// Answer the message with the given id as a threaded reply.
+    fn send_reply(
+        &self,
+        channel: &str,
+        parent_msg_id: &str,
+        message: &str,
+        priority: Priority,
+    ) -> Result<(), ConnectorError>;
+
+    /// Join another channel, also after every reconnect. Joining a channel twice does nothing.
+    fn join(&self, channel: &str) -> Result<(), ConnectorError>;
+
+    /// Leave the channel, it is not joined again after a reconnect.
+    fn part(&self, channel: &str) -> Result<(), ConnectorError>;
+
+    /// Whether twitch granted the capability, e.g. "twitch.tv/tags".
+    fn has_capability(&self, capability: &str) -> bool;
+
+    /// Hand the event to the handler after the delay, e.g. for repeating messages.
+    fn schedule(&self, delay: Duration, event: ChatBotEvent);
+
+    /// Lines not sent yet, most of them chat messages waiting for twitch's rate limit.
+    fn queue_depth(&self) -> usize;
+
+    /// Lines of the priority sent so far, and dropped because they waited too long.
+    fn send_count(&self, priority: Priority) -> SendCount;
+
+    /// Stop sending, with the goodbye in every joined channel as the last chat message.
+    async fn shutdown(
+        &self,
+        goodbye: Option<&str>,
+        deadline: Duration,
+    ) -> Result<(), ConnectorError>;
+
+    /// Hands every event to the handler until it breaks, returns an error or the events end.
+    async fn run<H: EventHandler>(&mut self, handler: &mut H) -> Result<(), Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        while let Some(event) = self.next_event().await {
+            if handler.handle(event, self).await?.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Called by [Connection::run] for every event, one at a time.
+pub trait EventHandler {
+    /// Break to stop running, e.g. after the Shutdown event.
+    async fn handle<C: Connection>(
+        &mut self,
+        event: ChatBotEvent,
+        chat: &C,
+    ) -> Result<ControlFlow<()>, Box<dyn Error>>;
+}
@@ -1,124 +1,2440 @@
 use super::{
-    auth::AccessTokenDispenser,
-    receive::{receive, ReceiveEvent},
-    send::{get_login_tasks, send, send_multiple, SendTask},
+    auth::{AccessTokenDispenser, Identity, SharedTokens},
+    connection::Connection,
+    duplicates::DuplicateGuard,
+    health::Health,
+    keepalive::{keepalive_loop, Activity, Keepalive, PONG_TIMEOUT},
+    line_assembler::LineAssembler,
+    outgoing::Outgoing,
+    priority::{Priority, PriorityQueue, SendCount, SendCounters},
+    rate_limit::{MessageLimiter, ModeratedChannels, SlidingWindow},
+    receive::{parse_line, ConnectorEvent, ReceiveEvent},
+    recent::RecentMessages,
+    retry_manager::{random_jitter, random_u64, Backoff},
+    send::get_login_lines,
+    split::{fit_message, Overflow},
+    transport::{
+        open, ChunkReader, ConnectionWriter, LineWriter, TransportReader, TransportWriter,
+    },
 };
 use crate::{
-    app_config::AppConfig,
-    connect::{error::ConnectorError, ChatBotEvent},
+    config::{ChatTransport, ConnectionSecurity, SharedConfig},
+    connect::{error::ConnectorError, ChatBotEvent, ConnectionState},
+    prometheus,
 };
 use std::{
-    net::TcpStream,
-    sync::mpsc::{self, Sender, SyncSender},
-    thread::{self, JoinHandle},
+    collections::{BTreeSet, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
-use websocket::{receiver::Reader, sync::Writer, ClientBuilder};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc, Notify,
+};
+
+const TWITCH_CHAT_HOST: &str = "irc.chat.twitch.tv";
+const TWITCH_CHAT_WEBSOCKET_HOST: &str = "irc-ws.chat.twitch.tv";
+const TWITCH_SERVER: &str = "tmi.twitch.tv";
+// logged in place of the access token
+const REDACTED: &str = "[redacted]";
 
-pub struct TwitchChatConnector<'a> {
+/// The real connection to twitch chat.
+pub struct TwitchChatConnector {
     _receive_thread: ReceiveThread,
-    send_thread: SendThread,
-    app_config: &'a AppConfig,
-}
-
-impl<'a> TwitchChatConnector<'a> {
-    pub async fn new(
-        app_config: &'a AppConfig,
-        chatbot_event_sender: Sender<ChatBotEvent>,
-    ) -> TwitchChatConnector<'a> {
-        let chat_client = ClientBuilder::new("ws://irc-ws.chat.twitch.tv:80")
-            .unwrap()
-            .connect_insecure()
-            .unwrap();
-        let (receiver, mut sender) = chat_client.split().unwrap();
-        let mut access_token_dispenser = AccessTokenDispenser::new(app_config)
-            .await
-            .expect("Could not instantiate Twitch connector");
-        let access_token: String = access_token_dispenser
-            .get()
-            .await
-            .expect("Could not get valid access token")
-            .to_owned();
-        send_multiple(
-            &mut sender,
-            get_login_tasks(
-                &access_token,
-                app_config.bot_user_name(),
-                app_config.channel_name(),
-            ),
-        )
-        .expect("Could not log in");
-        let send_thread = send_thread(sender);
-        let receive_thread = receive_thread(receiver, chatbot_event_sender, send_thread.tx.clone());
-        Self {
-            send_thread,
+    // subscribed before the connection opens, so that no event is missed
+    events: broadcast::Receiver<ChatBotEvent>,
+    chat: ChatHandle,
+    tokens: Option<SharedTokens>,
+}
+
+// events the handler hasn't taken yet, older ones are lost when it can't keep up
+const EVENT_CAPACITY: usize = 1024;
+
+// what the connector needs besides the login
+struct ConnectorSettings {
+    forward_pings: bool,
+    send: SendSettings,
+    keepalive: Duration,
+    // renews the access token after twitch rejected the login, None for anonymous logins
+    tokens: Option<SharedTokens>,
+}
+
+impl TwitchChatConnector {
+    /// The chat settings are read from the shared config whenever a line is sent,
+    /// everything else only once.
+    pub async fn new(shared: &SharedConfig) -> TwitchChatConnector {
+        let config = shared.load();
+        let (credentials, tokens) = if config.twitch.anonymous {
+            let nick = anonymous_nick();
+            (Credentials::Anonymous { nick }, None)
+        } else {
+            let mut access_token_dispenser = AccessTokenDispenser::new(&config, Identity::Bot)
+                .await
+                .expect("Could not instantiate Twitch connector");
+            let access_token: String = access_token_dispenser
+                .get()
+                .await
+                .expect("Could not get valid access token")
+                .to_owned();
+            let credentials = Credentials::Token {
+                access_token,
+                user_name: config.twitch.user.clone(),
+            };
+            (credentials, Some(SharedTokens::new(access_token_dispenser)))
+        };
+        let login = Login {
+            credentials,
+            channels: config.channel_names(),
+            capabilities: config.twitch.capabilities.clone(),
+            transport: config.connection.transport,
+            security: config.connection.security,
+            verify_certificates: config.connection.verify_certificates,
+            server: config.chat_server(),
+        };
+        let settings = ConnectorSettings {
+            forward_pings: config.connection.forward_pings,
+            send: SendSettings {
+                moderated: ModeratedChannels::default(),
+                config: shared.clone(),
+            },
+            keepalive: config.keepalive(),
+            tokens,
+        };
+        Self::start(login, settings).expect("Could not log in")
+    }
+
+    // the send task is spawned on the current tokio runtime
+    fn start(login: Login, settings: ConnectorSettings) -> Result<Self, ConnectorError> {
+        let read_only = matches!(login.credentials, Credentials::Anonymous { .. });
+        let activity = Activity::new(Instant::now());
+        let (receiver, sender) = connect(&login, &activity)?;
+        // the websocket client only writes blocking, so writing has a thread of its own
+        let sender = ConnectionWriter::spawn(sender);
+        let moderated = settings.send.moderated.clone();
+        let send_task = send_task(sender.clone(), RESEND_DELAY, settings.send);
+        let control = sender.clone();
+        let mut keepalive_control = sender.clone();
+        let keepalive_activity = activity.clone();
+        let mut keepalive = Keepalive::new(settings.keepalive, PONG_TIMEOUT);
+        tokio::spawn(async move {
+            loop {
+                keepalive_loop(
+                    &keepalive_activity,
+                    &mut keepalive_control,
+                    &mut keepalive,
+                    Instant::now,
+                    tokio::time::sleep,
+                )
+                .await;
+            }
+        });
+        let channels = Channels::new(login.channels.clone(), send_task.queue.clone());
+        let session = Session {
+            channels: channels.clone(),
+            forward_pings: settings.forward_pings,
+            capabilities: Capabilities::default(),
+            moderated,
+        };
+        let (events_tx, events) = broadcast::channel(EVENT_CAPACITY);
+        let chat = ChatHandle {
+            queue: send_task.queue.clone(),
+            counters: send_task.counters,
+            capabilities: session.capabilities.clone(),
+            read_only,
+            channels,
+            control: sender.clone(),
+            events: events_tx.clone(),
+        };
+        // the next connection logs in with the renewed token
+        let login = Arc::new(Mutex::new(login));
+        let renew_login = settings.tokens.clone().map(|tokens| {
+            let login = login.clone();
+            let runtime = tokio::runtime::Handle::current();
+            Box::new(move || {
+                let renewed = runtime.block_on(tokens.refresh())?;
+                if let Credentials::Token { access_token, .. } =
+                    &mut login.lock().unwrap().credentials
+                {
+                    *access_token = renewed;
+                }
+                Ok(())
+            }) as RenewLogin
+        });
+        let supervisor = Supervisor {
+            reconnect: move || {
+                let connection = connect(&login.lock().unwrap(), &activity)?;
+                switch_connection(&sender, connection)
+            },
+            sleep: thread::sleep,
+            backoff: Backoff::default(),
+            jitter: random_jitter,
+            max_attempts: MAX_RECONNECT_ATTEMPTS,
+            renew_login,
+        };
+        // the websocket client only reads blocking, so reading has a thread of its own too
+        let receive_thread = receive_thread(
+            receiver,
+            session,
+            control,
+            supervisor,
+            events_tx,
+            send_task.queue,
+        );
+        Ok(Self {
             _receive_thread: receive_thread,
-            app_config,
+            events,
+            chat,
+            tokens: settings.tokens,
+        })
+    }
+
+    /// For sending from other tasks while the connector runs.
+    pub fn handle(&self) -> ChatHandle {
+        self.chat.clone()
+    }
+
+    /// The tokens the bot logged in with, None when it reads chat anonymously.
+    pub fn tokens(&self) -> Option<SharedTokens> {
+        self.tokens.clone()
+    }
+
+    /// For the health endpoints, updated by the receive thread.
+    pub fn health(&self) -> Health {
+        self.chat.channels.health.clone()
+    }
+}
+
+impl Connection for TwitchChatConnector {
+    async fn next_event(&mut self) -> Option<ChatBotEvent> {
+        loop {
+            match self.events.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "the chat bot missed events")
+                }
+                Err(RecvError::Closed) => return None,
+            }
         }
     }
 
-    pub fn send_message(&self, message: &'a str) -> Result<(), ConnectorError> {
-        Ok(self.send_thread.tx.send(SendTask::PrivateMessage(
-            self.app_config.channel_name().to_string(),
-            message.to_string(),
-        ))?)
+    fn send_message(
+        &self,
+        channel: &str,
+        message: &str,
+        overflow: Overflow,
+        priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        self.chat.send_message(channel, message, overflow, priority)
+    }
+
+    fn send_reply(
+        &self,
+        channel: &str,
+        parent_msg_id: &str,
+        message: &str,
+        priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        self.chat
+            .send_reply(channel, parent_msg_id, message, priority)
+    }
+
+    fn join(&self, channel: &str) -> Result<(), ConnectorError> {
+        self.chat.join(channel)
+    }
+
+    fn part(&self, channel: &str) -> Result<(), ConnectorError> {
+        self.chat.part(channel)
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.chat.has_capability(capability)
+    }
+
+    fn schedule(&self, delay: Duration, event: ChatBotEvent) {
+        self.chat.schedule(delay, event)
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.chat.queue_depth()
+    }
+
+    fn send_count(&self, priority: Priority) -> SendCount {
+        self.chat.send_count(priority)
+    }
+
+    async fn shutdown(
+        &self,
+        goodbye: Option<&str>,
+        deadline: Duration,
+    ) -> Result<(), ConnectorError> {
+        self.chat.shutdown(goodbye, deadline).await
+    }
+}
+
+// the reading and writing threads end with the connection, so the runtime can shut down
+// also when the bot stopped without shutting down
+impl Drop for TwitchChatConnector {
+    fn drop(&mut self) {
+        self.chat.queue.close();
+        self.chat.control.clone().close();
+    }
+}
+
+/// Sends to twitch chat, cheap to clone and usable from any task.
+#[derive(Clone)]
+pub struct ChatHandle {
+    queue: SendQueue,
+    counters: SendCounters,
+    capabilities: Capabilities,
+    read_only: bool,
+    channels: Channels,
+    control: ConnectionWriter<TransportWriter>,
+    events: broadcast::Sender<ChatBotEvent>,
+}
+
+impl ChatHandle {
+    /// Hand the event to the handler after the delay, e.g. for repeating messages.
+    pub fn schedule(&self, delay: Duration, event: ChatBotEvent) {
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = events.send(event);
+        });
+    }
+
+    /// Stop sending, with the goodbye in every joined channel as the last chat message.
+    /// Lines still waiting for the rate limit at the deadline are lost, then the QUIT is sent.
+    pub async fn shutdown(
+        &self,
+        goodbye: Option<&str>,
+        deadline: Duration,
+    ) -> Result<(), ConnectorError> {
+        let goodbyes = match goodbye {
+            Some(goodbye) if !self.read_only => self
+                .channels
+                .names()
+                .iter()
+                .map(|channel| Outgoing::privmsg(channel, goodbye))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => Vec::new(),
+        };
+        let queue = self.queue.clone();
+        let mut control = self.control.clone();
+        // waiting for the queue to drain blocks
+        tokio::task::spawn_blocking(move || shut_down(&queue, &mut control, goodbyes, deadline))
+            .await
+            .unwrap_or_else(|error| Err(ConnectorError::MessageSendFailed(error.to_string())))
+    }
+
+    /// Join another channel, also after every reconnect. Joining a channel twice does nothing.
+    pub fn join(&self, channel: &str) -> Result<(), ConnectorError> {
+        self.channels.join(channel)
+    }
+
+    /// Leave the channel, it is not joined again after a reconnect.
+    pub fn part(&self, channel: &str) -> Result<(), ConnectorError> {
+        self.channels.part(channel)
+    }
+
+    /// Whether twitch granted the capability, e.g. "twitch.tv/tags", on the current connection.
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    /// Lines not sent yet, most of them chat messages waiting for twitch's rate limit.
+    pub fn queue_depth(&self) -> usize {
+        self.queue.depth()
+    }
+
+    /// Lines of the priority sent so far, and dropped because they waited too long.
+    pub fn send_count(&self, priority: Priority) -> SendCount {
+        self.counters.get(priority)
+    }
+
+    /// Messages starting with "/me " are sent as action. Long messages are split or cut off.
+    pub fn send_message(
+        &self,
+        channel: &str,
+        message: &str,
+        overflow: Overflow,
+        priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        self.check_writable("messages")?;
+        self.push_all(message_lines(channel, message, overflow)?, priority)
+    }
+
+    /// Answer the message with the given id as a threaded reply.
+    pub fn send_reply(
+        &self,
+        channel: &str,
+        parent_msg_id: &str,
+        message: &str,
+        priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        self.check_writable("replies")?;
+        self.push_all(reply_lines(channel, parent_msg_id, message)?, priority)
+    }
+
+    // the parts of a message are only queued once all of them are valid
+    fn push_all(&self, lines: Vec<Outgoing>, priority: Priority) -> Result<(), ConnectorError> {
+        for line in lines {
+            self.queue.push(line, priority)?;
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self, what: &'static str) -> Result<(), ConnectorError> {
+        check_writable(self.read_only, what)
+    }
+}
+
+// the lines sent for a chat message, an action when it starts with "/me "
+pub(super) fn message_lines(
+    channel: &str,
+    message: &str,
+    overflow: Overflow,
+) -> Result<Vec<Outgoing>, ConnectorError> {
+    let (action, text) = match message.strip_prefix("/me ") {
+        Some(action) => (true, action),
+        None => (false, message),
+    };
+    fit_message(text, overflow)
+        .iter()
+        .map(|part| {
+            if action {
+                Outgoing::action(channel, part)
+            } else {
+                Outgoing::privmsg(channel, part)
+            }
+        })
+        .collect()
+}
+
+pub(super) fn reply_lines(
+    channel: &str,
+    parent_msg_id: &str,
+    message: &str,
+) -> Result<Vec<Outgoing>, ConnectorError> {
+    fit_message(message, Overflow::Split)
+        .iter()
+        .map(|part| Outgoing::privmsg_reply(channel, parent_msg_id, part))
+        .collect()
+}
+
+// the goodbyes get ahead of answers and repeating messages, which may not make it anyway
+fn shut_down<C: LineWriter>(
+    queue: &SendQueue,
+    control: &mut C,
+    goodbyes: Vec<Outgoing>,
+    deadline: Duration,
+) -> Result<(), ConnectorError> {
+    for goodbye in goodbyes {
+        queue.push(goodbye, Priority::Moderation)?;
+    }
+    queue.close();
+    if !queue.flush(deadline) {
+        tracing::warn!(
+            lines = queue.depth(),
+            "lines were not sent before shutting down"
+        );
+    }
+    // the connection may already be gone
+    if let Err(error) = Outgoing::quit().and_then(|quit| control.write_line(quit)) {
+        tracing::warn!(%error, "could not send QUIT");
+    }
+    control.close();
+    Ok(())
+}
+
+// kept out of the connector so that it can be tested without a connection
+fn check_writable(read_only: bool, what: &'static str) -> Result<(), ConnectorError> {
+    if read_only {
+        Err(ConnectorError::ReadOnlyConnection(what))
+    } else {
+        Ok(())
+    }
+}
+
+// twitch lets anyone read chat as justinfan followed by digits, without a password
+enum Credentials {
+    Token {
+        access_token: String,
+        user_name: String,
+    },
+    Anonymous {
+        nick: String,
+    },
+}
+
+fn anonymous_nick() -> String {
+    format!("justinfan{}", random_u64() % 100_000)
+}
+
+// everything needed to log in again after a reconnect
+struct Login {
+    credentials: Credentials,
+    channels: Vec<String>,
+    capabilities: Vec<String>,
+    transport: ChatTransport,
+    security: ConnectionSecurity,
+    verify_certificates: bool,
+    // host and port instead of twitch's, for test harnesses
+    server: Option<(String, u16)>,
+}
+
+// the channels are joined once twitch confirmed the login
+fn connect(
+    login: &Login,
+    activity: &Activity,
+) -> Result<(ChatReceiver, TransportWriter), ConnectorError> {
+    let endpoint = match (login.transport, login.security) {
+        (ChatTransport::Tcp, ConnectionSecurity::Plain) => (TWITCH_CHAT_HOST, 6667),
+        (ChatTransport::Tcp, ConnectionSecurity::Tls) => (TWITCH_CHAT_HOST, 6697),
+        (ChatTransport::WebSocket, ConnectionSecurity::Plain) => (TWITCH_CHAT_WEBSOCKET_HOST, 80),
+        (ChatTransport::WebSocket, ConnectionSecurity::Tls) => (TWITCH_CHAT_WEBSOCKET_HOST, 443),
+    };
+    let endpoint = match &login.server {
+        Some((host, port)) => (host.as_str(), *port),
+        None => endpoint,
+    };
+    let span = tracing::info_span!(
+        "connect",
+        host = endpoint.0,
+        port = endpoint.1,
+        transport = ?login.transport,
+        security = ?login.security,
+    );
+    let _connect = span.enter();
+    let (receiver, mut sender) = open(
+        login.transport,
+        endpoint,
+        login.security,
+        login.verify_certificates,
+    )
+    .inspect_err(|error| tracing::warn!(%error, "could not connect"))?;
+    tracing::info!("connected");
+    log_in(&mut sender, login)?;
+    activity.received(Instant::now());
+    Ok((
+        ChatReceiver {
+            reader: receiver,
+            assembler: LineAssembler::new(),
+            activity: activity.clone(),
+        },
+        sender,
+    ))
+}
+
+fn log_in<W: LineWriter>(writer: &mut W, login: &Login) -> Result<(), ConnectorError> {
+    let (password, user_name) = match &login.credentials {
+        Credentials::Token {
+            access_token,
+            user_name,
+        } => (Some(access_token.as_str()), user_name),
+        Credentials::Anonymous { nick } => (None, nick),
+    };
+    let token = password.map(|_| REDACTED);
+    let span = tracing::info_span!("login", user = %user_name, token);
+    let _login = span.enter();
+    tracing::info!(capabilities = %login.capabilities.join(" "), "logging in");
+    for line in get_login_lines(password, user_name, &login.capabilities)? {
+        writer.write_line(line)?;
+    }
+    Ok(())
+}
+
+// the new writer replaces the old one in the writer thread, the reader is handed back
+// to the receive thread. Lines not sent yet stay queued for the new writer
+fn switch_connection<R, W>(
+    writer: &ConnectionWriter<W>,
+    (receiver, new_writer): (R, W),
+) -> Result<R, ConnectorError> {
+    tracing::info!("reconnected to twitch chat");
+    prometheus::RECONNECTS.inc(&[]);
+    writer.replace(new_writer);
+    Ok(receiver)
+}
+
+trait EventReceiver {
+    fn receive_events(&mut self) -> Result<Vec<ReceiveEvent>, ConnectorError>;
+}
+
+// lines may be split across reads, so the assembler lives as long as the connection
+struct ChatReceiver {
+    reader: TransportReader,
+    assembler: LineAssembler,
+    // any bytes count, also lines that can't be parsed
+    activity: Activity,
+}
+
+impl EventReceiver for ChatReceiver {
+    fn receive_events(&mut self) -> Result<Vec<ReceiveEvent>, ConnectorError> {
+        let chunk = self.reader.read_chunk()?;
+        self.activity.received(Instant::now());
+        Ok(self
+            .assembler
+            .push(&chunk)
+            .filter_map(|line| parse_line(&line))
+            .collect())
+    }
+}
+
+// where the receive thread delivers the events for the chat bot
+trait EventSink {
+    // false once nobody takes events anymore
+    fn deliver(&self, event: ChatBotEvent) -> bool;
+}
+
+impl EventSink for broadcast::Sender<ChatBotEvent> {
+    fn deliver(&self, event: ChatBotEvent) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+const MAX_RECONNECT_ATTEMPTS: u32 = 20;
+
+type RenewLogin = Box<dyn FnMut() -> Result<(), ConnectorError> + Send>;
+
+// reconnects after the connection was lost, waiting longer after every failed attempt
+struct Supervisor<F, S> {
+    reconnect: F,
+    sleep: S,
+    backoff: Backoff,
+    jitter: fn() -> f64,
+    max_attempts: u32,
+    renew_login: Option<RenewLogin>,
+}
+
+impl<F, S> Supervisor<F, S> {
+    // false when there is nothing to renew or renewing failed
+    fn renew_login(&mut self) -> bool {
+        let Some(renew_login) = self.renew_login.as_mut() else {
+            return false;
+        };
+        match renew_login() {
+            Ok(()) => {
+                tracing::info!("renewed the access token");
+                true
+            }
+            Err(error) => {
+                tracing::warn!(%error, "could not renew the access token");
+                false
+            }
+        }
+    }
+
+    // the first attempt doesn't wait, the following ones back off
+    fn reconnect_now<R>(&mut self, send_chat_bot_events: &impl EventSink) -> Option<R>
+    where
+        F: FnMut() -> Result<R, ConnectorError>,
+        S: FnMut(Duration),
+    {
+        match (self.reconnect)() {
+            Ok(receiver) => Some(receiver),
+            Err(error) => {
+                tracing::warn!(%error, "reconnecting failed");
+                self.recover(send_chat_bot_events)
+            }
+        }
+    }
+
+    // None when all attempts failed, the chat bot is told about the state changes
+    fn recover<R>(&mut self, send_chat_bot_events: &impl EventSink) -> Option<R>
+    where
+        F: FnMut() -> Result<R, ConnectorError>,
+        S: FnMut(Duration),
+    {
+        let report = |state| send_chat_bot_events.deliver(ChatBotEvent::Connection(state));
+        for attempt in 1..=self.max_attempts {
+            // the bot shut down, a new connection would only keep the runtime from ending
+            if !report(ConnectionState::Reconnecting { attempt }) {
+                tracing::info!("not reconnecting, nobody takes events anymore");
+                return None;
+            }
+            (self.sleep)(self.backoff.delay(attempt, (self.jitter)()));
+            match (self.reconnect)() {
+                Ok(receiver) => {
+                    report(ConnectionState::Connected);
+                    return Some(receiver);
+                }
+                Err(error) => tracing::warn!(attempt, %error, "reconnect attempt failed"),
+            }
+        }
+        report(ConnectionState::Disconnected);
+        None
     }
 }
 
 struct ReceiveThread {
-    _handle: JoinHandle<()>,
+    _handle: tokio::task::JoinHandle<()>,
 }
 
-fn receive_thread(
-    mut receiver: Reader<TcpStream>,
-    send_chat_bot_events: Sender<ChatBotEvent>,
-    send_tasks: SyncSender<SendTask>,
-) -> ReceiveThread {
-    use ReceiveEvent::*;
-    let handle = thread::spawn(move || 'outer: loop {
-        match receive(&mut receiver) {
+fn receive_thread<R, C, F, S, E>(
+    receiver: R,
+    session: Session,
+    control: C,
+    supervisor: Supervisor<F, S>,
+    send_chat_bot_events: E,
+    send_tasks: SendQueue,
+) -> ReceiveThread
+where
+    E: EventSink + Send + 'static,
+    R: EventReceiver + Send + 'static,
+    C: LineWriter + Send + 'static,
+    F: FnMut() -> Result<R, ConnectorError> + Send + 'static,
+    S: FnMut(Duration) + Send + 'static,
+{
+    let handle = tokio::task::spawn_blocking(move || {
+        if let Err(error) = receive_loop(
+            receiver,
+            &session,
+            control,
+            supervisor,
+            send_chat_bot_events,
+            send_tasks,
+        ) {
+            tracing::error!(%error, "reader thread stopped");
+        }
+    });
+    ReceiveThread { _handle: handle }
+}
+
+// PINGs are answered here, so the connection stays open no matter what the chat bot does.
+// A lost connection is reestablished by the supervisor, the channels are joined again after the login.
+// A rejected login is retried once with a renewed token, the same token would be rejected again
+fn receive_loop<R, C, F, S, E>(
+    mut receiver: R,
+    session: &Session,
+    mut control: C,
+    mut supervisor: Supervisor<F, S>,
+    send_chat_bot_events: E,
+    send_tasks: SendQueue,
+) -> Result<(), ConnectorError>
+where
+    E: EventSink,
+    R: EventReceiver,
+    C: LineWriter,
+    F: FnMut() -> Result<R, ConnectorError>,
+    S: FnMut(Duration),
+{
+    let mut recent = RecentMessages::default();
+    let mut login_renewed = false;
+    'outer: loop {
+        match receiver.receive_events() {
             Ok(events) => {
+                session.channels.health.read(Instant::now());
                 for event in events {
-                    if let ChatBotEvent(event_content) = event {
-                        if let Err(error) = send_chat_bot_events.send(event_content) {
-                            println!("Reader thread stopped with error {:?}", error);
-                            break 'outer;
+                    match event {
+                        ReceiveEvent::ChatBotEvent(event_content) => {
+                            if let Some(reason) = authentication_failure(&event_content) {
+                                // the token most likely expired, a renewed one is tried once
+                                if !login_renewed && supervisor.renew_login() {
+                                    login_renewed = true;
+                                    session.channels.logged_out();
+                                    match supervisor.reconnect_now(&send_chat_bot_events) {
+                                        Some(new_receiver) => {
+                                            receiver = new_receiver;
+                                            continue 'outer;
+                                        }
+                                        None => break 'outer,
+                                    }
+                                }
+                                send_chat_bot_events.deliver(ChatBotEvent::Connection(
+                                    ConnectionState::Disconnected,
+                                ));
+                                return Err(ConnectorError::AuthenticationFailed(
+                                    reason.to_owned(),
+                                ));
+                            }
+                            if !recent.is_new(&event_content, Instant::now()) {
+                                continue;
+                            }
+                            if let ChatBotEvent::UserState(user_state) = &event_content {
+                                session.moderated.update(user_state);
+                            }
+                            match &event_content {
+                                ChatBotEvent::Join { user, channel } => {
+                                    session.channels.joined(Some(user), channel)
+                                }
+                                ChatBotEvent::RoomState(room) => {
+                                    session.channels.joined(None, &room.channel)
+                                }
+                                _ => {}
+                            }
+                            if !send_chat_bot_events.deliver(event_content) {
+                                tracing::info!(
+                                    "reader thread stopped, nobody takes events anymore"
+                                );
+                                break 'outer;
+                            }
+                        }
+                        ReceiveEvent::ConnectorEvent(ConnectorEvent::Ping { server }) => {
+                            let pong = Outgoing::pong(if server.is_empty() {
+                                TWITCH_SERVER
+                            } else {
+                                &server
+                            });
+                            if let Err(error) = pong.and_then(|pong| control.write_line(pong)) {
+                                tracing::error!(%error, "reader thread stopped");
+                                break 'outer;
+                            }
+                            if session.forward_pings
+                                && !send_chat_bot_events.deliver(ChatBotEvent::Ping { server })
+                            {
+                                tracing::info!(
+                                    "reader thread stopped, nobody takes events anymore"
+                                );
+                                break 'outer;
+                            }
+                        }
+                        ReceiveEvent::ConnectorEvent(ConnectorEvent::Welcome { login }) => {
+                            tracing::info!(%login, "logged in");
+                            prometheus::CONNECTED.set(1);
+                            session.channels.health.logged_in(&login);
+                            login_renewed = false;
+                            if let Err(error) = session.channels.logged_in() {
+                                tracing::error!(%error, "reader thread stopped");
+                                break 'outer;
+                            }
+                        }
+                        ReceiveEvent::ConnectorEvent(ConnectorEvent::Numeric { .. }) => {}
+                        ReceiveEvent::ConnectorEvent(ConnectorEvent::CapAck(granted)) => {
+                            session.capabilities.grant(granted);
                         }
-                    } else {
-                        if let Err(error) = send_tasks.send(SendTask::Pong) {
-                            println!("Reader thread stopped with error {:?}", error);
-                            break 'outer;
+                        // the bot keeps working, features needing the capability fall back
+                        ReceiveEvent::ConnectorEvent(ConnectorEvent::CapNak(refused)) => {
+                            tracing::warn!(
+                                capabilities = %refused.join(" "),
+                                "twitch refused the capabilities"
+                            );
+                            session.capabilities.refuse(&refused);
+                        }
+                        ReceiveEvent::ConnectorEvent(ConnectorEvent::Reconnect) => {
+                            // twitch restarts the server, so lines queued so far are still sent
+                            // on the old connection and the first attempt doesn't wait
+                            if !send_tasks.flush(FLUSH_TIMEOUT) {
+                                tracing::info!("queued lines are sent after the reconnect");
+                            }
+                            session.channels.logged_out();
+                            match supervisor.reconnect_now(&send_chat_bot_events) {
+                                // events after RECONNECT belong to the old connection
+                                Some(new_receiver) => {
+                                    receiver = new_receiver;
+                                    continue 'outer;
+                                }
+                                None => break 'outer,
+                            }
                         }
                     }
                 }
             }
-            Err(error) => {
-                println!("Reader thread stopped with error {:?}", error);
+            // shutting down closed the connection
+            Err(_) if send_tasks.is_closed() => {
+                tracing::info!("reader thread stopped, the connection was shut down");
                 break 'outer;
             }
+            Err(error) => {
+                tracing::warn!(%error, "connection lost");
+                prometheus::CONNECTED.set(0);
+                session.channels.logged_out();
+                match supervisor.recover(&send_chat_bot_events) {
+                    Some(new_receiver) => receiver = new_receiver,
+                    None => break 'outer,
+                }
+            }
         }
-    });
-    ReceiveThread { _handle: handle }
+    }
+    Ok(())
+}
+
+// what the receive thread needs to know about the bot's session in the channels
+struct Session {
+    channels: Channels,
+    forward_pings: bool,
+    capabilities: Capabilities,
+    // the bot may send more messages where it is moderator
+    moderated: ModeratedChannels,
+}
+
+// capabilities granted by twitch, shared between the receive thread and the connector
+#[derive(Clone, Default)]
+struct Capabilities(Arc<Mutex<BTreeSet<String>>>);
+
+impl Capabilities {
+    fn contains(&self, capability: &str) -> bool {
+        self.0.lock().unwrap().contains(capability)
+    }
+
+    fn grant(&self, capabilities: Vec<String>) {
+        self.0.lock().unwrap().extend(capabilities);
+    }
+
+    fn refuse(&self, capabilities: &[String]) {
+        let mut granted = self.0.lock().unwrap();
+        for capability in capabilities {
+            granted.remove(capability);
+        }
+    }
+}
+
+// twitch sends these notices to '*' before closing the connection
+fn authentication_failure(event: &ChatBotEvent) -> Option<&str> {
+    match event {
+        ChatBotEvent::Notice(notice)
+            if notice.channel.is_none()
+                && matches!(
+                    notice.text.as_str(),
+                    "Login authentication failed" | "Improperly formatted auth"
+                ) =>
+        {
+            Some(&notice.text)
+        }
+        _ => None,
+    }
+}
+
+// twitch allows 20 joins per 10 seconds, a JOIN line counts once for each of its channels
+const JOIN_LIMIT: usize = 20;
+const JOIN_WINDOW: Duration = Duration::from_secs(10);
+// beyond the limit twitch drops joins without a word, so each has to be confirmed
+const JOIN_ATTEMPTS: u32 = 5;
+
+// how long twitch has to confirm a join, and how long to wait before the next attempt
+struct JoinRetry {
+    timeout: Duration,
+    backoff: Backoff,
+}
+
+impl Default for JoinRetry {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            backoff: Backoff::new(Duration::from_secs(2), Duration::from_secs(60)),
+        }
+    }
+}
+
+// a join twitch didn't confirm yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attempt {
+    // waiting in `pending`
+    Queued,
+    // sent, it failed when nothing confirmed it by then
+    Sent(Instant),
+    // failed, it is queued again at this time
+    Retry(Instant),
+}
+
+// a channel's join from the login until twitch confirmed it or the bot gave up, its events
+// are logged in the join's span
+struct Join {
+    attempt: u32,
+    next: Attempt,
+    span: tracing::Span,
+}
+
+impl Join {
+    fn queued(channel: &str) -> Self {
+        Self {
+            attempt: 0,
+            next: Attempt::Queued,
+            span: tracing::info_span!("join", channel),
+        }
+    }
+}
+
+// the channels the bot is in, joined again after every login. Channels joined at runtime
+// while logged out are joined with the others after the login
+#[derive(Clone)]
+struct Channels {
+    state: Arc<Mutex<ChannelsState>>,
+    // wakes the task watching the joins
+    changed: Arc<Notify>,
+    send_tasks: SendQueue,
+    health: Health,
+}
+
+struct ChannelsState {
+    names: Vec<String>,
+    logged_in: bool,
+    // waiting for the join window, joined by a task so that reading goes on meanwhile
+    pending: VecDeque<String>,
+    // the joins of this login that weren't confirmed yet, and the attempt they are at
+    unconfirmed: HashMap<String, Join>,
+    watching: bool,
+    window: SlidingWindow,
+    retry: JoinRetry,
+}
+
+impl ChannelsState {
+    // how long until something is to be done, None when all joins are confirmed or given up
+    fn next_check(&mut self, now: Instant) -> Option<Duration> {
+        let window = (!self.pending.is_empty()).then(|| self.window.delay(now));
+        let attempts = self
+            .unconfirmed
+            .values()
+            .filter_map(|join| match join.next {
+                Attempt::Queued => None,
+                Attempt::Sent(time) | Attempt::Retry(time) => {
+                    Some(time.saturating_duration_since(now))
+                }
+            });
+        window.into_iter().chain(attempts).min()
+    }
+}
+
+impl Channels {
+    fn new(names: Vec<String>, send_tasks: SendQueue) -> Self {
+        let queue = send_tasks.clone();
+        let health = Health::new(&names, move || queue.depth(), Instant::now());
+        Self {
+            state: Arc::new(Mutex::new(ChannelsState {
+                names,
+                logged_in: false,
+                pending: VecDeque::new(),
+                unconfirmed: HashMap::new(),
+                watching: false,
+                window: SlidingWindow::new(JOIN_LIMIT, JOIN_WINDOW),
+                retry: JoinRetry::default(),
+            })),
+            changed: Arc::default(),
+            send_tasks,
+            health,
+        }
+    }
+
+    fn logged_in(&self) -> Result<(), ConnectorError> {
+        let mut state = self.state.lock().unwrap();
+        state.logged_in = true;
+        state.pending = state.names.iter().cloned().collect();
+        state.unconfirmed = state
+            .names
+            .iter()
+            .map(|name| (name.clone(), Join::queued(name)))
+            .collect();
+        self.join_pending(&mut state)
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.state.lock().unwrap().names.clone()
+    }
+
+    fn logged_out(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.logged_in = false;
+        state.pending.clear();
+        state.unconfirmed.clear();
+        self.health.logged_out();
+    }
+
+    fn join(&self, name: &str) -> Result<(), ConnectorError> {
+        let mut state = self.state.lock().unwrap();
+        if state.names.iter().any(|joined| joined == name) {
+            tracing::debug!(channel = name, "already joined");
+            return Ok(());
+        }
+        state.names.push(name.to_owned());
+        self.health.wanted(name);
+        if state.logged_in {
+            state.pending.push_back(name.to_owned());
+            state
+                .unconfirmed
+                .insert(name.to_owned(), Join::queued(name));
+            self.join_pending(&mut state)?;
+        }
+        Ok(())
+    }
+
+    fn part(&self, name: &str) -> Result<(), ConnectorError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.names.iter().any(|joined| joined == name) {
+            tracing::debug!(channel = name, "not in the channel");
+            return Ok(());
+        }
+        state.names.retain(|joined| joined != name);
+        self.health.parted(name);
+        state.pending.retain(|pending| pending != name);
+        state.unconfirmed.remove(name);
+        if state.logged_in {
+            self.send_tasks
+                .push(Outgoing::part(&[name])?, Priority::Control)?;
+        }
+        Ok(())
+    }
+
+    // the bot's own JOIN, or a ROOMSTATE without a user
+    fn joined(&self, user: Option<&str>, channel: &str) {
+        if !self.health.joined(user, channel) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some(join) = state.unconfirmed.remove(channel) {
+            tracing::info!(parent: &join.span, attempt = join.attempt, "joined");
+        }
+    }
+
+    // joins as many pending channels as the window allows, the rest a bit later
+    fn join_pending(&self, state: &mut ChannelsState) -> Result<(), ConnectorError> {
+        let now = Instant::now();
+        let count = state.window.available(now).min(state.pending.len());
+        if count > 0 {
+            let batch: Vec<String> = state.pending.drain(..count).collect();
+            let until = now + state.retry.timeout;
+            for channel in &batch {
+                let join = state
+                    .unconfirmed
+                    .entry(channel.clone())
+                    .or_insert_with(|| Join::queued(channel));
+                join.attempt += 1;
+                join.next = Attempt::Sent(until);
+                tracing::info!(parent: &join.span, attempt = join.attempt, "joining");
+                self.health.sent(channel, join.attempt);
+            }
+            let batch: Vec<&str> = batch.iter().map(String::as_str).collect();
+            state.window.record(now, count);
+            queue(&self.send_tasks, Outgoing::join(&batch))?;
+        }
+        if state.unconfirmed.is_empty() {
+            return Ok(());
+        }
+        match state.watching {
+            true => self.changed.notify_one(),
+            false => {
+                state.watching = true;
+                tokio::spawn(self.clone().watch_joins());
+            }
+        }
+        Ok(())
+    }
+
+    // sends the pending joins as the window allows, and those twitch didn't confirm again
+    async fn watch_joins(self) {
+        loop {
+            let delay = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                self.check_joins(&mut state, now);
+                if let Err(error) = self.join_pending(&mut state) {
+                    tracing::error!(%error, "joining channels stopped");
+                    state.pending.clear();
+                    state.unconfirmed.clear();
+                }
+                let Some(delay) = state.next_check(now) else {
+                    state.watching = false;
+                    return;
+                };
+                delay
+            };
+            // a change wakes the task early
+            let _ = tokio::time::timeout(delay, self.changed.notified()).await;
+        }
+    }
+
+    fn check_joins(&self, state: &mut ChannelsState, now: Instant) {
+        let ChannelsState {
+            pending,
+            unconfirmed,
+            retry,
+            ..
+        } = state;
+        unconfirmed.retain(|channel, join| match join.next {
+            Attempt::Sent(until) if until <= now && join.attempt >= JOIN_ATTEMPTS => {
+                tracing::warn!(
+                    parent: &join.span,
+                    attempts = join.attempt,
+                    "could not join, twitch ignored every attempt"
+                );
+                self.health.gave_up(channel, join.attempt);
+                false
+            }
+            Attempt::Sent(until) if until <= now => {
+                let delay = retry.backoff.delay(join.attempt, random_jitter());
+                tracing::warn!(
+                    parent: &join.span,
+                    attempt = join.attempt,
+                    retry_in_ms = delay.as_millis() as u64,
+                    "twitch did not confirm the join, trying again"
+                );
+                self.health.retrying(channel, join.attempt);
+                join.next = Attempt::Retry(now + delay);
+                true
+            }
+            Attempt::Retry(at) if at <= now => {
+                pending.push_back(channel.clone());
+                join.next = Attempt::Queued;
+                true
+            }
+            _ => true,
+        });
+    }
+}
+
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn queue(
+    send_tasks: &SendQueue,
+    line: Result<Outgoing, ConnectorError>,
+) -> Result<(), ConnectorError> {
+    send_tasks.push(line?, Priority::Control)
+}
+
+// lines waiting for the send task, counted so that they can be flushed before a RECONNECT
+#[derive(Clone)]
+struct SendQueue {
+    tx: mpsc::UnboundedSender<(Outgoing, Priority)>,
+    pending: Arc<Pending>,
+    // set when shutting down, no more lines are taken
+    closed: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    written: Condvar,
+}
+
+impl Pending {
+    fn done(&self) {
+        *self.count.lock().unwrap() -= 1;
+        self.written.notify_all();
+    }
+}
+
+impl SendQueue {
+    fn new() -> (Self, mpsc::UnboundedReceiver<(Outgoing, Priority)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let pending = Arc::default();
+        let closed = Arc::default();
+        (
+            Self {
+                tx,
+                pending,
+                closed,
+            },
+            rx,
+        )
+    }
+
+    fn push(&self, line: Outgoing, priority: Priority) -> Result<(), ConnectorError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(ConnectorError::ShuttingDown);
+        }
+        *self.pending.count.lock().unwrap() += 1;
+        self.tx
+            .send((line, priority))
+            .map_err(|mpsc::error::SendError((line, _))| {
+                self.pending.done();
+                mpsc::error::SendError(line).into()
+            })
+    }
+
+    fn depth(&self) -> usize {
+        *self.pending.count.lock().unwrap()
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    // false when lines are still waiting after the timeout
+    fn flush(&self, timeout: Duration) -> bool {
+        let count = self.pending.count.lock().unwrap();
+        let (_count, result) = self
+            .pending
+            .written
+            .wait_timeout_while(count, timeout, |count| *count > 0)
+            .unwrap();
+        !result.timed_out()
+    }
 }
 
-struct SendThread {
-    _handle: JoinHandle<()>,
-    tx: SyncSender<SendTask>,
+struct SendTask {
+    _handle: tokio::task::JoinHandle<()>,
+    queue: SendQueue,
+    counters: SendCounters,
 }
 
-const SEND_CHAN_CAPACITY: usize = 10;
+const RESEND_DELAY: Duration = Duration::from_secs(1);
+
+// how the send task keeps within twitch's limits
+struct SendSettings {
+    moderated: ModeratedChannels,
+    // the duplicate handling and the ttl of low priority chat messages, which may be reloaded
+    config: SharedConfig,
+}
 
-fn send_thread(mut sender: Writer<TcpStream>) -> SendThread {
-    let (tx, rx) = mpsc::sync_channel(SEND_CHAN_CAPACITY);
-    let handle = thread::spawn(move || {
-        while let Ok(task) = rx.recv() {
-            if let Err(error) = send(&mut sender, task) {
-                println!("writer thread stopped with error {:?}", error);
-                break;
+// a line that could not be sent is retried until a reconnect replaced the broken writer,
+// so queued messages survive the reconnect. Chat messages over twitch's rate limit wait
+// in order of their priority, while other lines like JOIN are sent right away.
+// The writer thread writes the lines, the task only awaits them
+fn send_task<W>(
+    writer: ConnectionWriter<W>,
+    resend_delay: Duration,
+    settings: SendSettings,
+) -> SendTask
+where
+    W: Send + 'static,
+{
+    let (queue, mut rx) = SendQueue::new();
+    let pending = queue.pending.clone();
+    let counters = SendCounters::default();
+    let sent = counters.clone();
+    let handle = tokio::spawn(async move {
+        let mut limiter = MessageLimiter::new(settings.moderated.clone(), Instant::now());
+        let config = settings.config.load();
+        let mut duplicate_guard = DuplicateGuard::new(config.chat.duplicates, settings.moderated);
+        let mut waiting = PriorityQueue::new(config.message_ttl(), sent.clone());
+        loop {
+            let config = settings.config.load();
+            duplicate_guard.set_handling(config.chat.duplicates);
+            waiting.set_ttl(config.message_ttl());
+            for _ in 0..waiting.expire(Instant::now()) {
+                pending.done();
+            }
+            let next = waiting
+                .front()
+                .and_then(|line| Some((line, line.chat_channel()?)));
+            let received = match next {
+                Some((line, channel)) => {
+                    let now = Instant::now();
+                    let delay = limiter
+                        .delay(channel, now)
+                        .max(duplicate_guard.delay(line, now));
+                    if delay.is_zero() {
+                        limiter.record(now);
+                        let (line, priority) = waiting.pop().unwrap();
+                        let line = duplicate_guard.prepare(line, now);
+                        write(&writer, line, resend_delay).await;
+                        sent.sent(priority);
+                        pending.done();
+                        continue;
+                    }
+                    match tokio::time::timeout(delay, rx.recv()).await {
+                        Ok(received) => received,
+                        Err(_) => continue,
+                    }
+                }
+                None => rx.recv().await,
+            };
+            match received {
+                Some((line, priority)) if line.chat_channel().is_some() => {
+                    let now = Instant::now();
+                    let delay = line
+                        .chat_channel()
+                        .map_or(Duration::ZERO, |channel| limiter.delay(channel, now));
+                    if !delay.is_zero() {
+                        tracing::debug!(
+                            channel = line.chat_channel(),
+                            ?priority,
+                            delay_ms = delay.as_millis() as u64,
+                            "held back by the rate limit"
+                        );
+                    }
+                    waiting.push(line, priority, now)
+                }
+                Some((line, priority)) => {
+                    write(&writer, line, resend_delay).await;
+                    sent.sent(priority);
+                    pending.done();
+                }
+                None => break,
             }
         }
     });
-    SendThread {
+    SendTask {
         _handle: handle,
-        tx,
+        queue,
+        counters,
+    }
+}
+
+async fn write<W>(writer: &ConnectionWriter<W>, line: Outgoing, resend_delay: Duration) {
+    while let Err(error) = writer.write(line.clone()).await {
+        tracing::warn!(%error, "sending failed, retrying");
+        tokio::time::sleep(resend_delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        connection::EventHandler, health::HealthLimits, rate_limit::MESSAGE_LIMIT,
+        split::MAX_MESSAGE_CHARS,
+    };
+    use super::*;
+    use crate::{config::Config, connect::TextMessage};
+    use std::{cell::RefCell, error::Error, ops::ControlFlow, rc::Rc, sync::mpsc};
+
+    impl EventSink for mpsc::Sender<ChatBotEvent> {
+        fn deliver(&self, event: ChatBotEvent) -> bool {
+            self.send(event).is_ok()
+        }
+    }
+
+    // what was queued so far, without waiting for more
+    fn drain(
+        task_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(Outgoing, Priority)>,
+    ) -> Vec<(Outgoing, Priority)> {
+        let mut tasks = Vec::new();
+        while let Ok(task) = task_rx.try_recv() {
+            tasks.push(task);
+        }
+        tasks
+    }
+
+    impl Default for SendSettings {
+        fn default() -> Self {
+            Self {
+                moderated: ModeratedChannels::default(),
+                config: SharedConfig::new(Config::default()),
+            }
+        }
+    }
+
+    struct MockReceiver(VecDeque<Vec<ReceiveEvent>>);
+
+    impl EventReceiver for MockReceiver {
+        fn receive_events(&mut self) -> Result<Vec<ReceiveEvent>, ConnectorError> {
+            self.0
+                .pop_front()
+                .ok_or_else(|| ConnectorError::MessageReceiveFailed("connection closed".to_owned()))
+        }
+    }
+
+    // fails the first writes, then records the lines
+    #[derive(Clone, Default)]
+    struct MockWriter {
+        written: Arc<Mutex<Vec<String>>>,
+        failures: usize,
+        closed: Arc<Mutex<bool>>,
+    }
+
+    impl MockWriter {
+        fn written(&self) -> Vec<String> {
+            self.written.lock().unwrap().clone()
+        }
+    }
+
+    impl LineWriter for MockWriter {
+        fn write_line(&mut self, line: Outgoing) -> Result<(), ConnectorError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(ConnectorError::MessageSendFailed("broken pipe".to_owned()));
+            }
+            self.written.lock().unwrap().push(line.to_string());
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            *self.closed.lock().unwrap() = true;
+        }
+    }
+
+    // parses the raw lines like the connection to twitch would
+    struct MockTransport(VecDeque<Vec<&'static str>>);
+
+    impl EventReceiver for MockTransport {
+        fn receive_events(&mut self) -> Result<Vec<ReceiveEvent>, ConnectorError> {
+            let lines = self.0.pop_front().ok_or_else(|| {
+                ConnectorError::MessageReceiveFailed("connection closed".to_owned())
+            })?;
+            Ok(lines
+                .into_iter()
+                .filter_map(|line| ReceiveEvent::parse_from_message(line).ok())
+                .collect())
+        }
+    }
+
+    // remembers what was written to the control connection before each read
+    struct ObservedReceiver {
+        events: MockReceiver,
+        control: MockWriter,
+        observed: Rc<RefCell<Vec<Vec<String>>>>,
+    }
+
+    impl EventReceiver for ObservedReceiver {
+        fn receive_events(&mut self) -> Result<Vec<ReceiveEvent>, ConnectorError> {
+            self.observed.borrow_mut().push(self.control.written());
+            self.events.receive_events()
+        }
+    }
+
+    fn supervisor<F>(reconnect: F, max_attempts: u32) -> Supervisor<F, fn(Duration)> {
+        Supervisor {
+            reconnect,
+            sleep: |_| {},
+            backoff: Backoff::default(),
+            jitter: || 0.0,
+            max_attempts,
+            renew_login: None,
+        }
+    }
+
+    fn session(forward_pings: bool, send_tasks: &SendQueue) -> Session {
+        Session {
+            channels: Channels::new(vec!["captaincallback".to_owned()], send_tasks.clone()),
+            forward_pings,
+            capabilities: Capabilities::default(),
+            moderated: ModeratedChannels::default(),
+        }
+    }
+
+    // the connection can't be restored, the loop ends once the mock connection is closed
+    fn no_reconnect(
+    ) -> Supervisor<impl FnMut() -> Result<MockReceiver, ConnectorError>, fn(Duration)> {
+        supervisor(|| panic!("no reconnect expected"), 0)
+    }
+
+    fn ping(server: &str) -> ReceiveEvent {
+        ReceiveEvent::ConnectorEvent(ConnectorEvent::Ping {
+            server: server.to_owned(),
+        })
+    }
+
+    fn join(user: &str) -> ChatBotEvent {
+        ChatBotEvent::Join {
+            user: user.to_owned(),
+            channel: "captaincallback".to_owned(),
+        }
+    }
+
+    fn state(state: ConnectionState) -> ChatBotEvent {
+        ChatBotEvent::Connection(state)
+    }
+
+    #[test]
+    fn long_whitespace_sends_no_line() {
+        let blank = " ".repeat(MAX_MESSAGE_CHARS + 1);
+        for overflow in [Overflow::Split, Overflow::Truncate] {
+            assert!(message_lines("#carkhy", &blank, overflow)
+                .unwrap()
+                .is_empty());
+        }
+        assert_eq!(
+            message_lines("#carkhy", " ", Overflow::Truncate)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn health_follows_the_login_and_the_reconnect() {
+        let limits = HealthLimits {
+            stale_after: Duration::from_secs(300),
+            max_queue: 20,
+        };
+        let connection = MockReceiver(VecDeque::from(vec![vec![
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Welcome {
+                login: "botname".to_owned(),
+            }),
+            ReceiveEvent::ChatBotEvent(join("botname")),
+        ]]));
+        let (event_tx, _event_rx) = mpsc::channel();
+        let (task_tx, _task_rx) = SendQueue::new();
+        let session = session(false, &task_tx);
+        let health = session.channels.health.clone();
+        assert_eq!(
+            health.ready(Instant::now(), &limits).to_json()["checks"]["authenticated"],
+            "not logged in yet"
+        );
+        let mut while_reconnecting = None;
+        receive_loop(
+            connection,
+            &session,
+            MockWriter::default(),
+            supervisor(
+                || {
+                    while_reconnecting = Some(health.ready(Instant::now(), &limits).to_json());
+                    Err::<MockReceiver, _>(ConnectorError::ExternalServerError(
+                        "offline".to_owned(),
+                    ))
+                },
+                1,
+            ),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        let checks = &while_reconnecting.unwrap()["checks"];
+        assert_eq!(checks["authenticated"], "reconnecting to chat");
+        assert_eq!(checks["channels"], "not joined: captaincallback");
+    }
+
+    #[test]
+    fn reconnect_replaces_the_connection() {
+        let old_connection = MockReceiver(VecDeque::from(vec![vec![
+            ReceiveEvent::ChatBotEvent(join("carkhy")),
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Reconnect),
+            ReceiveEvent::ChatBotEvent(join("ignored")),
+        ]]));
+        let mut new_connections = vec![MockReceiver(VecDeque::from(vec![vec![
+            ReceiveEvent::ChatBotEvent(join("captaincallback")),
+        ]]))];
+        let mut reconnects = 0;
+        let (event_tx, event_rx) = mpsc::channel();
+        let (task_tx, _task_rx) = SendQueue::new();
+        receive_loop(
+            old_connection,
+            &session(false, &task_tx),
+            MockWriter::default(),
+            supervisor(
+                || {
+                    reconnects += 1;
+                    new_connections
+                        .pop()
+                        .ok_or_else(|| ConnectorError::ExternalServerError("offline".to_owned()))
+                },
+                0,
+            ),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        assert_eq!(reconnects, 1);
+        let events: Vec<ChatBotEvent> = event_rx.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                join("carkhy"),
+                join("captaincallback"),
+                state(ConnectionState::Disconnected)
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconnect_requested_by_twitch_logs_in_again() {
+        const WELCOME: &str = ":tmi.twitch.tv 001 botname :Welcome, GLHF!";
+        const HELLO: &str =
+            "@id=1 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Hello";
+        let old_writer = MockWriter::default();
+        let new_writer = MockWriter::default();
+        let shared = ConnectionWriter::spawn(old_writer.clone());
+        let send_task = send_task(
+            shared.clone(),
+            Duration::from_millis(1),
+            SendSettings::default(),
+        );
+        let login = Login {
+            credentials: Credentials::Token {
+                access_token: "token".to_owned(),
+                user_name: "botname".to_owned(),
+            },
+            channels: vec!["captaincallback".to_owned()],
+            capabilities: vec!["twitch.tv/tags".to_owned()],
+            transport: ChatTransport::WebSocket,
+            security: ConnectionSecurity::Tls,
+            verify_certificates: true,
+            server: None,
+        };
+        let mut new_connection = Some((
+            // the message sent before the switch arrives on the new connection again
+            MockTransport(VecDeque::from(vec![
+                vec![WELCOME, HELLO],
+                vec!["@id=2 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Welcome back"],
+            ])),
+            new_writer.clone(),
+        ));
+        let (event_tx, event_rx) = mpsc::channel();
+        receive_loop(
+            MockTransport(VecDeque::from(vec![vec![
+                WELCOME,
+                HELLO,
+                ":tmi.twitch.tv RECONNECT",
+            ]])),
+            &session(false, &send_task.queue),
+            shared.clone(),
+            supervisor(
+                || {
+                    let (transport, mut writer) = new_connection
+                        .take()
+                        .ok_or_else(|| ConnectorError::ExternalServerError("offline".to_owned()))?;
+                    log_in(&mut writer, &login)?;
+                    switch_connection(&shared, (transport, writer))
+                },
+                0,
+            ),
+            event_tx,
+            send_task.queue.clone(),
+        )
+        .unwrap();
+        assert!(send_task.queue.flush(Duration::from_secs(5)));
+        assert_eq!(old_writer.written(), vec!["JOIN #captaincallback\r\n"]);
+        assert!(*old_writer.closed.lock().unwrap());
+        assert_eq!(
+            new_writer.written(),
+            vec![
+                "PASS oauth:token\r\n",
+                "NICK botname\r\n",
+                "CAP REQ :twitch.tv/tags\r\n",
+                "JOIN #captaincallback\r\n"
+            ]
+        );
+        let texts: Vec<String> = event_rx
+            .try_iter()
+            .filter_map(|event| match event {
+                ChatBotEvent::TextMessage(message) => Some(message.text),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, vec!["Hello", "Welcome back"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn chat_messages_over_the_limit_wait_behind_other_lines() {
+        let writer = MockWriter::default();
+        let send_task = send_task(
+            ConnectionWriter::spawn(writer.clone()),
+            Duration::from_millis(1),
+            SendSettings::default(),
+        );
+        for i in 0..=MESSAGE_LIMIT {
+            let line = Outgoing::privmsg("captaincallback", &i.to_string()).unwrap();
+            send_task.queue.push(line, Priority::Response).unwrap();
+        }
+        send_task
+            .queue
+            .push(Outgoing::join(&["carkhy"]).unwrap(), Priority::Control)
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while writer.written().len() <= MESSAGE_LIMIT as usize && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let written = writer.written();
+        assert_eq!(written.len(), MESSAGE_LIMIT as usize + 1);
+        assert_eq!(written.last().unwrap(), "JOIN #carkhy\r\n");
+        assert_eq!(send_task.queue.depth(), 1);
+        // the 21st message follows once a token refilled
+        assert!(send_task.queue.flush(Duration::from_secs(5)));
+        assert_eq!(
+            writer.written().last().unwrap(),
+            "PRIVMSG #captaincallback :20\r\n"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_gives_up_on_lines_held_back_at_the_deadline() {
+        let writer = MockWriter::default();
+        let send_task = send_task(
+            ConnectionWriter::spawn(writer.clone()),
+            Duration::from_millis(1),
+            SendSettings::default(),
+        );
+        for i in 0..MESSAGE_LIMIT + 5 {
+            let line = Outgoing::privmsg("captaincallback", &i.to_string()).unwrap();
+            send_task.queue.push(line, Priority::Response).unwrap();
+        }
+        let goodbye = Outgoing::privmsg("captaincallback", "Bye!").unwrap();
+        let start = Instant::now();
+        shut_down(
+            &send_task.queue,
+            &mut writer.clone(),
+            vec![goodbye],
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+        let written = writer.written();
+        assert_eq!(written.len(), MESSAGE_LIMIT as usize + 1);
+        assert_eq!(written.last().unwrap(), "QUIT\r\n");
+        assert!(*writer.closed.lock().unwrap());
+        // the goodbye and five messages were still waiting
+        assert_eq!(send_task.queue.depth(), 6);
+        let late = Outgoing::privmsg("captaincallback", "too late").unwrap();
+        assert!(matches!(
+            send_task.queue.push(late, Priority::Response),
+            Err(ConnectorError::ShuttingDown)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn joining_and_parting_twice_sends_once() {
+        let (task_tx, mut task_rx) = SendQueue::new();
+        let channels = Channels::new(vec!["captaincallback".to_owned()], task_tx);
+        channels.logged_in().unwrap();
+        channels.join("carkhy").unwrap();
+        channels.join("carkhy").unwrap();
+        channels.join("captaincallback").unwrap();
+        channels.part("carkhy").unwrap();
+        channels.part("carkhy").unwrap();
+        let tasks: Vec<String> = drain(&mut task_rx)
+            .into_iter()
+            .map(|(line, _)| line.to_string())
+            .collect();
+        assert_eq!(
+            tasks,
+            vec![
+                "JOIN #captaincallback\r\n",
+                "JOIN #carkhy\r\n",
+                "PART #carkhy\r\n"
+            ]
+        );
+    }
+
+    // quick to retry, and a window that fits a test
+    fn impatient(channels: &Channels) {
+        let mut state = channels.state.lock().unwrap();
+        state.window = SlidingWindow::new(JOIN_LIMIT, Duration::from_millis(300));
+        state.retry = JoinRetry {
+            timeout: Duration::from_millis(50),
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(20)),
+        };
+    }
+
+    // the channels of the JOIN lines sent so far, and of each line
+    fn joins(
+        task_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(Outgoing, Priority)>,
+    ) -> Vec<Vec<String>> {
+        drain(task_rx)
+            .into_iter()
+            .filter_map(|(line, _)| {
+                let line = line.to_string();
+                let channels = line.strip_prefix("JOIN ")?.trim_end();
+                Some(
+                    channels
+                        .split(',')
+                        .map(|channel| channel[1..].to_owned())
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    // answers the joins like a server that drops every third, until all are confirmed. The
+    // lines sent and how often each channel was joined
+    fn drop_every_third(
+        channels: &Channels,
+        task_rx: &mut tokio::sync::mpsc::UnboundedReceiver<(Outgoing, Priority)>,
+    ) -> (Vec<Vec<String>>, HashMap<String, u32>) {
+        let limits = HealthLimits {
+            stale_after: Duration::from_secs(300),
+            max_queue: 50,
+        };
+        let mut sent: HashMap<String, u32> = HashMap::new();
+        let mut lines = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !channels.health.ready(Instant::now(), &limits).passed() {
+            assert!(Instant::now() < deadline, "{:?}", sent);
+            for line in joins(task_rx) {
+                for channel in &line {
+                    *sent.entry(channel.clone()).or_default() += 1;
+                    if sent.values().sum::<u32>() % 3 != 0 {
+                        channels.joined(Some("botname"), channel);
+                    }
+                }
+                lines.push(line);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        (lines, sent)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn joins_twitch_drops_are_sent_again() {
+        let names: Vec<String> = (0..25).map(|n| format!("channel{}", n)).collect();
+        let (task_tx, mut task_rx) = SendQueue::new();
+        let channels = Channels::new(names.clone(), task_tx);
+        impatient(&channels);
+        channels.health.logged_in("botname");
+        channels.logged_in().unwrap();
+        let (lines, sent) = drop_every_third(&channels, &mut task_rx);
+        // the first 20 at once, the rest after the window, with the first retries
+        assert_eq!(lines[0], names[..20]);
+        assert!(lines[1].starts_with(&names[20..]), "{:?}", lines);
+        assert!(lines.iter().all(|line| line.len() <= JOIN_LIMIT));
+        let retried = sent.values().filter(|&&count| count > 1).count();
+        assert!(retried >= 8, "{:?}", sent);
+        assert_eq!(sent.len(), 25);
+        assert!(channels.state.lock().unwrap().unconfirmed.is_empty());
+        // a reconnect joins all of them again the same way
+        channels.logged_out();
+        channels.health.logged_in("botname");
+        channels.logged_in().unwrap();
+        let (lines, sent) = drop_every_third(&channels, &mut task_rx);
+        assert_eq!(sent.len(), 25);
+        assert!(sent.values().any(|&count| count > 1));
+        assert!(lines.iter().all(|line| line.len() <= JOIN_LIMIT));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn joins_are_given_up_after_some_attempts() {
+        let (task_tx, mut task_rx) = SendQueue::new();
+        let channels = Channels::new(vec!["suspended".to_owned()], task_tx);
+        impatient(&channels);
+        channels.health.logged_in("botname");
+        channels.logged_in().unwrap();
+        let limits = HealthLimits {
+            stale_after: Duration::from_secs(300),
+            max_queue: 50,
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !channels.state.lock().unwrap().unconfirmed.is_empty() {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(joins(&mut task_rx).len(), JOIN_ATTEMPTS as usize);
+        assert_eq!(
+            channels.health.ready(Instant::now(), &limits).to_json()["checks"]["channels"],
+            "not joined: suspended (gave up after 5 attempts)"
+        );
+        // somebody else joining confirms nothing, the ROOMSTATE does
+        channels.joined(Some("carkhy"), "suspended");
+        assert!(!channels.health.ready(Instant::now(), &limits).passed());
+        channels.joined(None, "suspended");
+        assert!(channels.health.ready(Instant::now(), &limits).passed());
+    }
+
+    #[test]
+    fn every_attempt_of_a_join_is_logged_in_its_span() {
+        use crate::logging::testing::{capture, span};
+        let (task_tx, _task_rx) = SendQueue::new();
+        let channels = Channels::new(vec!["carkhy".to_owned()], task_tx);
+        // the test checks the joins itself, on the thread the log is captured on
+        channels.state.lock().unwrap().watching = true;
+        channels.health.logged_in("botname");
+        let lines = capture(|| {
+            channels.logged_in().unwrap();
+            let later = Instant::now() + Duration::from_secs(60);
+            let mut state = channels.state.lock().unwrap();
+            channels.check_joins(&mut state, later);
+            channels.check_joins(&mut state, later + Duration::from_secs(60));
+            channels.join_pending(&mut state).unwrap();
+            drop(state);
+            channels.joined(None, "carkhy");
+        });
+        let messages: Vec<_> = lines.iter().map(|line| &line["message"]).collect();
+        assert_eq!(
+            messages,
+            [
+                "joining",
+                "twitch did not confirm the join, trying again",
+                "joining",
+                "joined"
+            ]
+        );
+        assert!(lines
+            .iter()
+            .all(|line| span(line, "join").unwrap()["channel"] == "carkhy"));
+        assert_eq!(lines[3]["attempt"], 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn channels_changed_while_reconnecting_are_joined_after_the_login() {
+        const WELCOME: &str = ":tmi.twitch.tv 001 botname :Welcome, GLHF!";
+        let old_writer = MockWriter::default();
+        let new_writer = MockWriter::default();
+        let shared = ConnectionWriter::spawn(old_writer.clone());
+        let send_task = send_task(
+            shared.clone(),
+            Duration::from_millis(1),
+            SendSettings::default(),
+        );
+        let session = Session {
+            channels: Channels::new(
+                vec!["captaincallback".to_owned(), "oldchannel".to_owned()],
+                send_task.queue.clone(),
+            ),
+            forward_pings: false,
+            capabilities: Capabilities::default(),
+            moderated: ModeratedChannels::default(),
+        };
+        let channels = session.channels.clone();
+        let mut new_connection = Some((
+            MockTransport(VecDeque::from(vec![vec![WELCOME]])),
+            new_writer.clone(),
+        ));
+        let (event_tx, _event_rx) = mpsc::channel();
+        receive_loop(
+            MockTransport(VecDeque::from(vec![vec![
+                WELCOME,
+                ":tmi.twitch.tv RECONNECT",
+            ]])),
+            &session,
+            shared.clone(),
+            supervisor(
+                || {
+                    // nothing is sent for these until twitch confirmed the new login
+                    channels.join("carkhy")?;
+                    channels.join("carkhy")?;
+                    channels.part("oldchannel")?;
+                    let connection = new_connection
+                        .take()
+                        .ok_or_else(|| ConnectorError::ExternalServerError("offline".to_owned()))?;
+                    switch_connection(&shared, connection)
+                },
+                0,
+            ),
+            event_tx,
+            send_task.queue.clone(),
+        )
+        .unwrap();
+        // the connection is lost for good, this waits for the next login
+        channels.join("latecomer").unwrap();
+        assert!(send_task.queue.flush(Duration::from_secs(5)));
+        assert_eq!(
+            old_writer.written(),
+            vec!["JOIN #captaincallback,#oldchannel\r\n"]
+        );
+        assert_eq!(
+            new_writer.written(),
+            vec!["JOIN #captaincallback,#carkhy\r\n"]
+        );
+    }
+
+    #[test]
+    fn messages_are_only_forwarded_once() {
+        let message = |id: &str| {
+            ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(TextMessage {
+                text: "Hello".to_owned(),
+                message_id: Some(id.to_owned()),
+                ..Default::default()
+            }))
+        };
+        let connection = MockReceiver(VecDeque::from(vec![vec![
+            message("1"),
+            message("2"),
+            message("1"),
+            ReceiveEvent::ChatBotEvent(join("carkhy")),
+            ReceiveEvent::ChatBotEvent(join("carkhy")),
+        ]]));
+        let (event_tx, event_rx) = mpsc::channel();
+        let (task_tx, _task_rx) = SendQueue::new();
+        receive_loop(
+            connection,
+            &session(false, &task_tx),
+            MockWriter::default(),
+            no_reconnect(),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        // both messages, both joins and the disconnect
+        assert_eq!(event_rx.try_iter().count(), 5);
+    }
+
+    #[test]
+    fn lost_connection_is_restored_with_backoff() {
+        let sleeps = Rc::new(RefCell::new(Vec::new()));
+        let mut attempts = 0;
+        let (event_tx, event_rx) = mpsc::channel();
+        let (task_tx, _task_rx) = SendQueue::new();
+        receive_loop(
+            MockReceiver(VecDeque::new()),
+            &session(false, &task_tx),
+            MockWriter::default(),
+            Supervisor {
+                // the 3 attempts after the first loss fail, the restored connection is
+                // lost again and can't be restored anymore
+                reconnect: || {
+                    attempts += 1;
+                    match attempts {
+                        4 => Ok(MockReceiver(VecDeque::from(vec![vec![
+                            ReceiveEvent::ChatBotEvent(join("carkhy")),
+                        ]]))),
+                        _ => Err(ConnectorError::ExternalServerError("offline".to_owned())),
+                    }
+                },
+                sleep: |delay: Duration| sleeps.borrow_mut().push(delay.as_secs()),
+                backoff: Backoff::default(),
+                jitter: || 0.0,
+                max_attempts: 4,
+                renew_login: None,
+            },
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        assert_eq!(*sleeps.borrow(), vec![1, 2, 4, 8, 1, 2, 4, 8]);
+        let reconnecting = |attempt| state(ConnectionState::Reconnecting { attempt });
+        let events: Vec<ChatBotEvent> = event_rx.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                reconnecting(1),
+                reconnecting(2),
+                reconnecting(3),
+                reconnecting(4),
+                state(ConnectionState::Connected),
+                join("carkhy"),
+                reconnecting(1),
+                reconnecting(2),
+                reconnecting(3),
+                reconnecting(4),
+                state(ConnectionState::Disconnected),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn queued_lines_are_kept_until_they_are_sent() {
+        let writer = MockWriter {
+            failures: 2,
+            ..Default::default()
+        };
+        let send_task = send_task(
+            ConnectionWriter::spawn(writer.clone()),
+            Duration::from_millis(1),
+            SendSettings::default(),
+        );
+        let queue = send_task.queue;
+        queue
+            .push(
+                Outgoing::privmsg("channel", "first").unwrap(),
+                Priority::Response,
+            )
+            .unwrap();
+        queue
+            .push(
+                Outgoing::privmsg("channel", "second").unwrap(),
+                Priority::Response,
+            )
+            .unwrap();
+        assert!(queue.flush(Duration::from_secs(5)));
+        drop(queue);
+        send_task._handle.await.unwrap();
+        assert_eq!(
+            writer.written(),
+            vec![
+                "PRIVMSG #channel :first\r\n",
+                "PRIVMSG #channel :second\r\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn ping_is_answered_with_pong_before_the_next_read() {
+        let control = MockWriter::default();
+        let observed = Rc::new(RefCell::new(Vec::new()));
+        let connection = ObservedReceiver {
+            events: MockReceiver(VecDeque::from(vec![
+                vec![ping("tmi.twitch.tv")],
+                vec![ping("irc.example.com"), ping("")],
+            ])),
+            control: control.clone(),
+            observed: observed.clone(),
+        };
+        let (event_tx, event_rx) = mpsc::channel();
+        let (task_tx, mut task_rx) = SendQueue::new();
+        receive_loop(
+            connection,
+            &session(false, &task_tx),
+            control,
+            supervisor(
+                || -> Result<ObservedReceiver, _> { panic!("no reconnect expected") },
+                0,
+            ),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        assert_eq!(
+            *observed.borrow(),
+            vec![
+                vec![],
+                vec!["PONG :tmi.twitch.tv\r\n"],
+                vec![
+                    "PONG :tmi.twitch.tv\r\n",
+                    "PONG :irc.example.com\r\n",
+                    "PONG :tmi.twitch.tv\r\n"
+                ]
+            ]
+        );
+        // the pong bypasses the send queue and the chat bot never sees the ping
+        assert!(drain(&mut task_rx).is_empty());
+        let events: Vec<ChatBotEvent> = event_rx.try_iter().collect();
+        assert_eq!(events, vec![state(ConnectionState::Disconnected)]);
+    }
+
+    #[test]
+    fn ping_is_forwarded_when_opted_in() {
+        let control = MockWriter::default();
+        let connection = MockReceiver(VecDeque::from(vec![vec![ping("tmi.twitch.tv")]]));
+        let (event_tx, event_rx) = mpsc::channel();
+        let (task_tx, _task_rx) = SendQueue::new();
+        receive_loop(
+            connection,
+            &session(true, &task_tx),
+            control.clone(),
+            no_reconnect(),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        assert_eq!(control.written(), vec!["PONG :tmi.twitch.tv\r\n"]);
+        let events: Vec<ChatBotEvent> = event_rx.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                ChatBotEvent::Ping {
+                    server: "tmi.twitch.tv".to_owned()
+                },
+                state(ConnectionState::Disconnected)
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn channel_is_joined_after_welcome() {
+        let connection = MockReceiver(VecDeque::from(vec![vec![
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Numeric {
+                code: 372,
+                params: vec!["botname".to_owned()],
+            }),
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Welcome {
+                login: "botname".to_owned(),
+            }),
+        ]]));
+        let (event_tx, _event_rx) = mpsc::channel();
+        let (task_tx, mut task_rx) = SendQueue::new();
+        receive_loop(
+            connection,
+            &session(false, &task_tx),
+            MockWriter::default(),
+            no_reconnect(),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        let tasks: Vec<String> = drain(&mut task_rx)
+            .into_iter()
+            .map(|(line, _)| line.to_string())
+            .collect();
+        assert_eq!(tasks, vec!["JOIN #captaincallback\r\n"]);
+    }
+
+    #[test]
+    fn anonymous_login_sends_no_password() {
+        let mut writer = MockWriter::default();
+        let login = Login {
+            credentials: Credentials::Anonymous {
+                nick: "justinfan4711".to_owned(),
+            },
+            channels: vec!["captaincallback".to_owned()],
+            capabilities: vec!["twitch.tv/tags".to_owned()],
+            transport: ChatTransport::WebSocket,
+            security: ConnectionSecurity::Tls,
+            verify_certificates: true,
+            server: None,
+        };
+        log_in(&mut writer, &login).unwrap();
+        assert_eq!(
+            writer.written(),
+            vec!["NICK justinfan4711\r\n", "CAP REQ :twitch.tv/tags\r\n"]
+        );
+        let nick = anonymous_nick();
+        let digits = nick.strip_prefix("justinfan").unwrap();
+        assert!(!digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()));
+    }
+
+    #[test]
+    fn the_login_is_logged_without_the_token() {
+        use crate::logging::testing::{capture, span};
+        let login = Login {
+            credentials: Credentials::Token {
+                access_token: "s3cr3tt0k3n".to_owned(),
+                user_name: "botanist".to_owned(),
+            },
+            channels: vec!["captaincallback".to_owned()],
+            capabilities: vec!["twitch.tv/tags".to_owned()],
+            transport: ChatTransport::WebSocket,
+            security: ConnectionSecurity::Tls,
+            verify_certificates: true,
+            server: None,
+        };
+        let mut writer = MockWriter::default();
+        let lines = capture(|| log_in(&mut writer, &login).unwrap());
+        assert!(writer.written()[0].contains("s3cr3tt0k3n"));
+        assert_eq!(lines.len(), 1);
+        let span = span(&lines[0], "login").unwrap();
+        assert_eq!(span["user"], "botanist");
+        assert_eq!(span["token"], "[redacted]");
+        assert_eq!(lines[0]["capabilities"], "twitch.tv/tags");
+        assert!(!lines[0].to_string().contains("s3cr3tt0k3n"));
+    }
+
+    #[test]
+    fn anonymous_connection_refuses_to_send() {
+        let error = check_writable(true, "messages").unwrap_err();
+        assert!(matches!(
+            error,
+            ConnectorError::ReadOnlyConnection("messages")
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Anonymous connections are read-only, log in to send messages"
+        );
+        assert!(check_writable(false, "messages").is_ok());
+    }
+
+    #[test]
+    fn rejected_login_is_not_retried() {
+        for notice in [
+            ":tmi.twitch.tv NOTICE * :Login authentication failed",
+            ":tmi.twitch.tv NOTICE * :Improperly formatted auth",
+        ] {
+            let mut attempts = 0;
+            let (event_tx, event_rx) = mpsc::channel();
+            let (task_tx, _task_rx) = SendQueue::new();
+            let result = receive_loop(
+                MockTransport(VecDeque::from(vec![vec![notice]])),
+                &session(false, &task_tx),
+                MockWriter::default(),
+                supervisor(
+                    || {
+                        attempts += 1;
+                        Err(ConnectorError::ExternalServerError("offline".to_owned()))
+                    },
+                    3,
+                ),
+                event_tx,
+                task_tx,
+            );
+            assert!(
+                matches!(result, Err(ConnectorError::AuthenticationFailed(ref reason)) if notice.ends_with(reason.as_str())),
+                "{:?}",
+                result
+            );
+            assert_eq!(attempts, 0);
+            assert_eq!(
+                event_rx.try_iter().collect::<Vec<_>>(),
+                vec![state(ConnectionState::Disconnected)]
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejected_login_is_retried_once_with_a_renewed_token() {
+        const REJECTED: &str = ":tmi.twitch.tv NOTICE * :Login authentication failed";
+        const WELCOME: &str = ":tmi.twitch.tv 001 botname :Welcome, GLHF!";
+        for (second_login, expected_renewals) in [(WELCOME, 1), (REJECTED, 1)] {
+            let renewals = Arc::new(Mutex::new(0));
+            let counted = renewals.clone();
+            let (event_tx, _event_rx) = mpsc::channel();
+            let (task_tx, _task_rx) = SendQueue::new();
+            let mut supervisor = supervisor(
+                move || Ok(MockTransport(VecDeque::from(vec![vec![second_login]]))),
+                0,
+            );
+            supervisor.renew_login = Some(Box::new(move || {
+                *counted.lock().unwrap() += 1;
+                Ok(())
+            }));
+            let result = receive_loop(
+                MockTransport(VecDeque::from(vec![vec![REJECTED]])),
+                &session(false, &task_tx),
+                MockWriter::default(),
+                supervisor,
+                event_tx,
+                task_tx,
+            );
+            assert_eq!(*renewals.lock().unwrap(), expected_renewals);
+            assert_eq!(result.is_ok(), second_login == WELCOME, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn granted_capabilities_are_remembered() {
+        let (event_tx, _event_rx) = mpsc::channel();
+        let (task_tx, _task_rx) = SendQueue::new();
+        let session = session(false, &task_tx);
+        receive_loop(
+            MockTransport(VecDeque::from(vec![vec![
+                ":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands",
+                ":tmi.twitch.tv CAP * NAK :twitch.tv/commands",
+                ":tmi.twitch.tv CAP * ACK :twitch.tv/membership",
+            ]])),
+            &session,
+            MockWriter::default(),
+            supervisor(|| panic!("no reconnect expected"), 0),
+            event_tx,
+            task_tx,
+        )
+        .unwrap();
+        assert!(session.capabilities.contains("twitch.tv/tags"));
+        assert!(session.capabilities.contains("twitch.tv/membership"));
+        assert!(!session.capabilities.contains("twitch.tv/commands"));
+    }
+
+    // answers the first command and stops
+    struct Echo;
+
+    impl EventHandler for Echo {
+        async fn handle<C: Connection>(
+            &mut self,
+            event: ChatBotEvent,
+            chat: &C,
+        ) -> Result<ControlFlow<()>, Box<dyn Error>> {
+            match event {
+                ChatBotEvent::Command(command) => {
+                    chat.send_message(
+                        &command.message.channel,
+                        "Hello back",
+                        Overflow::Split,
+                        Priority::Response,
+                    )?;
+                    Ok(ControlFlow::Break(()))
+                }
+                _ => Ok(ControlFlow::Continue(())),
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn handler_answers_through_the_connection() {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let mut next_line = || lines.next().unwrap().unwrap();
+            assert_eq!(next_line(), "PASS oauth:token");
+            assert_eq!(next_line(), "NICK botname");
+            stream
+                .write_all(b":tmi.twitch.tv 001 botname :Welcome, GLHF!\r\n")
+                .unwrap();
+            assert_eq!(next_line(), "JOIN #captaincallback");
+            stream
+                .write_all(
+                    b":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!discord\r\n",
+                )
+                .unwrap();
+            next_line()
+        });
+        let login = Login {
+            credentials: Credentials::Token {
+                access_token: "token".to_owned(),
+                user_name: "botname".to_owned(),
+            },
+            channels: vec!["captaincallback".to_owned()],
+            capabilities: Vec::new(),
+            transport: ChatTransport::Tcp,
+            security: ConnectionSecurity::Plain,
+            verify_certificates: true,
+            server: Some(("127.0.0.1".to_owned(), port)),
+        };
+        let settings = ConnectorSettings {
+            forward_pings: false,
+            send: SendSettings::default(),
+            keepalive: Duration::from_secs(240),
+            tokens: None,
+        };
+        let mut connector = TwitchChatConnector::start(login, settings).unwrap();
+        connector.run(&mut Echo).await.unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            "PRIVMSG #captaincallback :Hello back"
+        );
     }
 }
@@ -0,0 +1,121 @@
+use super::{outgoing::Outgoing, rate_limit::ModeratedChannels};
+use crate::config::DuplicateMessages;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// twitch drops a message identical to the previous one in the channel for this long
+pub const DUPLICATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Remembers the last chat message sent to each channel, so that the next one isn't
+/// dropped as duplicate. Moderators may repeat themselves, so nothing changes for them.
+#[derive(Debug)]
+pub struct DuplicateGuard {
+    handling: DuplicateMessages,
+    moderated: ModeratedChannels,
+    last_sent: HashMap<String, (Outgoing, Instant)>,
+}
+
+impl DuplicateGuard {
+    pub fn new(handling: DuplicateMessages, moderated: ModeratedChannels) -> Self {
+        Self {
+            handling,
+            moderated,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Lines sent before are remembered either way.
+    pub fn set_handling(&mut self, handling: DuplicateMessages) {
+        self.handling = handling;
+    }
+
+    /// How long the line has to wait, zero if it may be sent right now.
+    pub fn delay(&self, line: &Outgoing, now: Instant) -> Duration {
+        match (self.handling, self.duplicate_since(line)) {
+            (DuplicateMessages::Delay, Some(sent)) => {
+                (sent + DUPLICATE_WINDOW).saturating_duration_since(now)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The line to send instead, varied if needed, which is remembered as sent.
+    pub fn prepare(&mut self, line: Outgoing, now: Instant) -> Outgoing {
+        let channel = match line.chat_channel() {
+            Some(channel) => channel.to_owned(),
+            None => return line,
+        };
+        let line = match (self.handling, self.duplicate_since(&line)) {
+            (DuplicateMessages::Vary, Some(sent)) if now < sent + DUPLICATE_WINDOW => line.varied(),
+            _ => line,
+        };
+        self.last_sent.insert(channel, (line.clone(), now));
+        line
+    }
+
+    // when the same line was last sent to its channel
+    fn duplicate_since(&self, line: &Outgoing) -> Option<Instant> {
+        let channel = line.chat_channel()?;
+        if self.moderated.contains(channel) {
+            return None;
+        }
+        self.last_sent
+            .get(channel)
+            .filter(|(sent, _)| sent == line)
+            .map(|&(_, time)| time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{Badge, UserState};
+
+    fn timer() -> Outgoing {
+        Outgoing::privmsg("captaincallback", "Drink some water!").unwrap()
+    }
+
+    #[test]
+    fn repeated_timer_is_delayed_past_the_window() {
+        let start = Instant::now();
+        let mut guard = DuplicateGuard::new(DuplicateMessages::Delay, ModeratedChannels::default());
+        assert_eq!(guard.delay(&timer(), start), Duration::ZERO);
+        guard.prepare(timer(), start);
+        let second_fire = start + Duration::from_secs(5);
+        assert_eq!(guard.delay(&timer(), second_fire), Duration::from_secs(25));
+        let other = Outgoing::privmsg("carkhy", "Drink some water!").unwrap();
+        assert_eq!(guard.delay(&other, second_fire), Duration::ZERO);
+        assert_eq!(guard.prepare(timer(), start + DUPLICATE_WINDOW), timer());
+    }
+
+    #[test]
+    fn repeated_timer_is_varied() {
+        let start = Instant::now();
+        let mut guard = DuplicateGuard::new(DuplicateMessages::Vary, ModeratedChannels::default());
+        let second_fire = start + Duration::from_secs(5);
+        let third_fire = start + Duration::from_secs(10);
+        assert_eq!(guard.prepare(timer(), start), timer());
+        assert_eq!(guard.delay(&timer(), second_fire), Duration::ZERO);
+        assert_eq!(guard.prepare(timer(), second_fire), timer().varied());
+        // differs from the varied one sent before
+        assert_eq!(guard.prepare(timer(), third_fire), timer());
+    }
+
+    #[test]
+    fn moderators_may_repeat_themselves() {
+        let start = Instant::now();
+        let moderated = ModeratedChannels::default();
+        moderated.update(&UserState {
+            channel: Some("captaincallback".to_owned()),
+            badges: vec![Badge::Moderator],
+            ..Default::default()
+        });
+        let mut guard = DuplicateGuard::new(DuplicateMessages::Delay, moderated);
+        guard.prepare(timer(), start);
+        let second_fire = start + Duration::from_secs(5);
+        assert_eq!(guard.delay(&timer(), second_fire), Duration::ZERO);
+        assert_eq!(guard.prepare(timer(), second_fire), timer());
+    }
+}
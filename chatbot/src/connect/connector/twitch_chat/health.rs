@@ -0,0 +1,313 @@
+//! What `/healthz` and `/readyz` answer, kept up to date by the receive thread
+//! so that a request never waits for twitch.
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How long the receive thread may be quiet and how many lines may wait to be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthLimits {
+    pub stale_after: Duration,
+    pub max_queue: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Login {
+    Pending,
+    LoggedIn(String),
+    // the connection was lost and is being restored
+    Lost,
+}
+
+// how far joining a channel got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Join {
+    #[default]
+    Waiting,
+    // the JOIN of this attempt was sent, starting at 1
+    Sent(u32),
+    // that many attempts weren't confirmed, another follows
+    Retrying(u32),
+    GaveUp(u32),
+    Joined,
+}
+
+impl Join {
+    // why the channel isn't joined yet, None if it is
+    fn missing(&self, channel: &str) -> Option<String> {
+        match *self {
+            Join::Waiting | Join::Sent(1) => Some(channel.to_owned()),
+            Join::Sent(attempt) => Some(format!("{} (attempt {})", channel, attempt)),
+            Join::Retrying(attempts) => Some(format!(
+                "{} (retrying after {} attempts)",
+                channel, attempts
+            )),
+            Join::GaveUp(attempts) => {
+                Some(format!("{} (gave up after {} attempts)", channel, attempts))
+            }
+            Join::Joined => None,
+        }
+    }
+}
+
+struct State {
+    // when the receive thread last handled what it read
+    last_read: Instant,
+    login: Login,
+    // the channels the bot should be in, and how far the join got
+    channels: BTreeMap<String, Join>,
+}
+
+/// The state of the chat connection, shared between the receive thread and the endpoints.
+#[derive(Clone)]
+pub struct Health {
+    state: Arc<Mutex<State>>,
+    queue_depth: Arc<dyn Fn() -> usize + Send + Sync>,
+}
+
+/// The result of each check, `ok` or why it failed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Report(BTreeMap<&'static str, String>);
+
+const OK: &str = "ok";
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.0.values().all(|result| result == OK)
+    }
+
+    /// E.g. `{"status":"unavailable","checks":{"authenticated":"not logged in yet"}}`.
+    pub fn to_json(&self) -> Value {
+        let status = if self.passed() { OK } else { "unavailable" };
+        json!({ "status": status, "checks": self.0 })
+    }
+}
+
+impl Health {
+    pub fn new(
+        channels: &[String],
+        queue_depth: impl Fn() -> usize + Send + Sync + 'static,
+        now: Instant,
+    ) -> Self {
+        let channels = channels
+            .iter()
+            .map(|name| (name.clone(), Join::Waiting))
+            .collect();
+        Self {
+            state: Arc::new(Mutex::new(State {
+                last_read: now,
+                login: Login::Pending,
+                channels,
+            })),
+            queue_depth: Arc::new(queue_depth),
+        }
+    }
+
+    pub fn read(&self, now: Instant) {
+        self.state.lock().unwrap().last_read = now;
+    }
+
+    pub fn logged_in(&self, login: &str) {
+        self.state.lock().unwrap().login = Login::LoggedIn(login.to_owned());
+    }
+
+    // the channels are joined again after the next login
+    pub fn logged_out(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.login = Login::Lost;
+        state
+            .channels
+            .values_mut()
+            .for_each(|join| *join = Join::Waiting);
+    }
+
+    pub fn wanted(&self, channel: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.channels.entry(channel.to_owned()).or_default();
+    }
+
+    pub fn parted(&self, channel: &str) {
+        self.state.lock().unwrap().channels.remove(channel);
+    }
+
+    pub fn sent(&self, channel: &str, attempt: u32) {
+        self.set(channel, Join::Sent(attempt));
+    }
+
+    pub fn retrying(&self, channel: &str, attempts: u32) {
+        self.set(channel, Join::Retrying(attempts));
+    }
+
+    pub fn gave_up(&self, channel: &str, attempts: u32) {
+        self.set(channel, Join::GaveUp(attempts));
+    }
+
+    /// Twitch confirms a join with the bot's own JOIN, or with the ROOMSTATE only the bot gets,
+    /// which has no user. Whether it confirmed a channel the bot wants to be in.
+    pub fn joined(&self, user: Option<&str>, channel: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let own = match (&state.login, user) {
+            (Login::LoggedIn(login), Some(user)) => login == user,
+            (Login::LoggedIn(_), None) => true,
+            _ => false,
+        };
+        match state.channels.get_mut(channel) {
+            Some(join) if own => {
+                *join = Join::Joined;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn set(&self, channel: &str, join: Join) {
+        if let Some(state) = self.state.lock().unwrap().channels.get_mut(channel) {
+            *state = join;
+        }
+    }
+
+    /// Whether the receive thread keeps up.
+    pub fn live(&self, now: Instant, limits: &HealthLimits) -> Report {
+        let state = self.state.lock().unwrap();
+        let mut checks = BTreeMap::new();
+        checks.insert("event_loop", event_loop(&state, now, limits));
+        Report(checks)
+    }
+
+    /// Whether the bot is in all its channels and can answer soon.
+    pub fn ready(&self, now: Instant, limits: &HealthLimits) -> Report {
+        let state = self.state.lock().unwrap();
+        let mut checks = BTreeMap::new();
+        checks.insert("event_loop", event_loop(&state, now, limits));
+        let login = match &state.login {
+            Login::Pending => "not logged in yet".to_owned(),
+            Login::LoggedIn(_) => OK.to_owned(),
+            Login::Lost => "reconnecting to chat".to_owned(),
+        };
+        checks.insert("authenticated", login);
+        let missing: Vec<String> = state
+            .channels
+            .iter()
+            .filter_map(|(name, join)| join.missing(name))
+            .collect();
+        let channels = match missing.is_empty() {
+            true => OK.to_owned(),
+            false => format!("not joined: {}", missing.join(", ")),
+        };
+        checks.insert("channels", channels);
+        let depth = (self.queue_depth)();
+        let queue = match depth > limits.max_queue {
+            true => format!("{} lines waiting, more than {}", depth, limits.max_queue),
+            false => OK.to_owned(),
+        };
+        checks.insert("queue", queue);
+        Report(checks)
+    }
+}
+
+fn event_loop(state: &State, now: Instant, limits: &HealthLimits) -> String {
+    let quiet = now.saturating_duration_since(state.last_read);
+    match quiet > limits.stale_after {
+        true => format!("nothing read from twitch for {}s", quiet.as_secs()),
+        false => OK.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const LIMITS: HealthLimits = HealthLimits {
+        stale_after: Duration::from_secs(300),
+        max_queue: 20,
+    };
+
+    fn health(now: Instant) -> Health {
+        let channels = ["captaincallback".to_owned(), "carkhy".to_owned()];
+        Health::new(&channels, || 0, now)
+    }
+
+    fn logged_in(health: &Health) {
+        health.logged_in("botname");
+        health.joined(Some("botname"), "captaincallback");
+        health.joined(Some("botname"), "carkhy");
+    }
+
+    #[test]
+    fn ready_only_after_the_login_and_the_joins() {
+        let now = Instant::now();
+        let health = health(now);
+        assert!(health.live(now, &LIMITS).passed());
+        let report = health.ready(now, &LIMITS);
+        assert_eq!(
+            report.to_json(),
+            json!({
+                "status": "unavailable",
+                "checks": {
+                    "authenticated": "not logged in yet",
+                    "channels": "not joined: captaincallback, carkhy",
+                    "event_loop": "ok",
+                    "queue": "ok",
+                }
+            })
+        );
+        health.logged_in("botname");
+        // somebody else joining doesn't count
+        health.joined(Some("carkhy"), "carkhy");
+        health.joined(Some("botname"), "captaincallback");
+        let report = health.ready(now, &LIMITS);
+        assert_eq!(report.0["channels"], "not joined: carkhy");
+        health.joined(Some("botname"), "carkhy");
+        assert_eq!(health.ready(now, &LIMITS).to_json()["status"], "ok");
+        health.parted("carkhy");
+        health.wanted("rustlang");
+        assert_eq!(
+            health.ready(now, &LIMITS).0["channels"],
+            "not joined: rustlang"
+        );
+    }
+
+    #[test]
+    fn not_ready_while_reconnecting() {
+        let now = Instant::now();
+        let health = health(now);
+        logged_in(&health);
+        health.logged_out();
+        let report = health.ready(now, &LIMITS);
+        assert!(!report.passed());
+        assert_eq!(report.0["authenticated"], "reconnecting to chat");
+        assert_eq!(report.0["channels"], "not joined: captaincallback, carkhy");
+        // a read keeps it alive, waiting for the next connection may not take too long
+        let later = now + Duration::from_secs(301);
+        assert_eq!(
+            health.live(later, &LIMITS).to_json(),
+            json!({
+                "status": "unavailable",
+                "checks": { "event_loop": "nothing read from twitch for 301s" }
+            })
+        );
+        health.read(later);
+        assert!(health.live(later, &LIMITS).passed());
+        logged_in(&health);
+        assert!(health.ready(later, &LIMITS).passed());
+    }
+
+    #[test]
+    fn not_ready_while_the_queue_is_backlogged() {
+        let now = Instant::now();
+        let depth = Arc::new(AtomicUsize::new(21));
+        let queue = depth.clone();
+        let health = Health::new(&[], move || queue.load(Ordering::SeqCst), now);
+        health.logged_in("botname");
+        let report = health.ready(now, &LIMITS);
+        assert_eq!(report.0["queue"], "21 lines waiting, more than 20");
+        // still alive, only busy
+        assert!(health.live(now, &LIMITS).passed());
+        depth.store(20, Ordering::SeqCst);
+        assert!(health.ready(now, &LIMITS).passed());
+    }
+}
@@ -0,0 +1,678 @@
+use super::receive::{
+    get_badges, parse_color, parse_emotes, parse_paid_message, parse_reply_parent, parse_timestamp,
+    HYPE_CHAT_LEVELS,
+};
+use crate::connect::{
+    Badge, ChatBotEvent, Color, EmoteSpan, RawTags, SubTier, Tags, TextMessage, UserInfo,
+    UserNotice, UserNoticeKind, UserState,
+};
+use std::{collections::BTreeMap, fmt, time::UNIX_EPOCH};
+
+const SERVER: &str = "tmi.twitch.tv";
+
+impl ChatBotEvent {
+    /// Write the event back as IRC line (without line ending), so that parsing the line
+    /// results in the same event again. Tags are sorted by name, so the line may differ
+    /// from the one originally received. None for events not sent by twitch.
+    pub fn to_irc_line(&self) -> Option<String> {
+        let line = match self {
+            ChatBotEvent::TextMessage(message) => private_message(message),
+            ChatBotEvent::Command(command) => private_message(&command.message),
+            ChatBotEvent::UserNotice(notice) => user_notice(notice),
+            ChatBotEvent::ClearChat(clear_chat) => {
+                let mut line =
+                    IrcLine::from_server("CLEARCHAT", vec![channel_param(&clear_chat.channel)]);
+                if let Some(duration) = clear_chat.duration {
+                    line.tag("ban-duration", duration.as_secs().to_string());
+                }
+                line.trailing = clear_chat.target_user.clone();
+                line
+            }
+            ChatBotEvent::ClearMessage(clear_message) => {
+                let mut line =
+                    IrcLine::from_server("CLEARMSG", vec![channel_param(&clear_message.channel)]);
+                line.tag("login", clear_message.login.clone());
+                line.tag("target-msg-id", clear_message.target_message_id.clone());
+                line.trailing = Some(clear_message.text.clone());
+                line
+            }
+            ChatBotEvent::RoomState(room_state) => {
+                let mut line =
+                    IrcLine::from_server("ROOMSTATE", vec![channel_param(&room_state.channel)]);
+                let flag = |value: bool| if value { "1" } else { "0" }.to_owned();
+                if let Some(emote_only) = room_state.emote_only {
+                    line.tag("emote-only", flag(emote_only));
+                }
+                if let Some(followers_only) = room_state.followers_only {
+                    line.tag("followers-only", followers_only.to_string());
+                }
+                if let Some(r9k) = room_state.r9k {
+                    line.tag("r9k", flag(r9k));
+                }
+                if let Some(slow) = room_state.slow {
+                    line.tag("slow", slow.to_string());
+                }
+                if let Some(subs_only) = room_state.subs_only {
+                    line.tag("subs-only", flag(subs_only));
+                }
+                line
+            }
+            ChatBotEvent::UserState(user_state) | ChatBotEvent::GlobalUserState(user_state) => {
+                user_state_line(user_state)
+            }
+            ChatBotEvent::Notice(notice) => {
+                let target = notice
+                    .channel
+                    .as_deref()
+                    .map_or("*".to_owned(), channel_param);
+                let mut line = IrcLine::from_server("NOTICE", vec![target]);
+                if let Some(kind) = &notice.kind {
+                    line.tag("msg-id", notice_id(kind));
+                }
+                line.trailing = Some(notice.text.clone());
+                line
+            }
+            ChatBotEvent::Whisper(whisper) => {
+                let mut line = IrcLine::from_user("WHISPER", &whisper.user.name);
+                line.params = vec![whisper.recipient.clone()];
+                line.tags = sorted(&whisper.tags);
+                user_tags(&whisper.user, &whisper.tags.tags(), &mut line.tags);
+                line.trailing = Some(whisper.text.clone());
+                line
+            }
+            ChatBotEvent::HostTarget {
+                channel,
+                target,
+                viewers,
+            } => {
+                let mut line = IrcLine::from_server("HOSTTARGET", vec![channel_param(channel)]);
+                let target = target.as_deref().unwrap_or("-");
+                line.trailing = Some(format!("{} {}", target, viewers));
+                line
+            }
+            ChatBotEvent::Join { user, channel } => {
+                let mut line = IrcLine::from_user("JOIN", user);
+                line.params = vec![channel_param(channel)];
+                line
+            }
+            ChatBotEvent::Part { user, channel } => {
+                let mut line = IrcLine::from_user("PART", user);
+                line.params = vec![channel_param(channel)];
+                line
+            }
+            ChatBotEvent::Names { channel, users } => {
+                let params = vec!["*".to_owned(), "=".to_owned(), channel_param(channel)];
+                let mut line = IrcLine::from_server("353", params);
+                line.trailing = Some(users.join(" "));
+                line
+            }
+            ChatBotEvent::EndOfNames { channel } => {
+                let mut line =
+                    IrcLine::from_server("366", vec!["*".to_owned(), channel_param(channel)]);
+                line.trailing = Some("End of /NAMES list".to_owned());
+                line
+            }
+            ChatBotEvent::Ping { server } => {
+                let mut line = IrcLine::from_server("PING", Vec::new());
+                line.trailing = Some(server.clone());
+                line
+            }
+            ChatBotEvent::Connection(_)
+            | ChatBotEvent::Internal(_)
+            | ChatBotEvent::Api(_)
+            | ChatBotEvent::LiveStatus { .. }
+            | ChatBotEvent::ShieldMode { .. }
+            | ChatBotEvent::EventSubWelcome { .. }
+            | ChatBotEvent::Redemption(_)
+            | ChatBotEvent::Follow { .. }
+            | ChatBotEvent::Subscribed { .. }
+            | ChatBotEvent::HypeTrain { .. }
+            | ChatBotEvent::Shutdown => return None,
+        };
+        Some(line.to_string())
+    }
+}
+
+/// `@tags :prefix COMMAND params :trailing`
+struct IrcLine {
+    tags: BTreeMap<String, String>,
+    prefix: String,
+    command: &'static str,
+    params: Vec<String>,
+    trailing: Option<String>,
+}
+
+impl IrcLine {
+    fn from_server(command: &'static str, params: Vec<String>) -> Self {
+        Self {
+            tags: BTreeMap::new(),
+            prefix: SERVER.to_owned(),
+            command,
+            params,
+            trailing: None,
+        }
+    }
+
+    // a name containing a '.' is a servername
+    fn from_user(command: &'static str, user_name: &str) -> Self {
+        Self {
+            tags: BTreeMap::new(),
+            prefix: if user_name.contains('.') {
+                user_name.to_owned()
+            } else {
+                format!("{0}!{0}@{0}.{1}", user_name, SERVER)
+            },
+            command,
+            params: Vec::new(),
+            trailing: None,
+        }
+    }
+
+    fn tag(&mut self, key: &str, value: String) {
+        self.tags.insert(key.to_owned(), value);
+    }
+}
+
+impl fmt::Display for IrcLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self
+                .tags
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, escape_tag_value(value)))
+                .collect();
+            write!(f, "@{} ", tags.join(";"))?;
+        }
+        write!(f, ":{} {}", self.prefix, self.command)?;
+        for param in &self.params {
+            write!(f, " {}", param)?;
+        }
+        if let Some(trailing) = &self.trailing {
+            write!(f, " :{}", trailing)?;
+        }
+        Ok(())
+    }
+}
+
+fn channel_param(channel: &str) -> String {
+    format!("#{}", channel)
+}
+
+// https://ircv3.net/specs/extensions/message-tags.html#escaping-values
+fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for codepoint in value.chars() {
+        match codepoint {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+fn sorted(tags: &RawTags) -> BTreeMap<String, String> {
+    tags.iter()
+        .map(|(key, value)| (key.to_owned(), value.into_owned()))
+        .collect()
+}
+
+fn private_message(message: &TextMessage) -> IrcLine {
+    let mut line = IrcLine::from_user("PRIVMSG", &message.user.name);
+    line.params = vec![channel_param(&message.channel)];
+    line.tags = sorted(&message.tags);
+    let tags = message.tags.tags();
+    user_tags(&message.user, &tags, &mut line.tags);
+    // tags are kept unless the structured fields were changed
+    let current_emotes = line
+        .tags
+        .get("emotes")
+        .map(|emotes| parse_emotes(emotes, &message.text))
+        .unwrap_or_default();
+    if current_emotes != message.emotes {
+        line.tag("emotes", emotes_tag(&message.emotes, &message.text));
+    }
+    let current_bits: Option<u32> = line.tags.get("bits").and_then(|bits| bits.parse().ok());
+    if current_bits != message.bits {
+        match message.bits {
+            Some(bits) => line.tag("bits", bits.to_string()),
+            None => {
+                line.tags.remove("bits");
+            }
+        }
+    }
+    for (tag, set) in [
+        ("first-msg", message.first_msg),
+        ("returning-chatter", message.returning_chatter),
+    ] {
+        if (line.tags.get(tag).map(String::as_str) == Some("1")) != set {
+            line.tag(tag, if set { "1" } else { "0" }.to_owned());
+        }
+    }
+    let current_id = line.tags.get("id").filter(|id| !id.is_empty());
+    if current_id != message.message_id.as_ref() {
+        match &message.message_id {
+            Some(id) => line.tag("id", id.clone()),
+            None => {
+                line.tags.remove("id");
+            }
+        }
+    }
+    if parse_timestamp(&tags) != message.timestamp {
+        match message
+            .timestamp
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        {
+            Some(sent) => line.tag("tmi-sent-ts", sent.as_millis().to_string()),
+            None => {
+                line.tags.remove("tmi-sent-ts");
+            }
+        }
+    }
+    if parse_reply_parent(&tags) != message.reply_to {
+        line.tags.retain(|key, _| !key.starts_with("reply-parent-"));
+        if let Some(parent) = &message.reply_to {
+            line.tag("reply-parent-msg-id", parent.message_id.clone());
+            line.tag("reply-parent-user-login", parent.user_login.clone());
+            line.tag("reply-parent-display-name", parent.display_name.clone());
+            line.tag("reply-parent-msg-body", parent.body.clone());
+        }
+    }
+    if parse_paid_message(&tags) != message.paid {
+        line.tags
+            .retain(|key, _| !key.starts_with("pinned-chat-paid-"));
+        if let Some(paid) = &message.paid {
+            line.tag("pinned-chat-paid-amount", paid.amount.to_string());
+            line.tag("pinned-chat-paid-currency", paid.currency.clone());
+            line.tag("pinned-chat-paid-exponent", paid.exponent.to_string());
+            let level = HYPE_CHAT_LEVELS.get(usize::from(paid.level).saturating_sub(1));
+            line.tag(
+                "pinned-chat-paid-level",
+                level.unwrap_or(&"ONE").to_string(),
+            );
+        }
+    }
+    line.trailing = Some(if message.is_action {
+        format!("\u{1}ACTION {}\u{1}", message.text)
+    } else {
+        message.text.clone()
+    });
+    line
+}
+
+fn user_notice(notice: &UserNotice) -> IrcLine {
+    let mut line = IrcLine::from_server("USERNOTICE", vec![channel_param(&notice.channel)]);
+    user_tags(&notice.user, &Tags::default(), &mut line.tags);
+    line.tag("login", notice.user.name.clone());
+    line.tag("system-msg", notice.system_message.clone());
+    let msg_id = match &notice.kind {
+        UserNoticeKind::Sub {
+            tier,
+            cumulative_months,
+            streak_months,
+        } => {
+            sub_tags(*tier, *cumulative_months, *streak_months, &mut line);
+            "sub".to_owned()
+        }
+        UserNoticeKind::Resub {
+            tier,
+            cumulative_months,
+            streak_months,
+        } => {
+            sub_tags(*tier, *cumulative_months, *streak_months, &mut line);
+            "resub".to_owned()
+        }
+        UserNoticeKind::SubGift { recipient } => {
+            line.tag("msg-param-recipient-user-name", recipient.clone());
+            "subgift".to_owned()
+        }
+        UserNoticeKind::SubMysteryGift { count } => {
+            line.tag("msg-param-mass-gift-count", count.to_string());
+            "submysterygift".to_owned()
+        }
+        UserNoticeKind::Raid { from, viewers } => {
+            line.tag("msg-param-displayName", from.clone());
+            line.tag("msg-param-viewerCount", viewers.to_string());
+            "raid".to_owned()
+        }
+        UserNoticeKind::Unknown(msg_id) => msg_id.clone(),
+    };
+    line.tag("msg-id", msg_id);
+    line.trailing = notice.text.clone();
+    line
+}
+
+fn sub_tags(tier: SubTier, cumulative_months: u32, streak_months: Option<u32>, line: &mut IrcLine) {
+    let plan = match tier {
+        SubTier::Prime => "Prime",
+        SubTier::Tier1 => "1000",
+        SubTier::Tier2 => "2000",
+        SubTier::Tier3 => "3000",
+    };
+    line.tag("msg-param-sub-plan", plan.to_owned());
+    line.tag("msg-param-cumulative-months", cumulative_months.to_string());
+    if let Some(streak_months) = streak_months {
+        line.tag("msg-param-should-share-streak", "1".to_owned());
+        line.tag("msg-param-streak-months", streak_months.to_string());
+    }
+}
+
+fn user_state_line(user_state: &UserState) -> IrcLine {
+    let mut line = match &user_state.channel {
+        Some(channel) => IrcLine::from_server("USERSTATE", vec![channel_param(channel)]),
+        None => IrcLine::from_server("GLOBALUSERSTATE", Vec::new()),
+    };
+    let user = UserInfo {
+        name: String::new(),
+        badges: user_state.badges.clone(),
+        display_name: user_state.display_name.clone(),
+        color: user_state.color,
+    };
+    user_tags(&user, &Tags::default(), &mut line.tags);
+    line.tag("emote-sets", user_state.emote_sets.join(","));
+    line
+}
+
+// writes the tags the user info is parsed from, where they don't match the received ones
+fn user_tags(user: &UserInfo, received: &Tags, tags: &mut BTreeMap<String, String>) {
+    let current_display_name = tags
+        .get("display-name")
+        .filter(|display_name| !display_name.is_empty());
+    if current_display_name != user.display_name.as_ref() {
+        let display_name = user.display_name.clone().unwrap_or_default();
+        tags.insert("display-name".to_owned(), display_name);
+    }
+    let current_color = tags.get("color").and_then(|color| parse_color(color));
+    if current_color != user.color {
+        tags.insert("color".to_owned(), color_tag(user.color));
+    }
+    if get_badges(received) != user.badges {
+        let (badges, badge_info) = badge_tags(&user.badges);
+        tags.insert("badges".to_owned(), badges);
+        tags.insert("badge-info".to_owned(), badge_info);
+    }
+}
+
+fn color_tag(color: Option<Color>) -> String {
+    color
+        .map(|color| format!("#{:02X}{:02X}{:02X}", color.red, color.green, color.blue))
+        .unwrap_or_default()
+}
+
+// subscriber months are sent in badge-info, the badge version is the tier
+fn badge_tags(badges: &[Badge]) -> (String, String) {
+    let mut badge_info = String::new();
+    let badges: Vec<String> = badges
+        .iter()
+        .map(|badge| match badge {
+            Badge::Broadcaster => "broadcaster/1".to_owned(),
+            Badge::Moderator => "moderator/1".to_owned(),
+            Badge::Vip => "vip/1".to_owned(),
+            Badge::Subscriber { months, tier } => {
+                badge_info = format!("subscriber/{}", months);
+                match tier {
+                    SubTier::Tier2 => "subscriber/2000".to_owned(),
+                    SubTier::Tier3 => "subscriber/3000".to_owned(),
+                    SubTier::Prime | SubTier::Tier1 => "subscriber/0".to_owned(),
+                }
+            }
+            Badge::Bits { amount } => format!("bits/{}", amount),
+            Badge::Unknown(name, version) => format!("{}/{}", name, version),
+        })
+        .collect();
+    (badges.join(","), badge_info)
+}
+
+// emotes=25:0-4,12-16/1902:6-10 with inclusive code point positions
+fn emotes_tag(emotes: &[EmoteSpan], text: &str) -> String {
+    let position = |offset: usize| text.get(..offset).map(|before| before.chars().count());
+    let mut ranges: Vec<(&str, Vec<String>)> = Vec::new();
+    for emote in emotes {
+        // spans that don't fit the text can't be written
+        let (Some(start), Some(end)) = (position(emote.start), position(emote.end)) else {
+            continue;
+        };
+        if start >= end {
+            continue;
+        }
+        let range = format!("{}-{}", start, end - 1);
+        match ranges.iter_mut().find(|(id, _)| *id == emote.id) {
+            Some((_, id_ranges)) => id_ranges.push(range),
+            None => ranges.push((&emote.id, vec![range])),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(id, id_ranges)| format!("{}:{}", id, id_ranges.join(",")))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn notice_id(kind: &crate::connect::NoticeKind) -> String {
+    use crate::connect::NoticeKind::*;
+    match kind {
+        MsgRatelimit => "msg_ratelimit".to_owned(),
+        MsgBanned => "msg_banned".to_owned(),
+        MsgTimedout => "msg_timedout".to_owned(),
+        UnrecognizedCmd => "unrecognized_cmd".to_owned(),
+        Other(msg_id) => msg_id.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::connector::twitch_chat::receive::ReceiveEvent;
+    use crate::connect::{InternalEvent, Notice, NoticeKind, PaidMessage, ReplyParent, UserLevel};
+
+    const CHAT_LOG: &str = include_str!("test_data/chat_log.txt");
+
+    fn parse(line: &str) -> ChatBotEvent {
+        match ReceiveEvent::parse_from_message(line) {
+            Ok(ReceiveEvent::ChatBotEvent(event)) => event,
+            other => panic!("{:?} parsed to {:?}", line, other),
+        }
+    }
+
+    fn assert_round_trip(event: ChatBotEvent) {
+        let line = event.to_irc_line().unwrap();
+        assert_eq!(parse(&line), event, "{:?}", line);
+    }
+
+    #[test]
+    fn captured_chat_log_round_trips() {
+        let events =
+            CHAT_LOG
+                .lines()
+                .filter_map(|line| match ReceiveEvent::parse_from_message(line) {
+                    Ok(ReceiveEvent::ChatBotEvent(event)) => Some(event),
+                    _ => None,
+                });
+        for event in events {
+            assert_round_trip(event);
+        }
+    }
+
+    #[test]
+    fn modified_message_round_trips() {
+        let line = "@badge-info=subscriber/8;badges=subscriber/6,bits/100;color=#1E90FF;display-name=Carkhy;emotes=25:0-4;id=1 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #channel :Kappa hi";
+        let mut message = match parse(line) {
+            ChatBotEvent::TextMessage(message) => message,
+            other => panic!("{:?}", other),
+        };
+        message.text = "😀 Kappa; hi Kappa".to_owned();
+        message.emotes = vec![
+            EmoteSpan {
+                id: "25".to_owned(),
+                start: 5,
+                end: 10,
+            },
+            EmoteSpan {
+                id: "25".to_owned(),
+                start: 15,
+                end: 20,
+            },
+        ];
+        message.user.display_name = None;
+        message.user.color = None;
+        message.user.badges = vec![
+            Badge::Moderator,
+            Badge::Subscriber {
+                months: 12,
+                tier: SubTier::Tier1,
+            },
+        ];
+        message.level = UserLevel::Moderator;
+        message.bits = Some(100);
+        message.is_action = true;
+        message.first_msg = true;
+        message.message_id = Some("c5d6e2a1".to_owned());
+        message.timestamp = Some(UNIX_EPOCH + std::time::Duration::from_millis(1637614002702));
+        message.reply_to = Some(ReplyParent {
+            message_id: "b34ccfc7".to_owned(),
+            user_login: "carkhy".to_owned(),
+            display_name: "Carkhy".to_owned(),
+            body: "is this; on?".to_owned(),
+        });
+        message.paid = Some(PaidMessage {
+            amount: 2500,
+            exponent: 2,
+            currency: "EUR".to_owned(),
+            level: 3,
+        });
+        let line = ChatBotEvent::TextMessage(message.clone())
+            .to_irc_line()
+            .unwrap();
+        assert!(line.contains("emotes=25:2-6,12-16;"), "{}", line);
+        assert!(line.contains("badge-info=subscriber/12;"), "{}", line);
+        assert!(line.contains("pinned-chat-paid-level=THREE;"), "{}", line);
+        assert!(
+            line.ends_with(":\u{1}ACTION 😀 Kappa; hi Kappa\u{1}"),
+            "{}",
+            line
+        );
+        // the tags derived from the modified fields were rewritten
+        match parse(&line) {
+            ChatBotEvent::TextMessage(reparsed) => {
+                assert_eq!(
+                    TextMessage {
+                        tags: message.tags.clone(),
+                        ..reparsed
+                    },
+                    message
+                )
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn emotes_that_dont_fit_the_text_are_left_out() {
+        let line = "@emotes=25:0-4 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #channel :Kappa hi";
+        let mut message = match parse(line) {
+            ChatBotEvent::TextMessage(message) => message,
+            other => panic!("{:?}", other),
+        };
+        message.text = "😀 Kappa".to_owned();
+        let span = |start, end| EmoteSpan {
+            id: "25".to_owned(),
+            start,
+            end,
+        };
+        // inside the 😀, empty, past the end and the one that fits
+        message.emotes = vec![span(1, 3), span(0, 0), span(5, 40), span(5, 10)];
+        let line = ChatBotEvent::TextMessage(message).to_irc_line().unwrap();
+        assert!(line.starts_with("@emotes=25:2-6 "), "{}", line);
+        match parse(&line) {
+            ChatBotEvent::TextMessage(reparsed) => assert_eq!(reparsed.emotes, vec![span(5, 10)]),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn tag_values_are_escaped() {
+        let event = ChatBotEvent::Notice(Notice {
+            channel: Some("channel".to_owned()),
+            kind: Some(NoticeKind::Other("a; b\\c".to_owned())),
+            text: "Some notice".to_owned(),
+        });
+        assert_eq!(
+            event.to_irc_line().unwrap(),
+            "@msg-id=a\\:\\sb\\\\c :tmi.twitch.tv NOTICE #channel :Some notice"
+        );
+        assert_round_trip(event);
+    }
+
+    #[test]
+    fn events_without_source_round_trip() {
+        assert_round_trip(ChatBotEvent::Notice(Notice {
+            channel: None,
+            kind: None,
+            text: "Login authentication failed".to_owned(),
+        }));
+        assert_round_trip(ChatBotEvent::HostTarget {
+            channel: "channel".to_owned(),
+            target: None,
+            viewers: 0,
+        });
+        assert_round_trip(ChatBotEvent::UserNotice(UserNotice {
+            kind: UserNoticeKind::Resub {
+                tier: SubTier::Tier2,
+                cumulative_months: 7,
+                streak_months: Some(3),
+            },
+            user: UserInfo {
+                name: "carkhy".to_owned(),
+                badges: vec![Badge::Subscriber {
+                    months: 7,
+                    tier: SubTier::Tier2,
+                }],
+                display_name: Some("Carkhy".to_owned()),
+                color: None,
+            },
+            channel: "channel".to_owned(),
+            system_message: "Carkhy subscribed at Tier 2.".to_owned(),
+            text: Some("Great stream!".to_owned()),
+        }));
+        assert_round_trip(ChatBotEvent::UserNotice(UserNotice {
+            kind: UserNoticeKind::Raid {
+                from: "Carkhy".to_owned(),
+                viewers: 12,
+            },
+            user: UserInfo {
+                name: "carkhy".to_owned(),
+                badges: Vec::new(),
+                display_name: None,
+                color: None,
+            },
+            channel: "channel".to_owned(),
+            system_message: "12 raiders from Carkhy have joined!".to_owned(),
+            text: None,
+        }));
+    }
+
+    #[test]
+    fn modified_whisper_round_trips() {
+        let line = "@badges=glhf-pledge/1;color=;display-name=Carkhy :carkhy!carkhy@carkhy.tmi.twitch.tv WHISPER botname :hello";
+        let mut whisper = match parse(line) {
+            ChatBotEvent::Whisper(whisper) => whisper,
+            other => panic!("{:?}", other),
+        };
+        whisper.text = "hello there".to_owned();
+        whisper.recipient = "otherbot".to_owned();
+        assert_round_trip(ChatBotEvent::Whisper(whisper));
+    }
+
+    #[test]
+    fn timed_messages_have_no_irc_line() {
+        let event = ChatBotEvent::Internal(InternalEvent::TimedMessage {
+            channel: "captaincallback".to_owned(),
+            name: "hello".to_owned(),
+            id: uuid::Uuid::new_v4(),
+        });
+        assert_eq!(event.to_irc_line(), None);
+    }
+}
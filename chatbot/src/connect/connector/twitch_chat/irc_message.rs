@@ -0,0 +1,206 @@
+use crate::connect::{error::ParseError, Tags};
+use std::borrow::Cow;
+
+/// A single IRC line split into its parts, borrowing from the line.
+/// (https://ircv3.net/specs/extensions/message-tags.html, https://datatracker.ietf.org/doc/html/rfc1459#section-2.3.1)
+///
+/// `@tags :prefix COMMAND middle params :trailing param`
+#[derive(Debug, PartialEq)]
+pub struct IrcMessage<'a> {
+    pub tags: Tags<'a>,
+    pub prefix: Option<&'a str>,
+    pub command: &'a str,
+    pub params: Vec<&'a str>,
+    pub trailing: Option<&'a str>,
+}
+
+impl<'a> IrcMessage<'a> {
+    pub fn parse(message: &'a str) -> Result<Self, ParseError> {
+        let mut rest = message.trim_end_matches(['\r', '\n']);
+
+        let mut tags = Tags::default();
+        if let Some(tags_and_rest) = rest.strip_prefix('@') {
+            let (tags_string, after_tags) = tags_and_rest
+                .split_once(' ')
+                .ok_or(ParseError::MissingCommand)?;
+            // tags start after the '@'
+            if let Some(offset) = malformed_tag_offset(tags_string) {
+                return Err(ParseError::MalformedTags { offset: offset + 1 });
+            }
+            tags = Tags::new(tags_string);
+            rest = after_tags.trim_start_matches(' ');
+        }
+
+        let mut prefix = None;
+        if let Some(prefix_and_rest) = rest.strip_prefix(':') {
+            let (prefix_string, after_prefix) = prefix_and_rest
+                .split_once(' ')
+                .unwrap_or((prefix_and_rest, ""));
+            // either a servername or nick!user@host, the nick must not be empty
+            if prefix_string.is_empty() || prefix_string.starts_with(['!', '@']) {
+                return Err(ParseError::MalformedPrefix(prefix_string.to_owned()));
+            }
+            prefix = Some(prefix_string);
+            rest = after_prefix.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return Err(ParseError::MissingCommand);
+        }
+
+        let mut params = Vec::new();
+        let mut trailing = None;
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing_param) = rest.strip_prefix(':') {
+                trailing = Some(trailing_param);
+                break;
+            }
+            let (param, after_param) = rest.split_once(' ').unwrap_or((rest, ""));
+            params.push(param);
+            rest = after_param;
+        }
+
+        Ok(Self {
+            tags,
+            prefix,
+            command,
+            params,
+            trailing,
+        })
+    }
+
+    /// Get the nick name out of a `nick!user@host` prefix.
+    pub fn nick(&self) -> Option<&'a str> {
+        self.prefix
+            .and_then(|prefix| prefix.split_once('!'))
+            .map(|(nick, _)| nick)
+    }
+
+    /// Get the sender of the message, which is the nick name or the
+    /// servername for messages sent by twitch itself (`:tmi.twitch.tv`).
+    pub fn sender(&self) -> Option<&'a str> {
+        self.nick().or(self.prefix)
+    }
+
+    /// Get the channel of the message (the first parameter) without the leading '#'.
+    pub fn channel(&self) -> Option<&'a str> {
+        self.params
+            .first()
+            .and_then(|channel| channel.strip_prefix('#'))
+    }
+
+    pub fn tag(&self, key: &str) -> Option<Cow<'a, str>> {
+        self.tags.get(key)
+    }
+}
+
+// an empty tags section or a tag without key can't be right
+fn malformed_tag_offset(tags_string: &str) -> Option<usize> {
+    if tags_string.is_empty() {
+        return Some(0);
+    }
+    let mut offset = 0;
+    for key_val_pair in tags_string.split(';') {
+        if key_val_pair.starts_with('=') {
+            return Some(offset);
+        }
+        offset += key_val_pair.len() + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitting_private_messages() {
+        let message = IrcMessage::parse(
+            "@badges=;color= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hello : there\r\n",
+        )
+        .unwrap();
+        assert_eq!(message.tags.iter().count(), 2);
+        assert_eq!(
+            message.prefix,
+            Some("chatter!chatter@chatter.tmi.twitch.tv")
+        );
+        assert_eq!(message.nick(), Some("chatter"));
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.params, vec!["#channel123"]);
+        assert_eq!(message.channel(), Some("channel123"));
+        assert_eq!(message.trailing, Some("Hello : there"));
+    }
+
+    #[test]
+    fn splitting_messages_with_server_prefix() {
+        let message = IrcMessage::parse(":tmi.twitch.tv USERNOTICE #channel123").unwrap();
+        assert_eq!(message.prefix, Some("tmi.twitch.tv"));
+        assert_eq!(message.nick(), None);
+        assert_eq!(message.sender(), Some("tmi.twitch.tv"));
+        assert_eq!(message.command, "USERNOTICE");
+        assert_eq!(message.channel(), Some("channel123"));
+        assert_eq!(message.trailing, None);
+    }
+
+    #[test]
+    fn splitting_messages_without_prefix() {
+        let message = IrcMessage::parse("PING :tmi.twitch.tv").unwrap();
+        assert_eq!(message.prefix, None);
+        assert_eq!(message.command, "PING");
+        assert!(message.params.is_empty());
+        assert_eq!(message.trailing, Some("tmi.twitch.tv"));
+    }
+
+    #[test]
+    fn splitting_incomplete_messages() {
+        for message in [
+            "",
+            "@badge-info=;badges=moderator/1",
+            "@badge-info= ",
+            ":tmi.twitch.tv",
+            ":tmi.twitch.tv ",
+        ] {
+            assert_eq!(
+                IrcMessage::parse(message),
+                Err(ParseError::MissingCommand),
+                "{:?}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn splitting_messages_with_malformed_prefix() {
+        assert_eq!(
+            IrcMessage::parse(":"),
+            Err(ParseError::MalformedPrefix(String::new()))
+        );
+        assert_eq!(
+            IrcMessage::parse(": PRIVMSG #channel123 :Hello"),
+            Err(ParseError::MalformedPrefix(String::new()))
+        );
+        assert_eq!(
+            IrcMessage::parse(":!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hello"),
+            Err(ParseError::MalformedPrefix(
+                "!chatter@chatter.tmi.twitch.tv".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn splitting_messages_with_malformed_tags() {
+        assert_eq!(
+            IrcMessage::parse("@ PING :tmi.twitch.tv"),
+            Err(ParseError::MalformedTags { offset: 1 })
+        );
+        assert_eq!(
+            IrcMessage::parse("@badges=;=value;color= PING :tmi.twitch.tv"),
+            Err(ParseError::MalformedTags { offset: 9 })
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use super::{
+    outgoing::Outgoing,
+    transport::{ConnectionWriter, LineWriter},
+};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// twitch answers the PING with "PONG :keepalive"
+const KEEPALIVE_PAYLOAD: &str = "keepalive";
+
+/// A PONG arriving later than this counts as a dead connection.
+pub const PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When bytes were last received on the current connection, parsed or not.
+#[derive(Debug, Clone)]
+pub struct Activity(Arc<Mutex<Instant>>);
+
+impl Activity {
+    pub fn new(now: Instant) -> Self {
+        Self(Arc::new(Mutex::new(now)))
+    }
+
+    pub fn received(&self, now: Instant) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    fn last(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeepaliveCheck {
+    Wait(Duration),
+    Ping,
+    Dead,
+}
+
+/// Pings twitch when the connection was quiet for `idle`, twitch's own PINGs come only
+/// every five minutes or so.
+#[derive(Debug)]
+pub struct Keepalive {
+    idle: Duration,
+    timeout: Duration,
+    ping_sent: Option<Instant>,
+}
+
+impl Keepalive {
+    pub fn new(idle: Duration, timeout: Duration) -> Self {
+        Self {
+            idle,
+            timeout,
+            ping_sent: None,
+        }
+    }
+
+    pub fn check(&mut self, last_received: Instant, now: Instant) -> KeepaliveCheck {
+        if let Some(sent) = self.ping_sent {
+            if last_received > sent {
+                self.ping_sent = None;
+            } else if now >= sent + self.timeout {
+                self.ping_sent = None;
+                return KeepaliveCheck::Dead;
+            } else {
+                return KeepaliveCheck::Wait(sent + self.timeout - now);
+            }
+        }
+        let idle_until = last_received + self.idle;
+        if now >= idle_until {
+            self.ping_sent = Some(now);
+            KeepaliveCheck::Ping
+        } else {
+            KeepaliveCheck::Wait(idle_until - now)
+        }
+    }
+}
+
+/// Runs until the connection is found dead and closed, which makes the receive thread
+/// reconnect. The timer starts again with the next connection.
+pub async fn keepalive_loop<C, N, S, F>(
+    activity: &Activity,
+    control: &mut ConnectionWriter<C>,
+    keepalive: &mut Keepalive,
+    mut now: N,
+    mut sleep: S,
+) where
+    N: FnMut() -> Instant,
+    S: FnMut(Duration) -> F,
+    F: Future<Output = ()>,
+{
+    loop {
+        match keepalive.check(activity.last(), now()) {
+            KeepaliveCheck::Wait(delay) => sleep(delay).await,
+            KeepaliveCheck::Ping => {
+                // a failing write is noticed by the missing PONG
+                let written = match Outgoing::ping(KEEPALIVE_PAYLOAD) {
+                    Ok(ping) => control.write(ping).await,
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = written {
+                    tracing::warn!(?error, "could not send the keepalive");
+                }
+            }
+            KeepaliveCheck::Dead => {
+                tracing::warn!("no answer to the keepalive, closing the connection");
+                control.close();
+                activity.received(now());
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::error::ConnectorError;
+    use std::{cell::Cell, rc::Rc};
+
+    const IDLE: Duration = Duration::from_secs(240);
+
+    // shared with the writer thread
+    #[derive(Default, Clone)]
+    struct MockControl {
+        written: Arc<Mutex<Vec<String>>>,
+        closed: Arc<Mutex<bool>>,
+    }
+
+    impl LineWriter for MockControl {
+        fn write_line(&mut self, line: Outgoing) -> Result<(), ConnectorError> {
+            self.written.lock().unwrap().push(line.to_string());
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            *self.closed.lock().unwrap() = true;
+        }
+    }
+
+    #[test]
+    fn received_bytes_postpone_the_ping() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let mut keepalive = Keepalive::new(IDLE, PONG_TIMEOUT);
+        assert_eq!(
+            keepalive.check(at(0), at(100)),
+            KeepaliveCheck::Wait(Duration::from_secs(140))
+        );
+        assert_eq!(
+            keepalive.check(at(200), at(300)),
+            KeepaliveCheck::Wait(Duration::from_secs(140))
+        );
+        assert_eq!(keepalive.check(at(200), at(440)), KeepaliveCheck::Ping);
+        assert_eq!(
+            keepalive.check(at(200), at(445)),
+            KeepaliveCheck::Wait(Duration::from_secs(5))
+        );
+        // the PONG arrived
+        assert_eq!(
+            keepalive.check(at(446), at(447)),
+            KeepaliveCheck::Wait(Duration::from_secs(239))
+        );
+    }
+
+    #[tokio::test]
+    async fn silent_connection_is_closed() {
+        let start = Instant::now();
+        let clock = Rc::new(Cell::new(start));
+        let activity = Activity::new(start);
+        let control = MockControl::default();
+        let mut writer = ConnectionWriter::spawn(control.clone());
+        let mut keepalive = Keepalive::new(IDLE, PONG_TIMEOUT);
+        keepalive_loop(
+            &activity,
+            &mut writer,
+            &mut keepalive,
+            || clock.get(),
+            |delay| {
+                clock.set(clock.get() + delay);
+                std::future::ready(())
+            },
+        )
+        .await;
+        // requests are handled in order, once this line is written the close was handled too
+        assert!(writer.write(Outgoing::ping("test").unwrap()).await.is_ok());
+        assert_eq!(
+            *control.written.lock().unwrap(),
+            vec!["PING :keepalive\r\n", "PING :test\r\n"]
+        );
+        assert!(*control.closed.lock().unwrap());
+        assert_eq!(clock.get(), start + IDLE + PONG_TIMEOUT);
+        // the next connection gets the full time again
+        assert_eq!(activity.last(), clock.get());
+    }
+}
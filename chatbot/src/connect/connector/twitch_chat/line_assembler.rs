@@ -0,0 +1,146 @@
+/// Splits the received chunks into IRC lines.
+/// A chunk may contain several lines and may end in the middle of a line (or of a
+/// UTF-8 sequence), such an incomplete line is kept until the rest of it arrives.
+#[derive(Debug, Default)]
+pub struct LineAssembler {
+    partial: Vec<u8>,
+    // an overlong line is being dropped until its end arrives
+    discarding: bool,
+}
+
+// twitch allows 8191 bytes of tags plus 512 bytes of message
+pub const MAX_LINE_LENGTH: usize = 8 * 1024 + 512;
+
+impl LineAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the lines completed by this chunk, without line endings and empty lines.
+    /// Invalid UTF-8 is replaced, lines longer than `MAX_LINE_LENGTH` are dropped.
+    pub fn push(&mut self, chunk: &[u8]) -> impl Iterator<Item = String> {
+        let mut lines = Vec::new();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+            let (line, after) = rest.split_at(end);
+            rest = &after[1..];
+            if self.discarding || self.partial.len() + line.len() > MAX_LINE_LENGTH {
+                tracing::warn!(
+                    max_bytes = MAX_LINE_LENGTH,
+                    "dropped a line that is too long"
+                );
+            } else {
+                self.partial.extend_from_slice(line);
+                let line = String::from_utf8_lossy(&self.partial);
+                let line = line.trim_end_matches('\r');
+                if !line.is_empty() {
+                    lines.push(line.to_owned());
+                }
+            }
+            self.partial.clear();
+            self.discarding = false;
+        }
+        if !self.discarding {
+            if self.partial.len() + rest.len() > MAX_LINE_LENGTH {
+                self.partial.clear();
+                self.discarding = true;
+            } else {
+                self.partial.extend_from_slice(rest);
+            }
+        }
+        lines.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(assembler: &mut LineAssembler, chunk: &str) -> Vec<String> {
+        assembler.push(chunk.as_bytes()).collect()
+    }
+
+    #[test]
+    fn splits_multiple_lines() {
+        let mut assembler = LineAssembler::new();
+        let lines = push(
+            &mut assembler,
+            "PING :tmi.twitch.tv\r\n:tmi.twitch.tv RECONNECT\r\n",
+        );
+        assert_eq!(
+            lines,
+            vec!["PING :tmi.twitch.tv", ":tmi.twitch.tv RECONNECT"]
+        );
+    }
+
+    #[test]
+    fn keeps_incomplete_lines_until_completed() {
+        let mut assembler = LineAssembler::new();
+        assert_eq!(
+            push(
+                &mut assembler,
+                "PING :tmi.twitch.tv\r\n:carkhy!carkhy@carkhy.tmi"
+            ),
+            vec!["PING :tmi.twitch.tv"]
+        );
+        assert!(push(&mut assembler, ".twitch.tv PRIVMSG #captaincallback :Hel").is_empty());
+        assert_eq!(
+            push(&mut assembler, "lo\r\n"),
+            vec![":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Hello"]
+        );
+    }
+
+    #[test]
+    fn splits_between_carriage_return_and_line_feed() {
+        let mut assembler = LineAssembler::new();
+        assert!(push(&mut assembler, "PING :tmi.twitch.tv\r").is_empty());
+        assert_eq!(push(&mut assembler, "\n"), vec!["PING :tmi.twitch.tv"]);
+    }
+
+    #[test]
+    fn skips_empty_lines() {
+        let mut assembler = LineAssembler::new();
+        let lines = push(&mut assembler, "\r\n\r\nPING :tmi.twitch.tv\r\n\r\n");
+        assert_eq!(lines, vec!["PING :tmi.twitch.tv"]);
+    }
+
+    #[test]
+    fn keeps_split_code_points() {
+        let mut assembler = LineAssembler::new();
+        let bytes = "PRIVMSG #c :😀\r\n".as_bytes();
+        let (first, second) = bytes.split_at(bytes.len() - 4);
+        assert_eq!(assembler.push(first).count(), 0);
+        assert_eq!(
+            assembler.push(second).collect::<Vec<_>>(),
+            vec!["PRIVMSG #c :😀"]
+        );
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        let mut assembler = LineAssembler::new();
+        let lines: Vec<String> = assembler
+            .push(b"PRIVMSG #c :\xff\xfe\r\nPING\r\n")
+            .collect();
+        assert_eq!(lines, vec!["PRIVMSG #c :\u{fffd}\u{fffd}", "PING"]);
+    }
+
+    #[test]
+    fn drops_overlong_lines() {
+        let mut assembler = LineAssembler::new();
+        let garbage = vec![b'x'; MAX_LINE_LENGTH / 2];
+        for _ in 0..10 {
+            assert_eq!(assembler.push(&garbage).count(), 0);
+        }
+        assert!(assembler.partial.len() <= MAX_LINE_LENGTH);
+        // the end of the dropped line still has to arrive before lines are accepted again
+        assert_eq!(push(&mut assembler, "xxx\r\nPING\r\n"), vec!["PING"]);
+        let overlong = format!("{}\r\nPING\r\n", "x".repeat(MAX_LINE_LENGTH + 1));
+        assert_eq!(push(&mut assembler, &overlong), vec!["PING"]);
+        let longest = "x".repeat(MAX_LINE_LENGTH);
+        assert_eq!(
+            push(&mut assembler, &format!("{}\n", longest)),
+            vec![longest]
+        );
+    }
+}
@@ -1,7 +1,40 @@
 mod auth;
+mod connection;
 mod connector;
+mod duplicates;
+mod health;
+mod irc_line;
+mod irc_message;
+mod keepalive;
+mod line_assembler;
+pub(crate) mod outgoing;
+mod priority;
+mod rate_limit;
 mod receive;
-mod retry_manager;
-pub(crate) mod send;
+mod recent;
+mod replay;
+pub(super) mod retry_manager;
+mod send;
+mod split;
+pub(super) mod stream;
+#[cfg(test)]
+pub mod testing;
+mod transport;
 
+pub use auth::{required_scopes, AccessTokenDispenser, Identity, SharedTokens, TokenInfo};
+pub use connection::{Connection, EventHandler};
 pub use connector::TwitchChatConnector;
+pub use health::{Health, HealthLimits, Report};
+pub use priority::Priority;
+pub use replay::{ReplaySource, ReplayTiming};
+pub use split::{fit_message, Overflow, MAX_MESSAGE_CHARS};
+
+/// Entry point of the fuzz target in `fuzz/`: the received bytes are split into lines
+/// and parsed like in `receive`, which must never panic.
+#[cfg(fuzzing)]
+pub fn fuzz_receive(data: &[u8]) {
+    let mut assembler = line_assembler::LineAssembler::new();
+    for line in assembler.push(data) {
+        let _ = receive::ReceiveEvent::parse_from_message(&line);
+    }
+}
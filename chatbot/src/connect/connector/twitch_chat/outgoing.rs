@@ -0,0 +1,261 @@
+use crate::connect::error::ConnectorError;
+use std::fmt;
+
+/// A line sent to twitch, including the terminating CRLF.
+/// Content containing CR or LF is rejected, so user provided text can't inject further commands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outgoing(String);
+
+impl Outgoing {
+    pub fn privmsg(channel: &str, text: &str) -> Result<Self, ConnectorError> {
+        Self::line("PRIVMSG", &[&channel_param(channel)], Some(text))
+    }
+
+    /// Threaded reply to the message with the given id.
+    pub fn privmsg_reply(
+        channel: &str,
+        parent_msg_id: &str,
+        text: &str,
+    ) -> Result<Self, ConnectorError> {
+        // tag values would need escaping, message ids never contain such characters
+        if parent_msg_id.is_empty() || parent_msg_id.contains([';', ' ', '\\', '\r', '\n']) {
+            return Err(invalid(format!("reply parent id {:?}", parent_msg_id)));
+        }
+        let line = Self::privmsg(channel, text)?;
+        Ok(Self(format!(
+            "@reply-parent-msg-id={} {}",
+            parent_msg_id, line
+        )))
+    }
+
+    /// Message shown like /me, the text is wrapped in a CTCP ACTION.
+    pub fn action(channel: &str, text: &str) -> Result<Self, ConnectorError> {
+        Self::privmsg(channel, &format!("\u{1}ACTION {}\u{1}", text))
+    }
+
+    pub fn ping(payload: &str) -> Result<Self, ConnectorError> {
+        Self::line("PING", &[], Some(payload))
+    }
+
+    pub fn pong(server: &str) -> Result<Self, ConnectorError> {
+        Self::line("PONG", &[], Some(server))
+    }
+
+    pub fn join(channels: &[&str]) -> Result<Self, ConnectorError> {
+        let channels: Vec<String> = channels
+            .iter()
+            .map(|channel| channel_param(channel))
+            .collect();
+        Self::line("JOIN", &[&channels.join(",")], None)
+    }
+
+    pub fn part(channels: &[&str]) -> Result<Self, ConnectorError> {
+        let channels: Vec<String> = channels
+            .iter()
+            .map(|channel| channel_param(channel))
+            .collect();
+        Self::line("PART", &[&channels.join(",")], None)
+    }
+
+    pub fn cap_req(capabilities: &[&str]) -> Result<Self, ConnectorError> {
+        Self::line("CAP", &["REQ"], Some(&capabilities.join(" ")))
+    }
+
+    pub fn pass(token: &str) -> Result<Self, ConnectorError> {
+        Self::line("PASS", &[&format!("oauth:{}", token)], None)
+    }
+
+    pub fn quit() -> Result<Self, ConnectorError> {
+        Self::line("QUIT", &[], None)
+    }
+
+    pub fn nick(login: &str) -> Result<Self, ConnectorError> {
+        Self::line("NICK", &[login], None)
+    }
+
+    /// Channel of a chat message, other lines are not limited by twitch's message rate.
+    pub fn chat_channel(&self) -> Option<&str> {
+        let line = match self.0.strip_prefix('@') {
+            Some(tagged) => tagged.split_once(' ')?.1,
+            None => &self.0,
+        };
+        let channel = line.strip_prefix("PRIVMSG #")?.split_once(' ')?.0;
+        Some(channel)
+    }
+
+    /// The same line with an invisible character at the end of the text, or without it again,
+    /// so that twitch doesn't take it for a duplicate.
+    pub fn varied(&self) -> Self {
+        let line = self.0.strip_suffix("\r\n").unwrap_or(&self.0);
+        let (text, action_end) = match line.strip_suffix('\u{1}') {
+            Some(action) => (action, "\u{1}"),
+            None => (line, ""),
+        };
+        let text = match text.strip_suffix(INVISIBLE_VARIATION) {
+            Some(original) => original.to_owned(),
+            None => format!("{}{}", text, INVISIBLE_VARIATION),
+        };
+        Self(format!("{}{}\r\n", text, action_end))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // middle parameters can't be empty, contain spaces or start with ':'
+    fn line(
+        command: &str,
+        params: &[&str],
+        trailing: Option<&str>,
+    ) -> Result<Self, ConnectorError> {
+        let mut line = command.to_owned();
+        for param in params {
+            if param.is_empty() || param.starts_with(':') || param.contains(' ') {
+                return Err(invalid(format!("{} parameter {:?}", command, param)));
+            }
+            line.push(' ');
+            line.push_str(param);
+        }
+        if let Some(trailing) = trailing {
+            line.push_str(" :");
+            line.push_str(trailing);
+        }
+        if line.contains(['\r', '\n']) {
+            return Err(invalid(format!("{} contains a line break", command)));
+        }
+        line.push_str("\r\n");
+        Ok(Self(line))
+    }
+}
+
+impl fmt::Display for Outgoing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// a space followed by the zero width tag space, twitch would trim a space alone
+const INVISIBLE_VARIATION: &str = " \u{e0000}";
+
+// channels are given by name, twitch expects a leading '#'
+fn channel_param(channel: &str) -> String {
+    format!("#{}", channel.trim_start_matches('#'))
+}
+
+fn invalid(reason: String) -> ConnectorError {
+    ConnectorError::InvalidOutgoingMessage(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_chat_messages_have_a_chat_channel() {
+        let reply = Outgoing::privmsg_reply("captaincallback", "abc-123", "Hi").unwrap();
+        assert_eq!(reply.chat_channel(), Some("captaincallback"));
+        let action = Outgoing::action("#carkhy", "waves").unwrap();
+        assert_eq!(action.chat_channel(), Some("carkhy"));
+        assert_eq!(
+            Outgoing::pong("tmi.twitch.tv").unwrap().chat_channel(),
+            None
+        );
+        assert_eq!(Outgoing::join(&["carkhy"]).unwrap().chat_channel(), None);
+    }
+
+    #[test]
+    fn variation_alternates() {
+        let line = Outgoing::privmsg("captaincallback", "Drink water!").unwrap();
+        let varied = line.varied();
+        assert_eq!(
+            varied.as_str(),
+            "PRIVMSG #captaincallback :Drink water! \u{e0000}\r\n"
+        );
+        assert_eq!(varied.varied(), line);
+        let action = Outgoing::action("captaincallback", "waves").unwrap();
+        assert_eq!(
+            action.varied().as_str(),
+            "PRIVMSG #captaincallback :\u{1}ACTION waves \u{e0000}\u{1}\r\n"
+        );
+    }
+
+    #[test]
+    fn private_messages_are_terminated() {
+        let line = Outgoing::privmsg("channelname", "Message : with colon").unwrap();
+        assert_eq!(
+            line.as_str(),
+            "PRIVMSG #channelname :Message : with colon\r\n"
+        );
+        let line = Outgoing::privmsg("#channelname", "Message").unwrap();
+        assert_eq!(line.as_str(), "PRIVMSG #channelname :Message\r\n");
+    }
+
+    #[test]
+    fn replies_reference_the_parent() {
+        let line = Outgoing::privmsg_reply("channelname", "b34ccfc7-4977", "yes").unwrap();
+        assert_eq!(
+            line.as_str(),
+            "@reply-parent-msg-id=b34ccfc7-4977 PRIVMSG #channelname :yes\r\n"
+        );
+        assert!(Outgoing::privmsg_reply("channelname", "", "yes").is_err());
+        assert!(Outgoing::privmsg_reply("channelname", "a;b=c", "yes").is_err());
+        assert!(Outgoing::privmsg_reply("channelname", "b34c\r\n", "yes").is_err());
+    }
+
+    #[test]
+    fn action_messages_are_wrapped() {
+        let line = Outgoing::action("channelname", "waves").unwrap();
+        assert_eq!(
+            line.as_str(),
+            "PRIVMSG #channelname :\u{1}ACTION waves\u{1}\r\n"
+        );
+    }
+
+    #[test]
+    fn pong_answers_the_server() {
+        let line = Outgoing::pong("tmi.twitch.tv").unwrap();
+        assert_eq!(line.as_str(), "PONG :tmi.twitch.tv\r\n");
+    }
+
+    #[test]
+    fn multiple_channels_are_joined_at_once() {
+        let line = Outgoing::join(&["channel123"]).unwrap();
+        assert_eq!(line.as_str(), "JOIN #channel123\r\n");
+        let line = Outgoing::join(&["first", "#second"]).unwrap();
+        assert_eq!(line.as_str(), "JOIN #first,#second\r\n");
+    }
+
+    #[test]
+    fn capabilities_are_requested_together() {
+        let line = Outgoing::cap_req(&["twitch.tv/membership", "twitch.tv/tags"]).unwrap();
+        assert_eq!(
+            line.as_str(),
+            "CAP REQ :twitch.tv/membership twitch.tv/tags\r\n"
+        );
+    }
+
+    #[test]
+    fn login_lines_are_terminated() {
+        let line = Outgoing::pass("admin123").unwrap();
+        assert_eq!(line.as_str(), "PASS oauth:admin123\r\n");
+        let line = Outgoing::nick("user123").unwrap();
+        assert_eq!(line.as_str(), "NICK user123\r\n");
+    }
+
+    #[test]
+    fn line_breaks_are_rejected() {
+        assert!(Outgoing::privmsg("channel", "hi\r\nPRIVMSG #other :spam").is_err());
+        assert!(Outgoing::privmsg("channel", "hi\n").is_err());
+        assert!(Outgoing::action("channel", "waves\r").is_err());
+        assert!(Outgoing::pass("token\r\nJOIN #other").is_err());
+        assert!(Outgoing::join(&["chan\nnel"]).is_err());
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        assert!(Outgoing::nick("").is_err());
+        assert!(Outgoing::nick("user name").is_err());
+        assert!(Outgoing::nick(":user").is_err());
+        assert!(Outgoing::join(&[]).is_err());
+    }
+}
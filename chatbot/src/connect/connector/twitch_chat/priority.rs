@@ -0,0 +1,190 @@
+use super::outgoing::Outgoing;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Which lines go first while twitch's rate limit holds back chat messages, highest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    // JOIN, PART and the like, never held back
+    Control,
+    // answers to moderators
+    Moderation,
+    Response,
+    // repeating messages
+    Timer,
+}
+
+const LEVELS: usize = 4;
+
+impl Priority {
+    const ALL: [Priority; LEVELS] = [
+        Priority::Control,
+        Priority::Moderation,
+        Priority::Response,
+        Priority::Timer,
+    ];
+
+    fn level(self) -> usize {
+        self as usize
+    }
+
+    // answers minutes late are worse than none
+    fn expires(self) -> bool {
+        matches!(self, Priority::Response | Priority::Timer)
+    }
+}
+
+/// How many lines of a priority were sent and how many were dropped for being too old.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SendCount {
+    pub sent: u64,
+    pub dropped: u64,
+}
+
+/// Shared between the send task counting and the connector reporting.
+#[derive(Debug, Clone, Default)]
+pub struct SendCounters(Arc<Mutex<[SendCount; LEVELS]>>);
+
+impl SendCounters {
+    pub fn get(&self, priority: Priority) -> SendCount {
+        self.0.lock().unwrap()[priority.level()]
+    }
+
+    pub fn sent(&self, priority: Priority) {
+        self.0.lock().unwrap()[priority.level()].sent += 1;
+    }
+
+    fn dropped(&self, priority: Priority) {
+        self.0.lock().unwrap()[priority.level()].dropped += 1;
+    }
+}
+
+/// Lines waiting to be sent, first in first out within each priority.
+#[derive(Debug)]
+pub struct PriorityQueue {
+    levels: [VecDeque<(Outgoing, Instant)>; LEVELS],
+    ttl: Duration,
+    counters: SendCounters,
+}
+
+impl PriorityQueue {
+    pub fn new(ttl: Duration, counters: SendCounters) -> Self {
+        Self {
+            levels: Default::default(),
+            ttl,
+            counters,
+        }
+    }
+
+    /// Lines already waiting are dropped by the new ttl.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    pub fn push(&mut self, line: Outgoing, priority: Priority, now: Instant) {
+        self.levels[priority.level()].push_back((line, now));
+    }
+
+    /// Drops the lines that waited longer than the ttl and tells how many.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let mut expired = 0;
+        for priority in Priority::ALL
+            .into_iter()
+            .filter(|priority| priority.expires())
+        {
+            let level = &mut self.levels[priority.level()];
+            while let Some((line, queued)) = level.front() {
+                if now.saturating_duration_since(*queued) <= self.ttl {
+                    break;
+                }
+                tracing::warn!(
+                    ?priority,
+                    queued_s = now.saturating_duration_since(*queued).as_secs(),
+                    line = line.as_str().trim_end(),
+                    "dropping a line that waited too long"
+                );
+                level.pop_front();
+                self.counters.dropped(priority);
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    pub fn front(&self) -> Option<&Outgoing> {
+        self.levels
+            .iter()
+            .find_map(|level| level.front().map(|(line, _)| line))
+    }
+
+    pub fn pop(&mut self) -> Option<(Outgoing, Priority)> {
+        Priority::ALL.into_iter().find_map(|priority| {
+            let (line, _) = self.levels[priority.level()].pop_front()?;
+            Some((line, priority))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Outgoing {
+        Outgoing::privmsg("captaincallback", text).unwrap()
+    }
+
+    #[test]
+    fn timer_flood_does_not_starve_moderation() {
+        let now = Instant::now();
+        let mut queue = PriorityQueue::new(Duration::from_secs(60), SendCounters::default());
+        for i in 0..100 {
+            queue.push(message(&format!("timer {}", i)), Priority::Timer, now);
+        }
+        queue.push(message("response"), Priority::Response, now);
+        queue.push(message("first timeout"), Priority::Moderation, now);
+        queue.push(message("second timeout"), Priority::Moderation, now);
+        assert_eq!(queue.front(), Some(&message("first timeout")));
+        let order: Vec<Outgoing> = std::iter::from_fn(|| queue.pop())
+            .map(|(line, _)| line)
+            .take(4)
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                message("first timeout"),
+                message("second timeout"),
+                message("response"),
+                message("timer 0")
+            ]
+        );
+    }
+
+    #[test]
+    fn old_low_priority_lines_are_dropped() {
+        let start = Instant::now();
+        let counters = SendCounters::default();
+        let mut queue = PriorityQueue::new(Duration::from_secs(60), counters.clone());
+        queue.push(message("old timer"), Priority::Timer, start);
+        queue.push(message("timeout"), Priority::Moderation, start);
+        let later = start + Duration::from_secs(45);
+        queue.push(message("new timer"), Priority::Timer, later);
+        assert_eq!(queue.expire(later), 0);
+        assert_eq!(queue.expire(start + Duration::from_secs(61)), 1);
+        assert_eq!(
+            queue.pop(),
+            Some((message("timeout"), Priority::Moderation))
+        );
+        assert_eq!(queue.pop(), Some((message("new timer"), Priority::Timer)));
+        assert_eq!(
+            counters.get(Priority::Timer),
+            SendCount {
+                sent: 0,
+                dropped: 1
+            }
+        );
+        assert_eq!(counters.get(Priority::Moderation), SendCount::default());
+    }
+}
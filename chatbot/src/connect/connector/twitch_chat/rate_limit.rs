@@ -0,0 +1,232 @@
+use crate::connect::{Badge, UserState};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Counts what was sent during the last `window`, e.g. twitch allows 20 joins per 10 seconds.
+#[derive(Debug)]
+pub struct SlidingWindow {
+    limit: usize,
+    window: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl SlidingWindow {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            sent: VecDeque::with_capacity(limit),
+        }
+    }
+
+    /// How many more may be sent right now.
+    pub fn available(&mut self, now: Instant) -> usize {
+        while let Some(&oldest) = self.sent.front() {
+            if now.saturating_duration_since(oldest) < self.window {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        self.limit - self.sent.len()
+    }
+
+    /// How long to wait until the next one may be sent, zero if it may be sent right now.
+    pub fn delay(&mut self, now: Instant) -> Duration {
+        if self.available(now) > 0 {
+            return Duration::ZERO;
+        }
+        self.sent
+            .front()
+            .map(|&oldest| (oldest + self.window).saturating_duration_since(now))
+            .unwrap_or_default()
+    }
+
+    /// Only record what `available` allowed.
+    pub fn record(&mut self, now: Instant, count: usize) {
+        self.sent.extend(std::iter::repeat_n(now, count));
+    }
+}
+
+/// Tokens refill one by one, `capacity` of them per `period`, and a full bucket allows a burst.
+/// Instead of counting tokens the bucket remembers when it is full again.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: u32,
+    period: Duration,
+    full_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, period: Duration, now: Instant) -> Self {
+        Self {
+            capacity,
+            period,
+            full_at: now,
+        }
+    }
+
+    /// Tokens taken so far stay taken, a bucket with less capacity may be empty right away.
+    pub fn set_capacity(&mut self, capacity: u32, now: Instant) {
+        if capacity == self.capacity {
+            return;
+        }
+        let missing = self.full_at.saturating_duration_since(now) * self.capacity / capacity;
+        self.full_at = now + missing.min(self.period);
+        self.capacity = capacity;
+    }
+
+    /// How long to wait until a token is available, zero if one is available right now.
+    pub fn delay(&self, now: Instant) -> Duration {
+        let missing = self.full_at.saturating_duration_since(now);
+        missing.saturating_sub(self.period - self.interval())
+    }
+
+    /// Only take a token after `delay` allowed it.
+    pub fn take(&mut self, now: Instant) {
+        self.full_at = self.full_at.max(now) + self.interval();
+    }
+
+    // time for a single token to refill
+    fn interval(&self) -> Duration {
+        self.period / self.capacity
+    }
+}
+
+// twitch allows 20 chat messages per 30 seconds, 100 in channels where the bot is moderator
+pub const MESSAGE_LIMIT: u32 = 20;
+pub const MODERATOR_MESSAGE_LIMIT: u32 = 100;
+pub const MESSAGE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Channels where the bot is moderator or broadcaster, learned from USERSTATE.
+#[derive(Debug, Clone, Default)]
+pub struct ModeratedChannels(Arc<Mutex<HashSet<String>>>);
+
+impl ModeratedChannels {
+    pub fn update(&self, user_state: &UserState) {
+        let channel = match &user_state.channel {
+            Some(channel) => channel,
+            None => return,
+        };
+        let moderator = user_state
+            .badges
+            .iter()
+            .any(|badge| matches!(badge, Badge::Moderator | Badge::Broadcaster));
+        let mut moderated = self.0.lock().unwrap();
+        if moderator {
+            moderated.insert(channel.to_owned());
+        } else {
+            moderated.remove(channel);
+        }
+    }
+
+    pub fn contains(&self, channel: &str) -> bool {
+        self.0.lock().unwrap().contains(channel)
+    }
+}
+
+/// Limits chat messages to what twitch allows in the channel they are sent to.
+#[derive(Debug)]
+pub struct MessageLimiter {
+    bucket: TokenBucket,
+    moderated: ModeratedChannels,
+}
+
+impl MessageLimiter {
+    pub fn new(moderated: ModeratedChannels, now: Instant) -> Self {
+        Self {
+            bucket: TokenBucket::new(MESSAGE_LIMIT, MESSAGE_PERIOD, now),
+            moderated,
+        }
+    }
+
+    /// How long a message to the channel has to wait, zero if it may be sent right now.
+    pub fn delay(&mut self, channel: &str, now: Instant) -> Duration {
+        let capacity = if self.moderated.contains(channel) {
+            MODERATOR_MESSAGE_LIMIT
+        } else {
+            MESSAGE_LIMIT
+        };
+        self.bucket.set_capacity(capacity, now);
+        self.bucket.delay(now)
+    }
+
+    pub fn record(&mut self, now: Instant) {
+        self.bucket.take(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sliding_window_frees_up_as_time_passes() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut window = SlidingWindow::new(20, Duration::from_secs(10));
+        assert_eq!(window.available(at(0)), 20);
+        window.record(at(0), 15);
+        window.record(at(4000), 5);
+        assert_eq!(window.available(at(5000)), 0);
+        assert_eq!(window.delay(at(5000)), Duration::from_secs(5));
+        assert_eq!(window.available(at(10000)), 15);
+        assert_eq!(window.delay(at(10000)), Duration::ZERO);
+        window.record(at(10000), 15);
+        assert_eq!(window.delay(at(12000)), Duration::from_secs(2));
+        assert_eq!(window.available(at(20000)), 20);
+    }
+
+    #[test]
+    fn token_bucket_refills_over_the_period() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut bucket = TokenBucket::new(20, Duration::from_secs(30), at(0));
+        for _ in 0..20 {
+            assert_eq!(bucket.delay(at(0)), Duration::ZERO);
+            bucket.take(at(0));
+        }
+        // a token every 1.5 seconds
+        assert_eq!(bucket.delay(at(0)), Duration::from_millis(1500));
+        assert_eq!(bucket.delay(at(1000)), Duration::from_millis(500));
+        assert_eq!(bucket.delay(at(1500)), Duration::ZERO);
+        bucket.take(at(1500));
+        // never more than the capacity, however long the bot was quiet
+        let mut taken = 0;
+        while bucket.delay(at(600_000)).is_zero() {
+            bucket.take(at(600_000));
+            taken += 1;
+        }
+        assert_eq!(taken, 20);
+    }
+
+    #[test]
+    fn moderators_may_send_more() {
+        let start = Instant::now();
+        let moderated = ModeratedChannels::default();
+        let mut limiter = MessageLimiter::new(moderated.clone(), start);
+        let mut user_state = UserState {
+            channel: Some("captaincallback".to_owned()),
+            badges: vec![Badge::Moderator],
+            ..Default::default()
+        };
+        moderated.update(&user_state);
+        let mut sent = 0;
+        while limiter.delay("captaincallback", start).is_zero() {
+            limiter.record(start);
+            sent += 1;
+        }
+        assert_eq!(sent, MODERATOR_MESSAGE_LIMIT);
+        // refills at the moderator rate, 100 messages per 30 seconds
+        let later = start + Duration::from_millis(300);
+        assert_eq!(limiter.delay("captaincallback", later), Duration::ZERO);
+        // the messages sent as moderator leave nothing for other channels
+        assert_eq!(limiter.delay("carkhy", later), Duration::from_millis(1500));
+
+        user_state.badges.clear();
+        moderated.update(&user_state);
+        assert!(!moderated.contains("captaincallback"));
+    }
+}
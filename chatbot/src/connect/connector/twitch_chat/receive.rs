@@ -1,24 +1,30 @@
-use crate::connect::error::ConnectorError;
-use crate::connect::{types::CommandType, Badge, ChatBotEvent, Command, TextMessage, UserInfo};
-use std::collections::{HashMap, HashSet};
-use std::net::TcpStream;
+use super::irc_message::IrcMessage;
+use super::stream::ChatStream;
+use crate::connect::error::{ConnectorError, ParseError};
+use crate::connect::{
+    types::CommandType, Badge, ChatBotEvent, ClearChat, ClearMessage, Color, Command, EmoteSpan,
+    Notice, NoticeKind, PaidMessage, ReplyParent, RoomState, SubTier, Tags, TextMessage, UserInfo,
+    UserLevel, UserNotice, UserNoticeKind, UserState, Whisper,
+};
+use crate::prometheus;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use websocket::WebSocketError;
 use websocket::{receiver::Reader, OwnedMessage};
 
-pub fn receive(receiver: &mut Reader<TcpStream>) -> Result<Vec<ReceiveEvent>, ConnectorError> {
+// the lines in a websocket message are split and parsed by the caller
+pub fn receive_chunk(receiver: &mut Reader<ChatStream>) -> Result<Vec<u8>, ConnectorError> {
     loop {
         match receiver.recv_message() {
             Err(WebSocketError::NoDataAvailable) => continue,
             response => match response {
                 Ok(owned_message) => match owned_message {
                     OwnedMessage::Text(text) => {
-                        println!("New websocket message: {}", text);
-                        let events = text
-                            .lines()
-                            .filter_map(ReceiveEvent::parse_from_message)
-                            .collect();
-                        return Ok(events);
+                        tracing::trace!(%text, "websocket message");
+                        return Ok(text.into_bytes());
                     }
+                    OwnedMessage::Binary(bytes) => return Ok(bytes),
                     _ => continue,
                 },
                 Err(err) => {
@@ -32,218 +38,748 @@ pub fn receive(receiver: &mut Reader<TcpStream>) -> Result<Vec<ReceiveEvent>, Co
     }
 }
 
-#[derive(Debug, PartialEq)]
+// twitch sends more than the bot understands (e.g. CAP ACK), those lines are
+// only worth a debug message while malformed lines are always reported
+pub fn parse_line(line: &str) -> Option<ReceiveEvent> {
+    match ReceiveEvent::parse_from_message(line) {
+        Ok(event) => Some(event),
+        Err(ParseError::UnsupportedCommand(command)) => {
+            tracing::debug!(%command, "ignoring an unsupported command");
+            None
+        }
+        Err(error) => {
+            tracing::warn!(line, %error, "could not parse");
+            prometheus::PARSE_ERRORS.inc(&[error.kind()]);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConnectorEvent {
-    Ping,
+    // keepalive, the server is echoed in the PONG. Empty when the PING had no payload
+    Ping { server: String },
+    // twitch is about to restart the server, the connection has to be reestablished
+    Reconnect,
+    // login succeeded (001), login is the name twitch assigned to the bot
+    Welcome { login: String },
+    // any other numeric reply, the trailing parameter is the last of params
+    Numeric { code: u16, params: Vec<String> },
+    // answers to CAP REQ, twitch grants or refuses all requested capabilities of one line
+    CapAck(Vec<String>),
+    CapNak(Vec<String>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
 pub enum ReceiveEvent {
     ChatBotEvent(ChatBotEvent),
     ConnectorEvent(ConnectorEvent),
 }
 
 impl ReceiveEvent {
-    fn parse_command_kind(command_name: &str) -> CommandType {
-        match command_name {
-            "help" => CommandType::Help,
-            "info" => CommandType::Info,
-            "newcommand" => CommandType::NewCommand,
-            "removecommand" => CommandType::RemoveCommand,
-            "slap" => CommandType::Slap,
-            "discord" => CommandType::Discord,
-            "newrepeating" => CommandType::NewRepeating,
-            "removerepeating" => CommandType::RemoveRepeating,
-            _ => CommandType::Dynamic(command_name.to_owned()),
-        }
+    fn parse_command_from_message(message: &str) -> Result<(CommandType, Vec<String>), ParseError> {
+        let mut words = message.split(' ');
+        let name = words
+            .next()
+            .and_then(|word| word.strip_prefix('!'))
+            .filter(|name| !name.is_empty())
+            .ok_or(ParseError::MissingText)?;
+        Ok((
+            CommandType::from_name(name),
+            words.map(String::from).collect(),
+        ))
     }
 
-    fn parse_command_from_message(message: &str) -> Option<(CommandType, Vec<String>)> {
-        if message == "!" {
-            return None;
-        }
-        let mut words = message.split(' ');
-        words.next().map(|name| {
-            (
-                ReceiveEvent::parse_command_kind(&name[1..]),
-                words.map(String::from).collect(),
-            )
-        })
+    pub fn parse_from_message(message: &str) -> Result<Self, ParseError> {
+        IrcMessage::parse(message)?.try_into()
     }
 
-    pub fn parse_from_message(message: &str) -> Option<Self> {
-        enum ParsingState {
-            Start,
-            Tags,
-            UserName,
-            AdditionalUserInfo,
-            MessageToken,
-            Channel,
-            MessageBody,
+    fn parse_private_message(irc_message: IrcMessage) -> Result<ChatBotEvent, ParseError> {
+        let user_name = irc_message.sender().ok_or(ParseError::MissingPrefix)?;
+        let channel = channel(&irc_message)?;
+        let (text, is_action) = unwrap_action(irc_message.trailing.unwrap_or_default());
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ParseError::MissingText);
         }
-        use ParsingState::*;
-
-        let mut state = Start;
-        let mut user_name = &message[0..0];
-        let mut marker = 0;
-        let mut tags_map = HashMap::<String, String>::new();
-
-        if message.starts_with("PING:") {
-            return Some(ReceiveEvent::ConnectorEvent(ConnectorEvent::Ping));
+        let emotes = irc_message
+            .tag("emotes")
+            .map(|emotes| parse_emotes(&emotes, text))
+            .unwrap_or_default();
+        // a malformed bits tag only loses the bits, not the message
+        let bits = irc_message.tag("bits").and_then(|bits| bits.parse().ok());
+        let user = get_user_info(user_name, &irc_message.tags);
+        let text_message = TextMessage {
+            level: UserLevel::from_badges(&user.badges),
+            text: text.to_owned(),
+            user,
+            channel: channel.to_owned(),
+            tags: irc_message.tags.into(),
+            emotes,
+            bits,
+            is_action,
+            reply_to: parse_reply_parent(&irc_message.tags),
+            timestamp: parse_timestamp(&irc_message.tags),
+            message_id: irc_message
+                .tag("id")
+                .filter(|id| !id.is_empty())
+                .map(String::from),
+            first_msg: irc_message.tag("first-msg").as_deref() == Some("1"),
+            returning_chatter: irc_message.tag("returning-chatter").as_deref() == Some("1"),
+            paid: parse_paid_message(&irc_message.tags),
+        };
+        // twitch starts replies with a mention of the parent's author: "@Carkhy !quote"
+        let command_text = match text_message.reply_to {
+            Some(_) => strip_mention(&text_message.text),
+            None => &text_message.text,
+        };
+        if command_text.starts_with('!') {
+            let (command_kind, command_options) =
+                ReceiveEvent::parse_command_from_message(command_text)?;
+            Ok(ChatBotEvent::Command(Command {
+                kind: command_kind,
+                options: command_options,
+                message: text_message,
+            }))
+        } else {
+            Ok(ChatBotEvent::TextMessage(text_message))
         }
+    }
+}
 
-        for (i, codepoint) in message.char_indices() {
-            match state {
-                Start => match codepoint {
-                    '@' => {
-                        state = Tags;
-                    }
-                    ':' => {
-                        state = UserName;
-                        marker = 1;
-                    }
-                    _ => return None,
-                },
-                Tags => {
-                    if codepoint == ' ' {
-                        state = UserName;
-                        tags_map = parse_tags(&message[1..i]);
-                        marker = i + 2;
-                    }
-                }
-                UserName => match codepoint {
-                    ' ' => return None,
-                    '!' => {
-                        user_name = &message[marker..i];
-                        state = AdditionalUserInfo;
-                    }
-                    _ => (),
-                },
-                AdditionalUserInfo => {
-                    if codepoint == ' ' {
-                        marker = i + 1;
-                        state = MessageToken
-                    }
-                }
-                MessageToken => {
-                    if codepoint == ' ' {
-                        let token = &message[marker..i];
-                        match token {
-                            "PRIVMSG" => {
-                                state = Channel;
-                            }
-                            "JOIN" => {
-                                return Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Join(
-                                    user_name.to_string(),
-                                )))
-                            }
-                            "PART" => {
-                                return Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Part(
-                                    user_name.to_string(),
-                                )))
-                            }
-                            _ => return None,
-                        };
-                    }
-                }
-                Channel => {
-                    if codepoint == ':' {
-                        state = MessageBody;
-                    }
-                }
-                MessageBody => {
-                    let badges = get_badges(tags_map);
-                    let user_info = UserInfo {
-                        name: user_name.to_owned(),
-                        badges,
-                    };
-                    let user_message = message[i..].trim();
-                    if codepoint == '!' {
-                        let (command_kind, command_options) =
-                            ReceiveEvent::parse_command_from_message(user_message)?;
-                        return Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-                            kind: command_kind,
-                            options: command_options,
-                            user: user_info,
-                        })));
-                    } else {
-                        return Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
-                            TextMessage {
-                                text: message[i..].trim().to_owned(),
-                                user: user_info,
-                            },
-                        )));
-                    }
-                }
+/// Copies what the bot needs out of the borrowed line.
+impl<'a> TryFrom<IrcMessage<'a>> for ReceiveEvent {
+    type Error = ParseError;
+
+    fn try_from(irc_message: IrcMessage<'a>) -> Result<Self, Self::Error> {
+        let event = match irc_message.command {
+            // PING :tmi.twitch.tv, but also PING tmi.twitch.tv or a bare PING
+            "PING" => {
+                return Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::Ping {
+                    server: irc_message
+                        .trailing
+                        .or_else(|| irc_message.params.first().copied())
+                        .unwrap_or_default()
+                        .to_owned(),
+                }))
+            }
+            "RECONNECT" => return Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::Reconnect)),
+            "001" => {
+                return Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::Welcome {
+                    login: irc_message
+                        .params
+                        .first()
+                        .ok_or(ParseError::MissingParameter { index: 0 })?
+                        .to_string(),
+                }))
             }
+            // :tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands
+            "CAP" => {
+                let capabilities = irc_message
+                    .trailing
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect();
+                return match irc_message.params.get(1) {
+                    Some(&"ACK") => Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::CapAck(
+                        capabilities,
+                    ))),
+                    Some(&"NAK") => Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::CapNak(
+                        capabilities,
+                    ))),
+                    Some(_) => Err(ParseError::UnsupportedCommand("CAP".to_owned())),
+                    None => Err(ParseError::MissingParameter { index: 1 }),
+                };
+            }
+            "PRIVMSG" => ReceiveEvent::parse_private_message(irc_message)?,
+            "USERNOTICE" => ChatBotEvent::UserNotice(parse_user_notice(irc_message)?),
+            "CLEARCHAT" => ChatBotEvent::ClearChat(parse_clear_chat(irc_message)?),
+            "CLEARMSG" => ChatBotEvent::ClearMessage(parse_clear_message(irc_message)?),
+            "ROOMSTATE" => ChatBotEvent::RoomState(parse_room_state(irc_message)?),
+            "USERSTATE" => ChatBotEvent::UserState(UserState {
+                channel: Some(channel(&irc_message)?.to_owned()),
+                ..parse_user_state(&irc_message.tags)
+            }),
+            "GLOBALUSERSTATE" => ChatBotEvent::GlobalUserState(parse_user_state(&irc_message.tags)),
+            "WHISPER" => ChatBotEvent::Whisper(parse_whisper(irc_message)?),
+            "NOTICE" => ChatBotEvent::Notice(parse_notice(irc_message)?),
+            "HOSTTARGET" => parse_host_target(irc_message)?,
+            // :bot.tmi.twitch.tv 353 bot = #channel :user1 user2 user3
+            "353" => ChatBotEvent::Names {
+                channel: irc_message
+                    .params
+                    .get(2)
+                    .ok_or(ParseError::MissingChannel)?
+                    .trim_start_matches('#')
+                    .to_owned(),
+                users: irc_message
+                    .trailing
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+            },
+            // :bot.tmi.twitch.tv 366 bot #channel :End of /NAMES list
+            "366" => ChatBotEvent::EndOfNames {
+                channel: irc_message
+                    .params
+                    .get(1)
+                    .ok_or(ParseError::MissingChannel)?
+                    .trim_start_matches('#')
+                    .to_owned(),
+            },
+            "JOIN" => ChatBotEvent::Join {
+                user: irc_message
+                    .nick()
+                    .ok_or(ParseError::MissingPrefix)?
+                    .to_owned(),
+                channel: channel(&irc_message)?.to_owned(),
+            },
+            "PART" => ChatBotEvent::Part {
+                user: irc_message
+                    .nick()
+                    .ok_or(ParseError::MissingPrefix)?
+                    .to_owned(),
+                channel: channel(&irc_message)?.to_owned(),
+            },
+            code if code.len() == 3 && code.bytes().all(|byte| byte.is_ascii_digit()) => {
+                return Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::Numeric {
+                    code: code
+                        .parse()
+                        .map_err(|_| ParseError::UnsupportedCommand(code.to_owned()))?,
+                    params: irc_message
+                        .params
+                        .iter()
+                        .copied()
+                        .chain(irc_message.trailing)
+                        .map(String::from)
+                        .collect(),
+                }))
+            }
+            other => return Err(ParseError::UnsupportedCommand(other.to_owned())),
+        };
+        Ok(ReceiveEvent::ChatBotEvent(event))
+    }
+}
+
+fn channel<'a>(irc_message: &IrcMessage<'a>) -> Result<&'a str, ParseError> {
+    irc_message.channel().ok_or(ParseError::MissingChannel)
+}
+
+fn required_tag<'a>(irc_message: &IrcMessage<'a>, key: &str) -> Result<Cow<'a, str>, ParseError> {
+    irc_message
+        .tag(key)
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| ParseError::MissingTag(key.to_owned()))
+}
+
+// /me messages are sent as CTCP ACTION: "\u{1}ACTION waves\u{1}", an empty one has no space
+fn unwrap_action(text: &str) -> (&str, bool) {
+    match text.strip_prefix("\u{1}ACTION") {
+        Some(action) if action.is_empty() || action.starts_with([' ', '\u{1}']) => {
+            (action.strip_suffix('\u{1}').unwrap_or(action), true)
         }
-        None
+        _ => (text, false),
     }
 }
 
-fn parse_tags(tags_string: &str) -> HashMap<String, String> {
-    tags_string
-        .split(';')
-        .map(|key_val_pair| {
-            let mut key_val_split = key_val_pair.split('=');
-            (
-                key_val_split.next().unwrap_or_default().to_owned(),
-                key_val_split.next().unwrap_or_default().to_owned(),
-            )
-        })
-        .collect()
+fn get_user_info(user_name: &str, tags: &Tags) -> UserInfo {
+    UserInfo {
+        name: user_name.to_owned(),
+        badges: get_badges(tags),
+        display_name: tags
+            .get("display-name")
+            .filter(|display_name| !display_name.is_empty())
+            .map(|display_name| display_name.to_string()),
+        color: tags.get("color").and_then(|color| parse_color(&color)),
+    }
+}
+
+// @badge-info=;badges=;display-name=Carkhy;login=carkhy;msg-id=resub;msg-param-cumulative-months=6;
+//     msg-param-streak-months=2;msg-param-sub-plan=1000;system-msg=Carkhy\ssubscribed... :tmi.twitch.tv USERNOTICE #channel :Great stream!
+// the trailing message is only sent when the user entered one, missing params fall back to defaults
+fn parse_user_notice(irc_message: IrcMessage) -> Result<UserNotice, ParseError> {
+    let channel = channel(&irc_message)?;
+    let login = required_tag(&irc_message, "login")?;
+    let optional_numeric_param = |name| irc_message.tag(name).and_then(|value| value.parse().ok());
+    let numeric_param = |name| optional_numeric_param(name).unwrap_or(0);
+    let tier = irc_message
+        .tag("msg-param-sub-plan")
+        .and_then(|plan| parse_sub_plan(&plan))
+        .unwrap_or_default();
+    // a streak of 0 months is sent when the user didn't share it
+    let streak_months = optional_numeric_param("msg-param-streak-months")
+        .filter(|months| *months > 0)
+        .filter(|_| irc_message.tag("msg-param-should-share-streak").as_deref() != Some("0"));
+    let kind = match irc_message.tag("msg-id").unwrap_or_default().as_ref() {
+        "sub" => UserNoticeKind::Sub {
+            tier,
+            cumulative_months: optional_numeric_param("msg-param-cumulative-months").unwrap_or(1),
+            streak_months,
+        },
+        "resub" => UserNoticeKind::Resub {
+            tier,
+            cumulative_months: numeric_param("msg-param-cumulative-months"),
+            streak_months,
+        },
+        "subgift" => UserNoticeKind::SubGift {
+            recipient: irc_message
+                .tag("msg-param-recipient-user-name")
+                .unwrap_or_default()
+                .into_owned(),
+        },
+        "submysterygift" => UserNoticeKind::SubMysteryGift {
+            count: numeric_param("msg-param-mass-gift-count"),
+        },
+        "raid" => UserNoticeKind::Raid {
+            from: irc_message
+                .tag("msg-param-displayName")
+                .filter(|display_name| !display_name.is_empty())
+                .unwrap_or_else(|| login.clone())
+                .into_owned(),
+            viewers: numeric_param("msg-param-viewerCount"),
+        },
+        other => UserNoticeKind::Unknown(other.to_owned()),
+    };
+    Ok(UserNotice {
+        kind,
+        user: get_user_info(&login, &irc_message.tags),
+        channel: channel.to_owned(),
+        system_message: irc_message
+            .tag("system-msg")
+            .unwrap_or_default()
+            .into_owned(),
+        text: irc_message
+            .trailing
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(String::from),
+    })
+}
+
+// @ban-duration=600;room-id=120630112;target-user-id=70346833 :tmi.twitch.tv CLEARCHAT #channel :carkhy
+// permanent bans have no ban-duration, clearing the whole chat has no target user
+fn parse_clear_chat(irc_message: IrcMessage) -> Result<ClearChat, ParseError> {
+    Ok(ClearChat {
+        channel: channel(&irc_message)?.to_owned(),
+        target_user: irc_message
+            .trailing
+            .map(str::trim)
+            .filter(|user| !user.is_empty())
+            .map(String::from),
+        duration: irc_message
+            .tag("ban-duration")
+            .and_then(|seconds| seconds.parse().ok())
+            .map(Duration::from_secs),
+    })
+}
+
+// @login=carkhy;room-id=;target-msg-id=c5d6e2a1-... :tmi.twitch.tv CLEARMSG #channel :the deleted text
+// without target-msg-id the deletion can't be matched to a message, so the line is rejected
+fn parse_clear_message(irc_message: IrcMessage) -> Result<ClearMessage, ParseError> {
+    Ok(ClearMessage {
+        login: irc_message
+            .tag("login")
+            .ok_or_else(|| ParseError::MissingTag("login".to_owned()))?
+            .into_owned(),
+        channel: channel(&irc_message)?.to_owned(),
+        target_message_id: required_tag(&irc_message, "target-msg-id")?.into_owned(),
+        text: irc_message.trailing.unwrap_or_default().trim().to_owned(),
+    })
+}
+
+// @emote-only=0;followers-only=-1;r9k=0;room-id=120630112;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #channel
+fn parse_room_state(irc_message: IrcMessage) -> Result<RoomState, ParseError> {
+    let flag = |name| irc_message.tag(name).map(|value| value == "1");
+    let number = |name| irc_message.tag(name).and_then(|value| value.parse().ok());
+    Ok(RoomState {
+        channel: channel(&irc_message)?.to_owned(),
+        emote_only: flag("emote-only"),
+        followers_only: number("followers-only"),
+        r9k: flag("r9k"),
+        slow: irc_message.tag("slow").and_then(|value| value.parse().ok()),
+        subs_only: flag("subs-only"),
+    })
+}
+
+// @badge-info=;badges=moderator/1;color=;display-name=TwitchBotanist;emote-sets=0,300374282;mod=1;subscriber=0;user-type=mod
+//     :tmi.twitch.tv USERSTATE #channel
+fn parse_user_state(tags: &Tags) -> UserState {
+    UserState {
+        channel: None,
+        badges: get_badges(tags),
+        display_name: tags
+            .get("display-name")
+            .filter(|display_name| !display_name.is_empty())
+            .map(|display_name| display_name.to_string()),
+        color: tags.get("color").and_then(|color| parse_color(&color)),
+        emote_sets: tags
+            .get("emote-sets")
+            .map(|emote_sets| {
+                emote_sets
+                    .split(',')
+                    .filter(|emote_set| !emote_set.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+// @badges=;color=;display-name=User;... :user!user@user.tmi.twitch.tv WHISPER botname :hello
+// the first parameter is the login of the recipient, not a channel
+fn parse_whisper(irc_message: IrcMessage) -> Result<Whisper, ParseError> {
+    let user_name = irc_message.sender().ok_or(ParseError::MissingPrefix)?;
+    let recipient = irc_message
+        .params
+        .first()
+        .ok_or(ParseError::MissingParameter { index: 0 })?;
+    let text = irc_message.trailing.unwrap_or_default().trim();
+    if text.is_empty() {
+        return Err(ParseError::MissingText);
+    }
+    Ok(Whisper {
+        text: text.to_owned(),
+        user: get_user_info(user_name, &irc_message.tags),
+        recipient: recipient.to_string(),
+        tags: irc_message.tags.into(),
+    })
 }
 
-fn get_badges(tags: HashMap<String, String>) -> HashSet<Badge> {
-    if let Some(badges) = tags.get("badges") {
-        if badges.is_empty() {
-            return HashSet::default();
-        }
-        badges
-            .split(',')
-            .map(|s| {
-                // TODO: use filter_map here to avoid potential panics
-                let mut splt = s.split('/');
-                Badge {
-                    name: splt.next().map(String::from).unwrap(),
-                    level: splt.next().and_then(|s| s.parse().ok()).unwrap_or(0),
+// :tmi.twitch.tv HOSTTARGET #hosting_channel :target_channel 5
+// the target is '-' when hosting ended, the viewer count may be missing
+fn parse_host_target(irc_message: IrcMessage) -> Result<ChatBotEvent, ParseError> {
+    let channel = channel(&irc_message)?;
+    let mut words = irc_message.trailing.unwrap_or_default().split_whitespace();
+    let target = words.next().ok_or(ParseError::MissingText)?;
+    Ok(ChatBotEvent::HostTarget {
+        channel: channel.to_owned(),
+        target: Some(target)
+            .filter(|target| *target != "-")
+            .map(String::from),
+        viewers: words
+            .next()
+            .and_then(|viewers| viewers.parse().ok())
+            .unwrap_or(0),
+    })
+}
+
+// @msg-id=msg_ratelimit :tmi.twitch.tv NOTICE #channel :Your message was not sent because you are sending messages too quickly.
+// before login notices come without tags and are sent to '*': :tmi.twitch.tv NOTICE * :Login authentication failed
+fn parse_notice(irc_message: IrcMessage) -> Result<Notice, ParseError> {
+    if irc_message.params.is_empty() {
+        return Err(ParseError::MissingChannel);
+    }
+    Ok(Notice {
+        channel: irc_message.channel().map(String::from),
+        kind: irc_message
+            .tag("msg-id")
+            .map(|msg_id| match msg_id.as_ref() {
+                "msg_ratelimit" => NoticeKind::MsgRatelimit,
+                "msg_banned" => NoticeKind::MsgBanned,
+                "msg_timedout" => NoticeKind::MsgTimedout,
+                "unrecognized_cmd" => NoticeKind::UnrecognizedCmd,
+                other => NoticeKind::Other(other.to_owned()),
+            }),
+        text: irc_message.trailing.unwrap_or_default().trim().to_owned(),
+    })
+}
+
+fn strip_mention(text: &str) -> &str {
+    match text.split_once(' ') {
+        Some((mention, rest)) if mention.starts_with('@') => rest.trim_start(),
+        _ => text,
+    }
+}
+
+// @reply-parent-display-name=Carkhy;reply-parent-msg-body=is\sthis\son?;reply-parent-msg-id=b34ccfc7-...;
+//     reply-parent-user-login=carkhy :botname!botname@... PRIVMSG #channel :@Carkhy yes
+pub(super) fn parse_reply_parent(tags: &Tags) -> Option<ReplyParent> {
+    let tag = |name| {
+        tags.get(name)
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+    };
+    Some(ReplyParent {
+        message_id: tags.get("reply-parent-msg-id")?.to_string(),
+        user_login: tag("reply-parent-user-login"),
+        display_name: tag("reply-parent-display-name"),
+        body: tag("reply-parent-msg-body"),
+    })
+}
+
+pub(super) const HYPE_CHAT_LEVELS: [&str; 10] = [
+    "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE", "TEN",
+];
+
+// pinned-chat-paid-amount=500;pinned-chat-paid-currency=USD;pinned-chat-paid-exponent=2;pinned-chat-paid-level=ONE
+// an unknown level counts as the lowest, an exponent too big for an u64 amount drops the payment
+pub(super) fn parse_paid_message(tags: &Tags) -> Option<PaidMessage> {
+    let exponent = match tags.get("pinned-chat-paid-exponent") {
+        Some(exponent) => exponent.parse().ok().filter(|exponent| *exponent < 20)?,
+        None => 0,
+    };
+    let level = tags
+        .get("pinned-chat-paid-level")
+        .and_then(|level| HYPE_CHAT_LEVELS.iter().position(|name| *name == level))
+        .unwrap_or(0);
+    Some(PaidMessage {
+        amount: tags.get("pinned-chat-paid-amount")?.parse().ok()?,
+        exponent,
+        currency: tags
+            .get("pinned-chat-paid-currency")
+            .filter(|currency| !currency.is_empty())?
+            .into_owned(),
+        level: level as u8 + 1,
+    })
+}
+
+// msg-param-sub-plan=Prime, 1000, 2000 or 3000
+fn parse_sub_plan(plan: &str) -> Option<SubTier> {
+    match plan {
+        "Prime" => Some(SubTier::Prime),
+        "1000" => Some(SubTier::Tier1),
+        "2000" => Some(SubTier::Tier2),
+        "3000" => Some(SubTier::Tier3),
+        _ => None,
+    }
+}
+
+// tmi-sent-ts=1637614002702 in milliseconds since the unix epoch
+pub(super) fn parse_timestamp(tags: &Tags) -> Option<SystemTime> {
+    let millis = tags.get("tmi-sent-ts")?.parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+// colors are sent as #RRGGBB hex codes
+pub(super) fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |range| u8::from_str_radix(&hex[range], 16).ok();
+    Some(Color {
+        red: channel(0..2)?,
+        green: channel(2..4)?,
+        blue: channel(4..6)?,
+    })
+}
+
+// emotes=25:0-4,12-16/1902:6-10
+// twitch sends inclusive code point positions, these are converted to byte offsets into text.
+// Ranges not fitting into the text are skipped.
+pub(super) fn parse_emotes(emotes: &str, text: &str) -> Vec<EmoteSpan> {
+    let char_offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let num_chars = char_offsets.len() - 1;
+    let mut spans: Vec<EmoteSpan> = emotes
+        .split('/')
+        .filter_map(|emote| emote.split_once(':'))
+        .flat_map(|(id, ranges)| {
+            ranges.split(',').filter_map(|range| {
+                let (start, end) = range.split_once('-')?;
+                let start: usize = start.parse().ok()?;
+                let end: usize = end.parse().ok()?;
+                if start > end || end >= num_chars {
+                    return None;
                 }
+                Some(EmoteSpan {
+                    id: id.to_owned(),
+                    start: char_offsets[start],
+                    end: char_offsets[end + 1],
+                })
             })
-            .collect()
+        })
+        .collect();
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+// badges=broadcaster/1,subscriber/3012 with badge-info=subscriber/27
+pub(super) fn get_badges(tags: &Tags) -> Vec<Badge> {
+    let badge_info = tags.get("badge-info").unwrap_or_default();
+    let badge_info: HashMap<&str, &str> = badge_info.split(',').filter_map(split_badge).collect();
+    tags.get("badges")
+        .map(|badges| {
+            badges
+                .split(',')
+                .filter_map(split_badge)
+                .map(|(name, version)| match name {
+                    "broadcaster" => Badge::Broadcaster,
+                    "moderator" => Badge::Moderator,
+                    "vip" => Badge::Vip,
+                    "subscriber" => Badge::Subscriber {
+                        months: badge_info
+                            .get("subscriber")
+                            .and_then(|months| months.parse().ok())
+                            .unwrap_or(0),
+                        // 3012 for tier 3, 0 or 12 for tier 1
+                        tier: match version.parse::<u32>().unwrap_or(0) / 1000 {
+                            2 => SubTier::Tier2,
+                            3 => SubTier::Tier3,
+                            _ => SubTier::Tier1,
+                        },
+                    },
+                    "bits" => Badge::Bits {
+                        amount: version.parse().unwrap_or(0),
+                    },
+                    _ => Badge::Unknown(name.to_owned(), version.to_owned()),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn split_badge(badge: &str) -> Option<(&str, &str)> {
+    let (name, version) = badge.split_once('/').unwrap_or((badge, ""));
+    if name.is_empty() {
+        None
     } else {
-        HashSet::default()
+        Some((name, version))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::connect::connector::twitch_chat::line_assembler::LineAssembler;
+    use crate::connect::RawTags;
+
+    const TEST_TAGS: &str = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type=";
+
+    fn test_tags() -> RawTags {
+        Tags::new(TEST_TAGS.trim_start_matches('@')).into()
+    }
+
+    const TEST_MESSAGE_ID: &str = "60904094-3684-4871-9e8c-1400648a804d";
+
+    // tmi-sent-ts of the test tags
+    fn test_timestamp() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1637614002702)
+    }
+
+    fn privmsg(text: &str) -> String {
+        format!(
+            "{} :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :{}",
+            TEST_TAGS, text
+        )
+    }
+
+    fn expected_command(kind: CommandType, options: Vec<String>, text: &str) -> ReceiveEvent {
+        ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
+            kind,
+            options,
+            message: TextMessage {
+                text: text.to_owned(),
+                user: UserInfo {
+                    name: "chatter".to_owned(),
+                    badges: Vec::default(),
+                    display_name: Some("carkhy".to_owned()),
+                    color: None,
+                },
+                channel: "channel123".to_owned(),
+                tags: test_tags(),
+                emotes: Vec::default(),
+                bits: None,
+                is_action: false,
+                reply_to: None,
+                timestamp: Some(test_timestamp()),
+                message_id: Some(TEST_MESSAGE_ID.to_owned()),
+                first_msg: false,
+                returning_chatter: false,
+                paid: None,
+                level: UserLevel::Everyone,
+            },
+        }))
+    }
 
     #[test]
     fn parsing_user_messages() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :This is a test message";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
+        let message = privmsg("This is a test message");
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
             TextMessage {
                 text: "This is a test message".to_owned(),
                 user: UserInfo {
                     name: "chatter".to_owned(),
-                    badges: HashSet::default(),
+                    badges: Vec::default(),
+                    display_name: Some("carkhy".to_owned()),
+                    color: None,
                 },
+                channel: "channel123".to_owned(),
+                tags: test_tags(),
+                emotes: Vec::default(),
+                bits: None,
+                is_action: false,
+                reply_to: None,
+                timestamp: Some(test_timestamp()),
+                message_id: Some(TEST_MESSAGE_ID.to_owned()),
+                first_msg: false,
+                returning_chatter: false,
+                paid: None,
+                level: UserLevel::Everyone,
             },
         )));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_user_messages_with_trailing_newlines() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :This is a test message\n";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
+        let message = privmsg("This is a test message\n");
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
             TextMessage {
                 text: "This is a test message".to_owned(),
                 user: UserInfo {
                     name: "chatter".to_owned(),
-                    badges: HashSet::default(),
+                    badges: Vec::default(),
+                    display_name: Some("carkhy".to_owned()),
+                    color: None,
+                },
+                channel: "channel123".to_owned(),
+                tags: test_tags(),
+                emotes: Vec::default(),
+                bits: None,
+                is_action: false,
+                reply_to: None,
+                timestamp: Some(test_timestamp()),
+                message_id: Some(TEST_MESSAGE_ID.to_owned()),
+                first_msg: false,
+                returning_chatter: false,
+                paid: None,
+                level: UserLevel::Everyone,
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
+    }
+
+    #[test]
+    fn parsing_user_messages_without_tags() {
+        let message = ":chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hello";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
+            TextMessage {
+                text: "Hello".to_owned(),
+                user: UserInfo {
+                    name: "chatter".to_owned(),
+                    badges: Vec::default(),
+                    display_name: None,
+                    color: None,
                 },
+                channel: "channel123".to_owned(),
+                tags: RawTags::default(),
+                emotes: Vec::default(),
+                bits: None,
+                is_action: false,
+                reply_to: None,
+                timestamp: None,
+                message_id: None,
+                first_msg: false,
+                returning_chatter: false,
+                paid: None,
+                level: UserLevel::Everyone,
             },
         )));
         assert_eq!(ReceiveEvent::parse_from_message(message), expected);
@@ -252,165 +788,1418 @@ mod tests {
     #[test]
     fn parsing_badges_list() {
         let message = "@badge-info=;badges=badge1/2,badge2/10;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :This is a test message";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
+        let raw = TEST_TAGS.replace(";badges=;", ";badges=badge1/2,badge2/10;");
+        let tags: RawTags = Tags::new(raw.trim_start_matches('@')).into();
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
             TextMessage {
                 text: "This is a test message".to_owned(),
                 user: UserInfo {
                     name: "chatter".to_owned(),
-                    badges: HashSet::from([
-                        Badge {
-                            name: "badge1".to_owned(),
-                            level: 2,
-                        },
-                        Badge {
-                            name: "badge2".to_owned(),
-                            level: 10,
-                        },
-                    ]),
+                    badges: vec![
+                        Badge::Unknown("badge1".to_owned(), "2".to_owned()),
+                        Badge::Unknown("badge2".to_owned(), "10".to_owned()),
+                    ],
+                    display_name: Some("carkhy".to_owned()),
+                    color: None,
                 },
+                channel: "channel123".to_owned(),
+                tags,
+                emotes: Vec::default(),
+                bits: None,
+                is_action: false,
+                reply_to: None,
+                timestamp: Some(test_timestamp()),
+                message_id: Some(TEST_MESSAGE_ID.to_owned()),
+                first_msg: false,
+                returning_chatter: false,
+                paid: None,
+                level: UserLevel::Everyone,
             },
         )));
         assert_eq!(ReceiveEvent::parse_from_message(message), expected);
     }
 
+    #[test]
+    fn parsing_typed_badges() {
+        let tags = Tags::new(
+            "badge-info=subscriber/27;badges=vip/1,subscriber/3012,bits/1000,glitchcon2020/1",
+        );
+        assert_eq!(
+            get_badges(&tags),
+            vec![
+                Badge::Vip,
+                Badge::Subscriber {
+                    months: 27,
+                    tier: SubTier::Tier3
+                },
+                Badge::Bits { amount: 1000 },
+                Badge::Unknown("glitchcon2020".to_owned(), "1".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parsing_subscriber_badge_without_badge_info() {
+        let tags = Tags::new("badge-info=;badges=subscriber/0");
+        assert_eq!(
+            get_badges(&tags),
+            vec![Badge::Subscriber {
+                months: 0,
+                tier: SubTier::Tier1
+            }]
+        );
+    }
+
+    #[test]
+    fn broadcaster_counts_as_moderator() {
+        let message = "@badge-info=;badges=broadcaster/1 :captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #captaincallback :Hi";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(text_message))) => {
+                assert_eq!(text_message.user.badges, vec![Badge::Broadcaster]);
+                assert_eq!(text_message.level, UserLevel::Broadcaster);
+                assert!(text_message.has_level(UserLevel::Moderator));
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_emote_positions() {
+        let text = "Kappa Keepo Kappa";
+        let emotes = parse_emotes("25:0-4,12-16/1902:6-10", text);
+        let emote_texts: Vec<(&str, &str)> = emotes
+            .iter()
+            .map(|emote| (emote.id.as_str(), &text[emote.start..emote.end]))
+            .collect();
+        assert_eq!(
+            emote_texts,
+            vec![("25", "Kappa"), ("1902", "Keepo"), ("25", "Kappa")]
+        );
+    }
+
+    #[test]
+    fn parsing_emote_positions_after_multibyte_characters() {
+        let text = "Grüße 😀 Kappa";
+        let emotes = parse_emotes("25:8-12", text);
+        assert_eq!(
+            emotes,
+            vec![EmoteSpan {
+                id: "25".to_owned(),
+                start: 13,
+                end: 18,
+            }]
+        );
+        assert_eq!(&text[emotes[0].start..emotes[0].end], "Kappa");
+    }
+
+    #[test]
+    fn skipping_emote_positions_outside_of_text() {
+        assert_eq!(
+            parse_emotes("25:0-4,6-10/1902:3-2,x-1", "Kappa"),
+            vec![EmoteSpan {
+                id: "25".to_owned(),
+                start: 0,
+                end: 5,
+            }]
+        );
+        assert_eq!(parse_emotes("", "Kappa"), Vec::default());
+    }
+
+    #[test]
+    fn parsing_emotes_from_message() {
+        let message = "@emotes=25:9-13 :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!welcome Kappa";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(command))) => {
+                let emote = &command.message.emotes[0];
+                assert_eq!(&command.message.text[emote.start..emote.end], "Kappa");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_escaped_semicolon_does_not_split_tags() {
+        let message = r"@display-name=carkhy;system-msg=one\:two :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hi";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(text_message))) => {
+                assert_eq!(text_message.tags.iter().count(), 2);
+                assert_eq!(text_message.tags.get("system-msg").unwrap(), "one;two");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_tag_block_without_prefix() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message("@badge-info=;badges=moderator/1"),
+            Err(ParseError::MissingCommand)
+        );
+        assert_eq!(
+            ReceiveEvent::parse_from_message("@badge-info= "),
+            Err(ParseError::MissingCommand)
+        );
+    }
+
+    #[test]
+    fn parsing_channel_with_hashes_in_text() {
+        let message =
+            ":chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #captaincallback :#rust is #1 :)";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(text_message))) => {
+                assert_eq!(text_message.channel, "captaincallback");
+                assert_eq!(text_message.text, "#rust is #1 :)");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_display_name_and_color() {
+        let message = "@color=#1E90FF;display-name=CaptainCallback :captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #captaincallback :Hi";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(text_message))) => {
+                assert_eq!(
+                    text_message.user.display_name,
+                    Some("CaptainCallback".to_owned())
+                );
+                assert_eq!(text_message.user.display_name(), "CaptainCallback");
+                assert_eq!(
+                    text_message.user.color,
+                    Some(Color {
+                        red: 0x1E,
+                        green: 0x90,
+                        blue: 0xFF,
+                    })
+                );
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_empty_display_name_falls_back_to_login() {
+        let message =
+            "@color=;display-name= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hi";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(text_message))) => {
+                assert_eq!(text_message.user.display_name, None);
+                assert_eq!(text_message.user.display_name(), "chatter");
+                assert_eq!(text_message.user.color, None);
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_invalid_colors() {
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("1E90FF"), None);
+        assert_eq!(parse_color("#GG90FF"), None);
+        assert_eq!(parse_color("#1E90FÄ"), None);
+        assert_eq!(
+            parse_color("#000000"),
+            Some(Color {
+                red: 0,
+                green: 0,
+                blue: 0
+            })
+        );
+    }
+
     #[test]
     fn parsing_help_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!help";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::Help,
-            options: Vec::default(),
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = privmsg("!help");
+        let expected = Ok(expected_command(CommandType::Help, Vec::default(), "!help"));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_info_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!info";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::Info,
-            options: Vec::default(),
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = privmsg("!info");
+        let expected = Ok(expected_command(CommandType::Info, Vec::default(), "!info"));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_join() {
         let message = ":carkhy!carkhy@carkhy.tmi.twitch.tv JOIN #captaincallback";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Join(
-            "carkhy".to_owned(),
-        )));
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Join {
+            user: "carkhy".to_owned(),
+            channel: "captaincallback".to_owned(),
+        }));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_own_join_confirmation() {
+        let message = ":botname!botname@botname.tmi.twitch.tv JOIN #captaincallback\r\n";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Join {
+            user: "botname".to_owned(),
+            channel: "captaincallback".to_owned(),
+        }));
         assert_eq!(ReceiveEvent::parse_from_message(message), expected);
     }
 
     #[test]
     fn parsing_part() {
         let message = ":carkhy!carkhy@carkhy.tmi.twitch.tv PART #captaincallback";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Part(
-            "carkhy".to_owned(),
-        )));
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Part {
+            user: "carkhy".to_owned(),
+            channel: "captaincallback".to_owned(),
+        }));
         assert_eq!(ReceiveEvent::parse_from_message(message), expected);
     }
 
     #[test]
     fn parsing_slap_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!slap anotheruser";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::Slap,
-            options: vec!["anotheruser".to_owned()],
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = privmsg("!slap anotheruser");
+        let expected = Ok(expected_command(
+            CommandType::Slap,
+            vec!["anotheruser".to_owned()],
+            "!slap anotheruser",
+        ));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_newcommand_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!newcommand command Text to output";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::NewCommand,
-            options: vec![
+        let message = privmsg("!newcommand command Text to output");
+        let expected = Ok(expected_command(
+            CommandType::NewCommand,
+            vec![
                 "command".to_owned(),
                 "Text".to_owned(),
                 "to".to_owned(),
                 "output".to_owned(),
             ],
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+            "!newcommand command Text to output",
+        ));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_removecommand_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!removecommand command";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::RemoveCommand,
-            options: vec!["command".to_owned()],
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = privmsg("!removecommand command");
+        let expected = Ok(expected_command(
+            CommandType::RemoveCommand,
+            vec!["command".to_owned()],
+            "!removecommand command",
+        ));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_discord_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!discord";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::Discord,
-            options: Vec::default(),
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = privmsg("!discord");
+        let expected = Ok(expected_command(
+            CommandType::Discord,
+            Vec::default(),
+            "!discord",
+        ));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_dynamic_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!unknown command";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::Dynamic("unknown".to_owned()),
-            options: vec!["command".to_owned()],
-            user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
-            },
-        })));
-        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = privmsg("!unknown command");
+        let expected = Ok(expected_command(
+            CommandType::Dynamic("unknown".to_owned()),
+            vec!["command".to_owned()],
+            "!unknown command",
+        ));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
     }
 
     #[test]
     fn parsing_newrepeating_command() {
-        let message = "@badge-info=;badges=;client-nonce=1e51cee7513a4516545bbc36a22f27eb;color=;display-name=carkhy;emotes=;first-msg=0;flags=;id=60904094-3684-4871-9e8c-1400648a804d;mod=0;room-id=120630112;subscriber=0;tmi-sent-ts=1637614002702;turbo=0;user-id=70346833;user-type= :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :!newrepeating command 60 Text to output";
-        let expected = Some(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(Command {
-            kind: CommandType::NewRepeating,
-            options: vec![
+        let message = privmsg("!newrepeating command 60 Text to output");
+        let expected = Ok(expected_command(
+            CommandType::NewRepeating,
+            vec![
                 "command".to_owned(),
                 "60".to_owned(),
                 "Text".to_owned(),
                 "to".to_owned(),
                 "output".to_owned(),
             ],
+            "!newrepeating command 60 Text to output",
+        ));
+        assert_eq!(ReceiveEvent::parse_from_message(&message), expected);
+    }
+
+    #[test]
+    fn parsing_ping() {
+        let ping = |server: &str| {
+            Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::Ping {
+                server: server.to_owned(),
+            }))
+        };
+        assert_eq!(
+            ReceiveEvent::parse_from_message("PING :tmi.twitch.tv"),
+            ping("tmi.twitch.tv")
+        );
+        assert_eq!(
+            ReceiveEvent::parse_from_message("PING tmi.twitch.tv"),
+            ping("tmi.twitch.tv")
+        );
+        assert_eq!(ReceiveEvent::parse_from_message("PING"), ping(""));
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv PING :tmi.twitch.tv"),
+            ping("tmi.twitch.tv")
+        );
+    }
+
+    #[test]
+    fn tagged_lines_are_not_mistaken_for_ping() {
+        let message = "@id=1;tmi-sent-ts=1637614002702 :chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :PING :tmi.twitch.tv";
+        assert!(matches!(
+            ReceiveEvent::parse_from_message(message),
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(TextMessage { text, .. })))
+                if text == "PING :tmi.twitch.tv"
+        ));
+    }
+
+    fn parse_user_notice_line(message: &str) -> UserNotice {
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::UserNotice(notice))) => notice,
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_sub_user_notice_without_message() {
+        let notice = parse_user_notice_line(
+            r"@badge-info=subscriber/0;badges=subscriber/0;color=;display-name=Carkhy;emotes=;flags=;id=2ed2d0b5-5a44-4b5c-a8d8-3ed0ab4cae84;login=carkhy;mod=0;msg-id=sub;msg-param-cumulative-months=1;msg-param-sub-plan=1000;room-id=120630112;subscriber=1;system-msg=Carkhy\ssubscribed\sat\sTier\s1.;tmi-sent-ts=1637614002702;user-id=70346833;user-type= :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Sub {
+                tier: SubTier::Tier1,
+                cumulative_months: 1,
+                streak_months: None
+            }
+        );
+        assert_eq!(notice.user.name, "carkhy");
+        assert_eq!(notice.user.display_name(), "Carkhy");
+        assert_eq!(
+            notice.user.badges,
+            vec![Badge::Subscriber {
+                months: 0,
+                tier: SubTier::Tier1
+            }]
+        );
+        assert_eq!(notice.channel, "captaincallback");
+        assert_eq!(notice.system_message, "Carkhy subscribed at Tier 1.");
+        assert_eq!(notice.text, None);
+    }
+
+    #[test]
+    fn parsing_resub_user_notice_with_message() {
+        let notice = parse_user_notice_line(
+            r"@badge-info=subscriber/6;badges=subscriber/6;display-name=Carkhy;login=carkhy;msg-id=resub;msg-param-cumulative-months=6;msg-param-streak-months=2;msg-param-sub-plan=Prime;system-msg=Carkhy\ssubscribed\swith\sPrime.\sThey've\ssubscribed\sfor\s6\smonths! :tmi.twitch.tv USERNOTICE #captaincallback :Great stream!",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Resub {
+                tier: SubTier::Prime,
+                cumulative_months: 6,
+                streak_months: Some(2)
+            }
+        );
+        assert_eq!(
+            notice.system_message,
+            "Carkhy subscribed with Prime. They've subscribed for 6 months!"
+        );
+        assert_eq!(notice.text, Some("Great stream!".to_owned()));
+    }
+
+    #[test]
+    fn parsing_sub_tiers_and_streaks() {
+        let tier = |plan| {
+            let line = format!("@login=carkhy;msg-id=resub;msg-param-sub-plan={} :tmi.twitch.tv USERNOTICE #channel", plan);
+            match parse_user_notice_line(&line).kind {
+                UserNoticeKind::Resub { tier, .. } => tier,
+                other => panic!("unexpected notice kind {:?}", other),
+            }
+        };
+        assert_eq!(tier("Prime"), SubTier::Prime);
+        assert_eq!(tier("1000"), SubTier::Tier1);
+        assert_eq!(tier("2000"), SubTier::Tier2);
+        assert_eq!(tier("3000"), SubTier::Tier3);
+        assert_eq!(tier("4000"), SubTier::Tier1);
+        let notice = parse_user_notice_line(
+            "@login=carkhy;msg-id=resub;msg-param-cumulative-months=12;msg-param-should-share-streak=0;msg-param-streak-months=5;msg-param-sub-plan=3000 :tmi.twitch.tv USERNOTICE #channel",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Resub {
+                tier: SubTier::Tier3,
+                cumulative_months: 12,
+                streak_months: None
+            }
+        );
+    }
+
+    #[test]
+    fn parsing_sub_user_notice_without_params() {
+        let notice =
+            parse_user_notice_line("@login=carkhy;msg-id=sub :tmi.twitch.tv USERNOTICE #channel");
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Sub {
+                tier: SubTier::Tier1,
+                cumulative_months: 1,
+                streak_months: None
+            }
+        );
+        let notice = parse_user_notice_line(
+            "@login=carkhy;msg-id=resub;msg-param-cumulative-months=many :tmi.twitch.tv USERNOTICE #channel",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Resub {
+                tier: SubTier::Tier1,
+                cumulative_months: 0,
+                streak_months: None
+            }
+        );
+    }
+
+    #[test]
+    fn parsing_gift_user_notices() {
+        let notice = parse_user_notice_line(
+            r"@display-name=Gifter;login=gifter;msg-id=subgift;msg-param-months=1;msg-param-recipient-display-name=Carkhy;msg-param-recipient-user-name=carkhy;msg-param-sub-plan=1000;system-msg=Gifter\sgifted\sa\sTier\s1\ssub\sto\sCarkhy! :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::SubGift {
+                recipient: "carkhy".to_owned()
+            }
+        );
+        let notice = parse_user_notice_line(
+            r"@display-name=Gifter;login=gifter;msg-id=submysterygift;msg-param-mass-gift-count=20;msg-param-sub-plan=1000;system-msg=Gifter\sis\sgifting\s20\sTier\s1\sSubs! :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert_eq!(notice.kind, UserNoticeKind::SubMysteryGift { count: 20 });
+    }
+
+    #[test]
+    fn parsing_unknown_user_notices() {
+        let notice = parse_user_notice_line(
+            r"@login=carkhy;msg-id=bitsbadgetier;system-msg=bits\sbadge :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Unknown("bitsbadgetier".to_owned())
+        );
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv USERNOTICE #captaincallback"),
+            Err(ParseError::MissingTag("login".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parsing_raid_user_notice() {
+        let notice = parse_user_notice_line(
+            r"@badge-info=;badges=partner/1;color=#5B99FF;display-name=StreamerFriend;emotes=;flags=;id=9d1c9b3e-02f6-4f5c-bd1d-44b1d4bd8f4f;login=streamerfriend;mod=0;msg-id=raid;msg-param-displayName=StreamerFriend;msg-param-login=streamerfriend;msg-param-profileImageURL=https://static-cdn.jtvnw.net/jtv_user_pictures/streamerfriend-profile_image-70x70.png;msg-param-viewerCount=42;room-id=120630112;subscriber=0;system-msg=42\sraiders\sfrom\sStreamerFriend\shave\sjoined!;tmi-sent-ts=1637614002702;user-id=70346834;user-type= :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Raid {
+                from: "StreamerFriend".to_owned(),
+                viewers: 42
+            }
+        );
+        assert_eq!(
+            notice.system_message,
+            "42 raiders from StreamerFriend have joined!"
+        );
+    }
+
+    #[test]
+    fn parsing_raid_user_notice_without_viewer_count() {
+        let notice = parse_user_notice_line(
+            r"@login=streamerfriend;msg-id=raid;msg-param-viewerCount=many;system-msg=raiders :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert_eq!(
+            notice.kind,
+            UserNoticeKind::Raid {
+                from: "streamerfriend".to_owned(),
+                viewers: 0
+            }
+        );
+        let notice = parse_user_notice_line(
+            r"@login=streamerfriend;msg-id=raid :tmi.twitch.tv USERNOTICE #captaincallback",
+        );
+        assert!(matches!(
+            notice.kind,
+            UserNoticeKind::Raid { viewers: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn parsing_clear_chat_timeout() {
+        let message = "@ban-duration=600;room-id=120630112;target-user-id=70346833;tmi-sent-ts=1637614002702 :tmi.twitch.tv CLEARCHAT #captaincallback :carkhy";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::ClearChat(
+            ClearChat {
+                channel: "captaincallback".to_owned(),
+                target_user: Some("carkhy".to_owned()),
+                duration: Some(Duration::from_secs(600)),
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_clear_chat_ban() {
+        let message = "@room-id=120630112;target-user-id=70346833;tmi-sent-ts=1637614002702 :tmi.twitch.tv CLEARCHAT #captaincallback :carkhy";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::ClearChat(
+            ClearChat {
+                channel: "captaincallback".to_owned(),
+                target_user: Some("carkhy".to_owned()),
+                duration: None,
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_full_chat_clear() {
+        let message = "@room-id=120630112;tmi-sent-ts=1637614002702 :tmi.twitch.tv CLEARCHAT #captaincallback";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::ClearChat(
+            ClearChat {
+                channel: "captaincallback".to_owned(),
+                target_user: None,
+                duration: None,
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_clear_message() {
+        let message = "@login=carkhy;room-id=;target-msg-id=c5d6e2a1-7f3b-4ab4-a2b4-3b8e3c0c3a11;tmi-sent-ts=1637614002702 :tmi.twitch.tv CLEARMSG #captaincallback :buy followers at spam.example";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::ClearMessage(
+            ClearMessage {
+                login: "carkhy".to_owned(),
+                channel: "captaincallback".to_owned(),
+                target_message_id: "c5d6e2a1-7f3b-4ab4-a2b4-3b8e3c0c3a11".to_owned(),
+                text: "buy followers at spam.example".to_owned(),
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn rejecting_clear_message_without_target_id() {
+        let expected = Err(ParseError::MissingTag("target-msg-id".to_owned()));
+        let message = "@login=carkhy;room-id= :tmi.twitch.tv CLEARMSG #captaincallback :text";
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        let message = "@login=carkhy;target-msg-id= :tmi.twitch.tv CLEARMSG #captaincallback :text";
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_full_room_state() {
+        let message = "@emote-only=0;followers-only=10;r9k=0;room-id=120630112;slow=30;subs-only=1 :tmi.twitch.tv ROOMSTATE #captaincallback";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::RoomState(
+            RoomState {
+                channel: "captaincallback".to_owned(),
+                emote_only: Some(false),
+                followers_only: Some(10),
+                r9k: Some(false),
+                slow: Some(30),
+                subs_only: Some(true),
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_room_state_delta() {
+        let message = "@emote-only=1;room-id=120630112 :tmi.twitch.tv ROOMSTATE #captaincallback";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::RoomState(
+            RoomState {
+                channel: "captaincallback".to_owned(),
+                emote_only: Some(true),
+                ..Default::default()
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_user_state() {
+        let message = "@badge-info=;badges=moderator/1;color=#1E90FF;display-name=TwitchBotanist;emote-sets=0,300374282;mod=1;subscriber=0;user-type=mod :tmi.twitch.tv USERSTATE #captaincallback";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::UserState(
+            UserState {
+                channel: Some("captaincallback".to_owned()),
+                badges: vec![Badge::Moderator],
+                display_name: Some("TwitchBotanist".to_owned()),
+                color: Some(Color {
+                    red: 0x1E,
+                    green: 0x90,
+                    blue: 0xFF,
+                }),
+                emote_sets: vec!["0".to_owned(), "300374282".to_owned()],
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_global_user_state_with_empty_emote_sets() {
+        let message = "@badge-info=;badges=;color=;display-name=TwitchBotanist;emote-sets=;user-id=12345678;user-type= :tmi.twitch.tv GLOBALUSERSTATE";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::GlobalUserState(
+            UserState {
+                channel: None,
+                badges: Vec::default(),
+                display_name: Some("TwitchBotanist".to_owned()),
+                color: None,
+                emote_sets: Vec::default(),
+            },
+        )));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_notice_with_msg_id() {
+        let message = "@msg-id=msg_ratelimit :tmi.twitch.tv NOTICE #captaincallback :Your message was not sent because you are sending messages too quickly.";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Notice(Notice {
+            channel: Some("captaincallback".to_owned()),
+            kind: Some(NoticeKind::MsgRatelimit),
+            text: "Your message was not sent because you are sending messages too quickly."
+                .to_owned(),
+        })));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_notice_with_unknown_msg_id() {
+        let message = "@msg-id=msg_duplicate :tmi.twitch.tv NOTICE #captaincallback :Your message is identical to the one you sent less than 30 seconds ago.";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Notice(notice))) => {
+                assert_eq!(
+                    notice.kind,
+                    Some(NoticeKind::Other("msg_duplicate".to_owned()))
+                );
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_notice_before_login() {
+        let message = ":tmi.twitch.tv NOTICE * :Login authentication failed";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Notice(Notice {
+            channel: None,
+            kind: None,
+            text: "Login authentication failed".to_owned(),
+        })));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_whisper_with_tags() {
+        let message = "@badges=;color=#1E90FF;display-name=CaptainCallback;emotes=;message-id=1;thread-id=1_2;turbo=0;user-id=1;user-type= :captaincallback!captaincallback@captaincallback.tmi.twitch.tv WHISPER botname :!help";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Whisper(whisper))) => {
+                assert_eq!(whisper.text, "!help");
+                assert_eq!(whisper.recipient, "botname");
+                assert_eq!(whisper.user.name, "captaincallback");
+                assert_eq!(whisper.user.display_name(), "CaptainCallback");
+                assert_eq!(whisper.tags.get("thread-id").as_deref(), Some("1_2"));
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_whisper_without_tags() {
+        let message =
+            ":captaincallback!captaincallback@captaincallback.tmi.twitch.tv WHISPER botname :hello";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Whisper(Whisper {
+            text: "hello".to_owned(),
             user: UserInfo {
-                name: "chatter".to_owned(),
-                badges: HashSet::default(),
+                name: "captaincallback".to_owned(),
+                ..Default::default()
             },
+            recipient: "botname".to_owned(),
+            tags: RawTags::default(),
         })));
         assert_eq!(ReceiveEvent::parse_from_message(message), expected);
     }
+
+    #[test]
+    fn parsing_reconnect() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv RECONNECT"),
+            Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::Reconnect))
+        );
+    }
+
+    #[test]
+    fn parsing_names_reply() {
+        let message =
+            ":botname.tmi.twitch.tv 353 botname = #captaincallback :carkhy captaincallback botname";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Names {
+            channel: "captaincallback".to_owned(),
+            users: vec![
+                "carkhy".to_owned(),
+                "captaincallback".to_owned(),
+                "botname".to_owned(),
+            ],
+        }));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_end_of_names() {
+        let message = ":botname.tmi.twitch.tv 366 botname #captaincallback :End of /NAMES list";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::EndOfNames {
+            channel: "captaincallback".to_owned(),
+        }));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_login_burst() {
+        let burst = ":tmi.twitch.tv 001 botname :Welcome, GLHF!\r\n\
+            :tmi.twitch.tv 002 botname :Your host is tmi.twitch.tv\r\n\
+            :tmi.twitch.tv 003 botname :This server is rather new\r\n\
+            :tmi.twitch.tv 004 botname :-\r\n\
+            :tmi.twitch.tv 375 botname :-\r\n\
+            :tmi.twitch.tv 372 botname :You are in a maze of twisty passages, all alike.\r\n\
+            :tmi.twitch.tv 376 botname :>\r\n";
+        let events: Vec<ReceiveEvent> = burst
+            .lines()
+            .filter_map(|line| ReceiveEvent::parse_from_message(line).ok())
+            .collect();
+        assert_eq!(events.len(), 7);
+        assert_eq!(
+            events[0],
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Welcome {
+                login: "botname".to_owned()
+            })
+        );
+        assert_eq!(
+            events[5],
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Numeric {
+                code: 372,
+                params: vec![
+                    "botname".to_owned(),
+                    "You are in a maze of twisty passages, all alike.".to_owned()
+                ],
+            })
+        );
+        assert!(events[1..].iter().all(|event| matches!(
+            event,
+            ReceiveEvent::ConnectorEvent(ConnectorEvent::Numeric { .. })
+        )));
+    }
+
+    #[test]
+    fn parsing_bits() {
+        let message = "@bits=100;display-name=carkhy :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Cheer100 great stream";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(message.bits, Some(100));
+                assert_eq!(message.text_without_cheermotes(), "great stream");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_malformed_bits() {
+        let message = "@bits=lots;display-name=carkhy :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Cheer100 great stream";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(message.bits, None);
+                assert_eq!(message.text, "Cheer100 great stream");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_action_message() {
+        let message = privmsg("\u{1}ACTION waves\u{1}");
+        match ReceiveEvent::parse_from_message(&message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(message.text, "waves");
+                assert!(message.is_action);
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_action_command() {
+        let message = privmsg("\u{1}ACTION !slap carkhy\u{1}");
+        match ReceiveEvent::parse_from_message(&message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(command))) => {
+                assert_eq!(command.kind, CommandType::Slap);
+                assert_eq!(command.options, vec!["carkhy".to_owned()]);
+                assert!(command.message.is_action);
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_host_start() {
+        let message = ":tmi.twitch.tv HOSTTARGET #captaincallback :carkhy 5";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::HostTarget {
+            channel: "captaincallback".to_owned(),
+            target: Some("carkhy".to_owned()),
+            viewers: 5,
+        }));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_host_start_without_viewers() {
+        let message = ":tmi.twitch.tv HOSTTARGET #captaincallback :carkhy";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::HostTarget {
+            channel: "captaincallback".to_owned(),
+            target: Some("carkhy".to_owned()),
+            viewers: 0,
+        }));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_host_stop() {
+        let message = ":tmi.twitch.tv HOSTTARGET #captaincallback :- 0";
+        let expected = Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::HostTarget {
+            channel: "captaincallback".to_owned(),
+            target: None,
+            viewers: 0,
+        }));
+        assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+    }
+
+    #[test]
+    fn parsing_emoji_in_username_position() {
+        let message = ":😀!😀@😀.tmi.twitch.tv PRIVMSG #captaincallback :😀 hi";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(message.user.name, "😀");
+                assert_eq!(message.text, "😀 hi");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_lines_ending_at_each_part() {
+        let message = "@badges=;emotes=25:0-4 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Kappa";
+        let text_start = message.rfind(" :").unwrap() + 2;
+        for (end, _) in message.char_indices() {
+            // lines cut before the text has started are no messages
+            let parsed = ReceiveEvent::parse_from_message(&message[..end]);
+            assert_eq!(parsed.is_ok(), end > text_start);
+        }
+        assert!(ReceiveEvent::parse_from_message(message).is_ok());
+    }
+
+    #[test]
+    fn parsing_all_colon_line() {
+        for length in 0..10 {
+            let line = ":".repeat(length);
+            assert!(ReceiveEvent::parse_from_message(&line).is_err());
+        }
+    }
+
+    // xorshift, so the fuzzing is reproducible without an extra dependency
+    fn pseudo_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn parsing_random_input_does_not_panic() {
+        const PIECES: [&str; 26] = [
+            "@",
+            ":",
+            ";",
+            "=",
+            "!",
+            "#",
+            " ",
+            "\r\n",
+            "\\",
+            "/",
+            ",",
+            "-",
+            "0",
+            "7",
+            "42",
+            "a",
+            "PRIVMSG",
+            "USERNOTICE",
+            "NOTICE",
+            "emotes=",
+            "badges=",
+            "bits=",
+            "msg-id=",
+            "😀",
+            "é",
+            "\u{1}ACTION ",
+        ];
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let mut assembler = LineAssembler::new();
+        for _ in 0..20_000 {
+            let length = pseudo_random(&mut state) % 24;
+            let chunk: String = (0..length)
+                .map(|_| PIECES[(pseudo_random(&mut state) % PIECES.len() as u64) as usize])
+                .collect();
+            for line in assembler.push(chunk.as_bytes()) {
+                let _ = ReceiveEvent::parse_from_message(&line);
+            }
+            let bytes: Vec<u8> = (0..length)
+                .map(|_| (pseudo_random(&mut state) % 256) as u8)
+                .collect();
+            let _ = ReceiveEvent::parse_from_message(&String::from_utf8_lossy(&bytes));
+        }
+    }
+
+    #[test]
+    fn parsing_private_message_with_server_prefix() {
+        let message = ":tmi.twitch.tv PRIVMSG #captaincallback :some text";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(message.user.name, "tmi.twitch.tv");
+                assert_eq!(message.text, "some text");
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_private_message_with_empty_prefix() {
+        let message = ": PRIVMSG #captaincallback :some text";
+        assert_eq!(
+            ReceiveEvent::parse_from_message(message),
+            Err(ParseError::MalformedPrefix(String::new()))
+        );
+    }
+
+    #[test]
+    fn rejecting_join_without_prefix() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message("JOIN #captaincallback"),
+            Err(ParseError::MissingPrefix)
+        );
+    }
+
+    #[test]
+    fn rejecting_unsupported_commands() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":jtv MODE #captaincallback +o carkhy"),
+            Err(ParseError::UnsupportedCommand("MODE".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parsing_capability_acknowledgements() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv CAP * ACK :twitch.tv/membership"),
+            Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::CapAck(vec![
+                "twitch.tv/membership".to_owned()
+            ])))
+        );
+        assert_eq!(
+            ReceiveEvent::parse_from_message(
+                ":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands"
+            ),
+            Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::CapAck(vec![
+                "twitch.tv/tags".to_owned(),
+                "twitch.tv/commands".to_owned()
+            ])))
+        );
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv CAP * NAK :twitch.tv/unknown"),
+            Ok(ReceiveEvent::ConnectorEvent(ConnectorEvent::CapNak(vec![
+                "twitch.tv/unknown".to_owned()
+            ])))
+        );
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv CAP *"),
+            Err(ParseError::MissingParameter { index: 1 })
+        );
+    }
+
+    #[test]
+    fn rejecting_private_message_without_channel() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG :Hello"),
+            Err(ParseError::MissingChannel)
+        );
+    }
+
+    #[test]
+    fn rejecting_private_message_without_text() {
+        let expected = Err(ParseError::MissingText);
+        for message in [
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :   ",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!",
+        ] {
+            assert_eq!(ReceiveEvent::parse_from_message(message), expected);
+        }
+    }
+
+    #[test]
+    fn rejecting_welcome_without_login() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message(":tmi.twitch.tv 001 :Welcome, GLHF!"),
+            Err(ParseError::MissingParameter { index: 0 })
+        );
+    }
+
+    #[test]
+    fn rejecting_malformed_tags() {
+        assert_eq!(
+            ReceiveEvent::parse_from_message("@=1 :tmi.twitch.tv ROOMSTATE #captaincallback"),
+            Err(ParseError::MalformedTags { offset: 1 })
+        );
+    }
+
+    #[test]
+    fn parsing_first_and_returning_chatters() {
+        let parse = |tags: &str| match ReceiveEvent::parse_from_message(&format!(
+            "{}:chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hello",
+            tags
+        )) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                (message.first_msg, message.returning_chatter)
+            }
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(parse("@first-msg=1;returning-chatter=0 "), (true, false));
+        assert_eq!(parse("@first-msg=0;returning-chatter=1 "), (false, true));
+        assert_eq!(parse(&format!("{} ", TEST_TAGS)), (false, false));
+        assert_eq!(parse(""), (false, false));
+    }
+
+    #[test]
+    fn parsing_hype_chats() {
+        let parse = |tags: &str| match ReceiveEvent::parse_from_message(&format!(
+            "{}:chatter!chatter@chatter.tmi.twitch.tv PRIVMSG #channel123 :Hype!",
+            tags
+        )) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => message.paid,
+            other => panic!("{:?}", other),
+        };
+        let paid = parse("@pinned-chat-paid-amount=500;pinned-chat-paid-currency=USD;pinned-chat-paid-exponent=2;pinned-chat-paid-is-system-message=0;pinned-chat-paid-level=ONE ").unwrap();
+        assert_eq!(
+            paid,
+            PaidMessage {
+                amount: 500,
+                exponent: 2,
+                currency: "USD".to_owned(),
+                level: 1
+            }
+        );
+        assert_eq!(paid.to_string(), "5.00 USD");
+        let paid = parse("@pinned-chat-paid-amount=12345;pinned-chat-paid-currency=DOGE;pinned-chat-paid-exponent=1;pinned-chat-paid-level=TEN ").unwrap();
+        assert_eq!(paid.to_string(), "1234.5 DOGE");
+        assert_eq!(paid.level, 10);
+        // unknown levels count as the lowest
+        let paid = parse("@pinned-chat-paid-amount=100;pinned-chat-paid-currency=EUR;pinned-chat-paid-exponent=2;pinned-chat-paid-level=ELEVEN ").unwrap();
+        assert_eq!(paid.level, 1);
+        assert_eq!(
+            parse("@pinned-chat-paid-amount=100;pinned-chat-paid-exponent=2 "),
+            None
+        );
+        assert_eq!(parse("@pinned-chat-paid-amount=100;pinned-chat-paid-currency=EUR;pinned-chat-paid-exponent=99 "), None);
+        assert_eq!(parse(&format!("{} ", TEST_TAGS)), None);
+    }
+
+    #[test]
+    fn parsing_sent_timestamp() {
+        let message = ReceiveEvent::parse_from_message(&format!(
+            "{} :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Hello",
+            TEST_TAGS
+        ));
+        match message {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                // 2021-11-22 20:46:42.702 UTC
+                let expected = UNIX_EPOCH + Duration::new(1_637_614_002, 702_000_000);
+                assert_eq!(message.timestamp, Some(expected));
+            }
+            other => panic!("{:?}", other),
+        }
+        let message = ReceiveEvent::parse_from_message(
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Hello",
+        );
+        assert!(matches!(
+            message,
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(
+                TextMessage {
+                    timestamp: None,
+                    message_id: None,
+                    first_msg: false,
+                    returning_chatter: false,
+                    level: UserLevel::Everyone,
+                    ..
+                }
+            )))
+        ));
+    }
+
+    #[test]
+    fn parsing_reply_parent() {
+        let message = "@display-name=TwitchBotanist;id=1;reply-parent-display-name=Carkhy;reply-parent-msg-body=is\\sthis\\son?;reply-parent-msg-id=b34ccfc7-4977;reply-parent-user-id=70346833;reply-parent-user-login=carkhy :twitchbotanist!twitchbotanist@twitchbotanist.tmi.twitch.tv PRIVMSG #captaincallback :@Carkhy yes";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(message.text, "@Carkhy yes");
+                assert_eq!(
+                    message.reply_to,
+                    Some(ReplyParent {
+                        message_id: "b34ccfc7-4977".to_owned(),
+                        user_login: "carkhy".to_owned(),
+                        display_name: "Carkhy".to_owned(),
+                        body: "is this on?".to_owned(),
+                    })
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_partial_reply_parent() {
+        let message = "@reply-parent-msg-id=b34ccfc7-4977 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :@someone hi";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(message))) => {
+                assert_eq!(
+                    message.reply_to,
+                    Some(ReplyParent {
+                        message_id: "b34ccfc7-4977".to_owned(),
+                        user_login: String::new(),
+                        display_name: String::new(),
+                        body: String::new(),
+                    })
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parsing_command_in_reply() {
+        let message = "@reply-parent-display-name=Carkhy;reply-parent-msg-body=so\\sit\\sbegins;reply-parent-msg-id=b34ccfc7-4977;reply-parent-user-login=carkhy :captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #captaincallback :@Carkhy !quote";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::Command(command))) => {
+                assert_eq!(command.kind, CommandType::Quote);
+                assert!(command.options.is_empty());
+                assert_eq!(command.message.text, "@Carkhy !quote");
+                assert_eq!(command.message.reply_to.unwrap().body, "so it begins");
+            }
+            other => panic!("{:?}", other),
+        }
+        // without reply tags a leading mention is no command
+        let message = ":captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #captaincallback :@Carkhy !quote";
+        assert!(matches!(
+            ReceiveEvent::parse_from_message(message),
+            Ok(ReceiveEvent::ChatBotEvent(ChatBotEvent::TextMessage(_)))
+        ));
+    }
+
+    const CHAT_LOG: &str = include_str!("test_data/chat_log.txt");
+
+    #[test]
+    fn parsing_degenerate_lines_fails() {
+        let lines = [
+            "",
+            "\r",
+            "\0",
+            " ",
+            "@",
+            "@ ",
+            "@a",
+            "@a=",
+            "@a=;b= ",
+            "@=x :tmi.twitch.tv PRIVMSG #c :hi",
+            ":",
+            ":tmi.twitch.tv",
+            ":tmi.twitch.tv ",
+            ":carkhy!carkhy@",
+            "@badges=1 :carkhy!carkhy@carkhy.tmi.twitch.tv",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #c :",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #c :\r",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #c :\u{1}ACTION\u{1}",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #c :!",
+            "\0PRIVMSG\0#c\0:hi",
+        ];
+        for line in lines {
+            assert!(
+                ReceiveEvent::parse_from_message(line).is_err(),
+                "{:?} parsed to {:?}",
+                line,
+                ReceiveEvent::parse_from_message(line)
+            );
+        }
+    }
+
+    // mutations of real lines reach deeper into the parser than random bytes
+    #[test]
+    fn parsing_mutated_lines_does_not_panic() {
+        const PIECES: &[&str] = &[
+            "\0", "\r", "\n", ":", "@", "=", ";", " ", "!", "#", "/", ",", "-", "\\", "😀", "\u{1}",
+        ];
+        let lines: Vec<&str> = CHAT_LOG.lines().collect();
+        let mut state = 0x853c_49e6_748f_ea9b;
+        let mut random = |bound: usize| (pseudo_random(&mut state) % bound as u64) as usize;
+        for _ in 0..20_000 {
+            let mut line = lines[random(lines.len())].to_owned();
+            for _ in 0..random(4) + 1 {
+                let boundaries: Vec<usize> = line
+                    .char_indices()
+                    .map(|(offset, _)| offset)
+                    .chain(std::iter::once(line.len()))
+                    .collect();
+                let at = boundaries[random(boundaries.len())];
+                let to = boundaries[random(boundaries.len())].max(at);
+                match random(4) {
+                    0 => line.insert_str(at, PIECES[random(PIECES.len())]),
+                    1 => line.replace_range(at..to, ""),
+                    2 => line.truncate(at),
+                    _ => {
+                        let copy = line[at..to].to_owned();
+                        line.insert_str(at, &copy);
+                    }
+                }
+            }
+            let _ = ReceiveEvent::parse_from_message(&line);
+        }
+    }
+
+    #[test]
+    fn chunked_chat_log_parses_like_whole() {
+        let session = CHAT_LOG.replace('\n', "\r\n");
+        let parse_all = |lines: Vec<String>| -> Vec<ReceiveEvent> {
+            lines.iter().filter_map(|line| parse_line(line)).collect()
+        };
+        let whole = parse_all(LineAssembler::new().push(session.as_bytes()).collect());
+        assert_eq!(
+            whole.len(),
+            parse_all(CHAT_LOG.lines().map(String::from).collect()).len()
+        );
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..50 {
+            let mut assembler = LineAssembler::new();
+            let mut lines = Vec::new();
+            let mut rest = session.as_bytes();
+            while !rest.is_empty() {
+                let length = (pseudo_random(&mut state) % 64) as usize + 1;
+                let (chunk, after) = rest.split_at(length.min(rest.len()));
+                lines.extend(assembler.push(chunk));
+                rest = after;
+            }
+            assert_eq!(parse_all(lines), whole);
+        }
+    }
+
+    #[test]
+    fn parsing_captured_chat_log() {
+        for line in CHAT_LOG.lines() {
+            let borrowed = IrcMessage::parse(line).unwrap();
+            assert!(
+                matches!(
+                    ReceiveEvent::try_from(borrowed),
+                    Ok(_) | Err(ParseError::UnsupportedCommand(_))
+                ),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture benchmark
+    #[test]
+    #[ignore]
+    fn benchmark_borrowed_and_owned_parsing() {
+        use std::time::Instant;
+        const ROUNDS: usize = 10_000;
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for line in CHAT_LOG.lines() {
+                std::hint::black_box(IrcMessage::parse(line).ok());
+            }
+        }
+        let borrowed = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for line in CHAT_LOG.lines() {
+                std::hint::black_box(ReceiveEvent::parse_from_message(line).ok());
+            }
+        }
+        let owned = start.elapsed();
+        let lines = (ROUNDS * CHAT_LOG.lines().count()) as u32;
+        println!(
+            "borrowed: {:?} per line, owned: {:?} per line",
+            borrowed / lines,
+            owned / lines
+        );
+    }
+
+    // cargo test --release -- --ignored --nocapture benchmark
+    #[test]
+    #[ignore]
+    fn benchmark_eager_and_lazy_tags() {
+        use std::time::Instant;
+        const ROUNDS: usize = 10_000;
+        let messages: Vec<IrcMessage> = CHAT_LOG
+            .lines()
+            .filter_map(|line| IrcMessage::parse(line).ok())
+            .collect();
+        // the tags are kept with the message like parsing does, then two are looked up
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for message in &messages {
+                let tags = RawTags::from(message.tags).to_map();
+                std::hint::black_box((tags.get("badges").cloned(), tags.get("bits").cloned()));
+            }
+        }
+        let eager = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for message in &messages {
+                let tags = RawTags::from(message.tags);
+                std::hint::black_box((
+                    tags.get("badges").map(Cow::into_owned),
+                    tags.get("bits").map(Cow::into_owned),
+                ));
+            }
+        }
+        let lazy = start.elapsed();
+        let lines = (ROUNDS * messages.len()) as u32;
+        println!(
+            "eager: {:?} per line, lazy: {:?} per line",
+            eager / lines,
+            lazy / lines
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tagged_private_message_survives_serde_round_trip() {
+        let message = "@badge-info=subscriber/27;badges=broadcaster/1,subscriber/3012;color=#1E90FF;display-name=CaptainCallback;emotes=25:6-10 :captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #captaincallback :Hello Kappa";
+        match ReceiveEvent::parse_from_message(message) {
+            Ok(ReceiveEvent::ChatBotEvent(event)) => {
+                let json = serde_json::to_string(&event).unwrap();
+                let deserialized: ChatBotEvent = serde_json::from_str(&json).unwrap();
+                assert_eq!(deserialized, event);
+            }
+            other => panic!("unexpected parsing result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,175 @@
+use crate::{
+    connect::{ChatBotEvent, TextMessage},
+    prometheus,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+// while switching connections twitch may deliver a message on both, within a few seconds
+const REMEMBERED_FOR: Duration = Duration::from_secs(10);
+// at most this many messages are remembered, the oldest is forgotten first
+const CAPACITY: usize = 1000;
+
+// a message by its id tag, or by what it says when it has none
+#[derive(Hash)]
+enum Key<'a> {
+    Id(&'a str),
+    // without tags there is no timestamp either, then only the short memory tells repeats
+    // apart
+    Fallback {
+        channel: &'a str,
+        user: &'a str,
+        text: &'a str,
+        timestamp: Option<u128>,
+    },
+}
+
+impl<'a> Key<'a> {
+    fn of(message: &'a TextMessage) -> Self {
+        match &message.message_id {
+            Some(id) => Key::Id(id),
+            None => Key::Fallback {
+                channel: &message.channel,
+                user: &message.user.name,
+                text: &message.text,
+                timestamp: message.timestamp.and_then(|time| {
+                    let since = time.duration_since(std::time::UNIX_EPOCH).ok()?;
+                    Some(since.as_millis())
+                }),
+            },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Key::Id(_) => "id",
+            Key::Fallback { .. } => "fallback",
+        }
+    }
+
+    // only the hash is remembered, so that no text is copied
+    fn digest(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        Hash::hash(self, &mut hasher);
+        hasher.finish()
+    }
+}
+
+/// The latest chat messages, so that one delivered twice is only handed to the bot once.
+#[derive(Debug, Default)]
+pub struct RecentMessages {
+    order: VecDeque<(u64, Instant)>,
+    seen: HashMap<u64, Instant>,
+}
+
+impl RecentMessages {
+    /// Whether the event wasn't seen recently, it is remembered if so. Events besides chat
+    /// messages and commands are always new.
+    pub fn is_new(&mut self, event: &ChatBotEvent, now: Instant) -> bool {
+        let message = match event {
+            ChatBotEvent::TextMessage(message) => message,
+            ChatBotEvent::Command(command) => &command.message,
+            _ => return true,
+        };
+        self.forget(now);
+        let key = Key::of(message);
+        let hash = key.digest();
+        if self.seen.contains_key(&hash) {
+            prometheus::DUPLICATES_DROPPED.inc(&[key.kind()]);
+            return false;
+        }
+        self.seen.insert(hash, now);
+        self.order.push_back((hash, now));
+        if self.order.len() > CAPACITY {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    fn forget(&mut self, now: Instant) {
+        while let Some(&(hash, seen)) = self.order.front() {
+            if now.saturating_duration_since(seen) < REMEMBERED_FOR {
+                break;
+            }
+            self.order.pop_front();
+            self.seen.remove(&hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::connector::twitch_chat::receive::{parse_line, ReceiveEvent};
+
+    fn event(line: &str) -> ChatBotEvent {
+        match parse_line(line) {
+            Some(ReceiveEvent::ChatBotEvent(event)) => event,
+            other => panic!("no chat event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_same_tagged_line_is_taken_once() {
+        let now = Instant::now();
+        let mut recent = RecentMessages::default();
+        let line = "@id=885196de-cb67-427a-baa8-82f9b0fcd05f;tmi-sent-ts=1507246572675 \
+                    :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!points";
+        let taken: Vec<_> = [line, line]
+            .into_iter()
+            .filter(|line| recent.is_new(&event(line), now))
+            .collect();
+        assert_eq!(taken.len(), 1);
+        // another id is another message, even with the same text
+        let other = line.replace("885196de", "985196de");
+        assert!(recent.is_new(&event(&other), now));
+        assert!(recent.is_new(&event(line), now + REMEMBERED_FOR));
+        let join = ChatBotEvent::Join {
+            user: "carkhy".to_owned(),
+            channel: "captaincallback".to_owned(),
+        };
+        assert!(recent.is_new(&join, now));
+        assert!(recent.is_new(&join, now));
+    }
+
+    #[test]
+    fn untagged_lines_are_told_apart_by_what_they_say() {
+        let now = Instant::now();
+        let mut recent = RecentMessages::default();
+        let hello = ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :Hello";
+        assert!(recent.is_new(&event(hello), now));
+        assert!(!recent.is_new(&event(hello), now + Duration::from_secs(1)));
+        assert!(recent.is_new(&event(&hello.replace("Hello", "Hi")), now));
+        assert!(recent.is_new(&event(&hello.replace("#captain", "#")), now));
+        assert!(recent.is_new(&event(&hello.replace(":carkhy!", ":carkhy2!")), now));
+        assert!(recent.is_new(&event(hello), now + REMEMBERED_FOR));
+        // tags without an id tell the same text apart by the time it was sent
+        let sent = |ts: &str| format!("@tmi-sent-ts={} {}", ts, hello);
+        assert!(recent.is_new(&event(&sent("1507246572675")), now));
+        assert!(!recent.is_new(&event(&sent("1507246572675")), now));
+        assert!(recent.is_new(&event(&sent("1507246572676")), now));
+    }
+
+    #[test]
+    fn only_the_latest_messages_are_remembered() {
+        let now = Instant::now();
+        let mut recent = RecentMessages::default();
+        let message = |id: usize| {
+            ChatBotEvent::TextMessage(TextMessage {
+                message_id: Some(id.to_string()),
+                ..Default::default()
+            })
+        };
+        for id in 0..=CAPACITY {
+            assert!(recent.is_new(&message(id), now));
+        }
+        assert_eq!(recent.seen.len(), CAPACITY);
+        assert!(recent.is_new(&message(0), now));
+        assert!(!recent.is_new(&message(CAPACITY), now));
+    }
+}
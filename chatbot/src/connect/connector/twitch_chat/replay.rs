@@ -0,0 +1,245 @@
+use super::{
+    connection::Connection,
+    connector::{message_lines, reply_lines},
+    irc_message::IrcMessage,
+    outgoing::Outgoing,
+    priority::SendCount,
+    receive::ReceiveEvent,
+    split::Overflow,
+    Priority,
+};
+use crate::connect::{
+    error::{ConnectorError, ParseError},
+    ChatBotEvent,
+};
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{BufRead, Lines, Write},
+    time::Duration,
+};
+
+/// How fast a log is replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTiming {
+    Fast,
+    // waits as long as between the tmi-sent-ts tags, lines without the tag don't wait
+    Original,
+}
+
+/// What a replay went through, reported at the end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub lines: usize,
+    pub parse_errors: usize,
+    pub messages_sent: usize,
+}
+
+impl fmt::Display for ReplaySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Replayed {} lines, {} could not be parsed, the bot would have sent {} messages",
+            self.lines, self.parse_errors, self.messages_sent
+        )
+    }
+}
+
+/// Connection replaying a raw IRC log, one line per log line, e.g. one written with CHAT_LOG.
+/// Whatever the bot sends goes to the sink instead of twitch.
+pub struct ReplaySource<R, W> {
+    log: Lines<R>,
+    timing: ReplayTiming,
+    last_sent_ts: Option<u64>,
+    sink: RefCell<W>,
+    summary: RefCell<ReplaySummary>,
+}
+
+impl<R: BufRead, W: Write> ReplaySource<R, W> {
+    pub fn new(log: R, timing: ReplayTiming, sink: W) -> Self {
+        Self {
+            log: log.lines(),
+            timing,
+            last_sent_ts: None,
+            sink: RefCell::new(sink),
+            summary: RefCell::default(),
+        }
+    }
+
+    pub fn summary(&self) -> ReplaySummary {
+        *self.summary.borrow()
+    }
+
+    // how long to wait before the line, going by its tmi-sent-ts tag
+    fn delay(&mut self, line: &str) -> Duration {
+        let sent_ts = IrcMessage::parse(line)
+            .ok()
+            .and_then(|message| message.tag("tmi-sent-ts")?.parse::<u64>().ok());
+        let Some(sent_ts) = sent_ts else {
+            return Duration::ZERO;
+        };
+        let delay = self
+            .last_sent_ts
+            .map(|last| Duration::from_millis(sent_ts.saturating_sub(last)))
+            .unwrap_or_default();
+        self.last_sent_ts = Some(sent_ts);
+        delay
+    }
+
+    fn record(&self, lines: Vec<Outgoing>) -> Result<(), ConnectorError> {
+        let mut sink = self.sink.borrow_mut();
+        for line in lines {
+            sink.write_all(line.as_str().as_bytes())
+                .map_err(|error| ConnectorError::MessageSendFailed(error.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Connection for ReplaySource<R, W> {
+    async fn next_event(&mut self) -> Option<ChatBotEvent> {
+        loop {
+            let line = match self.log.next()? {
+                Ok(line) => line,
+                Err(error) => {
+                    tracing::error!(%error, "could not read the log");
+                    return None;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.summary.borrow_mut().lines += 1;
+            if self.timing == ReplayTiming::Original {
+                tokio::time::sleep(self.delay(&line)).await;
+            }
+            match ReceiveEvent::parse_from_message(&line) {
+                Ok(ReceiveEvent::ChatBotEvent(event)) => return Some(event),
+                // the connector handles these, nothing for the bot
+                Ok(ReceiveEvent::ConnectorEvent(_)) | Err(ParseError::UnsupportedCommand(_)) => {}
+                Err(error) => {
+                    tracing::warn!(line, %error, "could not parse");
+                    self.summary.borrow_mut().parse_errors += 1;
+                }
+            }
+        }
+    }
+
+    fn send_message(
+        &self,
+        channel: &str,
+        message: &str,
+        overflow: Overflow,
+        _priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        let lines = message_lines(channel, message, overflow)?;
+        self.summary.borrow_mut().messages_sent += lines.len();
+        self.record(lines)
+    }
+
+    fn send_reply(
+        &self,
+        channel: &str,
+        parent_msg_id: &str,
+        message: &str,
+        _priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        let lines = reply_lines(channel, parent_msg_id, message)?;
+        self.summary.borrow_mut().messages_sent += lines.len();
+        self.record(lines)
+    }
+
+    fn join(&self, channel: &str) -> Result<(), ConnectorError> {
+        self.record(vec![Outgoing::join(&[channel])?])
+    }
+
+    fn part(&self, channel: &str) -> Result<(), ConnectorError> {
+        self.record(vec![Outgoing::part(&[channel])?])
+    }
+
+    // the log shows which tags twitch sent, so all of them are understood
+    fn has_capability(&self, _capability: &str) -> bool {
+        true
+    }
+
+    // repeating messages would never end the replay
+    fn schedule(&self, _delay: Duration, _event: ChatBotEvent) {}
+
+    fn queue_depth(&self) -> usize {
+        0
+    }
+
+    fn send_count(&self, _priority: Priority) -> SendCount {
+        SendCount::default()
+    }
+
+    async fn shutdown(
+        &self,
+        _goodbye: Option<&str>,
+        _deadline: Duration,
+    ) -> Result<(), ConnectorError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::EventHandler;
+    use std::{error::Error, ops::ControlFlow};
+
+    // repeats every command
+    struct Echo;
+
+    impl EventHandler for Echo {
+        async fn handle<C: Connection>(
+            &mut self,
+            event: ChatBotEvent,
+            chat: &C,
+        ) -> Result<ControlFlow<()>, Box<dyn Error>> {
+            if let ChatBotEvent::Command(command) = event {
+                chat.send_message(
+                    &command.message.channel,
+                    &command.message.text,
+                    Overflow::Split,
+                    Priority::Response,
+                )?;
+            }
+            Ok(ControlFlow::Continue(()))
+        }
+    }
+
+    const LOG: &str = "\
+:tmi.twitch.tv 001 botname :Welcome, GLHF!
+@tmi-sent-ts=1000 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :hi
+
+@tmi-sent-ts=1300 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!discord
+@broken PRIVMSG
+";
+
+    #[tokio::test]
+    async fn replay_sends_to_the_sink() {
+        let mut replay = ReplaySource::new(LOG.as_bytes(), ReplayTiming::Fast, Vec::new());
+        replay.run(&mut Echo).await.unwrap();
+        assert_eq!(
+            replay.summary(),
+            ReplaySummary {
+                lines: 4,
+                parse_errors: 1,
+                messages_sent: 1,
+            }
+        );
+        assert_eq!(
+            String::from_utf8(replay.sink.into_inner()).unwrap(),
+            "PRIVMSG #captaincallback :!discord\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn original_timing_waits_between_lines() {
+        let mut replay = ReplaySource::new(LOG.as_bytes(), ReplayTiming::Original, Vec::new());
+        let start = std::time::Instant::now();
+        replay.run(&mut Echo).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+}
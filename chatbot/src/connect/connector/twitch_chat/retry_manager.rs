@@ -21,7 +21,10 @@ impl ErrorHandler<ConnectorError> for ExponentialRetryManager {
 
     fn handle(&mut self, attempt: usize, err: ConnectorError) -> RetryPolicy<Self::OutError> {
         match err {
-            ConnectorError::HTTP404 => RetryPolicy::ForwardError(err),
+            // asking again gets the same answer
+            ConnectorError::HTTP404 | ConnectorError::TokenRequestRejected(_) => {
+                RetryPolicy::ForwardError(err)
+            }
             _ => {
                 if attempt > self.max_num_attempts {
                     RetryPolicy::ForwardError(err)
@@ -37,3 +40,83 @@ impl ErrorHandler<ConnectorError> for ExponentialRetryManager {
     }
     fn ok(&mut self, _attempt: usize) {}
 }
+
+/// Waiting time before a reconnect: 1s, 2s, 4s, ... capped at 2 minutes,
+/// less a random part of up to a quarter so that bots don't reconnect in lockstep,
+/// not even at the cap.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    /// The delay before the given attempt, starting at 1. `jitter` is in `[0, 1)`.
+    pub fn delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        let base = self.initial.saturating_mul(factor).min(self.max);
+        // jittered downward, so the cap can't flatten it
+        base - base.mul_f64(jitter.clamp(0.0, 1.0) / 4.0)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(120))
+    }
+}
+
+// good enough to spread reconnects, std hashes with random keys
+pub fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+pub fn random_jitter() -> f64 {
+    (random_u64() >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let backoff = Backoff::default();
+        let delays: Vec<u64> = (1..=9)
+            .map(|attempt| backoff.delay(attempt, 0.0).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 64, 120, 120]);
+        assert_eq!(backoff.delay(u32::MAX, 0.0), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn jitter_takes_off_up_to_a_quarter() {
+        let backoff = Backoff::default();
+        assert_eq!(backoff.delay(3, 0.5), Duration::from_millis(3500));
+        assert_eq!(backoff.delay(3, 1.0), Duration::from_secs(3));
+        for _ in 0..100 {
+            let jitter = random_jitter();
+            assert!((0.0..1.0).contains(&jitter), "{}", jitter);
+        }
+    }
+
+    #[test]
+    fn jitter_survives_the_cap() {
+        let backoff = Backoff::default();
+        assert_eq!(backoff.delay(8, 0.5), Duration::from_secs(105));
+        assert_eq!(backoff.delay(20, 0.99), Duration::from_millis(90_300));
+        let delays: std::collections::HashSet<Duration> = (0..10)
+            .map(|step| backoff.delay(u32::MAX, step as f64 / 10.0))
+            .collect();
+        assert_eq!(delays.len(), 10);
+        assert!(delays
+            .iter()
+            .all(|delay| (Duration::from_secs(90)..=Duration::from_secs(120)).contains(delay)));
+    }
+}
@@ -1,99 +1,40 @@
+use super::{outgoing::Outgoing, stream::ChatStream};
 use crate::connect::error::ConnectorError;
-use std::net::TcpStream;
+use std::io::Write;
 use websocket::{sync::Writer, Message};
 
-pub fn send(sender: &mut Writer<TcpStream>, task: SendTask) -> Result<(), ConnectorError> {
-    let message = Message::text(task.to_string());
+pub fn send(sender: &mut Writer<ChatStream>, line: Outgoing) -> Result<(), ConnectorError> {
+    let message = Message::text(line.as_str());
     sender.send_message(&message).map_err(|err| {
         ConnectorError::MessageSendFailed(format!("Could not send message: {:?}", err))
     })
 }
 
-pub fn send_multiple(
-    sender: &mut Writer<TcpStream>,
-    tasks: Vec<SendTask>,
-) -> Result<(), ConnectorError> {
-    for task in tasks {
-        send(sender, task)?;
-    }
-    Ok(())
-}
-
-pub fn get_login_tasks<'a>(
-    password: &'a str,
-    user_name: &'a str,
-    channel: &'a str,
-) -> Vec<SendTask> {
-    return vec![
-        SendTask::ProvideLoginPassword(password.to_string()),
-        SendTask::ProvideLoginUserName(user_name.to_string()),
-        SendTask::JoinChannel(channel.to_string()),
-        SendTask::RequestCapabilities("membership".to_string()),
-        SendTask::RequestCapabilities("tags".to_string()),
-    ];
-}
-
-pub enum SendTask {
-    PrivateMessage(String, String),
-    ProvideLoginPassword(String),
-    ProvideLoginUserName(String),
-    JoinChannel(String),
-    RequestCapabilities(String),
-    Pong,
+/// Outgoing lines already end in CRLF, so they are written to a TCP stream as they are.
+pub fn write_line(stream: &mut ChatStream, line: Outgoing) -> Result<(), ConnectorError> {
+    stream
+        .write_all(line.as_str().as_bytes())
+        .and_then(|()| stream.flush())
+        .map_err(|err| {
+            ConnectorError::MessageSendFailed(format!("Could not send message: {:?}", err))
+        })
 }
 
-impl ToString for SendTask {
-    fn to_string(&self) -> String {
-        match self {
-            Self::PrivateMessage(channel, message) => format!("PRIVMSG #{} :{}", channel, message),
-            Self::ProvideLoginPassword(password) => format!("PASS oauth:{}", password),
-            Self::ProvideLoginUserName(user_name) => format!("NICK {}", user_name),
-            Self::JoinChannel(channel) => format!("JOIN #{}", channel),
-            Self::RequestCapabilities(capability_name) => {
-                format!("CAP REQ :twitch.tv/{}", capability_name)
-            }
-            Self::Pong => format!("PONG :tmi.twitch.tv"),
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn prints_private_messages_correctly() {
-        let task = SendTask::PrivateMessage("channelname".to_string(), "Message".to_string());
-        assert_eq!(task.to_string(), "PRIVMSG #channelname :Message");
-    }
-
-    #[test]
-    fn prints_login_password_messages_correctly() {
-        let task = SendTask::ProvideLoginPassword("admin123".to_string());
-        assert_eq!(task.to_string(), "PASS oauth:admin123");
-    }
-
-    #[test]
-    fn prints_login_username_messages_correctly() {
-        let task = SendTask::ProvideLoginUserName("user123".to_string());
-        assert_eq!(task.to_string(), "NICK user123");
-    }
-
-    #[test]
-    fn prints_join_channel_messages_correctly() {
-        let task = SendTask::JoinChannel("channel123".to_string());
-        assert_eq!(task.to_string(), "JOIN #channel123");
-    }
-
-    #[test]
-    fn prints_request_capabilities_messages_correctly() {
-        let task = SendTask::RequestCapabilities("capability123".to_string());
-        assert_eq!(task.to_string(), "CAP REQ :twitch.tv/capability123");
-    }
-
-    #[test]
-    fn prints_pong_messages_correctly() {
-        let task = SendTask::Pong;
-        assert_eq!(task.to_string(), "PONG :tmi.twitch.tv");
-    }
+// the channel is joined once twitch confirmed the login
+// anonymous connections send no password, without capabilities there is nothing to request
+pub fn get_login_lines(
+    password: Option<&str>,
+    user_name: &str,
+    capabilities: &[String],
+) -> Result<Vec<Outgoing>, ConnectorError> {
+    let mut lines = Vec::new();
+    if let Some(password) = password {
+        lines.push(Outgoing::pass(password)?);
+    }
+    lines.push(Outgoing::nick(user_name)?);
+    if !capabilities.is_empty() {
+        let capabilities: Vec<&str> = capabilities.iter().map(String::as_str).collect();
+        lines.push(Outgoing::cap_req(&capabilities)?);
+    }
+    Ok(lines)
 }
@@ -0,0 +1,157 @@
+/// Twitch drops chat messages longer than this, counted in characters.
+pub const MAX_MESSAGE_CHARS: usize = 500;
+
+/// What to do with a message longer than twitch allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Send it as several messages ending in "(1/2)", "(2/2)".
+    #[default]
+    Split,
+    /// Cut it off after the last word that fits.
+    Truncate,
+}
+
+/// Splits at spaces, so emotes and other words stay whole. Only a word longer than a
+/// whole message, e.g. text without spaces, is broken between two characters. A long text
+/// of nothing but whitespace has no parts.
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    if text.chars().count() <= max_chars {
+        return vec![text.to_owned()];
+    }
+    // the suffix gets longer with the number of parts, so that number is guessed until it fits
+    let mut count = 2;
+    loop {
+        let suffix = format!(" ({0}/{0})", count).chars().count();
+        let parts = wrap(text, max_chars - suffix);
+        if parts.len() <= count {
+            let total = parts.len();
+            return parts
+                .into_iter()
+                .enumerate()
+                .map(|(index, part)| format!("{} ({}/{})", part, index + 1, total))
+                .collect();
+        }
+        count = parts.len();
+    }
+}
+
+/// Empty for a long text of nothing but whitespace.
+pub fn truncate_message(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+    let Some(mut truncated) = wrap(text, max_chars - 1).into_iter().next() else {
+        return String::new();
+    };
+    truncated.push('…');
+    truncated
+}
+
+/// The messages sent for the text, as the overflow asks. Nothing is sent for a long text of
+/// only whitespace.
+pub fn fit_message(text: &str, overflow: Overflow) -> Vec<String> {
+    match overflow {
+        Overflow::Split => split_message(text, MAX_MESSAGE_CHARS),
+        Overflow::Truncate => Some(truncate_message(text, MAX_MESSAGE_CHARS))
+            .filter(|truncated| !truncated.is_empty())
+            .into_iter()
+            .collect(),
+    }
+}
+
+// words are joined by single spaces into lines of at most `width` characters
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_chars = 0;
+    for word in text.split_whitespace() {
+        let word_chars = word.chars().count();
+        if line_chars > 0 && line_chars + 1 + word_chars <= width {
+            line.push(' ');
+            line.push_str(word);
+            line_chars += 1 + word_chars;
+            continue;
+        }
+        if line_chars > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        let mut chars = word.chars().peekable();
+        line_chars = 0;
+        while chars.peek().is_some() {
+            if line_chars == width {
+                lines.push(std::mem::take(&mut line));
+                line_chars = 0;
+            }
+            line.extend(chars.by_ref().take(width - line_chars));
+            line_chars = line.chars().count();
+        }
+    }
+    if line_chars > 0 {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_messages_are_kept() {
+        assert_eq!(split_message("Hello  Kappa", 500), vec!["Hello  Kappa"]);
+        assert_eq!(truncate_message("Hello Kappa", 500), "Hello Kappa");
+    }
+
+    #[test]
+    fn long_cjk_message_is_split_between_characters() {
+        let text = "你好世界".repeat(300);
+        let parts = split_message(&text, MAX_MESSAGE_CHARS);
+        assert_eq!(parts.len(), 3);
+        let mut joined = String::new();
+        for (index, part) in parts.iter().enumerate() {
+            assert!(part.chars().count() <= MAX_MESSAGE_CHARS);
+            let suffix = format!(" ({}/3)", index + 1);
+            joined.push_str(part.strip_suffix(&suffix).unwrap());
+        }
+        assert_eq!(joined, text);
+    }
+
+    #[test]
+    fn emotes_are_not_split() {
+        let text = "Kappa 顔文字 PogChamp ".repeat(60);
+        let parts = split_message(&text, MAX_MESSAGE_CHARS);
+        for part in &parts {
+            assert!(part.chars().count() <= MAX_MESSAGE_CHARS);
+            let words: Vec<&str> = part.rsplit_once(" (").unwrap().0.split(' ').collect();
+            assert!(words
+                .iter()
+                .all(|word| ["Kappa", "顔文字", "PogChamp"].contains(word)));
+        }
+        let words: usize = parts.iter().map(|part| part.split(' ').count() - 1).sum();
+        assert_eq!(words, 180);
+    }
+
+    #[test]
+    fn suffix_grows_with_the_number_of_parts() {
+        // " (2/2)" leaves room for 25 parts, " (25/25)" for 50
+        let parts = split_message(&"a".repeat(100), 10);
+        assert_eq!(parts.len(), 50);
+        assert_eq!(parts[0], "aa (1/50)");
+        assert_eq!(parts[49], "aa (50/50)");
+    }
+
+    #[test]
+    fn truncated_message_ends_after_a_word() {
+        let text = "日本語 Kappa ".repeat(100);
+        let truncated = truncate_message(&text, MAX_MESSAGE_CHARS);
+        assert!(truncated.chars().count() <= MAX_MESSAGE_CHARS);
+        assert!(truncated.ends_with("Kappa…") || truncated.ends_with("日本語…"));
+    }
+
+    #[test]
+    fn long_whitespace_has_nothing_to_send() {
+        let text = " \n\t".repeat(200);
+        assert_eq!(truncate_message(&text, MAX_MESSAGE_CHARS), "");
+        assert!(split_message(&text, MAX_MESSAGE_CHARS).is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{config::ConnectionSecurity, connect::error::ConnectorError};
+#[cfg(feature = "tls")]
+use native_tls::{TlsConnector, TlsStream};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+};
+#[cfg(feature = "tls")]
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Connection to twitch chat, plain TCP or TLS. Clones share the connection,
+/// so that reading and writing can happen on different threads.
+pub enum ChatStream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Arc<Mutex<TlsStream<TcpStream>>>),
+}
+
+// while waiting for data the TLS stream is unlocked this often, so that lines can be written
+#[cfg(feature = "tls")]
+const TLS_READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+impl ChatStream {
+    /// Certificates are only left unverified when the test harness asks for it.
+    pub fn connect(
+        host: &str,
+        port: u16,
+        security: ConnectionSecurity,
+        verify_certificates: bool,
+    ) -> Result<Self, ConnectorError> {
+        let tcp = TcpStream::connect((host, port)).map_err(|err| {
+            ConnectorError::ExternalServerError(format!(
+                "Could not connect to {}:{}: {:?}",
+                host, port, err
+            ))
+        })?;
+        match security {
+            ConnectionSecurity::Plain => Ok(ChatStream::Plain(tcp)),
+            ConnectionSecurity::Tls => tls(host, tcp, verify_certificates),
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            ChatStream::Plain(tcp) => Ok(ChatStream::Plain(tcp.try_clone()?)),
+            #[cfg(feature = "tls")]
+            ChatStream::Tls(tls) => Ok(ChatStream::Tls(tls.clone())),
+        }
+    }
+
+    /// Close the connection for all clones, errors don't matter anymore.
+    pub fn shutdown(&self) {
+        match self {
+            ChatStream::Plain(tcp) => {
+                let _ = tcp.shutdown(Shutdown::Both);
+            }
+            #[cfg(feature = "tls")]
+            ChatStream::Tls(tls) => {
+                let mut tls = tls.lock().unwrap();
+                let _ = tls.shutdown();
+                let _ = tls.get_ref().shutdown(Shutdown::Both);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn tls(
+    host: &str,
+    tcp: TcpStream,
+    verify_certificates: bool,
+) -> Result<ChatStream, ConnectorError> {
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(!verify_certificates)
+        .build()
+        .map_err(|err| ConnectorError::TlsHandshake(err.to_string()))?;
+    let stream = connector
+        .connect(host, tcp)
+        .map_err(|err| ConnectorError::TlsHandshake(err.to_string()))?;
+    stream
+        .get_ref()
+        .set_read_timeout(Some(TLS_READ_TIMEOUT))
+        .map_err(|err| ConnectorError::TlsHandshake(err.to_string()))?;
+    Ok(ChatStream::Tls(Arc::new(Mutex::new(stream))))
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls(_host: &str, _tcp: TcpStream, _verify: bool) -> Result<ChatStream, ConnectorError> {
+    Err(ConnectorError::TlsHandshake(
+        "built without the tls feature".to_owned(),
+    ))
+}
+
+impl Read for ChatStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ChatStream::Plain(tcp) => tcp.read(buf),
+            #[cfg(feature = "tls")]
+            ChatStream::Tls(tls) => loop {
+                let result = tls.lock().unwrap().read(buf);
+                match result {
+                    // give the writer a chance to take the lock
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        thread::sleep(Duration::from_millis(1))
+                    }
+                    result => return result,
+                }
+            },
+        }
+    }
+}
+
+impl Write for ChatStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ChatStream::Plain(tcp) => tcp.write(buf),
+            #[cfg(feature = "tls")]
+            ChatStream::Tls(tls) => tls.lock().unwrap().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ChatStream::Plain(tcp) => tcp.flush(),
+            #[cfg(feature = "tls")]
+            ChatStream::Tls(tls) => tls.lock().unwrap().flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // a server answering every connection with the given bytes
+    fn fake_server(answer: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.write_all(answer);
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn plain_clones_share_the_connection() {
+        let port = fake_server(b"PING :tmi.twitch.tv\r\n");
+        let stream =
+            ChatStream::connect("127.0.0.1", port, ConnectionSecurity::Plain, true).unwrap();
+        let mut reader = stream.try_clone().unwrap();
+        let mut line = [0; 21];
+        reader.read_exact(&mut line).unwrap();
+        assert_eq!(&line, b"PING :tmi.twitch.tv\r\n");
+        stream.shutdown();
+    }
+
+    #[test]
+    fn failed_tls_handshake_has_its_own_error() {
+        let port = fake_server(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        for verify_certificates in [true, false] {
+            let result = ChatStream::connect(
+                "127.0.0.1",
+                port,
+                ConnectionSecurity::Tls,
+                verify_certificates,
+            );
+            assert!(
+                matches!(result, Err(ConnectorError::TlsHandshake(_))),
+                "{:?}",
+                result.err()
+            );
+        }
+    }
+}
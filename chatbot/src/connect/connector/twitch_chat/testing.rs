@@ -0,0 +1,159 @@
+use super::{
+    connection::Connection,
+    connector::{message_lines, reply_lines},
+    outgoing::Outgoing,
+    priority::SendCount,
+    receive::{parse_line, ReceiveEvent},
+    split::Overflow,
+    Priority,
+};
+use crate::connect::{error::ConnectorError, Badge, ChatBotEvent, SubTier, TextMessage, UserInfo};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, VecDeque},
+    time::Duration,
+};
+
+/// A chat message in carkhy, from a subscriber in their first month if `subscriber`.
+pub fn message(login: &str, text: &str, subscriber: bool) -> TextMessage {
+    TextMessage {
+        channel: "carkhy".to_owned(),
+        text: text.to_owned(),
+        user: UserInfo {
+            name: login.to_owned(),
+            badges: match subscriber {
+                true => vec![Badge::Subscriber {
+                    months: 1,
+                    tier: SubTier::Tier1,
+                }],
+                false => Vec::new(),
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Connection for tests: hands out events parsed from scripted IRC lines and records
+/// every line the bot sends, exactly as it would be written to twitch.
+#[derive(Default)]
+pub struct MockConnection {
+    incoming: VecDeque<ChatBotEvent>,
+    sent: RefCell<Vec<String>>,
+    channels: RefCell<BTreeSet<String>>,
+    capabilities: BTreeSet<String>,
+    scheduled: RefCell<Vec<(Duration, ChatBotEvent)>>,
+}
+
+impl MockConnection {
+    /// Lines that don't result in an event for the chat bot, like PING, are left out.
+    pub fn new(lines: &[&str]) -> Self {
+        let incoming = lines
+            .iter()
+            .filter_map(|line| match parse_line(line)? {
+                ReceiveEvent::ChatBotEvent(event) => Some(event),
+                ReceiveEvent::ConnectorEvent(_) => None,
+            })
+            .collect();
+        Self {
+            incoming,
+            ..Default::default()
+        }
+    }
+
+    /// Pretend twitch granted the capability, e.g. "twitch.tv/tags" for threaded replies.
+    pub fn grant(mut self, capability: &str) -> Self {
+        self.capabilities.insert(capability.to_owned());
+        self
+    }
+
+    /// Every line sent so far, including the terminating CRLF.
+    pub fn sent(&self) -> Vec<String> {
+        self.sent.borrow().clone()
+    }
+
+    /// Events the bot asked for later, they are not handed out again.
+    pub fn scheduled(&self) -> Vec<(Duration, ChatBotEvent)> {
+        self.scheduled.borrow().clone()
+    }
+
+    fn record(&self, lines: Vec<Outgoing>) {
+        let mut sent = self.sent.borrow_mut();
+        sent.extend(lines.iter().map(Outgoing::to_string));
+    }
+}
+
+impl Connection for MockConnection {
+    async fn next_event(&mut self) -> Option<ChatBotEvent> {
+        self.incoming.pop_front()
+    }
+
+    fn send_message(
+        &self,
+        channel: &str,
+        message: &str,
+        overflow: Overflow,
+        _priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        self.record(message_lines(channel, message, overflow)?);
+        Ok(())
+    }
+
+    fn send_reply(
+        &self,
+        channel: &str,
+        parent_msg_id: &str,
+        message: &str,
+        _priority: Priority,
+    ) -> Result<(), ConnectorError> {
+        self.record(reply_lines(channel, parent_msg_id, message)?);
+        Ok(())
+    }
+
+    fn join(&self, channel: &str) -> Result<(), ConnectorError> {
+        if self.channels.borrow_mut().insert(channel.to_owned()) {
+            self.record(vec![Outgoing::join(&[channel])?]);
+        }
+        Ok(())
+    }
+
+    fn part(&self, channel: &str) -> Result<(), ConnectorError> {
+        if self.channels.borrow_mut().remove(channel) {
+            self.record(vec![Outgoing::part(&[channel])?]);
+        }
+        Ok(())
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    fn schedule(&self, delay: Duration, event: ChatBotEvent) {
+        self.scheduled.borrow_mut().push((delay, event));
+    }
+
+    // nothing waits, every line is recorded right away
+    fn queue_depth(&self) -> usize {
+        0
+    }
+
+    fn send_count(&self, _priority: Priority) -> SendCount {
+        SendCount::default()
+    }
+
+    async fn shutdown(
+        &self,
+        goodbye: Option<&str>,
+        _deadline: Duration,
+    ) -> Result<(), ConnectorError> {
+        let mut lines = Vec::new();
+        if let Some(goodbye) = goodbye {
+            for channel in self.channels.borrow().iter() {
+                lines.push(Outgoing::privmsg(channel, goodbye)?);
+            }
+        }
+        lines.push(Outgoing::quit()?);
+        self.record(lines);
+        Ok(())
+    }
+}
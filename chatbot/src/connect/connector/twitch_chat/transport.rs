@@ -0,0 +1,290 @@
+use super::{
+    outgoing::Outgoing,
+    receive::receive_chunk,
+    send::{send, write_line},
+    stream::ChatStream,
+};
+use crate::{
+    config::{ChatTransport, ConnectionSecurity},
+    connect::error::ConnectorError,
+};
+use std::io::Read;
+use tokio::sync::{mpsc, oneshot};
+use websocket::{
+    receiver::Reader,
+    sync::{stream::ReadWritePair, Writer},
+    ClientBuilder,
+};
+
+// big enough for most lines, longer ones are assembled from several reads
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// Reading half of a connection to twitch chat. A chunk may end in the middle of a line.
+pub trait ChunkReader {
+    fn read_chunk(&mut self) -> Result<Vec<u8>, ConnectorError>;
+}
+
+/// Writing half of a connection to twitch chat.
+pub trait LineWriter {
+    fn write_line(&mut self, line: Outgoing) -> Result<(), ConnectorError>;
+
+    // the connection is replaced, errors don't matter anymore
+    fn close(&mut self) {}
+}
+
+/// The writing half of the current connection, owned by a blocking thread of tokio's that
+/// writes the lines handed to it in order. Async tasks await the write, blocking threads wait
+/// for it. The thread ends with the last handle.
+pub struct ConnectionWriter<W> {
+    requests: mpsc::UnboundedSender<WriterRequest<W>>,
+}
+
+enum WriterRequest<W> {
+    Write(Outgoing, oneshot::Sender<Result<(), ConnectorError>>),
+    // the old connection is closed, the following lines go to the new one
+    Replace(W),
+    Close,
+}
+
+impl<W> Clone for ConnectionWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<W: LineWriter + Send + 'static> ConnectionWriter<W> {
+    pub fn spawn(mut writer: W) -> Self {
+        let (requests, mut received) = mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || {
+            while let Some(request) = received.blocking_recv() {
+                match request {
+                    WriterRequest::Write(line, written) => {
+                        // the writer may have stopped waiting
+                        let _ = written.send(writer.write_line(line));
+                    }
+                    WriterRequest::Replace(new_writer) => {
+                        writer.close();
+                        writer = new_writer;
+                    }
+                    WriterRequest::Close => writer.close(),
+                }
+            }
+        });
+        Self { requests }
+    }
+}
+
+impl<W> ConnectionWriter<W> {
+    pub async fn write(&self, line: Outgoing) -> Result<(), ConnectorError> {
+        self.request(line)?
+            .await
+            .map_err(|_| ConnectorError::ShuttingDown)?
+    }
+
+    pub fn replace(&self, writer: W) {
+        let _ = self.requests.send(WriterRequest::Replace(writer));
+    }
+
+    fn request(
+        &self,
+        line: Outgoing,
+    ) -> Result<oneshot::Receiver<Result<(), ConnectorError>>, ConnectorError> {
+        let (written, result) = oneshot::channel();
+        self.requests
+            .send(WriterRequest::Write(line, written))
+            .map_err(|_| ConnectorError::ShuttingDown)?;
+        Ok(result)
+    }
+}
+
+// for the blocking threads, async tasks use write
+impl<W> LineWriter for ConnectionWriter<W> {
+    fn write_line(&mut self, line: Outgoing) -> Result<(), ConnectorError> {
+        self.request(line)?
+            .blocking_recv()
+            .map_err(|_| ConnectorError::ShuttingDown)?
+    }
+
+    // the reader shares the connection, so the receive thread notices and reconnects
+    fn close(&mut self) {
+        let _ = self.requests.send(WriterRequest::Close);
+    }
+}
+
+/// IRC lines are sent as they are over TCP, the websocket wraps them in text messages.
+pub enum TransportReader {
+    Tcp(ChatStream),
+    WebSocket(Reader<ChatStream>),
+}
+
+pub enum TransportWriter {
+    Tcp(ChatStream),
+    WebSocket(Writer<ChatStream>),
+}
+
+/// Open a connection to twitch chat, the login is up to the caller.
+pub fn open(
+    transport: ChatTransport,
+    (host, port): (&str, u16),
+    security: ConnectionSecurity,
+    verify_certificates: bool,
+) -> Result<(TransportReader, TransportWriter), ConnectorError> {
+    let stream = ChatStream::connect(host, port, security, verify_certificates)?;
+    let reader = stream.try_clone().map_err(|err| {
+        ConnectorError::ExternalServerError(format!("Could not split connection: {:?}", err))
+    })?;
+    match transport {
+        ChatTransport::Tcp => Ok((TransportReader::Tcp(reader), TransportWriter::Tcp(stream))),
+        ChatTransport::WebSocket => {
+            let scheme = match security {
+                ConnectionSecurity::Plain => "ws",
+                ConnectionSecurity::Tls => "wss",
+            };
+            let url = format!("{}://{}:{}", scheme, host, port);
+            let chat_client = ClientBuilder::new(&url)
+                .map_err(|err| {
+                    ConnectorError::ExternalServerError(format!("Invalid url: {:?}", err))
+                })?
+                .connect_on(ReadWritePair(reader, stream))?;
+            let (receiver, sender) = chat_client.split().map_err(|err| {
+                ConnectorError::ExternalServerError(format!(
+                    "Could not split connection: {:?}",
+                    err
+                ))
+            })?;
+            Ok((
+                TransportReader::WebSocket(receiver),
+                TransportWriter::WebSocket(sender),
+            ))
+        }
+    }
+}
+
+impl ChunkReader for TransportReader {
+    fn read_chunk(&mut self) -> Result<Vec<u8>, ConnectorError> {
+        match self {
+            TransportReader::Tcp(stream) => {
+                let mut buffer = vec![0; READ_BUFFER_SIZE];
+                match stream.read(&mut buffer) {
+                    Ok(0) => Err(ConnectorError::MessageReceiveFailed(
+                        "Connection closed by twitch".to_owned(),
+                    )),
+                    Ok(read) => {
+                        buffer.truncate(read);
+                        Ok(buffer)
+                    }
+                    Err(err) => Err(ConnectorError::MessageReceiveFailed(format!(
+                        "Could not receive message: {:?}",
+                        err
+                    ))),
+                }
+            }
+            TransportReader::WebSocket(reader) => receive_chunk(reader),
+        }
+    }
+}
+
+impl LineWriter for TransportWriter {
+    fn write_line(&mut self, line: Outgoing) -> Result<(), ConnectorError> {
+        match self {
+            TransportWriter::Tcp(stream) => write_line(stream, line),
+            TransportWriter::WebSocket(writer) => send(writer, line),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            TransportWriter::Tcp(stream) => stream.shutdown(),
+            TransportWriter::WebSocket(writer) => writer.stream.shutdown(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::line_assembler::LineAssembler;
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+    use websocket::{sync::Server, OwnedMessage};
+
+    const PING: &str = "PING :tmi.twitch.tv\r\n";
+
+    // sends a PING split across two writes and hands back the first line it receives
+    fn tcp_server() -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (first, second) = PING.split_at(7);
+            stream.write_all(first.as_bytes()).unwrap();
+            stream.flush().unwrap();
+            stream.write_all(second.as_bytes()).unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            tx.send(line).unwrap();
+        });
+        (port, rx)
+    }
+
+    fn websocket_server() -> (u16, mpsc::Receiver<String>) {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().unwrap();
+            let (first, second) = PING.split_at(7);
+            client
+                .send_message(&OwnedMessage::Text(first.to_owned()))
+                .unwrap();
+            client
+                .send_message(&OwnedMessage::Text(second.to_owned()))
+                .unwrap();
+            if let OwnedMessage::Text(line) = client.recv_message().unwrap() {
+                tx.send(line).unwrap();
+            }
+        });
+        (port, rx)
+    }
+
+    fn exchange(transport: ChatTransport, port: u16) -> Vec<String> {
+        let (mut reader, mut writer) = open(
+            transport,
+            ("127.0.0.1", port),
+            ConnectionSecurity::Plain,
+            true,
+        )
+        .unwrap();
+        let mut assembler = LineAssembler::new();
+        let mut lines = Vec::new();
+        while lines.is_empty() {
+            lines.extend(assembler.push(&reader.read_chunk().unwrap()));
+        }
+        writer
+            .write_line(Outgoing::pong("tmi.twitch.tv").unwrap())
+            .unwrap();
+        lines
+    }
+
+    #[test]
+    fn transports_carry_the_same_lines() {
+        let (tcp_port, tcp_received) = tcp_server();
+        let (websocket_port, websocket_received) = websocket_server();
+        let tcp_lines = exchange(ChatTransport::Tcp, tcp_port);
+        let websocket_lines = exchange(ChatTransport::WebSocket, websocket_port);
+        assert_eq!(tcp_lines, vec!["PING :tmi.twitch.tv"]);
+        assert_eq!(websocket_lines, tcp_lines);
+        assert_eq!(tcp_received.recv().unwrap(), "PONG :tmi.twitch.tv\r\n");
+        assert_eq!(
+            websocket_received.recv().unwrap(),
+            "PONG :tmi.twitch.tv\r\n"
+        );
+    }
+}
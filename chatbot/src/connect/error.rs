@@ -1,6 +1,6 @@
-use super::connector::twitch_chat::send::SendTask;
-use std::sync::mpsc;
+use super::connector::twitch_chat::outgoing::Outgoing;
 use thiserror::Error;
+use tokio::sync::mpsc;
 use websocket::websocket_base;
 
 #[derive(Error, Debug)]
@@ -15,11 +15,27 @@ pub enum ConnectorError {
     HTTP404,
     #[error("Http status 403: forbidden: {0:?}")]
     HTTP403(String),
+    #[error("Twitch rejected the token request: {0}")]
+    TokenRequestRejected(String),
+    #[error("The bot was not authorized in time, start it again for a new code")]
+    AuthorizationExpired,
     #[error("No stored value available: {0}")]
     StoredValueNotAvailable(String),
+    #[error("Invalid outgoing message: {0}")]
+    InvalidOutgoingMessage(String),
+    #[error("TLS handshake with twitch chat failed: {0}")]
+    TlsHandshake(String),
+    #[error(
+        "Twitch rejected the login: {0} (the access token must be valid and sent as oauth:<token>)"
+    )]
+    AuthenticationFailed(String),
+    #[error("Anonymous connections are read-only, log in to send {0}")]
+    ReadOnlyConnection(&'static str),
+    #[error("The connection is shutting down, no more lines are sent")]
+    ShuttingDown,
     // Errors for other crates
     #[error("Send error {0:?}")]
-    MPSCSendError(#[from] mpsc::SendError<SendTask>),
+    MPSCSendError(#[from] mpsc::error::SendError<Outgoing>),
     #[error("Error in crate 'reqwest': {0:?}")]
     ReqwestError(#[from] reqwest::Error),
     #[error("Error in crate 'serde_json': {0:?}")]
@@ -29,3 +45,92 @@ pub enum ConnectorError {
     #[error("Error in crate 'websocket': {0:?}")]
     WebsocketError(#[from] websocket_base::result::WebSocketError),
 }
+
+/// Reasons why a line received from twitch could not be parsed.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("Line contains no command")]
+    MissingCommand,
+    #[error("Malformed prefix {0:?}")]
+    MalformedPrefix(String),
+    #[error("Line has no prefix naming the sender")]
+    MissingPrefix,
+    #[error("Unsupported command {0:?}")]
+    UnsupportedCommand(String),
+    #[error("Line has no channel")]
+    MissingChannel,
+    #[error("Line has no text")]
+    MissingText,
+    #[error("Missing parameter at position {index}")]
+    MissingParameter { index: usize },
+    #[error("Missing tag {0:?}")]
+    MissingTag(String),
+    #[error("Malformed tags at byte offset {offset}")]
+    MalformedTags { offset: usize },
+}
+
+impl ParseError {
+    /// The kind of error without its details, e.g. for the metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::MissingCommand => "missing_command",
+            ParseError::MalformedPrefix(_) => "malformed_prefix",
+            ParseError::MissingPrefix => "missing_prefix",
+            ParseError::UnsupportedCommand(_) => "unsupported_command",
+            ParseError::MissingChannel => "missing_channel",
+            ParseError::MissingText => "missing_text",
+            ParseError::MissingParameter { .. } => "missing_parameter",
+            ParseError::MissingTag(_) => "missing_tag",
+            ParseError::MalformedTags { .. } => "malformed_tags",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn parse_errors_are_displayed_readable() {
+        let cases = [
+            (ParseError::MissingCommand, "Line contains no command"),
+            (
+                ParseError::MalformedPrefix("!user@host".to_owned()),
+                "Malformed prefix \"!user@host\"",
+            ),
+            (
+                ParseError::MissingPrefix,
+                "Line has no prefix naming the sender",
+            ),
+            (
+                ParseError::UnsupportedCommand("CAP".to_owned()),
+                "Unsupported command \"CAP\"",
+            ),
+            (ParseError::MissingChannel, "Line has no channel"),
+            (ParseError::MissingText, "Line has no text"),
+            (
+                ParseError::MissingParameter { index: 0 },
+                "Missing parameter at position 0",
+            ),
+            (
+                ParseError::MissingTag("login".to_owned()),
+                "Missing tag \"login\"",
+            ),
+            (
+                ParseError::MalformedTags { offset: 9 },
+                "Malformed tags at byte offset 9",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn parse_errors_can_be_boxed() {
+        let error: Box<dyn Error> = Box::new(ParseError::MissingText);
+        assert_eq!(error.to_string(), "Line has no text");
+    }
+}
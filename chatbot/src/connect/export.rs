@@ -0,0 +1,223 @@
+//! One JSON object per chat message, e.g. for a browser source overlay.
+//!
+//! The schema is stable, fields are only ever added:
+//! - `type`: `"privmsg"` for chat messages (commands included) or `"usernotice"`
+//! - `channel`: channel name without the leading '#'
+//! - `user`: `{ "login", "display_name", "color" }`, color as `"#RRGGBB"` or null
+//! - `text`: the message, null for user notices without a message
+//! - `badges`: list of `"name"` or `"name/value"`, e.g. `"subscriber/27"`
+//! - `emotes`: list of `{ "id", "start", "end" }` with byte offsets into text, end exclusive
+//! - `timestamp`: milliseconds since the unix epoch as sent by twitch, or null
+//! - `system_message`: only for `"usernotice"`, the text twitch shows for the event
+use super::{Badge, ChatBotEvent, EmoteSpan, TextMessage, UserInfo};
+use serde_json::{json, Value};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Convert an event to its JSON form, None for events which are no chat messages.
+pub fn to_json(event: &ChatBotEvent) -> Option<Value> {
+    match event {
+        ChatBotEvent::TextMessage(message) => Some(text_message_to_json(message)),
+        ChatBotEvent::Command(command) => Some(text_message_to_json(&command.message)),
+        ChatBotEvent::UserNotice(notice) => Some(json!({
+            "type": "usernotice",
+            "channel": notice.channel,
+            "user": user_to_json(&notice.user),
+            "text": notice.text,
+            "badges": badges_to_json(&notice.user.badges),
+            "emotes": [],
+            "timestamp": null,
+            "system_message": notice.system_message,
+        })),
+        _ => None,
+    }
+}
+
+fn text_message_to_json(message: &TextMessage) -> Value {
+    json!({
+        "type": "privmsg",
+        "channel": message.channel,
+        "user": user_to_json(&message.user),
+        "text": message.text,
+        "badges": badges_to_json(&message.user.badges),
+        "emotes": message.emotes.iter().map(emote_to_json).collect::<Vec<_>>(),
+        "timestamp": timestamp(message.timestamp),
+    })
+}
+
+fn user_to_json(user: &UserInfo) -> Value {
+    json!({
+        "login": user.name,
+        "display_name": user.display_name(),
+        "color": user.color.map(|color| {
+            format!("#{:02X}{:02X}{:02X}", color.red, color.green, color.blue)
+        }),
+    })
+}
+
+fn badges_to_json(badges: &[Badge]) -> Vec<String> {
+    badges
+        .iter()
+        .map(|badge| match badge {
+            Badge::Broadcaster => "broadcaster".to_owned(),
+            Badge::Moderator => "moderator".to_owned(),
+            Badge::Vip => "vip".to_owned(),
+            Badge::Subscriber { months, .. } => format!("subscriber/{}", months),
+            Badge::Bits { amount } => format!("bits/{}", amount),
+            Badge::Unknown(name, version) => format!("{}/{}", name, version),
+        })
+        .collect()
+}
+
+fn emote_to_json(emote: &EmoteSpan) -> Value {
+    json!({ "id": emote.id, "start": emote.start, "end": emote.end })
+}
+
+fn timestamp(time: Option<SystemTime>) -> Option<u64> {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .and_then(|since_epoch| since_epoch.as_millis().try_into().ok())
+}
+
+/// Writes the JSON form of chat messages to a sink, one object per line.
+pub struct JsonExporter {
+    sink: Box<dyn Write>,
+}
+
+impl JsonExporter {
+    /// The target is either "stdout" or the path of a file the messages are appended to.
+    pub fn new(target: &str) -> io::Result<Self> {
+        Ok(Self {
+            sink: open_sink(target)?,
+        })
+    }
+
+    pub fn export(&mut self, event: &ChatBotEvent) -> io::Result<()> {
+        write_json_line(&mut self.sink, event)
+    }
+}
+
+/// Writes every event as normalized IRC line, tags sorted by name.
+pub struct IrcLogger {
+    sink: Box<dyn Write>,
+}
+
+impl IrcLogger {
+    /// The target is either "stdout" or the path of a file the lines are appended to.
+    pub fn new(target: &str) -> io::Result<Self> {
+        Ok(Self {
+            sink: open_sink(target)?,
+        })
+    }
+
+    pub fn log(&mut self, event: &ChatBotEvent) -> io::Result<()> {
+        if let Some(line) = event.to_irc_line() {
+            writeln!(self.sink, "{}", line)?;
+            self.sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn open_sink(target: &str) -> io::Result<Box<dyn Write>> {
+    Ok(match target {
+        "stdout" => Box::new(io::stdout()),
+        path => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+    })
+}
+
+fn write_json_line(sink: &mut dyn Write, event: &ChatBotEvent) -> io::Result<()> {
+    if let Some(json) = to_json(event) {
+        writeln!(sink, "{}", json)?;
+        sink.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{Color, Command, CommandType, SubTier, UserNotice, UserNoticeKind};
+    use std::time::Duration;
+
+    fn carkhy() -> UserInfo {
+        UserInfo {
+            name: "carkhy".to_owned(),
+            badges: vec![
+                Badge::Subscriber {
+                    months: 27,
+                    tier: SubTier::Tier1,
+                },
+                Badge::Vip,
+            ],
+            display_name: Some("Carkhy".to_owned()),
+            color: Some(Color {
+                red: 0x1E,
+                green: 0x90,
+                blue: 0xFF,
+            }),
+        }
+    }
+
+    fn export_to_string(events: &[ChatBotEvent]) -> String {
+        let mut output = Vec::new();
+        for event in events {
+            write_json_line(&mut output, event).unwrap();
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn exported_messages_match_golden_file() {
+        let message = TextMessage {
+            text: "Hello Kappa".to_owned(),
+            user: carkhy(),
+            channel: "captaincallback".to_owned(),
+            timestamp: Some(UNIX_EPOCH + Duration::from_millis(1637614002702)),
+            emotes: vec![EmoteSpan {
+                id: "25".to_owned(),
+                start: 6,
+                end: 11,
+            }],
+            ..Default::default()
+        };
+        let events = [
+            ChatBotEvent::TextMessage(message),
+            ChatBotEvent::Command(Command {
+                kind: CommandType::Help,
+                options: Vec::new(),
+                message: TextMessage {
+                    text: "!help".to_owned(),
+                    user: UserInfo {
+                        name: "captaincallback".to_owned(),
+                        badges: vec![Badge::Broadcaster],
+                        ..Default::default()
+                    },
+                    channel: "captaincallback".to_owned(),
+                    ..Default::default()
+                },
+            }),
+            ChatBotEvent::UserNotice(UserNotice {
+                kind: UserNoticeKind::Resub {
+                    tier: SubTier::Tier1,
+                    cumulative_months: 6,
+                    streak_months: None,
+                },
+                user: carkhy(),
+                channel: "captaincallback".to_owned(),
+                system_message: "Carkhy subscribed at Tier 1.".to_owned(),
+                text: None,
+            }),
+            ChatBotEvent::Part {
+                user: "carkhy".to_owned(),
+                channel: "captaincallback".to_owned(),
+            },
+        ];
+        assert_eq!(
+            export_to_string(&events),
+            include_str!("test_data/export_golden.jsonl")
+        );
+    }
+}
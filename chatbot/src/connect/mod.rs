@@ -1,6 +1,23 @@
 mod connector;
 mod error;
+mod export;
 mod types;
 
-pub use connector::TwitchChatConnector;
-pub use types::{Badge, ChatBotEvent, Command, CommandType, TextMessage, UserInfo};
+#[cfg(fuzzing)]
+pub use connector::fuzz_receive;
+pub use connector::{
+    connect_websocket, fit_message, random_jitter, required_scopes, spawn_eventsub,
+    AccessTokenDispenser, Backoff, ChatStream, Connection, EventHandler, Health, HealthLimits,
+    Identity, Overflow, Priority, ReplaySource, ReplayTiming, Report, SharedTokens, TokenInfo,
+    TwitchChatConnector, EVENTSUB_URL, MAX_MESSAGE_CHARS,
+};
+#[cfg(test)]
+pub use connector::{eventsub_testing, testing};
+pub use error::ConnectorError;
+pub use export::{to_json, IrcLogger, JsonExporter};
+pub use types::{
+    ApiAction, ApiAnswer, ApiCall, ApiRequest, Badge, ChatBotEvent, ClearChat, ClearMessage, Color,
+    Command, CommandType, ConnectionState, EmoteSpan, HypeTrainStage, InternalEvent, Notice,
+    NoticeKind, PaidMessage, RawTags, Redemption, ReplyParent, RoomState, SubTier, Tags,
+    TextMessage, UserInfo, UserLevel, UserNotice, UserNoticeKind, UserState, Whisper,
+};
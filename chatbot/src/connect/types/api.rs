@@ -0,0 +1,128 @@
+use serde_json::Value;
+use std::{
+    fmt,
+    sync::{mpsc, Arc, Mutex},
+};
+
+/// What a call of the HTTP API or the control socket asks the bot to do, see
+/// [ChatBotEvent::Api](super::ChatBotEvent).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiRequest {
+    // without the leading '#'
+    pub channel: String,
+    pub action: ApiAction,
+}
+
+/// Names are without the prefix, texts are checked like the chat commands check them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiAction {
+    Commands,
+    AddCommand {
+        name: String,
+        response: String,
+    },
+    EditCommand {
+        name: String,
+        response: String,
+    },
+    RemoveCommand {
+        name: String,
+    },
+    // the calls by day of one command, or the totals of all
+    CommandStats {
+        name: Option<String>,
+    },
+    Quotes,
+    AddQuote {
+        text: String,
+    },
+    EditQuote {
+        id: u64,
+        text: String,
+    },
+    RemoveQuote {
+        id: u64,
+    },
+    Counters,
+    AddCounter {
+        name: String,
+        response: String,
+    },
+    SetCounter {
+        name: String,
+        value: u64,
+    },
+    RemoveCounter {
+        name: String,
+    },
+    // the repeating messages, replaced when the name exists already
+    Timers,
+    SetTimer {
+        name: String,
+        interval: u64,
+        text: String,
+    },
+    RemoveTimer {
+        name: String,
+    },
+    Balance {
+        login: String,
+    },
+    Leaderboard {
+        count: usize,
+    },
+    SendMessage {
+        text: String,
+    },
+    // only from the control socket
+    Join,
+    Part,
+    // a chat command, run at the level `Admin`
+    RunCommand {
+        text: String,
+    },
+}
+
+/// The HTTP status and the JSON body of the answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiAnswer {
+    pub status: u16,
+    pub body: Value,
+}
+
+/// A request and the way back to the HTTP thread waiting for its answer.
+#[derive(Clone)]
+pub struct ApiCall {
+    pub request: ApiRequest,
+    reply: Arc<Mutex<Option<mpsc::Sender<ApiAnswer>>>>,
+}
+
+impl ApiCall {
+    pub fn new(request: ApiRequest) -> (Self, mpsc::Receiver<ApiAnswer>) {
+        let (sender, receiver) = mpsc::channel();
+        let reply = Arc::new(Mutex::new(Some(sender)));
+        (Self { request, reply }, receiver)
+    }
+
+    /// Only the first answer counts, nobody may be waiting anymore.
+    pub fn answer(&self, answer: ApiAnswer) {
+        if let Some(reply) = self.reply.lock().unwrap().take() {
+            let _ = reply.send(answer);
+        }
+    }
+}
+
+impl fmt::Debug for ApiCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ApiCall").field(&self.request).finish()
+    }
+}
+
+// the same call, not only the same request
+impl PartialEq for ApiCall {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.reply, &other.reply)
+    }
+}
+
+impl Eq for ApiCall {}
@@ -1,6 +1,7 @@
-use super::user_info::UserInfo;
+use super::text_message::TextMessage;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CommandType {
     Help,
     Info,
@@ -11,11 +12,54 @@ pub enum CommandType {
     Dynamic(String),
     NewRepeating,
     RemoveRepeating,
+    Quote,
+    Join,
+    Part,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl CommandType {
+    /// The command's name is without the prefix, e.g. "help".
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "help" => CommandType::Help,
+            "info" => CommandType::Info,
+            "newcommand" => CommandType::NewCommand,
+            "removecommand" => CommandType::RemoveCommand,
+            "slap" => CommandType::Slap,
+            "discord" => CommandType::Discord,
+            "newrepeating" => CommandType::NewRepeating,
+            "removerepeating" => CommandType::RemoveRepeating,
+            "quote" => CommandType::Quote,
+            "join" => CommandType::Join,
+            "part" => CommandType::Part,
+            _ => CommandType::Dynamic(name.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Command {
     pub kind: CommandType,
     pub options: Vec<String>,
-    pub user: UserInfo,
+    // the chat message the command was parsed from
+    pub message: TextMessage,
+}
+
+impl Command {
+    /// The message as a command, None unless it starts with '!' and a name.
+    pub fn parse(message: TextMessage) -> Option<Self> {
+        let mut words = message.text.split(' ');
+        let name = words
+            .next()
+            .and_then(|word| word.strip_prefix('!'))
+            .filter(|name| !name.is_empty())?;
+        let kind = CommandType::from_name(name);
+        let options = words.map(String::from).collect();
+        Some(Command {
+            kind,
+            options,
+            message,
+        })
+    }
 }
@@ -1,15 +1,230 @@
 use uuid::Uuid;
 
-use super::{text_message::TextMessage, Command};
+use super::{
+    text_message::TextMessage, ApiCall, ClearChat, ClearMessage, Command, Notice, Redemption,
+    RoomState, SubTier, UserNotice, UserState, Whisper,
+};
 
-#[derive(Debug, PartialEq)]
+/// State of the connection to twitch chat, see [ChatBotEvent::Connection].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectionState {
+    Connected,
+    // attempt starts at 1
+    Reconnecting { attempt: u32 },
+    // gave up reconnecting, no more events will follow
+    Disconnected,
+}
+
+/// How far a hype train is, see [ChatBotEvent::HypeTrain].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HypeTrainStage {
+    Begin,
+    // twitch tells about each contribution, not only new levels
+    Progress,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChatBotEvent {
     TextMessage(TextMessage),
     Command(Command),
-    Part(String),
-    Join(String),
-    // timer sends a message to the bot, String is the name of the message.
+    UserNotice(UserNotice),
+    ClearChat(ClearChat),
+    ClearMessage(ClearMessage),
+    RoomState(RoomState),
+    UserState(UserState),
+    GlobalUserState(UserState),
+    Notice(Notice),
+    // channel started hosting target, None when hosting ended (HOSTTARGET)
+    HostTarget {
+        channel: String,
+        target: Option<String>,
+        viewers: u32,
+    },
+    Whisper(Whisper),
+    // channels without the leading '#'
+    Part {
+        user: String,
+        channel: String,
+    },
+    Join {
+        user: String,
+        channel: String,
+    },
+    // chatters already in the channel when joining (353), the list can be
+    // split across several Names events and is complete with EndOfNames (366)
+    Names {
+        channel: String,
+        users: Vec<String>,
+    },
+    EndOfNames {
+        channel: String,
+    },
+    // the connection to twitch was lost or restored, the initial connection is not reported
+    Connection(ConnectionState),
+    // keep-alive of the server, the connector already answered it with a PONG.
+    // Only forwarded when FORWARD_PINGS is set
+    Ping {
+        server: String,
+    },
+    // a new EventSub session, the bot subscribes to the channels' events with its id.
+    // Not sent again when twitch moves the session, the subscriptions move along
+    EventSubWelcome {
+        session_id: String,
+    },
+    Redemption(Redemption),
+    // told by EventSub, chat doesn't tell about follows
+    Follow {
+        channel: String,
+        login: String,
+        name: String,
+    },
+    // told by EventSub, the USERNOTICE in chat is what gets thanked
+    Subscribed {
+        channel: String,
+        login: String,
+        name: String,
+        tier: SubTier,
+        gift: bool,
+    },
+    // told by EventSub, the contributors who gave the most are by display name
+    HypeTrain {
+        channel: String,
+        stage: HypeTrainStage,
+        level: u32,
+        contributors: Vec<String>,
+    },
+    // twitch answered whether the channel is live, scheduled by the bot itself. Or EventSub
+    // told that the stream went live or offline
+    LiveStatus {
+        channel: String,
+        live: bool,
+    },
+    // twitch's shield mode turned on or off, told by EventSub or after !shield. asked when a
+    // moderator turned it with the command, they are answered even if nothing changed
+    ShieldMode {
+        channel: String,
+        active: bool,
+        asked: bool,
+    },
+    // scheduled by the bot itself, twitch never sends these
+    Internal(InternalEvent),
+    // a call of the HTTP API, answered before the next event is handled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Api(ApiCall),
+    // the bot is asked to stop, handlers save what they want to keep. No more events follow
+    Shutdown,
+}
+
+/// What the bot schedules for itself, see [ChatBotEvent::Internal].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InternalEvent {
+    // timer sends a message to the bot, name is the name of the message in the channel.
     // uuid is the message id, used to deduplicate
     // messages when a command is redefined
-    TimedMessage(String, Uuid),
+    TimedMessage {
+        channel: String,
+        name: String,
+        id: Uuid,
+    },
+    // the timers check which of them are due, scheduled by the bot itself once a minute
+    TimerTick,
+    // the entries of the schedule due this minute are sent, scheduled by the bot itself at the
+    // start of every minute
+    ScheduleTick,
+    // twitch is still making the clip with id, the bot asks again whether it can be
+    // watched. Scheduled by the bot itself, attempt starts at 1
+    ClipPending {
+        channel: String,
+        id: String,
+        edit_url: String,
+        attempt: u32,
+    },
+    // a raid's pause of slow mode is over, unless a later raid with another id extended it.
+    // Scheduled by the bot itself
+    RestoreSlowMode {
+        channel: String,
+        id: Uuid,
+    },
+    // the next actions of a `!nuke`, paced to stay within twitch's rate limit.
+    // Scheduled by the bot itself
+    NukeTick,
+    // the challenge with id wasn't answered in time, unless the duel is over already.
+    // Scheduled by the bot itself
+    DuelExpired {
+        channel: String,
+        id: Uuid,
+    },
+    // the raffle with id ends after its duration, unless it ended already. Scheduled by the bot itself
+    RaffleEnd {
+        channel: String,
+        id: Uuid,
+    },
+    // the twitch poll with id should be over, the bot asks twitch for its results.
+    // Scheduled by the bot itself, attempt starts at 1
+    TwitchPollPending {
+        channel: String,
+        id: String,
+        attempt: u32,
+    },
+    // the poll with id ends after its duration, unless it ended already. Scheduled by the bot itself
+    PollEnd {
+        channel: String,
+        id: Uuid,
+    },
+    // twitch confirmed that the user who wrote the keyword of the raffle with id follows
+    // the channel. Scheduled by the bot itself
+    RaffleEntry {
+        channel: String,
+        id: Uuid,
+        login: String,
+        name: String,
+        tickets: u32,
+    },
+    // the hint, the end or the next question of a trivia game is due, unless the timer with
+    // id was replaced by an answer. Scheduled by the bot itself
+    TriviaTimer {
+        channel: String,
+        id: Uuid,
+    },
+    // the grace period of a user in the viewer queue who parted is over, they are dropped
+    // unless they came back since the part with id. Scheduled by the bot itself
+    QueueGrace {
+        channel: String,
+        login: String,
+        id: Uuid,
+    },
+    // the reminder with id is due, or overdue after a restart. Scheduled by the bot itself
+    ReminderDue {
+        id: u64,
+    },
+    // the lurks check whether the streams they are in went offline, scheduled by the bot itself
+    // every few minutes while anyone lurks
+    LurkTick,
+    // watch time is credited to the viewers of live streams, scheduled by the bot itself
+    // once a minute
+    WatchTick,
+    // the stream status asks twitch whether the streams are live, scheduled by the bot itself
+    // every couple of minutes without EventSub
+    StreamStatusTick,
+    // the stream went offline a while ago, unless it came back since. Scheduled by the bot itself
+    StreamEnded {
+        channel: String,
+        id: u64,
+    },
+    // twitch limited the whispers, the whisper is sent again. Scheduled by the bot itself,
+    // attempt starts at 2
+    WhisperRetry {
+        channel: String,
+        login: String,
+        text: String,
+        fallback: Option<String>,
+        attempt: u32,
+    },
+    // the points check whether an interval is over, scheduled by the bot itself once a minute
+    PointsTick,
 }
@@ -1,9 +1,27 @@
+mod api;
 mod command;
 mod event;
+mod moderation;
+mod notice;
+mod redemption;
+mod room_state;
+mod tags;
 mod text_message;
 mod user_info;
+mod user_notice;
+mod user_state;
+mod whisper;
 
+pub use api::{ApiAction, ApiAnswer, ApiCall, ApiRequest};
 pub use command::{Command, CommandType};
-pub use event::ChatBotEvent;
-pub use text_message::TextMessage;
-pub use user_info::{Badge, UserInfo};
+pub use event::{ChatBotEvent, ConnectionState, HypeTrainStage, InternalEvent};
+pub use moderation::{ClearChat, ClearMessage};
+pub use notice::{Notice, NoticeKind};
+pub use redemption::Redemption;
+pub use room_state::RoomState;
+pub use tags::{RawTags, Tags};
+pub use text_message::{EmoteSpan, PaidMessage, ReplyParent, TextMessage};
+pub use user_info::{Badge, Color, UserInfo, UserLevel};
+pub use user_notice::{SubTier, UserNotice, UserNoticeKind};
+pub use user_state::UserState;
+pub use whisper::Whisper;
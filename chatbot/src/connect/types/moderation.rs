@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+/// A user was timed out or banned, or the whole chat was cleared (CLEARCHAT).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClearChat {
+    // channel without the leading '#'
+    pub channel: String,
+    // None when the whole chat was cleared
+    pub target_user: Option<String>,
+    // None for permanent bans
+    pub duration: Option<Duration>,
+}
+
+/// A single message was deleted by a moderator (CLEARMSG).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClearMessage {
+    // login of the user whose message was deleted
+    pub login: String,
+    // channel without the leading '#'
+    pub channel: String,
+    // id tag of the deleted message
+    pub target_message_id: String,
+    // text of the deleted message
+    pub text: String,
+}
@@ -0,0 +1,21 @@
+/// msg-id of a NOTICE (https://dev.twitch.tv/docs/irc/msg-id/)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NoticeKind {
+    MsgRatelimit,
+    MsgBanned,
+    MsgTimedout,
+    UnrecognizedCmd,
+    Other(String),
+}
+
+/// Information from the server, e.g. why a message was not sent (NOTICE).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Notice {
+    // channel without the leading '#', None for notices not bound to a channel (NOTICE *)
+    pub channel: Option<String>,
+    // None when the notice came without tags, e.g. before login
+    pub kind: Option<NoticeKind>,
+    pub text: String,
+}
@@ -0,0 +1,17 @@
+/// A viewer redeemed a reward of channel points, told by EventSub rather than chat.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Redemption {
+    // of the redemption, to fulfill it
+    pub id: String,
+    // without the leading '#'
+    pub channel: String,
+    pub login: String,
+    pub display_name: String,
+    // what the viewer typed, empty unless the reward asks for it
+    pub input: String,
+    pub reward_id: String,
+    pub reward: String,
+    // channel points
+    pub cost: u64,
+}
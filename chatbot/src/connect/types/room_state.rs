@@ -0,0 +1,28 @@
+/// Chat settings of a channel (ROOMSTATE).
+/// Twitch sends all settings on join but only the changed ones afterwards,
+/// so every setting is None when it was not part of the message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomState {
+    // channel without the leading '#'
+    pub channel: String,
+    pub emote_only: Option<bool>,
+    // -1 when followers-only mode is off, otherwise the minutes a user must have followed
+    pub followers_only: Option<i32>,
+    // unique chat mode
+    pub r9k: Option<bool>,
+    // seconds a user has to wait between messages, 0 when slow mode is off
+    pub slow: Option<u32>,
+    pub subs_only: Option<bool>,
+}
+
+impl RoomState {
+    /// Apply the settings contained in a ROOMSTATE delta.
+    pub fn update(&mut self, delta: RoomState) {
+        self.emote_only = delta.emote_only.or(self.emote_only);
+        self.followers_only = delta.followers_only.or(self.followers_only);
+        self.r9k = delta.r9k.or(self.r9k);
+        self.slow = delta.slow.or(self.slow);
+        self.subs_only = delta.subs_only.or(self.subs_only);
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+};
+
+/// Tags borrowed from the line as `key=value;key=value`.
+/// Lookups scan the tags, a value is only unescaped (and copied) when it is requested.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tags<'a> {
+    raw: &'a str,
+}
+
+impl<'a> Tags<'a> {
+    pub fn new(raw: &'a str) -> Self {
+        Self { raw }
+    }
+
+    /// Get the unescaped value of a tag, a tag without '=' has an empty value.
+    /// Tags are scanned from the end, so for repeated keys the last value counts.
+    pub fn get(&self, key: &str) -> Option<Cow<'a, str>> {
+        self.raw
+            .rsplit(';')
+            .map(|key_val_pair| key_val_pair.split_once('=').unwrap_or((key_val_pair, "")))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| unescape_tag_value(value))
+    }
+
+    /// All tags in the order they were sent, with unescaped values.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, Cow<'a, str>)> + 'a {
+        self.raw
+            .split(';')
+            .filter(|key_val_pair| !key_val_pair.is_empty())
+            .map(|key_val_pair| match key_val_pair.split_once('=') {
+                Some((key, value)) => (key, unescape_tag_value(value)),
+                None => (key_val_pair, Cow::Borrowed("")),
+            })
+    }
+}
+
+/// Tags kept from a line as sent, for messages that outlive the line. Like [Tags] they are
+/// scanned on lookup, [RawTags::to_map] copies them all.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawTags(String);
+
+impl RawTags {
+    pub fn tags(&self) -> Tags<'_> {
+        Tags::new(&self.0)
+    }
+
+    // for handlers, the built-in ones read the fields parsed from the tags
+    #[allow(dead_code)]
+    pub fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        self.tags().get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Cow<'_, str>)> {
+        self.tags().iter()
+    }
+
+    /// All tags with unescaped values, for the last of repeated keys.
+    #[allow(dead_code)]
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.iter()
+            .map(|(key, value)| (key.to_owned(), value.into_owned()))
+            .collect()
+    }
+}
+
+impl From<Tags<'_>> for RawTags {
+    fn from(tags: Tags<'_>) -> Self {
+        Self(tags.raw.to_owned())
+    }
+}
+
+// the same tags in another order, e.g. sorted when written back, are equal
+impl PartialEq for RawTags {
+    fn eq(&self, other: &Self) -> bool {
+        fn sorted(tags: &RawTags) -> BTreeMap<&str, Cow<'_, str>> {
+            tags.iter().collect()
+        }
+        sorted(self) == sorted(other)
+    }
+}
+
+impl Eq for RawTags {}
+
+// https://ircv3.net/specs/extensions/message-tags.html#escaping-values
+fn unescape_tag_value(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(codepoint) = chars.next() {
+        if codepoint != '\\' {
+            unescaped.push(codepoint);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some(':') => unescaped.push(';'),
+            Some('\\') => unescaped.push('\\'),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            // invalid escapes drop the backslash, a trailing backslash is dropped entirely
+            Some(other) => unescaped.push(other),
+            None => (),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_tags_with_empty_values() {
+        let tags = Tags::new("badge-info=;badges=moderator/1;color=#FF0000;flags");
+        assert_eq!(tags.get("badge-info").unwrap(), "");
+        assert_eq!(tags.get("badges").unwrap(), "moderator/1");
+        assert_eq!(tags.get("color").unwrap(), "#FF0000");
+        assert_eq!(tags.get("flags").unwrap(), "");
+        assert_eq!(tags.iter().count(), 4);
+    }
+
+    #[test]
+    fn parsing_tags_with_escaped_values() {
+        let tags = Tags::new(r"system-msg=a\sb\:c\\d\re\nf;broken=trailing\;unknown=\x");
+        assert_eq!(tags.get("system-msg").unwrap(), "a b;c\\d\re\nf");
+        assert_eq!(tags.get("broken").unwrap(), "trailing");
+        assert_eq!(tags.get("unknown").unwrap(), "x");
+    }
+
+    #[test]
+    fn looking_up_tags_lazily() {
+        let tags = Tags::new(r"color=#FF0000;display-name=Carkhy;color=#00FF00;system-msg=a\sb");
+        assert_eq!(tags.get("color").unwrap(), "#00FF00");
+        assert!(matches!(
+            tags.get("display-name"),
+            Some(Cow::Borrowed("Carkhy"))
+        ));
+        assert!(matches!(tags.get("system-msg"), Some(Cow::Owned(_))));
+        assert_eq!(tags.get("colo"), None);
+        let keys: Vec<&str> = tags.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["color", "display-name", "color", "system-msg"]);
+        assert_eq!(Tags::default().iter().count(), 0);
+    }
+
+    #[test]
+    fn raw_tags_are_equal_in_any_order() {
+        let kept = RawTags::from(Tags::new(r"color=#FF0000;system-msg=a\sb;color=#00FF00"));
+        assert_eq!(kept.get("color").unwrap(), "#00FF00");
+        assert_eq!(kept.to_map().len(), 2);
+        assert_eq!(
+            kept,
+            RawTags::from(Tags::new(r"color=#00FF00;system-msg=a\sb"))
+        );
+        assert_ne!(kept, RawTags::from(Tags::new("color=#00FF00")));
+        assert_eq!(RawTags::default().iter().count(), 0);
+    }
+}
@@ -1,7 +1,161 @@
-use super::UserInfo;
+use std::{fmt, time::SystemTime};
 
-#[derive(Debug, PartialEq)]
+use super::{RawTags, UserInfo, UserLevel};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextMessage {
     pub text: String,
     pub user: UserInfo,
+    // channel the message was sent to, without the leading '#'
+    pub channel: String,
+    // IRCv3 tags sent along with the message as received, unescaped when looked up.
+    // Empty when the tags capability was not requested.
+    pub tags: RawTags,
+    // native twitch emotes occurring in the text, ordered by their position
+    pub emotes: Vec<EmoteSpan>,
+    // bits cheered with this message, None for messages without a (valid) bits tag
+    pub bits: Option<u32>,
+    // sent with /me, the text is already unwrapped from the CTCP ACTION
+    pub is_action: bool,
+    // the message this one answers to in a thread, from the reply-parent-* tags
+    pub reply_to: Option<ReplyParent>,
+    // time twitch received the message, from the tmi-sent-ts tag
+    pub timestamp: Option<SystemTime>,
+    // unique id of the message, referenced when deleting it. None without the tags capability
+    pub message_id: Option<String>,
+    // first message of the user in this channel ever, from the first-msg tag
+    pub first_msg: bool,
+    // the user chatted before and came back after a while, from the returning-chatter tag
+    pub returning_chatter: bool,
+    // highest privilege the badges of the user grant
+    pub level: UserLevel,
+    // the user paid to pin the message (Hype Chat), from the pinned-chat-paid-* tags
+    pub paid: Option<PaidMessage>,
+}
+
+impl TextMessage {
+    /// Check whether the sender has at least the given level.
+    pub fn has_level(&self, level: UserLevel) -> bool {
+        self.level >= level
+    }
+
+    /// Text of the message with cheermotes like `Cheer100` removed,
+    /// so only the words typed by the user remain.
+    pub fn text_without_cheermotes(&self) -> String {
+        if self.bits.is_none() {
+            return self.text.clone();
+        }
+        self.text
+            .split_whitespace()
+            .filter(|word| !is_cheermote(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// a cheermote is a name followed by the amount of bits, e.g. Cheer100 or BibleThump50
+fn is_cheermote(word: &str) -> bool {
+    let name = word.trim_end_matches(|c: char| c.is_ascii_digit());
+    name.len() < word.len() && !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Position of an emote within the text of a message.
+/// `start` and `end` are byte offsets into the text, so `&text[start..end]` is the emote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmoteSpan {
+    pub id: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The message a threaded reply answers to, as sent along with the reply.
+/// Only the id is required, the other fields are empty when their tag is missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplyParent {
+    pub message_id: String,
+    pub user_login: String,
+    pub display_name: String,
+    pub body: String,
+}
+
+/// Payment for an elevated "Hype Chat" message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PaidMessage {
+    // amount in the smallest unit of the currency, 500 with exponent 2 are 5.00
+    pub amount: u64,
+    pub exponent: u32,
+    // ISO 4217 code like USD, unknown codes are kept as sent
+    pub currency: String,
+    // 1 to 10, higher levels are pinned longer
+    pub level: u8,
+}
+
+impl fmt::Display for PaidMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.exponent as usize;
+        let amount = format!("{:0>width$}", self.amount, width = digits + 1);
+        let (whole, fraction) = amount.split_at(amount.len() - digits);
+        if fraction.is_empty() {
+            write!(f, "{} {}", whole, self.currency)
+        } else {
+            write!(f, "{}.{} {}", whole, fraction, self.currency)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cheer(text: &str, bits: Option<u32>) -> TextMessage {
+        TextMessage {
+            text: text.to_owned(),
+            bits,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cheermotes_are_removed() {
+        let message = cheer("Cheer100 thanks for the stream BibleThump50", Some(150));
+        assert_eq!(message.text_without_cheermotes(), "thanks for the stream");
+    }
+
+    #[test]
+    fn text_is_unchanged_without_bits() {
+        let message = cheer("I have 2 cats and a dog called Rex1", None);
+        assert_eq!(
+            message.text_without_cheermotes(),
+            "I have 2 cats and a dog called Rex1"
+        );
+    }
+
+    fn paid(amount: u64, exponent: u32, currency: &str) -> String {
+        PaidMessage {
+            amount,
+            exponent,
+            currency: currency.to_owned(),
+            level: 1,
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn paid_amounts_are_shown_as_decimals() {
+        assert_eq!(paid(500, 2, "USD"), "5.00 USD");
+        assert_eq!(paid(1999, 2, "EUR"), "19.99 EUR");
+        assert_eq!(paid(5, 2, "USD"), "0.05 USD");
+        assert_eq!(paid(1000, 0, "JPY"), "1000 JPY");
+        assert_eq!(paid(1500, 3, "XYZ"), "1.500 XYZ");
+    }
+
+    #[test]
+    fn numbers_are_kept() {
+        let message = cheer("Cheer10 gg 100", Some(10));
+        assert_eq!(message.text_without_cheermotes(), "gg 100");
+    }
 }
@@ -1,21 +1,124 @@
-use std::collections::HashSet;
+use super::SubTier;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-pub struct Badge {
-    pub name: String,
-    pub level: u16,
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Badge {
+    Broadcaster,
+    Moderator,
+    Vip,
+    // months are taken from the badge-info tag, the tier from the badge version
+    Subscriber { months: u32, tier: SubTier },
+    Bits { amount: u32 },
+    // badges we don't model: badge name and version
+    Unknown(String, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserInfo {
     pub name: String,
-    pub badges: HashSet<Badge>,
+    pub badges: Vec<Badge>,
+    // capitalized (or localized) name from the display-name tag, None when twitch sent it empty
+    pub display_name: Option<String>,
+    // chat color chosen by the user, None when the user never picked one
+    pub color: Option<Color>,
 }
 
-impl UserInfo {
-    pub fn has_elevated_rights(&self) -> bool {
-        self.badges
+/// Privilege of a user in the channel, ordered from `Everyone` up to `Broadcaster`. `Admin`
+/// is above, for the bot's admins in a whisper; no badge grants it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UserLevel {
+    #[default]
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+    Admin,
+}
+
+impl UserLevel {
+    /// The highest level any of the badges grants.
+    /// The broadcaster has no moderator badge but ranks above moderators anyway.
+    pub fn from_badges(badges: &[Badge]) -> Self {
+        badges
             .iter()
-            .any(|badge| badge.name == "broadcaster" || badge.name == "moderator")
+            .map(|badge| match badge {
+                Badge::Broadcaster => UserLevel::Broadcaster,
+                Badge::Moderator => UserLevel::Moderator,
+                Badge::Vip => UserLevel::Vip,
+                Badge::Subscriber { .. } => UserLevel::Subscriber,
+                Badge::Bits { .. } | Badge::Unknown(..) => UserLevel::Everyone,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl UserInfo {
+    /// Get the name to show in chat: the display name if available, the login name otherwise.
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_badge_determines_level() {
+        let level = |badges: &[Badge]| UserLevel::from_badges(badges);
+        assert_eq!(level(&[]), UserLevel::Everyone);
+        assert_eq!(level(&[Badge::Bits { amount: 100 }]), UserLevel::Everyone);
+        assert_eq!(
+            level(&[Badge::Subscriber {
+                months: 3,
+                tier: SubTier::Tier1
+            }]),
+            UserLevel::Subscriber
+        );
+        assert_eq!(
+            level(&[
+                Badge::Subscriber {
+                    months: 3,
+                    tier: SubTier::Tier1
+                },
+                Badge::Vip
+            ]),
+            UserLevel::Vip
+        );
+        assert_eq!(
+            level(&[
+                Badge::Vip,
+                Badge::Moderator,
+                Badge::Subscriber {
+                    months: 3,
+                    tier: SubTier::Tier1
+                }
+            ]),
+            UserLevel::Moderator
+        );
+        assert_eq!(
+            level(&[
+                Badge::Subscriber {
+                    months: 3,
+                    tier: SubTier::Tier1
+                },
+                Badge::Broadcaster
+            ]),
+            UserLevel::Broadcaster
+        );
+        assert!(UserLevel::Broadcaster > UserLevel::Moderator);
+        assert!(UserLevel::Subscriber > UserLevel::Everyone);
     }
 }
@@ -0,0 +1,55 @@
+use super::UserInfo;
+
+/// Subscription plan from msg-param-sub-plan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SubTier {
+    Prime,
+    #[default]
+    Tier1,
+    Tier2,
+    Tier3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UserNoticeKind {
+    // streak_months is only set when the user chose to share their streak
+    Sub {
+        tier: SubTier,
+        cumulative_months: u32,
+        streak_months: Option<u32>,
+    },
+    Resub {
+        tier: SubTier,
+        cumulative_months: u32,
+        streak_months: Option<u32>,
+    },
+    SubGift {
+        recipient: String,
+    },
+    SubMysteryGift {
+        count: u32,
+    },
+    // from is the display name of the raiding channel
+    Raid {
+        from: String,
+        viewers: u32,
+    },
+    // msg-id of notices we don't model
+    Unknown(String),
+}
+
+/// Notice about a user event like a subscription or a raid (USERNOTICE).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserNotice {
+    pub kind: UserNoticeKind,
+    pub user: UserInfo,
+    // channel without the leading '#'
+    pub channel: String,
+    // message twitch itself shows in chat, e.g. "carkhy subscribed at Tier 1."
+    pub system_message: String,
+    // message the user chose to share along with the event
+    pub text: Option<String>,
+}
@@ -0,0 +1,15 @@
+use super::{Badge, Color};
+
+/// State of the bot's own user, sent by twitch on connect (GLOBALUSERSTATE)
+/// and after joining a channel or sending a message (USERSTATE).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserState {
+    // channel without the leading '#', None for GLOBALUSERSTATE
+    pub channel: Option<String>,
+    pub badges: Vec<Badge>,
+    pub display_name: Option<String>,
+    pub color: Option<Color>,
+    // ids of the emote sets the bot may use
+    pub emote_sets: Vec<String>,
+}
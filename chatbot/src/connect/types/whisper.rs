@@ -0,0 +1,12 @@
+use super::{RawTags, UserInfo};
+
+/// Private message sent directly to the bot (WHISPER).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Whisper {
+    pub text: String,
+    pub user: UserInfo,
+    // login of the receiving user, i.e. the bot itself
+    pub recipient: String,
+    pub tags: RawTags,
+}
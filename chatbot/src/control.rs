@@ -0,0 +1,325 @@
+//! The control socket at `control.socket`, for scripts on the same host like stream deck
+//! macros. Each line is a JSON request, e.g. `{"command":"join","channel":"carkhy"}`, and is
+//! answered with a line like `{"ok":true,"joined":"carkhy"}` or `{"ok":false,"error":"..."}`.
+//! The bot answers between chat events, like it answers the HTTP API.
+use crate::connect::{ApiAction, ApiAnswer, ApiCall, ApiRequest, ChatBotEvent};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    fs, io,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+    sync::{mpsc::RecvTimeoutError, Arc},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::Semaphore,
+};
+
+// the longest request line in bytes, a longer one ends the connection
+const MAX_REQUEST: usize = 4096;
+// twitch's logins are at most this long
+const MAX_CHANNEL: usize = 25;
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case", deny_unknown_fields)]
+enum Request {
+    Send { channel: String, text: String },
+    // a chat command like "!timers off", run by an admin
+    Run { channel: String, text: String },
+    Join { channel: String },
+    Part { channel: String },
+    Reload,
+    Status,
+}
+
+/// How a request gets to the bot, and what answers the ones the bot doesn't.
+#[derive(Clone)]
+pub struct Control {
+    forward: Arc<dyn Fn(ChatBotEvent) + Send + Sync>,
+    status: Arc<dyn Fn() -> Value + Send + Sync>,
+    reload: Arc<dyn Fn() -> Result<String, String> + Send + Sync>,
+    // for the client to send a line, and for the bot to answer it
+    timeout: Duration,
+}
+
+fn failed(error: impl Into<String>) -> Value {
+    json!({ "ok": false, "error": error.into() })
+}
+
+// the answer of the bot with `ok` added, or its error
+fn reply(answer: ApiAnswer) -> Value {
+    if answer.status >= 400 {
+        return failed(answer.body["error"].as_str().unwrap_or("failed"));
+    }
+    let mut body = answer.body;
+    body["ok"] = json!(true);
+    body
+}
+
+fn channel(channel: &str) -> Result<String, Value> {
+    let name = channel.trim_start_matches('#').to_lowercase();
+    let valid =
+        name.len() <= MAX_CHANNEL && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match valid && !name.is_empty() {
+        true => Ok(name),
+        false => Err(failed(format!("{:?} is no channel name", channel))),
+    }
+}
+
+impl Control {
+    pub fn new(
+        forward: impl Fn(ChatBotEvent) + Send + Sync + 'static,
+        status: impl Fn() -> Value + Send + Sync + 'static,
+        reload: impl Fn() -> Result<String, String> + Send + Sync + 'static,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            forward: Arc::new(forward),
+            status: Arc::new(status),
+            reload: Arc::new(reload),
+            timeout,
+        }
+    }
+
+    /// The answer to one line.
+    async fn answer(&self, line: &str) -> Value {
+        let request = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(invalid) => return failed(format!("invalid request: {}", invalid)),
+        };
+        let (channel, action) = match request {
+            Request::Status => return json!({ "ok": true, "status": (self.status)() }),
+            Request::Reload => {
+                return match (self.reload)() {
+                    Ok(report) => json!({ "ok": true, "reload": report }),
+                    Err(error) => failed(error),
+                }
+            }
+            Request::Send { channel, text } => (channel, ApiAction::SendMessage { text }),
+            Request::Run { channel, text } => {
+                if text.trim().is_empty() {
+                    return failed("text is empty");
+                }
+                (channel, ApiAction::RunCommand { text })
+            }
+            Request::Join { channel } => (channel, ApiAction::Join),
+            Request::Part { channel } => (channel, ApiAction::Part),
+        };
+        let channel = match self::channel(&channel) {
+            Ok(channel) => channel,
+            Err(failed) => return failed,
+        };
+        let (call, answer) = ApiCall::new(ApiRequest { channel, action });
+        (self.forward)(ChatBotEvent::Api(call));
+        let timeout = self.timeout;
+        let answer = tokio::task::spawn_blocking(move || answer.recv_timeout(timeout)).await;
+        match answer {
+            Ok(Ok(answer)) => reply(answer),
+            Ok(Err(RecvTimeoutError::Timeout)) => failed("the bot did not answer in time"),
+            Ok(Err(RecvTimeoutError::Disconnected)) | Err(_) => failed("the bot is stopping"),
+        }
+    }
+
+    // one line after the other until the client is done, too slow or sends too much
+    async fn client(&self, stream: UnixStream) -> io::Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let mut limited = (&mut read).take(MAX_REQUEST as u64 + 1);
+            let reply = match tokio::time::timeout(
+                self.timeout,
+                limited.read_until(b'\n', &mut line),
+            )
+            .await
+            {
+                Ok(Ok(0)) => return Ok(()),
+                Ok(read) => {
+                    read?;
+                    if line.len() > MAX_REQUEST {
+                        let error = format!("requests are at most {} bytes", MAX_REQUEST);
+                        return self.send(&mut write, failed(error)).await;
+                    }
+                    match std::str::from_utf8(&line) {
+                        Ok(text) if text.trim().is_empty() => continue,
+                        Ok(text) => self.answer(text.trim()).await,
+                        Err(_) => failed("requests are UTF-8"),
+                    }
+                }
+                Err(_) => return self.send(&mut write, failed("timed out")).await,
+            };
+            self.send(&mut write, reply).await?;
+        }
+    }
+
+    // a client that doesn't read its answers is disconnected as well
+    async fn send(&self, write: &mut (impl AsyncWriteExt + Unpin), reply: Value) -> io::Result<()> {
+        let line = format!("{}\n", reply);
+        match tokio::time::timeout(self.timeout, write.write_all(line.as_bytes())).await {
+            Ok(written) => written,
+            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+/// Listens on the socket until the bot stops, only the bot's user may connect. A socket left
+/// behind by an earlier run is replaced, any other file at the path is an error.
+pub fn serve(path: &Path, max_clients: usize, control: Control) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, "not a socket")),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    let clients = Arc::new(Semaphore::new(max_clients));
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(error) => {
+                    tracing::warn!(%error, "could not accept a control client");
+                    continue;
+                }
+            };
+            let control = control.clone();
+            let Ok(client) = clients.clone().try_acquire_owned() else {
+                tracing::warn!(
+                    max_clients,
+                    "refused a control client, too many are connected"
+                );
+                tokio::spawn(async move {
+                    let (_, mut write) = stream.into_split();
+                    let _ = control.send(&mut write, failed("too many clients")).await;
+                });
+                continue;
+            };
+            tokio::spawn(async move {
+                if let Err(error) = control.client(stream).await {
+                    tracing::debug!(%error, "control client disconnected");
+                }
+                drop(client);
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env, process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    // the bot accepts every call
+    fn control(timeout: Duration) -> Control {
+        Control::new(
+            |event| {
+                if let ChatBotEvent::Api(call) = event {
+                    call.answer(ApiAnswer {
+                        status: 202,
+                        body: json!({ "channel": call.request.channel }),
+                    });
+                }
+            },
+            || json!({ "status": "ok" }),
+            || Err("Invalid value for control.timeout: must be at least 1 second".to_owned()),
+            timeout,
+        )
+    }
+
+    fn socket() -> std::path::PathBuf {
+        static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+        let socket = SOCKETS.fetch_add(1, Ordering::Relaxed);
+        env::temp_dir().join(format!("chatbot-control-{}-{}.sock", process::id(), socket))
+    }
+
+    async fn request(stream: &mut BufReader<UnixStream>, line: &str) -> Value {
+        stream.get_mut().write_all(line.as_bytes()).await.unwrap();
+        let mut answer = String::new();
+        stream.read_line(&mut answer).await.unwrap();
+        serde_json::from_str(&answer).unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_are_checked() {
+        let control = control(Duration::from_secs(1));
+        let answer = |line: &'static str| {
+            let control = control.clone();
+            async move { control.answer(line).await }
+        };
+        assert_eq!(
+            answer(r##"{"command":"join","channel":"#CarKhy"}"##).await,
+            json!({ "ok": true, "channel": "carkhy" })
+        );
+        assert_eq!(
+            answer(r#"{"command":"status"}"#).await,
+            json!({ "ok": true, "status": { "status": "ok" } })
+        );
+        assert_eq!(
+            answer(r#"{"command":"reload"}"#).await["error"],
+            "Invalid value for control.timeout: must be at least 1 second"
+        );
+        assert_eq!(
+            answer(r#"{"command":"join","channel":"car khy"}"#).await,
+            failed("\"car khy\" is no channel name")
+        );
+        assert_eq!(
+            answer(r#"{"command":"run","channel":"carkhy","text":" "}"#).await,
+            failed("text is empty")
+        );
+        for invalid in [
+            "join carkhy",
+            r#"{"command":"leave","channel":"carkhy"}"#,
+            r#"{"command":"send","channel":"carkhy"}"#,
+            r#"{"command":"join","channel":"carkhy","as":"admin"}"#,
+        ] {
+            let answer = answer(invalid).await;
+            assert_eq!(answer["ok"], false, "{}", invalid);
+            assert!(answer["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("invalid request: "));
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_are_limited() {
+        let path = socket();
+        serve(&path, 1, control(Duration::from_millis(300))).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mut first = BufReader::new(UnixStream::connect(&path).await.unwrap());
+        let answer = request(&mut first, "{\"command\":\"status\"}\n\n").await;
+        assert_eq!(answer["ok"], true);
+        // turned away while the first is connected
+        let mut second = BufReader::new(UnixStream::connect(&path).await.unwrap());
+        assert_eq!(
+            request(&mut second, "{\"command\":\"status\"}\n").await,
+            failed("too many clients")
+        );
+        // a silent client is disconnected, which lets the next one in
+        let mut timed_out = String::new();
+        first.read_line(&mut timed_out).await.unwrap();
+        assert_eq!(timed_out, "{\"error\":\"timed out\",\"ok\":false}\n");
+        assert_eq!(first.read_line(&mut timed_out).await.unwrap(), 0);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut third = BufReader::new(UnixStream::connect(&path).await.unwrap());
+        let long = format!("{}\n", "x".repeat(MAX_REQUEST + 1));
+        assert_eq!(
+            request(&mut third, &long).await,
+            failed("requests are at most 4096 bytes")
+        );
+        let mut closed = String::new();
+        assert_eq!(third.read_line(&mut closed).await.unwrap(), 0);
+        let _ = fs::remove_file(&path);
+    }
+}
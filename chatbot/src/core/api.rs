@@ -0,0 +1,506 @@
+//! The calls of the HTTP API, answered with the same methods the chat commands use, so that
+//! names and texts are checked alike. Every change is written to `api.log` in the storage.
+use super::{
+    bot::{repeat, RepeatingMessage},
+    calendar::{timestamp, Date},
+    commands::{
+        Change, Quote, Refusal, SharedCommandStats, SharedCustomCommands, SharedQuotes, Tally,
+    },
+    points::SharedPoints,
+    ChatBotCommand,
+};
+use crate::{
+    connect::{ApiAction, ApiAnswer, ApiRequest, Overflow, MAX_MESSAGE_CHARS},
+    storage::Storage,
+};
+use serde_json::{json, Value};
+use std::{collections::HashMap, time::SystemTime};
+
+const AUDIT_LOG: &str = "api";
+// who the changes are by, in the audit log and in the quotes
+const ACTOR: &str = "API";
+// the longest leaderboard
+const MAX_COUNT: usize = 100;
+
+/// What the API reads and changes.
+pub struct Handles<'a> {
+    pub custom: &'a SharedCustomCommands,
+    pub quotes: &'a SharedQuotes,
+    pub points: &'a SharedPoints,
+    pub stats: &'a SharedCommandStats,
+    pub storage: &'a Storage,
+    // of the request's channel
+    pub repeating: &'a mut HashMap<String, RepeatingMessage>,
+}
+
+fn answer(status: u16, body: Value) -> ApiAnswer {
+    ApiAnswer { status, body }
+}
+
+fn error(status: u16, message: impl Into<String>) -> ApiAnswer {
+    answer(status, json!({ "error": message.into() }))
+}
+
+fn refused(name: &str, refusal: Refusal) -> ApiAnswer {
+    match refusal {
+        Refusal::Builtin => error(409, format!("{} is a built-in command", name)),
+        Refusal::Exists => error(409, format!("{} exists already", name)),
+        Refusal::Missing => error(404, format!("there is no {}", name)),
+        Refusal::Taken(command) => error(409, format!("{} calls {} already", name, command)),
+        Refusal::Template(template) => error(422, template.to_string()),
+        Refusal::Script(script) => error(422, script.to_string()),
+    }
+}
+
+// like in chat: a single lowercase word, and a text that fits into a chat message
+fn check_name(name: &str) -> Result<String, ApiAnswer> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(error(422, "the name must be a single word"));
+    }
+    Ok(name.to_lowercase())
+}
+
+fn check_text(field: &str, text: &str) -> Result<(), ApiAnswer> {
+    if text.trim().is_empty() {
+        return Err(error(422, format!("{} is empty", field)));
+    }
+    if text.chars().count() > MAX_MESSAGE_CHARS {
+        return Err(error(
+            422,
+            format!("{} is longer than {} characters", field, MAX_MESSAGE_CHARS),
+        ));
+    }
+    Ok(())
+}
+
+// "2026-10-14 20:46:42 #captaincallback !hello: added by API"
+fn audit(storage: &Storage, channel: &str, target: &str, action: &str) {
+    let line = format!(
+        "{} #{} {}: {} by {}",
+        timestamp(SystemTime::now()),
+        channel,
+        target,
+        action,
+        ACTOR
+    );
+    if let Err(error) = storage.append(AUDIT_LOG, &line) {
+        tracing::warn!(%error, "could not log the API call");
+    }
+}
+
+/// The answer, and what the bot sends or schedules for the call.
+pub fn handle(handles: &mut Handles, request: &ApiRequest) -> (ApiAnswer, Option<ChatBotCommand>) {
+    match run(handles, request) {
+        Ok(result) => result,
+        Err(answer) => (answer, None),
+    }
+}
+
+fn run(
+    handles: &mut Handles,
+    request: &ApiRequest,
+) -> Result<(ApiAnswer, Option<ChatBotCommand>), ApiAnswer> {
+    let channel = request.channel.as_str();
+    let changed = |target: &str, action: &str| audit(handles.storage, channel, target, action);
+    let answer = match &request.action {
+        ApiAction::Commands => {
+            let custom = handles.custom.borrow();
+            let commands: Vec<Value> = custom
+                .commands(channel)
+                .map(|(name, command)| {
+                    let mut listed = json!({
+                        "name": name,
+                        "response": command.response,
+                        "aliases": command.aliases,
+                        "count": command.count,
+                    });
+                    if let Some(script) = &command.script {
+                        listed["script"] = json!(script);
+                    }
+                    listed
+                })
+                .collect();
+            answer(200, json!({ "commands": commands }))
+        }
+        ApiAction::AddCommand { name, response } => {
+            let name = check_name(name)?;
+            check_text("response", response)?;
+            let result = handles.custom.borrow_mut().add(channel, &name, response);
+            result.map_err(|refusal| refused(&name, refusal))?;
+            changed(&name, "command added");
+            answer(201, json!({ "name": name }))
+        }
+        ApiAction::EditCommand { name, response } => {
+            check_text("response", response)?;
+            let result = handles.custom.borrow_mut().edit(channel, name, response);
+            result.map_err(|refusal| refused(name, refusal))?;
+            changed(name, "command changed");
+            answer(200, json!({ "name": name }))
+        }
+        ApiAction::RemoveCommand { name } => {
+            let result = handles.custom.borrow_mut().remove(channel, name);
+            result.map_err(|refusal| refused(name, refusal))?;
+            changed(name, "command removed");
+            answer(200, json!({ "removed": name }))
+        }
+        ApiAction::CommandStats { name: Some(name) } => {
+            let stats = handles.stats.borrow();
+            let days: Vec<Value> = stats
+                .days(channel, name)
+                .into_iter()
+                .map(|(day, tally)| {
+                    let mut listed = tally_json(tally);
+                    listed["date"] = json!(day);
+                    listed
+                })
+                .collect();
+            answer(200, json!({ "name": name, "days": days }))
+        }
+        ApiAction::CommandStats { name: None } => {
+            let stats = handles.stats.borrow();
+            let totals: serde_json::Map<String, Value> = stats
+                .totals(channel)
+                .iter()
+                .map(|(name, tally)| (name.to_string(), tally_json(tally)))
+                .collect();
+            answer(200, json!({ "commands": totals }))
+        }
+        ApiAction::Quotes => {
+            let quotes = handles.quotes.borrow();
+            let quotes: Vec<Value> = quotes
+                .list(channel)
+                .into_iter()
+                .map(|(id, quote)| quote_json(id, quote))
+                .collect();
+            answer(200, json!({ "quotes": quotes }))
+        }
+        ApiAction::AddQuote { text } => {
+            check_text("text", text)?;
+            let quote = Quote {
+                text: text.to_owned(),
+                added_by: ACTOR.to_owned(),
+                date: Date::of(SystemTime::now()).to_string(),
+                game: None,
+            };
+            let id = handles.quotes.borrow_mut().add(channel, quote);
+            changed(&format!("quote #{}", id), "added");
+            answer(201, json!({ "id": id }))
+        }
+        ApiAction::EditQuote { id, text } => {
+            check_text("text", text)?;
+            let mut quotes = handles.quotes.borrow_mut();
+            let quote = quotes
+                .edit(channel, *id, text)
+                .ok_or_else(|| error(404, format!("there is no quote #{}", id)))?;
+            let body = quote_json(*id, quote);
+            changed(&format!("quote #{}", id), "changed");
+            answer(200, body)
+        }
+        ApiAction::RemoveQuote { id } => {
+            handles
+                .quotes
+                .borrow_mut()
+                .remove(channel, *id)
+                .ok_or_else(|| error(404, format!("there is no quote #{}", id)))?;
+            changed(&format!("quote #{}", id), "removed");
+            answer(200, json!({ "removed": id }))
+        }
+        ApiAction::Counters => {
+            let custom = handles.custom.borrow();
+            let counters: Vec<Value> = custom
+                .commands(channel)
+                .filter_map(|(name, command)| {
+                    let counter = command.counter.as_ref()?;
+                    Some(json!({
+                        "name": name,
+                        "response": command.response,
+                        "value": counter.value,
+                        "open": counter.open,
+                    }))
+                })
+                .collect();
+            answer(200, json!({ "counters": counters }))
+        }
+        ApiAction::AddCounter { name, response } => {
+            let name = check_name(name)?;
+            if Change::from_name(&name).is_some() {
+                return Err(error(422, "the name can't end with + or -"));
+            }
+            check_text("response", response)?;
+            let result = handles
+                .custom
+                .borrow_mut()
+                .add_counter(channel, &name, response);
+            result.map_err(|refusal| refused(&name, refusal))?;
+            changed(&name, "counter added");
+            answer(201, json!({ "name": name }))
+        }
+        ApiAction::SetCounter { name, value } => {
+            let result = handles
+                .custom
+                .borrow_mut()
+                .change(channel, name, Change::Set(*value));
+            let value = result.map_err(|refusal| refused(name, refusal))?;
+            changed(name, &format!("counter set to {}", value));
+            answer(200, json!({ "name": name, "value": value }))
+        }
+        ApiAction::RemoveCounter { name } => {
+            let is_counter = handles
+                .custom
+                .borrow()
+                .get(channel, name)
+                .is_some_and(|command| command.counter.is_some());
+            if !is_counter {
+                return Err(refused(name, Refusal::Missing));
+            }
+            let result = handles.custom.borrow_mut().remove(channel, name);
+            result.map_err(|refusal| refused(name, refusal))?;
+            changed(name, "counter removed");
+            answer(200, json!({ "removed": name }))
+        }
+        ApiAction::Timers => {
+            let mut timers: Vec<&RepeatingMessage> = handles.repeating.values().collect();
+            timers.sort_by(|a, b| a.name.cmp(&b.name));
+            let timers: Vec<Value> = timers
+                .iter()
+                .map(|timer| {
+                    json!({
+                        "name": timer.name,
+                        "interval": timer.interval.as_secs(),
+                        "text": timer.text,
+                    })
+                })
+                .collect();
+            answer(200, json!({ "timers": timers }))
+        }
+        ApiAction::SetTimer {
+            name,
+            interval,
+            text,
+        } => {
+            let name = check_name(name)?;
+            check_text("text", text)?;
+            if *interval == 0 {
+                return Err(error(422, "the interval must be at least 1 second"));
+            }
+            let status = if handles.repeating.contains_key(&name) {
+                200
+            } else {
+                201
+            };
+            let callback = repeat(handles.repeating, channel, &name, *interval, text);
+            changed(&name, &format!("timer set to every {}s", interval));
+            return Ok((answer(status, json!({ "name": name })), Some(callback)));
+        }
+        ApiAction::RemoveTimer { name } => {
+            handles
+                .repeating
+                .remove(name)
+                .ok_or_else(|| error(404, format!("there is no timer {}", name)))?;
+            changed(name, "timer removed");
+            answer(200, json!({ "removed": name }))
+        }
+        ApiAction::Balance { login } => {
+            let points = handles.points.borrow().balance(channel, login);
+            answer(
+                200,
+                json!({ "login": login.to_lowercase(), "points": points }),
+            )
+        }
+        ApiAction::Leaderboard { count } => {
+            let points = handles.points.borrow();
+            let top: Vec<Value> = points
+                .top(channel, (*count).min(MAX_COUNT))
+                .into_iter()
+                .map(|(login, points)| json!({ "login": login, "points": points }))
+                .collect();
+            answer(200, json!({ "leaderboard": top }))
+        }
+        ApiAction::SendMessage { text } => {
+            check_text("text", text)?;
+            changed("chat", "message sent");
+            let send = ChatBotCommand::SendMessage {
+                channel: channel.to_owned(),
+                text: text.to_owned(),
+                overflow: Overflow::Split,
+            };
+            return Ok((answer(202, json!({ "sent": true })), Some(send)));
+        }
+        // the bot answers these itself, see ChatBot::api
+        ApiAction::Join | ApiAction::Part | ApiAction::RunCommand { .. } => {
+            return Err(error(501, "not answered by the API"))
+        }
+    };
+    Ok((answer, None))
+}
+
+// the users are counted, not named
+fn tally_json(tally: &Tally) -> Value {
+    json!({
+        "succeeded": tally.succeeded,
+        "cooldown": tally.cooldown,
+        "denied": tally.denied,
+        "users": tally.users.len(),
+    })
+}
+
+fn quote_json(id: u64, quote: &Quote) -> Value {
+    json!({
+        "id": id,
+        "text": quote.text,
+        "added_by": quote.added_by,
+        "date": quote.date,
+        "game": quote.game,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        connect::{ApiAction, ApiAnswer, ApiCall, ApiRequest, ChatBotEvent, InternalEvent},
+        core::{timestamp, ChatBot, ChatBotCommand},
+        storage::Storage,
+    };
+    use serde_json::json;
+    use std::time::SystemTime;
+
+    // answered like the bot answers the HTTP thread
+    fn call(bot: &mut ChatBot, action: ApiAction) -> (ApiAnswer, Option<ChatBotCommand>) {
+        let request = ApiRequest {
+            channel: "captaincallback".to_owned(),
+            action,
+        };
+        let (call, answer) = ApiCall::new(request);
+        let command = bot.handle_event(ChatBotEvent::Api(call));
+        (answer.try_recv().unwrap(), command)
+    }
+
+    fn bot(storage: &Storage) -> ChatBot {
+        ChatBot::load(&Config::default(), storage.clone()).unwrap()
+    }
+
+    #[test]
+    fn commands_are_checked_like_in_chat() {
+        let storage = Storage::memory();
+        let mut bot = bot(&storage);
+        let before = SystemTime::now();
+        let add = |name: &str, response: &str| ApiAction::AddCommand {
+            name: name.to_owned(),
+            response: response.to_owned(),
+        };
+        let (answer, _) = call(&mut bot, add("Hello", "Hi $(user)!"));
+        assert_eq!(answer.status, 201);
+        assert_eq!(answer.body, json!({ "name": "hello" }));
+        let (answer, _) = call(&mut bot, add("hello", "again"));
+        assert_eq!(answer.body, json!({ "error": "hello exists already" }));
+        assert_eq!(answer.status, 409);
+        let (answer, _) = call(&mut bot, add("discord", "ours"));
+        assert_eq!(
+            answer.body,
+            json!({ "error": "discord is a built-in command" })
+        );
+        let (answer, _) = call(&mut bot, add("two words", "text"));
+        assert_eq!(answer.status, 422);
+        let (answer, _) = call(&mut bot, add("long", &"a".repeat(501)));
+        assert_eq!(
+            answer.body,
+            json!({ "error": "response is longer than 500 characters" })
+        );
+        let (answer, _) = call(&mut bot, add("broken", "$(random 1)"));
+        assert_eq!(answer.status, 422);
+        let (answer, _) = call(
+            &mut bot,
+            ApiAction::RemoveCommand {
+                name: "bye".to_owned(),
+            },
+        );
+        assert_eq!(answer.status, 404);
+
+        let (answer, _) = call(&mut bot, ApiAction::Commands);
+        assert_eq!(
+            answer.body["commands"],
+            json!([{ "name": "hello", "response": "Hi $(user)!", "aliases": [], "count": 0 }])
+        );
+        // only the changes are logged
+        let logged = [before, SystemTime::now()].iter().any(|time| {
+            let line = format!(
+                "{} #captaincallback hello: command added by API",
+                timestamp(*time)
+            );
+            storage.has_line("api", &line).unwrap()
+        });
+        assert!(logged);
+    }
+
+    #[test]
+    fn counters_quotes_and_timers_change() {
+        let storage = Storage::memory();
+        let mut bot = bot(&storage);
+        let (answer, _) = call(
+            &mut bot,
+            ApiAction::AddCounter {
+                name: "deaths".to_owned(),
+                response: "died $(value) times".to_owned(),
+            },
+        );
+        assert_eq!(answer.status, 201);
+        let (answer, _) = call(
+            &mut bot,
+            ApiAction::SetCounter {
+                name: "deaths".to_owned(),
+                value: 12,
+            },
+        );
+        assert_eq!(answer.body, json!({ "name": "deaths", "value": 12 }));
+
+        let (answer, _) = call(
+            &mut bot,
+            ApiAction::AddQuote {
+                text: "so it begins".to_owned(),
+            },
+        );
+        assert_eq!(answer.body, json!({ "id": 1 }));
+        let (answer, _) = call(
+            &mut bot,
+            ApiAction::EditQuote {
+                id: 2,
+                text: "never said".to_owned(),
+            },
+        );
+        assert_eq!(answer.status, 404);
+
+        let (answer, command) = call(
+            &mut bot,
+            ApiAction::SetTimer {
+                name: "discord".to_owned(),
+                interval: 600,
+                text: "Join the discord!".to_owned(),
+            },
+        );
+        assert_eq!(answer.status, 201);
+        assert!(matches!(
+            command,
+            Some(ChatBotCommand::TimedCallback {
+                event: ChatBotEvent::Internal(InternalEvent::TimedMessage { .. }),
+                ..
+            })
+        ));
+        let (answer, _) = call(&mut bot, ApiAction::Timers);
+        assert_eq!(
+            answer.body,
+            json!({ "timers": [{ "name": "discord", "interval": 600, "text": "Join the discord!" }] })
+        );
+        let (answer, command) = call(
+            &mut bot,
+            ApiAction::SendMessage {
+                text: "Hello from the panel".to_owned(),
+            },
+        );
+        assert_eq!(answer.status, 202);
+        assert!(matches!(
+            command,
+            Some(ChatBotCommand::SendMessage { text, .. }) if text == "Hello from the panel"
+        ));
+    }
+}
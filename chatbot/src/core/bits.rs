@@ -0,0 +1,198 @@
+use super::{
+    commands::{render_event, Args, Command, Context},
+    ChatBotCommand,
+};
+use crate::{
+    config::{BitsTier, EventsConfig},
+    connect::{Overflow, TextMessage, UserLevel},
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+// cheerers listed by `!topcheers`
+const TOP: usize = 5;
+const TOP_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Thanks cheerers by tiers of bits and adds up the bits of each user since the bot started.
+#[derive(Debug)]
+pub struct Bits {
+    tiers: Vec<BitsTier>,
+    channel_tiers: HashMap<String, Vec<BitsTier>>,
+    // by channel and lowercase login, the display name and the bits
+    cheered: HashMap<String, HashMap<String, (String, u64)>>,
+}
+
+pub type SharedBits = Rc<RefCell<Bits>>;
+
+impl Default for Bits {
+    fn default() -> Self {
+        Self::new(&EventsConfig::default())
+    }
+}
+
+impl Bits {
+    pub fn new(config: &EventsConfig) -> Self {
+        Self {
+            tiers: config.bits_tiers.clone(),
+            channel_tiers: config.channel_bits_tiers.clone(),
+            cheered: HashMap::new(),
+        }
+    }
+
+    /// Counts the bits of the message, the thank-you is None below the smallest tier.
+    pub fn cheer(&mut self, message: &TextMessage) -> Option<ChatBotCommand> {
+        let bits = message.bits?;
+        let (channel, name) = (&message.channel, message.user.display_name());
+        let cheered = self
+            .cheered
+            .entry(channel.clone())
+            .or_default()
+            .entry(message.user.name.to_lowercase())
+            .or_insert_with(|| (name.to_owned(), 0));
+        *cheered = (name.to_owned(), cheered.1 + u64::from(bits));
+        let tier = self
+            .channel_tiers
+            .get(channel)
+            .unwrap_or(&self.tiers)
+            .iter()
+            .filter(|tier| bits >= tier.bits)
+            .max_by_key(|tier| tier.bits)?;
+        if tier.message.is_empty() {
+            return None;
+        }
+        let event = [
+            ("bits", bits.to_string()),
+            ("message", message.text_without_cheermotes()),
+        ];
+        Some(ChatBotCommand::SendMessage {
+            channel: channel.clone(),
+            text: render_event(&tier.message, "bits message", name, channel, &event),
+            overflow: Overflow::Truncate,
+        })
+    }
+
+    /// The users who cheered the most in the channel, most bits first. The bits are only
+    /// counted since the bot started, nothing is kept in the storage to rank them.
+    pub fn top(&self, channel: &str) -> Vec<(&str, u64)> {
+        let mut top: Vec<_> = self
+            .cheered
+            .get(channel)
+            .into_iter()
+            .flat_map(HashMap::values)
+            .map(|(name, bits)| (name.as_str(), *bits))
+            .collect();
+        top.sort_by(|(a, a_bits), (b, b_bits)| b_bits.cmp(a_bits).then(a.cmp(b)));
+        top.truncate(TOP);
+        top
+    }
+
+    /// Forgets the channel's cheers, e.g. when a new stream starts.
+    pub fn reset(&mut self, channel: &str) {
+        self.cheered.remove(channel);
+    }
+}
+
+/// `!topcheers` lists who cheered the most since the bot started, `!topcheers reset`
+/// starts over for moderators.
+pub struct TopCheers(pub SharedBits);
+
+impl Command for TopCheers {
+    fn name(&self) -> &'static str {
+        "topcheers"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[reset]"
+    }
+
+    fn cooldown(&self) -> Duration {
+        TOP_COOLDOWN
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        if args
+            .next()
+            .is_some_and(|arg| arg.eq_ignore_ascii_case("reset"))
+            && ctx.message.has_level(UserLevel::Moderator)
+        {
+            self.0.borrow_mut().reset(channel);
+            return ctx.send("The cheers are counted from zero again.".to_owned());
+        }
+        let bits = self.0.borrow();
+        let top: Vec<_> = bits
+            .top(channel)
+            .into_iter()
+            .map(|(name, bits)| format!("{} ({})", name, bits))
+            .collect();
+        match top.is_empty() {
+            true => ctx.send("Nobody cheered yet.".to_owned()),
+            false => ctx.send(format!("Top cheerers: {}", top.join(", "))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+
+    fn cheer(login: &str, bits: u32, text: &str) -> TextMessage {
+        TextMessage {
+            channel: "captaincallback".to_owned(),
+            bits: Some(bits),
+            ..message(login, text, false)
+        }
+    }
+
+    fn text(command: Option<ChatBotCommand>) -> Option<String> {
+        match command? {
+            ChatBotCommand::SendMessage { text, .. } => Some(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn the_highest_tier_reached_is_thanked() {
+        let mut config = EventsConfig::default();
+        config.bits_tiers[0].message = "$(bits) from $(user): $(message)".to_owned();
+        config.channel_bits_tiers = HashMap::from([(
+            "carkhy".to_owned(),
+            vec![BitsTier {
+                bits: 1,
+                message: "Thanks!".to_owned(),
+            }],
+        )]);
+        let mut bits = Bits::new(&config);
+        assert_eq!(text(bits.cheer(&cheer("viewer", 99, "Cheer99"))), None);
+        assert_eq!(
+            text(bits.cheer(&cheer("viewer", 100, "Cheer50 great stream Kappa50"))).as_deref(),
+            Some("100 from viewer: great stream")
+        );
+        assert_eq!(
+            text(bits.cheer(&cheer("viewer", 999, "Cheer999"))).as_deref(),
+            Some("999 from viewer: ")
+        );
+        assert_eq!(
+            text(bits.cheer(&cheer("viewer", 1000, "Cheer1000"))).as_deref(),
+            Some("WOW, 1000 bits! Thank you so much, viewer!")
+        );
+        let mut small = cheer("viewer", 1, "Cheer1");
+        small.channel = "carkhy".to_owned();
+        assert_eq!(text(bits.cheer(&small)).as_deref(), Some("Thanks!"));
+    }
+
+    #[test]
+    fn cheers_add_up_per_channel() {
+        let mut bits = Bits::default();
+        for (login, amount) in [("viewer", 50), ("carkhy", 300), ("Viewer", 500)] {
+            bits.cheer(&cheer(login, amount, "Cheer"));
+        }
+        assert_eq!(
+            bits.top("captaincallback"),
+            [("Viewer", 550), ("carkhy", 300)]
+        );
+        assert!(bits.top("carkhy").is_empty());
+        bits.reset("captaincallback");
+        assert!(bits.top("captaincallback").is_empty());
+    }
+}
@@ -2,32 +2,172 @@
 
 use uuid::Uuid;
 
-use super::ChatBotCommand;
-use crate::connect::{ChatBotEvent, Command, CommandType};
+use super::{
+    api::{self, Handles},
+    bits::{Bits, SharedBits, TopCheers},
+    chat_stats::{ChatStats, SharedChatStats, Stats, TopChatters},
+    commands::{
+        command_args, render_event, Args, CommandRegistry, CommandStats, CustomCommands, Dispatch,
+        Export, IgnoreList, Quotes, Refusal, SharedIgnoreList,
+    },
+    emote_stats::{EmoteCount, EmoteStats, SharedEmoteStats, TopEmotes},
+    follows::Follows,
+    greeter::Greeter,
+    hype_trains::HypeTrains,
+    lurks::{Lurk, LurkStats, Lurks, SharedLurks, Unlurk},
+    markov::{Imitate, Markov, SharedMarkov},
+    metrics::Metrics,
+    moderation::{Moderation, SharedModeration},
+    obs::{Mute, Scene, Show},
+    points::{
+        Accept, Bet, Bets, Decline, Duel, Duels, Gamble, Games, Give, Points, PointsCommand,
+        SharedDuels, SharedPoints, Slots, Top, POINTS_TICK,
+    },
+    polls::{PollCommand, Polls, SharedPolls, TwitchPoll, Vote},
+    predictions::PredictionCommand,
+    queue::{self, Leave, Next, Position, QueueCommand, SharedViewerQueue, ViewerQueue},
+    raffles::{RaffleCommand, Raffles, SharedRaffles},
+    raids::Raids,
+    recent_messages::SharedRecentMessages,
+    redemptions::Redemptions,
+    reminders::{Remind, RemindMe, Reminders, SharedReminders},
+    schedule::{Action, Due, Schedule},
+    songs::{CurrentSong, Skip, SongRequest, Songs, WrongSong},
+    stream_status::{Change, StreamStatus},
+    subs::{self, Subs},
+    tasks::{self, announce},
+    timers::{SharedTimers, Timers, TIMER_TICK},
+    trivia::{load_questions, SharedTrivia, Trivia, TriviaCommand},
+    tts::Tts,
+    watch_time::{SharedWatchTime, WatchTime, WatchTimeCommand, WATCH_TICK},
+    ChatBotCommand, HelixTask,
+};
+use crate::{
+    config::{CommandsConfig, Config},
+    connect::{
+        ApiAction, ApiAnswer, ApiRequest, ChatBotEvent, Command, CommandType, ConnectionState,
+        InternalEvent, Overflow, Redemption, RoomState, TextMessage, UserInfo, UserLevel,
+        UserNoticeKind, Whisper,
+    },
+    csv_export::Exporter,
+    helix::Subscription,
+    storage::{Storage, StorageError},
+};
+use serde_json::json;
 use std::{
+    cell::RefCell,
     collections::{HashMap, HashSet},
-    time::Duration,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Debug)]
 pub struct ChatBot {
+    // state of each joined channel, keyed by the channel name without '#'
+    channels: HashMap<String, Channel>,
+    room_states: HashMap<String, RoomState>,
+    // shared with the registry, which matches replies to them
+    recent_messages: SharedRecentMessages,
+    // commands moved to the registry answer with the prefix of each channel
+    commands: CommandRegistry,
+    // shared with `!timers`, which pauses them
+    timers: SharedTimers,
+    // shared with `!ignore`, which changes it
+    ignored: SharedIgnoreList,
+    // shared with `!permit`
+    moderation: SharedModeration,
+    greeter: Greeter,
+    raids: Raids,
+    subs: Subs,
+    // shared with `!topcheers`
+    bits: SharedBits,
+    // shared with `!points`, `!give` and `!top`
+    points: SharedPoints,
+    // shared with `!duel`, `!accept` and `!decline`
+    duels: SharedDuels,
+    // shared with `!raffle`
+    raffles: SharedRaffles,
+    // shared with `!trivia`
+    trivia: SharedTrivia,
+    // shared with `!queue`, `!join` and the others of the viewer queue
+    queue: SharedViewerQueue,
+    // shared with `!remindme` and `!remind`
+    reminders: SharedReminders,
+    // shared with `!lurk`, `!unlurk` and `!lurkstats`
+    lurks: SharedLurks,
+    // shared with `!watchtime` and `!top`
+    watch_time: SharedWatchTime,
+    // shared with `!stats` and `!topchatters`
+    chat_stats: SharedChatStats,
+    // shared with `!topemotes` and `!emotecount`
+    emote_stats: SharedEmoteStats,
+    // shared with `!imitate`
+    markov: SharedMarkov,
+    // shared with `!poll` and `!vote`
+    polls: SharedPolls,
+    redemptions: Redemptions,
+    // what the text to speech program reads out
+    tts: Tts,
+    follows: Follows,
+    hype_trains: HypeTrains,
+    // by channel, what each new EventSub session is subscribed to
+    subscriptions: Vec<(String, Subscription)>,
+    stream_status: StreamStatus,
+    schedule: Schedule,
+    // flushed when the bot stops
+    storage: Storage,
+    metrics: Metrics,
+    // whether the latest message got past the ignore list and the filters
+    admitted: bool,
+}
+
+// everything the bot keeps apart between channels
+#[derive(Debug, Default)]
+struct Channel {
     chatters: HashSet<String>, // NOTE: probably replace String with a User struct when we need it.
-    dynamic_commands: HashMap<String, String>,
     repeating_messages: HashMap<String, RepeatingMessage>,
+    // channel currently hosted, repeating messages are paused meanwhile
+    hosting: Option<String>,
 }
 
 #[derive(Debug)]
-struct RepeatingMessage {
-    name: String,
-    text: String,
-    interval: Duration,
+pub(super) struct RepeatingMessage {
+    pub(super) name: String,
+    pub(super) text: String,
+    pub(super) interval: Duration,
     timer_id: Uuid,
 }
 
-const HELP_MESSAGE: &str =
-    "!help: Show this help | !info: Show some information about the chat bot";
-const INFO_MESSAGE: &str =
-    "Hello, my name is TwitchBotanist. I am a twitch chat bot written in Rust. My source code is on GitHub (https://github.com/CaptainCallback/TwitchBotanist). If you want to know what you can ask me, write '!help' into the chat!";
+/// Sets the repeating message, a message of the same name is replaced.
+pub(super) fn repeat(
+    messages: &mut HashMap<String, RepeatingMessage>,
+    channel: &str,
+    name: &str,
+    seconds: u64,
+    text: &str,
+) -> ChatBotCommand {
+    let interval = Duration::from_secs(seconds);
+    let id = Uuid::new_v4();
+    messages.insert(
+        name.to_owned(),
+        RepeatingMessage {
+            name: name.to_owned(),
+            text: text.to_owned(),
+            interval,
+            timer_id: id,
+        },
+    );
+    ChatBotCommand::TimedCallback {
+        duration: interval,
+        event: ChatBotEvent::Internal(InternalEvent::TimedMessage {
+            channel: channel.to_owned(),
+            name: name.to_owned(),
+            id,
+        }),
+    }
+}
+
 const NEW_COMMAND_SUCCESSFUL_MESSAGE: &str = "The new command has been defined successfully.";
 const NEW_COMMAND_NO_OPTION_MESSAGE: &str =
     "newcommand requires at least two options but less were given.";
@@ -35,170 +175,1285 @@ const REMOVE_COMMAND_NO_OPTION_MESSAGE: &str =
     "removecommand requires at least one option but none was given.";
 const REMOVE_COMMAND_SUCCESSFUL_MESSAGE: &str = "The command has been removed successfully.";
 const DENIED_MESSAGE: &str = "Denied: i ought to !slap you...";
-const DISCORD_MESSAGE: &str =
-    "You can join me on discord for news and updates here: https://discord.gg/qM6DTTQxDV";
+const CHANNEL_NO_OPTION_MESSAGE: &str = "join and part require the channel name.";
+// who the commands of the control socket are by
+const CONTROL_USER: &str = "control";
+// and those of the schedule
+const SCHEDULE_USER: &str = "schedule";
+
+// redemptions only with rewards to redeem, the rest with eventsub turned on
+fn subscriptions(config: &Config) -> Vec<(String, Subscription)> {
+    let mut kinds = Vec::new();
+    if config.events.eventsub {
+        kinds.extend([
+            Subscription::Follows,
+            Subscription::Subs,
+            Subscription::StreamOnline,
+            Subscription::StreamOffline,
+            Subscription::HypeTrainBegin,
+            Subscription::HypeTrainProgress,
+            Subscription::HypeTrainEnd,
+            Subscription::ShieldModeBegin,
+            Subscription::ShieldModeEnd,
+        ]);
+    }
+    if !config.redemptions.rewards.is_empty() || !config.tts.rewards.is_empty() {
+        kinds.push(Subscription::Redemptions);
+    }
+    let channels = config.channel_names();
+    channels
+        .into_iter()
+        .flat_map(|channel| kinds.iter().map(move |kind| (channel.clone(), *kind)))
+        .collect()
+}
 
-fn str_msg(string: &str) -> Option<ChatBotCommand> {
-    Some(ChatBotCommand::SendMessage(string.to_string()))
+// "#CaptainCallback" and "captaincallback" are the same channel
+// a command by the user at the level no badge grants
+fn as_admin(user: &str, channel: &str, text: &str) -> TextMessage {
+    TextMessage {
+        channel: channel.to_owned(),
+        text: text.to_owned(),
+        user: UserInfo {
+            name: user.to_owned(),
+            ..Default::default()
+        },
+        level: UserLevel::Admin,
+        ..Default::default()
+    }
+}
+
+fn channel_name(name: &str) -> String {
+    name.trim().trim_start_matches('#').to_lowercase()
+}
+
+fn send(channel: &str, text: String) -> ChatBotCommand {
+    ChatBotCommand::SendMessage {
+        channel: channel.to_owned(),
+        text,
+        overflow: Overflow::Split,
+    }
+}
+
+fn str_msg(channel: &str, string: &str) -> Option<ChatBotCommand> {
+    Some(send(channel, string.to_string()))
+}
+
+// answers in a thread when the message can be referenced, otherwise as plain message
+fn reply(message: &TextMessage, string: &str) -> Option<ChatBotCommand> {
+    match &message.message_id {
+        Some(parent_msg_id) => Some(ChatBotCommand::SendReply {
+            channel: message.channel.to_owned(),
+            parent_msg_id: parent_msg_id.to_owned(),
+            text: string.to_string(),
+        }),
+        None => str_msg(&message.channel, string),
+    }
+}
+
+// "[HH:MM:SS] " in UTC when twitch sent the time along with the message
+fn sent_time(message: &TextMessage) -> String {
+    message
+        .timestamp
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| {
+            let seconds = since_epoch.as_secs() % (24 * 60 * 60);
+            format!(
+                "[{:02}:{:02}:{:02}] ",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        })
+        .unwrap_or_default()
 }
 
 impl ChatBot {
     pub fn new() -> Self {
+        Self::with_commands(
+            &CommandsConfig::default(),
+            CustomCommands::default(),
+            Quotes::default(),
+            Timers::default(),
+            IgnoreList::default(),
+            Moderation::default(),
+            Storage::default(),
+        )
+    }
+
+    /// With the custom commands, quotes, ignored users and banned terms saved in the storage.
+    pub fn load(config: &Config, storage: Storage) -> Result<Self, StorageError> {
+        let quotes = Quotes::load(storage.clone())?;
+        let ignored = IgnoreList::load(
+            &config.twitch.user,
+            &config.moderation.ignored_users,
+            storage.clone(),
+        )?;
+        let greeter = Greeter::new(&config.events, storage.clone(), Instant::now());
+        let mut points = Points::load(&config.points, storage.clone())?;
+        let bets_storage = storage.clone();
+        let queue_storage = storage.clone();
+        let raffle_storage = storage.clone();
+        let mut bot = Self {
+            greeter,
+            raids: Raids::new(&config.events),
+            subs: Subs::new(&config.events),
+            redemptions: Redemptions::new(&config.redemptions),
+            follows: Follows::new(&config.events),
+            hype_trains: HypeTrains::new(&config.events),
+            subscriptions: subscriptions(config),
+            stream_status: StreamStatus::new(config),
+            ..Self::with_commands(
+                &config.commands,
+                CustomCommands::load(storage.clone())?,
+                quotes,
+                Timers::new(&config.timer_messages(), Instant::now()),
+                ignored,
+                Moderation::load(&config.twitch.user, &config.moderation, storage.clone())?,
+                storage,
+            )
+        };
+        // the channels with their own `[channels.<name>]`
+        for channel in config.channels.keys() {
+            let own = config.for_channel(channel);
+            bot.commands.scope(channel, &own.commands);
+            bot.moderation.borrow_mut().scope(channel, &own.moderation);
+            points.scope(channel, &own.points);
+        }
+        *bot.bits.borrow_mut() = Bits::new(&config.events);
+        bot.tts = Tts::new(&config.tts, bot.moderation.clone());
+        if config.timers.only_live {
+            bot.timers.borrow_mut().wait_for_live();
+        }
+        *bot.raffles.borrow_mut() = Raffles::load(
+            &config.raffle,
+            raffle_storage,
+            fastrand::Rng::new(),
+            bot.watch_time.clone(),
+            bot.points.clone(),
+        )?;
+        // the bot runs without trivia rather than not at all
+        let questions = match &config.trivia.questions {
+            Some(path) => load_questions(Path::new(path)).unwrap_or_else(|error| {
+                tracing::warn!(%error, "trivia runs without questions");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        *bot.trivia.borrow_mut() = Trivia::new(
+            &config.trivia,
+            questions,
+            bot.points.clone(),
+            fastrand::Rng::new(),
+        );
+        let songs = match config.songs.enabled {
+            true => Some(Rc::new(RefCell::new(Songs::load(
+                &config.songs,
+                queue_storage.clone(),
+            )?))),
+            false => None,
+        };
+        let mut commands: Vec<Box<dyn super::commands::Command>> = Vec::new();
+        if let Some(songs) = &songs {
+            commands.push(Box::new(SongRequest(songs.clone())));
+            commands.push(Box::new(CurrentSong(songs.clone())));
+            commands.push(Box::new(Skip(songs.clone())));
+            commands.push(Box::new(WrongSong(songs.clone())));
+        }
+        *bot.queue.borrow_mut() = ViewerQueue::load(&config.queue, queue_storage.clone())?;
+        *bot.reminders.borrow_mut() = Reminders::load(&config.reminders, queue_storage.clone())?;
+        *bot.lurks.borrow_mut() = Lurks::load(&config.lurk, queue_storage.clone())?;
+        *bot.watch_time.borrow_mut() = WatchTime::load(&config.watch_time, queue_storage.clone())?;
+        *bot.chat_stats.borrow_mut() = ChatStats::load(queue_storage.clone())?;
+        *bot.commands.stats().borrow_mut() = CommandStats::load(queue_storage.clone())?;
+        *bot.emote_stats.borrow_mut() = EmoteStats::load(&config.emotes, queue_storage.clone())?;
+        *bot.markov.borrow_mut() =
+            Markov::load(&config.imitate, queue_storage.clone(), fastrand::Rng::new())?;
+        bot.schedule = Schedule::load(config, queue_storage.clone(), SystemTime::now())?;
+        *bot.polls.borrow_mut() = Polls::load(&config.poll, queue_storage)?;
+        if config.imitate.enabled {
+            commands.push(Box::new(Imitate {
+                markov: bot.markov.clone(),
+                moderation: bot.moderation.clone(),
+            }));
+        }
+        if config.watch_time.enabled {
+            commands.push(Box::new(WatchTimeCommand(bot.watch_time.clone())));
+        }
+        commands.push(Box::new(QueueCommand {
+            viewers: bot.queue.clone(),
+            songs,
+        }));
+        commands.push(Box::new(Leave(bot.queue.clone())));
+        commands.push(Box::new(Position(bot.queue.clone())));
+        commands.push(Box::new(Next(bot.queue.clone())));
+        for command in commands {
+            bot.commands
+                .register(command)
+                .expect("the queue commands have names of their own");
+        }
+        let export = Export {
+            exporter: Exporter::new(config),
+            stats: bot.commands.stats().clone(),
+        };
+        bot.commands
+            .register(Box::new(export))
+            .expect("!export has a name of its own");
+        let top = Top {
+            points: points.is_enabled().then(|| bot.points.clone()),
+            watch_time: config.watch_time.enabled.then(|| bot.watch_time.clone()),
+        };
+        if top.points.is_some() || top.watch_time.is_some() {
+            bot.commands
+                .register(Box::new(top))
+                .expect("!top has a name of its own");
+        }
+        if points.is_enabled() {
+            *bot.points.borrow_mut() = points;
+            let mut commands: Vec<Box<dyn super::commands::Command>> = vec![
+                Box::new(PointsCommand(bot.points.clone())),
+                Box::new(Give(bot.points.clone())),
+            ];
+            // a round the bot didn't finish is refunded even with gambling turned off
+            let bets = Bets::load(bot.points.clone(), bets_storage)?;
+            if config.points.gambling {
+                let games = Rc::new(RefCell::new(Games::new(
+                    &config.points,
+                    fastrand::Rng::new(),
+                )));
+                commands.push(Box::new(Gamble(bot.points.clone(), games.clone())));
+                commands.push(Box::new(Slots(bot.points.clone(), games)));
+                *bot.duels.borrow_mut() = Duels::new(bot.points.clone(), fastrand::Rng::new());
+                commands.push(Box::new(Duel(bot.points.clone(), bot.duels.clone())));
+                commands.push(Box::new(Accept(bot.duels.clone())));
+                commands.push(Box::new(Decline(bot.duels.clone())));
+                commands.push(Box::new(Bet(bot.points.clone(), bets)));
+            }
+            for command in commands {
+                bot.commands
+                    .register(command)
+                    .expect("the points commands have names of their own");
+            }
+        }
+        if config.obs.url.is_some() {
+            let commands: [Box<dyn super::commands::Command>; 3] = [
+                Box::new(Scene),
+                Box::new(Mute),
+                Box::new(Show {
+                    seconds: config.obs.show_seconds,
+                }),
+            ];
+            for command in commands {
+                bot.commands
+                    .register(command)
+                    .expect("the OBS commands have names of their own");
+            }
+        }
+        Ok(bot)
+    }
+
+    fn with_commands(
+        config: &CommandsConfig,
+        custom: CustomCommands,
+        quotes: Quotes,
+        timers: Timers,
+        ignored: IgnoreList,
+        moderation: Moderation,
+        storage: Storage,
+    ) -> Self {
+        let timers = Rc::new(RefCell::new(timers));
+        let ignored = Rc::new(RefCell::new(ignored));
+        let moderation = Rc::new(RefCell::new(moderation));
+        let bits = Rc::new(RefCell::new(Bits::default()));
+        let mut commands = CommandRegistry::new(
+            config,
+            custom,
+            quotes,
+            timers.clone(),
+            ignored.clone(),
+            moderation.clone(),
+            storage.clone(),
+        );
+        commands
+            .register(Box::new(TopCheers(bits.clone())))
+            .expect("!topcheers has a name of its own");
+        let raffles = Rc::new(RefCell::new(Raffles::default()));
+        commands
+            .register(Box::new(RaffleCommand(raffles.clone())))
+            .expect("!raffle has a name of its own");
+        let points: SharedPoints = Rc::default();
+        let trivia = Rc::new(RefCell::new(Trivia::new(
+            &Default::default(),
+            Vec::new(),
+            points.clone(),
+            fastrand::Rng::new(),
+        )));
+        commands
+            .register(Box::new(TriviaCommand(trivia.clone())))
+            .expect("!trivia has a name of its own");
+        let reminders = Rc::new(RefCell::new(Reminders::default()));
+        let lurks: SharedLurks = Rc::default();
+        let chat_stats: SharedChatStats = Rc::default();
+        let emote_stats: SharedEmoteStats = Rc::default();
+        let polls: SharedPolls = Rc::default();
+        for command in [
+            Box::new(RemindMe(reminders.clone())) as Box<dyn super::commands::Command>,
+            Box::new(Remind(reminders.clone())),
+            Box::new(Lurk(lurks.clone())),
+            Box::new(Unlurk(lurks.clone())),
+            Box::new(LurkStats(lurks.clone())),
+            Box::new(Stats(chat_stats.clone())),
+            Box::new(TopChatters(chat_stats.clone())),
+            Box::new(TopEmotes(emote_stats.clone())),
+            Box::new(EmoteCount(emote_stats.clone())),
+            Box::new(PollCommand(polls.clone())),
+            Box::new(Vote(polls.clone())),
+            Box::new(TwitchPoll(polls.clone())),
+            Box::new(PredictionCommand),
+        ] {
+            commands.register(command).expect(
+                "the reminder, lurk, stats, poll and prediction commands have names of their own",
+            );
+        }
         Self {
-            chatters: HashSet::default(),
-            dynamic_commands: HashMap::default(),
-            repeating_messages: HashMap::default(),
+            channels: HashMap::default(),
+            room_states: HashMap::default(),
+            recent_messages: commands.recent().clone(),
+            commands,
+            timers,
+            ignored,
+            moderation,
+            greeter: Greeter::default(),
+            raids: Raids::default(),
+            subs: Subs::default(),
+            bits,
+            points,
+            duels: Rc::default(),
+            raffles,
+            trivia,
+            queue: Rc::default(),
+            reminders,
+            lurks,
+            watch_time: Rc::default(),
+            chat_stats,
+            emote_stats,
+            markov: Rc::default(),
+            polls,
+            redemptions: Redemptions::default(),
+            tts: Tts::default(),
+            follows: Follows::default(),
+            hype_trains: HypeTrains::default(),
+            subscriptions: Vec::new(),
+            stream_status: StreamStatus::default(),
+            schedule: Schedule::default(),
+            storage,
+            metrics: Metrics::default(),
+            admitted: false,
+        }
+    }
+
+    /// The first tick of the timers, the points, the watch time and the lurks, the reminders
+    /// and the schedule
+    /// saved before, and the first look at the streams,
+    /// None without any.
+    /// Each tick schedules the next one.
+    pub fn start_timers(&self) -> Option<ChatBotCommand> {
+        let mut ticks = Vec::new();
+        if !self.timers.borrow().is_empty() {
+            ticks.push(ChatBotCommand::TimedCallback {
+                duration: TIMER_TICK,
+                event: ChatBotEvent::Internal(InternalEvent::TimerTick),
+            });
+        }
+        if self.points.borrow().is_enabled() {
+            ticks.push(ChatBotCommand::TimedCallback {
+                duration: POINTS_TICK,
+                event: ChatBotEvent::Internal(InternalEvent::PointsTick),
+            });
+        }
+        if self.watch_time.borrow().is_enabled() {
+            ticks.push(ChatBotCommand::TimedCallback {
+                duration: WATCH_TICK,
+                event: ChatBotEvent::Internal(InternalEvent::WatchTick),
+            });
+        }
+        ticks.extend(self.stream_status.poll());
+        ticks.extend(self.lurks.borrow_mut().start());
+        ticks.extend(self.reminders.borrow().start(SystemTime::now()));
+        ticks.extend(self.schedule.start(SystemTime::now()));
+        match ticks.len() {
+            0 | 1 => ticks.pop(),
+            _ => Some(ChatBotCommand::MultipleCommands(ticks)),
+        }
+    }
+
+    fn channel(&mut self, name: &str) -> &mut Channel {
+        self.channels.entry(name.to_owned()).or_default()
+    }
+
+    // the fields apart, the repeating messages are borrowed mutably next to the commands
+    fn api(&mut self, request: &ApiRequest) -> (ApiAnswer, Option<ChatBotCommand>) {
+        let accepted = |body| ApiAnswer { status: 202, body };
+        match &request.action {
+            ApiAction::Join => {
+                let command = self.join(&request.channel);
+                return (accepted(json!({ "joined": request.channel })), command);
+            }
+            ApiAction::Part => {
+                let command = self.part(&request.channel);
+                return (accepted(json!({ "left": request.channel })), command);
+            }
+            ApiAction::RunCommand { text } => {
+                let command = self.run_command(as_admin(CONTROL_USER, &request.channel, text));
+                let body = json!({ "answered": command.is_some() });
+                return (accepted(body), command);
+            }
+            _ => {}
+        }
+        let channel = self.channels.entry(request.channel.clone()).or_default();
+        let mut handles = Handles {
+            custom: self.commands.custom(),
+            quotes: self.commands.quotes(),
+            points: &self.points,
+            stats: self.commands.stats(),
+            storage: &self.storage,
+            repeating: &mut channel.repeating_messages,
+        };
+        api::handle(&mut handles, request)
+    }
+
+    /// Join another channel, the connector ignores channels that are already joined.
+    pub fn join(&mut self, channel: &str) -> Option<ChatBotCommand> {
+        let name = channel_name(channel);
+        if name.is_empty() {
+            return None;
+        }
+        Some(ChatBotCommand::JoinChannel(name))
+    }
+
+    /// Leave the channel and forget what the bot kept for it, custom commands stay saved.
+    pub fn part(&mut self, channel: &str) -> Option<ChatBotCommand> {
+        let name = channel_name(channel);
+        if name.is_empty() {
+            return None;
         }
+        self.channels.remove(&name);
+        self.room_states.remove(&name);
+        self.points.borrow_mut().leave(&name);
+        self.watch_time.borrow_mut().leave(&name);
+        self.duels.borrow_mut().leave(&name);
+        self.raffles.borrow_mut().leave(&name);
+        self.trivia.borrow_mut().leave(&name);
+        self.recent_messages
+            .borrow_mut()
+            .retain(|message| message.channel != name);
+        Some(ChatBotCommand::PartChannel(name))
+    }
+
+    // the connector's `!join` and `!part` take the channel anyway, the other commands of an
+    // admin go to the registry
+    fn whisper(&mut self, whisper: &Whisper) -> Option<ChatBotCommand> {
+        let login = &whisper.user.name;
+        let mut args = Args::new(&whisper.text);
+        let name = args
+            .next()
+            .and_then(|name| name.strip_prefix(self.commands.prefix("")))
+            .map(str::to_lowercase);
+        let answer = match name.as_deref() {
+            _ if !self.commands.is_admin(login) => None,
+            Some("join") => args.next().map(|channel| self.join(channel)),
+            Some("part") => args.next().map(|channel| self.part(channel)),
+            _ => None,
+        };
+        let Some(answer) = answer else {
+            return self.commands.whisper(whisper, Instant::now());
+        };
+        let text = match &answer {
+            Some(ChatBotCommand::JoinChannel(channel)) => format!("Joining #{}.", channel),
+            Some(ChatBotCommand::PartChannel(channel)) => format!("Leaving #{}.", channel),
+            _ => CHANNEL_NO_OPTION_MESSAGE.to_owned(),
+        };
+        let whispered = tasks::whisper("", login, text, None);
+        Some(match answer {
+            Some(answer) => ChatBotCommand::MultipleCommands(vec![answer, whispered]),
+            None => whispered,
+        })
     }
 
     fn handle_command(&mut self, command: Command) -> Option<ChatBotCommand> {
-        println!("Executing this command: {:#?}", command);
+        tracing::debug!(kind = ?command.kind, options = ?command.options, "executing a command");
         use ChatBotCommand::*;
+        let name = command.message.channel.clone();
+        let channel = self.channels.entry(name.clone()).or_default();
         match command.kind {
-            CommandType::Discord => str_msg(DISCORD_MESSAGE),
-            CommandType::Help => str_msg(HELP_MESSAGE),
-            CommandType::Info => str_msg(INFO_MESSAGE),
+            // answered by the registry, unless the channel has another prefix
+            CommandType::Help | CommandType::Info | CommandType::Discord | CommandType::Quote => {
+                None
+            }
             CommandType::Slap => {
-                println!("Slapping one of these guys \n{:#?}", self.chatters);
                 // Notice how we can now do everything in a single expression
                 // because we removed the IO from this place
-                let slapping_user = command.user.name;
-                println!("This guy specifically : {}", &slapping_user);
+                let slapping_user = command.message.user.display_name();
+                tracing::debug!(chatters = channel.chatters.len(), slapping_user, "slapping");
                 command
                     .options
-                    .get(0)
-                    .and_then(|slapped_user| self.chatters.get(slapped_user))
+                    .first()
+                    .and_then(|slapped_user| channel.chatters.get(slapped_user))
                     .map(|slapped_user| {
-                        SendMessage(format!(
-                            "{} slaps {} around a bit with a large trout",
-                            slapping_user, slapped_user
-                        ))
+                        send(
+                            &name,
+                            format!(
+                                "{} slaps {} around a bit with a large trout",
+                                slapping_user, slapped_user
+                            ),
+                        )
                     })
             }
             CommandType::NewCommand => {
-                if command.user.has_elevated_rights() {
+                if command.message.has_level(UserLevel::Moderator) {
                     if command.options.len() < 2 {
-                        str_msg(NEW_COMMAND_NO_OPTION_MESSAGE)
+                        str_msg(&name, NEW_COMMAND_NO_OPTION_MESSAGE)
                     } else {
-                        let new_command_name = &command.options[0];
-                        let new_command_message = command.options[1..].join(" ");
-                        self.dynamic_commands
-                            .insert(new_command_name.to_owned(), new_command_message);
-                        str_msg(NEW_COMMAND_SUCCESSFUL_MESSAGE)
+                        let new_command_name = command.options[0].to_lowercase();
+                        // the text keeps its spacing
+                        let mut args = command_args(&command.message);
+                        args.next();
+                        let new_command_message = args.rest().unwrap_or_default();
+                        match self.commands.custom().borrow_mut().set(
+                            &name,
+                            &new_command_name,
+                            new_command_message,
+                        ) {
+                            Ok(()) => str_msg(&name, NEW_COMMAND_SUCCESSFUL_MESSAGE),
+                            Err(Refusal::Taken(command)) => Some(send(
+                                &name,
+                                format!("{} calls {} already.", new_command_name, command),
+                            )),
+                            Err(Refusal::Template(error)) => {
+                                Some(send(&name, format!("Nothing changed, {}.", error)))
+                            }
+                            Err(_) => Some(send(
+                                &name,
+                                format!("{} is a built-in command.", new_command_name),
+                            )),
+                        }
                     }
                 } else {
-                    str_msg(DENIED_MESSAGE)
+                    reply(&command.message, DENIED_MESSAGE)
                 }
             }
             CommandType::RemoveCommand => {
-                if command.user.has_elevated_rights() {
+                if command.message.has_level(UserLevel::Moderator) {
                     if command.options.is_empty() {
-                        str_msg(REMOVE_COMMAND_NO_OPTION_MESSAGE)
+                        str_msg(&name, REMOVE_COMMAND_NO_OPTION_MESSAGE)
                     } else {
-                        let command_name = &command.options[0];
-                        self.dynamic_commands.remove(command_name);
-                        str_msg(REMOVE_COMMAND_SUCCESSFUL_MESSAGE)
+                        let command_name = command.options[0].to_lowercase();
+                        // removing a command that isn't there is no error here
+                        let _: Result<(), Refusal> = self
+                            .commands
+                            .custom()
+                            .borrow_mut()
+                            .remove(&name, &command_name);
+                        str_msg(&name, REMOVE_COMMAND_SUCCESSFUL_MESSAGE)
                     }
                 } else {
-                    str_msg(DENIED_MESSAGE)
+                    reply(&command.message, DENIED_MESSAGE)
                 }
             }
 
             CommandType::NewRepeating => {
-                if command.user.has_elevated_rights() {
+                if command.message.has_level(UserLevel::Moderator) {
                     if command.options.len() < 2 {
                         // TODO: set the correct message here
-                        str_msg(NEW_COMMAND_NO_OPTION_MESSAGE)
+                        str_msg(&name, NEW_COMMAND_NO_OPTION_MESSAGE)
                     } else {
                         let message_name = &command.options[0];
-                        if let Ok(seconds) = &command.options[1].parse() {
-                            let interval = Duration::from_secs(*seconds);
-                            let id = Uuid::new_v4();
-                            self.repeating_messages.insert(
-                                message_name.to_string(),
-                                RepeatingMessage {
-                                    name: message_name.to_string(),
-                                    text: command.options[2..].join(" "),
-                                    interval,
-                                    timer_id: id,
-                                },
+                        if let Ok(seconds) = command.options[1].parse() {
+                            let text = command.options[2..].join(" ");
+                            let callback = repeat(
+                                &mut channel.repeating_messages,
+                                &name,
+                                message_name,
+                                seconds,
+                                &text,
                             );
                             // TODO: set the correct message here
                             Some(MultipleCommands(vec![
-                                ChatBotCommand::SendMessage(
-                                    NEW_COMMAND_SUCCESSFUL_MESSAGE.to_string(),
-                                ),
-                                TimedCallback {
-                                    duration: interval,
-                                    event: ChatBotEvent::TimedMessage(message_name.to_string(), id),
-                                },
+                                send(&name, NEW_COMMAND_SUCCESSFUL_MESSAGE.to_string()),
+                                callback,
                             ]))
                         } else {
                             // TODO: set the correct message here
-                            str_msg(NEW_COMMAND_NO_OPTION_MESSAGE)
+                            str_msg(&name, NEW_COMMAND_NO_OPTION_MESSAGE)
                         }
                     }
                 } else {
-                    str_msg(DENIED_MESSAGE)
+                    reply(&command.message, DENIED_MESSAGE)
                 }
             }
 
             CommandType::RemoveRepeating => {
-                if command.user.has_elevated_rights() {
+                if command.message.has_level(UserLevel::Moderator) {
                     if command.options.is_empty() {
                         // TODO: set the correct message here
-                        str_msg(REMOVE_COMMAND_NO_OPTION_MESSAGE)
+                        str_msg(&name, REMOVE_COMMAND_NO_OPTION_MESSAGE)
                     } else {
                         let command_name = &command.options[0];
-                        self.repeating_messages.remove(command_name);
+                        channel.repeating_messages.remove(command_name);
                         // TODO: set the correct message here
-                        str_msg(REMOVE_COMMAND_SUCCESSFUL_MESSAGE)
+                        str_msg(&name, REMOVE_COMMAND_SUCCESSFUL_MESSAGE)
                     }
                 } else {
-                    str_msg(DENIED_MESSAGE)
+                    reply(&command.message, DENIED_MESSAGE)
                 }
             }
 
-            CommandType::Dynamic(command_name) => self
-                .dynamic_commands
-                .get(&command_name)
-                .map(String::from)
-                .map(SendMessage),
+            // a bare `!join` is for the viewer queue, anyone may call it
+            CommandType::Join if command.options.is_empty() => {
+                Some(send(&name, queue::join(&self.queue, &command.message)))
+            }
+            CommandType::Join | CommandType::Part => {
+                if !command.message.has_level(UserLevel::Broadcaster) {
+                    return reply(&command.message, DENIED_MESSAGE);
+                }
+                match (command.kind, command.options.first()) {
+                    (_, None) => str_msg(&name, CHANNEL_NO_OPTION_MESSAGE),
+                    (CommandType::Join, Some(channel)) => self.join(channel),
+                    (_, Some(channel)) => self.part(channel),
+                }
+            }
+
+            // custom commands are called by the registry
+            CommandType::Dynamic(_) => None,
+        }
+    }
+
+    /// Whether the latest message or command got past the ignore list and the filters.
+    pub fn admitted(&self) -> bool {
+        self.admitted
+    }
+
+    /// Whether the bot needs EventSub, for what chat doesn't tell about.
+    pub fn wants_eventsub(&self) -> bool {
+        !self.subscriptions.is_empty()
+    }
+
+    fn subscribe(&self, session_id: &str) -> Option<ChatBotCommand> {
+        let mut tasks: Vec<_> = self
+            .subscriptions
+            .iter()
+            .map(|(channel, subscription)| {
+                ChatBotCommand::Helix(HelixTask::Subscribe {
+                    channel: channel.clone(),
+                    session_id: session_id.to_owned(),
+                    subscription: *subscription,
+                })
+            })
+            .collect();
+        tasks.extend(self.stream_status.welcomed());
+        (!tasks.is_empty()).then_some(ChatBotCommand::MultipleCommands(tasks))
+    }
+
+    // twitch is only asked while the stream status doesn't know
+    fn send_if_live(&self, command: ChatBotCommand) -> Option<ChatBotCommand> {
+        let ChatBotCommand::Helix(HelixTask::SendIfLive { channel, text }) = &command else {
+            return Some(command);
+        };
+        match self.stream_status.is_live(channel) {
+            Some(true) => Some(ChatBotCommand::SendMessage {
+                channel: channel.clone(),
+                text: text.clone(),
+                overflow: Overflow::Truncate,
+            }),
+            Some(false) => None,
+            None => Some(command),
+        }
+    }
+
+    // the handlers that only run while the stream is live
+    fn stream_changed(&mut self, change: Change) -> Option<ChatBotCommand> {
+        match change.live {
+            true => tracing::info!(channel = %change.channel, "went live"),
+            false => tracing::info!(channel = %change.channel, "went offline"),
+        }
+        self.timers
+            .borrow_mut()
+            .live_status(&change.channel, change.live);
+        change.message
+    }
+
+    // the reward's command runs as the broadcaster's, the fulfillment waits for its webhook
+    fn redeem(&mut self, redemption: Redemption) -> Option<ChatBotCommand> {
+        use ChatBotCommand::*;
+        let actions = self.redemptions.actions(&redemption)?;
+        let name = redemption.display_name.as_str();
+        // the input would end up in chat or in the command, half a redemption helps nobody
+        if self
+            .moderation
+            .borrow()
+            .banned
+            .punishment(&redemption.channel, &redemption.input)
+            .is_some()
+        {
+            return Some(LogTextMessage(format!(
+                "Not redeeming {} of {}, the input has a banned term: {}",
+                redemption.reward, name, redemption.input
+            )));
+        }
+        let mut commands = vec![LogTextMessage(format!(
+            "{} redeemed {}: {}",
+            name, redemption.reward, redemption.input
+        ))];
+        commands.extend(actions.message);
+        if let Some(message) = actions.command {
+            commands.extend(self.run_command(message));
+        }
+        match actions.webhook {
+            Some((url, body)) => commands.push(Webhook {
+                url,
+                body,
+                then: actions.fulfill.map(Box::new),
+            }),
+            None => commands.extend(actions.fulfill),
+        }
+        Some(MultipleCommands(commands))
+    }
+
+    // a message or command of the schedule
+    fn scheduled(&mut self, due: Due) -> Option<ChatBotCommand> {
+        match due.action {
+            Action::Message(text) => {
+                let local = due.local;
+                let event = [
+                    ("date", local.date.to_string()),
+                    ("time", format!("{:02}:{:02}", local.hour, local.minute)),
+                ];
+                Some(ChatBotCommand::SendMessage {
+                    text: render_event(&text, "scheduled message", "", &due.channel, &event),
+                    channel: due.channel,
+                    overflow: Overflow::Truncate,
+                })
+            }
+            Action::Command(text) => self.run_command(as_admin(SCHEDULE_USER, &due.channel, &text)),
+        }
+    }
+
+    // a command the bot runs without chat asking, like a redemption's
+    fn run_command(&mut self, message: TextMessage) -> Option<ChatBotCommand> {
+        match Command::parse(message.clone()) {
+            Some(command) => self.handle(ChatBotEvent::Command(command)),
+            // a channel's own prefix isn't '!'
+            None => match self.commands.dispatch(&message, Instant::now()) {
+                Dispatch::Handled(answer) => answer,
+                _ => None,
+            },
         }
     }
 
     pub fn handle_event(&mut self, event: ChatBotEvent) -> Option<ChatBotCommand> {
+        // other bots and the bot itself are ignored before anything else sees the message
+        let mut entries = Vec::new();
+        if let ChatBotEvent::TextMessage(message) | ChatBotEvent::Command(Command { message, .. }) =
+            &event
+        {
+            self.admitted = false;
+            if self.ignored.borrow().contains(&message.user.name) {
+                self.metrics.ignored += 1;
+                return None;
+            }
+            self.metrics.messages += 1;
+            let action = self.moderation.borrow_mut().check(message, Instant::now());
+            if action.is_some() {
+                return action;
+            }
+            self.admitted = true;
+            self.points.borrow_mut().message(message, Instant::now());
+            let command = matches!(event, ChatBotEvent::Command(_));
+            self.chat_stats
+                .borrow_mut()
+                .count(message, command, SystemTime::now());
+            self.emote_stats
+                .borrow_mut()
+                .count(message, SystemTime::now());
+            // the keyword may look like a command, e.g. "!enter"
+            entries.extend(self.raffles.borrow_mut().enter(message, Instant::now()));
+            entries.extend(self.trivia.borrow_mut().answer(message));
+            self.queue
+                .borrow_mut()
+                .seen(&message.channel, &message.user.name);
+            self.watch_time.borrow_mut().seen(
+                &message.channel,
+                &message.user.name,
+                SystemTime::now(),
+            );
+        }
+        // commands don't end a lurk, `!lurk` itself would end it right away
+        if let ChatBotEvent::TextMessage(message) = &event {
+            entries.extend(self.lurks.borrow_mut().message(message, SystemTime::now()));
+            self.markov.borrow_mut().learn(message, SystemTime::now());
+            self.polls.borrow_mut().message(message);
+            entries.extend(self.tts.message(message));
+        }
+        let mut commands: Vec<_> = self.handle(event).into_iter().chain(entries).collect();
+        match commands.len() {
+            0 | 1 => commands.pop(),
+            _ => Some(ChatBotCommand::MultipleCommands(commands)),
+        }
+    }
+
+    // what the bot does about an event that moderation let through
+    fn handle(&mut self, event: ChatBotEvent) -> Option<ChatBotCommand> {
         use ChatBotCommand::*;
         match event {
-            ChatBotEvent::Command(command) => self.handle_command(command),
-            ChatBotEvent::Join(user) => {
-                println!("{:?} joined", &user);
-                self.chatters.insert(user);
+            ChatBotEvent::Command(command) => {
+                let now = Instant::now();
+                match self.commands.dispatch(&command.message, now) {
+                    Dispatch::Handled(result) => result,
+                    Dispatch::Dropped => {
+                        self.metrics.dropped += 1;
+                        None
+                    }
+                    // the commands not moved to the registry yet have the same budget,
+                    // only moderators change something with them and they have no limit
+                    Dispatch::Unknown => {
+                        let spent = self.commands.is_spent(&command.message, now);
+                        let message = command.message.clone();
+                        let answer = self.handle_command(command)?;
+                        if spent {
+                            self.metrics.dropped += 1;
+                            return None;
+                        }
+                        self.commands.spend(&message, now);
+                        Some(answer)
+                    }
+                }
+            }
+            ChatBotEvent::Join { user, channel } => {
+                tracing::debug!(%channel, %user, "joined");
+                self.schedule.join(&channel, &user);
+                self.points.borrow_mut().join(&channel, &user);
+                self.watch_time
+                    .borrow_mut()
+                    .join(&channel, &user, SystemTime::now());
+                self.queue.borrow_mut().seen(&channel, &user);
+                self.raffles
+                    .borrow_mut()
+                    .join(&channel, &user, Instant::now());
+                self.channel(&channel).chatters.insert(user);
+                None
+            }
+            ChatBotEvent::Part { user, channel } => {
+                tracing::debug!(%channel, %user, "parted");
+                self.schedule.part(&channel, &user);
+                self.points.borrow_mut().part(&channel, &user);
+                self.watch_time.borrow_mut().part(&channel, &user);
+                self.raffles.borrow_mut().part(&channel, &user);
+                self.channel(&channel).chatters.remove(&user);
+                let grace = self.queue.borrow_mut().part(&channel, &user);
+                match (self.duels.borrow_mut().part(&channel, &user), grace) {
+                    (duel, None) => duel,
+                    (None, grace) => grace,
+                    (Some(duel), Some(grace)) => Some(MultipleCommands(vec![duel, grace])),
+                }
+            }
+            ChatBotEvent::Names { users, channel } => {
+                let (mut points, mut raffles, mut watch_time) = (
+                    self.points.borrow_mut(),
+                    self.raffles.borrow_mut(),
+                    self.watch_time.borrow_mut(),
+                );
+                for user in &users {
+                    points.join(&channel, user);
+                    raffles.join(&channel, user, Instant::now());
+                    watch_time.join(&channel, user, SystemTime::now());
+                }
+                drop((points, raffles, watch_time));
+                self.channel(&channel).chatters.extend(users);
+                None
+            }
+            ChatBotEvent::EndOfNames { channel } => {
+                let chatters = self.channel(&channel).chatters.len();
+                tracing::info!(%channel, chatters, "got the chatters");
                 None
             }
-            ChatBotEvent::Part(user) => {
-                println!("{:?} parted", &user);
-                self.chatters.remove(&user);
+            ChatBotEvent::Ping { .. } => None,
+            ChatBotEvent::Shutdown => {
+                self.chat_stats.borrow_mut().flush();
+                self.commands.stats().borrow_mut().flush();
+                self.emote_stats.borrow_mut().flush();
+                self.markov.borrow_mut().flush();
+                if let Err(error) = self.storage.flush() {
+                    tracing::error!(%error, "could not save the changes");
+                }
+                Some(LogTextMessage(format!("Shutting down ({})", self.metrics)))
+            }
+            ChatBotEvent::Connection(state) => Some(LogTextMessage(match state {
+                ConnectionState::Connected => {
+                    self.greeter.connected(Instant::now());
+                    "Reconnected to twitch chat".to_owned()
+                }
+                ConnectionState::Reconnecting { attempt } => {
+                    self.schedule.disconnected();
+                    format!("Connection lost, reconnect attempt {}", attempt)
+                }
+                ConnectionState::Disconnected => "Gave up reconnecting to twitch chat".to_owned(),
+            })),
+            ChatBotEvent::UserNotice(notice) => {
+                let mut commands = vec![LogTextMessage(notice.system_message.clone())];
+                let room_state = self.room_states.get(&notice.channel);
+                if let UserNoticeKind::Raid { from, viewers } = &notice.kind {
+                    let slow = room_state.and_then(|state| state.slow);
+                    commands.extend(self.raids.raid(&notice, from, *viewers, slow));
+                }
+                let subs_only = room_state.and_then(|state| state.subs_only);
+                commands.extend(self.subs.thank(
+                    &notice,
+                    subs_only.unwrap_or(false),
+                    Instant::now(),
+                ));
+                match commands.len() {
+                    1 => commands.pop(),
+                    _ => Some(MultipleCommands(commands)),
+                }
+            }
+            ChatBotEvent::ClearChat(clear_chat) => {
+                match &clear_chat.target_user {
+                    Some(user) => self
+                        .recent_messages
+                        .borrow_mut()
+                        .retain(|tm| tm.user.name != *user),
+                    None => self.recent_messages.borrow_mut().clear(),
+                }
+                Some(LogTextMessage(
+                    match (clear_chat.target_user, clear_chat.duration) {
+                        (Some(user), Some(duration)) => {
+                            format!("{} was timed out for {}s", user, duration.as_secs())
+                        }
+                        (Some(user), None) => format!("{} was banned", user),
+                        (None, _) => "The chat was cleared".to_owned(),
+                    },
+                ))
+            }
+            ChatBotEvent::RoomState(delta) => {
+                self.room_states
+                    .entry(delta.channel.to_owned())
+                    .or_insert_with(|| RoomState {
+                        channel: delta.channel.to_owned(),
+                        ..Default::default()
+                    })
+                    .update(delta);
                 None
             }
+            // the bot's own user state only matters for sending messages
+            ChatBotEvent::UserState(_) | ChatBotEvent::GlobalUserState(_) => None,
+            ChatBotEvent::HostTarget {
+                channel: name,
+                target,
+                ..
+            } => {
+                let channel = self.channel(&name);
+                channel.hosting = target;
+                match &channel.hosting {
+                    Some(target) => Some(send(
+                        &name,
+                        format!(
+                            "We are now hosting {}, go check them out at https://twitch.tv/{}",
+                            target, target
+                        ),
+                    )),
+                    None => Some(LogTextMessage("Hosting ended".to_owned())),
+                }
+            }
+            ChatBotEvent::Whisper(whisper) => self.whisper(&whisper),
+            ChatBotEvent::Notice(notice) => Some(LogTextMessage(format!(
+                "Notice from twitch: {}",
+                notice.text
+            ))),
+            ChatBotEvent::ClearMessage(clear_message) => {
+                let deleted = self
+                    .recent_messages
+                    .borrow_mut()
+                    .remove(&clear_message.target_message_id);
+                let author = match deleted {
+                    Some(deleted) => deleted.user.display_name().to_owned(),
+                    None => clear_message.login,
+                };
+                Some(LogTextMessage(format!(
+                    "Message {} of {} was deleted: {}",
+                    clear_message.target_message_id, author, clear_message.text
+                )))
+            }
             ChatBotEvent::TextMessage(tm) => {
-                Some(LogTextMessage(format!("{}: {}", &tm.user.name, &tm.text)))
-            }
-            ChatBotEvent::TimedMessage(message_name, id) => {
-                self.repeating_messages.get(&message_name).and_then(|msg| {
-                    if id == msg.timer_id {
-                        Some(MultipleCommands(vec![
-                            ChatBotCommand::SendMessage(msg.text.to_owned()),
-                            TimedCallback {
-                                duration: msg.interval,
-                                event: ChatBotEvent::TimedMessage(msg.name.to_owned(), id),
-                            },
-                        ]))
-                    } else {
-                        None
-                    }
-                })
+                self.recent_messages.borrow_mut().remember(&tm);
+                self.timers.borrow_mut().message(&tm.channel);
+                let line = match tm.bits {
+                    Some(bits) => format!(
+                        "{} cheered {} bits: {}",
+                        tm.user.display_name(),
+                        bits,
+                        tm.text_without_cheermotes()
+                    ),
+                    None if tm.is_action => format!("* {} {}", tm.user.display_name(), &tm.text),
+                    None => format!("{}: {}", tm.user.display_name(), &tm.text),
+                };
+                let mut commands = vec![LogTextMessage(format!("{}{}", sent_time(&tm), line))];
+                let greeting = self.greeter.greet(&tm, Instant::now());
+                commands.extend(greeting.and_then(|greeting| self.send_if_live(greeting)));
+                // messages with another prefix than '!' are no Command event
+                match self.commands.dispatch(&tm, Instant::now()) {
+                    Dispatch::Handled(Some(command)) => commands.push(command),
+                    Dispatch::Dropped => self.metrics.dropped += 1,
+                    _ => {}
+                }
+                commands.extend(self.bits.borrow_mut().cheer(&tm));
+                if commands.len() == 1 {
+                    commands.pop()
+                } else {
+                    Some(MultipleCommands(commands))
+                }
+            }
+            ChatBotEvent::LiveStatus { channel, live } => {
+                let now = SystemTime::now();
+                self.watch_time.borrow_mut().live_status(&channel, live);
+                self.lurks.borrow_mut().live_status(&channel, live, now);
+                let (change, check) =
+                    self.stream_status
+                        .live_status(&channel, live, now, &self.chat_stats.borrow());
+                let message = change.and_then(|change| self.stream_changed(change));
+                let commands: Vec<_> = message.into_iter().chain(check).collect();
+                (!commands.is_empty()).then_some(MultipleCommands(commands))
+            }
+            ChatBotEvent::Api(call) => {
+                let (answer, command) = self.api(&call.request);
+                call.answer(answer);
+                command
+            }
+            ChatBotEvent::EventSubWelcome { session_id } => self.subscribe(&session_id),
+            ChatBotEvent::Redemption(redemption) => {
+                let speak = self.tts.redemption(&redemption);
+                match (speak, self.redeem(redemption)) {
+                    (Some(speak), Some(actions)) => Some(MultipleCommands(vec![speak, actions])),
+                    (speak, actions) => speak.or(actions),
+                }
             }
+            ChatBotEvent::ShieldMode {
+                channel,
+                active,
+                asked,
+            } => self.moderation.borrow_mut().shield(&channel, active, asked),
+            ChatBotEvent::HypeTrain {
+                channel,
+                stage,
+                level,
+                contributors,
+            } => self
+                .hype_trains
+                .event(&channel, stage, level, &contributors),
+            ChatBotEvent::Follow {
+                channel,
+                login,
+                name,
+            } => {
+                tracing::info!(%channel, user = %login, "followed");
+                self.follows.follow(&channel, &login, &name)
+            }
+            // the USERNOTICE in chat is thanked, it tells the months as well
+            ChatBotEvent::Subscribed {
+                channel,
+                name,
+                tier,
+                gift,
+                ..
+            } => Some(LogTextMessage(match gift {
+                true => format!(
+                    "{} got a gifted {} sub in {}",
+                    name,
+                    subs::tier(tier),
+                    channel
+                ),
+                false => format!("{} subscribed at {} in {}", name, subs::tier(tier), channel),
+            })),
+            ChatBotEvent::Internal(event) => self.internal(event),
+        }
+    }
+
+    // what the bot does about what it scheduled for itself
+    fn internal(&mut self, event: InternalEvent) -> Option<ChatBotCommand> {
+        use ChatBotCommand::*;
+        match event {
+            InternalEvent::TimedMessage {
+                channel: name,
+                name: message_name,
+                id,
+            } => {
+                let channel = self.channels.get(&name)?;
+                channel
+                    .repeating_messages
+                    .get(&message_name)
+                    .and_then(|msg| {
+                        let callback = TimedCallback {
+                            duration: msg.interval,
+                            event: ChatBotEvent::Internal(InternalEvent::TimedMessage {
+                                channel: name.clone(),
+                                name: msg.name.to_owned(),
+                                id,
+                            }),
+                        };
+                        if id != msg.timer_id {
+                            None
+                        } else if channel.hosting.is_some() {
+                            // keep the timer running, but stay quiet while hosting
+                            Some(callback)
+                        } else {
+                            // sent again and again, so a single message is enough
+                            Some(MultipleCommands(vec![
+                                SendMessage {
+                                    channel: name.clone(),
+                                    text: msg.text.to_owned(),
+                                    overflow: Overflow::Truncate,
+                                },
+                                callback,
+                            ]))
+                        }
+                    })
+            }
+            InternalEvent::ScheduleTick => {
+                let now = SystemTime::now();
+                let stream_status = &self.stream_status;
+                let due = self
+                    .schedule
+                    .tick(now, |channel| stream_status.is_live(channel));
+                let mut commands: Vec<_> = due
+                    .into_iter()
+                    .filter_map(|due| self.scheduled(due))
+                    .collect();
+                commands.push(Schedule::next_tick(now));
+                Some(MultipleCommands(commands))
+            }
+            InternalEvent::TimerTick => {
+                let mut commands: Vec<_> = self
+                    .timers
+                    .borrow_mut()
+                    .tick(Instant::now())
+                    .into_iter()
+                    .map(|(channel, text, color)| match color {
+                        Some(color) => announce(&channel, text, color, Overflow::Truncate),
+                        None => SendMessage {
+                            channel,
+                            text,
+                            overflow: Overflow::Truncate,
+                        },
+                    })
+                    .collect();
+                commands.push(TimedCallback {
+                    duration: TIMER_TICK,
+                    event: ChatBotEvent::Internal(InternalEvent::TimerTick),
+                });
+                Some(MultipleCommands(commands))
+            }
+            InternalEvent::RestoreSlowMode { channel, id } => self.raids.restore(&channel, id),
+            InternalEvent::NukeTick => self.moderation.borrow_mut().nuker.tick(),
+            InternalEvent::RaffleEnd { channel, id } => {
+                let mut raffles = self.raffles.borrow_mut();
+                let text = raffles.end(&channel, Some(id))?;
+                raffles.with_whispers(Some(send(&channel, text)))
+            }
+            InternalEvent::PollEnd { channel, id } => {
+                let results = self.polls.borrow_mut().end(&channel, Some(id))?;
+                Some(send(&channel, format!("The poll is over: {}", results)))
+            }
+            InternalEvent::RaffleEntry {
+                channel,
+                id,
+                login,
+                name,
+                tickets,
+            } => {
+                self.raffles
+                    .borrow_mut()
+                    .follower(&channel, id, &login, &name, tickets);
+                None
+            }
+            InternalEvent::ReminderDue { id } => {
+                self.reminders.borrow_mut().deliver(id, SystemTime::now())
+            }
+            InternalEvent::LurkTick => {
+                let mut commands = self.lurks.borrow_mut().tick(SystemTime::now());
+                match commands.len() {
+                    0 | 1 => commands.pop(),
+                    _ => Some(MultipleCommands(commands)),
+                }
+            }
+            InternalEvent::WatchTick => {
+                let commands = self.watch_time.borrow_mut().tick(SystemTime::now());
+                Some(MultipleCommands(commands))
+            }
+            InternalEvent::StreamStatusTick => Some(MultipleCommands(self.stream_status.poll())),
+            InternalEvent::StreamEnded { channel, id } => {
+                let change = self
+                    .stream_status
+                    .ended(&channel, id, &self.chat_stats.borrow())?;
+                self.stream_changed(change)
+            }
+            InternalEvent::QueueGrace { channel, login, id } => {
+                self.queue.borrow_mut().grace_over(&channel, &login, id);
+                None
+            }
+            InternalEvent::TriviaTimer { channel, id } => {
+                self.trivia.borrow_mut().timer(&channel, id)
+            }
+            InternalEvent::DuelExpired { channel, id } => {
+                self.duels.borrow_mut().expire(&channel, id)
+            }
+            InternalEvent::PointsTick => {
+                let paid = self
+                    .points
+                    .borrow_mut()
+                    .tick(SystemTime::now(), Instant::now());
+                let tick = TimedCallback {
+                    duration: POINTS_TICK,
+                    event: ChatBotEvent::Internal(InternalEvent::PointsTick),
+                };
+                match paid {
+                    0 => Some(tick),
+                    _ => Some(MultipleCommands(vec![
+                        LogTextMessage(format!("Paid points to {} users", paid)),
+                        tick,
+                    ])),
+                }
+            }
+            InternalEvent::ClipPending {
+                channel,
+                id,
+                edit_url,
+                attempt,
+            } => Some(Helix(HelixTask::CheckClip {
+                channel,
+                id,
+                edit_url,
+                attempt,
+            })),
+            InternalEvent::WhisperRetry {
+                channel,
+                login,
+                text,
+                fallback,
+                attempt,
+            } => Some(ChatBotCommand::Helix(HelixTask::Whisper {
+                channel,
+                login,
+                text,
+                fallback,
+                attempt,
+            })),
+            InternalEvent::TwitchPollPending {
+                channel,
+                id,
+                attempt,
+            } => Some(Helix(HelixTask::CheckPoll {
+                channel,
+                id,
+                attempt,
+            })),
         }
     }
 }
@@ -206,26 +1461,71 @@ impl ChatBot {
 #[cfg(test)]
 mod testing {
     use super::*;
-    use crate::connect::{Badge, TextMessage, UserInfo};
+    use crate::config::TimerConfig;
+    use crate::connect::{Badge, ClearChat, ClearMessage, ReplyParent, UserInfo};
+    use crate::core::recent_messages::RECENT_MESSAGES;
+
+    // connected long enough ago to greet new chatters
+    fn greeting_bot() -> ChatBot {
+        let mut bot = ChatBot::new();
+        let connected = Instant::now().checked_sub(Duration::from_secs(10 * 60));
+        bot.greeter
+            .connected(connected.expect("the machine runs for a while"));
+        bot
+    }
 
     // It's now easy to test without connecting
     #[test]
     fn test_join() {
         let mut bot = ChatBot::new();
-        let result = bot.handle_event(ChatBotEvent::Join(String::from("Carkhy")));
-        assert!(matches!(result, None));
-        assert_eq!(bot.chatters.len(), 1);
-        assert_eq!(bot.chatters.get("Carkhy").unwrap(), "Carkhy");
+        let result = bot.handle_event(ChatBotEvent::Join {
+            user: String::from("Carkhy"),
+            channel: String::from("captaincallback"),
+        });
+        assert!(result.is_none());
+        assert_eq!(bot.channels["captaincallback"].chatters.len(), 1);
+        assert_eq!(
+            bot.channels["captaincallback"]
+                .chatters
+                .get("Carkhy")
+                .unwrap(),
+            "Carkhy"
+        );
+    }
+
+    #[test]
+    fn names_are_accumulated() {
+        let mut bot = ChatBot::new();
+        for users in [vec!["Carkhy", "CaptainCallback"], vec!["AnotherUser"]] {
+            bot.handle_event(ChatBotEvent::Names {
+                channel: String::from("captaincallback"),
+                users: users.into_iter().map(String::from).collect(),
+            });
+        }
+        let result = bot.handle_event(ChatBotEvent::EndOfNames {
+            channel: String::from("captaincallback"),
+        });
+        assert!(result.is_none());
+        assert_eq!(bot.channels["captaincallback"].chatters.len(), 3);
+        assert!(bot.channels["captaincallback"]
+            .chatters
+            .contains("AnotherUser"));
     }
 
     #[test]
     fn test_part() {
         let mut bot = ChatBot::new();
-        bot.handle_event(ChatBotEvent::Join(String::from("Carkhy")));
-        let result = bot.handle_event(ChatBotEvent::Part(String::from("Carkhy")));
-        assert!(matches!(result, None));
-        assert_eq!(bot.chatters.len(), 0);
-        assert!(matches!(bot.chatters.get("Carkhy"), None));
+        bot.handle_event(ChatBotEvent::Join {
+            user: String::from("Carkhy"),
+            channel: String::from("captaincallback"),
+        });
+        let result = bot.handle_event(ChatBotEvent::Part {
+            user: String::from("Carkhy"),
+            channel: String::from("captaincallback"),
+        });
+        assert!(result.is_none());
+        assert_eq!(bot.channels["captaincallback"].chatters.len(), 0);
+        assert!(!bot.channels["captaincallback"].chatters.contains("Carkhy"));
     }
 
     #[test]
@@ -235,8 +1535,10 @@ mod testing {
             text: "Hello".to_string(),
             user: UserInfo {
                 name: "Carkhy".to_owned(),
-                badges: HashSet::default(),
+                badges: Vec::default(),
+                ..Default::default()
             },
+            ..Default::default()
         }));
         assert!(
             matches!(result, Some(ChatBotCommand::LogTextMessage(message)) if message == "Carkhy: Hello")
@@ -244,86 +1546,678 @@ mod testing {
     }
 
     #[test]
-    fn invalid_slapping() {
+    fn text_message_log_uses_sent_time() {
         let mut bot = ChatBot::new();
-        let result = bot.handle_event(ChatBotEvent::Command(Command {
+        let result = bot.handle_event(ChatBotEvent::TextMessage(TextMessage {
+            text: "Hello".to_string(),
+            user: UserInfo {
+                name: "Carkhy".to_owned(),
+                ..Default::default()
+            },
+            timestamp: Some(UNIX_EPOCH + Duration::from_millis(1637614002702)),
+            ..Default::default()
+        }));
+        assert!(
+            matches!(result, Some(ChatBotCommand::LogTextMessage(message)) if message == "[20:46:42] Carkhy: Hello")
+        );
+    }
+
+    #[test]
+    fn ignored_users_and_the_bot_itself_get_no_answer() {
+        let mut config = Config::default();
+        config.twitch.user = "TwitchBotanist".to_owned();
+        config.moderation.ignored_users = vec!["Nightbot".to_owned()];
+        let mut bot = ChatBot::load(&config, Storage::default()).unwrap();
+        let message = |user: &str, text: &str| {
+            ChatBotEvent::TextMessage(TextMessage {
+                channel: "captaincallback".to_owned(),
+                text: text.to_owned(),
+                user: UserInfo {
+                    name: user.to_owned(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        for user in ["nightbot", "NightBot", "twitchbotanist"] {
+            assert!(
+                bot.handle_event(message(user, "?info")).is_none(),
+                "{}",
+                user
+            );
+        }
+        assert!(bot.handle_event(message("carkhy", "Hello")).is_some());
+        assert_eq!(
+            bot.metrics,
+            Metrics {
+                messages: 1,
+                ignored: 3,
+                dropped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn links_are_deleted_and_the_user_told_once() {
+        let mut config = Config::default();
+        config.moderation.link_filter = true;
+        let mut bot = ChatBot::load(&config, Storage::default()).unwrap();
+        let link = |id: &str| {
+            ChatBotEvent::TextMessage(TextMessage {
+                channel: "captaincallback".to_owned(),
+                text: "free followers at example.com".to_owned(),
+                message_id: Some(id.to_owned()),
+                user: UserInfo {
+                    name: "spammer".to_owned(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let commands = |result: Option<ChatBotCommand>| -> Vec<ChatBotCommand> {
+            let Some(ChatBotCommand::MultipleCommands(commands)) = result else {
+                panic!("{:?}", result);
+            };
+            commands
+        };
+        let first = commands(bot.handle_event(link("1")));
+        assert!(matches!(
+            &first[1],
+            ChatBotCommand::Helix(HelixTask::DeleteMessage { message_id, .. }) if message_id == "1"
+        ));
+        assert!(matches!(
+            &first[2],
+            ChatBotCommand::SendMessage { text, .. } if text == "@spammer, please ask a moderator before posting links."
+        ));
+        assert_eq!(commands(bot.handle_event(link("2"))).len(), 2);
+    }
+
+    #[test]
+    fn channels_have_their_own_config() {
+        let config: Config = toml::from_str(
+            "[twitch]\nchannels = [\"#captaincallback\", \"#carkhy\"]\n\
+             [points]\nenabled = true\n\
+             [moderation]\nbanned_terms = [{ term = \"spoiler\" }]\n\
+             [channels.carkhy]\ncommands = { prefix = \"?\" }\n\
+             points = { enabled = false }\n\
+             moderation = { banned_terms = [{ term = \"spam\" }] }\n",
+        )
+        .unwrap();
+        let mut bot = ChatBot::load(&config, Storage::default()).unwrap();
+        let mut message = |channel: &str, text: &str| {
+            let result = bot.handle_event(ChatBotEvent::TextMessage(TextMessage {
+                channel: channel.to_owned(),
+                text: text.to_owned(),
+                message_id: Some("1".to_owned()),
+                user: UserInfo {
+                    name: "viewer".to_owned(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }));
+            format!("{:?}", result)
+        };
+        // chatting in captaincallback earned a point, carkhy has none
+        assert!(message("captaincallback", "!points").contains("you have 1 points"));
+        assert!(!message("captaincallback", "?points").contains("you have"));
+        assert!(!message("carkhy", "?points").contains("you have"));
+        assert!(message("carkhy", "?discord").contains("SendMessage"));
+        assert!(message("captaincallback", "no spoiler please").contains("banned term"));
+        assert!(!message("captaincallback", "spam").contains("banned term"));
+        assert!(message("carkhy", "spam").contains("banned term"));
+        assert!(!message("carkhy", "no spoiler please").contains("banned term"));
+    }
+
+    #[test]
+    fn first_time_chatters_are_greeted_once() {
+        let mut bot = greeting_bot();
+        let first_message = || {
+            ChatBotEvent::TextMessage(TextMessage {
+                text: "Hi!".to_string(),
+                user: UserInfo {
+                    name: "carkhy".to_owned(),
+                    display_name: Some("Carkhy".to_owned()),
+                    ..Default::default()
+                },
+                first_msg: true,
+                ..Default::default()
+            })
+        };
+        let result = bot.handle_event(first_message());
+        assert!(
+            matches!(result, Some(ChatBotCommand::MultipleCommands(commands))
+                         if matches!(&commands[..], [ChatBotCommand::LogTextMessage(_), ChatBotCommand::SendMessage { text: greeting, .. }]
+                                     if greeting.starts_with("Welcome to the chat, Carkhy!")))
+        );
+        let result = bot.handle_event(first_message());
+        assert!(matches!(result, Some(ChatBotCommand::LogTextMessage(_))));
+    }
+
+    #[test]
+    fn quote_repeats_the_parent_message() {
+        let mut bot = ChatBot::new();
+        let quote = |reply_to: Option<ReplyParent>| {
+            ChatBotEvent::Command(Command {
+                kind: CommandType::Quote,
+                options: Vec::new(),
+                message: TextMessage {
+                    // twitch starts replies with a mention
+                    text: match reply_to {
+                        Some(_) => "@Carkhy !quote".to_string(),
+                        None => "!quote".to_string(),
+                    },
+                    reply_to,
+                    ..Default::default()
+                },
+            })
+        };
+        let parent = || ReplyParent {
+            message_id: "b34ccfc7".to_owned(),
+            user_login: "carkhy".to_owned(),
+            display_name: "Carkhy".to_owned(),
+            body: "so it begins".to_owned(),
+        };
+        // the bot didn't see the parent, the tags tell it
+        let result = bot.handle_event(quote(Some(parent())));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message == "\"so it begins\" - Carkhy")
+        );
+        // the message as the bot saw it
+        bot.handle_event(message_with_id("carkhy", "b34ccfc7"));
+        let result = bot.handle_event(quote(Some(parent())));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message == "\"Buy followers at example.com\" - CARKHY")
+        );
+        let result = bot.handle_event(quote(None));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message == "There are no quotes yet, add one with !addquote.")
+        );
+    }
+
+    fn message_with_id(user: &str, id: &str) -> ChatBotEvent {
+        ChatBotEvent::TextMessage(TextMessage {
+            text: "Buy followers at example.com".to_string(),
             user: UserInfo {
-                name: "CaptainCallback".to_owned(),
-                badges: HashSet::default(),
+                name: user.to_owned(),
+                display_name: Some(user.to_uppercase()),
+                ..Default::default()
             },
+            message_id: Some(id.to_owned()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn deleted_message_is_removed_from_recent_messages() {
+        let mut bot = ChatBot::new();
+        bot.handle_event(message_with_id("carkhy", "first"));
+        bot.handle_event(message_with_id("spammer", "second"));
+        let result = bot.handle_event(ChatBotEvent::ClearMessage(ClearMessage {
+            login: "spammer".to_owned(),
+            channel: "captaincallback".to_owned(),
+            target_message_id: "second".to_owned(),
+            text: "Buy followers at example.com".to_owned(),
+        }));
+        assert!(
+            matches!(result, Some(ChatBotCommand::LogTextMessage(message))
+                         if message == "Message second of SPAMMER was deleted: Buy followers at example.com")
+        );
+        let recent = bot.recent_messages.borrow();
+        assert!(recent.get("first").is_some());
+        assert!(recent.get("second").is_none());
+    }
+
+    #[test]
+    fn recent_messages_are_limited() {
+        let mut bot = ChatBot::new();
+        for id in 0..RECENT_MESSAGES + 5 {
+            bot.handle_event(message_with_id("carkhy", &id.to_string()));
+        }
+        assert!(bot.recent_messages.borrow().get("4").is_none());
+        assert!(bot.recent_messages.borrow().get("5").is_some());
+        bot.handle_event(ChatBotEvent::ClearChat(ClearChat {
+            channel: "captaincallback".to_owned(),
+            target_user: Some("carkhy".to_owned()),
+            duration: None,
+        }));
+        assert!(bot.recent_messages.borrow().get("104").is_none());
+    }
+
+    #[test]
+    fn invalid_slapping() {
+        let mut bot = ChatBot::new();
+        let result = bot.handle_event(ChatBotEvent::Command(Command {
             kind: CommandType::Slap,
             options: vec!["Carkhy".to_string()],
+            message: TextMessage {
+                user: UserInfo {
+                    name: "CaptainCallback".to_owned(),
+                    badges: Vec::default(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
         }));
-        assert!(matches!(result, None));
+        assert!(result.is_none());
     }
 
     #[test]
     fn valid_slapping_when_abstraction_detected() {
         let mut bot = ChatBot::new();
-        bot.handle_event(ChatBotEvent::Join(String::from("CaptainCallback")));
+        bot.handle_event(ChatBotEvent::Join {
+            user: String::from("CaptainCallback"),
+            channel: String::from("captaincallback"),
+        });
         let result = bot.handle_event(ChatBotEvent::Command(Command {
-            user: UserInfo {
-                name: "Carkhy".to_owned(),
-                badges: HashSet::default(),
-            },
             kind: CommandType::Slap,
             options: vec!["CaptainCallback".to_string()],
+            message: TextMessage {
+                user: UserInfo {
+                    name: "Carkhy".to_owned(),
+                    badges: Vec::default(),
+                    ..Default::default()
+                },
+                channel: "captaincallback".to_owned(),
+                ..Default::default()
+            },
         }));
-        assert!(matches!(result, Some(ChatBotCommand::SendMessage(message))
-                         if message == format!("{} slaps {} around a bit with a large trout", "Carkhy", "CaptainCallback")));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message == format!("{} slaps {} around a bit with a large trout", "Carkhy", "CaptainCallback"))
+        );
     }
 
     #[test]
     fn nonmods_cannot_newcommand() {
         let mut bot = ChatBot::new();
         let result = bot.handle_event(ChatBotEvent::Command(Command {
-            user: UserInfo {
-                name: "CaptainCallback".to_owned(),
-                badges: HashSet::default(),
-            },
             kind: CommandType::NewCommand,
             options: vec!["test".to_string(), "testing".to_string()],
+            message: TextMessage {
+                user: UserInfo {
+                    name: "CaptainCallback".to_owned(),
+                    badges: Vec::default(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
         }));
-        assert!(matches!(result, Some(ChatBotCommand::SendMessage(message))
-                         if message == DENIED_MESSAGE));
-        assert!(!bot.dynamic_commands.contains_key("test"));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message == DENIED_MESSAGE)
+        );
+        assert!(bot.commands.custom().borrow().get("", "test").is_none());
     }
 
     #[test]
-    fn broadcaster_can_newcommand() {
+    fn denial_is_a_threaded_reply() {
         let mut bot = ChatBot::new();
         let result = bot.handle_event(ChatBotEvent::Command(Command {
-            user: UserInfo {
-                name: "CaptainCallback".to_owned(),
-                badges: HashSet::from([Badge {
-                    name: "broadcaster".to_owned(),
-                    level: 1,
-                }]),
+            kind: CommandType::RemoveCommand,
+            options: vec!["test".to_string()],
+            message: TextMessage {
+                user: UserInfo {
+                    name: "CaptainCallback".to_owned(),
+                    ..Default::default()
+                },
+                message_id: Some("b34ccfc7".to_owned()),
+                ..Default::default()
             },
+        }));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendReply { parent_msg_id, text, .. })
+                         if parent_msg_id == "b34ccfc7" && text == DENIED_MESSAGE)
+        );
+    }
+
+    #[test]
+    fn broadcaster_can_newcommand() {
+        let mut bot = ChatBot::new();
+        let result = bot.handle_event(ChatBotEvent::Command(Command {
             kind: CommandType::NewCommand,
             options: vec!["test".to_string(), "testing".to_string()],
+            message: TextMessage {
+                user: UserInfo {
+                    name: "CaptainCallback".to_owned(),
+                    badges: vec![Badge::Broadcaster],
+                    ..Default::default()
+                },
+                level: UserLevel::Broadcaster,
+                ..Default::default()
+            },
         }));
-        assert!(matches!(result, Some(ChatBotCommand::SendMessage(message))
-                         if message != DENIED_MESSAGE));
-        assert!(bot.dynamic_commands.contains_key("test"));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message != DENIED_MESSAGE)
+        );
+        assert!(bot.commands.custom().borrow().get("", "test").is_some());
     }
 
     #[test]
     fn mods_can_newcommand() {
         let mut bot = ChatBot::new();
         let result = bot.handle_event(ChatBotEvent::Command(Command {
-            user: UserInfo {
-                name: "CaptainCallback".to_owned(),
-                badges: HashSet::from([Badge {
-                    name: "moderator".to_owned(),
-                    level: 1,
-                }]),
-            },
             kind: CommandType::NewCommand,
             options: vec!["test2".to_string(), "testing2".to_string()],
+            message: TextMessage {
+                user: UserInfo {
+                    name: "CaptainCallback".to_owned(),
+                    badges: vec![Badge::Moderator],
+                    ..Default::default()
+                },
+                level: UserLevel::Moderator,
+                ..Default::default()
+            },
+        }));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text: message, .. })
+                         if message != DENIED_MESSAGE)
+        );
+        assert!(bot.commands.custom().borrow().get("", "test2").is_some());
+    }
+
+    #[test]
+    fn a_keyword_like_a_command_enters_the_raffle() {
+        let mut bot = ChatBot::new();
+        let mut say = |login: &str, level, text: &str| {
+            let answer = bot.handle_event(ChatBotEvent::TextMessage(TextMessage {
+                channel: "carkhy".to_owned(),
+                text: text.to_owned(),
+                user: UserInfo {
+                    name: login.to_owned(),
+                    ..Default::default()
+                },
+                level,
+                ..Default::default()
+            }));
+            match answer {
+                Some(ChatBotCommand::MultipleCommands(commands)) => match &commands[..] {
+                    [ChatBotCommand::LogTextMessage(_), ChatBotCommand::SendMessage { text, .. }] => {
+                        Some(text.clone())
+                    }
+                    [ChatBotCommand::LogTextMessage(_)] => None,
+                    _ => panic!("{:?}", commands),
+                },
+                _ => None,
+            }
+        };
+        assert_eq!(
+            say("carkhy", UserLevel::Broadcaster, "!raffle start !enter").as_deref(),
+            Some("The raffle is on! Write !enter to enter.")
+        );
+        assert_eq!(say("viewer", UserLevel::Everyone, "!enter"), None);
+        assert_eq!(
+            say("carkhy", UserLevel::Broadcaster, "!raffle end").as_deref(),
+            Some("The raffle is over, the only entrant viewer wins!")
+        );
+    }
+
+    #[test]
+    fn channels_keep_their_own_state() {
+        let mut bot = greeting_bot();
+        let command = |kind: CommandType, options: &[&str], channel: &str| {
+            let name = match &kind {
+                CommandType::Dynamic(name) => name.to_owned(),
+                _ => "newcommand".to_owned(),
+            };
+            ChatBotEvent::Command(Command {
+                kind,
+                options: options.iter().map(|option| option.to_string()).collect(),
+                message: TextMessage {
+                    user: UserInfo {
+                        name: "captaincallback".to_owned(),
+                        ..Default::default()
+                    },
+                    channel: channel.to_owned(),
+                    level: UserLevel::Moderator,
+                    text: format!("!{} {}", name, options.join(" ")),
+                    ..Default::default()
+                },
+            })
+        };
+        bot.handle_event(command(
+            CommandType::NewCommand,
+            &["hi", "Shout-out!"],
+            "captaincallback",
+        ));
+        bot.handle_event(ChatBotEvent::Join {
+            user: "carkhy".to_owned(),
+            channel: "carkhy".to_owned(),
+        });
+        let result = bot.handle_event(command(
+            CommandType::Dynamic("hi".to_owned()),
+            &[],
+            "captaincallback",
+        ));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { channel, text, .. })
+                         if channel == "captaincallback" && text == "Shout-out!")
+        );
+        let result = bot.handle_event(command(
+            CommandType::Dynamic("hi".to_owned()),
+            &[],
+            "carkhy",
+        ));
+        assert!(result.is_none());
+        assert!(bot.channels["carkhy"].chatters.contains("carkhy"));
+        assert!(bot.channels["captaincallback"].chatters.is_empty());
+
+        // a first message is greeted in every channel
+        for channel in ["captaincallback", "carkhy"] {
+            let result = bot.handle_event(ChatBotEvent::TextMessage(TextMessage {
+                text: "Hi!".to_string(),
+                user: UserInfo {
+                    name: "viewer".to_owned(),
+                    ..Default::default()
+                },
+                channel: channel.to_owned(),
+                first_msg: true,
+                ..Default::default()
+            }));
+            assert!(
+                matches!(&result, Some(ChatBotCommand::MultipleCommands(commands))
+                             if matches!(&commands[..], [_, ChatBotCommand::SendMessage { channel: to, .. }] if to == channel)),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn only_the_broadcaster_joins_and_parts_channels() {
+        let mut bot = ChatBot::new();
+        let command = |kind, level| {
+            ChatBotEvent::Command(Command {
+                kind,
+                options: vec!["#Carkhy".to_owned()],
+                message: TextMessage {
+                    channel: "captaincallback".to_owned(),
+                    level,
+                    ..Default::default()
+                },
+            })
+        };
+        let result = bot.handle_event(command(CommandType::Join, UserLevel::Moderator));
+        assert!(
+            matches!(result, Some(ChatBotCommand::SendMessage { text, .. }) if text == DENIED_MESSAGE)
+        );
+        let result = bot.handle_event(command(CommandType::Join, UserLevel::Broadcaster));
+        assert!(matches!(result, Some(ChatBotCommand::JoinChannel(name)) if name == "carkhy"));
+
+        bot.handle_event(ChatBotEvent::Join {
+            user: "viewer".to_owned(),
+            channel: "carkhy".to_owned(),
+        });
+        bot.handle_event(ChatBotEvent::RoomState(RoomState {
+            channel: "carkhy".to_owned(),
+            slow: Some(30),
+            ..Default::default()
+        }));
+        for channel in ["carkhy", "captaincallback"] {
+            if let ChatBotEvent::TextMessage(message) = message_with_id("viewer", channel) {
+                bot.handle_event(ChatBotEvent::TextMessage(TextMessage {
+                    channel: channel.to_owned(),
+                    ..message
+                }));
+            }
+        }
+        let result = bot.handle_event(command(CommandType::Part, UserLevel::Broadcaster));
+        assert!(matches!(result, Some(ChatBotCommand::PartChannel(name)) if name == "carkhy"));
+        assert!(!bot.channels.contains_key("carkhy"));
+        assert!(!bot.room_states.contains_key("carkhy"));
+        let recent = bot.recent_messages.borrow();
+        assert!(recent.get("carkhy").is_none());
+        assert_eq!(
+            recent.get("captaincallback").unwrap().channel,
+            "captaincallback"
+        );
+    }
+
+    #[test]
+    fn room_state_deltas_are_merged() {
+        let mut bot = ChatBot::new();
+        bot.handle_event(ChatBotEvent::RoomState(RoomState {
+            channel: "captaincallback".to_owned(),
+            emote_only: Some(false),
+            followers_only: Some(-1),
+            r9k: Some(false),
+            slow: Some(0),
+            subs_only: Some(false),
+        }));
+        bot.handle_event(ChatBotEvent::RoomState(RoomState {
+            channel: "captaincallback".to_owned(),
+            slow: Some(30),
+            ..Default::default()
+        }));
+        let room_state = bot.room_states.get("captaincallback").unwrap();
+        assert_eq!(room_state.slow, Some(30));
+        assert_eq!(room_state.emote_only, Some(false));
+        assert_eq!(room_state.followers_only, Some(-1));
+    }
+
+    #[test]
+    fn repeating_messages_pause_while_hosting() {
+        let mut bot = ChatBot::new();
+        let id = Uuid::new_v4();
+        bot.channel("captaincallback").repeating_messages.insert(
+            "ad".to_owned(),
+            RepeatingMessage {
+                name: "ad".to_owned(),
+                text: "Follow the channel".to_owned(),
+                interval: Duration::from_secs(60),
+                timer_id: id,
+            },
+        );
+        let result = bot.handle_event(ChatBotEvent::HostTarget {
+            channel: "captaincallback".to_owned(),
+            target: Some("carkhy".to_owned()),
+            viewers: 5,
+        });
+        assert!(matches!(result, Some(ChatBotCommand::SendMessage { .. })));
+        let result = bot.handle_event(ChatBotEvent::Internal(InternalEvent::TimedMessage {
+            channel: "captaincallback".to_owned(),
+            name: "ad".to_owned(),
+            id,
         }));
-        assert!(matches!(result, Some(ChatBotCommand::SendMessage(message))
-                         if message != DENIED_MESSAGE));
-        assert!(bot.dynamic_commands.contains_key("test2"));
+        assert!(matches!(result, Some(ChatBotCommand::TimedCallback { .. })));
+
+        bot.handle_event(ChatBotEvent::HostTarget {
+            channel: "captaincallback".to_owned(),
+            target: None,
+            viewers: 0,
+        });
+        let result = bot.handle_event(ChatBotEvent::Internal(InternalEvent::TimedMessage {
+            channel: "captaincallback".to_owned(),
+            name: "ad".to_owned(),
+            id,
+        }));
+        assert!(matches!(result, Some(ChatBotCommand::MultipleCommands(_))));
+    }
+
+    #[test]
+    fn timers_tick_until_paused() {
+        let timer = TimerConfig {
+            channel: "#captaincallback".to_owned(),
+            name: "rules".to_owned(),
+            text: "Be nice".to_owned(),
+            interval: 600,
+            min_messages: 1,
+            announce: None,
+        };
+        // started long enough ago for the timer to be due
+        let start = Instant::now() - Duration::from_secs(600);
+        let mut bot = ChatBot::with_commands(
+            &CommandsConfig::default(),
+            CustomCommands::default(),
+            Quotes::default(),
+            Timers::new(&[timer], start),
+            IgnoreList::default(),
+            Moderation::default(),
+            Storage::default(),
+        );
+        assert!(ChatBot::new().start_timers().is_none());
+        assert!(bot.start_timers().is_some());
+        let texts = |result: Option<ChatBotCommand>| -> Vec<String> {
+            let Some(ChatBotCommand::MultipleCommands(commands)) = result else {
+                panic!("{:?}", result);
+            };
+            assert!(matches!(
+                commands.last(),
+                Some(ChatBotCommand::TimedCallback { duration, event: ChatBotEvent::Internal(InternalEvent::TimerTick) })
+                    if *duration == TIMER_TICK
+            ));
+            commands
+                .into_iter()
+                .filter_map(|command| match command {
+                    ChatBotCommand::SendMessage { text, .. } => Some(text),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert!(
+            texts(bot.handle_event(ChatBotEvent::Internal(InternalEvent::TimerTick))).is_empty()
+        );
+
+        let moderator = |text: &str| {
+            ChatBotEvent::TextMessage(TextMessage {
+                channel: "captaincallback".to_owned(),
+                text: text.to_owned(),
+                level: UserLevel::Moderator,
+                ..Default::default()
+            })
+        };
+        let result = bot.handle_event(moderator("!timers off"));
+        assert!(
+            matches!(&result, Some(ChatBotCommand::MultipleCommands(commands))
+                if matches!(&commands[1], ChatBotCommand::SendMessage { text, .. }
+                    if text == "Timers are paused.")),
+            "{:?}",
+            result
+        );
+        assert!(
+            texts(bot.handle_event(ChatBotEvent::Internal(InternalEvent::TimerTick))).is_empty()
+        );
+        bot.handle_event(moderator("!timers on"));
+        assert_eq!(
+            texts(bot.handle_event(ChatBotEvent::Internal(InternalEvent::TimerTick))),
+            vec!["Be nice"]
+        );
+    }
+
+    #[test]
+    fn eventsub_subscribes_in_every_channel() {
+        let mut config = Config::default();
+        config.twitch.channels = vec!["#captaincallback".to_owned(), "#carkhy".to_owned()];
+        assert!(subscriptions(&config).is_empty());
+        config.events.eventsub = true;
+        let subscribed = subscriptions(&config);
+        assert_eq!(subscribed.len(), 18);
+        assert!(subscribed.contains(&("carkhy".to_owned(), Subscription::Follows)));
+        assert!(!subscribed
+            .iter()
+            .any(|(_, kind)| *kind == Subscription::Redemptions));
     }
 }
@@ -0,0 +1,175 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A day in UTC, written like "2026-10-14".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    pub fn of(time: SystemTime) -> Self {
+        let days = time
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() / 86400)
+            .unwrap_or_default() as i64;
+        Self::from_days(days)
+    }
+
+    /// The date so many days after 1970-01-01, the civil_from_days algorithm by Howard Hinnant.
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Self { year, month, day }
+    }
+
+    /// Days since 1970-01-01, the days_from_civil algorithm by Howard Hinnant.
+    pub fn days(self) -> i64 {
+        let year = if self.month <= 2 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let doy = (153 * (self.month + if self.month > 2 { -3 } else { 9 }) + 2) / 5 + self.day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    /// "2026-10-14", None for anything else like "2026-02-30".
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split('-').map(|part| part.parse().ok());
+        let date = Self {
+            year: parts.next()??,
+            month: parts.next()??,
+            day: parts.next()??,
+        };
+        let valid =
+            (1..=12).contains(&date.month) && (1..=date.days_in_month()).contains(&date.day);
+        (valid && date.to_string() == text).then_some(date)
+    }
+
+    /// 0 for Sunday to 6 for Saturday, like cron counts.
+    pub fn weekday(self) -> i64 {
+        (self.days() + 4).rem_euclid(7)
+    }
+
+    pub fn days_in_month(self) -> i64 {
+        days_in_month(self.year, self.month)
+    }
+
+    /// Whole years, months and days from the date to the later one, like a person counts:
+    /// from January 31 to March 1 is 1 month and 1 day.
+    pub fn until(self, later: Date) -> (i64, i64, i64) {
+        let mut years = later.year - self.year;
+        let mut months = later.month - self.month;
+        let mut days = later.day - self.day;
+        if days < 0 {
+            months -= 1;
+            let (year, month) = match later.month {
+                1 => (later.year - 1, 12),
+                month => (later.year, month - 1),
+            };
+            // from the 31st, a shorter month ends on its last day
+            let length = days_in_month(year, month);
+            days = length - self.day.min(length) + later.day;
+        }
+        if months < 0 {
+            years -= 1;
+            months += 12;
+        }
+        (years, months, days)
+    }
+}
+
+/// "2026-10-14 20:46:42" in UTC, for logs read by people.
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() % 86400)
+        .unwrap_or_default();
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        Date::of(time),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn date(year: i64, month: i64, day: i64) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn dates_are_in_utc() {
+        let of = |seconds| Date::of(UNIX_EPOCH + Duration::from_secs(seconds)).to_string();
+        assert_eq!(of(0), "1970-01-01");
+        assert_eq!(of(951_782_400), "2000-02-29");
+        assert_eq!(of(1_637_614_002), "2021-11-22");
+        for days in [-719_468, -1, 0, 11_016, 20_740, 100_000] {
+            assert_eq!(Date::from_days(days).days(), days);
+        }
+        // a wednesday
+        assert_eq!(date(2026, 10, 14).weekday(), 3);
+        assert_eq!(Date::parse("2026-10-14"), Some(date(2026, 10, 14)));
+        for text in [
+            "2026-02-30",
+            "2026-13-01",
+            "2026-1-14",
+            "14.10.2026",
+            "2026-10-14-1",
+        ] {
+            assert_eq!(Date::parse(text), None, "{}", text);
+        }
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_637_614_002)),
+            "2021-11-22 20:46:42"
+        );
+    }
+
+    #[test]
+    fn months_are_counted_like_people_do() {
+        assert_eq!(date(2021, 11, 22).until(date(2026, 10, 14)), (4, 10, 22));
+        assert_eq!(date(2024, 1, 31).until(date(2024, 3, 1)), (0, 1, 1));
+        assert_eq!(date(2023, 12, 15).until(date(2024, 1, 14)), (0, 0, 30));
+        assert_eq!(date(2024, 2, 29).until(date(2025, 2, 28)), (0, 11, 30));
+        assert_eq!(date(2026, 10, 14).until(date(2026, 10, 14)), (0, 0, 0));
+    }
+}
@@ -0,0 +1,330 @@
+use super::{
+    calendar::Date,
+    commands::{Args, Command, Context},
+    ChatBotCommand,
+};
+use crate::{
+    connect::{TextMessage, MAX_MESSAGE_CHARS},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+const STORAGE_NAME: &str = "chat_stats";
+// the counts are written at most this often, not for each message
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+// users listed by `!topchatters`
+const TOP: usize = 5;
+
+/// What one user wrote, commands and emote-only messages are counted among the messages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counts {
+    pub messages: u64,
+    pub commands: u64,
+    pub emote_only: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Buckets {
+    // the day in UTC of the daily counts, like "2026-10-14"
+    day: String,
+    // by lowercase login
+    today: BTreeMap<String, Counts>,
+    all: BTreeMap<String, Counts>,
+}
+
+// the text outside of the emotes is only spaces
+fn is_emote_only(message: &TextMessage) -> bool {
+    let mut end = 0;
+    for emote in &message.emotes {
+        match message.text.get(end..emote.start) {
+            Some(before) if before.trim().is_empty() => end = emote.end,
+            _ => return false,
+        }
+    }
+    !message.emotes.is_empty()
+        && message
+            .text
+            .get(end..)
+            .is_some_and(|rest| rest.trim().is_empty())
+}
+
+/// How much everyone wrote in each channel, all-time and today, kept in the storage.
+#[derive(Debug, Default)]
+pub struct ChatStats {
+    storage: Storage,
+    // by channel
+    channels: BTreeMap<String, Buckets>,
+    // counts not saved yet, and when they were last saved
+    unsaved: bool,
+    flushed: Option<SystemTime>,
+}
+
+pub type SharedChatStats = Rc<RefCell<ChatStats>>;
+
+impl ChatStats {
+    pub fn load(storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            channels: storage.load(STORAGE_NAME)?,
+            storage,
+            ..Default::default()
+        })
+    }
+
+    /// Counts the message, the counts are saved with it if the last save was a while ago.
+    pub fn count(&mut self, message: &TextMessage, command: bool, now: SystemTime) {
+        let day = Date::of(now).to_string();
+        let buckets = self.channels.entry(message.channel.clone()).or_default();
+        if buckets.day != day {
+            buckets.day = day;
+            buckets.today.clear();
+        }
+        let login = message.user.name.to_lowercase();
+        let emote_only = is_emote_only(message);
+        for counts in [
+            buckets.today.entry(login.clone()).or_default(),
+            buckets.all.entry(login).or_default(),
+        ] {
+            counts.messages += 1;
+            counts.commands += u64::from(command);
+            counts.emote_only += u64::from(emote_only);
+        }
+        self.unsaved = true;
+        if self
+            .flushed
+            .is_none_or(|flushed| now >= flushed + FLUSH_INTERVAL)
+        {
+            self.flushed = Some(now);
+            self.flush();
+        }
+    }
+
+    /// Writes what was counted since the last save, the bot does so when it stops.
+    pub fn flush(&mut self) {
+        if !std::mem::take(&mut self.unsaved) {
+            return;
+        }
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.channels) {
+            tracing::warn!(%error, "could not save the chat stats");
+        }
+    }
+
+    // the daily counts are of an earlier day when nobody wrote today yet
+    fn bucket(
+        &self,
+        channel: &str,
+        today: bool,
+        now: SystemTime,
+    ) -> Option<&BTreeMap<String, Counts>> {
+        let buckets = self.channels.get(channel)?;
+        match today {
+            true => (buckets.day == Date::of(now).to_string()).then_some(&buckets.today),
+            false => Some(&buckets.all),
+        }
+    }
+
+    /// The user's all-time counts with their rank by messages, and today's counts.
+    pub fn stats(
+        &self,
+        channel: &str,
+        login: &str,
+        now: SystemTime,
+    ) -> Option<(Counts, usize, Counts)> {
+        let login = login.to_lowercase();
+        let all = self.bucket(channel, false, now)?;
+        let counts = *all.get(&login)?;
+        let rank = 1 + all
+            .values()
+            .filter(|other| other.messages > counts.messages)
+            .count();
+        let today = self
+            .bucket(channel, true, now)
+            .and_then(|today| today.get(&login))
+            .copied()
+            .unwrap_or_default();
+        Some((counts, rank, today))
+    }
+
+    /// The all-time messages of each user in the channel, by lowercase login.
+    pub fn messages(&self, channel: &str) -> BTreeMap<String, u64> {
+        self.channels
+            .get(channel)
+            .into_iter()
+            .flat_map(|buckets| &buckets.all)
+            .map(|(login, counts)| (login.clone(), counts.messages))
+            .collect()
+    }
+
+    /// The users who wrote the most messages, most first. Ranked here, not by the storage:
+    /// the messages are one of the counts kept for each user.
+    pub fn top(&self, channel: &str, today: bool, now: SystemTime) -> Vec<(&str, u64)> {
+        let mut top: Vec<_> = self
+            .bucket(channel, today, now)
+            .into_iter()
+            .flatten()
+            .map(|(login, counts)| (login.as_str(), counts.messages))
+            .collect();
+        top.sort_by(|(a, a_messages), (b, b_messages)| b_messages.cmp(a_messages).then(a.cmp(b)));
+        top.truncate(TOP);
+        top
+    }
+}
+
+/// `!stats [@user]` tells how many messages the user calling it or the given user wrote
+/// and their rank in the channel.
+pub struct Stats(pub SharedChatStats);
+
+impl Command for Stats {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[@user]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let user = match args.next() {
+            Some(user) => user.trim_start_matches('@'),
+            None => ctx.message.user.display_name(),
+        };
+        let stats = self.0.borrow();
+        ctx.send(match stats.stats(&ctx.message.channel, user, SystemTime::now()) {
+            Some((counts, rank, today)) => format!(
+                "{} wrote {} messages, {} of them today, and is #{} in this chat. {} were commands and {} only emotes.",
+                user, counts.messages, today.messages, rank, counts.commands, counts.emote_only
+            ),
+            None => format!("{} hasn't written here yet.", user),
+        })
+    }
+}
+
+/// `!topchatters [today|all]` lists who wrote the most, all-time by default.
+pub struct TopChatters(pub SharedChatStats);
+
+impl Command for TopChatters {
+    fn name(&self) -> &'static str {
+        "topchatters"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[today|all]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let today = match args.next().map(str::to_lowercase).as_deref() {
+            None | Some("all") => false,
+            Some("today") => true,
+            Some(_) => return ctx.send(format!("Usage: {}topchatters [today|all]", ctx.prefix)),
+        };
+        let stats = self.0.borrow();
+        let top = stats.top(&ctx.message.channel, today, SystemTime::now());
+        if top.is_empty() {
+            return ctx.send("Nobody has written yet.".to_owned());
+        }
+        let mut text = match today {
+            true => "Top chatters today:".to_owned(),
+            false => "Top chatters:".to_owned(),
+        };
+        for (index, (login, messages)) in top.into_iter().enumerate() {
+            let entry = format!(
+                "{} {} ({})",
+                if index == 0 { "" } else { "," },
+                login,
+                messages
+            );
+            if text.chars().count() + entry.chars().count() > MAX_MESSAGE_CHARS {
+                break;
+            }
+            text.push_str(&entry);
+        }
+        ctx.send(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{testing::message, EmoteSpan};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn counts_are_written_in_batches() {
+        let storage = Storage::memory();
+        let start = SystemTime::now();
+        let saved = || ChatStats::load(storage.clone()).unwrap();
+        let mut stats = saved();
+        stats.count(&message("Viewer", "hello", false), false, start);
+        assert_eq!(saved().top("carkhy", false, start), [("viewer", 1)]);
+        // nothing is written until the interval is over
+        let soon = start + Duration::from_secs(4);
+        stats.count(&message("viewer", "!uptime", false), true, soon);
+        stats.count(&message("Other", "hi", false), false, soon);
+        assert_eq!(saved().top("carkhy", false, start), [("viewer", 1)]);
+
+        let later = start + FLUSH_INTERVAL;
+        stats.count(&message("Other", "still here", false), false, later);
+        let viewer = Counts {
+            messages: 2,
+            commands: 1,
+            emote_only: 0,
+        };
+        assert_eq!(
+            saved().stats("carkhy", "Viewer", later),
+            Some((viewer, 1, viewer))
+        );
+        assert_eq!(
+            saved().top("carkhy", false, later),
+            [("other", 2), ("viewer", 2)]
+        );
+
+        // what is left is written when the bot stops
+        stats.count(&message("Viewer", "bye", false), false, later);
+        assert_eq!(saved().top("carkhy", false, later)[1], ("viewer", 2));
+        stats.flush();
+        assert_eq!(saved().top("carkhy", false, later)[0], ("viewer", 3));
+    }
+
+    #[test]
+    fn today_starts_at_midnight() {
+        let midnight = UNIX_EPOCH + Duration::from_secs(1_760_400_000);
+        let before = midnight - Duration::from_secs(1);
+        let mut stats = ChatStats::default();
+        let mut emotes = message("Viewer", "Kappa  Kappa ", false);
+        emotes.emotes = [(0, 5), (7, 12)]
+            .into_iter()
+            .map(|(start, end)| EmoteSpan {
+                id: "25".to_owned(),
+                start,
+                end,
+            })
+            .collect();
+        stats.count(&emotes, false, before);
+        stats.count(&message("Viewer", "Kappa hi", false), false, before);
+        stats.count(&message("Other", "hello", false), false, before);
+        assert_eq!(
+            stats.top("carkhy", true, before),
+            [("viewer", 2), ("other", 1)]
+        );
+
+        // nobody wrote yet today
+        let after = midnight + Duration::from_secs(1);
+        assert!(stats.top("carkhy", true, after).is_empty());
+        stats.count(&message("Other", "good morning", false), false, after);
+        assert_eq!(stats.top("carkhy", true, after), [("other", 1)]);
+        assert_eq!(
+            stats.top("carkhy", false, after),
+            [("other", 2), ("viewer", 2)]
+        );
+        let (all, rank, today) = stats.stats("carkhy", "viewer", after).unwrap();
+        assert_eq!(
+            (all.messages, all.emote_only, rank, today.messages),
+            (2, 1, 1, 0)
+        );
+    }
+}
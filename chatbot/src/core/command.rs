@@ -1,13 +1,49 @@
 use std::time::Duration;
 
-use crate::connect::ChatBotEvent;
+use super::{obs::ObsTask, tasks::HelixTask};
+use crate::connect::{ChatBotEvent, Overflow};
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum ChatBotCommand {
-    SendMessage(String),
+    // messages starting with "/me " are sent as action, channel without the leading '#'
+    SendMessage {
+        channel: String,
+        text: String,
+        // messages over twitch's length limit are split unless truncating reads better
+        overflow: Overflow,
+    },
+    // answer to the message with the given id, shown as a thread in chat
+    SendReply {
+        channel: String,
+        parent_msg_id: String,
+        text: String,
+    },
     LogTextMessage(String),
     // bot registers to be called back with the specified event
-    TimedCallback{duration: Duration, event: ChatBotEvent},
+    TimedCallback {
+        duration: Duration,
+        event: ChatBotEvent,
+    },
+    // channel names without the leading '#', joined again after a reconnect until parted
+    JoinChannel(String),
+    PartChannel(String),
+    // asks twitch's API, the answer is handled when it arrives
+    Helix(HelixTask),
+    // switches scenes, mutes inputs or shows sources in OBS, the answer goes to chat
+    Obs(ObsTask),
+    // read out by the local text to speech program, the voice is a hint for it
+    Speak {
+        speaker: String,
+        text: String,
+        voice: Option<String>,
+    },
+    // POSTs the JSON body to the url, then runs the command unless the webhook failed
+    Webhook {
+        url: String,
+        body: String,
+        then: Option<Box<ChatBotCommand>>,
+    },
     // bot sends more than one command
     MultipleCommands(Vec<ChatBotCommand>),
 }
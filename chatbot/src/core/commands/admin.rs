@@ -0,0 +1,277 @@
+//! Commands whispered by the logins of `commands.admins`, at the level `Admin` no badge
+//! grants. The answers go back as whispers, nothing shows up in chat.
+use super::{Args, Command, Context, SharedCommandStats};
+use crate::{
+    connect::{ChatBotEvent, UserLevel},
+    core::{tasks::whisper, ChatBotCommand, Date},
+    csv_export::{Exporter, Range},
+};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+// whispers of everyone else are logged no more often
+const IGNORED_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who may whisper commands, logins compared ignoring case.
+#[derive(Debug, Default)]
+pub struct Admins {
+    logins: HashSet<String>,
+    // when an ignored whisper was logged last, and how many were ignored since
+    logged: Option<Instant>,
+    ignored: usize,
+}
+
+impl Admins {
+    pub fn new(logins: &[String]) -> Self {
+        Self {
+            logins: logins.iter().map(|login| login.to_lowercase()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn contains(&self, login: &str) -> bool {
+        self.logins.contains(&login.to_lowercase())
+    }
+
+    /// A log line at most once a minute, with the whispers ignored meanwhile.
+    pub fn ignore(&mut self, login: &str, now: Instant) -> Option<ChatBotCommand> {
+        self.ignored += 1;
+        if self
+            .logged
+            .is_some_and(|logged| now < logged + IGNORED_LOG_INTERVAL)
+        {
+            return None;
+        }
+        self.logged = Some(now);
+        Some(ChatBotCommand::LogTextMessage(format!(
+            "Ignoring the whisper of {}, only admins may whisper commands ({} ignored in the last minute)",
+            login,
+            std::mem::take(&mut self.ignored)
+        )))
+    }
+}
+
+/// The chat messages of the answer whispered to the admin instead.
+pub fn as_whispers(login: &str, command: ChatBotCommand) -> ChatBotCommand {
+    match command {
+        ChatBotCommand::SendMessage { text, .. } | ChatBotCommand::SendReply { text, .. } => {
+            // no channel to fall back to
+            whisper("", login, text, None)
+        }
+        ChatBotCommand::MultipleCommands(commands) => ChatBotCommand::MultipleCommands(
+            commands
+                .into_iter()
+                .map(|command| as_whispers(login, command))
+                .collect(),
+        ),
+        command => command,
+    }
+}
+
+/// `!shutdown`, the bot saves what it keeps and stops.
+pub struct Shutdown;
+
+impl Command for Shutdown {
+    fn name(&self) -> &'static str {
+        "shutdown"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Admin
+    }
+
+    fn targets_channel(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        Some(ChatBotCommand::MultipleCommands(vec![
+            ctx.send("Shutting down.".to_owned())?,
+            // like a signal would
+            ChatBotCommand::TimedCallback {
+                duration: Duration::ZERO,
+                event: ChatBotEvent::Shutdown,
+            },
+        ]))
+    }
+}
+
+/// `!export [#channel] [since] [until]` writes the CSV files of the chat logs and the command
+/// stats, of every channel without one. The bot waits for the files.
+pub struct Export {
+    pub exporter: Exporter,
+    pub stats: SharedCommandStats,
+}
+
+impl Command for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[#channel] [since] [until]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Admin
+    }
+
+    fn targets_channel(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let mut channel = None;
+        let mut dates = Vec::new();
+        for arg in args.by_ref() {
+            match (arg.strip_prefix('#'), Date::parse(arg)) {
+                (Some(name), _) if channel.is_none() && dates.is_empty() => channel = Some(name),
+                (None, Some(date)) if dates.len() < 2 => dates.push(date),
+                _ => {
+                    return ctx.send(format!(
+                        "Usage: {}export [#channel] [since] [until], the days like 2026-10-14",
+                        ctx.prefix
+                    ))
+                }
+            }
+        }
+        let range = Range {
+            since: dates.first().copied(),
+            until: dates.get(1).copied(),
+        };
+        let exported = self.exporter.export(channel, &range, &self.stats.borrow());
+        ctx.send(match exported {
+            Ok(exported) => format!("{}.", exported),
+            Err(error) => format!("The export failed: {}", error),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::CommandsConfig,
+        connect::{Badge, TextMessage, UserInfo, Whisper},
+        core::{
+            commands::{
+                CommandRegistry, CustomCommands, Dispatch, Quotes, SharedIgnoreList,
+                SharedModeration,
+            },
+            timers::SharedTimers,
+            HelixTask,
+        },
+        storage::Storage,
+    };
+
+    fn registry() -> CommandRegistry {
+        let config = CommandsConfig {
+            admins: vec!["CaptainCallback".to_owned()],
+            ..Default::default()
+        };
+        CommandRegistry::new(
+            &config,
+            CustomCommands::default(),
+            Quotes::default(),
+            SharedTimers::default(),
+            SharedIgnoreList::default(),
+            SharedModeration::default(),
+            Storage::default(),
+        )
+    }
+
+    fn whispered(login: &str, text: &str) -> Whisper {
+        Whisper {
+            text: text.to_owned(),
+            user: UserInfo {
+                name: login.to_owned(),
+                ..Default::default()
+            },
+            recipient: "carkhybot".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    // the texts whispered back, and whether the bot is asked to stop
+    fn answers(command: Option<ChatBotCommand>) -> (Vec<String>, bool) {
+        let mut texts = Vec::new();
+        let mut shutdown = false;
+        let mut commands = command.into_iter().collect::<Vec<_>>();
+        while let Some(command) = commands.pop() {
+            match command {
+                ChatBotCommand::MultipleCommands(more) => commands.extend(more),
+                ChatBotCommand::Helix(HelixTask::Whisper { login, text, .. }) => {
+                    assert_eq!(login, "captaincallback");
+                    texts.insert(0, text);
+                }
+                ChatBotCommand::TimedCallback {
+                    event: ChatBotEvent::Shutdown,
+                    ..
+                } => shutdown = true,
+                command => panic!("not whispered: {:?}", command),
+            }
+        }
+        (texts, shutdown)
+    }
+
+    #[test]
+    fn only_admins_are_heard() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let (texts, shutdown) =
+            answers(registry.whisper(&whispered("captaincallback", "!shutdown"), now));
+        assert_eq!(texts, ["Shutting down."]);
+        assert!(shutdown);
+        assert!(matches!(
+            registry.whisper(&whispered("carkhy", "!shutdown"), now),
+            Some(ChatBotCommand::LogTextMessage(_))
+        ));
+        // logged once a minute
+        assert!(registry
+            .whisper(
+                &whispered("carkhy", "!shutdown"),
+                now + Duration::from_secs(5)
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn channel_commands_need_the_channel() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let (texts, _) =
+            answers(registry.whisper(&whispered("captaincallback", "!timers off"), now));
+        assert_eq!(
+            texts,
+            ["!timers needs the channel first in a whisper, like !timers #channel off"]
+        );
+        let (texts, _) =
+            answers(registry.whisper(&whispered("captaincallback", "!timers #Carkhy off"), now));
+        assert_eq!(texts, ["There are no timers in this channel."]);
+        let (texts, _) =
+            answers(registry.whisper(&whispered("captaincallback", "!ignore add @nightbot"), now));
+        assert_eq!(texts, ["Ignoring nightbot from now on."]);
+    }
+
+    #[test]
+    fn chat_never_reaches_admin_commands() {
+        let mut registry = registry();
+        let broadcaster = TextMessage {
+            channel: "captaincallback".to_owned(),
+            text: "!shutdown".to_owned(),
+            user: UserInfo {
+                name: "captaincallback".to_owned(),
+                badges: vec![Badge::Broadcaster],
+                ..Default::default()
+            },
+            level: UserLevel::from_badges(&[Badge::Broadcaster]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            registry.dispatch(&broadcaster, Instant::now()),
+            Dispatch::Handled(None)
+        ));
+    }
+}
@@ -0,0 +1,83 @@
+/// The words after a command's name, split at whitespace. The last argument may be
+/// the rest of the line instead, e.g. the text of a new command with its spacing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            rest: text.trim_start(),
+        }
+    }
+
+    /// Everything not taken yet, None if nothing is left.
+    pub fn rest(self) -> Option<&'a str> {
+        let rest = self.rest.trim_end();
+        (!rest.is_empty()).then_some(rest)
+    }
+
+    /// The next argument, one in double quotes as a whole without them, e.g. the question
+    /// of a poll. An unclosed quote takes the rest.
+    pub fn quoted(&mut self) -> Option<&'a str> {
+        let Some(inner) = self.rest.strip_prefix('"') else {
+            return self.next();
+        };
+        let (quoted, rest) = inner.split_once('"').unwrap_or((inner.trim_end(), ""));
+        self.rest = rest.trim_start();
+        Some(quoted)
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let end = self
+            .rest
+            .find(char::is_whitespace)
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest.trim_start();
+        Some(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_split_at_any_whitespace() {
+        let args = Args::new("  hello \t there  you ");
+        assert_eq!(args.collect::<Vec<_>>(), vec!["hello", "there", "you"]);
+    }
+
+    #[test]
+    fn rest_keeps_the_inner_spacing() {
+        let mut args = Args::new("hello  Hello   there! ");
+        assert_eq!(args.next(), Some("hello"));
+        assert_eq!(args.rest(), Some("Hello   there!"));
+        let mut args = Args::new("hello ");
+        args.next();
+        assert_eq!(args.rest(), None);
+    }
+
+    #[test]
+    fn quotes_keep_words_together() {
+        let mut args = Args::new(r#"start "Which boss next?" Ornstein "Nameless King"  "" Gael"#);
+        assert_eq!(args.next(), Some("start"));
+        let quoted: Vec<_> = std::iter::from_fn(|| args.quoted()).collect();
+        assert_eq!(
+            quoted,
+            ["Which boss next?", "Ornstein", "Nameless King", "", "Gael"]
+        );
+        let mut args = Args::new(r#""unclosed quote  "#);
+        assert_eq!(args.quoted(), Some("unclosed quote"));
+        assert_eq!(args.quoted(), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+// the answers counted are the ones of the last minute
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// How many answers a user's commands get per minute in each channel, across all commands.
+/// A limit of 0 doesn't limit them.
+#[derive(Debug, Default)]
+pub struct ResponseBudget {
+    limit: usize,
+    // by channel and login, oldest first and none older than the window
+    answered: HashMap<(String, String), VecDeque<Instant>>,
+}
+
+impl ResponseBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            answered: HashMap::new(),
+        }
+    }
+
+    pub fn is_spent(&self, channel: &str, login: &str, now: Instant) -> bool {
+        let key = (channel.to_owned(), login.to_owned());
+        self.limit > 0
+            && self.answered.get(&key).is_some_and(|times| {
+                times.iter().filter(|&&time| now < time + WINDOW).count() >= self.limit
+            })
+    }
+
+    /// Counts an answer, the users without one in the window are forgotten.
+    pub fn spend(&mut self, channel: &str, login: &str, now: Instant) {
+        if self.limit == 0 {
+            return;
+        }
+        self.answered.retain(|_, times| {
+            while times.front().is_some_and(|&time| now >= time + WINDOW) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        self.answered
+            .entry((channel.to_owned(), login.to_owned()))
+            .or_default()
+            .push_back(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_counted_per_minute() {
+        let mut budget = ResponseBudget::new(2);
+        let start = Instant::now();
+        budget.spend("captaincallback", "carkhy", start);
+        budget.spend("captaincallback", "carkhy", start + Duration::from_secs(30));
+        assert!(budget.is_spent("captaincallback", "carkhy", start + Duration::from_secs(59)));
+        assert!(!budget.is_spent("captaincallback", "viewer", start));
+        assert!(!budget.is_spent("carkhy", "carkhy", start));
+        assert!(!budget.is_spent("captaincallback", "carkhy", start + WINDOW));
+
+        budget.spend(
+            "captaincallback",
+            "viewer",
+            start + Duration::from_secs(100),
+        );
+        assert_eq!(budget.answered.len(), 1);
+        assert!(!ResponseBudget::new(0).is_spent("captaincallback", "carkhy", start));
+    }
+}
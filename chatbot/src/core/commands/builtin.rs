@@ -0,0 +1,402 @@
+use super::{Args, Command, Context};
+use crate::{
+    connect::UserLevel,
+    core::{
+        calendar::timestamp, moderation::SharedModeration, timers::SharedTimers, ChatBotCommand,
+        HelixTask,
+    },
+    storage::Storage,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime},
+};
+
+const INFO_MESSAGE: &str =
+    "Hello, my name is TwitchBotanist. I am a twitch chat bot written in Rust. My source code is on GitHub (https://github.com/CaptainCallback/TwitchBotanist). If you want to know what you can ask me, write '!help' into the chat!";
+const DISCORD_MESSAGE: &str =
+    "You can join me on discord for news and updates here: https://discord.gg/qM6DTTQxDV";
+
+// the link doesn't change, once in a while is enough
+const DISCORD_COOLDOWN: Duration = Duration::from_secs(30);
+// twitch makes clips of the same moment during a spam of `!clip`
+const CLIP_COOLDOWN: Duration = Duration::from_secs(30);
+// the lengths twitch allows for an ad, in seconds
+const COMMERCIAL_LENGTHS: [u32; 6] = [30, 60, 90, 120, 150, 180];
+// twitch refuses longer descriptions
+const MARKER_DESCRIPTION_LENGTH: usize = 140;
+// how long a `!permit` without seconds lasts
+const PERMIT_DURATION: Duration = Duration::from_secs(60);
+// for each user shouted out, other users can be shouted out meanwhile
+const SHOUTOUT_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+pub struct Info;
+
+impl Command for Info {
+    fn name(&self) -> &'static str {
+        "info"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        ctx.send(INFO_MESSAGE.to_owned())
+    }
+}
+
+pub struct Discord;
+
+impl Command for Discord {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["dc"]
+    }
+
+    fn cooldown(&self) -> Duration {
+        DISCORD_COOLDOWN
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        ctx.send(DISCORD_MESSAGE.to_owned())
+    }
+}
+
+pub struct Uptime;
+
+impl Command for Uptime {
+    fn name(&self) -> &'static str {
+        "uptime"
+    }
+
+    // answered by twitch, which helix caches for a while
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        Some(ChatBotCommand::Helix(HelixTask::Uptime {
+            channel: ctx.message.channel.clone(),
+        }))
+    }
+}
+
+pub struct Clip;
+
+impl Command for Clip {
+    fn name(&self) -> &'static str {
+        "clip"
+    }
+
+    fn cooldown(&self) -> Duration {
+        CLIP_COOLDOWN
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        Some(ChatBotCommand::Helix(HelixTask::Clip {
+            channel: ctx.message.channel.clone(),
+        }))
+    }
+}
+
+/// `!followage` for the user calling it, `!followage @user` for someone else.
+pub struct Followage;
+
+impl Command for Followage {
+    fn name(&self) -> &'static str {
+        "followage"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[@user]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let user = args.next().map(|user| user.trim_start_matches('@'));
+        let own = user.is_none_or(|user| user.eq_ignore_ascii_case(&ctx.message.user.name));
+        Some(ChatBotCommand::Helix(HelixTask::Followage {
+            channel: ctx.message.channel.clone(),
+            login: user.unwrap_or(&ctx.message.user.name).to_lowercase(),
+            own,
+        }))
+    }
+}
+
+/// `!so @user` points chat to the user's channel, at most once an hour per user.
+#[derive(Default)]
+pub struct Shoutout {
+    // by channel and the login shouted out
+    last: HashMap<(String, String), Instant>,
+}
+
+impl Command for Shoutout {
+    fn name(&self) -> &'static str {
+        "so"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user>"
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["shoutout", "host"]
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    // "!so @Carkhy" and "!so carkhy" both name the channel carkhy
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(user) = args.next().map(|user| user.trim_start_matches('@')) else {
+            return ctx.send(format!("Usage: {}so @user", ctx.prefix));
+        };
+        let login = user.to_lowercase();
+        let key = (ctx.message.channel.clone(), login.clone());
+        if let Some(&last) = self.last.get(&key) {
+            if ctx.now < last + SHOUTOUT_COOLDOWN {
+                return ctx.send(format!("{} was shouted out less than an hour ago.", user));
+            }
+        }
+        self.last.insert(key, ctx.now);
+        Some(ChatBotCommand::Helix(HelixTask::Shoutout {
+            channel: ctx.message.channel.clone(),
+            login,
+        }))
+    }
+}
+
+pub struct SetTitle;
+
+impl Command for SetTitle {
+    fn name(&self) -> &'static str {
+        "settitle"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<title>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(title) = args.rest() else {
+            return ctx.send(format!("Usage: {}settitle New title", ctx.prefix));
+        };
+        Some(ChatBotCommand::Helix(HelixTask::SetTitle {
+            channel: ctx.message.channel.clone(),
+            title: title.to_owned(),
+        }))
+    }
+}
+
+pub struct SetGame;
+
+impl Command for SetGame {
+    fn name(&self) -> &'static str {
+        "setgame"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<game>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(game) = args.rest() else {
+            return ctx.send(format!("Usage: {}setgame Game name", ctx.prefix));
+        };
+        Some(ChatBotCommand::Helix(HelixTask::SetGame {
+            channel: ctx.message.channel.clone(),
+            game: game.to_owned(),
+        }))
+    }
+}
+
+/// `!commercial 60` runs an ad. Without the length only if the config allows the default,
+/// an ad started by accident can't be stopped.
+pub struct Commercial {
+    pub default: bool,
+}
+
+impl Command for Commercial {
+    fn name(&self) -> &'static str {
+        "commercial"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<30|60|90|120|150|180>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let length = match args.next() {
+            Some(length) => length.parse().ok(),
+            None if self.default => Some(COMMERCIAL_LENGTHS[0]),
+            None => {
+                return ctx.send(format!(
+                    "Usage: {}commercial 30|60|90|120|150|180",
+                    ctx.prefix
+                ))
+            }
+        };
+        let Some(length) = length.filter(|length| COMMERCIAL_LENGTHS.contains(length)) else {
+            return ctx
+                .send("A commercial is 30, 60, 90, 120, 150 or 180 seconds long.".to_owned());
+        };
+        Some(ChatBotCommand::Helix(HelixTask::Commercial {
+            channel: ctx.message.channel.clone(),
+            length,
+        }))
+    }
+}
+
+/// `!shield on|off|status` turns twitch's shield mode on or off, or tells whether it's on.
+pub struct Shield;
+
+impl Command for Shield {
+    fn name(&self) -> &'static str {
+        "shield"
+    }
+
+    fn usage(&self) -> &'static str {
+        "on|off|status"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let active = match args.next().map(str::to_lowercase).as_deref() {
+            Some("on") => Some(true),
+            Some("off") => Some(false),
+            Some("status") => None,
+            _ => return ctx.send(format!("Usage: {}shield on|off|status", ctx.prefix)),
+        };
+        Some(ChatBotCommand::Helix(HelixTask::Shield {
+            channel: ctx.message.channel.clone(),
+            active,
+        }))
+    }
+}
+
+/// `!marker [description]` marks the moment in the VOD. Each marker is logged to
+/// markers.log in the storage first, in case twitch fails to create it.
+pub struct Marker(pub Storage);
+
+impl Command for Marker {
+    fn name(&self) -> &'static str {
+        "marker"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[description]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let description: String = args
+            .rest()
+            .unwrap_or_default()
+            .chars()
+            .take(MARKER_DESCRIPTION_LENGTH)
+            .collect();
+        let time = ctx.message.timestamp.unwrap_or_else(SystemTime::now);
+        let line = format!(
+            "{} #{} {}: {}",
+            timestamp(time),
+            ctx.message.channel,
+            ctx.message.user.name,
+            description
+        );
+        if let Err(error) = self.0.append("markers", &line) {
+            tracing::warn!(%error, "could not log the marker");
+        }
+        Some(ChatBotCommand::Helix(HelixTask::Marker {
+            channel: ctx.message.channel.clone(),
+            description,
+        }))
+    }
+}
+
+/// `!permit @user [seconds]` lets a user post one link despite the link filter.
+pub struct Permit(pub SharedModeration);
+
+impl Command for Permit {
+    fn name(&self) -> &'static str {
+        "permit"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user> [seconds]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let user = args.next().map(|user| user.trim_start_matches('@'));
+        let duration = match args.next().map(str::parse) {
+            None => Some(PERMIT_DURATION),
+            Some(Ok(seconds)) if seconds > 0 => Some(Duration::from_secs(seconds)),
+            Some(_) => None,
+        };
+        let (Some(user), Some(duration)) = (user, duration) else {
+            return ctx.send(format!("Usage: {}permit @user [seconds]", ctx.prefix));
+        };
+        self.0
+            .borrow_mut()
+            .links
+            .permit(&ctx.message.channel, user, ctx.now, duration);
+        ctx.send(format!(
+            "{} may post a link within the next {} seconds.",
+            user,
+            duration.as_secs()
+        ))
+    }
+}
+
+/// `!timers off` keeps the timers of the channel quiet until `!timers on`.
+pub struct TimersSwitch(pub SharedTimers);
+
+impl Command for TimersSwitch {
+    fn name(&self) -> &'static str {
+        "timers"
+    }
+
+    fn usage(&self) -> &'static str {
+        "off|on"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let paused = match args.next() {
+            Some("off") => true,
+            Some("on") => false,
+            _ => {
+                return ctx.send(format!(
+                    "Usage: {}timers off or {}timers on",
+                    ctx.prefix, ctx.prefix
+                ))
+            }
+        };
+        let found = self.0.borrow_mut().pause(&ctx.message.channel, paused);
+        ctx.send(match (found, paused) {
+            (false, _) => "There are no timers in this channel.".to_owned(),
+            (true, true) => "Timers are paused.".to_owned(),
+            (true, false) => "Timers are running again.".to_owned(),
+        })
+    }
+}
@@ -0,0 +1,246 @@
+use super::{Args, Command, Context};
+use crate::{
+    connect::UserLevel,
+    core::{ChatBotCommand, HelixTask},
+    helix::ChatSetting,
+};
+use std::ops::RangeInclusive;
+
+// what twitch allows, checked before asking
+const SLOW_SECONDS: RangeInclusive<u32> = 3..=120;
+const FOLLOWER_MINUTES: RangeInclusive<u32> = 0..=129_600;
+// twitch's own default for slow mode
+const SLOW_DEFAULT: u32 = 30;
+
+fn change(ctx: &Context, setting: ChatSetting) -> Option<ChatBotCommand> {
+    Some(ChatBotCommand::Helix(HelixTask::ChatMode {
+        channel: ctx.message.channel.clone(),
+        setting,
+    }))
+}
+
+// `on` or `off`, else the usage is answered
+fn switch(
+    ctx: &Context,
+    mut args: Args,
+    name: &str,
+    setting: fn(bool) -> ChatSetting,
+) -> Option<ChatBotCommand> {
+    match args.next().map(str::to_lowercase).as_deref() {
+        Some("on") => change(ctx, setting(true)),
+        Some("off") => change(ctx, setting(false)),
+        _ => ctx.send(format!("Usage: {}{} on|off", ctx.prefix, name)),
+    }
+}
+
+/// `!slow [seconds]` lets users write once every few seconds, 30 without them.
+pub struct Slow;
+
+impl Command for Slow {
+    fn name(&self) -> &'static str {
+        "slow"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[seconds]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let seconds = match args.next() {
+            Some(seconds) => seconds.parse().ok(),
+            None => Some(SLOW_DEFAULT),
+        };
+        match seconds.filter(|seconds| SLOW_SECONDS.contains(seconds)) {
+            Some(seconds) => change(ctx, ChatSetting::Slow(Some(seconds))),
+            None => ctx.send(format!(
+                "Usage: {}slow [seconds], from {} to {} seconds",
+                ctx.prefix,
+                SLOW_SECONDS.start(),
+                SLOW_SECONDS.end()
+            )),
+        }
+    }
+}
+
+/// `!slowoff` turns slow mode off.
+pub struct SlowOff;
+
+impl Command for SlowOff {
+    fn name(&self) -> &'static str {
+        "slowoff"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        change(ctx, ChatSetting::Slow(None))
+    }
+}
+
+/// `!emoteonly on|off`
+pub struct EmoteOnly;
+
+impl Command for EmoteOnly {
+    fn name(&self) -> &'static str {
+        "emoteonly"
+    }
+
+    fn usage(&self) -> &'static str {
+        "on|off"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        switch(ctx, args, self.name(), ChatSetting::EmoteOnly)
+    }
+}
+
+/// `!followers [minutes]` lets only users write who followed that long, any follower
+/// without the minutes. `!followers off` lets everyone write again.
+pub struct Followers;
+
+impl Command for Followers {
+    fn name(&self) -> &'static str {
+        "followers"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[minutes], off"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let minutes = match args.next() {
+            Some(off) if off.eq_ignore_ascii_case("off") => {
+                return change(ctx, ChatSetting::Followers(None))
+            }
+            Some(minutes) => minutes.parse().ok(),
+            None => Some(0),
+        };
+        match minutes.filter(|minutes| FOLLOWER_MINUTES.contains(minutes)) {
+            Some(minutes) => change(ctx, ChatSetting::Followers(Some(minutes))),
+            None => ctx.send(format!(
+                "Usage: {}followers [minutes] or {}followers off, up to {} minutes",
+                ctx.prefix,
+                ctx.prefix,
+                FOLLOWER_MINUTES.end()
+            )),
+        }
+    }
+}
+
+/// `!subscribers on|off`
+pub struct Subscribers;
+
+impl Command for Subscribers {
+    fn name(&self) -> &'static str {
+        "subscribers"
+    }
+
+    fn usage(&self) -> &'static str {
+        "on|off"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        switch(ctx, args, self.name(), ChatSetting::Subscribers)
+    }
+}
+
+/// `!uniquechat on|off` refuses messages that repeat a recent one.
+pub struct UniqueChat;
+
+impl Command for UniqueChat {
+    fn name(&self) -> &'static str {
+        "uniquechat"
+    }
+
+    fn usage(&self) -> &'static str {
+        "on|off"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        switch(ctx, args, self.name(), ChatSetting::UniqueChat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::TextMessage;
+    use std::time::Instant;
+
+    fn run(command: &mut dyn Command, args: &str) -> Result<ChatSetting, String> {
+        let message = TextMessage {
+            channel: "carkhy".to_owned(),
+            ..Default::default()
+        };
+        let ctx = Context {
+            message: &message,
+            prefix: "!",
+            now: Instant::now(),
+            replied: None,
+        };
+        match command.execute(&ctx, Args::new(args)) {
+            Some(ChatBotCommand::Helix(HelixTask::ChatMode { setting, .. })) => Ok(setting),
+            Some(ChatBotCommand::SendMessage { text, .. }) => Err(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn numbers_are_checked_before_asking() {
+        assert_eq!(run(&mut Slow, ""), Ok(ChatSetting::Slow(Some(30))));
+        assert_eq!(run(&mut Slow, "120"), Ok(ChatSetting::Slow(Some(120))));
+        let usage = Err("Usage: !slow [seconds], from 3 to 120 seconds".to_owned());
+        assert_eq!(run(&mut Slow, "2"), usage);
+        assert_eq!(run(&mut Slow, "slow"), usage);
+        assert_eq!(run(&mut SlowOff, ""), Ok(ChatSetting::Slow(None)));
+        assert_eq!(run(&mut Followers, ""), Ok(ChatSetting::Followers(Some(0))));
+        assert_eq!(
+            run(&mut Followers, "129600"),
+            Ok(ChatSetting::Followers(Some(129_600)))
+        );
+        assert_eq!(run(&mut Followers, "Off"), Ok(ChatSetting::Followers(None)));
+        assert_eq!(
+            run(&mut Followers, "129601"),
+            Err("Usage: !followers [minutes] or !followers off, up to 129600 minutes".to_owned())
+        );
+    }
+
+    #[test]
+    fn modes_are_switched_on_or_off() {
+        assert_eq!(run(&mut EmoteOnly, "on"), Ok(ChatSetting::EmoteOnly(true)));
+        assert_eq!(
+            run(&mut Subscribers, "OFF"),
+            Ok(ChatSetting::Subscribers(false))
+        );
+        assert_eq!(
+            run(&mut UniqueChat, "on"),
+            Ok(ChatSetting::UniqueChat(true))
+        );
+        assert_eq!(
+            run(&mut UniqueChat, ""),
+            Err("Usage: !uniquechat on|off".to_owned())
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use super::{custom::SharedCustomCommands, Args, Command, Context};
+use crate::{connect::UserLevel, core::ChatBotCommand};
+use serde::{Deserialize, Serialize};
+
+/// The number a custom command like `!deaths` counts, shown with `$(value)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Counter {
+    pub value: u64,
+    // everyone may count up and down, not only moderators
+    #[serde(default)]
+    pub open: bool,
+}
+
+/// What a call does to the counter besides showing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Increment,
+    Decrement,
+    Set(u64),
+    Open(bool),
+}
+
+impl Change {
+    /// "deaths+" increments deaths, "deaths-" decrements it.
+    pub fn from_name(name: &str) -> Option<(&str, Change)> {
+        match name.strip_suffix('+') {
+            Some(counter) => Some((counter, Change::Increment)),
+            None => Some((name.strip_suffix('-')?, Change::Decrement)),
+        }
+    }
+
+    /// "set 12", "open" or "close" after the counter's name.
+    pub fn from_args(mut args: Args) -> Option<Change> {
+        match args.next()? {
+            "set" => args.next()?.parse().ok().map(Change::Set),
+            "open" => Some(Change::Open(true)),
+            "close" => Some(Change::Open(false)),
+            _ => None,
+        }
+    }
+
+    /// Needed to make the change.
+    pub fn level(self, counter: &Counter) -> UserLevel {
+        match self {
+            Change::Increment | Change::Decrement if counter.open => UserLevel::Everyone,
+            _ => UserLevel::Moderator,
+        }
+    }
+}
+
+impl Counter {
+    // never below zero
+    pub fn apply(&mut self, change: Change) {
+        match change {
+            Change::Increment => self.value = self.value.saturating_add(1),
+            Change::Decrement => self.value = self.value.saturating_sub(1),
+            Change::Set(value) => self.value = value,
+            Change::Open(open) => self.open = open,
+        }
+    }
+}
+
+// "has died $(value) times" may be quoted, it doesn't have to
+fn unquoted(text: &str) -> &str {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+pub struct AddCounter(pub SharedCustomCommands);
+
+impl Command for AddCounter {
+    fn name(&self) -> &'static str {
+        "addcounter"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<name> \"<Text to return>\""
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    // "!addcounter deaths has died $(value) times", the prefix before the name is optional
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let name = args.next().map(|name| {
+            let name = name.strip_prefix(ctx.prefix).unwrap_or(name);
+            name.to_lowercase()
+        });
+        let Some(name) = name.filter(|name| Change::from_name(name).is_none() && !name.is_empty())
+        else {
+            return ctx.send(format!(
+                "Usage: {}addcounter name \"text with $(value)\"",
+                ctx.prefix
+            ));
+        };
+        let response = args.rest().map_or("$(value)", unquoted);
+        let result = self
+            .0
+            .borrow_mut()
+            .add_counter(&ctx.message.channel, &name, response);
+        super::custom::answer(ctx, &name, result, "Added")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_stop_at_zero() {
+        let mut counter = Counter::default();
+        counter.apply(Change::Decrement);
+        assert_eq!(counter.value, 0);
+        counter.apply(Change::Increment);
+        counter.apply(Change::Increment);
+        counter.apply(Change::Decrement);
+        assert_eq!(counter.value, 1);
+        counter.apply(Change::Set(u64::MAX));
+        counter.apply(Change::Increment);
+        assert_eq!(counter.value, u64::MAX);
+    }
+
+    #[test]
+    fn changes_are_named_or_given() {
+        assert_eq!(
+            Change::from_name("deaths+"),
+            Some(("deaths", Change::Increment))
+        );
+        assert_eq!(
+            Change::from_name("deaths-"),
+            Some(("deaths", Change::Decrement))
+        );
+        assert_eq!(Change::from_name("deaths"), None);
+        assert_eq!(
+            Change::from_args(Args::new("set 12")),
+            Some(Change::Set(12))
+        );
+        assert_eq!(Change::from_args(Args::new("set -1")), None);
+        assert_eq!(Change::from_args(Args::new("@carkhy")), None);
+
+        let open = Counter {
+            value: 0,
+            open: true,
+        };
+        assert_eq!(Change::Increment.level(&open), UserLevel::Everyone);
+        assert_eq!(Change::Set(1).level(&open), UserLevel::Moderator);
+        assert_eq!(
+            Change::Decrement.level(&Counter::default()),
+            UserLevel::Moderator
+        );
+    }
+}
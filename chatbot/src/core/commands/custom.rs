@@ -0,0 +1,866 @@
+use super::{
+    counter::{Change, Counter},
+    level,
+    script::{Host, Script, ScriptError, Value},
+    template::{Template, TemplateError, Values},
+    Args, Command, Context,
+};
+use crate::{
+    connect::UserLevel,
+    core::ChatBotCommand,
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+use thiserror::Error;
+
+// saved as custom_commands.json in the storage directory
+const STORAGE_NAME: &str = "custom_commands";
+// what the scripts keep, by channel and command
+const VALUES_NAME: &str = "script_values";
+
+/// A command answering with a text, added from chat.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CustomCommand {
+    // a template, checked before it is saved
+    pub response: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    // how often it was called, for $(count)
+    #[serde(default)]
+    pub count: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter: Option<Counter>,
+    // run instead of sending the response, checked before it is saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+}
+
+/// Why a custom command could not be changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    Builtin,
+    Exists,
+    Missing,
+    // the alias calls this custom command already
+    Taken(String),
+    Template(TemplateError),
+    Script(ScriptError),
+}
+
+/// Two built-in commands claim the same name, found when registering the second.
+#[derive(Debug, Error)]
+#[error("The command name {name:?} is taken by {taken_by:?} already")]
+pub struct NameTaken {
+    pub name: &'static str,
+    pub taken_by: &'static str,
+}
+
+// a command of the bot itself, listed by !commands
+#[derive(Debug)]
+struct Builtin {
+    aliases: Vec<&'static str>,
+    level: UserLevel,
+    // still parsed by the connector, so always called with '!'
+    legacy: bool,
+}
+
+/// The custom commands of every channel, saved again after each change.
+/// Built-in commands are known as well, so that custom ones can't shadow them.
+#[derive(Debug, Default)]
+pub struct CustomCommands {
+    storage: Storage,
+    // channel name without '#' to the commands by name without the prefix
+    commands: HashMap<String, BTreeMap<String, CustomCommand>>,
+    // what each script set, by channel and command
+    values: HashMap<String, BTreeMap<String, BTreeMap<String, Value>>>,
+    builtin: BTreeMap<&'static str, Builtin>,
+}
+
+pub type SharedCustomCommands = Rc<RefCell<CustomCommands>>;
+
+impl CustomCommands {
+    /// With the commands saved before.
+    pub fn load(storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            commands: storage.load(STORAGE_NAME)?,
+            values: storage.load(VALUES_NAME)?,
+            storage,
+            builtin: BTreeMap::new(),
+        })
+    }
+
+    pub(super) fn reserve(
+        &mut self,
+        name: &'static str,
+        aliases: &[&'static str],
+        level: UserLevel,
+        legacy: bool,
+    ) -> Result<(), NameTaken> {
+        for &claimed in aliases.iter().chain([&name]) {
+            if let Some(taken_by) = self.builtin_name(claimed) {
+                return Err(NameTaken {
+                    name: claimed,
+                    taken_by,
+                });
+            }
+        }
+        let builtin = Builtin {
+            aliases: aliases.to_vec(),
+            level,
+            legacy,
+        };
+        self.builtin.insert(name, builtin);
+        Ok(())
+    }
+
+    // the built-in command called by the name or one of its aliases
+    fn builtin_name(&self, name: &str) -> Option<&'static str> {
+        self.builtin
+            .iter()
+            .find(|(builtin, command)| {
+                builtin.eq_ignore_ascii_case(name)
+                    || command
+                        .aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(name))
+            })
+            .map(|(builtin, _)| *builtin)
+    }
+
+    pub fn is_builtin(&self, name: &str) -> bool {
+        self.builtin_name(name).is_some()
+    }
+
+    // the name of the custom command called by the name or one of its aliases
+    fn resolve(&self, channel: &str, name: &str) -> Option<&str> {
+        self.commands
+            .get(channel)?
+            .iter()
+            .find(|(command, custom)| *command == name || custom.aliases.iter().any(|a| a == name))
+            .map(|(command, _)| command.as_str())
+    }
+
+    /// Also by one of its aliases.
+    pub fn get(&self, channel: &str, name: &str) -> Option<&CustomCommand> {
+        let name = self.resolve(channel, name)?;
+        self.commands.get(channel)?.get(name)
+    }
+
+    /// A new command, an existing one is not replaced.
+    pub fn add(&mut self, channel: &str, name: &str, response: &str) -> Result<(), Refusal> {
+        if self.get(channel, name).is_some() {
+            return Err(Refusal::Exists);
+        }
+        self.set(channel, name, response)
+    }
+
+    /// Another response for an existing command, which may be called by an alias.
+    pub fn edit(&mut self, channel: &str, name: &str, response: &str) -> Result<(), Refusal> {
+        let name = self
+            .resolve(channel, name)
+            .ok_or(Refusal::Missing)?
+            .to_owned();
+        self.set(channel, &name, response)
+    }
+
+    /// A new command counting with `!name+` and `!name-`.
+    pub fn add_counter(
+        &mut self,
+        channel: &str,
+        name: &str,
+        response: &str,
+    ) -> Result<(), Refusal> {
+        self.add(channel, name, response)?;
+        if let Some(command) = self.commands.get_mut(channel).and_then(|c| c.get_mut(name)) {
+            command.counter = Some(Counter::default());
+        }
+        self.save();
+        Ok(())
+    }
+
+    /// Changes the counter of the command, its value after the change is returned.
+    pub fn change(&mut self, channel: &str, name: &str, change: Change) -> Result<u64, Refusal> {
+        let name = self
+            .resolve(channel, name)
+            .ok_or(Refusal::Missing)?
+            .to_owned();
+        let command = self
+            .commands
+            .get_mut(channel)
+            .and_then(|c| c.get_mut(&name));
+        let counter = command
+            .and_then(|command| command.counter.as_mut())
+            .ok_or(Refusal::Missing)?;
+        counter.apply(change);
+        let value = counter.value;
+        self.save();
+        Ok(value)
+    }
+
+    /// The commands of the channel by name.
+    pub fn commands(&self, channel: &str) -> impl Iterator<Item = (&str, &CustomCommand)> {
+        self.commands
+            .get(channel)
+            .into_iter()
+            .flatten()
+            .map(|(name, command)| (name.as_str(), command))
+    }
+
+    /// Adds the command or replaces its response, a script is replaced as well.
+    pub fn set(&mut self, channel: &str, name: &str, response: &str) -> Result<(), Refusal> {
+        Template::parse(response).map_err(Refusal::Template)?;
+        self.replace(channel, name, response, None)
+    }
+
+    /// Adds the command or replaces what it does with the script.
+    pub fn set_script(&mut self, channel: &str, name: &str, script: &str) -> Result<(), Refusal> {
+        Script::parse(script).map_err(Refusal::Script)?;
+        self.replace(channel, name, "", Some(script.to_owned()))
+    }
+
+    fn replace(
+        &mut self,
+        channel: &str,
+        name: &str,
+        response: &str,
+        script: Option<String>,
+    ) -> Result<(), Refusal> {
+        if self.is_builtin(name) {
+            return Err(Refusal::Builtin);
+        }
+        if let Some(command) = self
+            .resolve(channel, name)
+            .filter(|command| *command != name)
+        {
+            return Err(Refusal::Taken(command.to_owned()));
+        }
+        let command = self
+            .commands
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(name.to_owned())
+            .or_insert_with(|| CustomCommand {
+                response: String::new(),
+                aliases: Vec::new(),
+                count: 0,
+                counter: None,
+                script: None,
+            });
+        command.response = response.to_owned();
+        command.script = script;
+        self.save();
+        Ok(())
+    }
+
+    /// Another name for the command, no other command may be called by it yet.
+    pub fn alias(&mut self, channel: &str, name: &str, alias: &str) -> Result<(), Refusal> {
+        if self.is_builtin(alias) {
+            return Err(Refusal::Builtin);
+        }
+        if let Some(command) = self.resolve(channel, alias) {
+            return Err(Refusal::Taken(command.to_owned()));
+        }
+        let name = self
+            .resolve(channel, name)
+            .ok_or(Refusal::Missing)?
+            .to_owned();
+        let commands = self.commands.get_mut(channel).ok_or(Refusal::Missing)?;
+        let command = commands.get_mut(&name).ok_or(Refusal::Missing)?;
+        command.aliases.push(alias.to_owned());
+        self.save();
+        Ok(())
+    }
+
+    /// The response to the command called in the context, counted as another call.
+    /// Counters are changed first, unless the user is below the level returned then.
+    /// Every change is saved before the next message is handled, so none is lost.
+    /// A script may send a few messages or none.
+    pub fn call(
+        &mut self,
+        ctx: &Context,
+        name: &str,
+        args: Args,
+    ) -> Option<Result<Vec<String>, UserLevel>> {
+        let channel = &ctx.message.channel;
+        let (name, change) = match self.resolve(channel, name) {
+            Some(name) => (name.to_owned(), Change::from_args(args.clone())),
+            None => {
+                let (counter, change) = Change::from_name(name)?;
+                let counter = self.resolve(channel, counter)?.to_owned();
+                self.get(channel, &counter)?.counter.as_ref()?;
+                (counter, Some(change))
+            }
+        };
+        let command = self.commands.get_mut(channel)?.get_mut(&name)?;
+        // "!hug set 12" is just a call of !hug
+        if let (Some(counter), Some(change)) = (&mut command.counter, change) {
+            if level(ctx.message) < change.level(counter) {
+                return Some(Err(change.level(counter)));
+            }
+            counter.apply(change);
+            if let Change::Open(open) = change {
+                self.save();
+                let who = if open { "Everyone" } else { "Only moderators" };
+                return Some(Ok(vec![format!(
+                    "{} can count {}{} now.",
+                    who, ctx.prefix, name
+                )]));
+            }
+        }
+        command.count += 1;
+        if let Some(script) = command.script.clone() {
+            self.save();
+            return Some(Ok(self.run(ctx, &name, &script, args)));
+        }
+        let values = Values {
+            user: ctx.message.user.display_name(),
+            channel,
+            args,
+            count: command.count,
+            value: command.counter.as_ref().map(|counter| counter.value),
+            event: &[],
+        };
+        let response = match Template::parse(&command.response) {
+            Ok(template) => template.render(values),
+            // only when the file was changed by hand
+            Err(error) => {
+                tracing::warn!(command = %name, %error, "the response is sent as it is");
+                command.response.clone()
+            }
+        };
+        self.save();
+        Some(Ok(vec![response]))
+    }
+
+    // a moderator is told why the script stopped, for anyone else it is only logged
+    fn run(&mut self, ctx: &Context, name: &str, script: &str, args: Args) -> Vec<String> {
+        let channel = &ctx.message.channel;
+        let values = self
+            .values
+            .entry(channel.clone())
+            .or_default()
+            .entry(name.to_owned())
+            .or_default();
+        let mut host = Host {
+            user: ctx.message.user.display_name(),
+            login: &ctx.message.user.name,
+            channel,
+            moderator: level(ctx.message) >= UserLevel::Moderator,
+            args: args.collect(),
+            values,
+        };
+        let result = Script::parse(script).and_then(|script| script.run(&mut host));
+        self.save_values();
+        match result {
+            Ok(sent) => sent,
+            Err(error) if level(ctx.message) >= UserLevel::Moderator => vec![format!(
+                "@{}, {}{} stopped: {}.",
+                ctx.message.user.display_name(),
+                ctx.prefix,
+                name,
+                error
+            )],
+            Err(error) => {
+                tracing::info!(%channel, command = name, %error, "the script stopped");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Removes the command with its aliases, or only the alias if the name is one.
+    pub fn remove(&mut self, channel: &str, name: &str) -> Result<(), Refusal> {
+        let command = self
+            .resolve(channel, name)
+            .ok_or(Refusal::Missing)?
+            .to_owned();
+        if command == name {
+            if let Some(values) = self.values.get_mut(channel) {
+                values.remove(name);
+                self.save_values();
+            }
+        }
+        let commands = self.commands.get_mut(channel).ok_or(Refusal::Missing)?;
+        if command == name {
+            commands.remove(name);
+        } else if let Some(custom) = commands.get_mut(&command) {
+            custom.aliases.retain(|alias| alias != name);
+        }
+        if commands.is_empty() {
+            self.commands.remove(channel);
+        }
+        self.save();
+        Ok(())
+    }
+
+    // the change is kept until the bot stops, even if the file can't be written
+    fn save(&self) {
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.commands) {
+            tracing::warn!(%error, "could not save the custom commands");
+        }
+    }
+
+    fn save_values(&self) {
+        if let Err(error) = self.storage.save(VALUES_NAME, &self.values) {
+            tracing::warn!(%error, "could not save the values of the scripts");
+        }
+    }
+
+    /// The built-in commands the level may call and the custom commands of the channel,
+    /// each called with the prefix and followed by its aliases:
+    /// "Commands: !commands, !discord (!dc) | Custom: !hello (!hi)"
+    /// What `!help` lists: the built-in commands for the level, except the hidden ones, then
+    /// the custom ones of the channel.
+    pub(super) fn names(
+        &self,
+        channel: &str,
+        prefix: &str,
+        level: UserLevel,
+        hidden: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let builtin = self
+            .builtin
+            .iter()
+            .filter(|(name, command)| command.level <= level && !hidden(name))
+            .map(|(name, command)| match command.legacy {
+                true => format!("!{}", name),
+                false => format!("{}{}", prefix, name),
+            });
+        let custom = self
+            .commands(channel)
+            .map(|(name, _)| format!("{}{}", prefix, name));
+        builtin.chain(custom).collect()
+    }
+
+    pub fn list(&self, channel: &str, prefix: &str, level: UserLevel) -> String {
+        let builtin: Vec<_> = self
+            .builtin
+            .iter()
+            .filter(|(_, command)| command.level <= level)
+            .map(|(name, command)| {
+                let prefix = if command.legacy { "!" } else { prefix };
+                listed(prefix, name, &command.aliases)
+            })
+            .collect();
+        let mut list = format!("Commands: {}", builtin.join(", "));
+        if let Some(commands) = self.commands.get(channel) {
+            let custom: Vec<_> = commands
+                .iter()
+                .map(|(name, command)| listed(prefix, name, &command.aliases))
+                .collect();
+            list.push_str(&format!(" | Custom: {}", custom.join(", ")));
+        }
+        list
+    }
+}
+
+// "!so (!shoutout, !host)"
+fn listed(prefix: &str, name: &str, aliases: &[impl AsRef<str>]) -> String {
+    let mut listed = format!("{}{}", prefix, name);
+    if !aliases.is_empty() {
+        let aliases: Vec<_> = aliases
+            .iter()
+            .map(|alias| format!("{}{}", prefix, alias.as_ref()))
+            .collect();
+        listed.push_str(&format!(" ({})", aliases.join(", ")));
+    }
+    listed
+}
+
+// "!Discord" is the command "discord", the name must be called with the prefix
+fn custom_name(ctx: &Context, arg: &str) -> Option<String> {
+    let name = arg.strip_prefix(ctx.prefix)?.to_lowercase();
+    (!name.is_empty()).then_some(name)
+}
+
+pub(super) fn answer(
+    ctx: &Context,
+    name: &str,
+    result: Result<(), Refusal>,
+    done: &str,
+) -> Option<ChatBotCommand> {
+    let prefix = ctx.prefix;
+    ctx.send(match result {
+        Ok(()) => format!("{} {}{}.", done, prefix, name),
+        Err(Refusal::Builtin) => format!("{}{} is a built-in command.", prefix, name),
+        Err(Refusal::Exists) => format!(
+            "{}{} exists already, change it with {}editcmd.",
+            prefix, name, prefix
+        ),
+        Err(Refusal::Missing) => format!(
+            "There is no {}{}, add it with {}addcmd.",
+            prefix, name, prefix
+        ),
+        Err(Refusal::Taken(command)) => {
+            format!("{}{} calls {}{} already.", prefix, name, prefix, command)
+        }
+        Err(Refusal::Template(error)) => format!("Nothing changed, {}.", error),
+        Err(Refusal::Script(error)) => format!("Nothing changed, {}.", error),
+    })
+}
+
+// "!addcmd !discord Join us" changes !discord
+fn name_and_response<'a>(ctx: &Context, mut args: Args<'a>) -> Option<(String, &'a str)> {
+    let name = custom_name(ctx, args.next()?)?;
+    Some((name, args.rest()?))
+}
+
+fn usage(ctx: &Context, command: &str) -> Option<ChatBotCommand> {
+    ctx.send(format!(
+        "Usage: {}{} {}name response",
+        ctx.prefix, command, ctx.prefix
+    ))
+}
+
+pub struct AddCmd(pub SharedCustomCommands);
+
+impl Command for AddCmd {
+    fn name(&self) -> &'static str {
+        "addcmd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "!<name> <Text to return>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some((name, response)) = name_and_response(ctx, args) else {
+            return usage(ctx, self.name());
+        };
+        let result = self
+            .0
+            .borrow_mut()
+            .add(&ctx.message.channel, &name, response);
+        answer(ctx, &name, result, "Added")
+    }
+}
+
+pub struct EditCmd(pub SharedCustomCommands);
+
+impl Command for EditCmd {
+    fn name(&self) -> &'static str {
+        "editcmd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "!<name> <Text to return>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some((name, response)) = name_and_response(ctx, args) else {
+            return usage(ctx, self.name());
+        };
+        let result = self
+            .0
+            .borrow_mut()
+            .edit(&ctx.message.channel, &name, response);
+        answer(ctx, &name, result, "Changed")
+    }
+}
+
+/// `!editscript !name <script>` adds the command, or replaces what it does with the script.
+pub struct EditScript(pub SharedCustomCommands);
+
+impl Command for EditScript {
+    fn name(&self) -> &'static str {
+        "editscript"
+    }
+
+    fn usage(&self) -> &'static str {
+        "!<name> <script>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some((name, script)) = name_and_response(ctx, args) else {
+            return ctx.send(format!(
+                "Usage: {}editscript {}name script",
+                ctx.prefix, ctx.prefix
+            ));
+        };
+        let result = self
+            .0
+            .borrow_mut()
+            .set_script(&ctx.message.channel, &name, script);
+        answer(ctx, &name, result, "Scripted")
+    }
+}
+
+pub struct DelCmd(pub SharedCustomCommands);
+
+impl Command for DelCmd {
+    fn name(&self) -> &'static str {
+        "delcmd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "!<name>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(name) = args.next().and_then(|name| custom_name(ctx, name)) else {
+            return ctx.send(format!("Usage: {}delcmd {}name", ctx.prefix, ctx.prefix));
+        };
+        let result = self.0.borrow_mut().remove(&ctx.message.channel, &name);
+        answer(ctx, &name, result, "Removed")
+    }
+}
+
+pub struct AliasCmd(pub SharedCustomCommands);
+
+impl Command for AliasCmd {
+    fn name(&self) -> &'static str {
+        "aliascmd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "!<name> !<alias>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let mut name = || args.next().and_then(|name| custom_name(ctx, name));
+        let (Some(name), Some(alias)) = (name(), name()) else {
+            return ctx.send(format!(
+                "Usage: {}aliascmd {}name {}alias",
+                ctx.prefix, ctx.prefix, ctx.prefix
+            ));
+        };
+        let result = self
+            .0
+            .borrow_mut()
+            .alias(&ctx.message.channel, &name, &alias);
+        let prefix = ctx.prefix;
+        match result {
+            Ok(()) => ctx.send(format!("{}{} calls {}{} now.", prefix, alias, prefix, name)),
+            Err(Refusal::Missing) => answer(ctx, &name, result, ""),
+            Err(_) => answer(ctx, &alias, result, ""),
+        }
+    }
+}
+
+pub struct ListCommands(pub SharedCustomCommands);
+
+impl Command for ListCommands {
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let list = self
+            .0
+            .borrow()
+            .list(&ctx.message.channel, ctx.prefix, level(ctx.message));
+        ctx.send(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::TextMessage;
+    use std::time::Instant;
+
+    #[test]
+    fn commands_are_loaded_again() {
+        let storage = Storage::memory();
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        commands
+            .add("captaincallback", "hello", "Hi there!")
+            .unwrap();
+        commands
+            .add("captaincallback", "lurk", "Enjoy the lurk")
+            .unwrap();
+        commands.remove("captaincallback", "lurk").unwrap();
+        commands.add("carkhy", "hello", "Hey!").unwrap();
+        commands.alias("carkhy", "hello", "hi").unwrap();
+
+        let loaded = CustomCommands::load(storage.clone()).unwrap();
+        assert_eq!(loaded.commands, commands.commands);
+        assert_eq!(
+            loaded.get("carkhy", "hi").map(|c| c.response.as_str()),
+            Some("Hey!")
+        );
+        assert!(loaded.get("captaincallback", "lurk").is_none());
+    }
+
+    #[test]
+    fn builtin_names_are_refused() {
+        let mut commands = CustomCommands::default();
+        commands
+            .reserve("discord", &["dc"], UserLevel::Everyone, false)
+            .unwrap();
+        for name in ["discord", "dc"] {
+            assert_eq!(
+                commands.add("captaincallback", name, "Join us"),
+                Err(Refusal::Builtin)
+            );
+        }
+        assert_eq!(
+            commands.edit("captaincallback", "hello", "Hi"),
+            Err(Refusal::Missing)
+        );
+        commands.add("captaincallback", "hello", "Hi").unwrap();
+        assert_eq!(
+            commands.add("captaincallback", "hello", "Hello"),
+            Err(Refusal::Exists)
+        );
+    }
+
+    #[test]
+    fn list_shows_what_the_level_may_call() {
+        let mut commands = CustomCommands::default();
+        commands
+            .reserve("info", &[], UserLevel::Everyone, false)
+            .unwrap();
+        commands
+            .reserve("addcmd", &[], UserLevel::Moderator, false)
+            .unwrap();
+        commands
+            .reserve("help", &[], UserLevel::Everyone, true)
+            .unwrap();
+        assert_eq!(
+            commands.list("carkhy", "?", UserLevel::Everyone),
+            "Commands: !help, ?info"
+        );
+        commands.add("carkhy", "lurk", "Enjoy the lurk").unwrap();
+        assert_eq!(
+            commands.list("carkhy", "?", UserLevel::Moderator),
+            "Commands: ?addcmd, !help, ?info | Custom: ?lurk"
+        );
+    }
+
+    #[test]
+    fn calls_are_counted_across_restarts() {
+        let storage = Storage::memory();
+        let message = TextMessage {
+            channel: "captaincallback".to_owned(),
+            ..Default::default()
+        };
+        let ctx = Context {
+            message: &message,
+            prefix: "!",
+            now: Instant::now(),
+            replied: None,
+        };
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        commands
+            .add(
+                "captaincallback",
+                "hug",
+                "Hug number $(count) for $(touser)",
+            )
+            .unwrap();
+        commands.call(&ctx, "hug", Args::new("@carkhy"));
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        assert_eq!(
+            commands.call(&ctx, "hug", Args::new("")),
+            Some(Ok(vec!["Hug number 2 for ".to_owned()]))
+        );
+    }
+
+    #[test]
+    fn no_count_is_lost() {
+        let storage = Storage::memory();
+        let message = TextMessage {
+            channel: "captaincallback".to_owned(),
+            level: UserLevel::Moderator,
+            ..Default::default()
+        };
+        let ctx = Context {
+            message: &message,
+            prefix: "!",
+            now: Instant::now(),
+            replied: None,
+        };
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        commands
+            .add_counter("captaincallback", "deaths", "$(value)")
+            .unwrap();
+        for _ in 0..50 {
+            commands.call(&ctx, "deaths+", Args::new(""));
+        }
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        assert_eq!(
+            commands.call(&ctx, "deaths-", Args::new("")),
+            Some(Ok(vec!["49".to_owned()]))
+        );
+    }
+
+    #[test]
+    fn scripts_keep_values_of_their_own() {
+        let storage = Storage::memory();
+        let message = |channel: &str, level| TextMessage {
+            channel: channel.to_owned(),
+            level,
+            ..Default::default()
+        };
+        let call = |commands: &mut CustomCommands, message: &TextMessage, name: &str| {
+            let ctx = Context {
+                message,
+                prefix: "!",
+                now: Instant::now(),
+                replied: None,
+            };
+            commands.call(&ctx, name, Args::new("")).unwrap().unwrap()
+        };
+        let counting = "let n = get(\"n\", 0) + 1\nset(\"n\", n)\nsend(n)";
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        for (channel, name) in [
+            ("carkhy", "hug"),
+            ("carkhy", "pat"),
+            ("captaincallback", "hug"),
+        ] {
+            commands.set_script(channel, name, counting).unwrap();
+        }
+        let viewer = message("carkhy", UserLevel::Everyone);
+        assert_eq!(call(&mut commands, &viewer, "hug"), ["1"]);
+        assert_eq!(call(&mut commands, &viewer, "hug"), ["2"]);
+        // another command and another channel count on their own
+        assert_eq!(call(&mut commands, &viewer, "pat"), ["1"]);
+        let elsewhere = message("captaincallback", UserLevel::Everyone);
+        assert_eq!(call(&mut commands, &elsewhere, "hug"), ["1"]);
+
+        let mut commands = CustomCommands::load(storage.clone()).unwrap();
+        assert_eq!(call(&mut commands, &viewer, "hug"), ["3"]);
+        assert!(matches!(
+            commands.set_script("carkhy", "loop", "while true {"),
+            Err(Refusal::Script(ScriptError::End))
+        ));
+        commands
+            .set_script("carkhy", "loop", "while true { }")
+            .unwrap();
+        // only a moderator is told why a script stopped
+        assert!(call(&mut commands, &viewer, "loop").is_empty());
+        let moderator = message("carkhy", UserLevel::Moderator);
+        assert_eq!(
+            call(&mut commands, &moderator, "loop"),
+            ["@, !loop stopped: the script took more than 10000 steps."]
+        );
+        commands.remove("carkhy", "hug").unwrap();
+        commands.set_script("carkhy", "hug", counting).unwrap();
+        assert_eq!(call(&mut commands, &viewer, "hug"), ["1"]);
+    }
+}
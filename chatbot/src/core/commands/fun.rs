@@ -0,0 +1,301 @@
+use super::{template::render_event, Args, Command, Context};
+use crate::core::ChatBotCommand;
+use fastrand::Rng;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// long enough that one user can't fill the chat with answers
+const EIGHTBALL_COOLDOWN: Duration = Duration::from_secs(15);
+// the cooldowns over are forgotten when more users than this asked
+const ASKED_LIMIT: usize = 1000;
+// enough for any tabletop roll, small enough for one chat message
+const MAX_DICE: u32 = 20;
+const MAX_SIDES: u32 = 1000;
+const MAX_MODIFIER: i64 = 1000;
+
+/// `!8ball <question>` answers with one of the configured answers, each with the same chance.
+pub struct EightBall {
+    answers: Vec<String>,
+    rng: Rng,
+    // by channel and lowercase login, when the user last got an answer
+    asked: HashMap<(String, String), Instant>,
+}
+
+impl EightBall {
+    pub fn new(answers: &[String], rng: Rng) -> Self {
+        Self {
+            answers: answers.to_vec(),
+            rng,
+            asked: HashMap::new(),
+        }
+    }
+}
+
+impl Command for EightBall {
+    fn name(&self) -> &'static str {
+        "8ball"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<question>"
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let user = ctx.message.user.display_name();
+        if args.rest().is_none() {
+            return ctx.send(format!(
+                "{}, the magic 8-ball needs a question: {}8ball <question>",
+                user, ctx.prefix
+            ));
+        }
+        let channel = &ctx.message.channel;
+        let key = (channel.clone(), ctx.message.user.name.to_lowercase());
+        if self
+            .asked
+            .get(&key)
+            .is_some_and(|&asked| ctx.now < asked + EIGHTBALL_COOLDOWN)
+        {
+            return None;
+        }
+        if self.asked.len() >= ASKED_LIMIT {
+            let now = ctx.now;
+            self.asked
+                .retain(|_, &mut asked| now < asked + EIGHTBALL_COOLDOWN);
+        }
+        self.asked.insert(key, ctx.now);
+        // the config makes sure there is one
+        let answer = self
+            .answers
+            .get(self.rng.usize(..self.answers.len().max(1)))?;
+        ctx.send(render_event(answer, "8ball answer", user, channel, &[]))
+    }
+}
+
+/// A roll in dice notation, 3d6+2 is three six-sided dice and 2 more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Dice {
+    count: u32,
+    sides: u32,
+    modifier: i64,
+}
+
+/// Nothing is a d6 and a number alone a die with that many sides, otherwise NdM with an
+/// optional +K or -K. The count may be left out like in d20. None beyond the limits.
+fn dice(text: &str) -> Option<Dice> {
+    let text = text.trim().to_lowercase();
+    if text.is_empty() {
+        return Some(Dice {
+            count: 1,
+            sides: 6,
+            modifier: 0,
+        });
+    }
+    let digits = |text: &str| -> Option<u32> {
+        match !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+            true => text.parse().ok(),
+            false => None,
+        }
+    };
+    let (roll, modifier) = match text.find(['+', '-']) {
+        Some(at) => {
+            let modifier = i64::from(digits(&text[at + 1..])?);
+            match &text[at..at + 1] {
+                "-" => (&text[..at], -modifier),
+                _ => (&text[..at], modifier),
+            }
+        }
+        None => (text.as_str(), 0),
+    };
+    let (count, sides) = match roll.split_once('d') {
+        Some(("", sides)) => (1, digits(sides)?),
+        Some((count, sides)) => (digits(count)?, digits(sides)?),
+        None => (1, digits(roll)?),
+    };
+    let valid = (1..=MAX_DICE).contains(&count)
+        && (1..=MAX_SIDES).contains(&sides)
+        && modifier.abs() <= MAX_MODIFIER;
+    valid.then_some(Dice {
+        count,
+        sides,
+        modifier,
+    })
+}
+
+/// `!roll [NdM+K]` rolls the dice and tells each of them with the total.
+pub struct Roll(pub Rng);
+
+impl Command for Roll {
+    fn name(&self) -> &'static str {
+        "roll"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[dice]"
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(dice) = dice(args.rest().unwrap_or_default()) else {
+            return ctx.send(format!(
+                "Usage: {0}roll, {0}roll 20 or {0}roll 3d6+2, at most {1} dice of {2} sides",
+                ctx.prefix, MAX_DICE, MAX_SIDES
+            ));
+        };
+        let rolled: Vec<u32> = (0..dice.count)
+            .map(|_| self.0.u32(1..=dice.sides))
+            .collect();
+        let total = rolled.iter().map(|&die| i64::from(die)).sum::<i64>() + dice.modifier;
+        let shown: Vec<_> = rolled.iter().map(u32::to_string).collect();
+        let modifier = match dice.modifier {
+            0 => String::new(),
+            modifier if modifier > 0 => format!(" +{}", modifier),
+            modifier => format!(" {}", modifier),
+        };
+        let user = ctx.message.user.display_name();
+        ctx.send(match (rolled.len(), dice.modifier) {
+            (1, 0) => format!("{} rolled {}.", user, total),
+            _ => format!(
+                "{} rolled {}{} = {}",
+                user,
+                shown.join(", "),
+                modifier,
+                total
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::CommandsConfig,
+        connect::{TextMessage, UserInfo},
+    };
+
+    #[test]
+    fn every_answer_is_as_likely() {
+        let config = CommandsConfig::default();
+        assert_eq!(config.eightball_answers.len(), 20);
+        let mut eightball = EightBall::new(&config.eightball_answers, Rng::with_seed(8));
+        let message = TextMessage {
+            channel: "carkhy".to_owned(),
+            user: UserInfo {
+                name: "Viewer".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut ask = |question: &str, seconds| {
+            let ctx = Context {
+                message: &message,
+                prefix: "!",
+                now: start + Duration::from_secs(seconds),
+                replied: None,
+            };
+            match eightball.execute(&ctx, Args::new(question)) {
+                Some(ChatBotCommand::SendMessage { text, .. }) => Some(text),
+                None => None,
+                other => panic!("{:?}", other),
+            }
+        };
+        assert_eq!(
+            ask("   ", 0).as_deref(),
+            Some("Viewer, the magic 8-ball needs a question: !8ball <question>")
+        );
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for round in 0..20_000 {
+            let answer = ask("Will it work?", round * 15).unwrap();
+            *counts.entry(answer).or_default() += 1;
+            // within the cooldown nothing happens
+            assert_eq!(ask("Really?", round * 15 + 14), None);
+        }
+        assert_eq!(counts.len(), 20);
+        assert!(counts.contains_key("Viewer, signs point to yes."));
+        assert!(
+            counts.values().all(|&count| (850..1150).contains(&count)),
+            "{:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn dice_notation_has_limits() {
+        let roll = |count, sides, modifier| {
+            Some(Dice {
+                count,
+                sides,
+                modifier,
+            })
+        };
+        assert_eq!(dice(""), roll(1, 6, 0));
+        assert_eq!(dice("20"), roll(1, 20, 0));
+        assert_eq!(dice("d20"), roll(1, 20, 0));
+        assert_eq!(dice("3d6+2"), roll(3, 6, 2));
+        assert_eq!(dice(" 2D10-1 "), roll(2, 10, -1));
+        assert_eq!(dice("20d1000+1000"), roll(20, 1000, 1000));
+        for text in [
+            "0d6",
+            "3d0",
+            "3d6+",
+            "3d6-",
+            "21d6",
+            "1d1001",
+            "1d6+1001",
+            "99999999999d6",
+            "3d6+2+1",
+            "+3",
+            "-1d6",
+            "3d",
+            "d",
+            "3x6",
+            "3 d6",
+            "1.5d6",
+            "abc",
+        ] {
+            assert_eq!(dice(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn rolls_show_each_die_and_the_total() {
+        let message = TextMessage {
+            channel: "carkhy".to_owned(),
+            user: UserInfo {
+                name: "Viewer".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ctx = Context {
+            message: &message,
+            prefix: "!",
+            now: Instant::now(),
+            replied: None,
+        };
+        let mut command = Roll(Rng::with_seed(6));
+        let mut expected = Rng::with_seed(6);
+        let mut roll = |text: &str| match command.execute(&ctx, Args::new(text)) {
+            Some(ChatBotCommand::SendMessage { text, .. }) => text,
+            other => panic!("{:?}", other),
+        };
+        let dice: Vec<_> = (0..3).map(|_| expected.u32(1..=6)).collect();
+        assert_eq!(
+            roll("3d6+2"),
+            format!(
+                "Viewer rolled {}, {}, {} +2 = {}",
+                dice[0],
+                dice[1],
+                dice[2],
+                dice.iter().sum::<u32>() + 2
+            )
+        );
+        assert_eq!(roll(""), format!("Viewer rolled {}.", expected.u32(1..=6)));
+        assert_eq!(
+            roll("3d6+"),
+            "Usage: !roll, !roll 20 or !roll 3d6+2, at most 20 dice of 1000 sides"
+        );
+    }
+}
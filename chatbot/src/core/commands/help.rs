@@ -0,0 +1,89 @@
+use crate::connect::MAX_MESSAGE_CHARS;
+
+// "and 12 more, see !help 3"
+fn more(remaining: usize, prefix: &str, next: usize) -> String {
+    format!(" and {} more, see {}help {}", remaining, prefix, next)
+}
+
+/// The commands on the page, counted from 1, as a single chat message: as many as fit, and
+/// how to get the next page if there are more. None past the last page.
+pub fn page(names: &[String], page: usize, prefix: &str) -> Option<String> {
+    let mut start = 0;
+    for number in 1..=page {
+        let mut text = String::from("Commands: ");
+        let mut end = start;
+        while end < names.len() {
+            let separator = if end > start { ", " } else { "" };
+            let longer = format!("{}{}{}", text, separator, names[end]);
+            let rest = names.len() - end - 1;
+            let suffix = match rest {
+                0 => String::new(),
+                _ => more(rest, prefix, number + 1),
+            };
+            // a name too long for any page gets one of its own and is cut when sent
+            if end > start && longer.chars().count() + suffix.chars().count() > MAX_MESSAGE_CHARS {
+                break;
+            }
+            text = longer;
+            end += 1;
+        }
+        if number == page {
+            if end < names.len() {
+                text.push_str(&more(names.len() - end, prefix, number + 1));
+            }
+            return Some(text);
+        }
+        if end >= names.len() {
+            return None;
+        }
+        start = end;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("!command{:02}", index))
+            .collect()
+    }
+
+    #[test]
+    fn a_short_list_is_a_single_page() {
+        let names = names(3);
+        assert_eq!(
+            page(&names, 1, "!").unwrap(),
+            "Commands: !command00, !command01, !command02"
+        );
+        assert_eq!(page(&names, 2, "!"), None);
+    }
+
+    #[test]
+    fn pages_fit_into_a_message_and_point_to_the_next() {
+        let names = names(100);
+        let mut listed = Vec::new();
+        let mut number = 1;
+        while let Some(text) = page(&names, number, "?") {
+            assert!(text.chars().count() <= MAX_MESSAGE_CHARS, "{}", text);
+            let list = text.strip_prefix("Commands: ").unwrap();
+            let (list, more) = match list.split_once(" and ") {
+                Some((list, more)) => (list, Some(more)),
+                None => (list, None),
+            };
+            let on_page: Vec<_> = list.split(", ").map(str::to_owned).collect();
+            listed.extend(on_page);
+            if let Some(more) = more {
+                let expected = format!("{} more, see ?help {}", 100 - listed.len(), number + 1);
+                assert_eq!(more, expected);
+            }
+            number += 1;
+        }
+        assert_eq!(listed, names);
+        // 11 characters and a separator each, about 40 on a page
+        assert_eq!(number - 1, 3);
+        assert_eq!(page(&names, 0, "?"), None);
+    }
+}
@@ -0,0 +1,169 @@
+use super::{Args, Command, Context};
+use crate::{
+    connect::UserLevel,
+    core::ChatBotCommand,
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+
+const STORAGE_NAME: &str = "ignored_users";
+
+// what moderators changed with `!ignore`, the configured list stays as it is
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Changes {
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+}
+
+/// Users whose messages the bot doesn't react to, like other bots, and the bot itself.
+/// Logins are compared ignoring case.
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    storage: Storage,
+    // the bot's own, empty when unknown
+    login: String,
+    configured: BTreeSet<String>,
+    changes: Changes,
+}
+
+pub type SharedIgnoreList = Rc<RefCell<IgnoreList>>;
+
+impl IgnoreList {
+    /// The configured users with the changes saved before.
+    pub fn load(
+        login: &str,
+        configured: &[String],
+        storage: Storage,
+    ) -> Result<Self, StorageError> {
+        Ok(Self {
+            changes: storage.load(STORAGE_NAME)?,
+            storage,
+            login: login.to_lowercase(),
+            configured: configured.iter().map(|user| user.to_lowercase()).collect(),
+        })
+    }
+
+    pub fn contains(&self, login: &str) -> bool {
+        let login = login.to_lowercase();
+        if !self.login.is_empty() && login == self.login {
+            return true;
+        }
+        (self.configured.contains(&login) || self.changes.added.contains(&login))
+            && !self.changes.removed.contains(&login)
+    }
+
+    /// False if the user was ignored already.
+    pub fn add(&mut self, login: &str) -> bool {
+        if self.contains(login) {
+            return false;
+        }
+        let login = login.to_lowercase();
+        if !self.changes.removed.remove(&login) {
+            self.changes.added.insert(login);
+        }
+        self.save();
+        true
+    }
+
+    /// False if the user wasn't ignored, the bot itself can't be removed.
+    pub fn remove(&mut self, login: &str) -> bool {
+        let login = login.to_lowercase();
+        if !self.contains(&login) || login == self.login {
+            return false;
+        }
+        if !self.changes.added.remove(&login) {
+            self.changes.removed.insert(login);
+        }
+        self.save();
+        true
+    }
+
+    /// Sorted, without the bot itself.
+    pub fn users(&self) -> Vec<&str> {
+        self.configured
+            .union(&self.changes.added)
+            .filter(|user| !self.changes.removed.contains(*user))
+            .map(String::as_str)
+            .collect()
+    }
+
+    // the change is kept until the bot stops, even if the file can't be written
+    fn save(&self) {
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.changes) {
+            tracing::warn!(%error, "could not save the ignored users");
+        }
+    }
+}
+
+/// `!ignore add @user`, `!ignore remove @user` and `!ignore list`.
+pub struct IgnoreCommand(pub SharedIgnoreList);
+
+impl Command for IgnoreCommand {
+    fn name(&self) -> &'static str {
+        "ignore"
+    }
+
+    fn usage(&self) -> &'static str {
+        "add|remove @<user>, list"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    // the list is the same in every channel
+    fn targets_channel(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let action = args.next().map(str::to_lowercase);
+        let user = args.next().map(|user| user.trim_start_matches('@'));
+        let mut ignored = self.0.borrow_mut();
+        let text = match (action.as_deref(), user) {
+            (Some("add"), Some(user)) if ignored.add(user) => {
+                format!("Ignoring {} from now on.", user)
+            }
+            (Some("add"), Some(user)) => format!("{} is ignored already.", user),
+            (Some("remove"), Some(user)) if ignored.remove(user) => {
+                format!("{} isn't ignored anymore.", user)
+            }
+            (Some("remove"), Some(user)) => format!("{} can't be removed, it isn't ignored.", user),
+            (Some("list"), _) => match ignored.users()[..] {
+                [] => "No users are ignored.".to_owned(),
+                ref users => format!("Ignored users: {}", users.join(", ")),
+            },
+            _ => format!(
+                "Usage: {0}ignore add @user, {0}ignore remove @user or {0}ignore list",
+                ctx.prefix
+            ),
+        };
+        ctx.send(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logins_are_ignored_regardless_of_case() {
+        let configured = ["Nightbot".to_owned(), "streamelements".to_owned()];
+        let mut ignored =
+            IgnoreList::load("TwitchBotanist", &configured, Storage::default()).unwrap();
+        assert!(ignored.contains("nightbot"));
+        assert!(ignored.contains("StreamElements"));
+        assert!(ignored.contains("twitchbotanist"));
+        assert!(!ignored.contains("carkhy"));
+
+        assert!(ignored.add("Carkhy"));
+        assert!(!ignored.add("carkhy"));
+        assert!(ignored.remove("NIGHTBOT"));
+        assert!(!ignored.remove("twitchbotanist"));
+        assert_eq!(ignored.users(), ["carkhy", "streamelements"]);
+        assert!(!ignored.contains("nightbot"));
+        assert!(ignored.add("nightbot"));
+        assert_eq!(ignored.users(), ["carkhy", "nightbot", "streamelements"]);
+    }
+}
@@ -0,0 +1,1344 @@
+mod admin;
+mod args;
+mod budget;
+mod builtin;
+mod chat_modes;
+mod counter;
+mod custom;
+mod fun;
+mod help;
+mod ignored;
+mod quotes;
+mod script;
+mod stats;
+mod template;
+
+pub use admin::Export;
+use admin::{as_whispers, Admins};
+pub use args::Args;
+use budget::ResponseBudget;
+pub use counter::Change;
+pub use custom::{CustomCommands, NameTaken, Refusal, SharedCustomCommands};
+pub use ignored::{IgnoreList, SharedIgnoreList};
+pub use quotes::{Quote, Quotes, SharedQuotes};
+use stats::{CmdStats, Outcome};
+pub use stats::{CommandStats, SharedCommandStats, Tally};
+pub use template::render_event;
+
+use super::{
+    moderation::{
+        Ban, BanWord, EmoteSpam, Nuke, Pardon, RoleCommand, SharedModeration, Strikes, Timeout,
+        Unban,
+    },
+    recent_messages::{SharedRecentMessages, StoredMessage},
+    timers::SharedTimers,
+    ChatBotCommand,
+};
+use crate::{
+    config::{CommandsConfig, Denial},
+    connect::{Overflow, TextMessage, UserLevel, Whisper},
+    helix::Role,
+    prometheus::{COMMANDS, COMMAND_DURATION},
+    storage::Storage,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The message a command was called with.
+pub struct Context<'a> {
+    pub message: &'a TextMessage,
+    // of the message's channel
+    pub prefix: &'a str,
+    // when the registry dispatched the message
+    pub now: Instant,
+    // the message it replies to, if it is a reply
+    pub replied: Option<&'a StoredMessage>,
+}
+
+impl Context<'_> {
+    /// The message the command replies to, see `StoredMessage::is_complete` for whether the
+    /// bot still had it or only the reply's tags tell about it.
+    pub fn replied_message(&self) -> Option<&StoredMessage> {
+        self.replied
+    }
+
+    /// A chat message to the channel the command was called in.
+    pub fn send(&self, text: String) -> Option<ChatBotCommand> {
+        Some(ChatBotCommand::SendMessage {
+            channel: self.message.channel.to_owned(),
+            text,
+            overflow: Overflow::Split,
+        })
+    }
+
+    /// A threaded reply to the message when it can be referenced, otherwise a chat message.
+    pub fn reply(&self, text: String) -> Option<ChatBotCommand> {
+        match &self.message.message_id {
+            Some(parent_msg_id) => Some(ChatBotCommand::SendReply {
+                channel: self.message.channel.to_owned(),
+                parent_msg_id: parent_msg_id.to_owned(),
+                text,
+            }),
+            None => self.send(text),
+        }
+    }
+}
+
+/// A chat command like `!discord`, called by the [CommandRegistry].
+pub trait Command {
+    /// Lowercase and without the prefix.
+    fn name(&self) -> &'static str;
+
+    /// Other names the command is called by.
+    fn aliases(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// What follows the name, like `@<user> <amount>`, empty without arguments. `!` stands
+    /// for the channel's prefix.
+    fn usage(&self) -> &'static str {
+        ""
+    }
+
+    /// Users below the level can't call the command.
+    fn level(&self) -> UserLevel {
+        UserLevel::Everyone
+    }
+
+    /// Whether the command acts on the channel it is called in. Whispered, such a command
+    /// needs the channel as its first argument.
+    fn targets_channel(&self) -> bool {
+        true
+    }
+
+    /// How long the command can't be called again in the same channel.
+    fn cooldown(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Whether the channel, without the leading '#', has the command. Without it the name is
+    /// left to the channel's custom commands.
+    fn is_enabled(&self, _channel: &str) -> bool {
+        true
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand>;
+}
+
+/// Whether a message called one of the registered commands.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Dispatch {
+    // not a command, or one the registry doesn't know
+    Unknown,
+    // None when the command had nothing to say, or was not allowed or cooling down
+    Handled(Option<ChatBotCommand>),
+    // the user had as many answers in the last minute as a user gets
+    Dropped,
+}
+
+// twitch starts replies with a mention of the parent's author: "@Carkhy !quote"
+fn command_text(message: &TextMessage) -> &str {
+    match (&message.reply_to, message.text.split_once(' ')) {
+        (Some(_), Some((mention, rest))) if mention.starts_with('@') => rest.trim_start(),
+        _ => &message.text,
+    }
+}
+
+// without tags there are no badges, so everyone but the broadcaster is just a viewer
+pub fn level(message: &TextMessage) -> UserLevel {
+    if message.user.name == message.channel {
+        // an admin's whisper to their own channel stays above
+        message.level.max(UserLevel::Broadcaster)
+    } else {
+        message.level
+    }
+}
+
+fn level_name(level: UserLevel) -> &'static str {
+    match level {
+        UserLevel::Everyone => "everyone",
+        UserLevel::Subscriber => "subscribers",
+        UserLevel::Vip => "VIPs",
+        UserLevel::Moderator => "moderators",
+        UserLevel::Broadcaster => "the broadcaster",
+        UserLevel::Admin => "the bot's admins",
+    }
+}
+
+// what a user below the needed level hears
+fn deny(denial: Denial, ctx: &Context, name: &str, needed: UserLevel) -> Option<ChatBotCommand> {
+    match denial {
+        Denial::Silent => None,
+        Denial::Reply => ctx.reply(format!(
+            "Sorry, {}{} is only for {}.",
+            ctx.prefix,
+            name,
+            level_name(needed)
+        )),
+    }
+}
+
+fn record(stats: &SharedCommandStats, message: &TextMessage, name: &str, outcome: Outcome) {
+    stats.borrow_mut().record(
+        &message.channel,
+        name,
+        &message.user.name,
+        outcome,
+        SystemTime::now(),
+    );
+}
+
+/// The words after the command's name in the message.
+pub fn command_args(message: &TextMessage) -> Args<'_> {
+    let mut args = Args::new(command_text(message));
+    args.next();
+    args
+}
+
+// still parsed by the connector, custom commands can't be named like them either
+const LEGACY_COMMANDS: [(&str, UserLevel); 7] = [
+    ("slap", UserLevel::Everyone),
+    ("newcommand", UserLevel::Moderator),
+    ("removecommand", UserLevel::Moderator),
+    ("newrepeating", UserLevel::Moderator),
+    ("removerepeating", UserLevel::Moderator),
+    ("join", UserLevel::Broadcaster),
+    ("part", UserLevel::Broadcaster),
+];
+
+const HELP: &str = "help";
+
+/// Calls the command a chat message starts with, after the prefix of its channel.
+/// Custom commands of the channel are called when no registered command has the name.
+pub struct CommandRegistry {
+    commands: Vec<Box<dyn Command>>,
+    custom: SharedCustomCommands,
+    quotes: SharedQuotes,
+    prefix: String,
+    // channels without the leading '#'
+    channel_prefixes: HashMap<String, String>,
+    denial: Denial,
+    channel_denials: HashMap<String, Denial>,
+    // when each command was last called in each channel
+    last_called: HashMap<(String, &'static str), Instant>,
+    budget: ResponseBudget,
+    admins: Admins,
+    stats: SharedCommandStats,
+    recent: SharedRecentMessages,
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.commands.iter().map(|command| command.name()).collect();
+        f.debug_struct("CommandRegistry")
+            .field("commands", &names)
+            .field("prefix", &self.prefix)
+            .field("channel_prefixes", &self.channel_prefixes)
+            .field("denial", &self.denial)
+            .field("channel_denials", &self.channel_denials)
+            .finish()
+    }
+}
+
+impl CommandRegistry {
+    /// With the built-in commands registered.
+    pub fn new(
+        config: &CommandsConfig,
+        custom: CustomCommands,
+        quotes: Quotes,
+        timers: SharedTimers,
+        ignored: SharedIgnoreList,
+        moderation: SharedModeration,
+        storage: Storage,
+    ) -> Self {
+        let custom = Rc::new(RefCell::new(custom));
+        let quotes = Rc::new(RefCell::new(quotes));
+        for (name, level) in LEGACY_COMMANDS {
+            custom
+                .borrow_mut()
+                .reserve(name, &[], level, true)
+                .expect("legacy commands have distinct names");
+        }
+        // answered by the registry itself, it knows every command
+        custom
+            .borrow_mut()
+            .reserve(HELP, &[], UserLevel::Everyone, false)
+            .expect("!help has a name of its own");
+        let stats = SharedCommandStats::default();
+        let mut registry = Self {
+            commands: Vec::new(),
+            custom: custom.clone(),
+            quotes: quotes.clone(),
+            prefix: config.prefix.clone(),
+            channel_prefixes: config.channel_prefixes.clone(),
+            denial: config.denial,
+            channel_denials: HashMap::new(),
+            last_called: HashMap::new(),
+            budget: ResponseBudget::new(config.responses_per_user),
+            admins: Admins::new(&config.admins),
+            stats: stats.clone(),
+            recent: SharedRecentMessages::default(),
+        };
+        let builtin: [Box<dyn Command>; 46] = [
+            Box::new(builtin::Info),
+            Box::new(admin::Shutdown),
+            Box::new(fun::EightBall::new(
+                &config.eightball_answers,
+                fastrand::Rng::new(),
+            )),
+            Box::new(fun::Roll(fastrand::Rng::new())),
+            Box::new(builtin::Discord),
+            Box::new(builtin::Uptime),
+            Box::new(builtin::Clip),
+            Box::new(builtin::Followage),
+            Box::new(builtin::Shoutout::default()),
+            Box::new(builtin::SetTitle),
+            Box::new(builtin::SetGame),
+            Box::new(builtin::Marker(storage)),
+            Box::new(builtin::Commercial {
+                default: config.commercial_default,
+            }),
+            Box::new(builtin::Shield),
+            Box::new(chat_modes::Slow),
+            Box::new(chat_modes::SlowOff),
+            Box::new(chat_modes::EmoteOnly),
+            Box::new(chat_modes::Followers),
+            Box::new(chat_modes::Subscribers),
+            Box::new(chat_modes::UniqueChat),
+            Box::new(builtin::TimersSwitch(timers)),
+            Box::new(ignored::IgnoreCommand(ignored)),
+            Box::new(builtin::Permit(moderation.clone())),
+            Box::new(BanWord(moderation.clone())),
+            Box::new(EmoteSpam(moderation.clone())),
+            Box::new(Strikes(moderation.clone())),
+            Box::new(Pardon(moderation.clone())),
+            Box::new(Timeout(moderation.clone())),
+            Box::new(Ban(moderation.clone())),
+            Box::new(Unban(moderation.clone())),
+            Box::new(RoleCommand {
+                moderation: moderation.clone(),
+                role: Role::Vip,
+                add: true,
+            }),
+            Box::new(RoleCommand {
+                moderation: moderation.clone(),
+                role: Role::Vip,
+                add: false,
+            }),
+            Box::new(RoleCommand {
+                moderation: moderation.clone(),
+                role: Role::Moderator,
+                add: true,
+            }),
+            Box::new(RoleCommand {
+                moderation: moderation.clone(),
+                role: Role::Moderator,
+                add: false,
+            }),
+            Box::new(Nuke(moderation)),
+            Box::new(custom::ListCommands(custom.clone())),
+            Box::new(custom::AddCmd(custom.clone())),
+            Box::new(custom::EditCmd(custom.clone())),
+            Box::new(custom::EditScript(custom.clone())),
+            Box::new(custom::DelCmd(custom.clone())),
+            Box::new(custom::AliasCmd(custom.clone())),
+            Box::new(counter::AddCounter(custom)),
+            Box::new(quotes::QuoteCommand(quotes.clone())),
+            Box::new(quotes::AddQuote(quotes.clone())),
+            Box::new(quotes::DelQuote(quotes)),
+            Box::new(CmdStats(stats)),
+        ];
+        for command in builtin {
+            registry
+                .register(command)
+                .expect("built-in commands have distinct names");
+        }
+        registry
+    }
+
+    /// Fails if the command's name or one of its aliases is taken, nothing is replaced.
+    pub fn register(&mut self, command: Box<dyn Command>) -> Result<(), NameTaken> {
+        self.custom.borrow_mut().reserve(
+            command.name(),
+            command.aliases(),
+            command.level(),
+            false,
+        )?;
+        self.commands.push(command);
+        Ok(())
+    }
+
+    pub fn custom(&self) -> &SharedCustomCommands {
+        &self.custom
+    }
+
+    pub fn quotes(&self) -> &SharedQuotes {
+        &self.quotes
+    }
+
+    pub fn stats(&self) -> &SharedCommandStats {
+        &self.stats
+    }
+
+    /// The latest messages, which the bot keeps and replies are matched to.
+    pub fn recent(&self) -> &SharedRecentMessages {
+        &self.recent
+    }
+
+    pub fn prefix(&self, channel: &str) -> &str {
+        self.channel_prefixes.get(channel).unwrap_or(&self.prefix)
+    }
+
+    /// Takes the prefix and the denial of a channel from its own config.
+    pub fn scope(&mut self, channel: &str, config: &CommandsConfig) {
+        let channel = channel.trim_start_matches('#').to_owned();
+        self.channel_prefixes
+            .insert(channel.clone(), config.prefix.clone());
+        self.channel_denials.insert(channel, config.denial);
+    }
+
+    fn denial(&self, channel: &str) -> Denial {
+        self.channel_denials
+            .get(channel)
+            .copied()
+            .unwrap_or(self.denial)
+    }
+
+    /// Whether the user had all answers a minute allows, moderators are never limited.
+    pub fn is_spent(&self, message: &TextMessage, now: Instant) -> bool {
+        level(message) < UserLevel::Moderator
+            && self
+                .budget
+                .is_spent(&message.channel, &message.user.name, now)
+    }
+
+    /// Counts an answer to the user's command.
+    pub fn spend(&mut self, message: &TextMessage, now: Instant) {
+        self.budget.spend(&message.channel, &message.user.name, now);
+    }
+
+    pub fn is_admin(&self, login: &str) -> bool {
+        self.admins.contains(login)
+    }
+
+    /// Calls the command an admin whispered, at the level no badge grants, and whispers the
+    /// answer back. A command acting on a channel is whispered with the channel first, like
+    /// `!timers #carkhy off`. Whispers of everyone else are ignored.
+    pub fn whisper(&mut self, whisper: &Whisper, now: Instant) -> Option<ChatBotCommand> {
+        let login = &whisper.user.name;
+        if !self.admins.contains(login) {
+            return self.admins.ignore(login, now);
+        }
+        let Some(rest) = whisper
+            .text
+            .strip_prefix(&self.prefix)
+            .filter(|rest| !rest.starts_with(char::is_whitespace))
+        else {
+            return Some(ChatBotCommand::LogTextMessage(format!(
+                "Whisper from {}: {}",
+                whisper.user.display_name(),
+                whisper.text
+            )));
+        };
+        let mut args = Args::new(rest);
+        let name = args.next()?.to_lowercase();
+        let mut message = TextMessage {
+            text: whisper.text.clone(),
+            user: whisper.user.clone(),
+            level: UserLevel::Admin,
+            ..Default::default()
+        };
+        let answer = |text: String| {
+            Some(as_whispers(
+                login,
+                ChatBotCommand::SendMessage {
+                    channel: String::new(),
+                    text,
+                    overflow: Overflow::Split,
+                },
+            ))
+        };
+        // custom commands are the channel's own
+        let targets_channel = self
+            .commands
+            .iter()
+            .find(|command| command.name() == name || command.aliases().contains(&name.as_str()))
+            .is_none_or(|command| command.targets_channel());
+        if targets_channel {
+            let Some(channel) = args
+                .clone()
+                .next()
+                .and_then(|channel| channel.strip_prefix('#'))
+                .filter(|channel| !channel.is_empty())
+                .map(str::to_lowercase)
+            else {
+                return answer(
+                    format!(
+                        "{0}{1} needs the channel first in a whisper, like {0}{1} #channel {2}",
+                        self.prefix,
+                        name,
+                        args.rest().unwrap_or_default()
+                    )
+                    .trim_end()
+                    .to_owned(),
+                );
+            };
+            args.next();
+            message.text = format!(
+                "{}{} {}",
+                self.prefix(&channel),
+                name,
+                args.rest().unwrap_or_default()
+            );
+            message.channel = channel;
+        }
+        match self.dispatch(&message, now) {
+            Dispatch::Handled(handled) => handled.map(|handled| as_whispers(login, handled)),
+            Dispatch::Unknown if targets_channel => answer(format!(
+                "There is no {}{} in #{}.",
+                self.prefix, name, message.channel
+            )),
+            Dispatch::Unknown => answer(format!("There is no {}{}.", self.prefix, name)),
+            Dispatch::Dropped => None,
+        }
+    }
+
+    // `!help` lists what the user may call in the channel, `!help 2` the next page and
+    // `!help <command>` tells how to call one
+    fn help(&self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let level = level(ctx.message);
+        let disabled = |name: &str| {
+            let mut commands = self.commands.iter();
+            commands.any(|command| command.name() == name && !command.is_enabled(channel))
+        };
+        let number = match args.next() {
+            None => 1,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(number) => number,
+                Err(_) => {
+                    let name = arg.strip_prefix(ctx.prefix).unwrap_or(arg).to_lowercase();
+                    return ctx.send(self.describe(ctx, &name));
+                }
+            },
+        };
+        let names = self
+            .custom
+            .borrow()
+            .names(channel, ctx.prefix, level, disabled);
+        let page = help::page(&names, number, ctx.prefix);
+        ctx.send(page.unwrap_or_else(|| format!("There is no page {} of commands.", number)))
+    }
+
+    // "!give @<user> <amount>, for moderators, at most every 30 seconds"
+    fn describe(&self, ctx: &Context, name: &str) -> String {
+        let prefix = ctx.prefix;
+        if name == HELP {
+            return format!("{0}{1} [page], {0}{1} <command>", prefix, HELP);
+        }
+        let command = self
+            .commands
+            .iter()
+            .find(|command| command.name() == name || command.aliases().contains(&name));
+        let Some(command) = command.filter(|command| command.is_enabled(&ctx.message.channel))
+        else {
+            let custom = self.custom.borrow();
+            return match custom.get(&ctx.message.channel, name) {
+                Some(_) => format!("{}{} is a custom command of this channel.", prefix, name),
+                None if custom.is_builtin(name) => format!("!{} takes no help.", name),
+                None => format!("There is no {}{}.", prefix, name),
+            };
+        };
+        let mut text = format!("{}{}", prefix, command.name());
+        if !command.usage().is_empty() {
+            text.push(' ');
+            text.push_str(&command.usage().replace('!', prefix));
+        }
+        if !command.aliases().is_empty() {
+            let aliases: Vec<_> = command
+                .aliases()
+                .iter()
+                .map(|alias| format!("{}{}", prefix, alias))
+                .collect();
+            text.push_str(&format!(" (also {})", aliases.join(", ")));
+        }
+        if command.level() > UserLevel::Everyone {
+            text.push_str(&format!(", only for {}", level_name(command.level())));
+        }
+        if !command.cooldown().is_zero() {
+            text.push_str(&format!(
+                ", at most every {} seconds",
+                command.cooldown().as_secs()
+            ));
+        }
+        text
+    }
+
+    pub fn dispatch(&mut self, message: &TextMessage, now: Instant) -> Dispatch {
+        let prefix = self.prefix(&message.channel).to_owned();
+        // "!" alone or "! discord" is no command
+        let Some(rest) = command_text(message).strip_prefix(&prefix) else {
+            return Dispatch::Unknown;
+        };
+        if rest.starts_with(char::is_whitespace) {
+            return Dispatch::Unknown;
+        }
+        let mut args = Args::new(rest);
+        let Some(name) = args.next().map(str::to_lowercase) else {
+            return Dispatch::Unknown;
+        };
+        let replied = self.recent.borrow().replied(message);
+        let ctx = Context {
+            message,
+            prefix: &prefix,
+            now,
+            replied: replied.as_ref(),
+        };
+        let spent = self.is_spent(message, now);
+        if name == HELP {
+            if spent {
+                return Dispatch::Dropped;
+            }
+            let answer = self.help(&ctx, args);
+            record(&self.stats, message, HELP, Outcome::Succeeded);
+            COMMANDS.inc(&[HELP]);
+            self.spend(message, now);
+            return Dispatch::Handled(answer);
+        }
+        let denial = self.denial(&message.channel);
+        let Some(command) = self.commands.iter_mut().find(|command| {
+            // declared names are lowercase, unless a command gets it wrong
+            let called = |declared: &&str| declared.eq_ignore_ascii_case(&name);
+            (called(&command.name()) || command.aliases().iter().any(called))
+                && command.is_enabled(&message.channel)
+        }) else {
+            if spent && self.custom.borrow().get(&message.channel, &name).is_some() {
+                return Dispatch::Dropped;
+            }
+            let started = Instant::now();
+            let called = self.custom.borrow_mut().call(&ctx, &name, args);
+            if let Some(Ok(_)) = called {
+                COMMANDS.inc(&[&name]);
+                COMMAND_DURATION.observe(&[], started.elapsed());
+            }
+            match called {
+                Some(Ok(_)) if !spent => record(&self.stats, message, &name, Outcome::Succeeded),
+                Some(Err(_)) => record(&self.stats, message, &name, Outcome::Denied),
+                _ => {}
+            }
+            return match called {
+                // "!deaths+" is known only once called
+                Some(Ok(_)) if spent => Dispatch::Dropped,
+                Some(Ok(mut responses)) => {
+                    self.spend(message, now);
+                    Dispatch::Handled(match responses.len() {
+                        0 | 1 => responses.pop().and_then(|response| ctx.send(response)),
+                        _ => Some(ChatBotCommand::MultipleCommands(
+                            responses
+                                .into_iter()
+                                .filter_map(|response| ctx.send(response))
+                                .collect(),
+                        )),
+                    })
+                }
+                Some(Err(needed)) => Dispatch::Handled(deny(denial, &ctx, &name, needed)),
+                None => Dispatch::Unknown,
+            };
+        };
+        if level(message) < command.level() {
+            record(&self.stats, message, command.name(), Outcome::Denied);
+            return Dispatch::Handled(deny(denial, &ctx, command.name(), command.level()));
+        }
+        let key = (message.channel.clone(), command.name());
+        if let Some(&called) = self.last_called.get(&key) {
+            if now < called + command.cooldown() {
+                record(&self.stats, message, command.name(), Outcome::Cooldown);
+                return Dispatch::Handled(None);
+            }
+        }
+        if spent {
+            return Dispatch::Dropped;
+        }
+        self.last_called.insert(key, now);
+        let started = Instant::now();
+        let answer = command.execute(&ctx, args);
+        record(&self.stats, message, command.name(), Outcome::Succeeded);
+        COMMANDS.inc(&[command.name()]);
+        COMMAND_DURATION.observe(&[], started.elapsed());
+        if answer.is_some() {
+            self.spend(message, now);
+        }
+        Dispatch::Handled(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect::{Badge, ReplyParent, UserInfo},
+        core::HelixTask,
+        storage::testing::TempDir,
+    };
+
+    // repeats the arguments, with the last one as the rest of the line
+    struct Say;
+
+    impl Command for Say {
+        fn name(&self) -> &'static str {
+            "say"
+        }
+
+        fn level(&self) -> UserLevel {
+            UserLevel::Moderator
+        }
+
+        fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+            let first = args.next()?;
+            ctx.send(format!("{}|{}", first, args.rest().unwrap_or_default()))
+        }
+    }
+
+    fn message(channel: &str, text: &str) -> TextMessage {
+        TextMessage {
+            channel: channel.to_owned(),
+            text: text.to_owned(),
+            level: UserLevel::Moderator,
+            ..Default::default()
+        }
+    }
+
+    fn registry() -> CommandRegistry {
+        let config = CommandsConfig {
+            channel_prefixes: HashMap::from([("carkhy".to_owned(), "?".to_owned())]),
+            ..Default::default()
+        };
+        let mut registry = CommandRegistry::new(
+            &config,
+            CustomCommands::default(),
+            Quotes::default(),
+            SharedTimers::default(),
+            SharedIgnoreList::default(),
+            SharedModeration::default(),
+            Storage::default(),
+        );
+        registry.register(Box::new(Say)).unwrap();
+        registry
+    }
+
+    fn sent(dispatch: Dispatch) -> Option<String> {
+        match dispatch {
+            Dispatch::Handled(Some(ChatBotCommand::SendMessage { text, .. })) => Some(text),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn arguments_follow_the_name() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let said =
+            sent(registry.dispatch(&message("captaincallback", "!SAY   hi  there   you "), now));
+        assert_eq!(said.as_deref(), Some("hi|there   you"));
+    }
+
+    #[test]
+    fn prefix_alone_is_no_command() {
+        let mut registry = registry();
+        let now = Instant::now();
+        for text in ["!", "! say hi", "say hi", "!unknown", "?say hi"] {
+            assert!(
+                matches!(
+                    registry.dispatch(&message("captaincallback", text), now),
+                    Dispatch::Unknown
+                ),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn channels_have_their_own_prefix() {
+        let mut registry = registry();
+        let now = Instant::now();
+        assert_eq!(registry.prefix("carkhy"), "?");
+        assert!(sent(registry.dispatch(&message("carkhy", "?say hi"), now)).is_some());
+        assert!(matches!(
+            registry.dispatch(&message("carkhy", "!say hi"), now),
+            Dispatch::Unknown
+        ));
+    }
+
+    #[test]
+    fn commands_cool_down_per_channel() {
+        let mut registry = registry();
+        let now = Instant::now();
+        assert!(sent(registry.dispatch(&message("captaincallback", "!discord"), now)).is_some());
+        let later = now + Duration::from_secs(10);
+        assert!(matches!(
+            registry.dispatch(&message("captaincallback", "!dc"), later),
+            Dispatch::Handled(None)
+        ));
+        assert!(sent(registry.dispatch(&message("carkhy", "?discord"), later)).is_some());
+        let much_later = now + Duration::from_secs(30);
+        assert!(
+            sent(registry.dispatch(&message("captaincallback", "!discord"), much_later)).is_some()
+        );
+    }
+
+    // the level computed from the badges, just like the connector does
+    fn from(name: &str, badges: &[Badge], text: &str) -> TextMessage {
+        TextMessage {
+            user: UserInfo {
+                name: name.to_owned(),
+                badges: badges.to_vec(),
+                ..Default::default()
+            },
+            level: UserLevel::from_badges(badges),
+            message_id: Some("b34ccfc7".to_owned()),
+            ..message("captaincallback", text)
+        }
+    }
+
+    #[test]
+    fn moderator_commands_are_refused_below() {
+        let mut registry = registry();
+        let now = Instant::now();
+        for badges in [vec![], vec![Badge::Vip], vec![Badge::Bits { amount: 100 }]] {
+            let dispatch = registry.dispatch(&from("carkhy", &badges, "!say hi"), now);
+            assert!(matches!(dispatch, Dispatch::Handled(None)), "{:?}", badges);
+        }
+        let moderator = from("carkhy", &[Badge::Moderator], "!say hi");
+        assert!(sent(registry.dispatch(&moderator, now)).is_some());
+        let broadcaster = from("captaincallback", &[Badge::Broadcaster], "!say hi");
+        assert!(sent(registry.dispatch(&broadcaster, now)).is_some());
+    }
+
+    #[test]
+    fn broadcaster_passes_without_tags() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut broadcaster = message("captaincallback", "!say hi");
+        broadcaster.level = UserLevel::Everyone;
+        broadcaster.user.name = "captaincallback".to_owned();
+        assert!(sent(registry.dispatch(&broadcaster, now)).is_some());
+        let mut viewer = broadcaster.clone();
+        viewer.user.name = "carkhy".to_owned();
+        assert!(matches!(
+            registry.dispatch(&viewer, now),
+            Dispatch::Handled(None)
+        ));
+    }
+
+    #[test]
+    fn denial_can_be_a_reply() {
+        let mut registry = CommandRegistry::new(
+            &CommandsConfig {
+                denial: Denial::Reply,
+                ..Default::default()
+            },
+            CustomCommands::default(),
+            Quotes::default(),
+            SharedTimers::default(),
+            SharedIgnoreList::default(),
+            SharedModeration::default(),
+            Storage::default(),
+        );
+        registry.register(Box::new(Say)).unwrap();
+        let dispatch = registry.dispatch(&from("carkhy", &[Badge::Vip], "!say hi"), Instant::now());
+        assert!(
+            matches!(
+                &dispatch,
+                Dispatch::Handled(Some(ChatBotCommand::SendReply { parent_msg_id, text, .. }))
+                    if parent_msg_id == "b34ccfc7" && text == "Sorry, !say is only for moderators."
+            ),
+            "{:?}",
+            dispatch
+        );
+    }
+
+    #[test]
+    fn replies_start_with_a_mention() {
+        let mut registry = registry();
+        let mut reply = message("captaincallback", "@Carkhy !say hi");
+        reply.reply_to = Some(ReplyParent {
+            message_id: "b34ccfc7".to_owned(),
+            user_login: "carkhy".to_owned(),
+            display_name: "Carkhy".to_owned(),
+            body: "hi".to_owned(),
+        });
+        assert!(sent(registry.dispatch(&reply, Instant::now())).is_some());
+        let mut args = command_args(&reply);
+        assert_eq!(args.next(), Some("hi"));
+    }
+
+    #[test]
+    fn custom_commands_are_changed_from_chat() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |channel, text| sent(registry.dispatch(&message(channel, text), now));
+        assert_eq!(
+            say("carkhy", "?addcmd ?Lurk Enjoy  the lurk").as_deref(),
+            Some("Added ?lurk.")
+        );
+        assert_eq!(say("carkhy", "?lurk").as_deref(), Some("Enjoy  the lurk"));
+        assert_eq!(say("captaincallback", "!lurk"), None);
+        assert_eq!(
+            say("carkhy", "?editcmd ?lurk See you later").as_deref(),
+            Some("Changed ?lurk.")
+        );
+        assert_eq!(say("carkhy", "?LURK").as_deref(), Some("See you later"));
+        assert_eq!(
+            say("carkhy", "?delcmd ?lurk").as_deref(),
+            Some("Removed ?lurk.")
+        );
+        assert_eq!(say("carkhy", "?lurk"), None);
+        assert_eq!(
+            say("carkhy", "?delcmd ?lurk").as_deref(),
+            Some("There is no ?lurk, add it with ?addcmd.")
+        );
+    }
+
+    #[test]
+    fn custom_commands_need_a_valid_name() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |text: &str| sent(registry.dispatch(&message("captaincallback", text), now));
+        for text in ["!addcmd lurk Enjoy", "!addcmd ! Enjoy", "!addcmd !lurk"] {
+            assert_eq!(
+                say(text).as_deref(),
+                Some("Usage: !addcmd !name response"),
+                "{}",
+                text
+            );
+        }
+        for name in ["!dc", "!say", "!help", "!commands"] {
+            let text = format!("!addcmd {} Join us", name);
+            assert_eq!(say(&text), Some(format!("{} is a built-in command.", name)));
+        }
+    }
+
+    #[test]
+    fn commands_lists_what_the_user_may_call() {
+        let mut registry = registry();
+        let now = Instant::now();
+        registry.dispatch(&message("captaincallback", "!addcmd !lurk Enjoy"), now);
+        let viewer = from("carkhy", &[], "!commands");
+        assert_eq!(
+            sent(registry.dispatch(&viewer, now)).as_deref(),
+            Some(
+                "Commands: !8ball, !clip, !commands, !discord (!dc), !followage, !help, !info, !quote, !roll, !slap, !uptime | Custom: !lurk"
+            )
+        );
+    }
+
+    #[test]
+    fn help_lists_and_explains_what_the_user_may_call() {
+        let mut registry = registry();
+        let now = Instant::now();
+        registry.dispatch(&message("captaincallback", "!addcmd !lurk Enjoy"), now);
+        let viewer = sent(registry.dispatch(&from("carkhy", &[], "!help"), now)).unwrap();
+        assert!(viewer.starts_with("Commands: "), "{}", viewer);
+        assert!(
+            viewer.contains("!points") || viewer.contains("!lurk"),
+            "{}",
+            viewer
+        );
+        assert!(!viewer.contains("!addcmd"), "{}", viewer);
+        let later = now + Duration::from_secs(60);
+        let moderator = from("modname", &[Badge::Moderator], "!help");
+        let listed = sent(registry.dispatch(&moderator, later)).unwrap();
+        assert!(listed.contains("!addcmd"), "{}", listed);
+        let mut help = |text: &str, at: Duration| {
+            sent(registry.dispatch(&from("carkhy", &[], text), now + at))
+        };
+        assert_eq!(
+            help("!help !discord", Duration::from_secs(120)).as_deref(),
+            Some("!discord (also !dc), at most every 30 seconds")
+        );
+        assert_eq!(
+            help("!help addcmd", Duration::from_secs(180)).as_deref(),
+            Some("!addcmd !<name> <Text to return>, only for moderators")
+        );
+        assert_eq!(
+            help("!help lurk", Duration::from_secs(240)).as_deref(),
+            Some("!lurk is a custom command of this channel.")
+        );
+        assert_eq!(
+            help("!help 9", Duration::from_secs(300)).as_deref(),
+            Some("There is no page 9 of commands.")
+        );
+    }
+
+    #[test]
+    fn cmdstats_counts_the_outcomes() {
+        let mut registry = registry();
+        let now = Instant::now();
+        registry.dispatch(&from("viewer", &[], "!say hi"), now);
+        registry.dispatch(
+            &message("captaincallback", "!say hi"),
+            now + Duration::from_secs(60),
+        );
+        let stats = sent(registry.dispatch(
+            &message("captaincallback", "!cmdstats !say"),
+            now + Duration::from_secs(120),
+        ))
+        .unwrap();
+        assert!(
+            stats.starts_with("!say was called 2 times by 2 users in the last 30 days, 2 today: 1 succeeded, 0 on cooldown, 1 denied."),
+            "{}",
+            stats
+        );
+    }
+
+    // claims the alias of !discord
+    struct Dc;
+
+    impl Command for Dc {
+        fn name(&self) -> &'static str {
+            "dcc"
+        }
+
+        fn aliases(&self) -> &[&'static str] {
+            &["DC"]
+        }
+
+        fn execute(&mut self, _ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+            None
+        }
+    }
+
+    #[test]
+    fn names_are_claimed_once() {
+        let mut registry = registry();
+        assert!(matches!(
+            registry.register(Box::new(Say)),
+            Err(NameTaken {
+                name: "say",
+                taken_by: "say"
+            })
+        ));
+        assert!(matches!(
+            registry.register(Box::new(Dc)),
+            Err(NameTaken {
+                name: "DC",
+                taken_by: "discord"
+            })
+        ));
+        assert!(matches!(
+            registry.dispatch(&message("captaincallback", "!dcc"), Instant::now()),
+            Dispatch::Unknown
+        ));
+    }
+
+    #[test]
+    fn aliases_call_the_same_command() {
+        let mut registry = registry();
+        let now = Instant::now();
+        for text in ["!so @Carkhy", "!ShoutOut Viewer", "!HOST @Lurker"] {
+            let dispatch = registry.dispatch(&message("captaincallback", text), now);
+            assert!(
+                matches!(&dispatch, Dispatch::Handled(Some(ChatBotCommand::Helix(
+                    HelixTask::Shoutout { login, .. }
+                ))) if text.to_lowercase().ends_with(login.as_str())),
+                "{:?}",
+                dispatch
+            );
+        }
+        let mut say = |text: &str| sent(registry.dispatch(&message("captaincallback", text), now));
+        assert_eq!(
+            say("!so carkhy").as_deref(),
+            Some("carkhy was shouted out less than an hour ago.")
+        );
+
+        say("!addcmd !lurk Enjoy the lurk");
+        assert_eq!(
+            say("!aliascmd !lurk !Lurking").as_deref(),
+            Some("!lurking calls !lurk now.")
+        );
+        assert_eq!(say("!LURKING").as_deref(), Some("Enjoy the lurk"));
+        assert_eq!(
+            say("!aliascmd !lurk !dc").as_deref(),
+            Some("!dc is a built-in command.")
+        );
+        assert_eq!(
+            say("!addcmd !vip Hi!").as_deref(),
+            Some("!vip is a built-in command.")
+        );
+        say("!addcmd !hello Hi!");
+        assert_eq!(
+            say("!aliascmd !hello !lurking").as_deref(),
+            Some("!lurking calls !lurk already.")
+        );
+        assert_eq!(
+            say("!addcmd !lurking Bye").as_deref(),
+            Some("!lurking exists already, change it with !editcmd.")
+        );
+        assert_eq!(
+            say("!commands").as_deref(),
+            Some("Commands: !8ball, !addcmd, !addcounter, !addquote, !aliascmd, !ban, !banword, !clip, !cmdstats, !commands, !commercial, !delcmd, !delquote, !discord (!dc), !editcmd, !editscript, !emoteonly, !emotespam, !followage, !followers, !help, !ignore, !info, !marker, !newcommand, !newrepeating, !nuke, !pardon, !permit, !quote, !removecommand, !removerepeating, !roll, !say, !setgame, !settitle, !shield, !slap, !slow, !slowoff, !so (!shoutout, !host), !strikes, !subscribers, !timeout, !timers, !unban (!untimeout), !uniquechat, !uptime | Custom: !hello, !lurk (!lurking)")
+        );
+        assert_eq!(
+            say("!delcmd !lurking").as_deref(),
+            Some("Removed !lurking.")
+        );
+        assert_eq!(say("!lurk").as_deref(), Some("Enjoy the lurk"));
+        assert_eq!(say("!lurking"), None);
+    }
+
+    #[test]
+    fn template_errors_are_reported_when_saving() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |text: &str| sent(registry.dispatch(&message("captaincallback", text), now));
+        assert_eq!(
+            say("!addcmd !dice $(user) rolls $(random 6").as_deref(),
+            Some("Nothing changed, $( at character 14 is never closed.")
+        );
+        assert_eq!(say("!dice"), None);
+        say("!addcmd !dice rolls $(random 6 6)");
+        assert_eq!(say("!dice").as_deref(), Some("rolls 6"));
+    }
+
+    #[test]
+    fn counters_are_counted_by_moderators() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |text: &str| sent(registry.dispatch(&message("captaincallback", text), now));
+        assert_eq!(
+            say("!addcounter deaths \"has died $(value) times\"").as_deref(),
+            Some("Added !deaths.")
+        );
+        assert_eq!(say("!deaths").as_deref(), Some("has died 0 times"));
+        say("!deaths+");
+        assert_eq!(say("!Deaths+").as_deref(), Some("has died 2 times"));
+        assert_eq!(say("!deaths set 12").as_deref(), Some("has died 12 times"));
+        assert_eq!(say("!deaths-").as_deref(), Some("has died 11 times"));
+        assert_eq!(
+            say("!addcounter deaths+").as_deref(),
+            Some("Usage: !addcounter name \"text with $(value)\"")
+        );
+        say("!addcmd !hug hugs");
+        assert_eq!(say("!hug+"), None);
+        assert_eq!(say("!hug set 3").as_deref(), Some("hugs"));
+
+        let viewer = |text| from("carkhy", &[], text);
+        assert!(matches!(
+            registry.dispatch(&viewer("!deaths+"), now),
+            Dispatch::Handled(None)
+        ));
+        assert_eq!(
+            sent(registry.dispatch(&viewer("!deaths"), now)).as_deref(),
+            Some("has died 11 times")
+        );
+        assert_eq!(
+            sent(registry.dispatch(&message("captaincallback", "!deaths open"), now)).as_deref(),
+            Some("Everyone can count !deaths now.")
+        );
+        assert_eq!(
+            sent(registry.dispatch(&viewer("!deaths+"), now)).as_deref(),
+            Some("has died 12 times")
+        );
+        assert!(matches!(
+            registry.dispatch(&viewer("!deaths set 0"), now),
+            Dispatch::Handled(None)
+        ));
+    }
+
+    #[test]
+    fn quotes_keep_their_ids() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |text: &str| {
+            let mut message = message("captaincallback", text);
+            message.user.display_name = Some("Carkhy".to_owned());
+            // 2021-11-22
+            message.timestamp = Some(std::time::UNIX_EPOCH + Duration::from_secs(1637614002));
+            sent(registry.dispatch(&message, now))
+        };
+        assert_eq!(
+            say("!quote").as_deref(),
+            Some("There are no quotes yet, add one with !addquote.")
+        );
+        assert_eq!(
+            say("!quote search begins").as_deref(),
+            Some("No quote contains \"begins\".")
+        );
+        assert_eq!(
+            say("!addquote so it begins").as_deref(),
+            Some("Added quote #1.")
+        );
+        assert_eq!(
+            say("!addquote it begins again").as_deref(),
+            Some("Added quote #2.")
+        );
+        assert_eq!(say("!addquote the end").as_deref(), Some("Added quote #3."));
+        assert_eq!(say("!delquote 2").as_deref(), Some("Removed quote #2."));
+        assert_eq!(say("!quote 2").as_deref(), Some("There is no quote #2."));
+        assert_eq!(
+            say("!quote #3").as_deref(),
+            Some("#3: \"the end\" (added by Carkhy on 2021-11-22)")
+        );
+        assert_eq!(
+            say("!quote search BEGINS").as_deref(),
+            Some("#1: \"so it begins\" (added by Carkhy on 2021-11-22)")
+        );
+        say("!addquote begins and ends");
+        assert_eq!(
+            say("!quote search begins").as_deref(),
+            Some("2 quotes contain \"begins\": #1, #4")
+        );
+        assert_eq!(say("!delquote 9").as_deref(), Some("There is no quote #9."));
+    }
+
+    #[test]
+    fn markers_are_logged_before_asking_twitch() {
+        let directory = TempDir::new("chatbot-markers");
+        let mut registry = CommandRegistry::new(
+            &CommandsConfig::default(),
+            CustomCommands::default(),
+            Quotes::default(),
+            SharedTimers::default(),
+            SharedIgnoreList::default(),
+            SharedModeration::default(),
+            Storage::new(&directory),
+        );
+        let mut message = message("captaincallback", &format!("!marker {}", "a".repeat(150)));
+        message.user.name = "carkhy".to_owned();
+        message.timestamp = Some(std::time::UNIX_EPOCH + Duration::from_secs(1_637_614_002));
+        let dispatch = registry.dispatch(&message, Instant::now());
+        assert!(
+            matches!(&dispatch, Dispatch::Handled(Some(ChatBotCommand::Helix(
+                HelixTask::Marker { description, .. }
+            ))) if description.len() == 140),
+            "{:?}",
+            dispatch
+        );
+        assert_eq!(
+            std::fs::read_to_string(directory.join("markers.log")).unwrap(),
+            format!(
+                "2021-11-22 20:46:42 #captaincallback carkhy: {}\n",
+                "a".repeat(140)
+            )
+        );
+    }
+
+    #[test]
+    fn banned_terms_are_never_repeated_in_chat() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |text: &str| match registry.dispatch(&message("carkhy", text), now) {
+            Dispatch::Handled(Some(ChatBotCommand::SendMessage { text, .. })) => text,
+            Dispatch::Handled(Some(ChatBotCommand::MultipleCommands(commands))) => {
+                match &commands[..] {
+                    [ChatBotCommand::LogTextMessage(_), ChatBotCommand::SendMessage { text, .. }] => {
+                        text.clone()
+                    }
+                    _ => panic!("{:?}", commands),
+                }
+            }
+            _ => panic!("no answer to {}", text),
+        };
+        assert_eq!(
+            say("?banword add 600 follow bots"),
+            "Banned the term, messages with it are deleted with a 600 second timeout."
+        );
+        assert_eq!(
+            say("?banword add Follow Bots"),
+            "That term is banned already."
+        );
+        assert_eq!(
+            say("?banword add /b[o0/"),
+            "That regex is invalid, the bot's log tells why."
+        );
+        assert_eq!(
+            say("?banword add ban /v[i1]ewb[o0]t/"),
+            "Banned the term, messages with it are deleted and their author banned."
+        );
+        assert_eq!(
+            say("?banword list"),
+            "2 terms are banned, the bot's log lists them."
+        );
+        assert_eq!(
+            say("?banword remove /v[i1]ewb[o0]t/"),
+            "Removed the banned term."
+        );
+        assert_eq!(say("?banword remove viewbot"), "That term isn't banned.");
+    }
+
+    #[test]
+    fn commercials_need_an_allowed_length() {
+        let mut registry = registry();
+        let now = Instant::now();
+        let mut say = |text: &str| sent(registry.dispatch(&message("captaincallback", text), now));
+        assert_eq!(
+            say("!commercial").as_deref(),
+            Some("Usage: !commercial 30|60|90|120|150|180")
+        );
+        assert_eq!(
+            say("!commercial 45").as_deref(),
+            Some("A commercial is 30, 60, 90, 120, 150 or 180 seconds long.")
+        );
+        let commercial = |registry: &mut CommandRegistry, text: &str| match registry
+            .dispatch(&message("captaincallback", text), now)
+        {
+            Dispatch::Handled(Some(ChatBotCommand::Helix(HelixTask::Commercial {
+                length,
+                ..
+            }))) => Some(length),
+            _ => None,
+        };
+        assert_eq!(commercial(&mut registry, "!commercial 90"), Some(90));
+
+        let mut registry = CommandRegistry::new(
+            &CommandsConfig {
+                commercial_default: true,
+                ..Default::default()
+            },
+            CustomCommands::default(),
+            Quotes::default(),
+            SharedTimers::default(),
+            SharedIgnoreList::default(),
+            SharedModeration::default(),
+            Storage::default(),
+        );
+        assert_eq!(commercial(&mut registry, "!commercial"), Some(30));
+    }
+
+    #[test]
+    fn users_get_five_answers_a_minute() {
+        let mut registry = registry();
+        let start = Instant::now();
+        let mut call = |name: &str, badges: &[Badge], text: &str, now| {
+            registry.dispatch(&from(name, badges, text), now)
+        };
+        for text in ["!info", "!uptime", "!followage", "!clip", "!commands"] {
+            let dispatch = call("carkhy", &[], text, start);
+            assert!(matches!(dispatch, Dispatch::Handled(Some(_))), "{}", text);
+        }
+        assert!(matches!(
+            call("carkhy", &[], "!info", start),
+            Dispatch::Dropped
+        ));
+        assert!(matches!(
+            call("viewer", &[], "!info", start),
+            Dispatch::Handled(Some(_))
+        ));
+        for _ in 0..6 {
+            let dispatch = call("moderator", &[Badge::Moderator], "!info", start);
+            assert!(matches!(dispatch, Dispatch::Handled(Some(_))));
+        }
+        let next_minute = start + Duration::from_secs(60);
+        assert!(matches!(
+            call("carkhy", &[], "!info", next_minute),
+            Dispatch::Handled(Some(_))
+        ));
+    }
+}
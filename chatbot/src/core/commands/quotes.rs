@@ -0,0 +1,320 @@
+use super::{Args, Command, Context};
+use crate::{
+    connect::UserLevel,
+    core::{calendar::Date, ChatBotCommand},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    rc::Rc,
+    time::SystemTime,
+};
+
+// saved as quotes.json in the storage directory
+const STORAGE_NAME: &str = "quotes";
+
+// more matches than this are only counted
+const LISTED_MATCHES: usize = 10;
+
+/// Something said on stream, kept with who added it and when.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Quote {
+    pub text: String,
+    pub added_by: String,
+    // "2026-10-14" in UTC
+    pub date: String,
+    // the game streamed at the time, once it can be asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game: Option<String>,
+}
+
+// "#42: "so it begins" (added by Carkhy on 2026-10-14)"
+struct Numbered<'a>(u64, &'a Quote);
+
+impl fmt::Display for Numbered<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Numbered(id, quote) = self;
+        write!(
+            f,
+            "#{}: \"{}\" (added by {} on {}",
+            id, quote.text, quote.added_by, quote.date
+        )?;
+        if let Some(game) = &quote.game {
+            write!(f, ", playing {}", game)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The quotes of one channel. Ids are never given out twice, even after deleting a quote.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct QuoteBook {
+    next_id: u64,
+    quotes: BTreeMap<u64, Quote>,
+}
+
+/// The quotes of every channel, saved again after each change.
+#[derive(Debug, Default)]
+pub struct Quotes {
+    storage: Storage,
+    // by channel name without '#'
+    books: HashMap<String, QuoteBook>,
+}
+
+pub type SharedQuotes = Rc<RefCell<Quotes>>;
+
+impl Quotes {
+    /// With the quotes saved before.
+    pub fn load(storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            books: storage.load(STORAGE_NAME)?,
+            storage,
+        })
+    }
+
+    /// The id of the new quote.
+    pub fn add(&mut self, channel: &str, quote: Quote) -> u64 {
+        let book = self.books.entry(channel.to_owned()).or_default();
+        book.next_id += 1;
+        book.quotes.insert(book.next_id, quote);
+        let id = book.next_id;
+        self.save();
+        id
+    }
+
+    pub fn remove(&mut self, channel: &str, id: u64) -> Option<Quote> {
+        let quote = self.books.get_mut(channel)?.quotes.remove(&id)?;
+        self.save();
+        Some(quote)
+    }
+
+    pub fn get(&self, channel: &str, id: u64) -> Option<&Quote> {
+        self.books.get(channel)?.quotes.get(&id)
+    }
+
+    /// Another text for the quote, the rest stays.
+    pub fn edit(&mut self, channel: &str, id: u64, text: &str) -> Option<&Quote> {
+        let quote = self.books.get_mut(channel)?.quotes.get_mut(&id)?;
+        quote.text = text.to_owned();
+        self.save();
+        self.get(channel, id)
+    }
+
+    /// By id, oldest first.
+    pub fn list(&self, channel: &str) -> Vec<(u64, &Quote)> {
+        let Some(book) = self.books.get(channel) else {
+            return Vec::new();
+        };
+        book.quotes.iter().map(|(id, quote)| (*id, quote)).collect()
+    }
+
+    pub fn random(&self, channel: &str) -> Option<(u64, &Quote)> {
+        let quotes = &self.books.get(channel)?.quotes;
+        if quotes.is_empty() {
+            return None;
+        }
+        let (id, quote) = quotes.iter().nth(fastrand::usize(..quotes.len()))?;
+        Some((*id, quote))
+    }
+
+    /// The ids of the quotes containing the word, ignoring case.
+    pub fn search(&self, channel: &str, word: &str) -> Vec<u64> {
+        let word = word.to_lowercase();
+        let Some(book) = self.books.get(channel) else {
+            return Vec::new();
+        };
+        book.quotes
+            .iter()
+            .filter(|(_, quote)| quote.text.to_lowercase().contains(&word))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    // the change is kept until the bot stops, even if the file can't be written
+    fn save(&self) {
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.books) {
+            tracing::warn!(%error, "could not save the quotes");
+        }
+    }
+}
+
+/// `!quote` answers with a random quote, `!quote 42` with that one and `!quote search word`
+/// with the quotes containing the word. As a reply it repeats the parent message instead.
+pub struct QuoteCommand(pub SharedQuotes);
+
+impl Command for QuoteCommand {
+    fn name(&self) -> &'static str {
+        "quote"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[<number>|search <word>]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        // as a reply it repeats the replied message
+        if let Some(replied) = ctx
+            .replied_message()
+            .filter(|_| args.clone().rest().is_none())
+        {
+            let parent = &replied.message;
+            return ctx.send(format!(
+                "\"{}\" - {}",
+                parent.text,
+                parent.user.display_name()
+            ));
+        }
+        let quotes = self.0.borrow();
+        let channel = &ctx.message.channel;
+        ctx.send(match (args.next(), args.rest()) {
+            (None, _) => match quotes.random(channel) {
+                Some((id, quote)) => Numbered(id, quote).to_string(),
+                None => format!(
+                    "There are no quotes yet, add one with {}addquote.",
+                    ctx.prefix
+                ),
+            },
+            (Some("search"), Some(word)) => match &quotes.search(channel, word)[..] {
+                [] => format!("No quote contains \"{}\".", word),
+                [id] => Numbered(*id, quotes.get(channel, *id)?).to_string(),
+                ids => {
+                    let listed: Vec<_> = ids
+                        .iter()
+                        .take(LISTED_MATCHES)
+                        .map(|id| format!("#{}", id))
+                        .collect();
+                    format!(
+                        "{} quotes contain \"{}\": {}",
+                        ids.len(),
+                        word,
+                        listed.join(", ")
+                    )
+                }
+            },
+            (Some(id), None) => match id.trim_start_matches('#').parse() {
+                Ok(id) => match quotes.get(channel, id) {
+                    Some(quote) => Numbered(id, quote).to_string(),
+                    None => format!("There is no quote #{}.", id),
+                },
+                Err(_) => format!(
+                    "Usage: {0}quote, {0}quote 42 or {0}quote search word",
+                    ctx.prefix
+                ),
+            },
+            _ => format!(
+                "Usage: {0}quote, {0}quote 42 or {0}quote search word",
+                ctx.prefix
+            ),
+        })
+    }
+}
+
+/// `!addquote text` adds the text, as a reply without a text it adds the parent message.
+pub struct AddQuote(pub SharedQuotes);
+
+impl Command for AddQuote {
+    fn name(&self) -> &'static str {
+        "addquote"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<text>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let parent = ctx.replied_message();
+        let (text, said) = match (args.rest(), parent) {
+            (Some(text), _) => (text, ctx.message.timestamp),
+            // dated when the parent was said, if the bot saw it
+            (None, Some(parent)) if parent.is_complete() => {
+                (parent.message.text.as_str(), parent.message.timestamp)
+            }
+            (None, Some(parent)) => (parent.message.text.as_str(), ctx.message.timestamp),
+            (None, None) => return ctx.send(format!("Usage: {}addquote text", ctx.prefix)),
+        };
+        let quote = Quote {
+            text: text.to_owned(),
+            added_by: ctx.message.user.display_name().to_owned(),
+            date: Date::of(said.unwrap_or_else(SystemTime::now)).to_string(),
+            game: None,
+        };
+        let id = self.0.borrow_mut().add(&ctx.message.channel, quote);
+        ctx.send(format!("Added quote #{}.", id))
+    }
+}
+
+pub struct DelQuote(pub SharedQuotes);
+
+impl Command for DelQuote {
+    fn name(&self) -> &'static str {
+        "delquote"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<number>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(id) = args
+            .next()
+            .and_then(|id| id.trim_start_matches('#').parse().ok())
+        else {
+            return ctx.send(format!("Usage: {}delquote 42", ctx.prefix));
+        };
+        let removed = self.0.borrow_mut().remove(&ctx.message.channel, id);
+        ctx.send(match removed {
+            Some(_) => format!("Removed quote #{}.", id),
+            None => format!("There is no quote #{}.", id),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(text: &str) -> Quote {
+        Quote {
+            text: text.to_owned(),
+            added_by: "Carkhy".to_owned(),
+            date: "2026-10-14".to_owned(),
+            game: None,
+        }
+    }
+
+    #[test]
+    fn ids_stay_after_deleting() {
+        let storage = Storage::memory();
+        let mut quotes = Quotes::load(storage.clone()).unwrap();
+        for text in ["one", "two", "three"] {
+            quotes.add("captaincallback", quote(text));
+        }
+        assert!(quotes.remove("captaincallback", 2).is_some());
+        assert!(quotes.remove("captaincallback", 2).is_none());
+        assert_eq!(quotes.add("captaincallback", quote("four")), 4);
+
+        let mut quotes = Quotes::load(storage.clone()).unwrap();
+        assert_eq!(quotes.get("captaincallback", 3), Some(&quote("three")));
+        assert_eq!(quotes.search("captaincallback", "O"), vec![1, 4]);
+        assert!(quotes.edit("carkhy", 3, "three!").is_none());
+        assert!(quotes.edit("captaincallback", 3, "three!").is_some());
+        let ids: Vec<u64> = quotes
+            .list("captaincallback")
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert!(quotes.random("carkhy").is_none());
+    }
+}
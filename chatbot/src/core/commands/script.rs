@@ -0,0 +1,781 @@
+//! Scripts for custom commands, when a template isn't enough, e.g.
+//!
+//! ```text
+//! let hugs = get("hugs", 0) + 1;
+//! set("hugs", hugs);
+//! if arg(1) == "" { send(user + " hugged chat " + hugs + " times"); }
+//! else { send(user + " hugs " + arg(1)); }
+//! ```
+//!
+//! A script only reaches what it is given: the caller, the arguments, random numbers, the
+//! values of its command and `send`, whose messages go through the queue like any answer.
+//! It runs at most `MAX_STEPS` steps and `MAX_TIME`, so a loop that never ends stops.
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+// a step is a statement run or an expression evaluated
+const MAX_STEPS: usize = 10_000;
+const MAX_TIME: Duration = Duration::from_millis(50);
+// a text can't double its way to all memory in a loop
+const MAX_TEXT: usize = 2000;
+const MAX_MESSAGES: usize = 3;
+const MAX_VALUES: usize = 100;
+
+/// Why a script is refused, or stopped while it runs; shown to the moderator.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScriptError {
+    #[error("{found} at character {at} was not expected")]
+    Unexpected { found: String, at: usize },
+    #[error("the script ends in the middle")]
+    End,
+    #[error("the text at character {0} is never closed")]
+    Unclosed(usize),
+    #[error("{0} is not defined, it needs a let first")]
+    Undefined(String),
+    #[error("there is no function {0}")]
+    UnknownFunction(String),
+    #[error("{name} takes {expected}")]
+    Arguments {
+        name: String,
+        expected: &'static str,
+    },
+    #[error("{operation} doesn't work with {left} and {right}")]
+    Types {
+        operation: &'static str,
+        left: &'static str,
+        right: &'static str,
+    },
+    #[error("a number got too big, or was divided by zero")]
+    Arithmetic,
+    #[error("the script took more than {MAX_STEPS} steps")]
+    Steps,
+    #[error("the script took longer than {}ms", MAX_TIME.as_millis())]
+    Time,
+    #[error("a text got longer than {MAX_TEXT} characters")]
+    TooLong,
+    #[error("the script sends more than {MAX_MESSAGES} messages")]
+    Messages,
+    #[error("a command keeps at most {MAX_VALUES} values")]
+    Values,
+}
+
+/// What a script works with, and what its command keeps between calls.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Number(i64),
+    Text(String),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "true or false",
+            Value::Number(_) => "a number",
+            Value::Text(_) => "a text",
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            Value::Number(number) => *number != 0,
+            Value::Text(text) => !text.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Text(String),
+    Name(String),
+    // operators and punctuation
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ";", ",", "=", "<", ">", "+", "-", "*",
+    "/", "%", "!",
+];
+
+// each token with the character it starts at
+fn tokens(source: &str) -> Result<Vec<(Token, usize)>, ScriptError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            let number = digits.parse().map_err(|_| ScriptError::Arithmetic)?;
+            tokens.push((Token::Number(number), start));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Name(chars[start..i].iter().collect()), start));
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(ScriptError::Unclosed(start)),
+                    Some('"') => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&c) => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push((Token::Text(text), start));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| rest.starts_with(symbol))
+                .ok_or_else(|| ScriptError::Unexpected {
+                    found: c.to_string(),
+                    at: start,
+                })?;
+            i += symbol.len();
+            tokens.push((Token::Symbol(symbol), start));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Value(Value),
+    Variable(String),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Negative(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Statement>, Vec<Statement>),
+    While(Expr, Vec<Statement>),
+    Return,
+    Expr(Expr),
+}
+
+// the operators from the loosest to the tightest
+const PRECEDENCE: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn take(&mut self) -> Result<Token, ScriptError> {
+        let (token, _) = self.tokens.get(self.next).ok_or(ScriptError::End)?;
+        self.next += 1;
+        Ok(token.clone())
+    }
+
+    fn unexpected(&self) -> ScriptError {
+        match self.tokens.get(self.next) {
+            Some((token, at)) => ScriptError::Unexpected {
+                found: match token {
+                    Token::Number(number) => number.to_string(),
+                    Token::Text(text) => format!("\"{}\"", text),
+                    Token::Name(name) => name.clone(),
+                    Token::Symbol(symbol) => symbol.to_string(),
+                },
+                at: *at,
+            },
+            None => ScriptError::End,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ScriptError> {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.next += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name == keyword)
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, ScriptError> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.symbol("}") {
+            if self.peek().is_none() {
+                return Err(ScriptError::End);
+            }
+            statements.push(self.statement()?);
+        }
+        self.expect("}")?;
+        Ok(statements)
+    }
+
+    fn symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn statement(&mut self) -> Result<Statement, ScriptError> {
+        let statement = if self.keyword("let") {
+            self.next += 1;
+            let Token::Name(name) = self.take()? else {
+                self.next -= 1;
+                return Err(self.unexpected());
+            };
+            self.expect("=")?;
+            Statement::Let(name, self.expression(0)?)
+        } else if self.keyword("if") {
+            return self.condition();
+        } else if self.keyword("while") {
+            self.next += 1;
+            let condition = self.expression(0)?;
+            return Ok(Statement::While(condition, self.block()?));
+        } else if self.keyword("return") {
+            self.next += 1;
+            Statement::Return
+        } else if matches!(
+            self.tokens.get(self.next + 1),
+            Some((Token::Symbol("="), _))
+        ) {
+            let Token::Name(name) = self.take()? else {
+                self.next -= 1;
+                return Err(self.unexpected());
+            };
+            self.next += 1;
+            Statement::Assign(name, self.expression(0)?)
+        } else {
+            Statement::Expr(self.expression(0)?)
+        };
+        // the semicolon may be left out
+        if self.symbol(";") {
+            self.next += 1;
+        }
+        Ok(statement)
+    }
+
+    fn condition(&mut self) -> Result<Statement, ScriptError> {
+        self.next += 1;
+        let condition = self.expression(0)?;
+        let then = self.block()?;
+        let otherwise = match self.keyword("else") {
+            false => Vec::new(),
+            true => {
+                self.next += 1;
+                match self.keyword("if") {
+                    true => vec![self.condition()?],
+                    false => self.block()?,
+                }
+            }
+        };
+        Ok(Statement::If(condition, then, otherwise))
+    }
+
+    fn expression(&mut self, level: usize) -> Result<Expr, ScriptError> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.expression(level + 1)?;
+        while let Some(Token::Symbol(symbol)) = self.peek() {
+            let Some(operator) = operators.iter().find(|operator| *operator == symbol) else {
+                break;
+            };
+            self.next += 1;
+            let right = self.expression(level + 1)?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScriptError> {
+        if self.symbol("!") {
+            self.next += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.symbol("-") {
+            self.next += 1;
+            return Ok(Expr::Negative(Box::new(self.unary()?)));
+        }
+        match self.take()? {
+            Token::Number(number) => Ok(Expr::Value(Value::Number(number))),
+            Token::Text(text) => Ok(Expr::Value(Value::Text(text))),
+            Token::Name(name) if name == "true" || name == "false" => {
+                Ok(Expr::Value(Value::Bool(name == "true")))
+            }
+            Token::Name(name) if self.symbol("(") => {
+                self.next += 1;
+                let mut args = Vec::new();
+                while !self.symbol(")") {
+                    args.push(self.expression(0)?);
+                    if !self.symbol(")") {
+                        self.expect(",")?;
+                    }
+                }
+                self.next += 1;
+                Ok(Expr::Call(name, args))
+            }
+            Token::Name(name) => Ok(Expr::Variable(name)),
+            Token::Symbol("(") => {
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Token::Symbol(_) => {
+                self.next -= 1;
+                Err(self.unexpected())
+            }
+        }
+    }
+}
+
+/// A parsed script, ready to run as often as its command is called.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    statements: Vec<Statement>,
+}
+
+/// What a script may use of the call and of the bot.
+pub struct Host<'a> {
+    // display name
+    pub user: &'a str,
+    pub login: &'a str,
+    pub channel: &'a str,
+    pub moderator: bool,
+    pub args: Vec<&'a str>,
+    // the values of this command in this channel, nobody else's
+    pub values: &'a mut BTreeMap<String, Value>,
+}
+
+// a script returning early isn't an error
+enum Stop {
+    Return,
+    Error(ScriptError),
+}
+
+impl From<ScriptError> for Stop {
+    fn from(error: ScriptError) -> Self {
+        Stop::Error(error)
+    }
+}
+
+struct Run<'h, 'a> {
+    host: &'h mut Host<'a>,
+    variables: HashMap<String, Value>,
+    sent: Vec<String>,
+    steps: usize,
+    started: Instant,
+}
+
+impl Run<'_, '_> {
+    fn step(&mut self) -> Result<(), ScriptError> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(ScriptError::Steps);
+        }
+        if self.steps.is_multiple_of(100) && self.started.elapsed() > MAX_TIME {
+            return Err(ScriptError::Time);
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, statements: &[Statement]) -> Result<(), Stop> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), Stop> {
+        self.step()?;
+        match statement {
+            Statement::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.variables.insert(name.clone(), value);
+            }
+            Statement::Assign(name, expr) => {
+                let value = self.eval(expr)?;
+                match self.variables.get_mut(name) {
+                    Some(variable) => *variable = value,
+                    None => return Err(ScriptError::Undefined(name.clone()).into()),
+                }
+            }
+            Statement::If(condition, then, otherwise) => match self.eval(condition)?.truthy() {
+                true => self.block(then)?,
+                false => self.block(otherwise)?,
+            },
+            Statement::While(condition, body) => {
+                while self.eval(condition)?.truthy() {
+                    self.block(body)?;
+                }
+            }
+            Statement::Return => return Err(Stop::Return),
+            Statement::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn given(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "user" => Value::Text(self.host.user.to_owned()),
+            "login" => Value::Text(self.host.login.to_owned()),
+            "channel" => Value::Text(self.host.channel.to_owned()),
+            "moderator" => Value::Bool(self.host.moderator),
+            "args" => Value::Text(self.host.args.join(" ")),
+            _ => return None,
+        })
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, ScriptError> {
+        self.step()?;
+        match expr {
+            Expr::Value(value) => Ok(value.clone()),
+            Expr::Variable(name) => self
+                .variables
+                .get(name)
+                .cloned()
+                .or_else(|| self.given(name))
+                .ok_or_else(|| ScriptError::Undefined(name.clone())),
+            Expr::Not(inner) => Ok(Value::Bool(!self.eval(inner)?.truthy())),
+            Expr::Negative(inner) => match self.eval(inner)? {
+                Value::Number(number) => number
+                    .checked_neg()
+                    .map(Value::Number)
+                    .ok_or(ScriptError::Arithmetic),
+                value => Err(ScriptError::Types {
+                    operation: "-",
+                    left: value.kind(),
+                    right: value.kind(),
+                }),
+            },
+            // the right side is only evaluated when it matters
+            Expr::Binary("&&", left, right) => Ok(Value::Bool(
+                self.eval(left)?.truthy() && self.eval(right)?.truthy(),
+            )),
+            Expr::Binary("||", left, right) => Ok(Value::Bool(
+                self.eval(left)?.truthy() || self.eval(right)?.truthy(),
+            )),
+            Expr::Binary(operation, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(operation, left, right)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, args)
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, ScriptError> {
+        let wrong = |expected| ScriptError::Arguments {
+            name: name.to_owned(),
+            expected,
+        };
+        match (name, args.as_slice()) {
+            ("send", [text]) => {
+                if self.sent.len() == MAX_MESSAGES {
+                    return Err(ScriptError::Messages);
+                }
+                self.sent.push(text.to_string());
+                Ok(Value::Bool(true))
+            }
+            ("send", _) => Err(wrong("one text")),
+            ("arg", [Value::Number(n)]) => Ok(Value::Text(
+                usize::try_from(*n)
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|n| self.host.args.get(n))
+                    .unwrap_or(&"")
+                    .to_string(),
+            )),
+            ("arg", _) => Err(wrong("the number of the argument, the first is 1")),
+            ("count", []) => Ok(Value::Number(self.host.args.len() as i64)),
+            ("count", _) => Err(wrong("nothing")),
+            ("random", [Value::Number(low), Value::Number(high)]) if low <= high => {
+                Ok(Value::Number(fastrand::i64(*low..=*high)))
+            }
+            ("random", _) => Err(wrong("two numbers, the smaller first")),
+            // 0 for anything else, is_number tells them apart
+            ("number", [value]) => Ok(Value::Number(number(value).unwrap_or(0))),
+            ("number", _) => Err(wrong("one value")),
+            ("is_number", [value]) => Ok(Value::Bool(number(value).is_some())),
+            ("is_number", _) => Err(wrong("one value")),
+            ("length", [value]) => Ok(Value::Number(value.to_string().chars().count() as i64)),
+            ("length", _) => Err(wrong("one value")),
+            ("get", [Value::Text(key)]) => Ok(self
+                .host
+                .values
+                .get(key)
+                .cloned()
+                .unwrap_or(Value::Bool(false))),
+            ("get", [Value::Text(key), default]) => Ok(self
+                .host
+                .values
+                .get(key)
+                .cloned()
+                .unwrap_or_else(|| default.clone())),
+            ("get", _) => Err(wrong("a key, and what it is without a value")),
+            ("set", [Value::Text(key), value]) => {
+                let values = &mut self.host.values;
+                if !values.contains_key(key) && values.len() == MAX_VALUES {
+                    return Err(ScriptError::Values);
+                }
+                values.insert(key.clone(), value.clone());
+                Ok(value.clone())
+            }
+            ("set", _) => Err(wrong("a key and a value")),
+            _ => Err(ScriptError::UnknownFunction(name.to_owned())),
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => Some(*number),
+        value => value.to_string().trim().parse().ok(),
+    }
+}
+
+fn binary(operation: &'static str, left: Value, right: Value) -> Result<Value, ScriptError> {
+    use Value::*;
+    let value = match (operation, &left, &right) {
+        ("==", left, right) => Bool(left == right),
+        ("!=", left, right) => Bool(left != right),
+        // a text and anything else are joined, like "hug number " + 3
+        ("+", Text(_), _) | ("+", _, Text(_)) => {
+            let text = format!("{}{}", left, right);
+            if text.chars().count() > MAX_TEXT {
+                return Err(ScriptError::TooLong);
+            }
+            Text(text)
+        }
+        (_, Number(a), Number(b)) => {
+            let (a, b) = (*a, *b);
+            match operation {
+                "<" => Bool(a < b),
+                "<=" => Bool(a <= b),
+                ">" => Bool(a > b),
+                ">=" => Bool(a >= b),
+                _ => Number(
+                    match operation {
+                        "+" => a.checked_add(b),
+                        "-" => a.checked_sub(b),
+                        "*" => a.checked_mul(b),
+                        "/" => a.checked_div(b),
+                        _ => a.checked_rem(b),
+                    }
+                    .ok_or(ScriptError::Arithmetic)?,
+                ),
+            }
+        }
+        (_, Text(a), Text(b)) if matches!(operation, "<" | "<=" | ">" | ">=") => {
+            Bool(match operation {
+                "<" => a < b,
+                "<=" => a <= b,
+                ">" => a > b,
+                _ => a >= b,
+            })
+        }
+        _ => {
+            return Err(ScriptError::Types {
+                operation,
+                left: left.kind(),
+                right: right.kind(),
+            })
+        }
+    };
+    Ok(value)
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self, ScriptError> {
+        let mut parser = Parser {
+            tokens: tokens(source)?,
+            next: 0,
+        };
+        let mut statements = Vec::new();
+        while parser.peek().is_some() {
+            statements.push(parser.statement()?);
+        }
+        Ok(Self { statements })
+    }
+
+    /// The messages the script sent. The values it set before an error are kept.
+    pub fn run(&self, host: &mut Host) -> Result<Vec<String>, ScriptError> {
+        let mut run = Run {
+            host,
+            variables: HashMap::new(),
+            sent: Vec::new(),
+            steps: 0,
+            started: Instant::now(),
+        };
+        match run.block(&self.statements) {
+            Ok(()) | Err(Stop::Return) => Ok(run.sent),
+            Err(Stop::Error(error)) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        source: &str,
+        args: &[&str],
+        values: &mut BTreeMap<String, Value>,
+    ) -> Result<Vec<String>, ScriptError> {
+        let mut host = Host {
+            user: "Carkhy",
+            login: "carkhy",
+            channel: "captaincallback",
+            moderator: false,
+            args: args.to_vec(),
+            values,
+        };
+        Script::parse(source)?.run(&mut host)
+    }
+
+    #[test]
+    fn scripts_branch_on_their_arguments() {
+        let source = r#"
+            let hugs = get("hugs", 0) + 1
+            set("hugs", hugs)
+            if arg(1) == "" { send(user + " hugged chat " + hugs + " times") }
+            else if number(arg(1)) > 100 { send("That is too many hugs!") }
+            else { send(user + " hugs " + args) }
+        "#;
+        let mut values = BTreeMap::new();
+        assert_eq!(
+            run(source, &[], &mut values).unwrap(),
+            ["Carkhy hugged chat 1 times"]
+        );
+        assert_eq!(
+            run(source, &["500"], &mut values).unwrap(),
+            ["That is too many hugs!"]
+        );
+        assert_eq!(
+            run(source, &["@tenaciousbyte", "twice"], &mut values).unwrap(),
+            ["Carkhy hugs @tenaciousbyte twice"]
+        );
+        assert_eq!(values.get("hugs"), Some(&Value::Number(3)));
+        assert_eq!(
+            run(
+                "if is_number(arg(1)) { send(number(arg(1)) * 2) }",
+                &["21"],
+                &mut values
+            )
+            .unwrap(),
+            ["42"]
+        );
+        let roll = run(
+            "let n = random(1, 6)\nif n >= 1 && n <= 6 { send(\"ok\") }",
+            &[],
+            &mut values,
+        );
+        assert_eq!(roll.unwrap(), ["ok"]);
+    }
+
+    #[test]
+    fn scripts_stop_within_their_budget() {
+        let mut values = BTreeMap::new();
+        assert_eq!(
+            run("while true { }", &[], &mut values),
+            Err(ScriptError::Steps)
+        );
+        assert_eq!(
+            run(
+                "let s = \"hug\"\nwhile true { s = s + s }",
+                &[],
+                &mut values
+            ),
+            Err(ScriptError::TooLong)
+        );
+        assert_eq!(
+            run(
+                "let i = 0\nwhile i < 5 { send(i) i = i + 1 }",
+                &[],
+                &mut values
+            ),
+            Err(ScriptError::Messages)
+        );
+        assert_eq!(
+            run(
+                "let i = 0\nwhile true { set(\"key\" + i, i) i = i + 1 }",
+                &[],
+                &mut values
+            ),
+            Err(ScriptError::Values)
+        );
+        // what was set before the error stays
+        assert_eq!(values.len(), MAX_VALUES);
+        assert_eq!(
+            run("send(1 / 0)", &[], &mut values),
+            Err(ScriptError::Arithmetic)
+        );
+        assert_eq!(Script::parse("send(\"hi\"").unwrap_err(), ScriptError::End);
+        assert_eq!(
+            Script::parse("let = 3").unwrap_err(),
+            ScriptError::Unexpected {
+                found: "=".to_owned(),
+                at: 4
+            }
+        );
+        assert_eq!(
+            run("x = 3", &[], &mut values),
+            Err(ScriptError::Undefined("x".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use super::{Args, Command, Context};
+use crate::{
+    connect::UserLevel,
+    core::{calendar::Date, ChatBotCommand},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+const STORAGE_NAME: &str = "command_stats";
+// the counts are written at most this often, not for each call
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+// older days are forgotten
+pub const KEPT_DAYS: usize = 30;
+
+/// How a call of a command ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Succeeded,
+    // called again too soon in the channel
+    Cooldown,
+    // below the command's level
+    Denied,
+}
+
+/// The calls of a command on one day, or summed over the days.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub succeeded: u64,
+    pub cooldown: u64,
+    pub denied: u64,
+    // the calls by lowercase login
+    pub users: BTreeMap<String, u64>,
+}
+
+impl Tally {
+    pub fn calls(&self) -> u64 {
+        self.succeeded + self.cooldown + self.denied
+    }
+
+    fn add(&mut self, other: &Tally) {
+        self.succeeded += other.succeeded;
+        self.cooldown += other.cooldown;
+        self.denied += other.denied;
+        for (login, calls) in &other.users {
+            *self.users.entry(login.clone()).or_default() += calls;
+        }
+    }
+}
+
+// by day in UTC like "2026-10-14", then by channel and command
+type Days = BTreeMap<String, BTreeMap<String, BTreeMap<String, Tally>>>;
+
+/// How often each command was called in each channel per day, kept in the storage.
+#[derive(Debug, Default)]
+pub struct CommandStats {
+    storage: Storage,
+    days: Days,
+    // counts not saved yet, and when they were last saved
+    unsaved: bool,
+    flushed: Option<SystemTime>,
+}
+
+pub type SharedCommandStats = Rc<RefCell<CommandStats>>;
+
+impl CommandStats {
+    pub fn load(storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            days: storage.load(STORAGE_NAME)?,
+            storage,
+            ..Default::default()
+        })
+    }
+
+    /// Counts the call of the command, without the prefix, the counts are saved with it if the
+    /// last save was a while ago.
+    pub fn record(
+        &mut self,
+        channel: &str,
+        command: &str,
+        login: &str,
+        outcome: Outcome,
+        now: SystemTime,
+    ) {
+        let day = Date::of(now).to_string();
+        if !self.days.contains_key(&day) {
+            while self.days.len() >= KEPT_DAYS {
+                self.days.pop_first();
+            }
+        }
+        let tally = self
+            .days
+            .entry(day)
+            .or_default()
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(command.to_lowercase())
+            .or_default();
+        match outcome {
+            Outcome::Succeeded => tally.succeeded += 1,
+            Outcome::Cooldown => tally.cooldown += 1,
+            Outcome::Denied => tally.denied += 1,
+        }
+        *tally.users.entry(login.to_lowercase()).or_default() += 1;
+        self.unsaved = true;
+        if self
+            .flushed
+            .is_none_or(|flushed| now >= flushed + FLUSH_INTERVAL)
+        {
+            self.flushed = Some(now);
+            self.flush();
+        }
+    }
+
+    /// Writes what was counted since the last save, the bot does so when it stops.
+    pub fn flush(&mut self) {
+        if !std::mem::take(&mut self.unsaved) {
+            return;
+        }
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.days) {
+            tracing::warn!(%error, "could not save the command stats");
+        }
+    }
+
+    /// The days the command was called in the channel, oldest first.
+    pub fn days(&self, channel: &str, command: &str) -> Vec<(&str, &Tally)> {
+        let command = command.to_lowercase();
+        self.days
+            .iter()
+            .filter_map(|(day, channels)| {
+                let tally = channels.get(channel)?.get(&command)?;
+                Some((day.as_str(), tally))
+            })
+            .collect()
+    }
+
+    /// The calls of each day, channel and command, in that order.
+    pub fn each(&self) -> impl Iterator<Item = (&str, &str, &str, &Tally)> {
+        self.days.iter().flat_map(|(day, channels)| {
+            channels.iter().flat_map(move |(channel, commands)| {
+                commands.iter().map(move |(command, tally)| {
+                    (day.as_str(), channel.as_str(), command.as_str(), tally)
+                })
+            })
+        })
+    }
+
+    /// The calls of every command in the channel over the kept days, by name.
+    pub fn totals(&self, channel: &str) -> BTreeMap<&str, Tally> {
+        let mut totals: BTreeMap<&str, Tally> = BTreeMap::new();
+        let channels = self
+            .days
+            .values()
+            .filter_map(|channels| channels.get(channel));
+        for (command, tally) in channels.flatten() {
+            totals.entry(command.as_str()).or_default().add(tally);
+        }
+        totals
+    }
+}
+
+/// `!cmdstats <command>` tells moderators how often the command was called, today and over
+/// the kept days.
+pub struct CmdStats(pub SharedCommandStats);
+
+impl Command for CmdStats {
+    fn name(&self) -> &'static str {
+        "cmdstats"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<command>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(name) = args.next() else {
+            return ctx.send(format!("Usage: {}cmdstats <command>", ctx.prefix));
+        };
+        let name = name.strip_prefix(ctx.prefix).unwrap_or(name).to_lowercase();
+        let stats = self.0.borrow();
+        let days = stats.days(&ctx.message.channel, &name);
+        if days.is_empty() {
+            return ctx.send(format!(
+                "{}{} wasn't called in the last {} days.",
+                ctx.prefix, name, KEPT_DAYS
+            ));
+        }
+        let today = Date::of(SystemTime::now()).to_string();
+        let mut total = Tally::default();
+        days.iter().for_each(|(_, tally)| total.add(tally));
+        let today = days
+            .iter()
+            .find(|(day, _)| *day == today)
+            .map_or(0, |(_, tally)| tally.calls());
+        ctx.send(format!(
+            "{}{} was called {} times by {} users in the last {} days, {} today: {} succeeded, {} on cooldown, {} denied.",
+            ctx.prefix,
+            name,
+            total.calls(),
+            total.users.len(),
+            KEPT_DAYS,
+            today,
+            total.succeeded,
+            total.cooldown,
+            total.denied
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn calls_are_counted_by_day_and_outcome() {
+        let mut stats = CommandStats::default();
+        let monday = UNIX_EPOCH + 20_000 * DAY;
+        stats.record("carkhy", "points", "Viewer", Outcome::Succeeded, monday);
+        stats.record("carkhy", "points", "viewer", Outcome::Cooldown, monday);
+        stats.record(
+            "carkhy",
+            "Points",
+            "carkhy",
+            Outcome::Succeeded,
+            monday + DAY,
+        );
+        stats.record("carkhy", "give", "viewer", Outcome::Denied, monday + DAY);
+        stats.record("rustlang", "points", "viewer", Outcome::Succeeded, monday);
+        let days = stats.days("carkhy", "points");
+        assert_eq!(days.len(), 2);
+        assert_eq!((days[0].1.succeeded, days[0].1.cooldown), (1, 1));
+        assert_eq!(days[0].1.users["viewer"], 2);
+        let totals = stats.totals("carkhy");
+        assert_eq!(totals["points"].calls(), 3);
+        assert_eq!(totals["points"].users.len(), 2);
+        assert_eq!(totals["give"].denied, 1);
+    }
+
+    #[test]
+    fn only_the_latest_days_are_kept() {
+        let mut stats = CommandStats::default();
+        let start = UNIX_EPOCH + 20_000 * DAY;
+        for day in 0..=KEPT_DAYS as u32 {
+            stats.record(
+                "carkhy",
+                "points",
+                "viewer",
+                Outcome::Succeeded,
+                start + day * DAY,
+            );
+        }
+        let days = stats.days("carkhy", "points");
+        assert_eq!(days.len(), KEPT_DAYS);
+        assert_eq!(days[0].0, Date::of(start + DAY).to_string());
+    }
+}
@@ -0,0 +1,247 @@
+use super::Args;
+use thiserror::Error;
+
+/// Why a response can't be a template, shown to the moderator saving it.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TemplateError {
+    #[error("$( at character {0} is never closed")]
+    Unclosed(usize),
+    #[error("$( at character {0} is inside another variable")]
+    Nested(usize),
+    #[error("$() at character {0} names no variable")]
+    Empty(usize),
+    #[error("$(random) at character {0} needs two whole numbers, the smaller first")]
+    Random(usize),
+    #[error("$(arg0) at character {0}, arguments are counted from 1")]
+    ArgZero(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Variable {
+    User,
+    Channel,
+    Args,
+    // the first argument is 1
+    Arg(usize),
+    Count,
+    // of a counter
+    Value,
+    Random(i64, i64),
+    ToUser,
+    // rendered empty, the response still works
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Variable(Variable),
+}
+
+/// A response with variables like `$(user)`, filled in each time the command is called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+/// What the variables are filled in with.
+pub struct Values<'a> {
+    // display name of the user calling the command
+    pub user: &'a str,
+    pub channel: &'a str,
+    pub args: Args<'a>,
+    // how often the command was called, this call included
+    pub count: u64,
+    // None unless the command is a counter
+    pub value: Option<u64>,
+    // what an event fills in, e.g. ("from", ..) for $(from) in a raid's welcome
+    pub event: &'a [(&'a str, String)],
+}
+
+// the offsets in errors count characters, like a moderator would
+fn position(text: &str, index: usize) -> usize {
+    text[..index].chars().count()
+}
+
+fn variable(name: &str, at: usize) -> Result<Variable, TemplateError> {
+    let mut words = name.split_whitespace();
+    let variable = match words.next().ok_or(TemplateError::Empty(at))? {
+        "user" => Variable::User,
+        "channel" => Variable::Channel,
+        "args" => Variable::Args,
+        "count" => Variable::Count,
+        "value" => Variable::Value,
+        "touser" => Variable::ToUser,
+        "random" => {
+            let mut bound = || words.next().and_then(|word| word.parse::<i64>().ok());
+            match (bound(), bound()) {
+                (Some(low), Some(high)) if low <= high => Variable::Random(low, high),
+                _ => return Err(TemplateError::Random(at)),
+            }
+        }
+        word => match word
+            .strip_prefix("arg")
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            Some(0) => return Err(TemplateError::ArgZero(at)),
+            Some(n) => Variable::Arg(n),
+            None => Variable::Unknown(name.trim().to_owned()),
+        },
+    };
+    if words.next().is_some() {
+        return Ok(Variable::Unknown(name.trim().to_owned()));
+    }
+    Ok(variable)
+}
+
+impl Template {
+    /// Text outside of `$(...)` is kept as it is.
+    pub fn parse(text: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("$(") {
+            let offset = text.len() - rest.len();
+            let at = position(text, offset + start);
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let inside = &rest[start + 2..];
+            let end = inside.find(')').ok_or(TemplateError::Unclosed(at))?;
+            if let Some(nested) = inside[..end].find("$(") {
+                let nested = offset + start + 2 + nested;
+                return Err(TemplateError::Nested(position(text, nested)));
+            }
+            parts.push(Part::Variable(variable(&inside[..end], at)?));
+            rest = &inside[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn render(&self, values: Values) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(part) => text.push_str(part),
+                Part::Variable(Variable::User) => text.push_str(values.user),
+                Part::Variable(Variable::Channel) => text.push_str(values.channel),
+                Part::Variable(Variable::Args) => {
+                    text.push_str(values.args.clone().rest().unwrap_or_default())
+                }
+                Part::Variable(Variable::Arg(n)) => {
+                    text.push_str(values.args.clone().nth(n - 1).unwrap_or_default())
+                }
+                Part::Variable(Variable::Count) => text.push_str(&values.count.to_string()),
+                Part::Variable(Variable::Value) => match values.value {
+                    Some(value) => text.push_str(&value.to_string()),
+                    None => tracing::warn!("$(value) is only filled in for counters"),
+                },
+                Part::Variable(Variable::Random(low, high)) => {
+                    text.push_str(&fastrand::i64(*low..=*high).to_string())
+                }
+                Part::Variable(Variable::ToUser) => {
+                    let user = values
+                        .args
+                        .clone()
+                        .next()
+                        .map(|arg| arg.trim_start_matches('@'));
+                    text.push_str(user.unwrap_or(values.user))
+                }
+                Part::Variable(Variable::Unknown(name)) => {
+                    match values.event.iter().find(|(variable, _)| variable == name) {
+                        Some((_, value)) => text.push_str(value),
+                        None => tracing::warn!(name, "no such variable, it is left empty"),
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+/// The configured message for an event like a raid, named in the warning when it is broken.
+/// A broken template is sent as it is, like a custom command's response.
+pub fn render_event(
+    text: &str,
+    name: &str,
+    user: &str,
+    channel: &str,
+    event: &[(&str, String)],
+) -> String {
+    let values = Values {
+        user,
+        channel,
+        args: Args::new(""),
+        count: 0,
+        value: None,
+        event,
+    };
+    match Template::parse(text) {
+        Ok(template) => template.render(values),
+        Err(error) => {
+            tracing::warn!(name, %error, "the template is sent as it is");
+            text.to_owned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, args: &str) -> String {
+        Template::parse(template).unwrap().render(Values {
+            user: "Carkhy",
+            channel: "captaincallback",
+            args: Args::new(args),
+            count: 3,
+            value: Some(7),
+            event: &[("viewers", "12".to_owned())],
+        })
+    }
+
+    #[test]
+    fn variables_are_filled_in() {
+        assert_eq!(
+            render(
+                "$(user) hugs $(touser) in $(channel) [$(count)]",
+                "@Viewer  too"
+            ),
+            "Carkhy hugs Viewer in captaincallback [3]"
+        );
+        assert_eq!(
+            render("$(arg2), $(arg1): $(args)", "a  b c"),
+            "b, a: a  b c"
+        );
+        let roll: i64 = render("$(random 1 6)", "").parse().unwrap();
+        assert!((1..=6).contains(&roll));
+        assert_eq!(render("$(random -2 -2)", ""), "-2");
+    }
+
+    #[test]
+    fn missing_args_are_empty() {
+        assert_eq!(render("[$(args)] [$(arg1)] $(touser)", ""), "[] [] Carkhy");
+        assert_eq!(render("($(arg3))", "a b"), "()");
+    }
+
+    #[test]
+    fn unknown_variables_are_empty() {
+        assert_eq!(render("a$(uptime)b$(user now)c", ""), "abc");
+        // unless an event fills them in
+        assert_eq!(render("$(viewers) viewers", ""), "12 viewers");
+        assert_eq!(render("costs $5 (or (more))", ""), "costs $5 (or (more))");
+    }
+
+    #[test]
+    fn errors_point_at_the_variable() {
+        let error = |text| Template::parse(text).unwrap_err();
+        assert_eq!(error("hi $(user"), TemplateError::Unclosed(3));
+        assert_eq!(error("ä $(user $(args))"), TemplateError::Nested(9));
+        assert_eq!(error("$( )"), TemplateError::Empty(0));
+        assert_eq!(error("$(random 6 1)"), TemplateError::Random(0));
+        assert_eq!(error("$(random one)"), TemplateError::Random(0));
+        assert_eq!(error("x$(arg0)"), TemplateError::ArgZero(1));
+    }
+}
@@ -0,0 +1,326 @@
+use super::{
+    calendar::Date,
+    commands::{Args, Command, Context},
+    ChatBotCommand,
+};
+use crate::{
+    config::EmotesConfig,
+    connect::{TextMessage, MAX_MESSAGE_CHARS},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+const STORAGE_NAME: &str = "emote_stats";
+// the counts are written at most this often, not for each message
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+// emotes listed by `!topemotes`
+const TOP: usize = 5;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Buckets {
+    // the day in UTC of the daily counts, like "2026-10-14"
+    day: String,
+    // by the emote's name as written
+    today: BTreeMap<String, u64>,
+    all: BTreeMap<String, u64>,
+    // twitch's id of each of its emotes, the configured ones have none
+    ids: BTreeMap<String, String>,
+}
+
+// each use of an emote in the message, twitch's with their id. The configured names are
+// whole words outside of twitch's emotes
+fn emotes<'a>(
+    message: &'a TextMessage,
+    extra: &HashSet<String>,
+) -> Vec<(&'a str, Option<&'a str>)> {
+    let text = &message.text;
+    let mut emotes = Vec::new();
+    let mut outside = Vec::new();
+    let mut end = 0;
+    for span in &message.emotes {
+        if let (Some(before), Some(name)) =
+            (text.get(end..span.start), text.get(span.start..span.end))
+        {
+            outside.push(before);
+            emotes.push((name, Some(span.id.as_str())));
+            end = span.end;
+        }
+    }
+    outside.push(text.get(end..).unwrap_or_default());
+    let words = outside.into_iter().flat_map(str::split_whitespace);
+    emotes.extend(
+        words
+            .filter(|word| extra.contains(*word))
+            .map(|word| (word, None)),
+    );
+    emotes
+}
+
+/// How often each emote was used in each channel, all-time and today, kept in the storage
+/// where overlays read them from.
+#[derive(Debug, Default)]
+pub struct EmoteStats {
+    storage: Storage,
+    // third-party emotes like BTTV's, twitch doesn't tell where they are
+    extra: HashSet<String>,
+    // by channel
+    channels: BTreeMap<String, Buckets>,
+    // counts not saved yet, and when they were last saved
+    unsaved: bool,
+    flushed: Option<SystemTime>,
+}
+
+pub type SharedEmoteStats = Rc<RefCell<EmoteStats>>;
+
+impl EmoteStats {
+    pub fn load(config: &EmotesConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            channels: storage.load(STORAGE_NAME)?,
+            storage,
+            extra: config.extra_names.iter().cloned().collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Counts the emotes of the message, they are saved with it if the last save was a while ago.
+    pub fn count(&mut self, message: &TextMessage, now: SystemTime) {
+        let emotes = emotes(message, &self.extra);
+        if emotes.is_empty() {
+            return;
+        }
+        let day = Date::of(now).to_string();
+        let buckets = self.channels.entry(message.channel.clone()).or_default();
+        if buckets.day != day {
+            buckets.day = day;
+            buckets.today.clear();
+        }
+        for (name, id) in emotes {
+            *buckets.today.entry(name.to_owned()).or_default() += 1;
+            *buckets.all.entry(name.to_owned()).or_default() += 1;
+            if let Some(id) = id {
+                buckets.ids.insert(name.to_owned(), id.to_owned());
+            }
+        }
+        self.unsaved = true;
+        if self
+            .flushed
+            .is_none_or(|flushed| now >= flushed + FLUSH_INTERVAL)
+        {
+            self.flushed = Some(now);
+            self.flush();
+        }
+    }
+
+    /// Writes what was counted since the last save, the bot does so when it stops.
+    pub fn flush(&mut self) {
+        if !std::mem::take(&mut self.unsaved) {
+            return;
+        }
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.channels) {
+            tracing::warn!(%error, "could not save the emote stats");
+        }
+    }
+
+    // the daily counts are of an earlier day when no emote was used today yet
+    fn bucket(
+        &self,
+        channel: &str,
+        today: bool,
+        now: SystemTime,
+    ) -> Option<&BTreeMap<String, u64>> {
+        let buckets = self.channels.get(channel)?;
+        match today {
+            true => (buckets.day == Date::of(now).to_string()).then_some(&buckets.today),
+            false => Some(&buckets.all),
+        }
+    }
+
+    /// How often the emote was used, all-time and today.
+    pub fn uses(&self, channel: &str, name: &str, now: SystemTime) -> (u64, u64) {
+        let uses = |today| {
+            self.bucket(channel, today, now)
+                .and_then(|bucket| bucket.get(name))
+                .copied()
+                .unwrap_or_default()
+        };
+        (uses(false), uses(true))
+    }
+
+    /// The most used emotes, most first. The storage ranks them, e.g. the database, unless
+    /// the uses counted since the last save are only here.
+    pub fn top(&self, channel: &str, today: bool, now: SystemTime) -> Vec<(String, u64)> {
+        let Some(bucket) = self.bucket(channel, today, now) else {
+            return Vec::new();
+        };
+        if !self.unsaved {
+            let path = [channel, if today { "today" } else { "all" }];
+            match self.storage.top(STORAGE_NAME, &path, TOP) {
+                Ok(Some(top)) => return top,
+                Ok(None) => {}
+                Err(error) => tracing::warn!(%error, "could not rank the emotes"),
+            }
+        }
+        let mut top: Vec<_> = bucket
+            .iter()
+            .map(|(name, &uses)| (name.clone(), uses))
+            .collect();
+        top.sort_by(|(a, a_uses), (b, b_uses)| b_uses.cmp(a_uses).then(a.cmp(b)));
+        top.truncate(TOP);
+        top
+    }
+}
+
+/// `!topemotes [today]` lists the most used emotes, all-time by default.
+pub struct TopEmotes(pub SharedEmoteStats);
+
+impl Command for TopEmotes {
+    fn name(&self) -> &'static str {
+        "topemotes"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[today]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let today = match args.next().map(str::to_lowercase).as_deref() {
+            None | Some("all") => false,
+            Some("today") => true,
+            Some(_) => return ctx.send(format!("Usage: {}topemotes [today]", ctx.prefix)),
+        };
+        let stats = self.0.borrow();
+        let top = stats.top(&ctx.message.channel, today, SystemTime::now());
+        if top.is_empty() {
+            return ctx.send("No emotes were used yet.".to_owned());
+        }
+        let mut text = match today {
+            true => "Top emotes today:".to_owned(),
+            false => "Top emotes:".to_owned(),
+        };
+        for (index, (name, uses)) in top.into_iter().enumerate() {
+            let entry = format!("{} {} ({})", if index == 0 { "" } else { "," }, name, uses);
+            if text.chars().count() + entry.chars().count() > MAX_MESSAGE_CHARS {
+                break;
+            }
+            text.push_str(&entry);
+        }
+        ctx.send(text)
+    }
+}
+
+/// `!emotecount <emote>` tells how often the emote was used.
+pub struct EmoteCount(pub SharedEmoteStats);
+
+impl Command for EmoteCount {
+    fn name(&self) -> &'static str {
+        "emotecount"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<emote>"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(name) = args.next() else {
+            return ctx.send(format!("Usage: {}emotecount <emote>", ctx.prefix));
+        };
+        let (all, today) = self
+            .0
+            .borrow()
+            .uses(&ctx.message.channel, name, SystemTime::now());
+        ctx.send(match all {
+            0 => format!("{} wasn't used here yet.", name),
+            1 => match today {
+                1 => format!("{} was used once, today.", name),
+                _ => format!("{} was used once.", name),
+            },
+            _ => format!("{} was used {} times, {} of them today.", name, all, today),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::EmoteSpan;
+
+    fn message(text: &str, spans: &[(&str, usize, usize)]) -> TextMessage {
+        TextMessage {
+            channel: "carkhy".to_owned(),
+            text: text.to_owned(),
+            emotes: spans
+                .iter()
+                .map(|&(id, start, end)| EmoteSpan {
+                    id: id.to_owned(),
+                    start,
+                    end,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn each_use_counts() {
+        let now = SystemTime::now();
+        let mut stats = EmoteStats::load(&EmotesConfig::default(), Storage::memory()).unwrap();
+        stats.count(
+            &message(
+                "Kappa Kappa hi Kappa LUL",
+                &[
+                    ("25", 0, 5),
+                    ("25", 6, 11),
+                    ("25", 15, 20),
+                    ("425618", 21, 24),
+                ],
+            ),
+            now,
+        );
+        stats.count(&message("LUL", &[("425618", 0, 3)]), now);
+        // without its span a name is just a word
+        stats.count(&message("Kappa", &[]), now);
+        let top = [("Kappa".to_owned(), 3), ("LUL".to_owned(), 2)];
+        assert_eq!(stats.top("carkhy", false, now), top);
+        // ranked by the storage once saved
+        stats.flush();
+        assert_eq!(stats.top("carkhy", false, now), top);
+        assert_eq!(stats.channels["carkhy"].ids["LUL"], "425618");
+        assert_eq!(stats.uses("carkhy", "Kappa", now), (3, 3));
+        assert_eq!(stats.uses("carkhy", "kappa", now), (0, 0));
+    }
+
+    #[test]
+    fn configured_names_are_whole_words() {
+        let now = SystemTime::now();
+        let config = EmotesConfig {
+            extra_names: vec!["catJAM".to_owned(), "KEKW".to_owned()],
+        };
+        let mut stats = EmoteStats::load(&config, Storage::default()).unwrap();
+        stats.count(
+            &message(
+                "catJAM catJAM Kappa KEKW, kekw catJAMs KEKW",
+                &[("25", 14, 19)],
+            ),
+            now,
+        );
+        assert_eq!(
+            stats.top("carkhy", true, now),
+            [
+                ("catJAM".to_owned(), 2),
+                ("KEKW".to_owned(), 1),
+                ("Kappa".to_owned(), 1)
+            ]
+        );
+        assert!(!stats.channels["carkhy"].ids.contains_key("catJAM"));
+        // the next day starts over
+        let tomorrow = now + Duration::from_secs(24 * 60 * 60);
+        assert!(stats.top("carkhy", true, tomorrow).is_empty());
+        assert_eq!(stats.uses("carkhy", "catJAM", tomorrow), (2, 0));
+    }
+}
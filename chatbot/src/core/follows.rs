@@ -0,0 +1,71 @@
+use super::{commands::render_event, ChatBotCommand};
+use crate::{config::EventsConfig, connect::Overflow};
+use std::collections::{HashMap, HashSet};
+
+/// Thanks new followers, EventSub tells about them.
+#[derive(Debug)]
+pub struct Follows {
+    message: String,
+    // by channel, who was thanked since the bot started. Following again after an unfollow
+    // isn't thanked twice
+    thanked: HashMap<String, HashSet<String>>,
+}
+
+impl Default for Follows {
+    fn default() -> Self {
+        Self::new(&EventsConfig::default())
+    }
+}
+
+impl Follows {
+    pub fn new(config: &EventsConfig) -> Self {
+        Self {
+            message: config.follow_message.clone(),
+            thanked: HashMap::new(),
+        }
+    }
+
+    /// The thank-you, None with an empty message or for a follower thanked before.
+    pub fn follow(&mut self, channel: &str, login: &str, name: &str) -> Option<ChatBotCommand> {
+        let thanked = self.thanked.entry(channel.to_owned()).or_default();
+        if self.message.is_empty() || !thanked.insert(login.to_lowercase()) {
+            return None;
+        }
+        Some(ChatBotCommand::SendMessage {
+            channel: channel.to_owned(),
+            text: render_event(&self.message, "follow message", name, channel, &[]),
+            overflow: Overflow::Truncate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(command: Option<ChatBotCommand>) -> Option<String> {
+        match command? {
+            ChatBotCommand::SendMessage { text, .. } => Some(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn followers_are_thanked_once() {
+        let mut follows = Follows::default();
+        assert_eq!(
+            text(follows.follow("captaincallback", "carkhy", "Carkhy")).as_deref(),
+            Some("Thank you for the follow, Carkhy!")
+        );
+        assert_eq!(
+            text(follows.follow("captaincallback", "Carkhy", "Carkhy")),
+            None
+        );
+        assert!(text(follows.follow("carkhy", "carkhy", "Carkhy")).is_some());
+        let config = EventsConfig {
+            follow_message: String::new(),
+            ..Default::default()
+        };
+        assert_eq!(text(Follows::new(&config).follow("carkhy", "a", "A")), None);
+    }
+}
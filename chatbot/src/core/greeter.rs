@@ -0,0 +1,206 @@
+use super::{commands::render_event, ChatBotCommand, HelixTask};
+use crate::{
+    config::EventsConfig,
+    connect::{Overflow, TextMessage},
+    storage::Storage,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+// every greeted user, "channel login" on each line
+const STORAGE_NAME: &str = "greeted_users";
+// twitch may still deliver what was said before the bot connected
+const CONNECT_GRACE: Duration = Duration::from_secs(2 * 60);
+// the users kept in memory, the others are looked up in the storage
+const CAPACITY: usize = 10_000;
+
+/// Welcomes users to a channel with their first message there, at most once ever.
+#[derive(Debug)]
+pub struct Greeter {
+    storage: Storage,
+    greeting: String,
+    only_live: bool,
+    connected: Instant,
+    // the latest users seen, by channel and lowercase login, oldest first
+    seen: HashSet<(String, String)>,
+    order: VecDeque<(String, String)>,
+}
+
+impl Default for Greeter {
+    fn default() -> Self {
+        Self::new(&EventsConfig::default(), Storage::default(), Instant::now())
+    }
+}
+
+impl Greeter {
+    /// Nobody is greeted until a while after the bot connected.
+    pub fn new(config: &EventsConfig, storage: Storage, connected: Instant) -> Self {
+        Self {
+            storage,
+            greeting: config.greeting.clone(),
+            only_live: config.greet_only_live,
+            connected,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// After a reconnect, nobody is greeted for a while again.
+    pub fn connected(&mut self, now: Instant) {
+        self.connected = now;
+    }
+
+    // false if the user was seen before, now or in an earlier run
+    fn first_time(&mut self, channel: &str, login: &str) -> bool {
+        let key = (channel.to_owned(), login.to_lowercase());
+        if self.seen.contains(&key) {
+            return false;
+        }
+        let line = format!("{} {}", key.0, key.1);
+        let known = self
+            .storage
+            .has_line(STORAGE_NAME, &line)
+            .unwrap_or_else(|error| {
+                tracing::warn!(%error, "could not look up the greeted users");
+                false
+            });
+        if !known {
+            if let Err(error) = self.storage.append(STORAGE_NAME, &line) {
+                tracing::warn!(%error, "could not save the greeted user");
+            }
+        }
+        if self.order.len() == CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(key.clone());
+        self.order.push_back(key);
+        !known
+    }
+
+    /// The greeting for a first message, the user counts as greeted even while it isn't sent.
+    pub fn greet(&mut self, message: &TextMessage, now: Instant) -> Option<ChatBotCommand> {
+        // twitch may mark another message as first, e.g. after the first one was deleted
+        if !message.first_msg || !self.first_time(&message.channel, &message.user.name) {
+            return None;
+        }
+        if now < self.connected + CONNECT_GRACE || self.greeting.is_empty() {
+            return None;
+        }
+        let text = render_event(
+            &self.greeting,
+            "greeting",
+            message.user.display_name(),
+            &message.channel,
+            &[],
+        );
+        let channel = message.channel.clone();
+        Some(match self.only_live {
+            true => ChatBotCommand::Helix(HelixTask::SendIfLive { channel, text }),
+            false => ChatBotCommand::SendMessage {
+                channel,
+                text,
+                overflow: Overflow::Truncate,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::UserInfo;
+
+    fn first_message(channel: &str, login: &str) -> TextMessage {
+        TextMessage {
+            channel: channel.to_owned(),
+            text: "Hi!".to_owned(),
+            user: UserInfo {
+                name: login.to_owned(),
+                display_name: Some("Carkhy".to_owned()),
+                ..Default::default()
+            },
+            first_msg: true,
+            ..Default::default()
+        }
+    }
+
+    fn greeting(command: Option<ChatBotCommand>) -> Option<String> {
+        match command? {
+            ChatBotCommand::SendMessage { text, .. } => Some(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn users_are_greeted_once_ever() {
+        let storage = Storage::memory();
+        let config = EventsConfig {
+            greeting: "Welcome to $(channel), $(user)!".to_owned(),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let later = start + CONNECT_GRACE;
+        let mut greeter = Greeter::new(&config, storage.clone(), start);
+        assert_eq!(
+            greeting(greeter.greet(&first_message("carkhy", "carkhy"), later)).as_deref(),
+            Some("Welcome to carkhy, Carkhy!")
+        );
+        assert!(greeter
+            .greet(&first_message("carkhy", "Carkhy"), later)
+            .is_none());
+        // once in each channel
+        assert!(greeter
+            .greet(&first_message("captaincallback", "carkhy"), later)
+            .is_some());
+
+        // after a restart, the storage remembers
+        let mut greeter = Greeter::new(&config, storage.clone(), start);
+        assert!(greeter
+            .greet(&first_message("carkhy", "carkhy"), later)
+            .is_none());
+        assert!(greeter
+            .greet(&first_message("carkhy", "viewer"), later)
+            .is_some());
+    }
+
+    #[test]
+    fn nobody_is_greeted_right_after_connecting() {
+        let start = Instant::now();
+        let mut greeter = Greeter::new(&EventsConfig::default(), Storage::default(), start);
+        let soon = start + CONNECT_GRACE - Duration::from_secs(1);
+        assert!(greeter
+            .greet(&first_message("carkhy", "carkhy"), soon)
+            .is_none());
+        // a chatter from the backlog isn't welcomed late
+        assert!(greeter
+            .greet(&first_message("carkhy", "carkhy"), start + CONNECT_GRACE)
+            .is_none());
+        assert!(greeter
+            .greet(&first_message("carkhy", "viewer"), start + CONNECT_GRACE)
+            .is_some());
+
+        greeter.connected(start + CONNECT_GRACE);
+        assert!(greeter
+            .greet(&first_message("carkhy", "newcomer"), start + CONNECT_GRACE)
+            .is_none());
+    }
+
+    #[test]
+    fn greetings_can_wait_for_the_stream() {
+        let config = EventsConfig {
+            greet_only_live: true,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut greeter = Greeter::new(&config, Storage::default(), start);
+        assert!(matches!(
+            greeter.greet(&first_message("carkhy", "carkhy"), start + CONNECT_GRACE),
+            Some(ChatBotCommand::Helix(HelixTask::SendIfLive { text, .. }))
+                if text == "Welcome to the chat, Carkhy! Write '!help' to see what I can do."
+        ));
+    }
+}
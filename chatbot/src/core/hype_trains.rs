@@ -0,0 +1,132 @@
+use super::{commands::render_event, ChatBotCommand};
+use crate::{
+    config::EventsConfig,
+    connect::{HypeTrainStage, Overflow},
+};
+use std::collections::HashMap;
+
+/// Announces the hype trains EventSub tells about, each level once.
+#[derive(Debug)]
+pub struct HypeTrains {
+    begin: String,
+    level: String,
+    end: String,
+    only_ends: bool,
+    // by channel, the highest level announced of the running train
+    announced: HashMap<String, u32>,
+}
+
+impl Default for HypeTrains {
+    fn default() -> Self {
+        Self::new(&EventsConfig::default())
+    }
+}
+
+impl HypeTrains {
+    pub fn new(config: &EventsConfig) -> Self {
+        Self {
+            begin: config.hype_train_begin.clone(),
+            level: config.hype_train_level.clone(),
+            end: config.hype_train_end.clone(),
+            only_ends: config.hype_train_only_ends,
+            announced: HashMap::new(),
+        }
+    }
+
+    /// The announcement, None for progress within a level announced before.
+    pub fn event(
+        &mut self,
+        channel: &str,
+        stage: HypeTrainStage,
+        level: u32,
+        contributors: &[String],
+    ) -> Option<ChatBotCommand> {
+        let template = match stage {
+            HypeTrainStage::Begin => {
+                self.announced.insert(channel.to_owned(), level);
+                &self.begin
+            }
+            HypeTrainStage::Progress => {
+                // a train the bot didn't see begin has its level announced once
+                let announced = self.announced.entry(channel.to_owned()).or_default();
+                if level <= *announced {
+                    return None;
+                }
+                *announced = level;
+                if self.only_ends {
+                    return None;
+                }
+                &self.level
+            }
+            HypeTrainStage::End => {
+                self.announced.remove(channel);
+                &self.end
+            }
+        };
+        if template.is_empty() {
+            return None;
+        }
+        let event = [
+            ("level", level.to_string()),
+            ("contributors", contributors.join(", ")),
+        ];
+        Some(ChatBotCommand::SendMessage {
+            channel: channel.to_owned(),
+            text: render_event(template, "hype train message", channel, channel, &event),
+            overflow: Overflow::Truncate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(command: Option<ChatBotCommand>) -> Option<String> {
+        match command? {
+            ChatBotCommand::SendMessage { text, .. } => Some(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn each_level_is_announced_once() {
+        let mut trains = HypeTrains::default();
+        let mut event = |stage, level| {
+            let contributors = ["Carkhy".to_owned(), "TenaciousByte".to_owned()];
+            text(trains.event("captaincallback", stage, level, &contributors))
+        };
+        assert_eq!(
+            event(HypeTrainStage::Begin, 1).as_deref(),
+            Some("Hype Train level 1 started!")
+        );
+        assert!(event(HypeTrainStage::Progress, 1).is_none());
+        assert_eq!(
+            event(HypeTrainStage::Progress, 2).as_deref(),
+            Some("The Hype Train reached level 2!")
+        );
+        assert!(event(HypeTrainStage::Progress, 2).is_none());
+        assert_eq!(
+            event(HypeTrainStage::End, 2).as_deref(),
+            Some("The Hype Train ended at level 2! Thanks to Carkhy, TenaciousByte")
+        );
+    }
+
+    #[test]
+    fn only_the_ends_can_be_announced() {
+        let config = EventsConfig {
+            hype_train_only_ends: true,
+            ..Default::default()
+        };
+        let mut trains = HypeTrains::new(&config);
+        assert!(trains
+            .event("carkhy", HypeTrainStage::Begin, 1, &[])
+            .is_some());
+        assert!(trains
+            .event("carkhy", HypeTrainStage::Progress, 2, &[])
+            .is_none());
+        assert!(trains
+            .event("carkhy", HypeTrainStage::End, 2, &[])
+            .is_some());
+    }
+}
@@ -0,0 +1,419 @@
+use super::{
+    commands::{render_event, Args, Command, Context},
+    tasks::uptime,
+    ChatBotCommand, HelixTask,
+};
+use crate::{
+    config::LurkConfig,
+    connect::{ChatBotEvent, InternalEvent, Overflow, TextMessage},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const STORAGE_NAME: &str = "lurks";
+// how often the lurks ask twitch whether their streams are still live
+const LURK_TICK: Duration = Duration::from_secs(5 * 60);
+// a lurk nobody ended, e.g. as its stream was never seen live, counts this long at most
+const MAX_LURK: Duration = Duration::from_secs(12 * 60 * 60);
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Lurker {
+    name: String,
+    // seconds since 1970
+    since: u64,
+    // the stream was live during the lurk, it ends when the stream goes offline
+    seen_live: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    // by channel and lowercase login
+    lurking: BTreeMap<String, BTreeMap<String, Lurker>>,
+    // the seconds of the lurks that ended, by channel and lowercase login
+    totals: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+/// Who lurks in each channel and for how long everyone lurked before, kept in the storage.
+#[derive(Debug, Default)]
+pub struct Lurks {
+    config: LurkConfig,
+    storage: Storage,
+    saved: Saved,
+    // by channel, what twitch answered last
+    live: HashMap<String, bool>,
+    // a LurkTick is scheduled
+    ticking: bool,
+}
+
+pub type SharedLurks = Rc<RefCell<Lurks>>;
+
+impl Lurks {
+    pub fn load(config: &LurkConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            config: config.clone(),
+            saved: storage.load(STORAGE_NAME)?,
+            storage,
+            ..Default::default()
+        })
+    }
+
+    fn save(&self) {
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.saved) {
+            tracing::warn!(%error, "could not save the lurks");
+        }
+    }
+
+    /// The first LurkTick while anyone lurks and none is scheduled.
+    pub fn start(&mut self) -> Option<ChatBotCommand> {
+        if self.ticking || self.saved.lurking.values().all(BTreeMap::is_empty) {
+            return None;
+        }
+        self.ticking = true;
+        Some(ChatBotCommand::TimedCallback {
+            duration: LURK_TICK,
+            event: ChatBotEvent::Internal(InternalEvent::LurkTick),
+        })
+    }
+
+    /// Starts the user's lurk, one that started before is counted up to now.
+    pub fn lurk(&mut self, channel: &str, name: &str, now: SystemTime) -> Option<ChatBotCommand> {
+        let login = name.to_lowercase();
+        self.end(channel, &login, now);
+        let lurker = Lurker {
+            name: name.to_owned(),
+            since: seconds(now),
+            seen_live: self.live.get(channel) == Some(&true),
+        };
+        self.saved
+            .lurking
+            .entry(channel.to_owned())
+            .or_default()
+            .insert(login, lurker);
+        self.save();
+        self.start()
+    }
+
+    // how long the lurk lasted, None if the user doesn't lurk
+    fn end(&mut self, channel: &str, login: &str, now: SystemTime) -> Option<Duration> {
+        let lurker = self.saved.lurking.get_mut(channel)?.remove(login)?;
+        let lurked = Duration::from_secs(seconds(now).saturating_sub(lurker.since)).min(MAX_LURK);
+        *self
+            .saved
+            .totals
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(login.to_owned())
+            .or_default() += lurked.as_secs();
+        self.save();
+        Some(lurked)
+    }
+
+    /// Ends the user's lurk and tells how long it lasted, None if the user doesn't lurk.
+    pub fn unlurk(&mut self, channel: &str, name: &str, now: SystemTime) -> Option<Duration> {
+        self.end(channel, &name.to_lowercase(), now)
+    }
+
+    /// None if no welcome back is configured.
+    pub fn welcome_back(&self, channel: &str, name: &str, lurked: Duration) -> Option<String> {
+        if self.config.welcome_back.is_empty() {
+            return None;
+        }
+        Some(render_event(
+            &self.config.welcome_back,
+            "welcome back",
+            name,
+            channel,
+            &[("duration", uptime(lurked))],
+        ))
+    }
+
+    /// The welcome back of a lurker who chats again, if chatting ends lurks.
+    pub fn message(&mut self, message: &TextMessage, now: SystemTime) -> Option<ChatBotCommand> {
+        if !self.config.end_on_message {
+            return None;
+        }
+        let name = message.user.display_name();
+        let lurked = self.unlurk(&message.channel, name, now)?;
+        Some(ChatBotCommand::SendMessage {
+            channel: message.channel.clone(),
+            text: self.welcome_back(&message.channel, name, lurked)?,
+            overflow: Overflow::Truncate,
+        })
+    }
+
+    /// How long the user lurked in the channel including now, and how many lurk there.
+    pub fn stats(&self, channel: &str, login: &str, now: SystemTime) -> (Duration, usize) {
+        let lurking = self.saved.lurking.get(channel);
+        let current = lurking
+            .and_then(|lurking| lurking.get(login))
+            .map(|lurker| seconds(now).saturating_sub(lurker.since))
+            .unwrap_or_default()
+            .min(MAX_LURK.as_secs());
+        let before = self
+            .saved
+            .totals
+            .get(channel)
+            .and_then(|totals| totals.get(login))
+            .copied()
+            .unwrap_or_default();
+        (
+            Duration::from_secs(before + current),
+            lurking.map(BTreeMap::len).unwrap_or_default(),
+        )
+    }
+
+    /// Ends the lurks that lasted too long and asks twitch about the streams of the others,
+    /// with the next tick while anyone lurks.
+    pub fn tick(&mut self, now: SystemTime) -> Vec<ChatBotCommand> {
+        let expired: Vec<_> = self
+            .saved
+            .lurking
+            .iter()
+            .flat_map(|(channel, lurking)| {
+                lurking
+                    .iter()
+                    .filter(|(_, lurker)| {
+                        seconds(now).saturating_sub(lurker.since) >= MAX_LURK.as_secs()
+                    })
+                    .map(|(login, _)| (channel.clone(), login.clone()))
+            })
+            .collect();
+        for (channel, login) in expired {
+            self.end(&channel, &login, now);
+        }
+        self.saved.lurking.retain(|_, lurking| !lurking.is_empty());
+        let mut commands: Vec<_> = self
+            .saved
+            .lurking
+            .keys()
+            .map(|channel| {
+                ChatBotCommand::Helix(HelixTask::LiveStatus {
+                    channel: channel.clone(),
+                })
+            })
+            .collect();
+        self.ticking = false;
+        commands.extend(self.start());
+        commands
+    }
+
+    /// Once the channel is offline, ends the lurks that saw it live.
+    pub fn live_status(&mut self, channel: &str, live: bool, now: SystemTime) {
+        self.live.insert(channel.to_owned(), live);
+        let Some(lurking) = self.saved.lurking.get_mut(channel) else {
+            return;
+        };
+        if live {
+            lurking
+                .values_mut()
+                .for_each(|lurker| lurker.seen_live = true);
+            self.save();
+            return;
+        }
+        let over: Vec<_> = lurking
+            .iter()
+            .filter(|(_, lurker)| lurker.seen_live)
+            .map(|(login, _)| login.clone())
+            .collect();
+        for login in over {
+            self.end(channel, &login, now);
+        }
+    }
+}
+
+/// `!lurk` tells the chat the user lurks from now on.
+pub struct Lurk(pub SharedLurks);
+
+impl Command for Lurk {
+    fn name(&self) -> &'static str {
+        "lurk"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let user = ctx.message.user.display_name();
+        let mut lurks = self.0.borrow_mut();
+        let tick = lurks.lurk(channel, user, SystemTime::now());
+        let text = render_event(&lurks.config.message, "lurk message", user, channel, &[]);
+        let answer = ctx.send(text)?;
+        Some(match tick {
+            Some(tick) => ChatBotCommand::MultipleCommands(vec![answer, tick]),
+            None => answer,
+        })
+    }
+}
+
+/// `!unlurk` ends the user's lurk.
+pub struct Unlurk(pub SharedLurks);
+
+impl Command for Unlurk {
+    fn name(&self) -> &'static str {
+        "unlurk"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let user = ctx.message.user.display_name();
+        let mut lurks = self.0.borrow_mut();
+        match lurks.unlurk(channel, user, SystemTime::now()) {
+            Some(lurked) => ctx.send(lurks.welcome_back(channel, user, lurked)?),
+            None => ctx.send(format!("{}, you aren't lurking.", user)),
+        }
+    }
+}
+
+/// `!lurkstats` tells how long the user lurked in total and how many lurk right now.
+pub struct LurkStats(pub SharedLurks);
+
+impl Command for LurkStats {
+    fn name(&self) -> &'static str {
+        "lurkstats"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let (total, lurking) = self.0.borrow().stats(
+            &ctx.message.channel,
+            &ctx.message.user.name.to_lowercase(),
+            SystemTime::now(),
+        );
+        let lurkers = match lurking {
+            1 => "1 viewer is".to_owned(),
+            lurking => format!("{} viewers are", lurking),
+        };
+        ctx.send(format!(
+            "{} lurked for {} in total, {} lurking right now.",
+            ctx.message.user.display_name(),
+            uptime(total),
+            lurkers
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn lurking_again_counts_the_time_once() {
+        let storage = Storage::memory();
+        let config = LurkConfig::default();
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut lurks = Lurks::load(&config, storage.clone()).unwrap();
+        assert!(matches!(
+            lurks.lurk("carkhy", "Viewer", start),
+            Some(ChatBotCommand::TimedCallback {
+                event: ChatBotEvent::Internal(InternalEvent::LurkTick),
+                ..
+            })
+        ));
+        // the tick is scheduled already
+        assert!(lurks
+            .lurk("carkhy", "viewer", start + minutes(20))
+            .is_none());
+        assert_eq!(
+            lurks.stats("carkhy", "viewer", start + minutes(30)),
+            (minutes(30), 1)
+        );
+
+        // a restart keeps the lurk and what was counted
+        let mut lurks = Lurks::load(&config, storage.clone()).unwrap();
+        assert!(lurks.start().is_some());
+        let lurked = lurks.unlurk("carkhy", "Viewer", start + minutes(47));
+        assert_eq!(lurked, Some(minutes(27)));
+        assert_eq!(
+            lurks.welcome_back("carkhy", "Viewer", minutes(27)).unwrap(),
+            "Welcome back Viewer, you lurked for 27 minutes."
+        );
+        assert_eq!(lurks.unlurk("carkhy", "Viewer", start + minutes(50)), None);
+        assert_eq!(
+            lurks.stats("carkhy", "viewer", start + minutes(50)),
+            (minutes(47), 0)
+        );
+        // the last tick once nobody lurks
+        assert!(lurks.tick(start + minutes(50)).is_empty());
+    }
+
+    #[test]
+    fn chatting_ends_a_lurk_if_configured() {
+        let start = SystemTime::now();
+        let mut lurks = Lurks::default();
+        lurks.config.end_on_message = false;
+        lurks.lurk("carkhy", "Viewer", start);
+        assert!(lurks
+            .message(&message("Viewer", "I'm back", false), start)
+            .is_none());
+        assert_eq!(lurks.stats("carkhy", "viewer", start).1, 1);
+
+        lurks.config.end_on_message = true;
+        assert!(lurks
+            .message(&message("Other", "I'm back", false), start)
+            .is_none());
+        match lurks.message(&message("Viewer", "I'm back", false), start + minutes(135)) {
+            Some(ChatBotCommand::SendMessage { text, .. }) => assert_eq!(
+                text,
+                "Welcome back Viewer, you lurked for 2 hours 15 minutes."
+            ),
+            other => panic!("{:?}", other),
+        }
+        assert!(lurks
+            .message(&message("Viewer", "I'm back", false), start)
+            .is_none());
+        assert_eq!(lurks.stats("carkhy", "viewer", start).1, 0);
+
+        // without a welcome back the lurk ends quietly
+        lurks.config.welcome_back.clear();
+        lurks.lurk("carkhy", "Viewer", start);
+        assert!(lurks
+            .message(&message("Viewer", "I'm back", false), start)
+            .is_none());
+        assert_eq!(lurks.stats("carkhy", "viewer", start).1, 0);
+    }
+
+    #[test]
+    fn lurks_end_with_the_stream() {
+        let start = SystemTime::now();
+        let mut lurks = Lurks::default();
+        // offline before the stream, counted until it ends
+        lurks.lurk("carkhy", "Early", start);
+        lurks.live_status("carkhy", true, start + minutes(5));
+        lurks.lurk("carkhy", "Viewer", start + minutes(10));
+        lurks.live_status("carkhy", false, start + minutes(70));
+        lurks.lurk("carkhy", "Late", start + minutes(75));
+        assert!(matches!(
+            lurks.tick(start + minutes(76)).as_slice(),
+            [
+                ChatBotCommand::Helix(HelixTask::LiveStatus { .. }),
+                ChatBotCommand::TimedCallback {
+                    event: ChatBotEvent::Internal(InternalEvent::LurkTick),
+                    ..
+                }
+            ]
+        ));
+        lurks.live_status("carkhy", false, start + minutes(80));
+        let stats = |lurks: &Lurks, login, now| lurks.stats("carkhy", login, now);
+        assert_eq!(stats(&lurks, "early", start), (minutes(70), 1));
+        assert_eq!(stats(&lurks, "viewer", start), (minutes(60), 1));
+        assert_eq!(stats(&lurks, "late", start + minutes(80)), (minutes(5), 1));
+
+        // the lurk of a stream never seen live ends after the longest a lurk counts
+        assert!(lurks.tick(start + minutes(75) + MAX_LURK).is_empty());
+        assert_eq!(stats(&lurks, "late", start), (MAX_LURK, 0));
+    }
+}
@@ -0,0 +1,502 @@
+use super::{
+    commands::{Args, Command, Context},
+    moderation::{linked_hosts, SharedModeration},
+    ChatBotCommand,
+};
+use crate::{
+    config::ImitateConfig,
+    connect::TextMessage,
+    storage::{Storage, StorageError},
+};
+use fastrand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+const STORAGE_NAME: &str = "imitate";
+// the models are written at most this often, not for each message
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+// a sentence that came out empty or with a banned term is made up anew this often
+const ATTEMPTS: usize = 10;
+// a made up sentence ends before it gets longer
+const MAX_CHARS: usize = 300;
+// pruning forgets a tenth more than needed, so it doesn't happen for each message
+const PRUNE_PERCENT: usize = 90;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Followers {
+    // the next words and how often they followed, "" ends the sentence
+    words: BTreeMap<String, u32>,
+    // when the words before were last learned or used, by the clock of the models
+    used: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Chain {
+    // by the words before the next one joined with spaces, "" pads the start of a sentence
+    ngrams: HashMap<String, Followers>,
+    messages: u64,
+}
+
+impl Chain {
+    fn learn(&mut self, words: &[&str], order: usize, clock: u64) {
+        let padded: Vec<_> = [""]
+            .repeat(order)
+            .into_iter()
+            .chain(words.iter().copied())
+            .chain([""])
+            .collect();
+        for window in padded.windows(order + 1) {
+            let followers = self.ngrams.entry(window[..order].join(" ")).or_default();
+            *followers.words.entry(window[order].to_owned()).or_default() += 1;
+            followers.used = clock;
+        }
+        self.messages += 1;
+    }
+
+    // takes out what the other chain learned
+    fn unlearn(&mut self, other: &Chain) {
+        for (key, learned) in &other.ngrams {
+            let Some(followers) = self.ngrams.get_mut(key) else {
+                continue;
+            };
+            for (word, count) in &learned.words {
+                if let Some(own) = followers.words.get_mut(word) {
+                    *own = own.saturating_sub(*count);
+                }
+            }
+            followers.words.retain(|_, count| *count > 0);
+            if followers.words.is_empty() {
+                self.ngrams.remove(key);
+            }
+        }
+        self.messages = self.messages.saturating_sub(other.messages);
+    }
+
+    fn generate(&mut self, order: usize, max_words: usize, rng: &mut Rng, clock: u64) -> String {
+        let mut before = vec![String::new(); order];
+        let mut sentence = String::new();
+        for _ in 0..max_words {
+            let Some(followers) = self.ngrams.get_mut(&before.join(" ")) else {
+                break;
+            };
+            followers.used = clock;
+            let total: u32 = followers.words.values().sum();
+            let mut pick = rng.u32(..total.max(1));
+            let Some(word) = followers
+                .words
+                .iter()
+                .find_map(|(word, &count)| match pick < count {
+                    true => Some(word),
+                    false => {
+                        pick -= count;
+                        None
+                    }
+                })
+            else {
+                break;
+            };
+            if word.is_empty() || sentence.len() + word.len() + 1 > MAX_CHARS {
+                break;
+            }
+            if !sentence.is_empty() {
+                sentence.push(' ');
+            }
+            sentence.push_str(word);
+            before.remove(0);
+            before.push(word.clone());
+        }
+        sentence
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    clock: u64,
+    // by channel
+    channels: BTreeMap<String, Chain>,
+    // by channel and lowercase login
+    users: BTreeMap<String, BTreeMap<String, Chain>>,
+    // lowercase logins
+    opted_out: BTreeSet<String>,
+}
+
+/// Why `!imitate` has no sentence.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refusal {
+    OptedOut,
+    // the messages learned of the user
+    TooLittle(u64),
+    Nothing,
+}
+
+/// What chat wrote in each channel as Markov chains, one of the channel and one of each user,
+/// kept in the storage.
+#[derive(Debug, Default)]
+pub struct Markov {
+    config: ImitateConfig,
+    storage: Storage,
+    rng: Rng,
+    saved: Saved,
+    // the word sequences of all chains
+    ngrams: usize,
+    unsaved: bool,
+    flushed: Option<SystemTime>,
+}
+
+pub type SharedMarkov = Rc<RefCell<Markov>>;
+
+impl Markov {
+    pub fn load(config: &ImitateConfig, storage: Storage, rng: Rng) -> Result<Self, StorageError> {
+        let mut markov = Self {
+            config: config.clone(),
+            saved: storage.load(STORAGE_NAME)?,
+            storage,
+            rng,
+            ..Default::default()
+        };
+        markov.count();
+        Ok(markov)
+    }
+
+    fn count(&mut self) {
+        let users = self.saved.users.values().flat_map(BTreeMap::values);
+        self.ngrams = self
+            .saved
+            .channels
+            .values()
+            .chain(users)
+            .map(|chain| chain.ngrams.len())
+            .sum();
+    }
+
+    /// Learns the message unless it links somewhere or its user opted out, the models are
+    /// saved with it if the last save was a while ago.
+    pub fn learn(&mut self, message: &TextMessage, now: SystemTime) {
+        let login = message.user.name.to_lowercase();
+        if !self.config.enabled
+            || self.saved.opted_out.contains(&login)
+            || !linked_hosts(&message.text).is_empty()
+        {
+            return;
+        }
+        let words: Vec<_> = message.text.split_whitespace().collect();
+        if words.is_empty() {
+            return;
+        }
+        self.saved.clock += 1;
+        let (order, clock) = (self.config.order, self.saved.clock);
+        let channel = &message.channel;
+        self.saved
+            .channels
+            .entry(channel.clone())
+            .or_default()
+            .learn(&words, order, clock);
+        self.saved
+            .users
+            .entry(channel.clone())
+            .or_default()
+            .entry(login)
+            .or_default()
+            .learn(&words, order, clock);
+        self.count();
+        if self.ngrams > self.config.max_ngrams {
+            self.prune();
+        }
+        self.unsaved = true;
+        if self
+            .flushed
+            .is_none_or(|flushed| now >= flushed + FLUSH_INTERVAL)
+        {
+            self.flushed = Some(now);
+            self.flush();
+        }
+    }
+
+    // forgets the least recently used word sequences of all chains
+    fn prune(&mut self) {
+        let keep = self.config.max_ngrams * PRUNE_PERCENT / 100;
+        let mut used: Vec<u64> = Vec::with_capacity(self.ngrams);
+        let users = self.saved.users.values().flat_map(BTreeMap::values);
+        for chain in self.saved.channels.values().chain(users) {
+            used.extend(chain.ngrams.values().map(|followers| followers.used));
+        }
+        used.sort_unstable_by(|a, b| b.cmp(a));
+        // the sequences used at that time or later are kept, ties may keep a few more
+        let Some(&oldest) = used.get(keep.saturating_sub(1)) else {
+            return;
+        };
+        let users = self.saved.users.values_mut().flat_map(BTreeMap::values_mut);
+        for chain in self.saved.channels.values_mut().chain(users) {
+            chain.ngrams.retain(|_, followers| followers.used > oldest);
+        }
+        self.count();
+    }
+
+    /// Writes what was learned since the last save, the bot does so when it stops.
+    pub fn flush(&mut self) {
+        if !std::mem::take(&mut self.unsaved) {
+            return;
+        }
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.saved) {
+            tracing::warn!(%error, "could not save the chat models");
+        }
+    }
+
+    /// A sentence like the channel's chat or the user's, `allowed` refuses those that
+    /// shouldn't be sent.
+    pub fn imitate(
+        &mut self,
+        channel: &str,
+        user: Option<&str>,
+        allowed: impl Fn(&str) -> bool,
+    ) -> Result<String, Refusal> {
+        self.saved.clock += 1;
+        let (order, max_words, clock) =
+            (self.config.order, self.config.max_words, self.saved.clock);
+        let chain = match user.map(str::to_lowercase) {
+            Some(login) => {
+                if self.saved.opted_out.contains(&login) {
+                    return Err(Refusal::OptedOut);
+                }
+                let chain = self
+                    .saved
+                    .users
+                    .get_mut(channel)
+                    .and_then(|users| users.get_mut(&login));
+                let messages = chain
+                    .as_ref()
+                    .map(|chain| chain.messages)
+                    .unwrap_or_default();
+                match chain {
+                    Some(chain) if messages >= self.config.min_user_messages => chain,
+                    _ => return Err(Refusal::TooLittle(messages)),
+                }
+            }
+            None => self
+                .saved
+                .channels
+                .get_mut(channel)
+                .ok_or(Refusal::Nothing)?,
+        };
+        (0..ATTEMPTS)
+            .map(|_| chain.generate(order, max_words, &mut self.rng, clock))
+            .find(|sentence| !sentence.is_empty() && allowed(sentence))
+            .ok_or(Refusal::Nothing)
+    }
+
+    /// Forgets what the user wrote and learns nothing of them anymore.
+    pub fn opt_out(&mut self, login: &str) {
+        let login = login.to_lowercase();
+        for (channel, users) in &mut self.saved.users {
+            if let Some(chain) = users.remove(&login) {
+                if let Some(channel) = self.saved.channels.get_mut(channel) {
+                    channel.unlearn(&chain);
+                }
+            }
+        }
+        self.saved.opted_out.insert(login);
+        self.count();
+        self.unsaved = true;
+        self.flush();
+    }
+
+    pub fn opt_in(&mut self, login: &str) {
+        if self.saved.opted_out.remove(&login.to_lowercase()) {
+            self.unsaved = true;
+            self.flush();
+        }
+    }
+}
+
+/// `!imitate [@user]` makes up a sentence like chat or the user would write,
+/// `!imitate optout` and `!imitate optin` for users who'd rather not be imitated.
+pub struct Imitate {
+    pub markov: SharedMarkov,
+    // what the banned terms forbid is not sent
+    pub moderation: SharedModeration,
+}
+
+impl Command for Imitate {
+    fn name(&self) -> &'static str {
+        "imitate"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[@user], optout, optin"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let name = ctx.message.user.display_name();
+        let mut markov = self.markov.borrow_mut();
+        let user = match args.next() {
+            Some(command) if command.eq_ignore_ascii_case("optout") => {
+                markov.opt_out(&ctx.message.user.name);
+                return ctx.send(format!(
+                    "{}, I forgot what you wrote and won't imitate you anymore.",
+                    name
+                ));
+            }
+            Some(command) if command.eq_ignore_ascii_case("optin") => {
+                markov.opt_in(&ctx.message.user.name);
+                return ctx.send(format!("{}, I'll learn from your messages again.", name));
+            }
+            user => user.map(|user| user.trim_start_matches('@')),
+        };
+        let moderation = self.moderation.borrow();
+        let allowed = |text: &str| {
+            moderation
+                .banned
+                .punishment(&ctx.message.channel, text)
+                .is_none()
+        };
+        ctx.send(match markov.imitate(&ctx.message.channel, user, allowed) {
+            Ok(sentence) => sentence,
+            Err(Refusal::OptedOut) => format!("{} doesn't want to be imitated.", user?),
+            Err(Refusal::TooLittle(_)) => format!("I don't know {} well enough yet.", user?),
+            Err(Refusal::Nothing) => "I have nothing to say yet.".to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+
+    const CORPUS: [&str; 4] = [
+        "the cat sat on the mat",
+        "the cat ate the fish",
+        "a dog sat on the cat",
+        "the dog ate the cat food",
+    ];
+
+    fn markov(config: ImitateConfig, seed: u64) -> Markov {
+        let config = ImitateConfig {
+            enabled: true,
+            ..config
+        };
+        Markov::load(&config, Storage::default(), Rng::with_seed(seed)).unwrap()
+    }
+
+    // the sequences of three words of the texts, padded like the chains do
+    fn trigrams(texts: &[&str]) -> Vec<Vec<String>> {
+        texts
+            .iter()
+            .flat_map(|text| {
+                let words: Vec<_> = ["", ""]
+                    .into_iter()
+                    .chain(text.split_whitespace())
+                    .chain([""])
+                    .map(str::to_owned)
+                    .collect();
+                words.windows(3).map(<[String]>::to_vec).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_seed_makes_up_the_same_sentences() {
+        let now = SystemTime::now();
+        let sentences = |seed| {
+            let mut markov = markov(ImitateConfig::default(), seed);
+            for text in CORPUS {
+                markov.learn(&message("Viewer", text, false), now);
+            }
+            (0..20)
+                .map(|_| markov.imitate("carkhy", None, |_| true).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let sentences_of_8 = sentences(8);
+        assert_eq!(sentences_of_8, sentences(8));
+        assert_ne!(sentences_of_8, sentences(9));
+        let known = trigrams(&CORPUS);
+        for sentence in &sentences_of_8 {
+            for trigram in trigrams(&[sentence.as_str()]) {
+                assert!(known.contains(&trigram), "{:?} of {}", trigram, sentence);
+            }
+        }
+
+        // a single way through the chain, and nothing once it is refused
+        let mut markov = markov(ImitateConfig::default(), 8);
+        markov.learn(&message("Viewer", "hello there general kenobi", false), now);
+        assert_eq!(
+            markov.imitate("carkhy", None, |_| true).as_deref(),
+            Ok("hello there general kenobi")
+        );
+        let allowed = |text: &str| !text.contains("kenobi");
+        assert_eq!(
+            markov.imitate("carkhy", None, allowed),
+            Err(Refusal::Nothing)
+        );
+    }
+
+    #[test]
+    fn users_are_imitated_unless_they_opt_out() {
+        let now = SystemTime::now();
+        let config = ImitateConfig {
+            min_user_messages: 3,
+            ..Default::default()
+        };
+        let mut markov = markov(config, 8);
+        markov.learn(&message("Viewer", "hello chat", false), now);
+        markov.learn(
+            &message("Viewer", "look at https://example.com", false),
+            now,
+        );
+        markov.learn(&message("Viewer", "hello chat", false), now);
+        markov.learn(&message("Other", "good morning", false), now);
+        assert_eq!(
+            markov.imitate("carkhy", Some("viewer"), |_| true),
+            Err(Refusal::TooLittle(2))
+        );
+        markov.learn(&message("Viewer", "hello chat", false), now);
+        assert_eq!(
+            markov
+                .imitate("carkhy", Some("Viewer"), |_| true)
+                .as_deref(),
+            Ok("hello chat")
+        );
+
+        markov.opt_out("VIEWER");
+        markov.learn(&message("Viewer", "hello again", false), now);
+        assert_eq!(
+            markov.imitate("carkhy", Some("viewer"), |_| true),
+            Err(Refusal::OptedOut)
+        );
+        // the channel's chain forgot them as well
+        assert_eq!(
+            markov.imitate("carkhy", None, |_| true).as_deref(),
+            Ok("good morning")
+        );
+        markov.opt_in("viewer");
+        assert_eq!(
+            markov.imitate("carkhy", Some("viewer"), |_| true),
+            Err(Refusal::TooLittle(0))
+        );
+    }
+
+    #[test]
+    fn the_least_recently_used_are_forgotten() {
+        let now = SystemTime::now();
+        let config = ImitateConfig {
+            order: 1,
+            max_ngrams: 15,
+            ..Default::default()
+        };
+        let mut markov = markov(config, 8);
+        markov.learn(&message("Viewer", "old words here", false), now);
+        for text in ["first new thing", "second new thing", "third one"] {
+            markov.learn(&message("Viewer", text, false), now);
+            assert!(markov.ngrams <= 15, "{}", markov.ngrams);
+        }
+        let known =
+            |markov: &Markov, key: &str| markov.saved.channels["carkhy"].ngrams.contains_key(key);
+        assert!(!known(&markov, "old"));
+        assert!(known(&markov, "third"));
+    }
+}
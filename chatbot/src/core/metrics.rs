@@ -0,0 +1,22 @@
+use std::fmt;
+
+/// What the bot counted since it started, logged when it stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    // chat messages the bot handled
+    pub messages: u64,
+    // messages of ignored users, which the bot didn't react to
+    pub ignored: u64,
+    // commands not answered, the user had too many answers within a minute
+    pub dropped: u64,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "messages: {}, ignored: {}, dropped: {}",
+            self.messages, self.ignored, self.dropped
+        )
+    }
+}
@@ -1,5 +1,41 @@
+mod api;
+mod bits;
 mod bot;
+mod calendar;
+mod chat_stats;
 mod command;
+mod commands;
+mod emote_stats;
+mod follows;
+mod greeter;
+mod hype_trains;
+mod lurks;
+mod markov;
+mod metrics;
+mod moderation;
+mod obs;
+mod points;
+mod polls;
+mod predictions;
+mod queue;
+mod raffles;
+mod raids;
+mod recent_messages;
+mod redemptions;
+mod reminders;
+mod schedule;
+mod songs;
+mod stream_status;
+mod subs;
+mod tasks;
+mod timers;
+mod trivia;
+mod tts;
+mod watch_time;
 
 pub use bot::ChatBot;
+pub use calendar::{timestamp, Date};
 pub use command::ChatBotCommand;
+pub use commands::CommandStats;
+pub use schedule::{Cron, TimeZone};
+pub use tasks::HelixTask;
@@ -0,0 +1,335 @@
+use super::SharedModeration;
+use crate::{
+    connect::UserLevel,
+    core::{
+        calendar::timestamp,
+        commands::{Args, Command, Context},
+        ChatBotCommand, HelixTask,
+    },
+    helix::Role,
+};
+use std::time::{Duration, SystemTime};
+
+// twitch's limits for a timeout
+pub(super) const MAX_TIMEOUT: u64 = 14 * 24 * 60 * 60;
+const DEFAULT_TIMEOUT: u64 = 600;
+
+// the target of `!timeout` and `!ban`, the answer why not as the error
+fn target<'a>(
+    moderation: &SharedModeration,
+    ctx: &Context,
+    args: &mut Args<'a>,
+    usage: &str,
+) -> Result<&'a str, String> {
+    let Some(user) = args.next().map(|user| user.trim_start_matches('@')) else {
+        return Err(format!("Usage: {}{}", ctx.prefix, usage));
+    };
+    if moderation.borrow().is_protected(&ctx.message.channel, user) {
+        return Err(format!(
+            "{} is the broadcaster, a moderator or the bot itself.",
+            user
+        ));
+    }
+    Ok(user)
+}
+
+fn audit(moderation: &SharedModeration, ctx: &Context, target: &str, action: &str, reason: &str) {
+    moderation.borrow().punisher.audit(&format!(
+        "{} #{} {}: {} by {} ({})",
+        timestamp(SystemTime::now()),
+        ctx.message.channel,
+        target.to_lowercase(),
+        action,
+        ctx.message.user.name,
+        reason
+    ));
+}
+
+/// `!timeout @user [seconds] [reason]`, 600 seconds without them.
+pub struct Timeout(pub SharedModeration);
+
+impl Command for Timeout {
+    fn name(&self) -> &'static str {
+        "timeout"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user> [seconds] [reason]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let usage = "timeout @user [seconds] [reason]";
+        let login = match target(&self.0, ctx, &mut args, usage) {
+            Ok(login) => login,
+            Err(answer) => return ctx.send(answer),
+        };
+        // a reason may start with a word, but not with a number
+        let mut peek = args.clone();
+        let seconds = match peek.next() {
+            Some(word) if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                match word.parse() {
+                    Ok(seconds @ 1..=MAX_TIMEOUT) => {
+                        args = peek;
+                        seconds
+                    }
+                    _ => {
+                        return ctx.send(format!(
+                            "A timeout lasts from 1 to {} seconds, two weeks.",
+                            MAX_TIMEOUT
+                        ))
+                    }
+                }
+            }
+            _ => DEFAULT_TIMEOUT,
+        };
+        let reason = args.rest().unwrap_or_default();
+        audit(&self.0, ctx, login, &format!("timeout {}", seconds), reason);
+        Some(ChatBotCommand::Helix(HelixTask::Ban {
+            channel: ctx.message.channel.clone(),
+            login: login.to_owned(),
+            duration: Some(Duration::from_secs(seconds)),
+            reason: reason.to_owned(),
+            answer: true,
+        }))
+    }
+}
+
+/// `!ban @user [reason]`.
+pub struct Ban(pub SharedModeration);
+
+impl Command for Ban {
+    fn name(&self) -> &'static str {
+        "ban"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user> [reason]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let login = match target(&self.0, ctx, &mut args, "ban @user [reason]") {
+            Ok(login) => login,
+            Err(answer) => return ctx.send(answer),
+        };
+        let reason = args.rest().unwrap_or_default();
+        audit(&self.0, ctx, login, "ban", reason);
+        Some(ChatBotCommand::Helix(HelixTask::Ban {
+            channel: ctx.message.channel.clone(),
+            login: login.to_owned(),
+            duration: None,
+            reason: reason.to_owned(),
+            answer: true,
+        }))
+    }
+}
+
+/// `!unban @user` lifts a ban or timeout.
+pub struct Unban(pub SharedModeration);
+
+impl Command for Unban {
+    fn name(&self) -> &'static str {
+        "unban"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user>"
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["untimeout"]
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(login) = args.next().map(|user| user.trim_start_matches('@')) else {
+            return ctx.send(format!("Usage: {}unban @user", ctx.prefix));
+        };
+        audit(&self.0, ctx, login, "unban", "");
+        Some(ChatBotCommand::Helix(HelixTask::Unban {
+            channel: ctx.message.channel.clone(),
+            login: login.to_owned(),
+        }))
+    }
+}
+
+/// `!vip @user`, `!unvip @user`, `!mod @user` and `!unmod @user`, only the broadcaster's
+/// token may change the roles.
+pub struct RoleCommand {
+    pub moderation: SharedModeration,
+    pub role: Role,
+    pub add: bool,
+}
+
+impl Command for RoleCommand {
+    fn name(&self) -> &'static str {
+        match (self.role, self.add) {
+            (Role::Vip, true) => "vip",
+            (Role::Vip, false) => "unvip",
+            (Role::Moderator, true) => "mod",
+            (Role::Moderator, false) => "unmod",
+        }
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Broadcaster
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(login) = args.next().map(|user| user.trim_start_matches('@')) else {
+            return ctx.send(format!("Usage: {}{} @user", ctx.prefix, self.name()));
+        };
+        if self.moderation.borrow().is_bot(login) {
+            return ctx.send("I won't change my own role.".to_owned());
+        }
+        audit(&self.moderation, ctx, login, self.name(), "");
+        Some(ChatBotCommand::Helix(HelixTask::Role {
+            channel: ctx.message.channel.clone(),
+            login: login.to_owned(),
+            role: self.role,
+            add: self.add,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connect::TextMessage, core::moderation::Moderation};
+    use std::{cell::RefCell, rc::Rc, time::Instant};
+
+    fn execute(command: &mut dyn Command, text: &str) -> Option<ChatBotCommand> {
+        let message = TextMessage {
+            channel: "carkhy".to_owned(),
+            text: text.to_owned(),
+            level: UserLevel::Moderator,
+            ..Default::default()
+        };
+        let ctx = Context {
+            message: &message,
+            prefix: "!",
+            now: Instant::now(),
+            replied: None,
+        };
+        command.execute(&ctx, Args::new(text))
+    }
+
+    fn answer(command: &mut dyn Command, text: &str) -> String {
+        match execute(command, text) {
+            Some(ChatBotCommand::SendMessage { text, .. }) => text,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn moderation() -> SharedModeration {
+        let moderation = Moderation::load("CarkhyBot", &Default::default(), Default::default());
+        Rc::new(RefCell::new(moderation.unwrap()))
+    }
+
+    #[test]
+    fn timeouts_take_seconds_and_a_reason() {
+        let mut timeout = Timeout(moderation());
+        let mut ban = |text| match execute(&mut timeout, text) {
+            Some(ChatBotCommand::Helix(HelixTask::Ban {
+                login,
+                duration,
+                reason,
+                ..
+            })) => (login, duration.unwrap().as_secs(), reason),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(ban("@Viewer"), ("Viewer".to_owned(), 600, String::new()));
+        assert_eq!(
+            ban("viewer 30 calm down"),
+            ("viewer".to_owned(), 30, "calm down".to_owned())
+        );
+        assert_eq!(
+            ban("viewer no spam"),
+            ("viewer".to_owned(), 600, "no spam".to_owned())
+        );
+        for text in ["viewer 0", "viewer -5", "viewer 1209601", "viewer 10s"] {
+            assert_eq!(
+                answer(&mut timeout, text),
+                "A timeout lasts from 1 to 1209600 seconds, two weeks.",
+                "{}",
+                text
+            );
+        }
+        assert_eq!(
+            answer(&mut timeout, ""),
+            "Usage: !timeout @user [seconds] [reason]"
+        );
+    }
+
+    #[test]
+    fn the_broadcaster_moderators_and_the_bot_are_protected() {
+        let moderation = moderation();
+        let message = TextMessage {
+            channel: "carkhy".to_owned(),
+            text: "hello".to_owned(),
+            level: UserLevel::Moderator,
+            user: crate::connect::UserInfo {
+                name: "ModFriend".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(moderation
+            .borrow_mut()
+            .check(&message, Instant::now())
+            .is_none());
+        let mut ban = Ban(moderation.clone());
+        for user in ["@Carkhy", "carkhybot", "modfriend"] {
+            assert_eq!(
+                answer(&mut ban, user),
+                format!(
+                    "{} is the broadcaster, a moderator or the bot itself.",
+                    user.trim_start_matches('@')
+                )
+            );
+        }
+        assert!(matches!(
+            execute(&mut ban, "viewer being rude"),
+            Some(ChatBotCommand::Helix(HelixTask::Ban { duration: None, .. }))
+        ));
+        // moderators may be unbanned, that harms nobody
+        assert!(matches!(
+            execute(&mut Unban(moderation), "@modfriend"),
+            Some(ChatBotCommand::Helix(HelixTask::Unban { .. }))
+        ));
+    }
+
+    #[test]
+    fn roles_are_not_changed_for_the_bot() {
+        let mut unmod = RoleCommand {
+            moderation: moderation(),
+            role: Role::Moderator,
+            add: false,
+        };
+        assert_eq!(unmod.name(), "unmod");
+        assert_eq!(
+            answer(&mut unmod, "@CarkhyBot"),
+            "I won't change my own role."
+        );
+        assert_eq!(answer(&mut unmod, ""), "Usage: !unmod @user");
+        assert!(matches!(
+            execute(&mut unmod, "@ModFriend"),
+            Some(ChatBotCommand::Helix(HelixTask::Role { login, add: false, .. }))
+                if login == "ModFriend"
+        ));
+    }
+}
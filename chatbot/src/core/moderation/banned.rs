@@ -0,0 +1,417 @@
+use super::SharedModeration;
+use crate::{
+    config::{BannedTermConfig, Punishment},
+    connect::UserLevel,
+    core::{
+        commands::{Args, Command, Context},
+        ChatBotCommand,
+    },
+    storage::{Storage, StorageError},
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+const STORAGE_NAME: &str = "banned_terms";
+
+// "fr33 f0ll0w3r5" reads "free followers"
+fn normalize(text: &str) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' | '|' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect()
+}
+
+fn same(banned: &BannedTermConfig, term: &str) -> bool {
+    banned.term.to_lowercase() == term.to_lowercase()
+}
+
+fn regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+enum Matcher {
+    // normalized
+    Text(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn matches(&self, text: &str, normalized: &str) -> bool {
+        match self {
+            Matcher::Text(term) => normalized.contains(term.as_str()),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+// what moderators changed with `!banword`, the configured terms stay as they are
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Changes {
+    added: Vec<BannedTermConfig>,
+    // lowercase
+    removed: BTreeSet<String>,
+}
+
+/// Words and phrases not allowed in chat. Terms are compared ignoring case.
+#[derive(Default)]
+pub struct BannedTerms {
+    storage: Storage,
+    configured: Vec<BannedTermConfig>,
+    // the channels with their own configured terms, without the leading '#'
+    channels: HashMap<String, Vec<BannedTermConfig>>,
+    // of every channel, `!banword` changes them all
+    changes: Changes,
+    // of every term in effect, built again after each change
+    matchers: Vec<(Matcher, Punishment)>,
+    channel_matchers: HashMap<String, Vec<(Matcher, Punishment)>>,
+}
+
+impl std::fmt::Debug for BannedTerms {
+    // the terms stay out of the debug output, like anything else printed by the bot
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BannedTerms")
+            .field("terms", &self.matchers.len())
+            .finish()
+    }
+}
+
+impl BannedTerms {
+    /// The configured terms with the changes saved before.
+    pub fn load(configured: &[BannedTermConfig], storage: Storage) -> Result<Self, StorageError> {
+        let mut terms = Self {
+            changes: storage.load(STORAGE_NAME)?,
+            storage,
+            configured: configured.to_vec(),
+            channels: HashMap::new(),
+            matchers: Vec::new(),
+            channel_matchers: HashMap::new(),
+        };
+        terms.compile();
+        Ok(terms)
+    }
+
+    /// Gives the channel its own configured terms instead of the global ones.
+    pub fn scope(&mut self, channel: &str, configured: &[BannedTermConfig]) {
+        let channel = channel.trim_start_matches('#').to_owned();
+        self.channels.insert(channel, configured.to_vec());
+        self.compile();
+    }
+
+    fn compile(&mut self) {
+        self.matchers = compile(self.terms(""));
+        self.channel_matchers = self
+            .channels
+            .keys()
+            .map(|channel| (channel.clone(), compile(self.terms(channel))))
+            .collect();
+    }
+
+    fn configured(&self, channel: &str) -> &[BannedTermConfig] {
+        self.channels.get(channel).unwrap_or(&self.configured)
+    }
+
+    /// The ones in effect in the channel, configured first.
+    pub fn terms(&self, channel: &str) -> Vec<&BannedTermConfig> {
+        let configured = self.configured(channel);
+        let added = self.changes.added.iter();
+        configured
+            .iter()
+            .filter(|banned| !self.changes.removed.contains(&banned.term.to_lowercase()))
+            // added at runtime, unless the channel has it configured as well
+            .chain(added.filter(|banned| !configured.iter().any(|other| same(other, &banned.term))))
+            .collect()
+    }
+
+    fn contains(&self, channel: &str, term: &str) -> bool {
+        self.terms(channel).iter().any(|banned| same(banned, term))
+    }
+
+    /// The harshest punishment of the channel's terms in the text, None without one.
+    pub fn punishment(&self, channel: &str, text: &str) -> Option<Punishment> {
+        let normalized = normalize(text);
+        self.channel_matchers
+            .get(channel)
+            .unwrap_or(&self.matchers)
+            .iter()
+            .filter(|(matcher, _)| matcher.matches(text, &normalized))
+            .map(|&(_, punishment)| punishment)
+            .max()
+    }
+
+    /// False if the term was banned in the channel already, an invalid regex is refused.
+    pub fn add(&mut self, channel: &str, banned: BannedTermConfig) -> Result<bool, regex::Error> {
+        if banned.regex {
+            regex(&banned.term)?;
+        }
+        if self.contains(channel, &banned.term) {
+            return Ok(false);
+        }
+        let term = banned.term.to_lowercase();
+        if !self.changes.removed.remove(&term) {
+            self.changes.added.push(banned);
+        }
+        self.save();
+        Ok(true)
+    }
+
+    /// False if the term wasn't banned in the channel, it goes in every channel.
+    pub fn remove(&mut self, channel: &str, term: &str) -> bool {
+        if !self.contains(channel, term) {
+            return false;
+        }
+        self.changes.added.retain(|banned| !same(banned, term));
+        let mut configured = self.channels.values().flatten().chain(&self.configured);
+        if configured.any(|banned| same(banned, term)) {
+            self.changes.removed.insert(term.to_lowercase());
+        }
+        self.save();
+        true
+    }
+
+    // the change is kept until the bot stops, even if the file can't be written
+    fn save(&mut self) {
+        self.compile();
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.changes) {
+            tracing::warn!(%error, "could not save the banned terms");
+        }
+    }
+}
+
+fn compile(terms: Vec<&BannedTermConfig>) -> Vec<(Matcher, Punishment)> {
+    terms
+        .into_iter()
+        .filter_map(|banned| {
+            let matcher = if banned.regex {
+                // the config is validated and `!banword` checks its regexes,
+                // only an edited file gets here
+                match regex(&banned.term) {
+                    Ok(regex) => Matcher::Regex(regex),
+                    Err(error) => {
+                        tracing::warn!(
+                            term = %banned.term,
+                            %error,
+                            "skipping an invalid banned term"
+                        );
+                        return None;
+                    }
+                }
+            } else {
+                Matcher::Text(normalize(&banned.term))
+            };
+            Some((matcher, banned.punishment))
+        })
+        .collect()
+}
+
+fn describe(punishment: Punishment) -> String {
+    match punishment {
+        Punishment::Delete => "deleted".to_owned(),
+        Punishment::Timeout(seconds) => format!("deleted with a {} second timeout", seconds),
+        Punishment::Ban => "deleted and their author banned".to_owned(),
+    }
+}
+
+/// `!banword add [delete|<seconds>|ban] <term>`, `/<regex>/` instead of the term,
+/// `!banword remove <term>` and `!banword list`. The answers in chat never repeat a term.
+pub struct BanWord(pub SharedModeration);
+
+impl BanWord {
+    fn add(&self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let mut peek = args.clone();
+        let punishment = match peek.next().map(str::to_lowercase).as_deref() {
+            Some("delete") => Some(Punishment::Delete),
+            Some("ban") => Some(Punishment::Ban),
+            Some(word) => word
+                .parse()
+                .ok()
+                .filter(|&seconds| seconds > 0)
+                .map(Punishment::Timeout),
+            None => None,
+        };
+        if punishment.is_some() {
+            args = peek;
+        }
+        let Some(term) = args.rest() else {
+            return ctx.send(format!(
+                "Usage: {}banword add [delete|<seconds>|ban] <term or /regex/>",
+                ctx.prefix
+            ));
+        };
+        let punishment = punishment.unwrap_or(Punishment::Delete);
+        let banned = match term
+            .strip_prefix('/')
+            .and_then(|term| term.strip_suffix('/'))
+        {
+            Some(pattern) if !pattern.is_empty() => BannedTermConfig {
+                term: pattern.to_owned(),
+                regex: true,
+                punishment,
+            },
+            _ => BannedTermConfig {
+                term: term.to_owned(),
+                regex: false,
+                punishment,
+            },
+        };
+        match self.0.borrow_mut().banned.add(&ctx.message.channel, banned) {
+            Ok(true) => ctx.send(format!(
+                "Banned the term, messages with it are {}.",
+                describe(punishment)
+            )),
+            Ok(false) => ctx.send("That term is banned already.".to_owned()),
+            // the error quotes the pattern, so only the log gets it
+            Err(error) => Some(ChatBotCommand::MultipleCommands(vec![
+                ChatBotCommand::LogTextMessage(format!("Refused a banned term: {}", error)),
+                ctx.send("That regex is invalid, the bot's log tells why.".to_owned())?,
+            ])),
+        }
+    }
+}
+
+impl Command for BanWord {
+    fn name(&self) -> &'static str {
+        "banword"
+    }
+
+    fn usage(&self) -> &'static str {
+        "add [delete|<seconds>|ban] <term>, remove <term>, list"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        match args.next().map(str::to_lowercase).as_deref() {
+            Some("add") => self.add(ctx, args),
+            Some("remove") => {
+                let term = args.rest().map(|term| {
+                    term.strip_prefix('/')
+                        .and_then(|term| term.strip_suffix('/'))
+                        .filter(|pattern| !pattern.is_empty())
+                        .unwrap_or(term)
+                });
+                let text = match term {
+                    Some(term) if self.0.borrow_mut().banned.remove(&ctx.message.channel, term) => "Removed the banned term.",
+                    Some(_) => "That term isn't banned.",
+                    None => return ctx.send(format!("Usage: {}banword remove <term>", ctx.prefix)),
+                };
+                ctx.send(text.to_owned())
+            }
+            Some("list") => {
+                let moderation = self.0.borrow();
+                let terms = moderation.banned.terms(&ctx.message.channel);
+                if terms.is_empty() {
+                    return ctx.send("No terms are banned.".to_owned());
+                }
+                let list: Vec<_> = terms
+                    .iter()
+                    .map(|banned| match banned.regex {
+                        true => format!("/{}/ ({:?})", banned.term, banned.punishment),
+                        false => format!("{:?} ({:?})", banned.term, banned.punishment),
+                    })
+                    .collect();
+                Some(ChatBotCommand::MultipleCommands(vec![
+                    ChatBotCommand::LogTextMessage(format!(
+                        "Banned terms: {}",
+                        list.join(", ")
+                    )),
+                    ctx.send(format!(
+                        "{} terms are banned, the bot's log lists them.",
+                        terms.len()
+                    ))?,
+                ]))
+            }
+            _ => ctx.send(format!(
+                "Usage: {0}banword add [delete|<seconds>|ban] <term or /regex/>, {0}banword remove <term> or {0}banword list",
+                ctx.prefix
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(term: &str, regex: bool, punishment: Punishment) -> BannedTermConfig {
+        BannedTermConfig {
+            term: term.to_owned(),
+            regex,
+            punishment,
+        }
+    }
+
+    #[test]
+    fn leetspeak_is_read_as_letters() {
+        assert_eq!(
+            normalize("FR33 F0LL0W3R5 @t $h0p!"),
+            "free followers at shopi"
+        );
+        let terms = BannedTerms::load(
+            &[term("free followers", false, Punishment::Delete)],
+            Storage::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            terms.punishment("", "get fr33 f0ll0w3rs now"),
+            Some(Punishment::Delete)
+        );
+        assert_eq!(
+            terms.punishment("", "Free Followers!"),
+            Some(Punishment::Delete)
+        );
+        assert_eq!(terms.punishment("", "free to follow"), None);
+    }
+
+    #[test]
+    fn the_harshest_punishment_applies() {
+        let configured = [
+            term("spam", false, Punishment::Delete),
+            term("buy", false, Punishment::Timeout(60)),
+            term(r"b[o0]ts?\b", true, Punishment::Timeout(600)),
+        ];
+        let mut terms = BannedTerms::load(&configured, Storage::default()).unwrap();
+        assert_eq!(terms.punishment("", "spam"), Some(Punishment::Delete));
+        assert_eq!(
+            terms.punishment("", "buy spam"),
+            Some(Punishment::Timeout(60))
+        );
+        assert_eq!(
+            terms.punishment("", "buy spam BOTS"),
+            Some(Punishment::Timeout(600))
+        );
+        assert_eq!(terms.punishment("", "the bottle"), None);
+
+        assert!(terms
+            .add("", term("viewbot", false, Punishment::Ban))
+            .unwrap());
+        assert!(!terms.add("", term("SPAM", false, Punishment::Ban)).unwrap());
+        assert_eq!(terms.punishment("", "buy a v1ewb0t"), Some(Punishment::Ban));
+        assert!(terms
+            .add("", term("(unclosed", true, Punishment::Ban))
+            .is_err());
+
+        assert!(terms.remove("", "Viewbot"));
+        assert!(terms.remove("", "buy"));
+        assert!(!terms.remove("", "buy"));
+        assert_eq!(
+            terms.punishment("", "buy a viewbot"),
+            Some(Punishment::Timeout(600))
+        );
+        assert_eq!(
+            terms.punishment("", "buy and spam"),
+            Some(Punishment::Delete)
+        );
+        assert_eq!(terms.terms("").len(), 2);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::connect::{TextMessage, UserLevel};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The text with twitch's emotes like "PogChamp" cut out, the spans are ordered.
+pub fn without_emotes(message: &TextMessage) -> String {
+    let mut words = String::new();
+    let mut end = 0;
+    for emote in &message.emotes {
+        if let Some(before) = message.text.get(end..emote.start) {
+            words.push_str(before);
+            end = emote.end;
+        }
+    }
+    words.push_str(message.text.get(end..).unwrap_or_default());
+    words
+}
+
+// only letters with a case count, so emotes, numbers and scripts like CJK don't shout
+fn uppercase_letters(message: &TextMessage) -> (usize, usize) {
+    let words = without_emotes(message);
+    let cased = words
+        .chars()
+        .filter(|c| c.is_uppercase() || c.is_lowercase());
+    cased.fold((0, 0), |(upper, letters), c| {
+        (upper + usize::from(c.is_uppercase()), letters + 1)
+    })
+}
+
+/// Warns users sending mostly uppercase letters, a second time within the window is removed.
+#[derive(Debug)]
+pub struct CapsFilter {
+    enabled: bool,
+    level: UserLevel,
+    min_length: usize,
+    max_percent: usize,
+    window: Duration,
+    // when a user was last warned, by channel and login
+    warned: HashMap<(String, String), Instant>,
+}
+
+/// What the filter makes of a message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Caps {
+    Fine,
+    Warning,
+    Repeated,
+}
+
+impl CapsFilter {
+    pub fn new(
+        enabled: bool,
+        level: UserLevel,
+        min_length: usize,
+        max_percent: u8,
+        window: Duration,
+    ) -> Self {
+        Self {
+            enabled,
+            level,
+            min_length,
+            max_percent: max_percent.into(),
+            window,
+            warned: HashMap::new(),
+        }
+    }
+
+    fn is_shouting(&self, message: &TextMessage) -> bool {
+        let (upper, letters) = uppercase_letters(message);
+        letters >= self.min_length && upper * 100 > letters * self.max_percent
+    }
+
+    pub fn check(&mut self, message: &TextMessage, level: UserLevel, now: Instant) -> Caps {
+        if !self.enabled || level >= self.level || !self.is_shouting(message) {
+            return Caps::Fine;
+        }
+        let window = self.window;
+        self.warned.retain(|_, warned| now < *warned + window);
+        let key = (message.channel.clone(), message.user.name.to_lowercase());
+        match self.warned.insert(key, now) {
+            Some(_) => Caps::Repeated,
+            None => Caps::Warning,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{testing::message, EmoteSpan};
+
+    fn filter() -> CapsFilter {
+        CapsFilter::new(true, UserLevel::Vip, 10, 70, Duration::from_secs(300))
+    }
+
+    #[test]
+    fn only_letters_outside_emotes_count() {
+        let filter = filter();
+        let mut emotes = message("viewer", "PogChamp KEKW PogChamp LUL KEKW", false);
+        emotes.emotes = [(0, 8), (9, 13), (14, 22), (23, 26), (27, 31)]
+            .into_iter()
+            .map(|(start, end)| EmoteSpan {
+                id: "1".to_owned(),
+                start,
+                end,
+            })
+            .collect();
+        assert!(!filter.is_shouting(&emotes));
+        assert!(!filter.is_shouting(&message(
+            "viewer",
+            "GG 1000 BITS!!! 日本語のメッセージです",
+            false
+        )));
+        assert!(!filter.is_shouting(&message(
+            "viewer",
+            "Grüße aus der Straße, schönes Spiel!",
+            false
+        )));
+        assert!(filter.is_shouting(&message(
+            "viewer",
+            "GRÜßE AUS DER STRAßE, SCHÖNES SPIEL!",
+            false
+        )));
+        assert!(filter.is_shouting(&message(
+            "viewer",
+            "WHY WOULD YOU EVER DO THAT, THIS IS THE WORST PLAY I HAVE SEEN",
+            false
+        )));
+        assert!(!filter.is_shouting(&message("viewer", "OMG LOL", false)));
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_removed() {
+        let mut filter = filter();
+        let shout = message("viewer", "STOP PLAYING LIKE THIS", false);
+        let now = Instant::now();
+        assert_eq!(
+            filter.check(&shout, UserLevel::Everyone, now),
+            Caps::Warning
+        );
+        let later = now + Duration::from_secs(299);
+        assert_eq!(
+            filter.check(&shout, UserLevel::Everyone, later),
+            Caps::Repeated
+        );
+        assert_eq!(filter.check(&shout, UserLevel::Vip, later), Caps::Fine);
+        let much_later = later + Duration::from_secs(300);
+        assert_eq!(
+            filter.check(&shout, UserLevel::Everyone, much_later),
+            Caps::Warning
+        );
+    }
+}
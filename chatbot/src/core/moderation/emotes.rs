@@ -0,0 +1,206 @@
+use super::SharedModeration;
+use crate::{
+    connect::{TextMessage, UserLevel},
+    core::{
+        commands::{Args, Command, Context},
+        ChatBotCommand,
+    },
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// a word repeated this often is taken for an emote of BTTV or FFZ, twitch doesn't tag those
+const REPEATS: usize = 3;
+// the share only matters from a few emotes on, "hi Kappa" is fine
+const SHARE_FROM: usize = 6;
+// how long a `!emotespam off` without minutes lasts
+const CELEBRATION: Duration = Duration::from_secs(10 * 60);
+
+// the emotes and all words of the text
+fn count_emotes(message: &TextMessage) -> (usize, usize) {
+    let text = &message.text;
+    let mut words: Vec<(usize, &str)> = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        let word = &rest[start..];
+        let end = word.find(char::is_whitespace).unwrap_or(word.len());
+        words.push((text.len() - word.len(), &word[..end]));
+        rest = &word[end..];
+    }
+    let is_native = |start: usize| message.emotes.iter().any(|emote| emote.start == start);
+    let mut repeated: HashMap<&str, usize> = HashMap::new();
+    for &(start, word) in &words {
+        if !is_native(start) {
+            *repeated.entry(word).or_default() += 1;
+        }
+    }
+    let emotes = words
+        .iter()
+        .filter(|&&(start, word)| is_native(start) || repeated[word] >= REPEATS)
+        .count();
+    (emotes, words.len())
+}
+
+/// Deletes messages with too many emotes, emote-only ones can be allowed for a while.
+#[derive(Debug)]
+pub struct EmoteFilter {
+    enabled: bool,
+    level: UserLevel,
+    max_emotes: usize,
+    max_percent: usize,
+    // until when messages of only emotes are fine, by channel
+    celebrations: HashMap<String, Instant>,
+}
+
+impl EmoteFilter {
+    pub fn new(enabled: bool, level: UserLevel, max_emotes: usize, max_percent: u8) -> Self {
+        Self {
+            enabled,
+            level,
+            max_emotes,
+            max_percent: max_percent.into(),
+            celebrations: HashMap::new(),
+        }
+    }
+
+    /// Messages of only emotes are fine in the channel until the time, None stops that.
+    pub fn celebrate(&mut self, channel: &str, until: Option<Instant>) {
+        match until {
+            Some(until) => self.celebrations.insert(channel.to_owned(), until),
+            None => self.celebrations.remove(channel),
+        };
+    }
+
+    pub fn is_violation(&self, message: &TextMessage, level: UserLevel, now: Instant) -> bool {
+        if !self.enabled || level >= self.level {
+            return false;
+        }
+        let (emotes, words) = count_emotes(message);
+        let celebrating = self
+            .celebrations
+            .get(&message.channel)
+            .is_some_and(|&until| now < until);
+        // the share is of the words around the emotes, only emotes are judged by the number
+        if emotes == words {
+            return !celebrating && emotes > self.max_emotes;
+        }
+        emotes > self.max_emotes
+            || (emotes >= SHARE_FROM && emotes * 100 > words * self.max_percent)
+    }
+}
+
+/// `!emotespam off [minutes]` allows messages of only emotes for a while, `!emotespam on`
+/// filters them again.
+pub struct EmoteSpam(pub SharedModeration);
+
+impl Command for EmoteSpam {
+    fn name(&self) -> &'static str {
+        "emotespam"
+    }
+
+    fn usage(&self) -> &'static str {
+        "off [minutes], on"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let usage = || {
+            ctx.send(format!(
+                "Usage: {0}emotespam off [minutes] or {0}emotespam on",
+                ctx.prefix
+            ))
+        };
+        let channel = &ctx.message.channel;
+        let mut moderation = self.0.borrow_mut();
+        match args.next() {
+            Some("off") => {
+                let duration = match args.next().map(str::parse::<u64>) {
+                    None => CELEBRATION,
+                    Some(Ok(minutes)) if minutes > 0 => Duration::from_secs(minutes * 60),
+                    Some(_) => return usage(),
+                };
+                moderation
+                    .emotes
+                    .celebrate(channel, Some(ctx.now + duration));
+                ctx.send(format!(
+                    "Messages of only emotes are fine for the next {} minutes.",
+                    duration.as_secs() / 60
+                ))
+            }
+            Some("on") => {
+                moderation.emotes.celebrate(channel, None);
+                ctx.send("Emote spam is filtered again.".to_owned())
+            }
+            _ => usage(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::EmoteSpan;
+
+    // "Kappa" as twitch's emote at each of the positions
+    fn message(text: &str, kappas: &[usize]) -> TextMessage {
+        TextMessage {
+            channel: "carkhy".to_owned(),
+            text: text.to_owned(),
+            emotes: kappas
+                .iter()
+                .map(|&start| EmoteSpan {
+                    id: "25".to_owned(),
+                    start,
+                    end: start + 5,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn kappas(count: usize) -> TextMessage {
+        let text = vec!["Kappa"; count].join(" ");
+        message(
+            &text,
+            &(0..count).map(|index| index * 6).collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn the_limit_is_inclusive() {
+        let filter = EmoteFilter::new(true, UserLevel::Vip, 15, 80);
+        let now = Instant::now();
+        assert!(!filter.is_violation(&kappas(15), UserLevel::Everyone, now));
+        assert!(filter.is_violation(&kappas(16), UserLevel::Everyone, now));
+        assert!(!filter.is_violation(&kappas(16), UserLevel::Vip, now));
+        // 6 of 7 words are emotes, a word twice is no emote yet
+        let text = "so catJAM catJAM catJAM Kappa Kappa Kappa";
+        assert!(filter.is_violation(&message(text, &[24, 30, 36]), UserLevel::Everyone, now));
+        let text = "so catJAM catJAM Kappa Kappa Kappa good";
+        assert!(!filter.is_violation(&message(text, &[17, 23, 29]), UserLevel::Everyone, now));
+        assert!(!filter.is_violation(&kappas(1), UserLevel::Everyone, now));
+    }
+
+    #[test]
+    fn celebrations_allow_only_emotes_for_a_while() {
+        let mut filter = EmoteFilter::new(true, UserLevel::Vip, 15, 80);
+        let now = Instant::now();
+        let mixed = message(
+            &format!("hype {}", vec!["Kappa"; 16].join(" ")),
+            &(0..16).map(|index| 5 + index * 6).collect::<Vec<_>>(),
+        );
+        filter.celebrate("carkhy", Some(now + Duration::from_secs(60)));
+        assert!(!filter.is_violation(&kappas(30), UserLevel::Everyone, now));
+        assert!(filter.is_violation(&mixed, UserLevel::Everyone, now));
+        let later = now + Duration::from_secs(60);
+        assert!(filter.is_violation(&kappas(30), UserLevel::Everyone, later));
+        filter.celebrate("carkhy", Some(later + Duration::from_secs(60)));
+        filter.celebrate("carkhy", None);
+        assert!(filter.is_violation(&kappas(30), UserLevel::Everyone, later));
+    }
+}
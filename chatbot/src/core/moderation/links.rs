@@ -0,0 +1,214 @@
+use crate::connect::UserLevel;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+// the usual generic ones and the ones spammers like, a scheme makes any word a link
+const TLDS: &[&str] = &[
+    "app", "au", "be", "biz", "ca", "cc", "club", "co", "com", "de", "dev", "eu", "fr", "gg",
+    "info", "io", "link", "live", "ly", "me", "net", "nl", "online", "org", "ru", "shop", "site",
+    "store", "to", "top", "tv", "uk", "us", "xyz",
+];
+// spelled out dots, with the spaces around " dot "
+const DOTS: [&str; 4] = ["(dot)", "[dot]", "{dot}", " dot "];
+
+// "(https://Clips.twitch.tv/abc)," links to clips.twitch.tv
+fn host(word: &str) -> Option<&str> {
+    let word = word
+        .trim_start_matches(['(', '[', '<', '"', '\''])
+        .trim_end_matches(['.', ',', '!', '?', ';', ':', ')', ']', '>', '"', '\'']);
+    let (scheme, rest) = match word.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, word),
+    };
+    let host = rest.split(['/', '?', '#']).next()?;
+    // user@host:port
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next()?;
+    match scheme {
+        Some("http" | "https") => (!host.is_empty()).then_some(host),
+        Some(_) => None,
+        None => {
+            let labels: Vec<_> = host.split('.').collect();
+            let is_label = |label: &&str| {
+                !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+            };
+            let tld = labels.last()?;
+            (labels.len() >= 2 && labels.iter().all(is_label) && TLDS.contains(tld)).then_some(host)
+        }
+    }
+}
+
+/// The hosts the text links to in lowercase, e.g. "example dot com" links to example.com.
+pub fn linked_hosts(text: &str) -> Vec<String> {
+    let text = DOTS
+        .iter()
+        .fold(text.to_lowercase(), |text, dot| text.replace(dot, "."));
+    text.split_whitespace()
+        .filter_map(host)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Deletes links of users below a level, unless a moderator permitted one.
+#[derive(Debug)]
+pub struct LinkFilter {
+    enabled: bool,
+    level: UserLevel,
+    // lowercase, their subdomains are allowed as well
+    allowed: Vec<String>,
+    // until when a user may post one link, by channel and login
+    permits: HashMap<(String, String), Instant>,
+    // channels in shield mode, links of everyone below moderator are deleted there
+    shielded: HashSet<String>,
+}
+
+impl LinkFilter {
+    pub fn new(enabled: bool, level: UserLevel, allowed: &[String]) -> Self {
+        Self {
+            enabled,
+            level,
+            allowed: allowed
+                .iter()
+                .map(|domain| domain.trim_start_matches("*.").to_lowercase())
+                .collect(),
+            permits: HashMap::new(),
+            shielded: HashSet::new(),
+        }
+    }
+
+    /// Filters links in the channel while shield mode is on, even if the filter is off.
+    /// Whether shield mode changed.
+    pub fn shield(&mut self, channel: &str, active: bool) -> bool {
+        match active {
+            true => self.shielded.insert(channel.to_owned()),
+            false => self.shielded.remove(channel),
+        }
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.allowed.iter().any(|domain| {
+            host == domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+    }
+
+    /// The user may post a link within the duration, the first one uses the permit up.
+    pub fn permit(&mut self, channel: &str, login: &str, now: Instant, duration: Duration) {
+        self.permits.retain(|_, until| *until > now);
+        self.permits
+            .insert((channel.to_owned(), login.to_lowercase()), now + duration);
+    }
+
+    /// Whether the text has a link the user may not post.
+    pub fn is_violation(
+        &mut self,
+        channel: &str,
+        login: &str,
+        level: UserLevel,
+        text: &str,
+        now: Instant,
+    ) -> bool {
+        let level_needed = match self.shielded.contains(channel) {
+            true => UserLevel::Moderator,
+            false if self.enabled => self.level,
+            false => return false,
+        };
+        if level >= level_needed {
+            return false;
+        }
+        if !linked_hosts(text).iter().any(|host| !self.is_allowed(host)) {
+            return false;
+        }
+        let key = (channel.to_owned(), login.to_lowercase());
+        match self.permits.remove(&key) {
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_are_found_in_words() {
+        assert_eq!(
+            linked_hosts("look at https://Example.org/page?x=1."),
+            ["example.org"]
+        );
+        assert_eq!(linked_hosts("(see www.EXAMPLE.COM)!"), ["www.example.com"]);
+        assert_eq!(
+            linked_hosts("free followers at example dot com"),
+            ["example.com"]
+        );
+        assert_eq!(
+            linked_hosts("bit(dot)ly/abc and x[dot]xyz"),
+            ["bit.ly", "x.xyz"]
+        );
+        assert_eq!(linked_hosts("http://localhost:8080/"), ["localhost"]);
+        assert!(linked_hosts("e.g. this is 1.5 times better...").is_empty());
+        assert!(linked_hosts("why.not, ftp://example.com").is_empty());
+    }
+
+    #[test]
+    fn allowed_domains_include_subdomains() {
+        let filter = LinkFilter::new(
+            true,
+            UserLevel::Vip,
+            &["Twitch.tv".to_owned(), "*.youtube.com".to_owned()],
+        );
+        assert!(filter.is_allowed("twitch.tv"));
+        assert!(filter.is_allowed("clips.twitch.tv"));
+        assert!(filter.is_allowed("www.youtube.com"));
+        assert!(!filter.is_allowed("nottwitch.tv"));
+        assert!(!filter.is_allowed("twitch.tv.example.com"));
+    }
+
+    #[test]
+    fn shield_mode_filters_everyone_below_moderator() {
+        let mut filter = LinkFilter::new(false, UserLevel::Vip, &["twitch.tv".to_owned()]);
+        let now = Instant::now();
+        let link = |filter: &mut LinkFilter, level, text| {
+            filter.is_violation("carkhy", "viewer", level, text, now)
+        };
+        assert!(!link(&mut filter, UserLevel::Everyone, "example.com"));
+        assert!(filter.shield("carkhy", true));
+        assert!(!filter.shield("carkhy", true));
+        assert!(link(&mut filter, UserLevel::Vip, "example.com"));
+        assert!(!link(&mut filter, UserLevel::Moderator, "example.com"));
+        assert!(!link(&mut filter, UserLevel::Everyone, "clips.twitch.tv"));
+        assert!(filter.shield("carkhy", false));
+        assert!(!link(&mut filter, UserLevel::Vip, "example.com"));
+    }
+
+    #[test]
+    fn permits_allow_one_link_until_they_expire() {
+        let mut filter = LinkFilter::new(true, UserLevel::Subscriber, &[]);
+        let now = Instant::now();
+        let mut link = |login: &str, level, now| {
+            filter.is_violation("carkhy", login, level, "see example.com", now)
+        };
+        assert!(link("viewer", UserLevel::Everyone, now));
+        assert!(!link("subscriber", UserLevel::Subscriber, now));
+
+        filter.permit("carkhy", "Viewer", now, Duration::from_secs(60));
+        let mut link =
+            |now| filter.is_violation("carkhy", "viewer", UserLevel::Everyone, "example.com", now);
+        assert!(!link(now + Duration::from_secs(30)));
+        assert!(link(now + Duration::from_secs(31)));
+
+        filter.permit("carkhy", "viewer", now, Duration::from_secs(60));
+        assert!(filter.is_violation(
+            "carkhy",
+            "viewer",
+            UserLevel::Everyone,
+            "example.com",
+            now + Duration::from_secs(61)
+        ));
+    }
+}
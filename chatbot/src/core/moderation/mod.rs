@@ -0,0 +1,392 @@
+mod actions;
+mod banned;
+mod caps;
+mod emotes;
+mod links;
+mod nuke;
+mod punisher;
+mod similar;
+mod symbols;
+
+use super::{commands::level, ChatBotCommand, HelixTask};
+use crate::{
+    config::{Level, ModerationConfig, Punishment},
+    connect::{Overflow, TextMessage, UserLevel},
+    storage::{Storage, StorageError},
+};
+pub use actions::{Ban, RoleCommand, Timeout, Unban};
+pub use banned::BanWord;
+use banned::BannedTerms;
+use caps::{Caps, CapsFilter};
+use emotes::EmoteFilter;
+pub use emotes::EmoteSpam;
+pub use links::linked_hosts;
+use links::LinkFilter;
+pub use nuke::Nuke;
+use nuke::Nuker;
+use punisher::Punisher;
+pub use punisher::{Pardon, Strikes};
+use similar::SimilarityFilter;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+use symbols::SymbolFilter;
+
+// a user is told once a minute, more messages are only removed
+const NOTICE_COOLDOWN: Duration = Duration::from_secs(60);
+// a banned term is worse than spam
+const BANNED_TERM_WEIGHT: usize = 2;
+const CAPS_WARNING: &str = "please don't write in capitals.";
+// without the message's id, a timeout of a second clears the user's messages instead
+const PURGE: Duration = Duration::from_secs(1);
+
+fn user_level(level: Level) -> UserLevel {
+    match level {
+        Level::Everyone => UserLevel::Everyone,
+        Level::Subscriber => UserLevel::Subscriber,
+        Level::Vip => UserLevel::Vip,
+        Level::Moderator => UserLevel::Moderator,
+        Level::Broadcaster => UserLevel::Broadcaster,
+    }
+}
+
+// the message is gone in any case, the reason is shown to the moderators
+fn punish(
+    message: &TextMessage,
+    punishment: Punishment,
+    reason: &str,
+    notice: Option<&str>,
+) -> ChatBotCommand {
+    let (channel, login) = (&message.channel, &message.user.name);
+    let name = message.user.display_name();
+    let ban = |duration| HelixTask::Ban {
+        channel: channel.clone(),
+        login: login.clone(),
+        duration,
+        reason: reason.to_owned(),
+        answer: false,
+    };
+    let (task, log) = match (punishment, &message.message_id) {
+        (Punishment::Delete, Some(id)) => (
+            HelixTask::DeleteMessage {
+                channel: channel.clone(),
+                message_id: id.clone(),
+            },
+            format!("Deleting a message of {}", name),
+        ),
+        (Punishment::Delete, None) => (ban(Some(PURGE)), format!("Purging {}", name)),
+        (Punishment::Timeout(seconds), _) => (
+            ban(Some(Duration::from_secs(seconds))),
+            format!("Timing out {} for {} seconds", name, seconds),
+        ),
+        (Punishment::Ban, _) => (ban(None), format!("Banning {}", name)),
+    };
+    let mut commands = vec![
+        ChatBotCommand::LogTextMessage(format!("{} ({}): {}", log, reason, message.text)),
+        ChatBotCommand::Helix(task),
+    ];
+    if let Some(notice) = notice {
+        commands.push(ChatBotCommand::SendMessage {
+            channel: channel.clone(),
+            text: format!("@{}, {}", name, notice),
+            overflow: Overflow::Truncate,
+        });
+    }
+    ChatBotCommand::MultipleCommands(commands)
+}
+
+// a rule broken by a message
+struct Violation {
+    // strikes the user gets for it
+    weight: usize,
+    reason: &'static str,
+    // told the user, unless they were told a moment ago
+    notice: Option<String>,
+    // punished at least like this, whatever the strikes say
+    minimum: Punishment,
+}
+
+/// The filters keeping order in chat, shared with the commands that change them.
+#[derive(Debug)]
+pub struct Moderation {
+    pub links: LinkFilter,
+    pub banned: BannedTerms,
+    banned_warning: Option<String>,
+    // channels without the leading '#' that have their own
+    channel_warnings: HashMap<String, Option<String>>,
+    caps: CapsFilter,
+    pub emotes: EmoteFilter,
+    symbols: SymbolFilter,
+    similar: SimilarityFilter,
+    pub punisher: Punisher,
+    pub nuker: Nuker,
+    shield_message: String,
+    shield_off_message: String,
+    // the bot's own login, lowercase
+    login: String,
+    // who was seen with a moderator's badge, by channel and lowercase login
+    moderators: HashSet<(String, String)>,
+    // when a user was last told why a message was removed, by channel and login
+    notified: HashMap<(String, String), Instant>,
+}
+
+pub type SharedModeration = Rc<RefCell<Moderation>>;
+
+impl Default for Moderation {
+    fn default() -> Self {
+        Self::load("", &ModerationConfig::default(), Storage::default())
+            .expect("nothing is read without a storage directory")
+    }
+}
+
+impl Moderation {
+    /// With the banned terms and strikes saved in the storage, for the bot with the login.
+    pub fn load(
+        login: &str,
+        config: &ModerationConfig,
+        storage: Storage,
+    ) -> Result<Self, StorageError> {
+        Ok(Self {
+            links: LinkFilter::new(
+                config.link_filter,
+                user_level(config.link_level),
+                &config.allowed_domains,
+            ),
+            banned: BannedTerms::load(&config.banned_terms, storage.clone())?,
+            banned_warning: config.banned_term_warning.clone(),
+            channel_warnings: HashMap::new(),
+            caps: CapsFilter::new(
+                config.caps_filter,
+                user_level(config.caps_level),
+                config.caps_min_length,
+                config.caps_max_percent,
+                Duration::from_secs(config.caps_window),
+            ),
+            emotes: EmoteFilter::new(
+                config.emote_filter,
+                user_level(config.emote_level),
+                config.max_emotes,
+                config.emote_max_percent,
+            ),
+            symbols: SymbolFilter::new(
+                config.symbol_filter,
+                user_level(config.symbol_level),
+                config.symbol_min_length,
+                config.symbol_max_percent,
+                config.max_combining_marks,
+                config.max_repeated_chars,
+            ),
+            similar: SimilarityFilter::new(
+                config.similarity_filter,
+                user_level(config.similarity_level),
+                config.similarity_percent,
+                config.similarity_min_length,
+                Duration::from_secs(config.similarity_window),
+                config.similarity_messages,
+            ),
+            punisher: Punisher::load(
+                Duration::from_secs(config.strike_decay),
+                &config.strike_ladder,
+                storage,
+            )?,
+            nuker: Nuker::new(Duration::from_secs(config.nuke_filter)),
+            shield_message: config.shield_message.clone(),
+            shield_off_message: config.shield_off_message.clone(),
+            login: login.to_lowercase(),
+            moderators: HashSet::new(),
+            notified: HashMap::new(),
+        })
+    }
+
+    /// Takes the banned terms and their warning of a channel from its own config.
+    pub fn scope(&mut self, channel: &str, config: &ModerationConfig) {
+        self.banned.scope(channel, &config.banned_terms);
+        let channel = channel.trim_start_matches('#').to_owned();
+        self.channel_warnings
+            .insert(channel, config.banned_term_warning.clone());
+    }
+
+    /// The broadcaster, moderators who chatted and the bot itself can't be punished.
+    pub fn is_protected(&self, channel: &str, login: &str) -> bool {
+        let login = login.to_lowercase();
+        login == channel
+            || login == self.login
+            || self.moderators.contains(&(channel.to_owned(), login))
+    }
+
+    /// Whether the login is the bot's own.
+    pub fn is_bot(&self, login: &str) -> bool {
+        login.eq_ignore_ascii_case(&self.login)
+    }
+
+    // whether the user is told about the removed message, at most once a minute
+    fn notify(&mut self, channel: &str, login: &str, now: Instant) -> bool {
+        self.notified
+            .retain(|_, notified| now < *notified + NOTICE_COOLDOWN);
+        let key = (channel.to_owned(), login.to_lowercase());
+        if self.notified.contains_key(&key) {
+            return false;
+        }
+        self.notified.insert(key, now);
+        true
+    }
+
+    // the first rule the message breaks, banned terms go before the others and capitals last
+    fn violation(
+        &mut self,
+        message: &TextMessage,
+        level: UserLevel,
+        now: Instant,
+    ) -> Option<Violation> {
+        let (channel, login) = (&message.channel, &message.user.name);
+        if let Some(punishment) = self.banned.punishment(channel, &message.text) {
+            return Some(Violation {
+                weight: BANNED_TERM_WEIGHT,
+                reason: "Used a banned term",
+                notice: self
+                    .channel_warnings
+                    .get(channel)
+                    .unwrap_or(&self.banned_warning)
+                    .clone(),
+                minimum: punishment,
+            });
+        }
+        // a spam wave, the spammers aren't told
+        if let Some(punishment) = self.nuker.armed(channel, &message.text, now) {
+            return Some(Violation {
+                weight: 1,
+                reason: "Said a nuked phrase",
+                notice: None,
+                minimum: punishment,
+            });
+        }
+        let (reason, notice) = if self
+            .links
+            .is_violation(channel, login, level, &message.text, now)
+        {
+            (
+                "Posted a link without permission",
+                "please ask a moderator before posting links.",
+            )
+        } else if self.emotes.is_violation(message, level, now) {
+            ("Emote spam", "please don't spam emotes.")
+        } else if self.symbols.is_violation(&message.text, level) {
+            ("Symbol spam", "please don't spam symbols.")
+        } else if self.similar.is_violation(message, level, now) {
+            ("Repeated spam", "please don't repeat messages.")
+        } else {
+            return None;
+        };
+        Some(Violation {
+            weight: 1,
+            reason,
+            notice: Some(notice.to_owned()),
+            minimum: Punishment::Delete,
+        })
+    }
+
+    /// Tightens the filters while shield mode is on, and announces when it turns on or off.
+    /// A moderator who asked is told when it was on or off already.
+    pub fn shield(&mut self, channel: &str, active: bool, asked: bool) -> Option<ChatBotCommand> {
+        let text = match (self.links.shield(channel, active), active) {
+            (true, true) => self.shield_message.clone(),
+            (true, false) => self.shield_off_message.clone(),
+            (false, true) if asked => "Shield mode is on already.".to_owned(),
+            (false, false) if asked => "Shield mode is off already.".to_owned(),
+            (false, _) => return None,
+        };
+        (!text.is_empty()).then(|| ChatBotCommand::SendMessage {
+            channel: channel.to_owned(),
+            text,
+            overflow: Overflow::Truncate,
+        })
+    }
+
+    /// What the bot does about the message, None if it is fine.
+    pub fn check(&mut self, message: &TextMessage, now: Instant) -> Option<ChatBotCommand> {
+        let (channel, login, level) = (&message.channel, &message.user.name, level(message));
+        if level >= UserLevel::Moderator {
+            self.moderators
+                .insert((channel.clone(), login.to_lowercase()));
+            return None;
+        }
+        let violation = match self.violation(message, level, now) {
+            Some(violation) => violation,
+            None => match self.caps.check(message, level, now) {
+                Caps::Fine => {
+                    self.nuker.remember(message, now);
+                    return None;
+                }
+                // only a warning the first time, no strike yet
+                Caps::Warning => {
+                    self.nuker.remember(message, now);
+                    return Some(ChatBotCommand::SendMessage {
+                        channel: channel.clone(),
+                        text: format!("@{}, {}", message.user.display_name(), CAPS_WARNING),
+                        overflow: Overflow::Truncate,
+                    });
+                }
+                Caps::Repeated => Violation {
+                    weight: 1,
+                    reason: "Too many capitals",
+                    notice: Some(CAPS_WARNING.to_owned()),
+                    minimum: Punishment::Delete,
+                },
+            },
+        };
+        let (_, punishment) = self.punisher.strike(
+            channel,
+            login,
+            violation.weight,
+            violation.reason,
+            SystemTime::now(),
+        );
+        let notice = violation
+            .notice
+            .filter(|_| self.notify(channel, login, now));
+        Some(punish(
+            message,
+            punishment.max(violation.minimum),
+            violation.reason,
+            notice.as_deref(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_are_told_once_a_minute() {
+        let mut moderation = Moderation::default();
+        let now = Instant::now();
+        assert!(moderation.notify("carkhy", "viewer", now));
+        assert!(!moderation.notify("carkhy", "Viewer", now + Duration::from_secs(59)));
+        assert!(moderation.notify("captaincallback", "viewer", now));
+        assert!(moderation.notify("carkhy", "viewer", now + NOTICE_COOLDOWN));
+    }
+
+    #[test]
+    fn shield_mode_is_announced_when_it_changes() {
+        let mut moderation = Moderation::default();
+        let mut shield = |active, asked| match moderation.shield("carkhy", active, asked) {
+            Some(ChatBotCommand::SendMessage { text, .. }) => Some(text),
+            command => command.map(|command| panic!("{:?}", command)),
+        };
+        assert_eq!(
+            shield(true, false).as_deref(),
+            Some("Shield mode is on, links are removed for everyone below moderator.")
+        );
+        // EventSub tells again after the command turned it on
+        assert_eq!(shield(true, false), None);
+        assert_eq!(
+            shield(true, true).as_deref(),
+            Some("Shield mode is on already.")
+        );
+        assert_eq!(shield(false, true).as_deref(), Some("Shield mode is off."));
+    }
+}
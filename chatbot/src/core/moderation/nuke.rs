@@ -0,0 +1,350 @@
+use super::{actions::MAX_TIMEOUT, SharedModeration};
+use crate::{
+    config::Punishment,
+    connect::{ChatBotEvent, InternalEvent, Overflow, TextMessage, UserLevel},
+    core::{
+        calendar::timestamp,
+        commands::{Args, Command, Context},
+        ChatBotCommand, HelixTask,
+    },
+};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    time::{Duration, Instant, SystemTime},
+};
+
+// how far back `!nuke` looks
+const HISTORY: Duration = Duration::from_secs(10 * 60);
+// messages kept per channel, during a flood the oldest go early
+const HISTORY_LIMIT: usize = 2000;
+// twitch allows 800 requests a minute, this leaves most of them for everything else
+const BATCH: usize = 10;
+const BATCH_PAUSE: Duration = Duration::from_secs(2);
+
+// a message that can still be deleted
+#[derive(Debug)]
+struct Said {
+    at: Instant,
+    login: String,
+    message_id: String,
+    // lowercase
+    text: String,
+}
+
+// a phrase nuked before anyone said it
+#[derive(Debug)]
+struct Armed {
+    // lowercase
+    phrase: String,
+    punishment: Punishment,
+    until: Instant,
+}
+
+/// Remembers the recent messages of each channel, so `!nuke` can remove them
+/// after a spam wave. The actions are sent a few at a time.
+#[derive(Debug)]
+pub struct Nuker {
+    filter: Duration,
+    history: HashMap<String, VecDeque<Said>>,
+    armed: HashMap<String, Vec<Armed>>,
+    // actions waiting for their batch, each nuke's summary after its last one
+    pending: VecDeque<ChatBotCommand>,
+    // whether a NukeTick is scheduled, it takes the next batch
+    ticking: bool,
+}
+
+impl Nuker {
+    /// Phrases nuked before anyone said them stay filtered for the duration.
+    pub fn new(filter: Duration) -> Self {
+        Self {
+            filter,
+            history: HashMap::new(),
+            armed: HashMap::new(),
+            pending: VecDeque::new(),
+            ticking: false,
+        }
+    }
+
+    /// Keeps the message for a later nuke, messages without an id can't be deleted.
+    pub fn remember(&mut self, message: &TextMessage, now: Instant) {
+        let Some(id) = &message.message_id else {
+            return;
+        };
+        let history = self.history.entry(message.channel.clone()).or_default();
+        while history
+            .front()
+            .is_some_and(|said| history.len() >= HISTORY_LIMIT || said.at + HISTORY <= now)
+        {
+            history.pop_front();
+        }
+        history.push_back(Said {
+            at: now,
+            login: message.user.name.to_lowercase(),
+            message_id: id.clone(),
+            text: message.text.to_lowercase(),
+        });
+    }
+
+    /// The punishment for the text if it has an armed phrase.
+    pub fn armed(&mut self, channel: &str, text: &str, now: Instant) -> Option<Punishment> {
+        let armed = self.armed.get_mut(channel)?;
+        armed.retain(|armed| now < armed.until);
+        let text = text.to_lowercase();
+        armed
+            .iter()
+            .filter(|armed| text.contains(&armed.phrase))
+            .map(|armed| armed.punishment)
+            .max()
+    }
+
+    /// Queues a deletion of each recent message with the phrase, or a timeout of each
+    /// user who said it. Without one, the phrase is armed instead. Returns the number
+    /// of messages and users.
+    pub fn nuke(
+        &mut self,
+        channel: &str,
+        phrase: &str,
+        timeout: Option<u64>,
+        now: Instant,
+    ) -> (usize, usize) {
+        let phrase = phrase.to_lowercase();
+        let history = self.history.entry(channel.to_owned()).or_default();
+        let (nuked, kept) = history
+            .drain(..)
+            .filter(|said| said.at + HISTORY > now)
+            .partition::<VecDeque<_>, _>(|said| said.text.contains(&phrase));
+        *history = kept;
+        if nuked.is_empty() {
+            self.armed
+                .entry(channel.to_owned())
+                .or_default()
+                .push(Armed {
+                    phrase,
+                    punishment: timeout.map_or(Punishment::Delete, Punishment::Timeout),
+                    until: now + self.filter,
+                });
+            return (0, 0);
+        }
+        let users: BTreeSet<_> = nuked.iter().map(|said| said.login.clone()).collect();
+        match timeout {
+            // the timeout removes the user's messages as well
+            Some(seconds) => self.pending.extend(users.iter().map(|login| {
+                ChatBotCommand::Helix(HelixTask::Ban {
+                    channel: channel.to_owned(),
+                    login: login.clone(),
+                    duration: Some(Duration::from_secs(seconds)),
+                    reason: "Nuked".to_owned(),
+                    answer: false,
+                })
+            })),
+            None => self.pending.extend(nuked.iter().map(|said| {
+                ChatBotCommand::Helix(HelixTask::DeleteMessage {
+                    channel: channel.to_owned(),
+                    message_id: said.message_id.clone(),
+                })
+            })),
+        }
+        let (messages, users) = (nuked.len(), users.len());
+        self.pending.push_back(ChatBotCommand::SendMessage {
+            channel: channel.to_owned(),
+            text: format!(
+                "Nuked {} from {}.",
+                count(messages, "message"),
+                count(users, "user")
+            ),
+            overflow: Overflow::Truncate,
+        });
+        (messages, users)
+    }
+
+    /// The next batch after a NukeTick.
+    pub fn tick(&mut self) -> Option<ChatBotCommand> {
+        self.ticking = false;
+        self.batch()
+    }
+
+    // None while a tick is scheduled, it takes the batch
+    fn batch(&mut self) -> Option<ChatBotCommand> {
+        if self.ticking || self.pending.is_empty() {
+            return None;
+        }
+        let size = BATCH.min(self.pending.len());
+        let mut commands: Vec<_> = self.pending.drain(..size).collect();
+        // a summary doesn't wait for the next batch
+        while let Some(ChatBotCommand::SendMessage { .. }) = self.pending.front() {
+            commands.extend(self.pending.pop_front());
+        }
+        if !self.pending.is_empty() {
+            self.ticking = true;
+            commands.push(ChatBotCommand::TimedCallback {
+                duration: BATCH_PAUSE,
+                event: ChatBotEvent::Internal(InternalEvent::NukeTick),
+            });
+        }
+        Some(ChatBotCommand::MultipleCommands(commands))
+    }
+}
+
+// "1 message", "14 messages"
+fn count(amount: usize, unit: &str) -> String {
+    match amount {
+        1 => format!("1 {}", unit),
+        _ => format!("{} {}s", amount, unit),
+    }
+}
+
+/// `!nuke <phrase> [seconds]` deletes the messages of the last ten minutes with the phrase,
+/// with the seconds it times out everyone who said it.
+pub struct Nuke(pub SharedModeration);
+
+impl Command for Nuke {
+    fn name(&self) -> &'static str {
+        "nuke"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<phrase> [seconds]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(rest) = args.rest() else {
+            return ctx.send(format!("Usage: {}nuke <phrase> [seconds]", ctx.prefix));
+        };
+        let (phrase, timeout) = match rest.rsplit_once(' ') {
+            Some((phrase, seconds)) if seconds.starts_with(|c: char| c.is_ascii_digit()) => {
+                match seconds.parse() {
+                    Ok(seconds @ 1..=MAX_TIMEOUT) => (phrase.trim_end(), Some(seconds)),
+                    _ => {
+                        return ctx.send(format!(
+                            "A timeout lasts from 1 to {} seconds, two weeks.",
+                            MAX_TIMEOUT
+                        ))
+                    }
+                }
+            }
+            _ => (rest, None),
+        };
+        let mut moderation = self.0.borrow_mut();
+        let channel = &ctx.message.channel;
+        let (messages, users) = moderation.nuker.nuke(channel, phrase, timeout, ctx.now);
+        moderation.punisher.audit(&format!(
+            "{} #{}: nuked {} messages from {} users by {}, {} ({:?})",
+            timestamp(SystemTime::now()),
+            channel,
+            messages,
+            users,
+            ctx.message.user.name,
+            timeout.map_or("deleted".to_owned(), |seconds| format!(
+                "timeout {}",
+                seconds
+            )),
+            phrase
+        ));
+        if messages == 0 {
+            // the phrase isn't repeated, it may well be spam
+            return ctx.send(format!(
+                "No recent message has it, new ones are removed for the next {} minutes.",
+                moderation.nuker.filter.as_secs().div_ceil(60)
+            ));
+        }
+        moderation.nuker.batch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing;
+
+    fn message(login: &str, id: usize, text: &str) -> TextMessage {
+        TextMessage {
+            message_id: Some(id.to_string()),
+            ..testing::message(login, text, false)
+        }
+    }
+
+    // the helix tasks of each batch until the summary
+    fn batches(nuker: &mut Nuker, mut batch: Option<ChatBotCommand>) -> (Vec<usize>, String) {
+        let mut sizes = Vec::new();
+        loop {
+            let Some(ChatBotCommand::MultipleCommands(commands)) = batch else {
+                panic!("{:?}", batch);
+            };
+            sizes.push(
+                commands
+                    .iter()
+                    .filter(|command| matches!(command, ChatBotCommand::Helix(_)))
+                    .count(),
+            );
+            match commands.last() {
+                Some(ChatBotCommand::SendMessage { text, .. }) => return (sizes, text.clone()),
+                Some(ChatBotCommand::TimedCallback {
+                    duration: BATCH_PAUSE,
+                    event: ChatBotEvent::Internal(InternalEvent::NukeTick),
+                }) => {
+                    // a second nuke meanwhile waits for the scheduled tick
+                    assert!(nuker.batch().is_none());
+                    batch = nuker.tick();
+                }
+                _ => panic!("{:?}", commands),
+            }
+        }
+    }
+
+    #[test]
+    fn fifty_spammers_are_timed_out_in_batches() {
+        let mut nuker = Nuker::new(Duration::from_secs(300));
+        let now = Instant::now();
+        for user in 0..50 {
+            nuker.remember(
+                &message(&format!("bot{}", user), user * 2, "Buy FOLLOWS at scam"),
+                now,
+            );
+            nuker.remember(&message(&format!("bot{}", user), user * 2 + 1, "hi"), now);
+        }
+        nuker.remember(&message("viewer", 100, "what's that follows thing"), now);
+        nuker.remember(&message("viewer", 101, "hello"), now);
+
+        let later = now + Duration::from_secs(60);
+        assert_eq!(
+            nuker.nuke("carkhy", "buy follows", Some(60), later),
+            (50, 50)
+        );
+        let batch = nuker.batch();
+        let (sizes, summary) = batches(&mut nuker, batch);
+        assert_eq!(sizes, [10, 10, 10, 10, 10]);
+        assert_eq!(summary, "Nuked 50 messages from 50 users.");
+
+        // the nuked messages are gone, the others only once they are too old
+        assert_eq!(nuker.nuke("carkhy", "FOLLOWS", None, later), (1, 1));
+        let batch = nuker.batch();
+        assert_eq!(
+            batches(&mut nuker, batch),
+            (vec![1], "Nuked 1 message from 1 user.".to_owned())
+        );
+        assert_eq!(nuker.nuke("carkhy", "hello", None, now + HISTORY), (0, 0));
+        assert!(nuker.batch().is_none());
+    }
+
+    #[test]
+    fn a_phrase_nobody_said_yet_is_armed() {
+        let mut nuker = Nuker::new(Duration::from_secs(300));
+        let now = Instant::now();
+        nuker.remember(&message("viewer", 1, "hello"), now);
+        assert_eq!(nuker.nuke("carkhy", "Free Subs", Some(600), now), (0, 0));
+        let soon = now + Duration::from_secs(299);
+        assert_eq!(
+            nuker.armed("carkhy", "FREE SUBS here", soon),
+            Some(Punishment::Timeout(600))
+        );
+        assert_eq!(nuker.armed("captaincallback", "free subs", soon), None);
+        assert_eq!(
+            nuker.armed("carkhy", "free subs", now + Duration::from_secs(300)),
+            None
+        );
+    }
+}
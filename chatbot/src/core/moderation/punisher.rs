@@ -0,0 +1,267 @@
+use super::SharedModeration;
+use crate::{
+    config::Punishment,
+    connect::UserLevel,
+    core::{
+        calendar::timestamp,
+        commands::{Args, Command, Context},
+        ChatBotCommand,
+    },
+    storage::{Storage, StorageError},
+};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const STORAGE_NAME: &str = "strikes";
+const AUDIT_LOG: &str = "moderation";
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn describe(punishment: Punishment) -> String {
+    match punishment {
+        Punishment::Delete => "a deleted message".to_owned(),
+        Punishment::Timeout(seconds) => format!("a {} second timeout", seconds),
+        Punishment::Ban => "a ban".to_owned(),
+    }
+}
+
+/// Counts the strikes of users breaking the rules, more strikes get harsher punishments.
+/// A strike expires on its own after a while. The strikes are kept in the storage,
+/// so a restart forgives nobody.
+#[derive(Debug)]
+pub struct Punisher {
+    storage: Storage,
+    decay: Duration,
+    // by the number of strikes, the last one for any more
+    ladder: Vec<Punishment>,
+    // by channel and lowercase login, when each strike was given in seconds since 1970
+    strikes: BTreeMap<String, BTreeMap<String, Vec<u64>>>,
+}
+
+impl Punisher {
+    pub fn load(
+        decay: Duration,
+        ladder: &[Punishment],
+        storage: Storage,
+    ) -> Result<Self, StorageError> {
+        Ok(Self {
+            strikes: storage.load(STORAGE_NAME)?,
+            storage,
+            decay,
+            ladder: ladder.to_vec(),
+        })
+    }
+
+    // the strikes of the user that haven't expired yet
+    fn current(&mut self, channel: &str, login: &str, now: SystemTime) -> &mut Vec<u64> {
+        let (now, decay) = (seconds(now), self.decay.as_secs());
+        let strikes = self
+            .strikes
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(login.to_lowercase())
+            .or_default();
+        strikes.retain(|&given| now < given + decay);
+        strikes
+    }
+
+    fn punishment(&self, strikes: usize) -> Punishment {
+        let step = strikes.clamp(1, self.ladder.len().max(1)) - 1;
+        self.ladder.get(step).copied().unwrap_or(Punishment::Delete)
+    }
+
+    /// Adds the weight of the violation to the user's strikes, the punishment is for all of them.
+    /// The reason is recorded in the audit log.
+    pub fn strike(
+        &mut self,
+        channel: &str,
+        login: &str,
+        weight: usize,
+        reason: &str,
+        now: SystemTime,
+    ) -> (usize, Punishment) {
+        let strikes = self.current(channel, login, now);
+        strikes.extend(std::iter::repeat_n(seconds(now), weight.max(1)));
+        let count = strikes.len();
+        let punishment = self.punishment(count);
+        self.save();
+        self.audit(&format!(
+            "{} #{} {}: {} for strike {} ({})",
+            timestamp(now),
+            channel,
+            login,
+            describe(punishment),
+            count,
+            reason
+        ));
+        (count, punishment)
+    }
+
+    /// How many strikes the user has right now.
+    pub fn strikes(&mut self, channel: &str, login: &str, now: SystemTime) -> usize {
+        self.current(channel, login, now).len()
+    }
+
+    /// Forgives every strike of the user, the number of them is returned.
+    pub fn pardon(&mut self, channel: &str, login: &str, now: SystemTime) -> usize {
+        let count = self.strikes(channel, login, now);
+        if let Some(users) = self.strikes.get_mut(channel) {
+            users.remove(&login.to_lowercase());
+        }
+        self.save();
+        count
+    }
+
+    /// Writes the line to `moderation.log` in the storage directory.
+    pub fn audit(&self, line: &str) {
+        if let Err(error) = self.storage.append(AUDIT_LOG, line) {
+            tracing::warn!(%error, "could not log the moderation");
+        }
+    }
+
+    // users without strikes are left out, so the file doesn't grow forever
+    fn save(&mut self) {
+        for users in self.strikes.values_mut() {
+            users.retain(|_, strikes| !strikes.is_empty());
+        }
+        self.strikes.retain(|_, users| !users.is_empty());
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.strikes) {
+            tracing::warn!(%error, "could not save the strikes");
+        }
+    }
+}
+
+/// `!strikes @user` tells how many strikes the user has and what the next one brings.
+pub struct Strikes(pub SharedModeration);
+
+impl Command for Strikes {
+    fn name(&self) -> &'static str {
+        "strikes"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(user) = args.next().map(|user| user.trim_start_matches('@')) else {
+            return ctx.send(format!("Usage: {}strikes @user", ctx.prefix));
+        };
+        let mut moderation = self.0.borrow_mut();
+        let punisher = &mut moderation.punisher;
+        let count = punisher.strikes(&ctx.message.channel, user, SystemTime::now());
+        let next = describe(punisher.punishment(count + 1));
+        ctx.send(match count {
+            0 => format!("{} has no strikes, the first one brings {}.", user, next),
+            1 => format!("{} has 1 strike, the next one brings {}.", user, next),
+            _ => format!(
+                "{} has {} strikes, the next one brings {}.",
+                user, count, next
+            ),
+        })
+    }
+}
+
+/// `!pardon @user` forgives every strike of the user.
+pub struct Pardon(pub SharedModeration);
+
+impl Command for Pardon {
+    fn name(&self) -> &'static str {
+        "pardon"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(user) = args.next().map(|user| user.trim_start_matches('@')) else {
+            return ctx.send(format!("Usage: {}pardon @user", ctx.prefix));
+        };
+        let now = SystemTime::now();
+        let moderation = &mut self.0.borrow_mut().punisher;
+        let count = moderation.pardon(&ctx.message.channel, user, now);
+        if count == 0 {
+            return ctx.send(format!("{} has no strikes.", user));
+        }
+        moderation.audit(&format!(
+            "{} #{} {}: pardoned by {}, {} strikes",
+            timestamp(now),
+            ctx.message.channel,
+            user.to_lowercase(),
+            ctx.message.user.name,
+            count
+        ));
+        ctx.send(format!("Pardoned {}, their strikes are gone.", user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LADDER: [Punishment; 4] = [
+        Punishment::Delete,
+        Punishment::Timeout(60),
+        Punishment::Timeout(600),
+        Punishment::Ban,
+    ];
+
+    fn punisher(decay: u64) -> Punisher {
+        Punisher::load(Duration::from_secs(decay), &LADDER, Storage::default()).unwrap()
+    }
+
+    #[test]
+    fn strikes_climb_the_ladder() {
+        let mut punisher = punisher(3600);
+        let now = SystemTime::now();
+        let mut strike = |weight| punisher.strike("carkhy", "Viewer", weight, "test", now);
+        assert_eq!(strike(1), (1, Punishment::Delete));
+        assert_eq!(strike(1), (2, Punishment::Timeout(60)));
+        assert_eq!(strike(1), (3, Punishment::Timeout(600)));
+        assert_eq!(strike(1), (4, Punishment::Ban));
+        assert_eq!(strike(2), (6, Punishment::Ban));
+        assert_eq!(punisher.strikes("carkhy", "viewer", now), 6);
+        assert_eq!(punisher.strikes("captaincallback", "viewer", now), 0);
+        assert_eq!(punisher.pardon("carkhy", "VIEWER", now), 6);
+        assert_eq!(
+            punisher.strike("carkhy", "viewer", 2, "test", now),
+            (2, Punishment::Timeout(60))
+        );
+    }
+
+    #[test]
+    fn each_strike_expires_on_its_own() {
+        let mut punisher = punisher(600);
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |seconds| start + Duration::from_secs(seconds);
+        punisher.strike("carkhy", "viewer", 1, "test", at(0));
+        punisher.strike("carkhy", "viewer", 1, "test", at(300));
+        assert_eq!(punisher.strikes("carkhy", "viewer", at(599)), 2);
+        // the first one is gone after 600 seconds, the second one 300 seconds later
+        assert_eq!(punisher.strikes("carkhy", "viewer", at(600)), 1);
+        assert_eq!(
+            punisher.strike("carkhy", "viewer", 1, "test", at(700)),
+            (2, Punishment::Timeout(60))
+        );
+        assert_eq!(punisher.strikes("carkhy", "viewer", at(900)), 1);
+        assert_eq!(punisher.strikes("carkhy", "viewer", at(1300)), 0);
+        assert!(punisher.strikes.contains_key("carkhy"));
+        punisher.save();
+        assert!(punisher.strikes.is_empty());
+    }
+}
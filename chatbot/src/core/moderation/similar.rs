@@ -0,0 +1,222 @@
+use super::caps::without_emotes;
+use crate::connect::{TextMessage, UserLevel};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+// characters of a message compared, the rest of a long one doesn't matter
+const MAX_CHARS: usize = 150;
+
+// lowercase letters and digits, the words separated by single spaces
+fn normalize(message: &TextMessage) -> Vec<char> {
+    let mut text = Vec::new();
+    for c in without_emotes(message).chars() {
+        if c.is_alphanumeric() {
+            text.extend(c.to_lowercase());
+        } else if c.is_whitespace() && text.last().is_some_and(|last| *last != ' ') {
+            text.push(' ');
+        }
+        if text.len() >= MAX_CHARS {
+            break;
+        }
+    }
+    if text.last() == Some(&' ') {
+        text.pop();
+    }
+    text.truncate(MAX_CHARS);
+    text
+}
+
+// the edits turning one into the other, in two rows
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let replaced = previous[j] + usize::from(a != b);
+            current[j + 1] = replaced.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+// whether at least the percent of the longer text stays the same
+fn is_similar(a: &[char], b: &[char], percent: usize) -> bool {
+    let longest = a.len().max(b.len());
+    let least_edits = a.len().abs_diff(b.len());
+    // the lengths alone can tell, without comparing
+    if (longest - least_edits) * 100 < percent * longest {
+        return false;
+    }
+    (longest - distance(a, b)) * 100 >= percent * longest
+}
+
+// a message of the window
+#[derive(Debug)]
+struct Seen {
+    at: Instant,
+    // lowercase
+    login: String,
+    text: Vec<char>,
+    flagged: bool,
+}
+
+/// Catches messages like one the user sent a moment ago, or like spam of another user,
+/// also when they vary a little.
+#[derive(Debug)]
+pub struct SimilarityFilter {
+    enabled: bool,
+    level: UserLevel,
+    percent: usize,
+    min_length: usize,
+    window: Duration,
+    messages: usize,
+    recent: HashMap<String, VecDeque<Seen>>,
+}
+
+impl SimilarityFilter {
+    /// Keeps the last messages of each channel within the window.
+    pub fn new(
+        enabled: bool,
+        level: UserLevel,
+        percent: u8,
+        min_length: usize,
+        window: Duration,
+        messages: usize,
+    ) -> Self {
+        Self {
+            enabled,
+            level,
+            percent: percent.into(),
+            min_length,
+            window,
+            messages,
+            recent: HashMap::new(),
+        }
+    }
+
+    pub fn is_violation(&mut self, message: &TextMessage, level: UserLevel, now: Instant) -> bool {
+        if !self.enabled || level >= self.level {
+            return false;
+        }
+        let text = normalize(message);
+        if text.len() < self.min_length {
+            return false;
+        }
+        let recent = self.recent.entry(message.channel.clone()).or_default();
+        let (window, messages) = (self.window, self.messages);
+        while recent
+            .front()
+            .is_some_and(|seen| recent.len() >= messages || seen.at + window <= now)
+        {
+            recent.pop_front();
+        }
+        let login = message.user.name.to_lowercase();
+        // only the user's own messages and spam are compared, others may say the same
+        let flagged = recent.iter().any(|seen| {
+            (seen.login == login || seen.flagged) && is_similar(&seen.text, &text, self.percent)
+        });
+        if self.messages > 0 {
+            recent.push_back(Seen {
+                at: now,
+                login,
+                text,
+                flagged,
+            });
+        }
+        flagged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{testing::message, EmoteSpan};
+
+    fn filter() -> SimilarityFilter {
+        SimilarityFilter::new(true, UserLevel::Vip, 80, 10, Duration::from_secs(60), 50)
+    }
+
+    #[test]
+    fn texts_are_compared_without_emotes_and_punctuation() {
+        let mut emote = message("viewer", "Kappa Best   viewers at bigfollows*com!!", false);
+        emote.emotes = vec![EmoteSpan {
+            id: "25".to_owned(),
+            start: 0,
+            end: 5,
+        }];
+        let normalized: String = normalize(&emote).into_iter().collect();
+        assert_eq!(normalized, "best viewers at bigfollowscom");
+        let text = |text: &str| normalize(&message("viewer", text, false));
+        assert!(is_similar(
+            &text("Best viewers at bigfollows.com 1"),
+            &text("BEST VIEWERS @ bigfollows . com #2"),
+            80
+        ));
+        assert!(!is_similar(
+            &text("Best viewers at bigfollows.com"),
+            &text("what game is this again?"),
+            80
+        ));
+        assert_eq!(
+            normalize(&message("viewer", &"a".repeat(500), false)).len(),
+            MAX_CHARS
+        );
+        assert_eq!(distance(&text("kitten"), &text("sitting")), 3);
+    }
+
+    #[test]
+    fn a_spam_wave_is_caught() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let wave = [
+            ("spammer1", "Best viewers at bigfollows.com 1"),
+            ("spammer1", "Best viewers at bigfollows.com 2"),
+            ("spammer2", "best viewers @ bigfollows.com !!"),
+            ("spammer3", "Best viewers at bigfollows dot com"),
+            ("spammer4", "BEST VIEWERS AT BIGFOLLOWS.COM"),
+            ("spammer5", "Best viewerz at bigfollows.com"),
+            ("spammer2", "Best viewers at bigfollows.com 3"),
+            ("spammer3", "Best viewers at bigfollows.com 4"),
+            ("spammer4", "Best viewers at bigfollows.com 5"),
+            ("spammer5", "Best viewers at bigfollows.com 6"),
+        ];
+        let caught: Vec<bool> = wave
+            .iter()
+            .map(|(login, text)| {
+                filter.is_violation(&message(login, text, false), UserLevel::Everyone, now)
+            })
+            .collect();
+        // the first is like nothing before, the second repeats it, then the others copy spam
+        assert_eq!(
+            caught,
+            [false, true, true, true, true, true, true, true, true, true]
+        );
+        let chat = message("viewer", "what game is this again?", false);
+        assert!(!filter.is_violation(&chat, UserLevel::Everyone, now));
+        // the spam is forgotten after the window
+        let later = now + Duration::from_secs(60);
+        let copy = message("latecomer", "Best viewers at bigfollows.com 7", false);
+        assert!(!filter.is_violation(&copy, UserLevel::Everyone, later));
+    }
+
+    #[test]
+    fn others_saying_the_same_is_fine() {
+        let mut filter = filter();
+        let now = Instant::now();
+        let mut check = |login: &str, text: &str, level| {
+            filter.is_violation(&message(login, text, false), level, now)
+        };
+        assert!(!check("viewer", "good game everyone", UserLevel::Everyone));
+        assert!(!check("carkhy", "good game everyone!", UserLevel::Everyone));
+        // too short to compare, and VIPs aren't checked
+        assert!(!check("viewer", "lol", UserLevel::Everyone));
+        assert!(!check("viewer", "lol", UserLevel::Everyone));
+        assert!(!check("vip", "hype hype hype", UserLevel::Vip));
+        assert!(!check("vip", "hype hype hype", UserLevel::Vip));
+        assert!(check("viewer", "Good game everyone", UserLevel::Everyone));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::connect::UserLevel;
+
+// the blocks of combining marks zalgo is made of, the vowel signs of scripts like
+// Devanagari or Thai are combining as well and stay out of it
+fn is_combining(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+/// How a text is made up, whitespace doesn't count.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Shape {
+    chars: usize,
+    // neither letters nor digits of any script, like punctuation or box drawing
+    symbols: usize,
+    // the most combining marks on one character
+    marks: usize,
+    // the longest run of the same character
+    run: usize,
+}
+
+fn shape(text: &str) -> Shape {
+    let mut shape = Shape::default();
+    let (mut marks, mut run, mut last) = (0, 0, None);
+    for c in text.chars() {
+        if is_combining(c) {
+            marks += 1;
+            shape.marks = shape.marks.max(marks);
+            continue;
+        }
+        marks = 0;
+        run = if last == Some(c) { run + 1 } else { 1 };
+        last = Some(c);
+        if c.is_whitespace() {
+            continue;
+        }
+        shape.run = shape.run.max(run);
+        shape.chars += 1;
+        if !c.is_alphanumeric() {
+            shape.symbols += 1;
+        }
+    }
+    shape
+}
+
+/// Deletes walls of symbols, zalgo and long runs of one character like ASCII art.
+#[derive(Debug)]
+pub struct SymbolFilter {
+    enabled: bool,
+    level: UserLevel,
+    min_length: usize,
+    max_percent: usize,
+    max_marks: usize,
+    max_run: usize,
+}
+
+impl SymbolFilter {
+    pub fn new(
+        enabled: bool,
+        level: UserLevel,
+        min_length: usize,
+        max_percent: u8,
+        max_marks: usize,
+        max_run: usize,
+    ) -> Self {
+        Self {
+            enabled,
+            level,
+            min_length,
+            max_percent: max_percent.into(),
+            max_marks,
+            max_run,
+        }
+    }
+
+    pub fn is_violation(&self, text: &str, level: UserLevel) -> bool {
+        if !self.enabled || level >= self.level {
+            return false;
+        }
+        let shape = shape(text);
+        shape.marks > self.max_marks
+            || shape.run > self.max_run
+            || (shape.chars >= self.min_length
+                && shape.symbols * 100 > shape.chars * self.max_percent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> SymbolFilter {
+        SymbolFilter::new(true, UserLevel::Vip, 10, 50, 2, 20)
+    }
+
+    fn is_spam(text: &str) -> bool {
+        filter().is_violation(text, UserLevel::Everyone)
+    }
+
+    #[test]
+    fn zalgo_and_walls_are_spam() {
+        assert!(is_spam("h\u{0335}\u{0321}\u{0334}e\u{0336}\u{0322}llo"));
+        assert!(!is_spam("Vie\u{0323}\u{0302}t Nam"));
+        assert_eq!(shape("Vie\u{0323}\u{0302}t").marks, 2);
+        assert!(is_spam(&"█".repeat(21)));
+        assert!(!is_spam(&format!("n{}ice", "o".repeat(20))));
+        assert!(is_spam(&format!("n{}ice", "o".repeat(21))));
+        assert!(is_spam("!!!!!! ??? #### ~~~~ :: wow"));
+        assert!(!is_spam("Wait... what?!"));
+    }
+
+    #[test]
+    fn other_scripts_are_words() {
+        assert!(!is_spam(
+            "(ﾉ◕ヮ◕)ﾉ*:･ﾟ✧ thanks for the raid, see you soon (◕‿◕✿)"
+        ));
+        assert!(!is_spam("Привет всем, как дела? Отличный стрим сегодня!"));
+        assert!(!is_spam("今日の配信も楽しかったです、ありがとう！"));
+        assert!(filter().is_violation("▓▒░▓▒░▓▒░▓▒░▓▒░", UserLevel::Subscriber));
+        assert!(!filter().is_violation("▓▒░▓▒░▓▒░▓▒░▓▒░", UserLevel::Vip));
+    }
+}
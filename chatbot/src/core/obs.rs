@@ -0,0 +1,261 @@
+//! `!scene`, `!mute` and `!show` let moderators control OBS, a redemption runs them with its
+//! `command`, e.g. `!show Confetti 10`.
+use super::{
+    commands::{Args, Command, Context},
+    ChatBotCommand,
+};
+use crate::{
+    connect::{Overflow, UserLevel},
+    obs::Obs,
+};
+use std::{thread, time::Duration};
+
+// a source is shown at most this long
+const MAX_SHOW_SECONDS: u64 = 600;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObsAction {
+    SetScene(String),
+    ToggleMute(String),
+    // hidden again after the duration
+    Show { source: String, duration: Duration },
+}
+
+/// What a command asks of OBS, main runs it like a [super::HelixTask]. The answer goes to chat.
+#[derive(Debug)]
+pub struct ObsTask {
+    // without the leading '#'
+    pub channel: String,
+    pub action: ObsAction,
+}
+
+impl ObsTask {
+    /// OBS's requests block, so they wait on a thread of their own.
+    pub async fn run(self, obs: Option<&Obs>) -> Option<ChatBotCommand> {
+        let text = match obs.cloned() {
+            Some(obs) => tokio::task::spawn_blocking(move || answer(&obs, self.action))
+                .await
+                .ok()?,
+            None => "Sorry, OBS is not set up.".to_owned(),
+        };
+        Some(ChatBotCommand::SendMessage {
+            channel: self.channel,
+            text,
+            overflow: Overflow::Split,
+        })
+    }
+}
+
+fn answer(obs: &Obs, action: ObsAction) -> String {
+    let result = match action {
+        ObsAction::SetScene(name) => obs
+            .set_scene(&name)
+            .map(|scene| format!("Switched to the scene {}.", scene)),
+        ObsAction::ToggleMute(name) => obs.toggle_mute(&name).map(|(input, muted)| match muted {
+            true => format!("Muted {}.", input),
+            false => format!("Unmuted {}.", input),
+        }),
+        ObsAction::Show { source, duration } => obs.show(&source).map(|shown| {
+            let text = format!("Showing {} for {}s.", shown.source, duration.as_secs());
+            let obs = obs.clone();
+            thread::spawn(move || {
+                thread::sleep(duration);
+                if let Err(error) = obs.hide(&shown) {
+                    tracing::warn!(source = %shown.source, %error, "could not hide the source again");
+                }
+            });
+            text
+        }),
+    };
+    result.unwrap_or_else(|error| format!("Sorry, {}.", error.to_string().trim_end_matches('.')))
+}
+
+fn task(ctx: &Context, action: ObsAction) -> Option<ChatBotCommand> {
+    Some(ChatBotCommand::Obs(ObsTask {
+        channel: ctx.message.channel.clone(),
+        action,
+    }))
+}
+
+/// `!scene <name>` switches OBS to the scene.
+pub struct Scene;
+
+impl Command for Scene {
+    fn name(&self) -> &'static str {
+        "scene"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<name>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(name) = args.rest() else {
+            return ctx.send(format!("Usage: {}scene <name>", ctx.prefix));
+        };
+        task(ctx, ObsAction::SetScene(name.to_owned()))
+    }
+}
+
+/// `!mute <input>` mutes the input, or turns it on again.
+pub struct Mute;
+
+impl Command for Mute {
+    fn name(&self) -> &'static str {
+        "mute"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<input>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(name) = args.rest() else {
+            return ctx.send(format!("Usage: {}mute <input>", ctx.prefix));
+        };
+        task(ctx, ObsAction::ToggleMute(name.to_owned()))
+    }
+}
+
+/// `!show <source> [seconds]` shows the source of the program's scene for a while.
+pub struct Show {
+    // without a number of seconds
+    pub seconds: u64,
+}
+
+impl Command for Show {
+    fn name(&self) -> &'static str {
+        "show"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<source> [seconds]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let usage = || ctx.send(format!("Usage: {}show <source> [seconds]", ctx.prefix));
+        let Some(rest) = args.rest() else {
+            return usage();
+        };
+        // source names may have spaces, a number at the end is the seconds
+        let (source, seconds) = match rest
+            .rsplit_once(char::is_whitespace)
+            .map(|(source, seconds)| (source.trim_end(), seconds.parse::<u64>()))
+        {
+            Some((source, Ok(seconds))) => (source, seconds),
+            _ => (rest, self.seconds),
+        };
+        if !(1..=MAX_SHOW_SECONDS).contains(&seconds) {
+            return ctx.send(format!(
+                "A source is shown for 1 to {} seconds.",
+                MAX_SHOW_SECONDS
+            ));
+        }
+        task(
+            ctx,
+            ObsAction::Show {
+                source: source.to_owned(),
+                duration: Duration::from_secs(seconds),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::ObsConfig,
+        connect::TextMessage,
+        obs::testing::{obs_server, wait_connected},
+    };
+    use std::time::Instant;
+
+    fn action(command: &mut dyn Command, text: &str) -> Option<ObsAction> {
+        let message = TextMessage {
+            channel: "captaincallback".to_owned(),
+            text: text.to_owned(),
+            level: UserLevel::Moderator,
+            ..Default::default()
+        };
+        let ctx = Context {
+            message: &message,
+            prefix: "!",
+            now: Instant::now(),
+            replied: None,
+        };
+        match command.execute(&ctx, Args::new(text)) {
+            Some(ChatBotCommand::Obs(task)) => Some(task.action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn show_takes_the_seconds_after_the_source() {
+        let mut show = Show { seconds: 10 };
+        let shown = |source: &str, seconds| {
+            Some(ObsAction::Show {
+                source: source.to_owned(),
+                duration: Duration::from_secs(seconds),
+            })
+        };
+        assert_eq!(action(&mut show, "Confetti"), shown("Confetti", 10));
+        assert_eq!(action(&mut show, "Confetti  30"), shown("Confetti", 30));
+        assert_eq!(
+            action(&mut show, "Big Confetti 5"),
+            shown("Big Confetti", 5)
+        );
+        assert_eq!(action(&mut show, "Confetti 3000"), None);
+        assert_eq!(
+            action(&mut Scene, "Be Right Back"),
+            Some(ObsAction::SetScene("Be Right Back".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn shown_sources_are_hidden_again() {
+        let (url, requests) = obs_server(None);
+        let obs = Obs::start(&ObsConfig {
+            url: Some(url),
+            ..Default::default()
+        })
+        .unwrap();
+        wait_connected(&obs);
+        let task = ObsTask {
+            channel: "captaincallback".to_owned(),
+            action: ObsAction::Show {
+                source: "confetti".to_owned(),
+                duration: Duration::from_millis(50),
+            },
+        };
+        let Some(ChatBotCommand::SendMessage { text, .. }) = task.run(Some(&obs)).await else {
+            panic!("OBS's answer is not sent to chat");
+        };
+        assert_eq!(text, "Showing Confetti for 0s.");
+        let enabled: Vec<_> = requests
+            .iter()
+            .filter(|request| request["requestType"] == "SetSceneItemEnabled")
+            .take(2)
+            .map(|request| request["requestData"].clone())
+            .collect();
+        assert_eq!(
+            enabled,
+            vec![
+                serde_json::json!({ "sceneName": "Main", "sceneItemId": 7, "sceneItemEnabled": true }),
+                serde_json::json!({ "sceneName": "Main", "sceneItemId": 7, "sceneItemEnabled": false }),
+            ]
+        );
+    }
+}
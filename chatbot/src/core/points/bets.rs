@@ -0,0 +1,470 @@
+use super::{games::wager, SharedPoints};
+use crate::{
+    connect::UserLevel,
+    core::{
+        commands::{Args, Command, Context},
+        ChatBotCommand,
+    },
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const STORAGE_NAME: &str = "bets";
+const MAX_OUTCOMES: usize = 10;
+// winners named when a round is resolved
+const BIGGEST_WINNERS: usize = 3;
+// the outcomes can't be called like the moderators' words
+const RESERVED: [&str; 4] = ["open", "lock", "resolve", "refund"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Wager {
+    name: String,
+    outcome: usize,
+    points: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Round {
+    question: String,
+    // lowercase
+    outcomes: Vec<String>,
+    locked: bool,
+    // by lowercase login
+    wagers: BTreeMap<String, Wager>,
+}
+
+/// Why a bet changed nothing.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BetRefusal {
+    NoRound,
+    Running,
+    Locked,
+    UnknownOutcome,
+    // the outcome the user bet on before
+    Switch(String),
+    Overdraft(u64),
+    Nothing,
+}
+
+/// What each wager wins besides getting its points back. The winners share the losing
+/// wagers by their own wager, rounded down; what is left over goes to nobody.
+fn winnings(wagers: &[(usize, u64)], winner: usize) -> Vec<u64> {
+    let pool = |won: bool| -> u128 {
+        wagers
+            .iter()
+            .filter(|(outcome, _)| (*outcome == winner) == won)
+            .map(|&(_, points)| u128::from(points))
+            .sum()
+    };
+    let (winning, losing) = (pool(true), pool(false));
+    wagers
+        .iter()
+        .map(
+            |&(outcome, points)| match outcome == winner && winning > 0 {
+                true => (u128::from(points) * losing / winning) as u64,
+                false => 0,
+            },
+        )
+        .collect()
+}
+
+/// The betting rounds of every channel. The wagers are taken from the points right away
+/// and saved with the round, a round the bot didn't finish is refunded when it starts again.
+#[derive(Debug, Default)]
+pub struct Bets {
+    points: SharedPoints,
+    storage: Storage,
+    // by channel
+    rounds: BTreeMap<String, Round>,
+}
+
+impl Bets {
+    /// Refunds the rounds saved before, the bot stopped while they were running.
+    pub fn load(points: SharedPoints, storage: Storage) -> Result<Self, StorageError> {
+        let mut bets = Self {
+            rounds: storage.load(STORAGE_NAME)?,
+            points,
+            storage,
+        };
+        let channels: Vec<_> = bets.rounds.keys().cloned().collect();
+        for channel in channels {
+            let refunded = bets.refund(&channel).unwrap_or_default();
+            tracing::info!(%channel, refunded, "refunded the bets of an unfinished round");
+        }
+        Ok(bets)
+    }
+
+    // the round is kept until the bot stops, even if the file can't be written
+    fn save(&self) {
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.rounds) {
+            tracing::warn!(%error, "could not save the bets");
+        }
+    }
+
+    pub fn open(
+        &mut self,
+        channel: &str,
+        question: &str,
+        outcomes: Vec<String>,
+    ) -> Result<(), BetRefusal> {
+        if self.rounds.contains_key(channel) {
+            return Err(BetRefusal::Running);
+        }
+        self.rounds.insert(
+            channel.to_owned(),
+            Round {
+                question: question.to_owned(),
+                outcomes,
+                locked: false,
+                wagers: BTreeMap::new(),
+            },
+        );
+        self.save();
+        Ok(())
+    }
+
+    /// Takes the points for the outcome, the user's whole wager on it is returned.
+    pub fn bet(
+        &mut self,
+        channel: &str,
+        (login, name): (&str, &str),
+        outcome: &str,
+        points: u64,
+    ) -> Result<u64, BetRefusal> {
+        let round = self.rounds.get_mut(channel).ok_or(BetRefusal::NoRound)?;
+        if round.locked {
+            return Err(BetRefusal::Locked);
+        }
+        let outcome = round
+            .outcomes
+            .iter()
+            .position(|known| known.eq_ignore_ascii_case(outcome))
+            .ok_or(BetRefusal::UnknownOutcome)?;
+        let login = login.to_lowercase();
+        if let Some(earlier) = round.wagers.get(&login) {
+            if earlier.outcome != outcome {
+                return Err(BetRefusal::Switch(round.outcomes[earlier.outcome].clone()));
+            }
+        }
+        if points == 0 {
+            return Err(BetRefusal::Nothing);
+        }
+        let balance = self.points.borrow().balance(channel, &login);
+        if balance < points {
+            return Err(BetRefusal::Overdraft(balance));
+        }
+        let wager = round.wagers.entry(login.clone()).or_insert(Wager {
+            name: name.to_owned(),
+            outcome,
+            points: 0,
+        });
+        wager.points += points;
+        let total = wager.points;
+        // saved before the points are taken, a crash in between refunds rather than loses them
+        self.save();
+        // the balance was checked, the points are there
+        self.points
+            .borrow_mut()
+            .settle(channel, &login, points, 0)
+            .ok();
+        Ok(total)
+    }
+
+    pub fn lock(&mut self, channel: &str) -> Result<(), BetRefusal> {
+        let round = self.rounds.get_mut(channel).ok_or(BetRefusal::NoRound)?;
+        if round.locked {
+            return Err(BetRefusal::Locked);
+        }
+        round.locked = true;
+        self.save();
+        Ok(())
+    }
+
+    /// Pays the winners and ends the round. Without a wager on the outcome everyone gets
+    /// their points back instead.
+    pub fn resolve(&mut self, channel: &str, outcome: &str) -> Result<String, BetRefusal> {
+        let round = self.rounds.get(channel).ok_or(BetRefusal::NoRound)?;
+        let winner = round
+            .outcomes
+            .iter()
+            .position(|known| known.eq_ignore_ascii_case(outcome))
+            .ok_or(BetRefusal::UnknownOutcome)?;
+        let name = round.outcomes[winner].clone();
+        if !round.wagers.values().any(|wager| wager.outcome == winner) {
+            let refunded = self.refund(channel)?;
+            return Ok(format!(
+                "{} wins, but nobody bet on it. The {} bets were refunded.",
+                name, refunded
+            ));
+        }
+        let round = self.rounds.remove(channel).expect("found before");
+        let wagers: Vec<_> = round
+            .wagers
+            .values()
+            .map(|wager| (wager.outcome, wager.points))
+            .collect();
+        let mut won: Vec<_> = round
+            .wagers
+            .iter()
+            .zip(winnings(&wagers, winner))
+            .filter(|((_, wager), _)| wager.outcome == winner)
+            .map(|((login, wager), winnings)| (login, wager, winnings))
+            .collect();
+        let mut points = self.points.borrow_mut();
+        for (login, wager, winnings) in &won {
+            points.credit(channel, login, wager.points + winnings);
+        }
+        drop(points);
+        self.save();
+        won.sort_by(|(_, a, a_won), (_, b, b_won)| b_won.cmp(a_won).then(a.name.cmp(&b.name)));
+        let paid: u64 = won.iter().map(|(_, _, winnings)| winnings).sum();
+        let biggest: Vec<_> = won
+            .iter()
+            .take(BIGGEST_WINNERS)
+            .map(|(_, wager, winnings)| format!("{} (+{})", wager.name, winnings))
+            .collect();
+        Ok(format!(
+            "{} wins! {} winning bets share {} points, the biggest winners: {}",
+            name,
+            won.len(),
+            paid,
+            biggest.join(", ")
+        ))
+    }
+
+    /// Ends the round and gives everyone their points back, how many bets there were is returned.
+    pub fn refund(&mut self, channel: &str) -> Result<usize, BetRefusal> {
+        let round = self.rounds.remove(channel).ok_or(BetRefusal::NoRound)?;
+        let mut points = self.points.borrow_mut();
+        for (login, wager) in &round.wagers {
+            points.credit(channel, login, wager.points);
+        }
+        drop(points);
+        self.save();
+        Ok(round.wagers.len())
+    }
+}
+
+// `"Will I beat the boss?" yes no` as the question and the lowercase outcomes
+fn question(text: &str) -> Option<(&str, Vec<String>)> {
+    let (question, outcomes) = text.strip_prefix('"')?.split_once('"')?;
+    let outcomes: Vec<_> = outcomes.split_whitespace().map(str::to_lowercase).collect();
+    let mut distinct = outcomes.clone();
+    distinct.sort();
+    distinct.dedup();
+    let valid = !question.trim().is_empty()
+        && (2..=MAX_OUTCOMES).contains(&outcomes.len())
+        && distinct.len() == outcomes.len()
+        && !outcomes
+            .iter()
+            .any(|outcome| RESERVED.contains(&outcome.as_str()));
+    valid.then_some((question.trim(), outcomes))
+}
+
+/// `!bet <outcome> <amount|all|50%>` for everyone, moderators run the round with
+/// `!bet open "<question>" <outcome> <outcome>...`, `!bet lock`, `!bet resolve <outcome>`
+/// and `!bet refund`.
+pub struct Bet(pub SharedPoints, pub Bets);
+
+impl Bet {
+    fn moderate(&mut self, ctx: &Context, action: &str, args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let result = match action {
+            "open" => {
+                let Some((question, outcomes)) = args.rest().and_then(question) else {
+                    return ctx.send(format!(
+                        "Usage: {}bet open \"<question>\" <outcome> <outcome>..., 2 to {} different outcomes",
+                        ctx.prefix, MAX_OUTCOMES
+                    ));
+                };
+                let list = outcomes.join(", ");
+                self.1.open(channel, question, outcomes).map(|_| {
+                    format!(
+                        "Bets are open: {} Bet with {}bet <{}> <amount>.",
+                        question,
+                        ctx.prefix,
+                        list.replace(", ", "|")
+                    )
+                })
+            }
+            "lock" => self
+                .1
+                .lock(channel)
+                .map(|_| "Bets are locked, good luck!".to_owned()),
+            "resolve" => match args.rest() {
+                Some(outcome) => self.1.resolve(channel, outcome),
+                None => return ctx.send(format!("Usage: {}bet resolve <outcome>", ctx.prefix)),
+            },
+            _ => self
+                .1
+                .refund(channel)
+                .map(|bets| format!("The round is cancelled, {} bets were refunded.", bets)),
+        };
+        ctx.send(match result {
+            Ok(text) => text,
+            Err(BetRefusal::Running) => format!(
+                "A round is running already, end it with {0}bet resolve or {0}bet refund.",
+                ctx.prefix
+            ),
+            Err(BetRefusal::Locked) => "The bets are locked already.".to_owned(),
+            Err(BetRefusal::UnknownOutcome) => "That isn't one of the outcomes.".to_owned(),
+            Err(_) => "No round is running.".to_owned(),
+        })
+    }
+}
+
+impl Command for Bet {
+    fn name(&self) -> &'static str {
+        "bet"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<outcome> <amount|all|50%>, open \"<question>\" <outcome> <outcome>..., lock, resolve <outcome>, refund"
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.0.borrow().is_enabled_in(channel)
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let message = ctx.message;
+        let Some(first) = args.next().map(str::to_lowercase) else {
+            return ctx.send(format!("Usage: {}bet <outcome> <amount>", ctx.prefix));
+        };
+        if RESERVED.contains(&first.as_str()) {
+            if !message.has_level(UserLevel::Moderator) {
+                return None;
+            }
+            return self.moderate(ctx, &first, args);
+        }
+        let (channel, name) = (&message.channel, message.user.display_name());
+        let balance = self.0.borrow().balance(channel, &message.user.name);
+        let Some(points) = args.next().and_then(|amount| wager(amount, balance)) else {
+            return ctx.send(format!("Usage: {}bet <outcome> <amount>", ctx.prefix));
+        };
+        let result = self
+            .1
+            .bet(channel, (&message.user.name, name), &first, points);
+        ctx.send(match result {
+            Ok(total) if total == points => format!("{} bet {} points on {}.", name, points, first),
+            Ok(total) => format!(
+                "{} bet {} points on {}, {} in total.",
+                name, points, first, total
+            ),
+            Err(BetRefusal::NoRound) => return None,
+            Err(BetRefusal::Locked) => "The bets are locked.".to_owned(),
+            Err(BetRefusal::UnknownOutcome) => "That isn't one of the outcomes.".to_owned(),
+            Err(BetRefusal::Switch(outcome)) => {
+                format!(
+                    "{}, you bet on {} already, you can only add to it.",
+                    name, outcome
+                )
+            }
+            Err(BetRefusal::Overdraft(balance)) => {
+                format!("{}, you have only {} points.", name, balance)
+            }
+            Err(_) => "Betting 0 points changes nothing.".to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::points::Points;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn winners_share_the_losing_wagers() {
+        // 100 lost points shared 1:2 among the winners, the point left over is nobody's
+        let wagers = [(0, 10), (0, 20), (1, 60), (1, 40)];
+        assert_eq!(winnings(&wagers, 0), [33, 66, 0, 0]);
+        assert_eq!(winnings(&wagers, 1), [0, 0, 18, 12]);
+        assert_eq!(winnings(&[(0, 5)], 0), [0]);
+        assert_eq!(winnings(&[(1, u64::MAX), (0, u64::MAX)], 0), [0, u64::MAX]);
+
+        let points: SharedPoints = Rc::new(RefCell::new(Points::default()));
+        for (user, balance) in [("a", 10), ("b", 20), ("c", 60), ("d", 40)] {
+            points.borrow_mut().credit("carkhy", user, balance);
+        }
+        let mut bets = Bets {
+            points: points.clone(),
+            ..Default::default()
+        };
+        let outcomes = vec!["yes".to_owned(), "no".to_owned()];
+        bets.open("carkhy", "Will I win?", outcomes).unwrap();
+        for (user, outcome, wager) in [
+            ("a", "yes", 10),
+            ("b", "YES", 15),
+            ("c", "no", 60),
+            ("d", "no", 40),
+        ] {
+            bets.bet("carkhy", (user, user), outcome, wager).unwrap();
+        }
+        assert_eq!(bets.bet("carkhy", ("b", "b"), "yes", 5), Ok(20));
+        assert_eq!(
+            bets.bet("carkhy", ("b", "b"), "no", 1),
+            Err(BetRefusal::Switch("yes".to_owned()))
+        );
+        assert_eq!(
+            bets.bet("carkhy", ("a", "a"), "yes", 1),
+            Err(BetRefusal::Overdraft(0))
+        );
+        bets.lock("carkhy").unwrap();
+        assert_eq!(
+            bets.bet("carkhy", ("c", "c"), "no", 1),
+            Err(BetRefusal::Locked)
+        );
+        assert_eq!(
+            bets.resolve("carkhy", "Yes").unwrap(),
+            "yes wins! 2 winning bets share 99 points, the biggest winners: b (+66), a (+33)"
+        );
+        let balance = |user| points.borrow().balance("carkhy", user);
+        assert_eq!(
+            [balance("a"), balance("b"), balance("c"), balance("d")],
+            [43, 86, 0, 0]
+        );
+        assert_eq!(bets.resolve("carkhy", "yes"), Err(BetRefusal::NoRound));
+    }
+
+    #[test]
+    fn an_unfinished_round_is_refunded_on_restart() {
+        let storage = Storage::memory();
+        let points: SharedPoints = Rc::new(RefCell::new(Points::default()));
+        points.borrow_mut().credit("carkhy", "viewer", 100);
+        let mut bets = Bets::load(points.clone(), storage.clone()).unwrap();
+        let outcomes = vec!["yes".to_owned(), "no".to_owned()];
+        bets.open("carkhy", "Will I win?", outcomes).unwrap();
+        bets.bet("carkhy", ("viewer", "Viewer"), "no", 70).unwrap();
+        assert_eq!(points.borrow().balance("carkhy", "viewer"), 30);
+
+        // the bot crashed, the round is all that stayed
+        let bets = Bets::load(points.clone(), storage.clone()).unwrap();
+        assert!(bets.rounds.is_empty());
+        assert_eq!(points.borrow().balance("carkhy", "viewer"), 100);
+        let bets = Bets::load(points.clone(), storage).unwrap();
+        assert!(bets.rounds.is_empty());
+        assert_eq!(points.borrow().balance("carkhy", "viewer"), 100);
+    }
+
+    #[test]
+    fn questions_need_distinct_outcomes() {
+        assert_eq!(
+            question("\"Will I beat the boss?\" Yes no"),
+            Some((
+                "Will I beat the boss?",
+                vec!["yes".to_owned(), "no".to_owned()]
+            ))
+        );
+        for text in [
+            "Will I? yes no",
+            "\"Will I?\" yes",
+            "\"Will I?\" yes YES",
+            "\"\" yes no",
+            "\"Hm\" lock no",
+        ] {
+            assert_eq!(question(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use super::{Refusal, SharedPoints};
+use crate::{
+    connect::UserLevel,
+    core::{
+        commands::{Args, Command, Context},
+        tasks::uptime,
+        watch_time::SharedWatchTime,
+        ChatBotCommand,
+    },
+};
+use std::time::Duration;
+
+// users listed by `!top points` and `!top watchtime`
+const TOP: usize = 5;
+const TOP_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// `!points [@user]` tells how many points the user calling it or the given user has.
+pub struct Points(pub SharedPoints);
+
+impl Command for Points {
+    fn name(&self) -> &'static str {
+        "points"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[@user]"
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.0.borrow().is_enabled_in(channel)
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let points = self.0.borrow();
+        let channel = &ctx.message.channel;
+        match args.next().map(|user| user.trim_start_matches('@')) {
+            Some(user) => ctx.send(format!(
+                "{} has {} points.",
+                user,
+                points.balance(channel, user)
+            )),
+            None => ctx.send(format!(
+                "{}, you have {} points.",
+                ctx.message.user.display_name(),
+                points.balance(channel, &ctx.message.user.name)
+            )),
+        }
+    }
+}
+
+/// `!give @user <amount>`, moderators make new points, viewers pass on their own
+/// if `peer_give` allows it.
+pub struct Give(pub SharedPoints);
+
+impl Command for Give {
+    fn name(&self) -> &'static str {
+        "give"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user> <amount>"
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.0.borrow().is_enabled_in(channel)
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let mut points = self.0.borrow_mut();
+        let moderator = ctx.message.has_level(UserLevel::Moderator);
+        if !moderator && !points.peer_give() {
+            return ctx.send("Only moderators give points here.".to_owned());
+        }
+        let (Some(user), Some(amount)) = (args.next(), args.next()) else {
+            return ctx.send(format!("Usage: {}give @user <amount>", ctx.prefix));
+        };
+        let user = user.trim_start_matches('@');
+        let Ok(amount) = amount.parse() else {
+            return ctx.send("The amount is a whole number of points, e.g. 100.".to_owned());
+        };
+        let from = (!moderator).then_some(ctx.message.user.name.as_str());
+        ctx.send(
+            match points.give(&ctx.message.channel, from, user, amount) {
+                Ok(balance) => format!("{} got {} points and has {} now.", user, amount, balance),
+                Err(Refusal::Nothing) => "Giving 0 points changes nothing.".to_owned(),
+                Err(Refusal::Themselves) => "You have your points already.".to_owned(),
+                Err(Refusal::Overdraft(balance)) => {
+                    format!("You have only {} points to give.", balance)
+                }
+            },
+        )
+    }
+}
+
+/// `!top points` lists the users with the most points, `!top watchtime` those who watched
+/// the longest. Each list is there if its feature is enabled.
+pub struct Top {
+    pub points: Option<SharedPoints>,
+    pub watch_time: Option<SharedWatchTime>,
+}
+
+impl Top {
+    // the points, unless the channel has none
+    fn points_in(&self, channel: &str) -> Option<&SharedPoints> {
+        let points = self.points.as_ref();
+        points.filter(|points| points.borrow().is_enabled_in(channel))
+    }
+}
+
+impl Command for Top {
+    fn name(&self) -> &'static str {
+        "top"
+    }
+
+    fn usage(&self) -> &'static str {
+        "points|watchtime"
+    }
+
+    fn cooldown(&self) -> Duration {
+        TOP_COOLDOWN
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.points_in(channel).is_some() || self.watch_time.is_some()
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let list = args.next().map(str::to_lowercase);
+        let channel = &ctx.message.channel;
+        let points = self.points_in(channel);
+        match (list.as_deref(), points, &self.watch_time) {
+            (Some("points"), Some(points), _) => {
+                let points = points.borrow();
+                let top: Vec<_> = points
+                    .top(channel, TOP)
+                    .into_iter()
+                    .map(|(login, points)| format!("{} ({})", login, points))
+                    .collect();
+                match top.is_empty() {
+                    true => ctx.send("Nobody has points yet.".to_owned()),
+                    false => ctx.send(format!("Top points: {}", top.join(", "))),
+                }
+            }
+            (Some("watchtime"), _, Some(watch_time)) => {
+                let watch_time = watch_time.borrow();
+                let top: Vec<_> = watch_time
+                    .top(channel, TOP)
+                    .into_iter()
+                    .map(|(login, watched)| format!("{} ({})", login, uptime(watched)))
+                    .collect();
+                match top.is_empty() {
+                    true => ctx.send("Nobody has watched yet.".to_owned()),
+                    false => ctx.send(format!("Top watch time: {}", top.join(", "))),
+                }
+            }
+            _ => {
+                let lists: Vec<_> = [
+                    points.map(|_| "points"),
+                    self.watch_time.as_ref().map(|_| "watchtime"),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                ctx.send(format!("Usage: {}top {}", ctx.prefix, lists.join("|")))
+            }
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use super::{games::wager, Refusal, SharedPoints};
+use crate::{
+    connect::{ChatBotEvent, InternalEvent, Overflow},
+    core::{
+        commands::{Args, Command, Context},
+        ChatBotCommand,
+    },
+};
+use fastrand::Rng;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// How long the challenged user has to answer.
+pub const DUEL_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Challenge {
+    id: Uuid,
+    // lowercase logins, and the names to answer with
+    challenger: String,
+    challenger_name: String,
+    target: String,
+    target_name: String,
+    // held back from the challenger until the duel is over
+    points: u64,
+    issued: Instant,
+}
+
+/// Why there is no duel.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DuelRefusal {
+    Themselves,
+    // the user who is in another duel already
+    Busy(String),
+    // the challenger's balance
+    Overdraft(u64),
+    // the challenged user's balance
+    TargetShort(u64),
+    Nothing,
+    NoChallenge,
+}
+
+/// The challenges waiting for an answer. Both users of a duel are in no other one, so the
+/// points of one duel can't be spent in another. The challenger's points are held back
+/// until the duel is over, the challenged user's are taken when accepting.
+#[derive(Debug, Default)]
+pub struct Duels {
+    points: SharedPoints,
+    rng: Rng,
+    // by channel
+    challenges: HashMap<String, Vec<Challenge>>,
+}
+
+pub type SharedDuels = Rc<RefCell<Duels>>;
+
+fn send(channel: &str, text: String) -> ChatBotCommand {
+    ChatBotCommand::SendMessage {
+        channel: channel.to_owned(),
+        text,
+        overflow: Overflow::Truncate,
+    }
+}
+
+impl Duels {
+    pub fn new(points: SharedPoints, rng: Rng) -> Self {
+        Self {
+            points,
+            rng,
+            challenges: HashMap::new(),
+        }
+    }
+
+    fn find(&self, channel: &str, login: &str) -> Option<usize> {
+        self.challenges
+            .get(channel)?
+            .iter()
+            .position(|challenge| challenge.challenger == login || challenge.target == login)
+    }
+
+    /// Holds back the challenger's points, the id is the one to expire it with.
+    pub fn challenge(
+        &mut self,
+        channel: &str,
+        (challenger, challenger_name): (&str, &str),
+        target_name: &str,
+        points: u64,
+        now: Instant,
+    ) -> Result<Uuid, DuelRefusal> {
+        let (challenger, target) = (challenger.to_lowercase(), target_name.to_lowercase());
+        if challenger == target {
+            return Err(DuelRefusal::Themselves);
+        }
+        for (login, name) in [(&challenger, challenger_name), (&target, target_name)] {
+            if self.find(channel, login).is_some() {
+                return Err(DuelRefusal::Busy(name.to_owned()));
+            }
+        }
+        let target_balance = self.points.borrow().balance(channel, &target);
+        if target_balance < points {
+            return Err(DuelRefusal::TargetShort(target_balance));
+        }
+        match self
+            .points
+            .borrow_mut()
+            .settle(channel, &challenger, points, 0)
+        {
+            Ok(_) => {}
+            Err(Refusal::Overdraft(balance)) => return Err(DuelRefusal::Overdraft(balance)),
+            Err(_) => return Err(DuelRefusal::Nothing),
+        }
+        let id = Uuid::new_v4();
+        self.challenges
+            .entry(channel.to_owned())
+            .or_default()
+            .push(Challenge {
+                id,
+                challenger,
+                challenger_name: challenger_name.to_owned(),
+                target,
+                target_name: target_name.to_owned(),
+                points,
+                issued: now,
+            });
+        Ok(id)
+    }
+
+    fn remove(&mut self, channel: &str, index: usize) -> Challenge {
+        let challenges = self.challenges.get_mut(channel).expect("found before");
+        let challenge = challenges.remove(index);
+        if challenges.is_empty() {
+            self.challenges.remove(channel);
+        }
+        challenge
+    }
+
+    // the challenger gets the points back
+    fn refund(&mut self, channel: &str, index: usize) -> Challenge {
+        let challenge = self.remove(channel, index);
+        self.points
+            .borrow_mut()
+            .credit(channel, &challenge.challenger, challenge.points);
+        challenge
+    }
+
+    /// The challenged user takes on the duel, the winner gets both wagers.
+    pub fn accept(
+        &mut self,
+        channel: &str,
+        login: &str,
+        now: Instant,
+    ) -> Result<String, DuelRefusal> {
+        let login = login.to_lowercase();
+        let index = self
+            .challenges
+            .get(channel)
+            .and_then(|challenges| {
+                challenges
+                    .iter()
+                    .position(|challenge| challenge.target == login)
+            })
+            .ok_or(DuelRefusal::NoChallenge)?;
+        if now >= self.challenges[channel][index].issued + DUEL_TIMEOUT {
+            // the expiry is on its way
+            self.refund(channel, index);
+            return Err(DuelRefusal::NoChallenge);
+        }
+        let points = self.challenges[channel][index].points;
+        if let Err(refusal) = self.points.borrow_mut().settle(channel, &login, points, 0) {
+            return Err(match refusal {
+                Refusal::Overdraft(balance) => DuelRefusal::TargetShort(balance),
+                _ => DuelRefusal::Nothing,
+            });
+        }
+        let challenge = self.remove(channel, index);
+        let pot = challenge.points * 2;
+        let (winner, winner_name, loser_name) = match self.rng.bool() {
+            true => (
+                &challenge.challenger,
+                &challenge.challenger_name,
+                &challenge.target_name,
+            ),
+            false => (
+                &challenge.target,
+                &challenge.target_name,
+                &challenge.challenger_name,
+            ),
+        };
+        self.points.borrow_mut().credit(channel, winner, pot);
+        Ok(format!(
+            "{} wins the duel against {} and takes {} points!",
+            winner_name, loser_name, pot
+        ))
+    }
+
+    /// The challenged user refuses, the challenger gets the points back.
+    pub fn decline(&mut self, channel: &str, login: &str) -> Option<String> {
+        let login = login.to_lowercase();
+        let index = self
+            .challenges
+            .get(channel)?
+            .iter()
+            .position(|challenge| challenge.target == login)?;
+        let challenge = self.refund(channel, index);
+        Some(format!(
+            "{} declined the duel, {} got the {} points back.",
+            challenge.target_name, challenge.challenger_name, challenge.points
+        ))
+    }
+
+    /// The challenge with the id wasn't answered in time, unless it is over already.
+    pub fn expire(&mut self, channel: &str, id: Uuid) -> Option<ChatBotCommand> {
+        let index = self
+            .challenges
+            .get(channel)?
+            .iter()
+            .position(|challenge| challenge.id == id)?;
+        let challenge = self.refund(channel, index);
+        Some(send(
+            channel,
+            format!(
+                "{} didn't answer the duel, {} got the {} points back.",
+                challenge.target_name, challenge.challenger_name, challenge.points
+            ),
+        ))
+    }
+
+    /// A user left the channel, their duel is off.
+    pub fn part(&mut self, channel: &str, login: &str) -> Option<ChatBotCommand> {
+        let index = self.find(channel, &login.to_lowercase())?;
+        let challenge = self.refund(channel, index);
+        Some(send(
+            channel,
+            format!(
+                "The duel of {} and {} is off, one of them left.",
+                challenge.challenger_name, challenge.target_name
+            ),
+        ))
+    }
+
+    /// The bot left the channel, every challenger gets the points back.
+    pub fn leave(&mut self, channel: &str) {
+        while self.challenges.contains_key(channel) {
+            self.refund(channel, 0);
+        }
+    }
+}
+
+/// `!duel @user <amount>` challenges the user to wager as many points, the winner takes both.
+pub struct Duel(pub SharedPoints, pub SharedDuels);
+
+impl Command for Duel {
+    fn name(&self) -> &'static str {
+        "duel"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user> <amount|all|50%>"
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.0.borrow().is_enabled_in(channel)
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let (Some(target), Some(amount)) = (args.next(), args.next()) else {
+            return ctx.send(format!("Usage: {}duel @user <amount>", ctx.prefix));
+        };
+        let target = target.trim_start_matches('@');
+        let message = ctx.message;
+        let (channel, name) = (&message.channel, message.user.display_name());
+        let balance = self.0.borrow().balance(channel, &message.user.name);
+        let Some(points) = wager(amount, balance).filter(|&points| points > 0) else {
+            return ctx.send(
+                "The wager is a whole number of points, \"all\" or a percentage like 50%."
+                    .to_owned(),
+            );
+        };
+        let result = self.1.borrow_mut().challenge(
+            channel,
+            (&message.user.name, name),
+            target,
+            points,
+            ctx.now,
+        );
+        let id = match result {
+            Ok(id) => id,
+            Err(refusal) => {
+                return ctx.send(match refusal {
+                    DuelRefusal::Themselves => "You can't duel yourself.".to_owned(),
+                    DuelRefusal::Busy(user) => format!("{} is in a duel already.", user),
+                    DuelRefusal::Overdraft(balance) => {
+                        format!("{}, you have only {} points.", name, balance)
+                    }
+                    DuelRefusal::TargetShort(balance) => {
+                        format!("{} has only {} points.", target, balance)
+                    }
+                    DuelRefusal::Nothing | DuelRefusal::NoChallenge => return None,
+                })
+            }
+        };
+        Some(ChatBotCommand::MultipleCommands(vec![
+            ctx.send(format!(
+                "@{}, {} challenges you to a duel for {} points! Answer with {}accept or {}decline within {} seconds.",
+                target,
+                name,
+                points,
+                ctx.prefix,
+                ctx.prefix,
+                DUEL_TIMEOUT.as_secs()
+            ))?,
+            ChatBotCommand::TimedCallback {
+                duration: DUEL_TIMEOUT,
+                event: ChatBotEvent::Internal(InternalEvent::DuelExpired {
+                    channel: channel.clone(),
+                    id,
+                }),
+            },
+        ]))
+    }
+}
+
+/// `!accept` takes on the duel the user was challenged to.
+pub struct Accept(pub SharedDuels);
+
+impl Command for Accept {
+    fn name(&self) -> &'static str {
+        "accept"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let message = ctx.message;
+        let result = self
+            .0
+            .borrow_mut()
+            .accept(&message.channel, &message.user.name, ctx.now);
+        ctx.send(match result {
+            Ok(announcement) => announcement,
+            Err(DuelRefusal::TargetShort(balance)) => format!(
+                "{}, you have only {} points, the duel needs more.",
+                message.user.display_name(),
+                balance
+            ),
+            // nobody challenged the user, nothing to answer
+            Err(_) => return None,
+        })
+    }
+}
+
+/// `!decline` refuses the duel the user was challenged to.
+pub struct Decline(pub SharedDuels);
+
+impl Command for Decline {
+    fn name(&self) -> &'static str {
+        "decline"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let message = ctx.message;
+        let answer = self
+            .0
+            .borrow_mut()
+            .decline(&message.channel, &message.user.name)?;
+        ctx.send(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::points::Points;
+
+    fn duels() -> (SharedPoints, Duels) {
+        let points: SharedPoints = Rc::new(RefCell::new(Points::default()));
+        for user in ["carkhy", "viewer", "lurker"] {
+            points.borrow_mut().credit("channel", user, 100);
+        }
+        (points.clone(), Duels::new(points, Rng::with_seed(1)))
+    }
+
+    fn total(points: &SharedPoints) -> u64 {
+        ["carkhy", "viewer", "lurker"]
+            .iter()
+            .map(|user| points.borrow().balance("channel", user))
+            .sum()
+    }
+
+    #[test]
+    fn the_winner_takes_both_wagers() {
+        let (points, mut duels) = duels();
+        let now = Instant::now();
+        let challenge = |duels: &mut Duels, from: &str, to: &str, amount| {
+            duels.challenge("channel", (from, from), to, amount, now)
+        };
+        assert_eq!(
+            challenge(&mut duels, "carkhy", "Carkhy", 10),
+            Err(DuelRefusal::Themselves)
+        );
+        assert_eq!(
+            challenge(&mut duels, "carkhy", "viewer", 101),
+            Err(DuelRefusal::TargetShort(100))
+        );
+        points.borrow_mut().credit("channel", "viewer", 100);
+        assert_eq!(
+            challenge(&mut duels, "carkhy", "viewer", 101),
+            Err(DuelRefusal::Overdraft(100))
+        );
+        assert!(challenge(&mut duels, "carkhy", "viewer", 60).is_ok());
+        assert_eq!(points.borrow().balance("channel", "carkhy"), 40);
+        assert_eq!(
+            challenge(&mut duels, "carkhy", "lurker", 10),
+            Err(DuelRefusal::Busy("carkhy".to_owned()))
+        );
+        assert_eq!(
+            challenge(&mut duels, "lurker", "Viewer", 10),
+            Err(DuelRefusal::Busy("Viewer".to_owned()))
+        );
+        assert_eq!(
+            duels.accept("channel", "lurker", now),
+            Err(DuelRefusal::NoChallenge)
+        );
+        let announcement = duels.accept("channel", "Viewer", now).unwrap();
+        assert!(
+            announcement.ends_with("takes 120 points!"),
+            "{}",
+            announcement
+        );
+        assert_eq!(total(&points), 400);
+        let balances = (
+            points.borrow().balance("channel", "carkhy"),
+            points.borrow().balance("channel", "viewer"),
+        );
+        assert!(
+            balances == (160, 140) || balances == (40, 260),
+            "{:?}",
+            balances
+        );
+
+        // both are free for the next duel, which declined returns the points
+        assert!(challenge(&mut duels, "lurker", "carkhy", 30).is_ok());
+        assert!(duels.decline("channel", "carkhy").is_some());
+        assert_eq!(points.borrow().balance("channel", "lurker"), 100);
+    }
+
+    #[test]
+    fn unanswered_duels_return_the_points() {
+        let (points, mut duels) = duels();
+        let start = Instant::now();
+        let id = duels
+            .challenge("channel", ("carkhy", "Carkhy"), "viewer", 50, start)
+            .unwrap();
+        points.borrow_mut().credit("channel", "somebody", 10);
+        let other = duels
+            .challenge("channel", ("lurker", "Lurker"), "somebody", 10, start)
+            .unwrap();
+        // the expiry of another duel changes nothing
+        assert!(duels.expire("channel", Uuid::new_v4()).is_none());
+        assert!(duels.expire("channel", other).is_some());
+        assert_eq!(
+            duels.accept("channel", "viewer", start + DUEL_TIMEOUT),
+            Err(DuelRefusal::NoChallenge)
+        );
+        assert_eq!(points.borrow().balance("channel", "carkhy"), 100);
+        // expired already, the callback comes too late
+        assert!(duels.expire("channel", id).is_none());
+
+        let id = duels
+            .challenge("channel", ("carkhy", "Carkhy"), "viewer", 50, start)
+            .unwrap();
+        match duels.expire("channel", id) {
+            Some(ChatBotCommand::SendMessage { text, .. }) => assert_eq!(
+                text,
+                "viewer didn't answer the duel, Carkhy got the 50 points back."
+            ),
+            other => panic!("{:?}", other),
+        }
+        duels
+            .challenge("channel", ("carkhy", "Carkhy"), "viewer", 50, start)
+            .unwrap();
+        assert!(duels.part("channel", "lurker").is_none());
+        assert!(duels.part("channel", "Carkhy").is_some());
+        assert_eq!(total(&points), 300);
+        assert!(duels.challenges.is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use super::SharedPoints;
+use crate::{
+    config::{PointsConfig, SlotsSymbol},
+    core::{
+        commands::{Args, Command, Context},
+        ChatBotCommand,
+    },
+};
+use fastrand::Rng;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+// users who played within the cooldown are kept, the others are forgotten from this many on
+const PLAYED_LIMIT: usize = 1000;
+
+fn share(points: u64, percent: u64) -> u64 {
+    points.saturating_mul(percent) / 100
+}
+
+/// The wager of "100", "all" or "50%" of the balance, None unless it is a whole number of points.
+pub(super) fn wager(text: &str, balance: u64) -> Option<u64> {
+    if text.eq_ignore_ascii_case("all") {
+        return Some(balance);
+    }
+    if let Some(percent) = text.strip_suffix('%') {
+        let percent: u64 = percent.parse().ok().filter(|percent| *percent <= 100)?;
+        return Some(share(balance, percent));
+    }
+    text.parse().ok()
+}
+
+// "won 100 points", what the user has more or less than before
+fn outcome(wager: u64, payout: u64) -> String {
+    match payout.cmp(&wager) {
+        std::cmp::Ordering::Greater => format!("won {} points", payout - wager),
+        std::cmp::Ordering::Equal => "kept their points".to_owned(),
+        std::cmp::Ordering::Less => format!("lost {} points", wager - payout),
+    }
+}
+
+/// The chance games played with points. The random numbers come from a generator of
+/// their own, so tests give it a seed.
+#[derive(Debug)]
+pub struct Games {
+    cooldown: Duration,
+    win_percent: u8,
+    payout_percent: u64,
+    symbols: Vec<SlotsSymbol>,
+    pair_percent: u64,
+    rng: Rng,
+    // by channel and lowercase login, when the user played last
+    played: HashMap<(String, String), Instant>,
+}
+
+pub type SharedGames = Rc<RefCell<Games>>;
+
+impl Games {
+    pub fn new(config: &PointsConfig, rng: Rng) -> Self {
+        Self {
+            cooldown: Duration::from_secs(config.gamble_cooldown),
+            win_percent: config.gamble_win_percent,
+            payout_percent: config.gamble_payout_percent,
+            symbols: config.slots_symbols.clone(),
+            pair_percent: config.slots_pair_percent,
+            rng,
+            played: HashMap::new(),
+        }
+    }
+
+    // false while the user's cooldown lasts, otherwise it starts again
+    fn play(&mut self, channel: &str, login: &str, now: Instant) -> bool {
+        let key = (channel.to_owned(), login.to_lowercase());
+        if let Some(&played) = self.played.get(&key) {
+            if now < played + self.cooldown {
+                return false;
+            }
+        }
+        if self.played.len() >= PLAYED_LIMIT {
+            let cooldown = self.cooldown;
+            self.played.retain(|_, &mut played| now < played + cooldown);
+        }
+        self.played.insert(key, now);
+        true
+    }
+
+    /// What a coin flip pays for the wager, nothing when it is lost.
+    pub fn flip(&mut self, wager: u64) -> u64 {
+        match self.rng.u8(..100) < self.win_percent {
+            true => share(wager, self.payout_percent),
+            false => 0,
+        }
+    }
+
+    /// The three symbols rolled and what they pay for the wager.
+    pub fn spin(&mut self, wager: u64) -> (String, u64) {
+        let reels: Vec<_> = (0..3)
+            .map(|_| &self.symbols[self.rng.usize(..self.symbols.len())])
+            .collect();
+        let percent = match (
+            reels[0] == reels[1],
+            reels[1] == reels[2],
+            reels[0] == reels[2],
+        ) {
+            (true, true, _) => reels[0].payout_percent,
+            (false, false, false) => 0,
+            _ => self.pair_percent,
+        };
+        let rolled = reels.iter().map(|reel| reel.symbol.as_str()).collect();
+        (rolled, share(wager, percent))
+    }
+}
+
+// the wager of the game's only argument, or why there is none
+fn validate(
+    ctx: &Context,
+    args: &mut Args,
+    points: &SharedPoints,
+    usage: &str,
+) -> Result<u64, String> {
+    let Some(text) = args.next() else {
+        return Err(format!("Usage: {}{}", ctx.prefix, usage));
+    };
+    let message = ctx.message;
+    let balance = points
+        .borrow()
+        .balance(&message.channel, &message.user.name);
+    match wager(text, balance) {
+        Some(0) if balance == 0 => Err(format!(
+            "{}, you have no points to play with.",
+            message.user.display_name()
+        )),
+        Some(wager) if wager > balance => Err(format!(
+            "{}, you have only {} points.",
+            message.user.display_name(),
+            balance
+        )),
+        Some(wager) if wager > 0 => Ok(wager),
+        _ => Err(format!(
+            "The wager is a whole number of points, \"all\" or a percentage like 50%: {}{}",
+            ctx.prefix, usage
+        )),
+    }
+}
+
+// the user's balance after the game, the wager was validated right before
+fn settle(ctx: &Context, points: &SharedPoints, wager: u64, payout: u64) -> Option<u64> {
+    let message = ctx.message;
+    points
+        .borrow_mut()
+        .settle(&message.channel, &message.user.name, wager, payout)
+        .ok()
+}
+
+/// `!gamble <amount|all|50%>` wins the payout with the configured chance, or loses the wager.
+pub struct Gamble(pub SharedPoints, pub SharedGames);
+
+impl Command for Gamble {
+    fn name(&self) -> &'static str {
+        "gamble"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<amount|all|50%>"
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.0.borrow().is_enabled_in(channel)
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let wager = match validate(ctx, &mut args, &self.0, "gamble <amount|all|50%>") {
+            Ok(wager) => wager,
+            Err(answer) => return ctx.send(answer),
+        };
+        let mut games = self.1.borrow_mut();
+        if !games.play(&ctx.message.channel, &ctx.message.user.name, ctx.now) {
+            return None;
+        }
+        let payout = games.flip(wager);
+        let balance = settle(ctx, &self.0, wager, payout)?;
+        ctx.send(format!(
+            "{} {}, balance {}.",
+            ctx.message.user.display_name(),
+            outcome(wager, payout),
+            balance
+        ))
+    }
+}
+
+/// `!slots <amount|all|50%>` rolls three symbols, three or two of a kind pay out.
+pub struct Slots(pub SharedPoints, pub SharedGames);
+
+impl Command for Slots {
+    fn name(&self) -> &'static str {
+        "slots"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<amount|all|50%>"
+    }
+
+    fn is_enabled(&self, channel: &str) -> bool {
+        self.0.borrow().is_enabled_in(channel)
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let wager = match validate(ctx, &mut args, &self.0, "slots <amount|all|50%>") {
+            Ok(wager) => wager,
+            Err(answer) => return ctx.send(answer),
+        };
+        let mut games = self.1.borrow_mut();
+        if !games.play(&ctx.message.channel, &ctx.message.user.name, ctx.now) {
+            return None;
+        }
+        let (rolled, payout) = games.spin(wager);
+        let balance = settle(ctx, &self.0, wager, payout)?;
+        ctx.send(format!(
+            "{} rolled {} and {}, balance {}.",
+            ctx.message.user.display_name(),
+            rolled,
+            outcome(wager, payout),
+            balance
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect::{TextMessage, UserInfo},
+        core::points::Points,
+    };
+
+    fn games(seed: u64) -> Games {
+        Games::new(&PointsConfig::default(), Rng::with_seed(seed))
+    }
+
+    #[test]
+    fn wagers_are_whole_points_of_the_balance() {
+        assert_eq!(wager("100", 500), Some(100));
+        assert_eq!(wager("ALL", 500), Some(500));
+        assert_eq!(wager("50%", 501), Some(250));
+        assert_eq!(wager("100%", 501), Some(501));
+        for text in [
+            "-5", "1.5", "NaN", "inf", "1e3", "150%", "-50%", "%", "half",
+        ] {
+            assert_eq!(wager(text, 500), None, "{}", text);
+        }
+        assert_eq!(wager("0", 500), Some(0));
+    }
+
+    #[test]
+    fn payouts_follow_the_config() {
+        let mut games = games(7);
+        let flips: Vec<_> = (0..1000).map(|_| games.flip(10)).collect();
+        assert!(flips.iter().all(|&payout| payout == 0 || payout == 20));
+        let won = flips.iter().filter(|&&payout| payout == 20).count();
+        assert!((400..500).contains(&won), "{}", won);
+
+        for _ in 0..1000 {
+            let (rolled, payout) = games.spin(100);
+            let reels: Vec<_> = games
+                .symbols
+                .iter()
+                .map(|symbol| (symbol, rolled.matches(symbol.symbol.as_str()).count()))
+                .collect();
+            let expected = match reels.iter().find(|(_, count)| *count > 1) {
+                Some((symbol, 3)) => symbol.payout_percent,
+                Some(_) => 50,
+                None => 0,
+            };
+            assert_eq!(payout, expected, "{}", rolled);
+        }
+    }
+
+    #[test]
+    fn games_move_exactly_the_wager_and_payout() {
+        let points: SharedPoints = Rc::new(RefCell::new(Points::default()));
+        points
+            .borrow_mut()
+            .give("carkhy", None, "viewer", 1000)
+            .unwrap();
+        let shared = Rc::new(RefCell::new(Games {
+            cooldown: Duration::from_secs(30),
+            ..games(42)
+        }));
+        let mut gamble = Gamble(points.clone(), shared.clone());
+        let message = TextMessage {
+            channel: "carkhy".to_owned(),
+            user: UserInfo {
+                name: "viewer".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut play = |text: &str, seconds| {
+            let ctx = Context {
+                message: &message,
+                prefix: "!",
+                now: start + Duration::from_secs(seconds),
+                replied: None,
+            };
+            match gamble.execute(&ctx, Args::new(text)) {
+                Some(ChatBotCommand::SendMessage { text, .. }) => Some(text),
+                None => None,
+                other => panic!("{:?}", other),
+            }
+        };
+        assert_eq!(
+            play("0", 0).as_deref(),
+            Some("The wager is a whole number of points, \"all\" or a percentage like 50%: !gamble <amount|all|50%>")
+        );
+        assert_eq!(
+            play("1001", 0).as_deref(),
+            Some("viewer, you have only 1000 points.")
+        );
+        let mut expected = Games {
+            cooldown: Duration::ZERO,
+            ..games(42)
+        };
+        let mut balance = 1000;
+        for (round, text) in ["100", "50%", "all"].into_iter().enumerate() {
+            let stake = wager(text, balance).unwrap();
+            balance = balance - stake + expected.flip(stake);
+            let answer = play(text, round as u64 * 30).unwrap();
+            assert!(
+                answer.ends_with(&format!("balance {}.", balance)),
+                "{}",
+                answer
+            );
+            // within the cooldown nothing happens, an empty balance is told before
+            if balance > 0 {
+                assert_eq!(play("1", round as u64 * 30 + 29), None);
+            }
+        }
+        assert_eq!(points.borrow().balance("carkhy", "viewer"), balance);
+    }
+}
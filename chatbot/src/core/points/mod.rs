@@ -0,0 +1,431 @@
+mod bets;
+mod commands;
+mod duels;
+mod games;
+
+use crate::{
+    config::PointsConfig,
+    connect::{Badge, TextMessage},
+    storage::{Storage, StorageError},
+};
+pub use bets::{Bet, Bets};
+pub use commands::{Give, Points as PointsCommand, Top};
+pub use duels::{Accept, Decline, Duel, Duels, SharedDuels};
+pub use games::{Gamble, Games, Slots};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const STORAGE_NAME: &str = "points";
+/// Viewers in chat earn points once per interval.
+pub const INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How often the points check whether an interval is over, a missed tick pays late but once.
+pub const POINTS_TICK: Duration = Duration::from_secs(60);
+
+// the intervals are counted from 1970, so a restart knows which one was paid
+fn interval(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / INTERVAL.as_secs()
+}
+
+/// Why `!give` moved no points.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refusal {
+    Nothing,
+    Themselves,
+    // the giver's balance
+    Overdraft(u64),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    // by channel, the last interval that was paid
+    paid: BTreeMap<String, u64>,
+    // by channel and lowercase login
+    balances: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+/// The points of every user, kept in the storage. Users earn them by being in chat
+/// each interval and by writing.
+#[derive(Debug, Default)]
+pub struct Points {
+    storage: Storage,
+    config: PointsConfig,
+    // the channels with their own, without the leading '#'
+    channels: HashMap<String, PointsConfig>,
+    saved: Saved,
+    // by channel, the lowercase logins of the users who joined
+    joined: HashMap<String, HashSet<String>>,
+    // by channel, who wrote since the last interval was paid, twitch doesn't tell
+    // about everyone joining a big channel
+    active: HashMap<String, HashSet<String>>,
+    // by channel, who had a subscriber badge on their last message
+    subscribers: HashMap<String, HashSet<String>>,
+    // by channel and lowercase login, when a message last earned points
+    rewarded: HashMap<(String, String), Instant>,
+    // earned points not saved yet, messages are saved with the next tick
+    unsaved: bool,
+}
+
+pub type SharedPoints = Rc<RefCell<Points>>;
+
+impl Points {
+    pub fn load(config: &PointsConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            saved: storage.load(STORAGE_NAME)?,
+            storage,
+            config: config.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Takes the earning of a channel from its own config.
+    pub fn scope(&mut self, channel: &str, config: &PointsConfig) {
+        let channel = channel.trim_start_matches('#').to_owned();
+        self.channels.insert(channel, config.clone());
+    }
+
+    fn config(&self, channel: &str) -> &PointsConfig {
+        self.channels.get(channel).unwrap_or(&self.config)
+    }
+
+    /// Whether any channel has points.
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled || self.channels.values().any(|config| config.enabled)
+    }
+
+    pub fn is_enabled_in(&self, channel: &str) -> bool {
+        self.config(channel).enabled
+    }
+
+    pub fn peer_give(&self) -> bool {
+        self.config.peer_give
+    }
+
+    fn earned(&self, channel: &str, login: &str, points: u64) -> u64 {
+        let subscribed = self
+            .subscribers
+            .get(channel)
+            .is_some_and(|subscribers| subscribers.contains(login));
+        match subscribed {
+            true => points.saturating_mul(self.config(channel).sub_percent) / 100,
+            false => points,
+        }
+    }
+
+    fn add(&mut self, channel: &str, login: &str, points: u64) -> u64 {
+        let balance = self
+            .saved
+            .balances
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(login.to_lowercase())
+            .or_default();
+        *balance = balance.saturating_add(points);
+        *balance
+    }
+
+    pub fn join(&mut self, channel: &str, login: &str) {
+        let joined = self.joined.entry(channel.to_owned()).or_default();
+        joined.insert(login.to_lowercase());
+    }
+
+    pub fn part(&mut self, channel: &str, login: &str) {
+        if let Some(joined) = self.joined.get_mut(channel) {
+            joined.remove(&login.to_lowercase());
+        }
+    }
+
+    /// The channel was left, its users aren't in chat anymore.
+    pub fn leave(&mut self, channel: &str) {
+        self.joined.remove(channel);
+        self.active.remove(channel);
+    }
+
+    /// The user was in chat, the message earns points unless the last one did shortly before.
+    pub fn message(&mut self, message: &TextMessage, now: Instant) {
+        let (channel, login) = (&message.channel, message.user.name.to_lowercase());
+        let config = self.config(channel).clone();
+        if !config.enabled {
+            return;
+        }
+        let subscribers = self.subscribers.entry(channel.clone()).or_default();
+        match message
+            .user
+            .badges
+            .iter()
+            .any(|badge| matches!(badge, Badge::Subscriber { .. }))
+        {
+            true => subscribers.insert(login.clone()),
+            false => subscribers.remove(&login),
+        };
+        let active = self.active.entry(channel.clone()).or_default();
+        active.insert(login.clone());
+        let key = (channel.clone(), login);
+        let cooldown = Duration::from_secs(config.message_cooldown);
+        if let Some(&rewarded) = self.rewarded.get(&key) {
+            if now < rewarded + cooldown {
+                return;
+            }
+        }
+        let points = self.earned(channel, &key.1, config.message_points);
+        self.add(channel, &key.1, points);
+        self.rewarded.insert(key, now);
+        self.unsaved = true;
+    }
+
+    /// Pays every user in chat once the interval they were there in is over, each interval
+    /// only once even across restarts. Returns how many users were paid.
+    /// A single pass over the users and one write, a few thousand of them take no time.
+    pub fn tick(&mut self, now: SystemTime, instant: Instant) -> usize {
+        if !self.is_enabled() {
+            return 0;
+        }
+        let current = interval(now);
+        let mut channels: HashSet<String> = self.joined.keys().cloned().collect();
+        channels.extend(self.active.keys().cloned());
+        channels.retain(|channel| self.is_enabled_in(channel));
+        let mut paid = 0;
+        for channel in channels {
+            let last = self.saved.paid.get(&channel).copied();
+            // the first interval in a channel is the one the bot started in
+            if last.is_some_and(|last| last >= current) {
+                continue;
+            }
+            self.saved.paid.insert(channel.clone(), current);
+            if last.is_none() {
+                self.unsaved = true;
+                continue;
+            }
+            let mut users = self.active.remove(&channel).unwrap_or_default();
+            users.extend(self.joined.get(&channel).into_iter().flatten().cloned());
+            for login in &users {
+                let points = self.earned(&channel, login, self.config(&channel).interval_points);
+                self.add(&channel, login, points);
+            }
+            paid += users.len();
+            self.unsaved = true;
+        }
+        let (config, channels) = (&self.config, &self.channels);
+        self.rewarded.retain(|(channel, _), &mut rewarded| {
+            let config = channels.get(channel).unwrap_or(config);
+            instant < rewarded + Duration::from_secs(config.message_cooldown)
+        });
+        if self.unsaved {
+            self.save();
+        }
+        paid
+    }
+
+    pub fn balance(&self, channel: &str, login: &str) -> u64 {
+        self.saved
+            .balances
+            .get(channel)
+            .and_then(|balances| balances.get(&login.to_lowercase()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Moves the points from one user to the other, without a giver they are new.
+    /// The receiver's balance is returned.
+    pub fn give(
+        &mut self,
+        channel: &str,
+        from: Option<&str>,
+        to: &str,
+        points: u64,
+    ) -> Result<u64, Refusal> {
+        if points == 0 {
+            return Err(Refusal::Nothing);
+        }
+        if let Some(from) = from {
+            if from.eq_ignore_ascii_case(to) {
+                return Err(Refusal::Themselves);
+            }
+            let balance = self.balance(channel, from);
+            if balance < points {
+                return Err(Refusal::Overdraft(balance));
+            }
+            self.set(channel, from, balance - points);
+        }
+        let balance = self.add(channel, to, points);
+        self.save();
+        Ok(balance)
+    }
+
+    /// Takes the wager and pays out in one go, so no other game spends the same points.
+    /// The user's balance is returned.
+    pub fn settle(
+        &mut self,
+        channel: &str,
+        login: &str,
+        wager: u64,
+        payout: u64,
+    ) -> Result<u64, Refusal> {
+        if wager == 0 {
+            return Err(Refusal::Nothing);
+        }
+        let balance = self.balance(channel, login);
+        if balance < wager {
+            return Err(Refusal::Overdraft(balance));
+        }
+        let balance = self.set(channel, login, (balance - wager).saturating_add(payout));
+        self.save();
+        Ok(balance)
+    }
+
+    fn set(&mut self, channel: &str, login: &str, points: u64) -> u64 {
+        self.saved
+            .balances
+            .entry(channel.to_owned())
+            .or_default()
+            .insert(login.to_lowercase(), points);
+        points
+    }
+
+    /// Pays the user points held back before, like the pot of a duel.
+    pub fn credit(&mut self, channel: &str, login: &str, points: u64) -> u64 {
+        let balance = self.add(channel, login, points);
+        self.save();
+        balance
+    }
+
+    /// The users with the most points, most first. The storage ranks them, e.g. the
+    /// database, unless the points earned since the last save are only here.
+    pub fn top(&self, channel: &str, count: usize) -> Vec<(String, u64)> {
+        if !self.unsaved {
+            match self
+                .storage
+                .top(STORAGE_NAME, &["balances", channel], count)
+            {
+                Ok(Some(top)) => return top,
+                Ok(None) => {}
+                Err(error) => tracing::warn!(%error, "could not rank the points"),
+            }
+        }
+        let mut top: Vec<_> = self
+            .saved
+            .balances
+            .get(channel)
+            .into_iter()
+            .flatten()
+            .filter(|(_, &points)| points > 0)
+            .map(|(login, &points)| (login.clone(), points))
+            .collect();
+        top.sort_by(|(a, a_points), (b, b_points)| b_points.cmp(a_points).then(a.cmp(b)));
+        top.truncate(count);
+        top
+    }
+
+    // the points are kept until the bot stops, even if the file can't be written
+    fn save(&mut self) {
+        self.unsaved = false;
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.saved) {
+            tracing::warn!(%error, "could not save the points");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+
+    fn config() -> PointsConfig {
+        PointsConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    // the start of an interval
+    fn at(minutes: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_100 + minutes * 60)
+    }
+
+    #[test]
+    fn each_interval_is_paid_once() {
+        let mut points = Points::load(&config(), Storage::default()).unwrap();
+        let now = Instant::now();
+        points.join("carkhy", "Lurker");
+        // the interval the bot started in is not paid, nobody was watched all of it
+        assert_eq!(points.tick(at(0), now), 0);
+        points.message(&message("viewer", "hello", false), now);
+        points.message(
+            &message("viewer", "hello", false),
+            now + Duration::from_secs(59),
+        );
+        points.message(&message("subscriber", "hello", true), now);
+        assert_eq!(points.balance("carkhy", "viewer"), 1);
+        assert_eq!(points.balance("carkhy", "subscriber"), 1);
+        assert_eq!(points.tick(at(4), now), 0);
+        assert_eq!(points.tick(at(5), now), 3);
+        assert_eq!(points.tick(at(9), now), 0);
+        assert_eq!(points.balance("carkhy", "lurker"), 10);
+        assert_eq!(points.balance("carkhy", "viewer"), 11);
+        assert_eq!(points.balance("carkhy", "subscriber"), 16);
+        // writing once is being there for a single interval
+        points.part("carkhy", "lurker");
+        assert_eq!(points.tick(at(10), now), 0);
+        points.message(
+            &message("viewer", "hello", false),
+            now + Duration::from_secs(60),
+        );
+        assert_eq!(points.tick(at(15), now), 1);
+        assert_eq!(points.balance("carkhy", "viewer"), 22);
+    }
+
+    #[test]
+    fn only_points_one_has_are_given() {
+        let mut points = Points::load(&config(), Storage::memory()).unwrap();
+        assert_eq!(points.give("carkhy", None, "Viewer", 50), Ok(50));
+        assert_eq!(
+            points.give("carkhy", Some("viewer"), "friend", 51),
+            Err(Refusal::Overdraft(50))
+        );
+        assert_eq!(
+            points.give("carkhy", Some("viewer"), "friend", 0),
+            Err(Refusal::Nothing)
+        );
+        assert_eq!(
+            points.give("carkhy", Some("viewer"), "VIEWER", 10),
+            Err(Refusal::Themselves)
+        );
+        assert_eq!(points.give("carkhy", Some("Viewer"), "friend", 50), Ok(50));
+        assert_eq!(points.balance("carkhy", "viewer"), 0);
+        assert_eq!(
+            points.give("captaincallback", Some("friend"), "viewer", 1),
+            Err(Refusal::Overdraft(0))
+        );
+        assert_eq!(points.top("carkhy", 5), [("friend".to_owned(), 50)]);
+        // earned by a message and not saved yet
+        points.message(&message("lurker", "hello", false), Instant::now());
+        assert_eq!(
+            points.top("carkhy", 5),
+            [("friend".to_owned(), 50), ("lurker".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn points_and_paid_intervals_survive_a_restart() {
+        let storage = Storage::memory();
+        let now = Instant::now();
+        let mut points = Points::load(&config(), storage.clone()).unwrap();
+        points.join("carkhy", "viewer");
+        points.tick(at(0), now);
+        assert_eq!(points.tick(at(5), now), 1);
+        points.message(&message("viewer", "hello", false), now);
+        points.tick(at(6), now);
+
+        let mut points = Points::load(&config(), storage.clone()).unwrap();
+        assert_eq!(points.balance("carkhy", "viewer"), 11);
+        // reconnected within the interval that was paid already
+        points.join("carkhy", "viewer");
+        assert_eq!(points.tick(at(8), now), 0);
+        assert_eq!(points.tick(at(10), now), 1);
+        assert_eq!(points.balance("carkhy", "viewer"), 21);
+    }
+}
@@ -0,0 +1,494 @@
+use super::{
+    commands::{Args, Command, Context},
+    ChatBotCommand, HelixTask,
+};
+use crate::{
+    config::PollConfig,
+    connect::{Badge, ChatBotEvent, InternalEvent, TextMessage, UserLevel},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::RangeInclusive,
+    rc::Rc,
+    time::Duration,
+};
+use uuid::Uuid;
+
+// the results of the last poll of each channel
+const STORAGE_NAME: &str = "polls";
+pub const MAX_OPTIONS: usize = 5;
+// what twitch allows for the polls it shows on stream
+const TWITCH_TITLE_CHARS: usize = 60;
+const TWITCH_CHOICE_CHARS: usize = 25;
+const TWITCH_DURATION: RangeInclusive<u64> = 15..=1800;
+
+#[derive(Debug)]
+struct Poll {
+    id: Uuid,
+    question: String,
+    options: Vec<String>,
+    // by lowercase login, the index of the option and what the vote counts
+    votes: HashMap<String, (usize, u32)>,
+}
+
+/// What a poll ended with, kept for `!poll last`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollResults {
+    pub question: String,
+    // with the votes they got, subscribers' weighted
+    pub options: Vec<(String, u64)>,
+    pub voters: usize,
+}
+
+impl PollResults {
+    fn of(poll: &Poll) -> Self {
+        let mut options: Vec<_> = poll
+            .options
+            .iter()
+            .map(|option| (option.clone(), 0))
+            .collect();
+        for &(option, weight) in poll.votes.values() {
+            options[option].1 += u64::from(weight);
+        }
+        Self {
+            question: poll.question.clone(),
+            options,
+            voters: poll.votes.len(),
+        }
+    }
+}
+
+impl fmt::Display for PollResults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: u64 = self.options.iter().map(|(_, votes)| votes).sum();
+        write!(f, "{}", self.question)?;
+        for (index, (option, votes)) in self.options.iter().enumerate() {
+            let percent = (votes * 100).checked_div(total).unwrap_or_default();
+            let separator = if index == 0 { "" } else { "," };
+            write!(f, "{} {}: {} ({}%)", separator, option, votes, percent)?;
+        }
+        match self.voters {
+            1 => write!(f, " - 1 voter"),
+            voters => write!(f, " - {} voters", voters),
+        }
+    }
+}
+
+/// Why a poll isn't started or a vote isn't counted.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PollRefusal {
+    Running,
+    // fewer than 2, more than MAX_OPTIONS, empty or the same twice
+    Options,
+    NoPoll,
+    UnknownOption,
+}
+
+/// The polls of every channel, chat votes with the option's number.
+#[derive(Debug, Default)]
+pub struct Polls {
+    config: PollConfig,
+    storage: Storage,
+    // by channel, while they take votes
+    polls: HashMap<String, Poll>,
+    // by channel
+    last: BTreeMap<String, PollResults>,
+}
+
+pub type SharedPolls = Rc<RefCell<Polls>>;
+
+impl Polls {
+    pub fn load(config: &PollConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            config: config.clone(),
+            last: storage.load(STORAGE_NAME)?,
+            storage,
+            ..Default::default()
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.config.duration)
+    }
+
+    pub fn start(
+        &mut self,
+        channel: &str,
+        question: &str,
+        options: Vec<String>,
+    ) -> Result<Uuid, PollRefusal> {
+        if self.polls.contains_key(channel) {
+            return Err(PollRefusal::Running);
+        }
+        let distinct = options.iter().enumerate().all(|(index, option)| {
+            !option.is_empty()
+                && !options[..index]
+                    .iter()
+                    .any(|other| other.eq_ignore_ascii_case(option))
+        });
+        if !(2..=MAX_OPTIONS).contains(&options.len()) || !distinct {
+            return Err(PollRefusal::Options);
+        }
+        let id = Uuid::new_v4();
+        self.polls.insert(
+            channel.to_owned(),
+            Poll {
+                id,
+                question: question.to_owned(),
+                options,
+                votes: HashMap::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Counts the user's vote for the option with the number, an earlier vote is changed.
+    pub fn vote(&mut self, message: &TextMessage, number: &str) -> Result<(), PollRefusal> {
+        let poll = self
+            .polls
+            .get_mut(&message.channel)
+            .ok_or(PollRefusal::NoPoll)?;
+        let option = number
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=poll.options.len()).contains(number))
+            .ok_or(PollRefusal::UnknownOption)?;
+        let subscriber = message
+            .user
+            .badges
+            .iter()
+            .any(|badge| matches!(badge, Badge::Subscriber { .. }));
+        let weight = match subscriber {
+            true => self.config.sub_weight,
+            false => 1,
+        };
+        poll.votes
+            .insert(message.user.name.to_lowercase(), (option - 1, weight));
+        Ok(())
+    }
+
+    /// A message of only a number votes while a poll runs.
+    pub fn message(&mut self, message: &TextMessage) {
+        let text = message.text.trim();
+        if !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit()) {
+            self.vote(message, text).ok();
+        }
+    }
+
+    /// Stops the votes and keeps the results. With an id only that poll ends,
+    /// a later one started meanwhile keeps going.
+    pub fn end(&mut self, channel: &str, id: Option<Uuid>) -> Option<PollResults> {
+        self.polls
+            .get(channel)
+            .filter(|poll| id.is_none_or(|id| id == poll.id))?;
+        let poll = self.polls.remove(channel).expect("found before");
+        let results = PollResults::of(&poll);
+        self.last.insert(channel.to_owned(), results.clone());
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.last) {
+            tracing::warn!(%error, "could not save the poll results");
+        }
+        Some(results)
+    }
+
+    pub fn last(&self, channel: &str) -> Option<&PollResults> {
+        self.last.get(channel)
+    }
+}
+
+/// `!poll start "<question>" <option> <option>...`, `!poll end` and `!poll last`,
+/// options of several words are quoted.
+pub struct PollCommand(pub SharedPolls);
+
+impl Command for PollCommand {
+    fn name(&self) -> &'static str {
+        "poll"
+    }
+
+    fn usage(&self) -> &'static str {
+        "start \"<question>\" <option> <option>..., end, last"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let moderator = ctx.message.has_level(UserLevel::Moderator);
+        let mut polls = self.0.borrow_mut();
+        match args.next().map(str::to_lowercase).as_deref() {
+            Some("start") if moderator => {
+                let question = args.quoted().unwrap_or_default().to_owned();
+                let options: Vec<_> = std::iter::from_fn(|| args.quoted())
+                    .map(str::to_owned)
+                    .collect();
+                let list = options
+                    .iter()
+                    .enumerate()
+                    .map(|(index, option)| format!("{}. {}", index + 1, option))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let id = match polls.start(channel, &question, options) {
+                    Ok(id) => id,
+                    Err(PollRefusal::Running) => {
+                        return ctx.send(format!(
+                            "A poll is running already, end it with {}poll end.",
+                            ctx.prefix
+                        ))
+                    }
+                    Err(_) => {
+                        return ctx.send(format!(
+                            "Usage: {}poll start \"<question>\" <option> <option>..., 2 to {} different options",
+                            ctx.prefix, MAX_OPTIONS
+                        ))
+                    }
+                };
+                let duration = polls.duration();
+                Some(ChatBotCommand::MultipleCommands(vec![
+                    ctx.send(format!(
+                        "Poll: {} {} Vote with the number, it ends in {} seconds.",
+                        question,
+                        list,
+                        duration.as_secs()
+                    ))?,
+                    ChatBotCommand::TimedCallback {
+                        duration,
+                        event: ChatBotEvent::Internal(InternalEvent::PollEnd {
+                            channel: channel.clone(),
+                            id,
+                        }),
+                    },
+                ]))
+            }
+            Some("end") if moderator => ctx.send(match polls.end(channel, None) {
+                Some(results) => format!("The poll is over: {}", results),
+                None => "No poll is running.".to_owned(),
+            }),
+            Some("last") => ctx.send(match polls.last(channel) {
+                Some(results) => format!("The last poll: {}", results),
+                None => "There was no poll yet.".to_owned(),
+            }),
+            _ if moderator => ctx.send(format!(
+                "Usage: {0}poll start \"<question>\" <option> <option>..., {0}poll end or {0}poll last",
+                ctx.prefix
+            )),
+            _ => ctx.send(format!("Usage: {}poll last", ctx.prefix)),
+        }
+    }
+}
+
+/// `!vote <number>`, the same as writing only the number. Nothing is said unless the number
+/// isn't one of the options.
+pub struct Vote(pub SharedPolls);
+
+impl Command for Vote {
+    fn name(&self) -> &'static str {
+        "vote"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<number>"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let Some(number) = args.next() else {
+            return ctx.send(format!("Usage: {}vote <number>", ctx.prefix));
+        };
+        match self.0.borrow_mut().vote(ctx.message, number) {
+            Err(PollRefusal::UnknownOption) => {
+                ctx.send(format!("{} isn't the number of an option.", number))
+            }
+            _ => None,
+        }
+    }
+}
+
+// why twitch would refuse the poll, checked before asking it
+fn check_twitch_poll(title: &str, choices: &[String], duration: u64) -> Result<(), String> {
+    if title.is_empty() || title.chars().count() > TWITCH_TITLE_CHARS {
+        return Err(format!(
+            "The title of a twitch poll has 1 to {} characters.",
+            TWITCH_TITLE_CHARS
+        ));
+    }
+    if !(2..=MAX_OPTIONS).contains(&choices.len()) {
+        return Err(format!("A twitch poll has 2 to {} choices.", MAX_OPTIONS));
+    }
+    if let Some(choice) = choices
+        .iter()
+        .find(|choice| choice.is_empty() || choice.chars().count() > TWITCH_CHOICE_CHARS)
+    {
+        return Err(format!(
+            "\"{}\" isn't a choice of 1 to {} characters.",
+            choice, TWITCH_CHOICE_CHARS
+        ));
+    }
+    if !TWITCH_DURATION.contains(&duration) {
+        return Err(format!(
+            "A twitch poll runs {} to {} seconds.",
+            TWITCH_DURATION.start(),
+            TWITCH_DURATION.end()
+        ));
+    }
+    Ok(())
+}
+
+/// `!twitchpoll "<title>" <choice> <choice>... [seconds]` starts a poll twitch shows on stream,
+/// its results are announced in chat when it ends.
+pub struct TwitchPoll(pub SharedPolls);
+
+impl Command for TwitchPoll {
+    fn name(&self) -> &'static str {
+        "twitchpoll"
+    }
+
+    fn usage(&self) -> &'static str {
+        "\"<title>\" <choice> <choice>... [seconds]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let title = args.quoted().unwrap_or_default().to_owned();
+        let mut choices: Vec<_> = std::iter::from_fn(|| args.quoted())
+            .map(str::to_owned)
+            .collect();
+        // the last argument is the duration if it is a number after two choices
+        let duration = match choices.last().map(|last| last.parse::<u64>()) {
+            Some(Ok(duration)) if choices.len() > 2 => {
+                choices.pop();
+                duration
+            }
+            _ => self.0.borrow().duration().as_secs(),
+        };
+        if choices.is_empty() {
+            return ctx.send(format!(
+                "Usage: {}twitchpoll \"<title>\" <choice> <choice>... [seconds]",
+                ctx.prefix
+            ));
+        }
+        if let Err(problem) = check_twitch_poll(&title, &choices, duration) {
+            return ctx.send(problem);
+        }
+        Some(ChatBotCommand::Helix(HelixTask::CreatePoll {
+            channel: ctx.message.channel.clone(),
+            title,
+            choices,
+            duration: duration as u32,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+
+    fn options(options: &[&str]) -> Vec<String> {
+        options.iter().map(|option| option.to_string()).collect()
+    }
+
+    #[test]
+    fn votes_can_change_until_the_end() {
+        let mut polls = Polls::default();
+        let id = polls
+            .start(
+                "carkhy",
+                "Which boss next?",
+                options(&["Gael", "Nameless King"]),
+            )
+            .unwrap();
+        assert_eq!(
+            polls.start("carkhy", "Another?", options(&["a", "b"])),
+            Err(PollRefusal::Running)
+        );
+        polls.message(&message("a", "1", false));
+        polls.message(&message("a", " 2 ", false));
+        polls.message(&message("b", "1 please", false));
+        polls.message(&message("c", "3", false));
+        assert_eq!(
+            polls.vote(&message("b", "", false), "0"),
+            Err(PollRefusal::UnknownOption)
+        );
+        polls.vote(&message("C", "", false), "1").unwrap();
+        // the expiry of an earlier poll ends nothing
+        assert!(polls.end("carkhy", Some(Uuid::new_v4())).is_none());
+        let results = polls.end("carkhy", Some(id)).unwrap();
+        assert_eq!(
+            results.to_string(),
+            "Which boss next? Gael: 1 (50%), Nameless King: 1 (50%) - 2 voters"
+        );
+        assert_eq!(
+            polls.vote(&message("d", "", false), "1"),
+            Err(PollRefusal::NoPoll)
+        );
+        assert_eq!(polls.last("carkhy"), Some(&results));
+    }
+
+    #[test]
+    fn options_are_checked() {
+        let mut polls = Polls::default();
+        for invalid in [
+            &["only"][..],
+            &["a", "b", "c", "d", "e", "f"],
+            &["a", ""],
+            &["Gael", "gael"],
+        ] {
+            assert_eq!(
+                polls.start("carkhy", "?", options(invalid)),
+                Err(PollRefusal::Options)
+            );
+        }
+        assert!(polls
+            .start("carkhy", "?", options(&["a", "b", "c", "d", "e"]))
+            .is_ok());
+    }
+
+    #[test]
+    fn subscribers_can_count_more() {
+        let config = PollConfig {
+            sub_weight: 3,
+            ..Default::default()
+        };
+        let mut polls = Polls::load(&config, Storage::default()).unwrap();
+        polls
+            .start("carkhy", "Snacks?", options(&["yes", "no", "maybe"]))
+            .unwrap();
+        for (login, vote, subscriber) in [("a", "1", true), ("b", "2", false), ("c", "2", false)] {
+            polls.message(&message(login, vote, subscriber));
+        }
+        assert_eq!(
+            polls.end("carkhy", None).unwrap().to_string(),
+            "Snacks? yes: 3 (60%), no: 2 (40%), maybe: 0 (0%) - 3 voters"
+        );
+    }
+
+    #[test]
+    fn twitch_polls_are_checked_before_asking() {
+        let check = |title: &str, choices: &[&str], duration| {
+            check_twitch_poll(title, &options(choices), duration)
+        };
+        assert_eq!(
+            check("Which boss next?", &["Gael", "Nameless King"], 60),
+            Ok(())
+        );
+        assert_eq!(
+            check("Which boss next?", &["Gael"], 60),
+            Err("A twitch poll has 2 to 5 choices.".to_owned())
+        );
+        assert_eq!(
+            check("?", &["Gael", "The Nameless King of the Storm"], 60),
+            Err(
+                "\"The Nameless King of the Storm\" isn't a choice of 1 to 25 characters."
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            check("?", &["a", "b"], 10),
+            Err("A twitch poll runs 15 to 1800 seconds.".to_owned())
+        );
+        assert!(check("", &["a", "b"], 60).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use super::{
+    commands::{Args, Command, Context},
+    tasks::{PredictionAction, PREDICTION_WINDOW},
+    ChatBotCommand, HelixTask,
+};
+use crate::connect::UserLevel;
+
+// what twitch allows for its predictions
+const TITLE_CHARS: usize = 45;
+const OUTCOME_CHARS: usize = 25;
+const MAX_OUTCOMES: usize = 10;
+// seconds, without a window given
+const DEFAULT_WINDOW: u64 = 120;
+
+// why twitch would refuse the prediction, checked before asking it
+fn check(title: &str, outcomes: &[String], window: u64) -> Result<(), String> {
+    if title.is_empty() || title.chars().count() > TITLE_CHARS {
+        return Err(format!(
+            "The title of a prediction has 1 to {} characters.",
+            TITLE_CHARS
+        ));
+    }
+    if !(2..=MAX_OUTCOMES).contains(&outcomes.len()) {
+        return Err(format!("A prediction has 2 to {} outcomes.", MAX_OUTCOMES));
+    }
+    if let Some(outcome) = outcomes
+        .iter()
+        .find(|outcome| outcome.is_empty() || outcome.chars().count() > OUTCOME_CHARS)
+    {
+        return Err(format!(
+            "\"{}\" isn't an outcome of 1 to {} characters.",
+            outcome, OUTCOME_CHARS
+        ));
+    }
+    if !PREDICTION_WINDOW.contains(&window) {
+        return Err(format!(
+            "A prediction takes points for {} to {} seconds.",
+            PREDICTION_WINDOW.start(),
+            PREDICTION_WINDOW.end()
+        ));
+    }
+    Ok(())
+}
+
+/// `!prediction start "<title>" <outcome> <outcome>... [seconds]`, `!prediction lock`,
+/// `!prediction resolve <number>` and `!prediction cancel` manage twitch's prediction of
+/// channel points, the bot remembers which one is running.
+pub struct PredictionCommand;
+
+impl Command for PredictionCommand {
+    fn name(&self) -> &'static str {
+        "prediction"
+    }
+
+    fn usage(&self) -> &'static str {
+        "start \"<title>\" <outcome> <outcome>... [seconds], lock, resolve <number>, cancel"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let usage = format!(
+            "Usage: {0}prediction start \"<title>\" <outcome> <outcome>... [seconds], {0}prediction lock, {0}prediction resolve <number> or {0}prediction cancel",
+            ctx.prefix
+        );
+        let action = match args.next().map(str::to_lowercase).as_deref() {
+            Some("start") => {
+                let title = args.quoted().unwrap_or_default().to_owned();
+                let mut outcomes: Vec<_> = std::iter::from_fn(|| args.quoted())
+                    .map(str::to_owned)
+                    .collect();
+                // the last argument is the window if it is a number after two outcomes
+                let window = match outcomes.last().map(|last| last.parse::<u64>()) {
+                    Some(Ok(window)) if outcomes.len() > 2 => {
+                        outcomes.pop();
+                        window
+                    }
+                    _ => DEFAULT_WINDOW,
+                };
+                if outcomes.is_empty() {
+                    return ctx.send(usage);
+                }
+                if let Err(problem) = check(&title, &outcomes, window) {
+                    return ctx.send(problem);
+                }
+                PredictionAction::Start {
+                    title,
+                    outcomes,
+                    window: window as u32,
+                }
+            }
+            Some("lock") => PredictionAction::Lock,
+            Some("resolve") => match args.next().map(str::parse) {
+                Some(Ok(number)) => PredictionAction::Resolve(number),
+                _ => return ctx.send(format!("Usage: {}prediction resolve <number>", ctx.prefix)),
+            },
+            Some("cancel") => PredictionAction::Cancel,
+            _ => return ctx.send(usage),
+        };
+        Some(ChatBotCommand::Helix(HelixTask::Prediction {
+            channel: ctx.message.channel.clone(),
+            action,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predictions_are_checked_before_asking() {
+        let outcomes = |outcomes: &[&str]| -> Vec<String> {
+            outcomes.iter().map(|outcome| outcome.to_string()).collect()
+        };
+        assert_eq!(
+            check("Will we win?", &outcomes(&["Yes", "No"]), 120),
+            Ok(())
+        );
+        assert_eq!(
+            check(
+                "Will we win?",
+                &outcomes(&["Yes", "No, the boss is way too strong"]),
+                120
+            ),
+            Err(
+                "\"No, the boss is way too strong\" isn't an outcome of 1 to 25 characters."
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            check("Will we win?", &outcomes(&["Yes"]), 120),
+            Err("A prediction has 2 to 10 outcomes.".to_owned())
+        );
+        assert_eq!(
+            check("Will we win?", &outcomes(&["Yes", "No"]), 10),
+            Err("A prediction takes points for 30 to 1800 seconds.".to_owned())
+        );
+        assert!(check(&"?".repeat(46), &outcomes(&["Yes", "No"]), 120).is_err());
+    }
+}
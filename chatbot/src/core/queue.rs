@@ -0,0 +1,478 @@
+use super::{
+    commands::{Args, Command, Context},
+    songs::{SharedSongs, SongQueue},
+    ChatBotCommand,
+};
+use crate::{
+    config::QueueConfig,
+    connect::{Badge, ChatBotEvent, InternalEvent, TextMessage, UserLevel, MAX_MESSAGE_CHARS},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    time::Duration,
+};
+use uuid::Uuid;
+
+const STORAGE_NAME: &str = "viewer_queue";
+// `!next` announces at most this many at once
+const MAX_NEXT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entrant {
+    // lowercase
+    login: String,
+    name: String,
+    subscriber: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Line {
+    open: bool,
+    // first in line first
+    entrants: Vec<Entrant>,
+}
+
+/// Why a user isn't in line.
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueueRefusal {
+    Closed,
+    // the position the user has already
+    Queued(usize),
+}
+
+/// The viewers waiting to play with the streamer in every channel. The lines are kept
+/// in the storage, so a restart in the middle of a stream keeps everyone's spot.
+#[derive(Debug, Default)]
+pub struct ViewerQueue {
+    config: QueueConfig,
+    storage: Storage,
+    // by channel
+    lines: BTreeMap<String, Line>,
+    // by channel and lowercase login, the grace period of each user in line who parted
+    parted: HashMap<(String, String), Uuid>,
+}
+
+pub type SharedViewerQueue = Rc<RefCell<ViewerQueue>>;
+
+impl ViewerQueue {
+    pub fn load(config: &QueueConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            config: config.clone(),
+            lines: storage.load(STORAGE_NAME)?,
+            storage,
+            parted: HashMap::new(),
+        })
+    }
+
+    fn save(&mut self) {
+        self.lines
+            .retain(|_, line| line.open || !line.entrants.is_empty());
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.lines) {
+            tracing::warn!(%error, "could not save the viewer queue");
+        }
+    }
+
+    /// Opens or closes the line, the users in it keep their spots either way.
+    /// False if it was open or closed already.
+    pub fn set_open(&mut self, channel: &str, open: bool) -> bool {
+        let line = self.lines.entry(channel.to_owned()).or_default();
+        let changed = line.open != open;
+        line.open = open;
+        self.save();
+        changed
+    }
+
+    fn position(&self, channel: &str, login: &str) -> Option<usize> {
+        let line = self.lines.get(channel)?;
+        let index = line
+            .entrants
+            .iter()
+            .position(|entrant| entrant.login == login)?;
+        Some(index + 1)
+    }
+
+    /// Puts the user in line, behind the other subscribers with sub_priority.
+    /// The position counts the first in line as 1.
+    pub fn join(&mut self, message: &TextMessage) -> Result<usize, QueueRefusal> {
+        let (channel, login) = (&message.channel, message.user.name.to_lowercase());
+        if let Some(position) = self.position(channel, &login) {
+            return Err(QueueRefusal::Queued(position));
+        }
+        let line = self
+            .lines
+            .get_mut(channel)
+            .filter(|line| line.open)
+            .ok_or(QueueRefusal::Closed)?;
+        let subscriber = message
+            .user
+            .badges
+            .iter()
+            .any(|badge| matches!(badge, Badge::Subscriber { .. }));
+        let index = match subscriber && self.config.sub_priority {
+            true => line
+                .entrants
+                .iter()
+                .position(|entrant| !entrant.subscriber)
+                .unwrap_or(line.entrants.len()),
+            false => line.entrants.len(),
+        };
+        line.entrants.insert(
+            index,
+            Entrant {
+                login,
+                name: message.user.display_name().to_owned(),
+                subscriber,
+            },
+        );
+        self.save();
+        Ok(index + 1)
+    }
+
+    /// Takes the user out of line, False if they weren't in it.
+    pub fn leave(&mut self, channel: &str, login: &str) -> bool {
+        let login = login.to_lowercase();
+        self.parted.remove(&(channel.to_owned(), login.clone()));
+        let Some(line) = self.lines.get_mut(channel) else {
+            return false;
+        };
+        let before = line.entrants.len();
+        line.entrants.retain(|entrant| entrant.login != login);
+        let left = line.entrants.len() < before;
+        self.save();
+        left
+    }
+
+    /// The names of the first users in line, who are out of it then.
+    pub fn next(&mut self, channel: &str, count: usize) -> Vec<String> {
+        let Some(line) = self.lines.get_mut(channel) else {
+            return Vec::new();
+        };
+        let count = count.min(line.entrants.len());
+        let next: Vec<_> = line.entrants.drain(..count).collect();
+        for entrant in &next {
+            self.parted
+                .remove(&(channel.to_owned(), entrant.login.clone()));
+        }
+        self.save();
+        next.into_iter().map(|entrant| entrant.name).collect()
+    }
+
+    /// The users in line with their positions, as many as fit in one chat message.
+    pub fn list(&self, channel: &str) -> String {
+        let entrants = match self.lines.get(channel) {
+            Some(line) if !line.entrants.is_empty() => &line.entrants,
+            _ => return "Nobody is in the queue.".to_owned(),
+        };
+        let mut text = format!("Queue ({}):", entrants.len());
+        for (index, entrant) in entrants.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+            let item = format!("{} {}. {}", separator, index + 1, entrant.name);
+            // the others are on later pages, the first ones go next anyway
+            if text.chars().count() + item.chars().count() + 4 > MAX_MESSAGE_CHARS {
+                text.push_str(" ...");
+                break;
+            }
+            text.push_str(&item);
+        }
+        text
+    }
+
+    /// A user in line left the channel, they are dropped after the grace period
+    /// unless they come back. Nothing without drop_parted.
+    pub fn part(&mut self, channel: &str, login: &str) -> Option<ChatBotCommand> {
+        let login = login.to_lowercase();
+        if !self.config.drop_parted {
+            return None;
+        }
+        self.position(channel, &login)?;
+        let id = Uuid::new_v4();
+        self.parted.insert((channel.to_owned(), login.clone()), id);
+        Some(ChatBotCommand::TimedCallback {
+            duration: Duration::from_secs(self.config.part_grace_minutes * 60),
+            event: ChatBotEvent::Internal(InternalEvent::QueueGrace {
+                channel: channel.to_owned(),
+                login,
+                id,
+            }),
+        })
+    }
+
+    /// The user joined or wrote, they keep their spot.
+    pub fn seen(&mut self, channel: &str, login: &str) {
+        if !self.parted.is_empty() {
+            self.parted
+                .remove(&(channel.to_owned(), login.to_lowercase()));
+        }
+    }
+
+    /// The grace period is over, the user is dropped if they haven't come back since.
+    pub fn grace_over(&mut self, channel: &str, login: &str, id: Uuid) {
+        let key = (channel.to_owned(), login.to_owned());
+        if self.parted.get(&key) == Some(&id) {
+            self.leave(channel, login);
+        }
+    }
+}
+
+/// `!queue open`, `!queue close` and `!queue list`. A bare `!queue` lists the song
+/// requests when they are enabled, since both want the name.
+pub struct QueueCommand {
+    pub viewers: SharedViewerQueue,
+    pub songs: Option<SharedSongs>,
+}
+
+impl Command for QueueCommand {
+    fn name(&self) -> &'static str {
+        "queue"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[open|close|list]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let moderator = ctx.message.has_level(UserLevel::Moderator);
+        match args.next().map(str::to_lowercase).as_deref() {
+            Some("open") if moderator => {
+                let text = match self.viewers.borrow_mut().set_open(channel, true) {
+                    true => format!("The queue is open, join it with {}join!", ctx.prefix),
+                    false => "The queue is open already.".to_owned(),
+                };
+                ctx.send(text)
+            }
+            Some("close") if moderator => {
+                let text = match self.viewers.borrow_mut().set_open(channel, false) {
+                    true => "The queue is closed, whoever is in it keeps their spot.",
+                    false => "The queue is closed already.",
+                };
+                ctx.send(text.to_owned())
+            }
+            Some("list") => ctx.send(self.viewers.borrow().list(channel)),
+            None => match &self.songs {
+                Some(songs) => SongQueue(songs.clone()).execute(ctx, args),
+                None => ctx.send(self.viewers.borrow().list(channel)),
+            },
+            _ if moderator => ctx.send(format!(
+                "Usage: {0}queue open, {0}queue close or {0}queue list",
+                ctx.prefix
+            )),
+            _ => ctx.send(format!("Usage: {}queue list", ctx.prefix)),
+        }
+    }
+}
+
+/// The answer to a bare `!join`, which enters the queue. With a channel it is still the
+/// broadcaster's command to join one.
+pub fn join(queue: &SharedViewerQueue, message: &TextMessage) -> String {
+    let name = message.user.display_name();
+    match queue.borrow_mut().join(message) {
+        Ok(position) => format!("{} joined the queue at number {}.", name, position),
+        Err(QueueRefusal::Closed) => "The queue is closed.".to_owned(),
+        Err(QueueRefusal::Queued(position)) => {
+            format!(
+                "{}, you are number {} in the queue already.",
+                name, position
+            )
+        }
+    }
+}
+
+/// `!leave` takes the user out of the queue.
+pub struct Leave(pub SharedViewerQueue);
+
+impl Command for Leave {
+    fn name(&self) -> &'static str {
+        "leave"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let user = &ctx.message.user;
+        let left = self.0.borrow_mut().leave(&ctx.message.channel, &user.name);
+        ctx.send(match left {
+            true => format!("{} left the queue.", user.display_name()),
+            false => format!("{}, you aren't in the queue.", user.display_name()),
+        })
+    }
+}
+
+/// `!position` tells the user their spot in the queue.
+pub struct Position(pub SharedViewerQueue);
+
+impl Command for Position {
+    fn name(&self) -> &'static str {
+        "position"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let user = &ctx.message.user;
+        let position = self
+            .0
+            .borrow()
+            .position(&ctx.message.channel, &user.name.to_lowercase());
+        ctx.send(match position {
+            Some(position) => format!(
+                "{}, you are number {} in the queue.",
+                user.display_name(),
+                position
+            ),
+            None => format!(
+                "{}, you aren't in the queue, join it with {}join.",
+                user.display_name(),
+                ctx.prefix
+            ),
+        })
+    }
+}
+
+/// `!next [n]` takes the first users out of the queue and announces them.
+pub struct Next(pub SharedViewerQueue);
+
+impl Command for Next {
+    fn name(&self) -> &'static str {
+        "next"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[n]"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let count = match args.next().map(str::parse) {
+            None => 1,
+            Some(Ok(count)) if (1..=MAX_NEXT).contains(&count) => count,
+            Some(_) => {
+                return ctx.send(format!(
+                    "Usage: {}next [n], at most {} at once",
+                    ctx.prefix, MAX_NEXT
+                ))
+            }
+        };
+        let next = self.0.borrow_mut().next(&ctx.message.channel, count);
+        ctx.send(match next.as_slice() {
+            [] => "Nobody is in the queue.".to_owned(),
+            [name] => format!("{}, you're up!", name),
+            names => format!("{}, you're up!", names.join(", ")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+
+    fn queue(sub_priority: bool) -> ViewerQueue {
+        let config = QueueConfig {
+            sub_priority,
+            ..Default::default()
+        };
+        let mut queue = ViewerQueue::load(&config, Storage::default()).unwrap();
+        queue.set_open("carkhy", true);
+        queue
+    }
+
+    #[test]
+    fn users_are_in_line_once() {
+        let mut queue = queue(false);
+        assert_eq!(queue.join(&message("viewer", "", false)), Ok(1));
+        assert_eq!(queue.join(&message("other", "", false)), Ok(2));
+        assert_eq!(
+            queue.join(&message("Viewer", "", false)),
+            Err(QueueRefusal::Queued(1))
+        );
+        queue.set_open("carkhy", false);
+        assert_eq!(
+            queue.join(&message("late", "", false)),
+            Err(QueueRefusal::Closed)
+        );
+        // closing keeps the line, a user in it still gets their position
+        assert_eq!(
+            queue.join(&message("other", "", false)),
+            Err(QueueRefusal::Queued(2))
+        );
+        assert!(queue.leave("carkhy", "Viewer"));
+        assert!(!queue.leave("carkhy", "viewer"));
+        assert_eq!(queue.position("carkhy", "other"), Some(1));
+    }
+
+    #[test]
+    fn subscribers_go_ahead_of_viewers() {
+        let mut queue = queue(true);
+        assert_eq!(queue.join(&message("viewer", "", false)), Ok(1));
+        assert_eq!(queue.join(&message("sub", "", true)), Ok(1));
+        assert_eq!(queue.join(&message("other", "", false)), Ok(3));
+        // behind the subscribers before them
+        assert_eq!(queue.join(&message("sub2", "", true)), Ok(2));
+        let mut queue = self::queue(false);
+        assert_eq!(queue.join(&message("viewer", "", false)), Ok(1));
+        assert_eq!(queue.join(&message("sub", "", true)), Ok(2));
+    }
+
+    #[test]
+    fn next_takes_them_in_order_and_survives_a_restart() {
+        let storage = Storage::memory();
+        let config = QueueConfig::default();
+        let mut queue = ViewerQueue::load(&config, storage.clone()).unwrap();
+        queue.set_open("carkhy", true);
+        for login in ["first", "second", "third", "fourth"] {
+            queue.join(&message(login, "", false)).unwrap();
+        }
+        assert_eq!(queue.next("carkhy", 1), ["first"]);
+        let mut queue = ViewerQueue::load(&config, storage.clone()).unwrap();
+        assert_eq!(queue.next("carkhy", 2), ["second", "third"]);
+        assert_eq!(queue.join(&message("fifth", "", false)), Ok(2));
+        assert_eq!(queue.next("carkhy", 5), ["fourth", "fifth"]);
+        assert!(queue.next("carkhy", 1).is_empty());
+    }
+
+    #[test]
+    fn parted_users_are_dropped_after_the_grace_period() {
+        let config = QueueConfig {
+            drop_parted: true,
+            ..Default::default()
+        };
+        let mut queue = ViewerQueue::load(&config, Storage::default()).unwrap();
+        queue.set_open("carkhy", true);
+        queue.join(&message("viewer", "", false)).unwrap();
+        queue.join(&message("other", "", false)).unwrap();
+        let grace = |command| match command {
+            Some(ChatBotCommand::TimedCallback {
+                event: ChatBotEvent::Internal(InternalEvent::QueueGrace { login, id, .. }),
+                ..
+            }) => (login, id),
+            other => panic!("{:?}", other),
+        };
+        let (login, id) = grace(queue.part("carkhy", "Viewer"));
+        assert!(queue.part("carkhy", "nobody").is_none());
+        let (other, other_id) = grace(queue.part("carkhy", "other"));
+        // coming back keeps the spot
+        queue.seen("carkhy", "Other");
+        queue.grace_over("carkhy", &other, other_id);
+        queue.grace_over("carkhy", &login, id);
+        assert_eq!(queue.position("carkhy", "viewer"), None);
+        assert_eq!(queue.position("carkhy", "other"), Some(1));
+    }
+
+    #[test]
+    fn the_list_fits_in_a_message() {
+        let mut queue = queue(false);
+        for index in 0..100 {
+            queue
+                .join(&message(&format!("viewer_number_{}", index), "", false))
+                .unwrap();
+        }
+        let list = queue.list("carkhy");
+        assert!(list.starts_with("Queue (100): 1. viewer_number_0, 2. viewer_number_1,"));
+        assert!(list.ends_with(" ..."));
+        assert!(list.chars().count() <= MAX_MESSAGE_CHARS);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{config::RaffleConfig, connect::SubTier};
+use fastrand::Rng;
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+// the weight of a ticket, so luck decay can take a part of it
+const TICKET: u64 = 100;
+
+/// Why an entrant can't win a drawing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exclusion {
+    // by an earlier drawing of the raffle
+    Drawn,
+    WatchTime,
+    Points,
+    RecentWinner,
+    // luck decay took all of their weight
+    Decayed,
+}
+
+impl fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Exclusion::Drawn => "drawn already",
+            Exclusion::WatchTime => "watched too little",
+            Exclusion::Points => "too few points",
+            Exclusion::RecentWinner => "won recently",
+            Exclusion::Decayed => "no luck left",
+        })
+    }
+}
+
+/// What a drawing knows about an entrant.
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    // lowercase
+    pub login: &'a str,
+    // by their subscriber badge when they entered
+    pub tickets: u32,
+    // in the channel, counted by the watch time
+    pub watched: Duration,
+    pub points: u64,
+    // when they won in the channel before
+    pub wins: &'a [SystemTime],
+}
+
+/// The weight of each candidate or why they were left out, and the winners drawn with the
+/// seed, for the audit log.
+#[derive(Debug)]
+pub struct Drawing {
+    pub seed: u64,
+    // in the order of the candidates
+    pub weights: Vec<(String, Result<u64, Exclusion>)>,
+    // the candidates in the order they were drawn
+    pub winners: Vec<usize>,
+}
+
+impl fmt::Display for Drawing {
+    // "viewer 1.00, subscriber 2.00, lurker out (watched too little)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (login, weight)) in self.weights.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            match weight {
+                Ok(weight) => write!(f, "{} {}.{:02}", login, weight / TICKET, weight % TICKET)?,
+                Err(exclusion) => write!(f, "{} out ({})", login, exclusion)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Weighs the entrants of a raffle by the rules of the `[raffle]` table and draws the
+/// winners. The same seed and entrants draw the same winners.
+#[derive(Debug, Clone, Default)]
+pub struct DrawEngine {
+    sub_tickets: u32,
+    tier_scaled: bool,
+    min_watch: Duration,
+    min_points: u64,
+    exclude_winners: Duration,
+    luck_decay: Duration,
+    luck_decay_percent: u64,
+}
+
+impl DrawEngine {
+    pub fn new(config: &RaffleConfig) -> Self {
+        Self {
+            sub_tickets: config.sub_tickets,
+            tier_scaled: config.tier_scaled,
+            min_watch: Duration::from_secs(config.min_total_watch_minutes * 60),
+            min_points: config.min_points,
+            exclude_winners: DAY * config.exclude_winners_days,
+            luck_decay: DAY * config.luck_decay_days,
+            luck_decay_percent: config.luck_decay_percent.into(),
+        }
+    }
+
+    /// The entries of a user, by the tier of their subscriber badge.
+    pub fn tickets(&self, tier: Option<SubTier>) -> u32 {
+        match (tier, self.tier_scaled) {
+            (None, _) => 1,
+            (Some(SubTier::Tier2), true) => self.sub_tickets.saturating_mul(2),
+            (Some(SubTier::Tier3), true) => self.sub_tickets.saturating_mul(3),
+            (Some(_), _) => self.sub_tickets,
+        }
+    }
+
+    /// How long wins are remembered for the rules.
+    pub fn remembered(&self) -> Duration {
+        match self.luck_decay_percent {
+            0 => self.exclude_winners,
+            _ => self.exclude_winners.max(self.luck_decay),
+        }
+    }
+
+    /// The chance of the candidate, relative to the others, or why they can't win.
+    pub fn weight(&self, candidate: &Candidate, now: SystemTime) -> Result<u64, Exclusion> {
+        if candidate.watched < self.min_watch {
+            return Err(Exclusion::WatchTime);
+        }
+        if candidate.points < self.min_points {
+            return Err(Exclusion::Points);
+        }
+        // a win in the future, by a clock set back, counts as recent
+        let won_within = |period: Duration| {
+            candidate
+                .wins
+                .iter()
+                .filter(|won| now.duration_since(**won).unwrap_or_default() < period)
+                .count()
+        };
+        if won_within(self.exclude_winners) > 0 {
+            return Err(Exclusion::RecentWinner);
+        }
+        let mut weight = u64::from(candidate.tickets) * TICKET;
+        if self.luck_decay_percent > 0 {
+            for _ in 0..won_within(self.luck_decay) {
+                weight = weight * (100 - self.luck_decay_percent) / 100;
+            }
+        }
+        match weight {
+            0 => Err(Exclusion::Decayed),
+            weight => Ok(weight),
+        }
+    }
+
+    /// Draws up to count distinct winners among the candidates not drawn before.
+    pub fn draw(
+        &self,
+        candidates: &[Candidate],
+        drawn: &[String],
+        count: usize,
+        seed: u64,
+        now: SystemTime,
+    ) -> Drawing {
+        let weights: Vec<_> = candidates
+            .iter()
+            .map(|candidate| {
+                let weight = match drawn.iter().any(|login| login == candidate.login) {
+                    true => Err(Exclusion::Drawn),
+                    false => self.weight(candidate, now),
+                };
+                (candidate.login.to_owned(), weight)
+            })
+            .collect();
+        let mut rng = Rng::with_seed(seed);
+        let mut left: Vec<(usize, u64)> = weights
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, weight))| Some((index, *weight.as_ref().ok()?)))
+            .collect();
+        let mut winners = Vec::new();
+        while winners.len() < count && !left.is_empty() {
+            let total: u64 = left.iter().map(|(_, weight)| weight).sum();
+            let mut ticket = rng.u64(..total);
+            let position = left
+                .iter()
+                .position(|(_, weight)| match ticket < *weight {
+                    true => true,
+                    false => {
+                        ticket -= weight;
+                        false
+                    }
+                })
+                .expect("the ticket is one of them");
+            winners.push(left.remove(position).0);
+        }
+        Drawing {
+            seed,
+            weights,
+            winners,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate<'a>(login: &'a str, tickets: u32, wins: &'a [SystemTime]) -> Candidate<'a> {
+        Candidate {
+            login,
+            tickets,
+            watched: Duration::from_secs(60 * 60),
+            points: 500,
+            wins,
+        }
+    }
+
+    // how often each candidate wins a single drawing, over many seeds
+    fn wins(engine: &DrawEngine, candidates: &[Candidate], now: SystemTime) -> Vec<u32> {
+        let mut wins = vec![0; candidates.len()];
+        for seed in 0..10_000 {
+            for winner in engine.draw(candidates, &[], 1, seed, now).winners {
+                wins[winner] += 1;
+            }
+        }
+        wins
+    }
+
+    #[test]
+    fn tiers_and_luck_decay_weigh_the_chances() {
+        let config = RaffleConfig {
+            sub_tickets: 2,
+            tier_scaled: true,
+            luck_decay_days: 30,
+            luck_decay_percent: 50,
+            ..Default::default()
+        };
+        let engine = DrawEngine::new(&config);
+        assert_eq!(engine.tickets(None), 1);
+        assert_eq!(engine.tickets(Some(SubTier::Prime)), 2);
+        assert_eq!(engine.tickets(Some(SubTier::Tier1)), 2);
+        assert_eq!(engine.tickets(Some(SubTier::Tier3)), 6);
+        let now = SystemTime::now();
+        let won = [now - DAY * 3];
+        let candidates = [
+            candidate("viewer", 1, &[]),
+            candidate("subscriber", 2, &[]),
+            // six tickets, halved by the recent win
+            candidate("tier3", 6, &won),
+        ];
+        let wins = wins(&engine, &candidates, now);
+        // 1 : 2 : 3 of the drawings
+        for (wins, expected) in wins.iter().zip([1667, 3333, 5000]) {
+            assert!(
+                wins.abs_diff(expected) < 200,
+                "{} instead of {}",
+                wins,
+                expected
+            );
+        }
+        let drawing = engine.draw(&candidates, &[], 3, 7, now);
+        assert_eq!(
+            drawing.to_string(),
+            "viewer 1.00, subscriber 2.00, tier3 3.00"
+        );
+        assert_eq!(drawing.winners.len(), 3);
+        assert_eq!(
+            engine.draw(&candidates, &[], 3, 7, now).winners,
+            drawing.winners
+        );
+    }
+
+    #[test]
+    fn winners_are_distinct() {
+        let engine = DrawEngine::default();
+        let logins: Vec<_> = (0..10).map(|index| index.to_string()).collect();
+        let candidates: Vec<_> = logins
+            .iter()
+            .zip(1..)
+            .map(|(login, tickets)| candidate(login, tickets, &[]))
+            .collect();
+        for seed in 0..100 {
+            let mut drawn = engine
+                .draw(&candidates, &["9".to_owned()], 20, seed, SystemTime::now())
+                .winners;
+            assert_eq!(drawn.len(), 9);
+            drawn.sort();
+            drawn.dedup();
+            assert_eq!(drawn, (0..9).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn rules_exclude_exactly() {
+        let config = RaffleConfig {
+            min_total_watch_minutes: 60,
+            min_points: 100,
+            exclude_winners_days: 7,
+            luck_decay_days: 30,
+            luck_decay_percent: 100,
+            ..Default::default()
+        };
+        let engine = DrawEngine::new(&config);
+        let now = SystemTime::now();
+        let (week_ago, nearly_a_week_ago) =
+            ([now - DAY * 7], [now - DAY * 7 + Duration::from_secs(1)]);
+        let month_ago = [now - DAY * 30];
+        let future = [now + DAY];
+        let mut short = candidate("short", 1, &[]);
+        short.watched = Duration::from_secs(59 * 60);
+        let mut poor = candidate("poor", 1, &[]);
+        poor.points = 99;
+        let mut enough = candidate("enough", 1, &month_ago);
+        enough.points = 100;
+        let candidates = [
+            short,
+            poor,
+            enough,
+            candidate("recent", 1, &nearly_a_week_ago),
+            candidate("unlucky", 1, &week_ago),
+            candidate("skewed", 1, &future),
+            candidate("drawn", 1, &[]),
+        ];
+        let drawing = engine.draw(&candidates, &["drawn".to_owned()], 10, 1, now);
+        let weights: Vec<_> = drawing.weights.iter().map(|(_, weight)| *weight).collect();
+        assert_eq!(
+            weights,
+            [
+                Err(Exclusion::WatchTime),
+                Err(Exclusion::Points),
+                Ok(100),
+                Err(Exclusion::RecentWinner),
+                Err(Exclusion::Decayed),
+                Err(Exclusion::RecentWinner),
+                Err(Exclusion::Drawn),
+            ]
+        );
+        assert_eq!(drawing.winners, [2]);
+        assert_eq!(engine.remembered(), DAY * 30);
+        assert!(drawing
+            .to_string()
+            .starts_with("short out (watched too little), "));
+    }
+}
@@ -0,0 +1,633 @@
+mod draw;
+
+use draw::{Candidate, DrawEngine};
+
+use super::{
+    calendar::timestamp,
+    commands::{Args, Command, Context},
+    points::SharedPoints,
+    tasks::whisper,
+    watch_time::SharedWatchTime,
+    ChatBotCommand, HelixTask,
+};
+use crate::{
+    config::{KeywordMatch, RaffleConfig},
+    connect::{Badge, ChatBotEvent, InternalEvent, TextMessage, UserLevel},
+    storage::{Storage, StorageError},
+};
+use fastrand::Rng;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+// entrants and winners of every drawing, with the seed to draw them again
+const AUDIT_LOG: &str = "raffles";
+// when each user won, for the rules about recent winners
+const WINS_STORAGE: &str = "raffle_wins";
+const MAX_WINNERS: usize = 20;
+
+// by channel and lowercase login, the seconds since the epoch of each win
+type Wins = BTreeMap<String, BTreeMap<String, Vec<u64>>>;
+
+#[derive(Debug)]
+struct Entrant {
+    // lowercase
+    login: String,
+    name: String,
+    tickets: u32,
+}
+
+#[derive(Debug)]
+struct Raffle {
+    id: Uuid,
+    keyword: String,
+    winners: usize,
+    open: bool,
+    // in the order they entered
+    entrants: Vec<Entrant>,
+    // lowercase logins of the entrants and of those twitch is asked about
+    entered: HashSet<String>,
+    // lowercase logins of everyone drawn, rerolls exclude them
+    drawn: Vec<String>,
+}
+
+fn names(entrants: &[Entrant], drawn: &[usize]) -> String {
+    let names: Vec<_> = drawn
+        .iter()
+        .map(|&index| entrants[index].name.as_str())
+        .collect();
+    names.join(", ")
+}
+
+/// The giveaways of every channel, users enter by writing the keyword.
+#[derive(Debug, Default)]
+pub struct Raffles {
+    config: RaffleConfig,
+    storage: Storage,
+    // only picks the seeds of the drawings
+    rng: Rng,
+    // by channel, the latest raffle stays after its end for rerolls
+    raffles: HashMap<String, Raffle>,
+    // by channel and lowercase login, since when the user is in chat,
+    // only kept with min_watch_minutes
+    seen: HashMap<(String, String), Instant>,
+    // by channel, the winners drawn but not whispered yet, with whisper_winners
+    untold: Vec<(String, String)>,
+    engine: DrawEngine,
+    // what the rules of the engine ask about the entrants
+    watch_time: SharedWatchTime,
+    points: SharedPoints,
+    wins: Wins,
+}
+
+pub type SharedRaffles = Rc<RefCell<Raffles>>;
+
+impl Raffles {
+    /// With the earlier winners saved in the storage.
+    pub fn load(
+        config: &RaffleConfig,
+        storage: Storage,
+        rng: Rng,
+        watch_time: SharedWatchTime,
+        points: SharedPoints,
+    ) -> Result<Self, StorageError> {
+        Ok(Self {
+            config: config.clone(),
+            wins: storage.load(WINS_STORAGE)?,
+            storage,
+            rng,
+            engine: DrawEngine::new(config),
+            watch_time,
+            points,
+            ..Default::default()
+        })
+    }
+
+    fn watched(&self) -> Duration {
+        Duration::from_secs(self.config.min_watch_minutes * 60)
+    }
+
+    /// The user is in chat from now on unless seen before.
+    pub fn join(&mut self, channel: &str, login: &str, now: Instant) {
+        if self.watched().is_zero() {
+            return;
+        }
+        self.seen
+            .entry((channel.to_owned(), login.to_lowercase()))
+            .or_insert(now);
+    }
+
+    pub fn part(&mut self, channel: &str, login: &str) {
+        self.seen
+            .remove(&(channel.to_owned(), login.to_lowercase()));
+    }
+
+    /// The bot left the channel, its raffle is gone.
+    pub fn leave(&mut self, channel: &str) {
+        self.raffles.remove(channel);
+        self.seen.retain(|(seen, _), _| seen != channel);
+    }
+
+    fn audit(&self, line: &str) {
+        if let Err(error) = self.storage.append(AUDIT_LOG, line) {
+            tracing::warn!(%error, "could not log the raffle");
+        }
+    }
+
+    /// A new raffle, refused while another one takes entries.
+    pub fn start(&mut self, channel: &str, keyword: &str, winners: usize) -> Option<Uuid> {
+        if self.raffles.get(channel).is_some_and(|raffle| raffle.open) {
+            return None;
+        }
+        let id = Uuid::new_v4();
+        self.raffles.insert(
+            channel.to_owned(),
+            Raffle {
+                id,
+                keyword: keyword.to_owned(),
+                winners,
+                open: true,
+                entrants: Vec::new(),
+                entered: HashSet::new(),
+                drawn: Vec::new(),
+            },
+        );
+        Some(id)
+    }
+
+    /// Enters the user if the message has the keyword and they may enter. Followers are
+    /// entered after twitch confirmed it, see [InternalEvent::RaffleEntry].
+    pub fn enter(&mut self, message: &TextMessage, now: Instant) -> Option<ChatBotCommand> {
+        let (channel, login) = (&message.channel, message.user.name.to_lowercase());
+        self.join(channel, &login, now);
+        let watched = self.watched();
+        let since = self.seen.get(&(channel.clone(), login.clone())).copied();
+        let raffle = self.raffles.get_mut(channel).filter(|raffle| raffle.open)?;
+        let keyword = &raffle.keyword;
+        let has_keyword = match self.config.keyword_match {
+            KeywordMatch::Message => message.text.trim().eq_ignore_ascii_case(keyword),
+            KeywordMatch::Word => message
+                .text
+                .split_whitespace()
+                .any(|word| word.eq_ignore_ascii_case(keyword)),
+        };
+        if !has_keyword || raffle.entered.contains(&login) {
+            return None;
+        }
+        // the tier when entering counts, also if the subscription ends before the drawing
+        let tier = message.user.badges.iter().find_map(|badge| match badge {
+            Badge::Subscriber { tier, .. } => Some(*tier),
+            _ => None,
+        });
+        if self.config.subs_only && tier.is_none() {
+            return None;
+        }
+        if !watched.is_zero() && since.is_none_or(|since| now < since + watched) {
+            return None;
+        }
+        let tickets = self.engine.tickets(tier);
+        raffle.entered.insert(login.clone());
+        let name = message.user.display_name().to_owned();
+        if self.config.followers_only {
+            return Some(ChatBotCommand::Helix(HelixTask::RaffleFollower {
+                channel: channel.clone(),
+                login,
+                name,
+                tickets,
+                raffle: raffle.id,
+            }));
+        }
+        raffle.entrants.push(Entrant {
+            login,
+            name,
+            tickets,
+        });
+        None
+    }
+
+    /// Twitch confirmed the user follows, they enter unless the raffle ended meanwhile.
+    pub fn follower(&mut self, channel: &str, id: Uuid, login: &str, name: &str, tickets: u32) {
+        if let Some(raffle) = self
+            .raffles
+            .get_mut(channel)
+            .filter(|raffle| raffle.open && raffle.id == id)
+        {
+            raffle.entrants.push(Entrant {
+                login: login.to_owned(),
+                name: name.to_owned(),
+                tickets,
+            });
+        }
+    }
+
+    // the winners of a new drawing by the rules, logged with its seed and the weights
+    fn draw(&mut self, channel: &str, count: usize, what: &str) -> Option<(String, usize)> {
+        let seed = self.rng.u64(..);
+        let now = SystemTime::now();
+        let raffle = self.raffles.get_mut(channel)?;
+        let drawing = {
+            let (watch_time, points) = (self.watch_time.borrow(), self.points.borrow());
+            let wins: Vec<Vec<SystemTime>> = raffle
+                .entrants
+                .iter()
+                .map(|entrant| {
+                    let won = self
+                        .wins
+                        .get(channel)
+                        .and_then(|wins| wins.get(&entrant.login));
+                    won.into_iter()
+                        .flatten()
+                        .map(|seconds| UNIX_EPOCH + Duration::from_secs(*seconds))
+                        .collect()
+                })
+                .collect();
+            let candidates: Vec<_> = raffle
+                .entrants
+                .iter()
+                .zip(&wins)
+                .map(|(entrant, wins)| Candidate {
+                    login: &entrant.login,
+                    tickets: entrant.tickets,
+                    watched: watch_time.watched(channel, &entrant.login),
+                    points: points.balance(channel, &entrant.login),
+                    wins,
+                })
+                .collect();
+            self.engine
+                .draw(&candidates, &raffle.drawn, count, seed, now)
+        };
+        let drawn = &drawing.winners;
+        raffle.drawn.extend(
+            drawn
+                .iter()
+                .map(|&index| raffle.entrants[index].login.clone()),
+        );
+        let winners = names(&raffle.entrants, drawn);
+        if self.config.whisper_winners {
+            self.untold.extend(
+                drawn
+                    .iter()
+                    .map(|&index| (channel.to_owned(), raffle.entrants[index].login.clone())),
+            );
+        }
+        let line = format!(
+            "{} #{} {} of \"{}\" with seed {}, {} entrants: {}; drawn: {}",
+            timestamp(now),
+            channel,
+            what,
+            raffle.keyword,
+            drawing.seed,
+            raffle.entrants.len(),
+            drawing,
+            winners
+        );
+        let logins: Vec<_> = drawn
+            .iter()
+            .map(|&index| raffle.entrants[index].login.clone())
+            .collect();
+        self.audit(&line);
+        self.won(channel, logins, now);
+        Some((winners, drawn.len()))
+    }
+
+    // remembered as long as the rules ask about them
+    fn won(&mut self, channel: &str, logins: Vec<String>, now: SystemTime) {
+        let remembered = self.engine.remembered();
+        if remembered.is_zero() || logins.is_empty() {
+            return;
+        }
+        let seconds = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        };
+        let oldest = seconds(now).saturating_sub(remembered.as_secs());
+        let wins = self.wins.entry(channel.to_owned()).or_default();
+        for login in logins {
+            wins.entry(login).or_default().push(seconds(now));
+        }
+        for won in wins.values_mut() {
+            won.retain(|&won| won > oldest);
+        }
+        wins.retain(|_, won| !won.is_empty());
+        if let Err(error) = self.storage.save(WINS_STORAGE, &self.wins) {
+            tracing::warn!(%error, "could not save the raffle winners");
+        }
+    }
+
+    /// Stops the entries and announces the winners. With an id only that raffle ends,
+    /// a later one started meanwhile keeps going.
+    pub fn end(&mut self, channel: &str, id: Option<Uuid>) -> Option<String> {
+        let raffle = self
+            .raffles
+            .get_mut(channel)
+            .filter(|raffle| raffle.open && id.is_none_or(|id| id == raffle.id))?;
+        raffle.open = false;
+        let (count, entered) = (raffle.winners, raffle.entrants.len());
+        let (winners, drawn) = self.draw(channel, count, "drawing")?;
+        Some(match (drawn, entered) {
+            (0, 0) => "The raffle is over, nobody entered.".to_owned(),
+            (0, _) => format!(
+                "The raffle is over, none of the {} entrants can win this time.",
+                entered
+            ),
+            (1, 1) => format!("The raffle is over, the only entrant {} wins!", winners),
+            _ => format!(
+                "The raffle is over, {} entered. Congratulations to {}!",
+                entered, winners
+            ),
+        })
+    }
+
+    /// The answer, along with a whisper to each winner drawn since the last.
+    pub fn with_whispers(&mut self, answer: Option<ChatBotCommand>) -> Option<ChatBotCommand> {
+        if self.untold.is_empty() {
+            return answer;
+        }
+        let mut commands: Vec<_> = answer.into_iter().collect();
+        for (channel, login) in std::mem::take(&mut self.untold) {
+            let keyword = self
+                .raffles
+                .get(&channel)
+                .map(|raffle| raffle.keyword.clone())
+                .unwrap_or_default();
+            let text = format!("You won the raffle for {} in #{}!", keyword, channel);
+            commands.push(whisper(&channel, &login, text, None));
+        }
+        Some(ChatBotCommand::MultipleCommands(commands))
+    }
+
+    /// Draws another winner among those not drawn before, once the raffle ended.
+    pub fn reroll(&mut self, channel: &str) -> String {
+        match self.raffles.get(channel) {
+            None => return "There was no raffle yet.".to_owned(),
+            Some(raffle) if raffle.open => {
+                return "The raffle is still running, end it first.".to_owned()
+            }
+            Some(_) => {}
+        }
+        match self.draw(channel, 1, "reroll") {
+            Some((winner, 1)) => format!("The new winner is {}, congratulations!", winner),
+            _ => "Everyone who entered was drawn already.".to_owned(),
+        }
+    }
+}
+
+/// `!raffle start <keyword> [seconds] [winners]`, `!raffle end` and `!raffle reroll`.
+pub struct RaffleCommand(pub SharedRaffles);
+
+impl Command for RaffleCommand {
+    fn name(&self) -> &'static str {
+        "raffle"
+    }
+
+    fn usage(&self) -> &'static str {
+        "start <keyword> [seconds] [winners], end, reroll"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let usage = format!(
+            "Usage: {0}raffle start <keyword> [seconds] [winners], {0}raffle end or {0}raffle reroll",
+            ctx.prefix
+        );
+        match args.next().map(str::to_lowercase).as_deref() {
+            Some("start") => {
+                let Some(keyword) = args.next() else {
+                    return ctx.send(usage);
+                };
+                let numbers: Option<Vec<u64>> = args.map(|arg| arg.parse().ok()).collect();
+                let (seconds, winners) = match numbers.as_deref() {
+                    Some([]) => (0, 1),
+                    Some([seconds]) => (*seconds, 1),
+                    Some([seconds, winners]) if (1..=MAX_WINNERS as u64).contains(winners) => {
+                        (*seconds, *winners as usize)
+                    }
+                    _ => return ctx.send(usage),
+                };
+                let Some(id) = self.0.borrow_mut().start(channel, keyword, winners) else {
+                    return ctx.send(format!(
+                        "A raffle is running already, end it with {}raffle end.",
+                        ctx.prefix
+                    ));
+                };
+                let text = format!("The raffle is on! Write {} to enter", keyword);
+                if seconds == 0 {
+                    return ctx.send(format!("{}.", text));
+                }
+                Some(ChatBotCommand::MultipleCommands(vec![
+                    ctx.send(format!("{}, it ends in {} seconds.", text, seconds))?,
+                    ChatBotCommand::TimedCallback {
+                        duration: Duration::from_secs(seconds),
+                        event: ChatBotEvent::Internal(InternalEvent::RaffleEnd {
+                            channel: channel.clone(),
+                            id,
+                        }),
+                    },
+                ]))
+            }
+            Some("end") => {
+                let mut raffles = self.0.borrow_mut();
+                let text = raffles.end(channel, None);
+                raffles.with_whispers(
+                    ctx.send(text.unwrap_or_else(|| "No raffle is running.".to_owned())),
+                )
+            }
+            Some("reroll") => {
+                let mut raffles = self.0.borrow_mut();
+                let text = raffles.reroll(channel);
+                raffles.with_whispers(ctx.send(text))
+            }
+            _ => ctx.send(usage),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{testing::message, SubTier};
+
+    fn raffles(config: &RaffleConfig) -> Raffles {
+        let (watch_time, points) = (Rc::default(), Rc::default());
+        Raffles::load(
+            config,
+            Storage::default(),
+            Rng::with_seed(3),
+            watch_time,
+            points,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rerolls_skip_earlier_winners() {
+        let config = RaffleConfig {
+            sub_tickets: 2,
+            ..Default::default()
+        };
+        let mut raffles = raffles(&config);
+        let now = Instant::now();
+        let id = raffles.start("carkhy", "!enter", 2).unwrap();
+        assert!(raffles.start("carkhy", "other", 1).is_none());
+        for (login, text) in [
+            ("a", "!enter"),
+            ("a", "!enter please"),
+            ("b", "I want to !ENTER"),
+            ("c", "enter"),
+            ("d", "!enter"),
+        ] {
+            assert!(raffles
+                .enter(&message(login, text, login == "d"), now)
+                .is_none());
+        }
+        let raffle = &raffles.raffles["carkhy"];
+        let tickets: Vec<_> = raffle
+            .entrants
+            .iter()
+            .map(|entrant| (entrant.login.as_str(), entrant.tickets))
+            .collect();
+        assert_eq!(tickets, [("a", 1), ("b", 1), ("d", 2)]);
+        assert_eq!(
+            raffles.reroll("carkhy"),
+            "The raffle is still running, end it first."
+        );
+        // the expiry of an earlier raffle ends nothing
+        assert!(raffles.end("carkhy", Some(Uuid::new_v4())).is_none());
+        let end = raffles.end("carkhy", Some(id)).unwrap();
+        assert!(end.starts_with("The raffle is over, 3 entered."), "{}", end);
+        assert!(raffles.enter(&message("e", "!enter", false), now).is_none());
+        assert!(raffles.raffles["carkhy"].entrants.len() == 3);
+        assert!(raffles.reroll("carkhy").starts_with("The new winner is"));
+        assert_eq!(
+            raffles.reroll("carkhy"),
+            "Everyone who entered was drawn already."
+        );
+        let mut drawn = raffles.raffles["carkhy"].drawn.clone();
+        drawn.sort();
+        assert_eq!(drawn, ["a", "b", "d"]);
+    }
+
+    #[test]
+    fn winners_can_be_whispered() {
+        let config = RaffleConfig {
+            whisper_winners: true,
+            ..Default::default()
+        };
+        let mut raffles = raffles(&config);
+        raffles.start("carkhy", "!enter", 1).unwrap();
+        raffles.enter(&message("a", "!enter", false), Instant::now());
+        raffles.end("carkhy", None).unwrap();
+        match raffles.with_whispers(None) {
+            Some(ChatBotCommand::MultipleCommands(commands)) => match &commands[..] {
+                [ChatBotCommand::Helix(HelixTask::Whisper { login, text, .. })] => {
+                    assert_eq!(login, "a");
+                    assert_eq!(text, "You won the raffle for !enter in #carkhy!");
+                }
+                commands => panic!("{:?}", commands),
+            },
+            command => panic!("{:?}", command),
+        }
+        assert!(raffles.with_whispers(None).is_none());
+    }
+
+    #[test]
+    fn recent_winners_sit_out() {
+        let config = RaffleConfig {
+            sub_tickets: 2,
+            tier_scaled: true,
+            exclude_winners_days: 7,
+            ..Default::default()
+        };
+        let mut raffles = raffles(&config);
+        let now = Instant::now();
+        let mut tier3 = message("c", "!enter", true);
+        tier3.user.badges = vec![Badge::Subscriber {
+            months: 1,
+            tier: SubTier::Tier3,
+        }];
+        raffles.start("carkhy", "!enter", 1).unwrap();
+        raffles.enter(&message("a", "!enter", false), now);
+        raffles.enter(&message("b", "!enter", true), now);
+        raffles.enter(&tier3, now);
+        let tickets: Vec<_> = raffles.raffles["carkhy"]
+            .entrants
+            .iter()
+            .map(|entrant| entrant.tickets)
+            .collect();
+        assert_eq!(tickets, [1, 2, 6]);
+        raffles.end("carkhy", None).unwrap();
+        let winner = raffles.raffles["carkhy"].drawn[0].clone();
+        assert_eq!(raffles.wins["carkhy"][&winner].len(), 1);
+        // the winner enters the next raffle but can't win it
+        raffles.start("carkhy", "!enter", 1).unwrap();
+        raffles.enter(&message(&winner, "!enter", false), now);
+        assert_eq!(
+            raffles.end("carkhy", None).as_deref(),
+            Some("The raffle is over, none of the 1 entrants can win this time.")
+        );
+    }
+
+    #[test]
+    fn entrants_need_the_points() {
+        let config = RaffleConfig {
+            min_points: 100,
+            ..Default::default()
+        };
+        let mut raffles = raffles(&config);
+        raffles.points.borrow_mut().credit("carkhy", "rich", 100);
+        raffles.start("carkhy", "!enter", 2).unwrap();
+        raffles.enter(&message("poor", "!enter", false), Instant::now());
+        raffles.enter(&message("Rich", "!enter", false), Instant::now());
+        assert_eq!(
+            raffles.end("carkhy", None).as_deref(),
+            Some("The raffle is over, 2 entered. Congratulations to Rich!")
+        );
+        // nothing to remember without rules about winners
+        assert!(raffles.wins.is_empty());
+    }
+
+    #[test]
+    fn only_eligible_users_enter() {
+        let config = RaffleConfig {
+            keyword_match: KeywordMatch::Message,
+            min_watch_minutes: 10,
+            followers_only: true,
+            ..Default::default()
+        };
+        let mut raffles = raffles(&config);
+        let start = Instant::now();
+        raffles.join("carkhy", "Lurker", start);
+        let id = raffles.start("carkhy", "giveaway", 1).unwrap();
+        let later = start + Duration::from_secs(10 * 60);
+        assert!(raffles
+            .enter(&message("new", "giveaway", false), later)
+            .is_none());
+        assert!(raffles
+            .enter(&message("lurker", "giveaway now", false), later)
+            .is_none());
+        match raffles.enter(&message("lurker", "Giveaway", false), later) {
+            Some(ChatBotCommand::Helix(HelixTask::RaffleFollower { login, raffle, .. })) => {
+                assert_eq!((login.as_str(), raffle), ("lurker", id))
+            }
+            other => panic!("{:?}", other),
+        }
+        // twitch is asked once
+        assert!(raffles
+            .enter(&message("lurker", "giveaway", false), later)
+            .is_none());
+        raffles.follower("carkhy", id, "lurker", "Lurker", 1);
+        assert_eq!(
+            raffles.end("carkhy", None).as_deref(),
+            Some("The raffle is over, the only entrant Lurker wins!")
+        );
+    }
+}
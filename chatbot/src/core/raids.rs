@@ -0,0 +1,186 @@
+use super::{commands::render_event, ChatBotCommand, HelixTask};
+use crate::{
+    config::EventsConfig,
+    connect::{ChatBotEvent, InternalEvent, Overflow, UserNotice},
+};
+use std::{collections::HashMap, time::Duration};
+use uuid::Uuid;
+
+/// Welcomes raids, shouts out the raiders and pauses slow mode while they arrive.
+#[derive(Debug)]
+pub struct Raids {
+    message: String,
+    shoutout: bool,
+    // zero leaves slow mode alone
+    slow_pause: Duration,
+    min_viewers: u32,
+    // by channel, the slow mode to restore and the id of the latest raid's restore,
+    // so an earlier raid doesn't restore it while the later one arrives
+    paused: HashMap<String, (u32, Uuid)>,
+}
+
+impl Default for Raids {
+    fn default() -> Self {
+        Self::new(&EventsConfig::default())
+    }
+}
+
+impl Raids {
+    pub fn new(config: &EventsConfig) -> Self {
+        Self {
+            message: config.raid_message.clone(),
+            shoutout: config.raid_shoutout,
+            slow_pause: Duration::from_secs(config.raid_slow_pause),
+            min_viewers: config.raid_min_viewers,
+            paused: HashMap::new(),
+        }
+    }
+
+    /// What the bot does about the raid, slow is the channel's slow mode in seconds right now.
+    pub fn raid(
+        &mut self,
+        notice: &UserNotice,
+        from: &str,
+        viewers: u32,
+        slow: Option<u32>,
+    ) -> Vec<ChatBotCommand> {
+        let channel = &notice.channel;
+        if viewers < self.min_viewers {
+            return Vec::new();
+        }
+        let mut commands = Vec::new();
+        if !self.message.is_empty() {
+            let event = [("from", from.to_owned()), ("viewers", viewers.to_string())];
+            commands.push(ChatBotCommand::SendMessage {
+                channel: channel.clone(),
+                text: render_event(&self.message, "raid message", from, channel, &event),
+                overflow: Overflow::Truncate,
+            });
+        }
+        if self.shoutout {
+            commands.push(ChatBotCommand::Helix(HelixTask::Shoutout {
+                channel: channel.clone(),
+                login: notice.user.name.clone(),
+            }));
+        }
+        // a raid during the pause of another one keeps its slow mode to restore
+        let previous = match self.paused.get(channel) {
+            Some(&(previous, _)) => previous,
+            None => match slow {
+                Some(seconds) if seconds > 0 => seconds,
+                _ => return commands,
+            },
+        };
+        if self.slow_pause.is_zero() {
+            return commands;
+        }
+        let id = Uuid::new_v4();
+        self.paused.insert(channel.clone(), (previous, id));
+        commands.push(ChatBotCommand::Helix(HelixTask::SlowMode {
+            channel: channel.clone(),
+            wait: None,
+        }));
+        commands.push(ChatBotCommand::TimedCallback {
+            duration: self.slow_pause,
+            event: ChatBotEvent::Internal(InternalEvent::RestoreSlowMode {
+                channel: channel.clone(),
+                id,
+            }),
+        });
+        commands
+    }
+
+    /// Sets slow mode back once the latest raid's pause is over.
+    pub fn restore(&mut self, channel: &str, id: Uuid) -> Option<ChatBotCommand> {
+        match self.paused.get(channel) {
+            Some(&(previous, latest)) if latest == id => {
+                self.paused.remove(channel);
+                Some(ChatBotCommand::Helix(HelixTask::SlowMode {
+                    channel: channel.to_owned(),
+                    wait: Some(previous),
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{UserInfo, UserNoticeKind};
+
+    fn raid(from: &str, viewers: u32) -> UserNotice {
+        UserNotice {
+            kind: UserNoticeKind::Raid {
+                from: from.to_owned(),
+                viewers,
+            },
+            user: UserInfo {
+                name: from.to_lowercase(),
+                ..Default::default()
+            },
+            channel: "captaincallback".to_owned(),
+            system_message: format!("{} viewers from {} have joined!", viewers, from),
+            text: None,
+        }
+    }
+
+    fn config() -> EventsConfig {
+        EventsConfig {
+            raid_shoutout: true,
+            raid_slow_pause: 120,
+            ..Default::default()
+        }
+    }
+
+    // the restore's id, after the message, the shoutout and slow mode turned off
+    fn paused(commands: &[ChatBotCommand]) -> Uuid {
+        match commands {
+            [ChatBotCommand::SendMessage { .. }, ChatBotCommand::Helix(HelixTask::Shoutout { .. }), ChatBotCommand::Helix(HelixTask::SlowMode { wait: None, .. }), ChatBotCommand::TimedCallback {
+                duration,
+                event: ChatBotEvent::Internal(InternalEvent::RestoreSlowMode { id, .. }),
+            }] if *duration == Duration::from_secs(120) => *id,
+            _ => panic!("{:?}", commands),
+        }
+    }
+
+    #[test]
+    fn raids_are_welcomed_unless_small() {
+        let mut raids = Raids::new(&config());
+        let commands = raids.raid(&raid("Carkhy", 42), "Carkhy", 42, None);
+        assert!(
+            matches!(&commands[..], [
+                ChatBotCommand::SendMessage { text, .. },
+                ChatBotCommand::Helix(HelixTask::Shoutout { login, .. }),
+            ] if text == "Carkhy is raiding with 42 viewers!" && login == "carkhy"),
+            "{:?}",
+            commands
+        );
+        assert!(raids
+            .raid(&raid("ViewBot", 1), "ViewBot", 1, Some(30))
+            .is_empty());
+    }
+
+    #[test]
+    fn raids_in_a_row_restore_slow_mode_once() {
+        let mut raids = Raids::new(&config());
+        let first = paused(&raids.raid(&raid("Carkhy", 42), "Carkhy", 42, Some(30)));
+        // 30 seconds later, slow mode is already off
+        let second = paused(&raids.raid(&raid("Viewer", 10), "Viewer", 10, Some(0)));
+        // the first pause is over, but the second raid still arrives
+        assert!(raids.restore("captaincallback", first).is_none());
+        assert!(matches!(
+            raids.restore("captaincallback", second),
+            Some(ChatBotCommand::Helix(HelixTask::SlowMode {
+                wait: Some(30),
+                ..
+            }))
+        ));
+        assert!(raids.restore("captaincallback", second).is_none());
+
+        // without slow mode there is nothing to pause
+        let commands = raids.raid(&raid("Carkhy", 42), "Carkhy", 42, Some(0));
+        assert_eq!(commands.len(), 2);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::connect::{TextMessage, UserInfo};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+pub const RECENT_MESSAGES: usize = 100;
+
+/// Where a replied message came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplySource {
+    // the bot saw it, with its author, emotes and time
+    Buffer,
+    // it scrolled out, only the body and author of the reply's tags are known
+    Tags,
+}
+
+/// A message a reply answers.
+#[derive(Debug, Clone)]
+pub struct StoredMessage {
+    pub message: TextMessage,
+    pub source: ReplySource,
+}
+
+impl StoredMessage {
+    /// Whether it is the message as the bot saw it, not only what the reply tells.
+    pub fn is_complete(&self) -> bool {
+        self.source == ReplySource::Buffer
+    }
+}
+
+/// The latest messages with an id, so deletions and replies can be matched to them.
+#[derive(Debug, Default)]
+pub struct RecentMessages {
+    // the ids, oldest first
+    order: VecDeque<String>,
+    messages: HashMap<String, TextMessage>,
+}
+
+pub type SharedRecentMessages = Rc<RefCell<RecentMessages>>;
+
+impl RecentMessages {
+    /// Keeps the message if it has an id, forgetting the oldest when full.
+    pub fn remember(&mut self, message: &TextMessage) {
+        let Some(id) = &message.message_id else {
+            return;
+        };
+        if self.messages.insert(id.clone(), message.clone()).is_some() {
+            return;
+        }
+        self.order.push_back(id.clone());
+        if self.order.len() > RECENT_MESSAGES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.messages.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, message_id: &str) -> Option<TextMessage> {
+        let removed = self.messages.remove(message_id)?;
+        self.order.retain(|id| id != message_id);
+        Some(removed)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&TextMessage) -> bool) {
+        self.messages.retain(|_, message| keep(message));
+        let messages = &self.messages;
+        self.order.retain(|id| messages.contains_key(id));
+    }
+
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.messages.clear();
+    }
+
+    pub fn get(&self, message_id: &str) -> Option<&TextMessage> {
+        self.messages.get(message_id)
+    }
+
+    /// The message the reply answers, as the bot saw it if it is still kept, otherwise as the
+    /// reply's tags tell. None if the message is no reply.
+    pub fn replied(&self, reply: &TextMessage) -> Option<StoredMessage> {
+        let parent = reply.reply_to.as_ref()?;
+        if let Some(message) = self.get(&parent.message_id) {
+            return Some(StoredMessage {
+                message: message.clone(),
+                source: ReplySource::Buffer,
+            });
+        }
+        let display_name = Some(parent.display_name.clone()).filter(|name| !name.is_empty());
+        Some(StoredMessage {
+            message: TextMessage {
+                channel: reply.channel.clone(),
+                text: parent.body.clone(),
+                user: UserInfo {
+                    name: parent.user_login.clone(),
+                    display_name,
+                    ..Default::default()
+                },
+                message_id: Some(parent.message_id.clone()),
+                ..Default::default()
+            },
+            source: ReplySource::Tags,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::{testing, ReplyParent};
+
+    fn message(id: &str, login: &str, text: &str) -> TextMessage {
+        TextMessage {
+            message_id: Some(id.to_owned()),
+            ..testing::message(login, text, false)
+        }
+    }
+
+    fn reply_to(id: &str) -> TextMessage {
+        TextMessage {
+            reply_to: Some(ReplyParent {
+                message_id: id.to_owned(),
+                user_login: "carkhy".to_owned(),
+                display_name: "Carkhy".to_owned(),
+                body: "so it begins".to_owned(),
+            }),
+            ..message("reply", "viewer", "@Carkhy !quote")
+        }
+    }
+
+    #[test]
+    fn the_oldest_messages_are_forgotten() {
+        let mut recent = RecentMessages::default();
+        for id in 0..RECENT_MESSAGES + 5 {
+            recent.remember(&message(&id.to_string(), "viewer", "hi"));
+        }
+        recent.remember(&message("104", "viewer", "hi again"));
+        recent.remember(&TextMessage::default());
+        assert_eq!(recent.order.len(), RECENT_MESSAGES);
+        assert_eq!(recent.messages.len(), RECENT_MESSAGES);
+        assert_eq!(recent.get("104").unwrap().text, "hi again");
+        assert!(recent.get("4").is_none());
+        assert!(recent.get("5").is_some());
+        recent.remember(&message("other", "carkhy", "hello"));
+        recent.retain(|message| message.user.name != "viewer");
+        assert_eq!(recent.order, ["other"]);
+        assert_eq!(recent.remove("other").unwrap().text, "hello");
+        assert!(recent.order.is_empty());
+    }
+
+    #[test]
+    fn replies_find_the_kept_message() {
+        let mut recent = RecentMessages::default();
+        let mut parent = message("abc", "carkhy", "so it begins, for real");
+        parent.user.display_name = Some("Carkhy".to_owned());
+        recent.remember(&parent);
+        let replied = recent.replied(&reply_to("abc")).unwrap();
+        assert_eq!(replied.source, ReplySource::Buffer);
+        assert!(replied.is_complete());
+        assert_eq!(replied.message.text, "so it begins, for real");
+        assert!(recent
+            .replied(&message("xyz", "viewer", "no reply"))
+            .is_none());
+    }
+
+    #[test]
+    fn replies_fall_back_on_their_tags() {
+        let recent = RecentMessages::default();
+        let replied = recent.replied(&reply_to("abc")).unwrap();
+        assert_eq!(replied.source, ReplySource::Tags);
+        assert!(!replied.is_complete());
+        assert_eq!(replied.message.text, "so it begins");
+        assert_eq!(replied.message.user.display_name(), "Carkhy");
+        assert_eq!(replied.message.message_id.as_deref(), Some("abc"));
+        assert_eq!(replied.message.channel, "carkhy");
+    }
+}
@@ -0,0 +1,188 @@
+use super::{commands::render_event, tasks::HelixTask, ChatBotCommand};
+use crate::{
+    config::{RedemptionsConfig, RewardConfig},
+    connect::{Badge, Overflow, Redemption, TextMessage, UserInfo, UserLevel},
+};
+use serde_json::json;
+
+/// What a reward does when it is redeemed, each None unless configured.
+#[derive(Debug)]
+pub struct Actions {
+    pub message: Option<ChatBotCommand>,
+    // as if the broadcaster had sent it
+    pub command: Option<TextMessage>,
+    // the url and the JSON body
+    pub webhook: Option<(String, String)>,
+    pub fulfill: Option<ChatBotCommand>,
+}
+
+/// The configured rewards of channel points, EventSub tells the bot about their redemptions.
+#[derive(Debug, Default)]
+pub struct Redemptions {
+    config: RedemptionsConfig,
+}
+
+impl Redemptions {
+    pub fn new(config: &RedemptionsConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    // by the id, or the title ignoring case
+    fn reward(&self, redemption: &Redemption) -> Option<&RewardConfig> {
+        self.config.rewards.iter().find(|reward| {
+            reward.reward == redemption.reward_id
+                || reward.reward.eq_ignore_ascii_case(&redemption.reward)
+        })
+    }
+
+    /// None for the rewards without actions, they are left to the broadcaster.
+    pub fn actions(&self, redemption: &Redemption) -> Option<Actions> {
+        let reward = self.reward(redemption)?;
+        let channel = &redemption.channel;
+        let event = [
+            ("input", redemption.input.clone()),
+            ("reward", redemption.reward.clone()),
+            ("cost", redemption.cost.to_string()),
+        ];
+        let render = |text: &str| {
+            render_event(
+                text,
+                "reward action",
+                &redemption.display_name,
+                channel,
+                &event,
+            )
+        };
+        let message = (!reward.message.is_empty()).then(|| ChatBotCommand::SendMessage {
+            channel: channel.clone(),
+            text: render(&reward.message),
+            overflow: Overflow::Truncate,
+        });
+        let command =
+            (!reward.command.is_empty()).then(|| as_broadcaster(channel, render(&reward.command)));
+        let webhook = (!reward.webhook.is_empty()).then(|| {
+            let body = json!({
+                "channel": channel,
+                "id": redemption.id,
+                "login": redemption.login,
+                "display_name": redemption.display_name,
+                "input": redemption.input,
+                "reward_id": redemption.reward_id,
+                "reward": redemption.reward,
+                "cost": redemption.cost,
+            });
+            (reward.webhook.clone(), body.to_string())
+        });
+        let fulfill = self.config.fulfill.then(|| {
+            ChatBotCommand::Helix(HelixTask::FulfillRedemption {
+                channel: channel.clone(),
+                reward_id: redemption.reward_id.clone(),
+                id: redemption.id.clone(),
+            })
+        });
+        Some(Actions {
+            message,
+            command,
+            webhook,
+            fulfill,
+        })
+    }
+}
+
+// a chat message of the broadcaster, commands check the level before they run
+fn as_broadcaster(channel: &str, text: String) -> TextMessage {
+    TextMessage {
+        channel: channel.to_owned(),
+        text,
+        user: UserInfo {
+            name: channel.to_owned(),
+            badges: vec![Badge::Broadcaster],
+            ..Default::default()
+        },
+        level: UserLevel::Broadcaster,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redemption(reward: &str, input: &str) -> Redemption {
+        Redemption {
+            id: "17fa2df1".to_owned(),
+            channel: "captaincallback".to_owned(),
+            login: "carkhy".to_owned(),
+            display_name: "Carkhy".to_owned(),
+            input: input.to_owned(),
+            reward_id: "92af127c".to_owned(),
+            reward: reward.to_owned(),
+            cost: 100,
+        }
+    }
+
+    fn redemptions(fulfill: bool) -> Redemptions {
+        let config = RedemptionsConfig {
+            fulfill,
+            rewards: vec![
+                RewardConfig {
+                    reward: "hydrate".to_owned(),
+                    message: "$(user) spent $(cost) on $(reward): $(input)".to_owned(),
+                    command: "!so $(input)".to_owned(),
+                    webhook: String::new(),
+                },
+                RewardConfig {
+                    reward: "92af127c".to_owned(),
+                    message: String::new(),
+                    command: String::new(),
+                    webhook: "http://localhost:8080/song".to_owned(),
+                },
+            ],
+        };
+        Redemptions::new(&config)
+    }
+
+    #[test]
+    fn rewards_are_found_by_title_or_id() {
+        let redemptions = redemptions(false);
+        let actions = redemptions
+            .actions(&Redemption {
+                reward_id: "1".to_owned(),
+                ..redemption("Hydrate", "tenaciousbyte")
+            })
+            .unwrap();
+        match actions.message {
+            Some(ChatBotCommand::SendMessage { text, .. }) => {
+                assert_eq!(text, "Carkhy spent 100 on Hydrate: tenaciousbyte")
+            }
+            message => panic!("{:?}", message),
+        }
+        let command = actions.command.unwrap();
+        assert_eq!(command.text, "!so tenaciousbyte");
+        assert!(command.has_level(UserLevel::Broadcaster));
+        assert!(actions.webhook.is_none() && actions.fulfill.is_none());
+        // the title doesn't matter when the id is configured
+        let actions = redemptions.actions(&redemption("Song", "")).unwrap();
+        assert!(actions.message.is_none() && actions.command.is_none());
+        let (url, body) = actions.webhook.unwrap();
+        assert_eq!(url, "http://localhost:8080/song");
+        assert!(body.contains(r#""login":"carkhy""#), "{}", body);
+        let other = Redemption {
+            reward_id: "1".to_owned(),
+            ..redemption("Song", "")
+        };
+        assert!(redemptions.actions(&other).is_none());
+    }
+
+    #[test]
+    fn fulfilling_is_configured() {
+        let fulfilled = |fulfill| {
+            let actions = redemptions(fulfill).actions(&redemption("Hydrate", ""));
+            actions.unwrap().fulfill.is_some()
+        };
+        assert!(fulfilled(true));
+        assert!(!fulfilled(false));
+    }
+}
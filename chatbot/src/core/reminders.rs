@@ -0,0 +1,433 @@
+use super::{
+    commands::{Args, Command, Context},
+    tasks::whisper,
+    ChatBotCommand,
+};
+use crate::{
+    config::RemindersConfig,
+    connect::{ChatBotEvent, InternalEvent, Overflow, UserLevel},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const STORAGE_NAME: &str = "reminders";
+/// The longest a reminder can wait.
+pub const MAX_REMINDER: Duration = Duration::from_secs(24 * 60 * 60);
+// a reminder delivered later than this was due while the bot was offline
+const OVERDUE_AFTER: Duration = Duration::from_secs(60);
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// "90s", "20m", "1h30m" and the like, the units in this order and each once.
+/// None for nothing, zero or more than a day.
+fn duration(text: &str) -> Option<Duration> {
+    let text = text.to_lowercase();
+    let mut total = 0u64;
+    let mut units = ['h', 'm', 's'].into_iter();
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let amount: u64 = rest[..digits].parse().ok()?;
+        let unit = rest[digits..].chars().next()?;
+        let factor = match units.find(|&next| next == unit)? {
+            'h' => 3600,
+            'm' => 60,
+            _ => 1,
+        };
+        total = total.checked_add(amount.checked_mul(factor)?)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+    let duration = Duration::from_secs(total);
+    (!duration.is_zero() && duration <= MAX_REMINDER).then_some(duration)
+}
+
+// "1 hour 30 minutes", parts that are zero are left out
+fn describe(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let parts: Vec<_> = [
+        (seconds / 3600, "hour"),
+        (seconds / 60 % 60, "minute"),
+        (seconds % 60, "second"),
+    ]
+    .into_iter()
+    .filter(|&(amount, _)| amount > 0)
+    .map(|(amount, unit)| match amount {
+        1 => format!("1 {}", unit),
+        _ => format!("{} {}s", amount, unit),
+    })
+    .collect();
+    parts.join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Reminder {
+    id: u64,
+    channel: String,
+    // lowercase, and the name to mention
+    login: String,
+    name: String,
+    note: String,
+    // seconds since 1970
+    due: u64,
+    // the moderator who set it for the user
+    from: Option<String>,
+}
+
+/// The reminders waiting to be delivered, kept in the storage so a restart loses none.
+#[derive(Debug, Default)]
+pub struct Reminders {
+    storage: Storage,
+    per_user: usize,
+    whisper: bool,
+    pending: Vec<Reminder>,
+}
+
+pub type SharedReminders = Rc<RefCell<Reminders>>;
+
+fn due(reminder: &Reminder, now: SystemTime) -> ChatBotCommand {
+    let due = UNIX_EPOCH + Duration::from_secs(reminder.due);
+    ChatBotCommand::TimedCallback {
+        duration: due.duration_since(now).unwrap_or_default(),
+        event: ChatBotEvent::Internal(InternalEvent::ReminderDue { id: reminder.id }),
+    }
+}
+
+impl Reminders {
+    pub fn load(config: &RemindersConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            pending: storage.load(STORAGE_NAME)?,
+            storage,
+            per_user: config.per_user,
+            whisper: config.whisper,
+        })
+    }
+
+    // nothing is kept once every reminder was delivered
+    fn save(&self) {
+        let saved = match self.pending.is_empty() {
+            true => self.storage.delete(STORAGE_NAME),
+            false => self.storage.save(STORAGE_NAME, &self.pending),
+        };
+        if let Err(error) = saved {
+            tracing::warn!(%error, "could not save the reminders");
+        }
+    }
+
+    /// A timer for each reminder saved before, the overdue ones are delivered right away.
+    pub fn start(&self, now: SystemTime) -> Vec<ChatBotCommand> {
+        self.pending
+            .iter()
+            .map(|reminder| due(reminder, now))
+            .collect()
+    }
+
+    /// Reminds the user in the channel after the duration, refused with the user's
+    /// pending reminders once they have as many as allowed.
+    pub fn add(
+        &mut self,
+        channel: &str,
+        name: &str,
+        note: &str,
+        from: Option<&str>,
+        after: Duration,
+        now: SystemTime,
+    ) -> Result<ChatBotCommand, usize> {
+        let login = name.to_lowercase();
+        let pending = self
+            .pending
+            .iter()
+            .filter(|reminder| reminder.login == login)
+            .count();
+        if pending >= self.per_user {
+            return Err(pending);
+        }
+        let id = self
+            .pending
+            .iter()
+            .map(|reminder| reminder.id + 1)
+            .max()
+            .unwrap_or_default();
+        let reminder = Reminder {
+            id,
+            channel: channel.to_owned(),
+            login,
+            name: name.to_owned(),
+            note: note.to_owned(),
+            due: seconds(now + after),
+            from: from.map(str::to_owned),
+        };
+        let timer = due(&reminder, now);
+        self.pending.push(reminder);
+        self.save();
+        Ok(timer)
+    }
+
+    /// Delivers the reminder, saying so if it is late.
+    pub fn deliver(&mut self, id: u64, now: SystemTime) -> Option<ChatBotCommand> {
+        let index = self.pending.iter().position(|reminder| reminder.id == id)?;
+        let reminder = self.pending.remove(index);
+        self.save();
+        let mut text = match &reminder.from {
+            Some(from) => format!("reminder from {}: {}", from, reminder.note),
+            None => format!("reminder: {}", reminder.note),
+        };
+        if seconds(now) > reminder.due + OVERDUE_AFTER.as_secs() {
+            text.push_str(" (overdue, the bot was offline when it was due)");
+        }
+        let public = format!("@{}, {}", reminder.name, text);
+        if self.whisper {
+            let text = format!("{} in #{}", text, reminder.channel);
+            let whispered = whisper(&reminder.channel, &reminder.login, text, Some(public));
+            return Some(whispered);
+        }
+        Some(ChatBotCommand::SendMessage {
+            channel: reminder.channel,
+            text: public,
+            overflow: Overflow::Split,
+        })
+    }
+}
+
+// the reminder of the user, or none with the answer to a mistake
+fn remind(
+    reminders: &SharedReminders,
+    ctx: &Context,
+    user: &str,
+    from: Option<&str>,
+    mut args: Args,
+    usage: String,
+) -> Option<ChatBotCommand> {
+    let (Some(after), Some(note)) = (args.next().and_then(duration), args.rest()) else {
+        return ctx.send(usage);
+    };
+    let result = reminders.borrow_mut().add(
+        &ctx.message.channel,
+        user,
+        note,
+        from,
+        after,
+        SystemTime::now(),
+    );
+    match result {
+        Ok(timer) => {
+            let text = match from {
+                Some(_) => format!("I'll remind {} in {}.", user, describe(after)),
+                None => format!("{}, I'll remind you in {}.", user, describe(after)),
+            };
+            Some(ChatBotCommand::MultipleCommands(vec![
+                ctx.send(text)?,
+                timer,
+            ]))
+        }
+        Err(pending) => ctx.send(format!(
+            "{} has {} reminders waiting already, that's the limit.",
+            user, pending
+        )),
+    }
+}
+
+/// `!remindme <duration> <note>` mentions the user with the note after the duration.
+pub struct RemindMe(pub SharedReminders);
+
+impl Command for RemindMe {
+    fn name(&self) -> &'static str {
+        "remindme"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<duration> <note>"
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let usage = format!(
+            "Usage: {}remindme <duration like 90s, 20m or 1h30m> <note>, at most 24 hours",
+            ctx.prefix
+        );
+        let user = ctx.message.user.display_name();
+        remind(&self.0, ctx, user, None, args, usage)
+    }
+}
+
+/// `!remind @user <duration> <note>` reminds someone else.
+pub struct Remind(pub SharedReminders);
+
+impl Command for Remind {
+    fn name(&self) -> &'static str {
+        "remind"
+    }
+
+    fn usage(&self) -> &'static str {
+        "@<user> <duration> <note>"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let usage = format!(
+            "Usage: {}remind @user <duration like 90s, 20m or 1h30m> <note>, at most 24 hours",
+            ctx.prefix
+        );
+        let Some(user) = args.next().map(|user| user.trim_start_matches('@')) else {
+            return ctx.send(usage);
+        };
+        let from = ctx.message.user.display_name();
+        remind(&self.0, ctx, user, Some(from), args, usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::HelixTask;
+
+    #[test]
+    fn durations_are_read_like_people_write_them() {
+        let secs = |seconds| Some(Duration::from_secs(seconds));
+        assert_eq!(duration("90s"), secs(90));
+        assert_eq!(duration("20m"), secs(20 * 60));
+        assert_eq!(duration("1h30m"), secs(90 * 60));
+        assert_eq!(duration("1H5M10S"), secs(3910));
+        assert_eq!(duration("24h"), secs(24 * 3600));
+        for text in [
+            "",
+            "20",
+            "m",
+            "0m",
+            "24h1s",
+            "25h",
+            "30m1h",
+            "1h1h",
+            "1d",
+            "-5m",
+            "1.5h",
+            "20 m",
+            "99999999999999999999h",
+            "5é",
+        ] {
+            assert_eq!(duration(text), None, "{}", text);
+        }
+        assert_eq!(
+            describe(Duration::from_secs(3661)),
+            "1 hour 1 minute 1 second"
+        );
+        assert_eq!(describe(Duration::from_secs(1200)), "20 minutes");
+    }
+
+    #[test]
+    fn reminders_survive_a_restart() {
+        let storage = Storage::memory();
+        let config = RemindersConfig {
+            per_user: 2,
+            ..Default::default()
+        };
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut reminders = Reminders::load(&config, storage.clone()).unwrap();
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        reminders
+            .add(
+                "carkhy",
+                "Viewer",
+                "check the oven",
+                None,
+                minutes(20),
+                start,
+            )
+            .unwrap();
+        reminders
+            .add(
+                "carkhy",
+                "Other",
+                "raid",
+                Some("Carkhy"),
+                minutes(60),
+                start,
+            )
+            .unwrap();
+
+        // the bot was offline for half an hour
+        let now = start + minutes(30);
+        let mut reminders = Reminders::load(&config, storage.clone()).unwrap();
+        let timers: Vec<_> = reminders
+            .start(now)
+            .into_iter()
+            .map(|timer| match timer {
+                ChatBotCommand::TimedCallback {
+                    duration,
+                    event: ChatBotEvent::Internal(InternalEvent::ReminderDue { id }),
+                } => (id, duration),
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(timers, [(0, Duration::ZERO), (1, minutes(30))]);
+        let text = |command| match command {
+            Some(ChatBotCommand::SendMessage { text, .. }) => text,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            text(reminders.deliver(0, now)),
+            "@Viewer, reminder: check the oven (overdue, the bot was offline when it was due)"
+        );
+        assert!(reminders.deliver(0, now).is_none());
+        let mut reminders = Reminders::load(&config, storage.clone()).unwrap();
+        assert_eq!(
+            text(reminders.deliver(1, start + minutes(60))),
+            "@Other, reminder from Carkhy: raid"
+        );
+        assert!(reminders.start(now).is_empty());
+    }
+
+    #[test]
+    fn users_have_a_limit() {
+        let mut reminders = Reminders {
+            per_user: 2,
+            ..Default::default()
+        };
+        let now = SystemTime::now();
+        let mut add = |name| reminders.add("carkhy", name, "note", None, MAX_REMINDER, now);
+        assert!(add("Viewer").is_ok());
+        assert!(add("viewer").is_ok());
+        assert_eq!(add("VIEWER").err(), Some(2));
+        assert!(add("other").is_ok());
+        reminders.deliver(0, now);
+        assert!(reminders
+            .add("carkhy", "viewer", "note", None, MAX_REMINDER, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn reminders_can_be_whispered() {
+        let mut reminders = Reminders {
+            per_user: 1,
+            whisper: true,
+            ..Default::default()
+        };
+        let now = SystemTime::now();
+        reminders
+            .add("carkhy", "Viewer", "stretch", None, MAX_REMINDER, now)
+            .unwrap();
+        match reminders.deliver(0, now + MAX_REMINDER) {
+            Some(ChatBotCommand::Helix(HelixTask::Whisper {
+                login,
+                text,
+                fallback,
+                ..
+            })) => {
+                assert_eq!(login, "viewer");
+                assert_eq!(text, "reminder: stretch in #carkhy");
+                assert_eq!(fallback.as_deref(), Some("@Viewer, reminder: stretch"));
+            }
+            command => panic!("{:?}", command),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Cron expressions like "0 20 * * sat": minute, hour, day of the month, month and weekday,
+//! read like Vixie cron does. A field is `*`, a number or name, a range like `1-5`, any of
+//! these with a step like `*/15`, or a list of them like `mon,wed,fri`.
+use crate::core::Date;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CronError {
+    #[error("needs 5 fields, minute hour day month weekday, not {0}")]
+    Fields(usize),
+    #[error("{value:?} is no {field}, it goes from {min} to {max}")]
+    Value {
+        field: &'static str,
+        value: String,
+        min: u32,
+        max: u32,
+    },
+    #[error("{0:?} is no step, steps are 1 or more")]
+    Step(String),
+    #[error("{0:?} is no shortcut, there are @hourly, @daily, @weekly, @monthly and @yearly")]
+    Shortcut(String),
+}
+
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+}
+
+const MINUTE: Field = Field {
+    name: "minute",
+    min: 0,
+    max: 59,
+    names: &[],
+};
+const HOUR: Field = Field {
+    name: "hour",
+    min: 0,
+    max: 23,
+    names: &[],
+};
+const DAY: Field = Field {
+    name: "day of the month",
+    min: 1,
+    max: 31,
+    names: &[],
+};
+const MONTH: Field = Field {
+    name: "month",
+    min: 1,
+    max: 12,
+    names: &[
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ],
+};
+// 7 is Sunday as well
+const WEEKDAY: Field = Field {
+    name: "weekday",
+    min: 0,
+    max: 7,
+    names: &["sun", "mon", "tue", "wed", "thu", "fri", "sat"],
+};
+
+impl Field {
+    fn value(&self, text: &str) -> Result<u32, CronError> {
+        let lower = text.to_lowercase();
+        let named = self
+            .names
+            .iter()
+            .position(|name| *name == lower)
+            .map(|index| index as u32 + self.min);
+        match named.or_else(|| text.parse().ok()) {
+            Some(value) if (self.min..=self.max).contains(&value) => Ok(value),
+            _ => Err(CronError::Value {
+                field: self.name,
+                value: text.to_owned(),
+                min: self.min,
+                max: self.max,
+            }),
+        }
+    }
+
+    // the values as bits
+    fn parse(&self, text: &str) -> Result<u64, CronError> {
+        let mut bits = 0;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => return Err(CronError::Step(step.to_owned())),
+                },
+                None => (part, 1),
+            };
+            let (first, last) = match range.split_once('-') {
+                _ if range == "*" => (self.min, self.max),
+                Some((first, last)) => (self.value(first)?, self.value(last)?),
+                // "5/10" runs from 5 to the end
+                None if step > 1 => (self.value(range)?, self.max),
+                None => {
+                    let value = self.value(range)?;
+                    (value, value)
+                }
+            };
+            if first > last {
+                return Err(CronError::Value {
+                    field: self.name,
+                    value: range.to_owned(),
+                    min: self.min,
+                    max: self.max,
+                });
+            }
+            for value in (first..=last).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(bits)
+    }
+}
+
+/// A local time as cron reads it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Local {
+    pub date: Date,
+    pub hour: i64,
+    pub minute: i64,
+}
+
+impl Local {
+    /// Of seconds since 1970 in local time.
+    pub fn of(local: i64) -> Self {
+        let seconds = local.rem_euclid(86400);
+        Self {
+            date: Date::from_days(local.div_euclid(86400)),
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+        }
+    }
+}
+
+/// When something is due.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // a field starting with '*' doesn't restrict the day
+    any_day: bool,
+    any_weekday: bool,
+    // the minute or hour starts with '*', which runs in clock time when the clocks change
+    repeating: bool,
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Self, CronError> {
+        let text = match text.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            shortcut if shortcut.starts_with('@') => {
+                return Err(CronError::Shortcut(shortcut.to_owned()))
+            }
+            text => text,
+        };
+        let fields: Vec<_> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(CronError::Fields(fields.len()));
+        };
+        let mut weekdays = WEEKDAY.parse(weekday)?;
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: MINUTE.parse(minute)?,
+            hours: HOUR.parse(hour)?,
+            days: DAY.parse(day)?,
+            months: MONTH.parse(month)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+            repeating: minute.starts_with('*') || hour.starts_with('*'),
+        })
+    }
+
+    /// Whether it runs in the same clock time again when the clocks are turned back, and
+    /// misses the times the clocks skip. Other expressions run once, and right after the
+    /// clocks skipped their time.
+    pub fn is_repeating(&self) -> bool {
+        self.repeating
+    }
+
+    pub fn matches(&self, local: &Local) -> bool {
+        let bit = |bits: u64, value: i64| bits & 1 << value != 0;
+        let day = bit(self.days, local.date.day);
+        let weekday = bit(self.weekdays, local.date.weekday());
+        // restricting both runs on either, like cron always did
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day && bit(self.minutes, local.minute)
+            && bit(self.hours, local.hour)
+            && bit(self.months, local.date.month)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> Local {
+        Local {
+            date: Date { year, month, day },
+            hour,
+            minute,
+        }
+    }
+
+    #[test]
+    fn fields_match_like_cron() {
+        // 2026-10-17 is a saturday
+        let game_night = Cron::parse("0 20 * * sat").unwrap();
+        assert!(game_night.matches(&at(2026, 10, 17, 20, 0)));
+        assert!(!game_night.matches(&at(2026, 10, 17, 20, 1)));
+        assert!(!game_night.matches(&at(2026, 10, 18, 20, 0)));
+        assert!(!game_night.is_repeating());
+        let quarters = Cron::parse("*/15 9-17 * * mon-fri").unwrap();
+        assert!(quarters.matches(&at(2026, 10, 14, 9, 45)));
+        assert!(!quarters.matches(&at(2026, 10, 14, 18, 0)));
+        assert!(!quarters.matches(&at(2026, 10, 17, 9, 45)));
+        assert!(quarters.is_repeating());
+        // the 1st or a sunday
+        let both = Cron::parse("30 12 1 * 7").unwrap();
+        assert!(both.matches(&at(2026, 10, 1, 12, 30)));
+        assert!(both.matches(&at(2026, 10, 18, 12, 30)));
+        assert!(!both.matches(&at(2026, 10, 17, 12, 30)));
+        let list = Cron::parse("5,35/10 0 * JAN,jul *").unwrap();
+        assert!(list.matches(&at(2026, 7, 3, 0, 45)));
+        assert!(!list.matches(&at(2026, 7, 3, 0, 15)));
+        assert_eq!(Cron::parse("@daily"), Cron::parse("0 0 * * *"));
+    }
+
+    #[test]
+    fn broken_expressions_are_refused() {
+        assert_eq!(Cron::parse("0 20 * *"), Err(CronError::Fields(4)));
+        assert_eq!(
+            Cron::parse("60 * * * *").unwrap_err().to_string(),
+            "\"60\" is no minute, it goes from 0 to 59"
+        );
+        assert_eq!(
+            Cron::parse("*/0 * * * *"),
+            Err(CronError::Step("0".to_owned()))
+        );
+        assert!(Cron::parse("0 20 * * fri-mon").is_err());
+        assert!(Cron::parse("0 0 0 * *").is_err());
+        assert_eq!(
+            Cron::parse("@reboot"),
+            Err(CronError::Shortcut("@reboot".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! The entries of `schedule.entries`, sent at the times of their cron expressions in
+//! `schedule.timezone`. The schedule is checked at the start of every minute.
+mod cron;
+mod zone;
+
+pub use cron::{Cron, Local};
+pub use zone::TimeZone;
+
+use super::ChatBotCommand;
+use crate::{
+    config::Config,
+    connect::{ChatBotEvent, InternalEvent},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const STORAGE_NAME: &str = "schedule";
+// times missed longer ago aren't made up
+const MAX_CATCH_UP: i64 = 7 * 24 * 60 * 60;
+
+/// What an entry does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // a template with $(date) and $(time)
+    Message(String),
+    // a chat command, run as an admin
+    Command(String),
+}
+
+/// An entry at one of its times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Due {
+    pub name: String,
+    // without '#'
+    pub channel: String,
+    pub action: Action,
+    // when it was due, earlier than now if it is made up
+    pub local: Local,
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    cron: Cron,
+    channel: String,
+    action: Action,
+    only_live: bool,
+    catch_up: bool,
+}
+
+// the minute checked last, kept for making up missed times after a restart
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    checked: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Schedule {
+    zone: TimeZone,
+    entries: Vec<Entry>,
+    // the bot's own login, whose joins tell which channels the entries may go to
+    login: String,
+    joined: HashSet<String>,
+    storage: Storage,
+    // seconds since 1970 of the minute checked last
+    checked: i64,
+    // of the run before, until the first tick made up what it missed
+    missed_since: Option<i64>,
+}
+
+// seconds since 1970
+fn seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+impl Schedule {
+    /// Times before now don't count, the ones missed before the start are only made up for
+    /// entries with `catch_up`.
+    pub fn load(config: &Config, storage: Storage, now: SystemTime) -> Result<Self, StorageError> {
+        let saved: Saved = storage.load(STORAGE_NAME)?;
+        // the config was validated
+        let zone = TimeZone::load(&config.schedule.timezone).unwrap_or_else(|error| {
+            tracing::warn!(%error, "the schedule runs in UTC");
+            TimeZone::UTC
+        });
+        let entries = config
+            .schedule
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                Some(Entry {
+                    name: entry.name.clone(),
+                    cron: Cron::parse(&entry.cron).ok()?,
+                    channel: entry.channel.trim_start_matches('#').to_lowercase(),
+                    action: match (&entry.text, &entry.command) {
+                        (Some(text), _) => Action::Message(text.clone()),
+                        (None, Some(command)) => Action::Command(command.clone()),
+                        (None, None) => return None,
+                    },
+                    only_live: entry.only_live,
+                    catch_up: entry.catch_up,
+                })
+            })
+            .collect();
+        let now = seconds(now);
+        Ok(Self {
+            zone,
+            entries,
+            login: config.twitch.user.to_lowercase(),
+            joined: HashSet::new(),
+            storage,
+            checked: now - now.rem_euclid(60),
+            missed_since: saved.checked,
+        })
+    }
+
+    /// The first tick, None without entries.
+    pub fn start(&self, now: SystemTime) -> Option<ChatBotCommand> {
+        (!self.entries.is_empty()).then(|| Self::next_tick(now))
+    }
+
+    /// At the start of the next minute.
+    pub fn next_tick(now: SystemTime) -> ChatBotCommand {
+        let since = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let into_minute = Duration::from_millis((since.as_millis() % 60_000) as u64);
+        ChatBotCommand::TimedCallback {
+            duration: Duration::from_secs(60) - into_minute,
+            event: ChatBotEvent::Internal(InternalEvent::ScheduleTick),
+        }
+    }
+
+    /// Someone joined the channel, perhaps the bot.
+    pub fn join(&mut self, channel: &str, user: &str) {
+        if user.eq_ignore_ascii_case(&self.login) {
+            self.joined.insert(channel.to_owned());
+        }
+    }
+
+    pub fn part(&mut self, channel: &str, user: &str) {
+        if user.eq_ignore_ascii_case(&self.login) {
+            self.joined.remove(channel);
+        }
+    }
+
+    /// The connection is lost, the channels are joined again after it.
+    pub fn disconnected(&mut self) {
+        self.joined.clear();
+    }
+
+    // whether the entry is due at the minute
+    fn fires(&self, cron: &Cron, utc: i64) -> bool {
+        let local = self.zone.local(utc);
+        if cron.matches(&Local::of(local)) {
+            // turned back clocks show a time twice, most entries run the first time only
+            return cron.is_repeating() || self.zone.utc(local) == Some(utc);
+        }
+        if cron.is_repeating() {
+            return false;
+        }
+        // the times the clocks skipped run right after
+        let unchanged = self.zone.local(utc - 60) + 60;
+        (unchanged..local)
+            .step_by(60)
+            .any(|skipped| cron.matches(&Local::of(skipped)))
+    }
+
+    /// The entries due since the last tick, each once. An entry is skipped while the bot
+    /// hasn't joined its channel, or with `only_live` while the stream isn't known to be live.
+    pub fn tick(&mut self, now: SystemTime, is_live: impl Fn(&str) -> Option<bool>) -> Vec<Due> {
+        let now = seconds(now);
+        let now = now - now.rem_euclid(60);
+        let missed_since = self.missed_since.take();
+        let mut due = Vec::new();
+        for entry in &self.entries {
+            let since = match (entry.catch_up, missed_since) {
+                (true, Some(saved)) => saved.max(now - MAX_CATCH_UP).min(self.checked),
+                _ => self.checked,
+            };
+            let Some(minute) = (since / 60 + 1..=now / 60)
+                .rev()
+                .map(|minute| minute * 60)
+                .find(|&minute| self.fires(&entry.cron, minute))
+            else {
+                continue;
+            };
+            if !self.joined.contains(&entry.channel) {
+                tracing::info!(
+                    entry = %entry.name,
+                    channel = %entry.channel,
+                    "skipping an entry of the schedule, the bot hasn't joined the channel"
+                );
+                continue;
+            }
+            if entry.only_live && is_live(&entry.channel) != Some(true) {
+                tracing::info!(
+                    entry = %entry.name,
+                    channel = %entry.channel,
+                    "skipping an entry of the schedule, the channel isn't live"
+                );
+                continue;
+            }
+            tracing::info!(
+                entry = %entry.name,
+                channel = %entry.channel,
+                catch_up = minute <= self.checked,
+                "an entry of the schedule is due"
+            );
+            due.push(Due {
+                name: entry.name.clone(),
+                channel: entry.channel.clone(),
+                action: entry.action.clone(),
+                local: Local::of(self.zone.local(minute)),
+            });
+        }
+        self.checked = now;
+        let saved = Saved { checked: Some(now) };
+        if let Err(error) = self.storage.save(STORAGE_NAME, &saved) {
+            tracing::warn!(%error, "could not save the schedule");
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ScheduleConfig, ScheduledConfig},
+        core::Date,
+    };
+
+    fn time(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> SystemTime {
+        let days = Date { year, month, day }.days();
+        UNIX_EPOCH + Duration::from_secs((days * 86400 + hour * 3600 + minute * 60) as u64)
+    }
+
+    fn entry(name: &str, cron: &str) -> ScheduledConfig {
+        ScheduledConfig {
+            name: name.to_owned(),
+            cron: cron.to_owned(),
+            channel: "#captaincallback".to_owned(),
+            text: Some(format!("{} at $(time)", name)),
+            command: None,
+            only_live: false,
+            catch_up: false,
+        }
+    }
+
+    fn schedule(entries: Vec<ScheduledConfig>, storage: Storage, now: SystemTime) -> Schedule {
+        let mut config = Config::default();
+        config.twitch.user = "CarkhyBot".to_owned();
+        config.schedule = ScheduleConfig {
+            timezone: "CET-1CEST,M3.5.0,M10.5.0/3".to_owned(),
+            entries,
+        };
+        let mut schedule = Schedule::load(&config, storage, now).unwrap();
+        schedule.join("captaincallback", "carkhybot");
+        schedule
+    }
+
+    // the names and local times of the entries due, a tick every minute from start to end
+    fn run(schedule: &mut Schedule, start: SystemTime, end: SystemTime) -> Vec<String> {
+        let mut fired = Vec::new();
+        let mut now = start;
+        while now <= end {
+            for due in schedule.tick(now, |_| Some(true)) {
+                let local = due.local;
+                fired.push(format!(
+                    "{} {} {:02}:{:02}",
+                    due.name, local.date, local.hour, local.minute
+                ));
+            }
+            now += Duration::from_secs(60);
+        }
+        fired
+    }
+
+    #[test]
+    fn entries_keep_local_time_over_the_clock_changes() {
+        let start = time(2026, 3, 27, 23, 59);
+        let mut schedule = schedule(
+            vec![
+                entry("game night", "0 20 * * sat"),
+                entry("late", "30 2 * * *"),
+                entry("hourly", "0 * * * sun"),
+            ],
+            Storage::default(),
+            start,
+        );
+        // the clocks skip from 2:00 to 3:00 on March 29, 1:00 UTC
+        let fired = run(&mut schedule, start, time(2026, 3, 29, 3, 0));
+        assert_eq!(
+            fired,
+            [
+                "late 2026-03-28 02:30",
+                "game night 2026-03-28 20:00",
+                "hourly 2026-03-29 00:00",
+                "hourly 2026-03-29 01:00",
+                // the skipped 2:30 runs right after the change
+                "late 2026-03-29 03:00",
+                "hourly 2026-03-29 03:00",
+                "hourly 2026-03-29 04:00",
+                "hourly 2026-03-29 05:00",
+            ]
+        );
+        // game night is at 20:00 in summer time as well, 18:00 UTC
+        let start = time(2026, 4, 4, 17, 59);
+        let mut schedule = self::schedule(
+            vec![entry("game night", "0 20 * * sat")],
+            Storage::default(),
+            start,
+        );
+        let fired = run(&mut schedule, start, time(2026, 4, 4, 18, 1));
+        assert_eq!(fired, ["game night 2026-04-04 20:00"]);
+    }
+
+    #[test]
+    fn turned_back_clocks_run_entries_once() {
+        let start = time(2026, 10, 24, 23, 59);
+        let mut schedule = schedule(
+            vec![entry("late", "30 2 * * *"), entry("hourly", "0 * * * sun")],
+            Storage::default(),
+            start,
+        );
+        // the clocks go back from 3:00 to 2:00 on October 25, 1:00 UTC
+        let fired = run(&mut schedule, start, time(2026, 10, 25, 2, 0));
+        assert_eq!(
+            fired,
+            [
+                "hourly 2026-10-25 02:00",
+                "late 2026-10-25 02:30",
+                // in clock time an hour after the last, though it reads the same
+                "hourly 2026-10-25 02:00",
+                "hourly 2026-10-25 03:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn missed_times_are_only_made_up_when_asked() {
+        let storage = Storage::memory();
+        // at 19:00 in summer time
+        let stop = time(2026, 10, 17, 17, 0);
+        let mut before = schedule(Vec::new(), storage.clone(), stop);
+        assert!(before.tick(stop, |_| None).is_empty());
+        // the bot was stopped during game night
+        let restart = time(2026, 10, 17, 19, 0);
+        let mut made_up = entry("made up", "0 20 * * sat");
+        made_up.catch_up = true;
+        let mut live = entry("live", "1 21 * * sat");
+        live.only_live = true;
+        let mut elsewhere = entry("elsewhere", "1 21 * * *");
+        elsewhere.channel = "#carkhy".to_owned();
+        let mut schedule = schedule(
+            vec![entry("missed", "0 20 * * sat"), made_up, live, elsewhere],
+            storage,
+            restart,
+        );
+        let mut due = Vec::new();
+        let lines = crate::logging::testing::capture(|| {
+            due = schedule.tick(restart + Duration::from_secs(60), |_| Some(false));
+        });
+        // the skipped entries are logged
+        let skipped: Vec<_> = lines
+            .iter()
+            .filter(|line| line["message"].as_str().unwrap().starts_with("skipping"))
+            .map(|line| {
+                (
+                    line["entry"].as_str().unwrap(),
+                    line["message"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (
+                    "live",
+                    "skipping an entry of the schedule, the channel isn't live"
+                ),
+                (
+                    "elsewhere",
+                    "skipping an entry of the schedule, the bot hasn't joined the channel"
+                )
+            ]
+        );
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].name, "made up");
+        assert_eq!((due[0].local.hour, due[0].local.minute), (20, 0));
+        assert_eq!(
+            due[0].action,
+            Action::Message("made up at $(time)".to_owned())
+        );
+        // only the first tick makes up, and only once
+        assert!(schedule
+            .tick(restart + Duration::from_secs(120), |_| Some(true))
+            .is_empty());
+    }
+}
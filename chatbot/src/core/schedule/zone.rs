@@ -0,0 +1,330 @@
+//! The time zone of `schedule.timezone`: a name of the tz database like "Europe/Berlin", or a
+//! POSIX rule like "CET-1CEST,M3.5.0,M10.5.0/3". Named zones are read from the system's
+//! zoneinfo files, whose last line is such a rule; the history before it doesn't matter for
+//! what is scheduled from now on.
+use crate::core::Date;
+use std::{fs, path::Path};
+use thiserror::Error;
+
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ZoneError {
+    #[error("no time zone {0:?} in {ZONEINFO}")]
+    Unknown(String),
+    #[error("{0:?} is no time zone rule like \"CET-1CEST,M3.5.0,M10.5.0/3\"")]
+    Rule(String),
+}
+
+// a day of the year the clocks change on, and the local time they change at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Day {
+    // Mm.w.d: weekday d of week w of month m, week 5 is the last
+    Weekday { month: i64, week: i64, weekday: i64 },
+    // Jn: day 1 to 365, February 29 isn't counted
+    Julian(i64),
+    // n: day 0 to 365, February 29 is counted
+    Zero(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Change {
+    day: Day,
+    // seconds after local midnight, may be negative or past a day
+    time: i64,
+}
+
+/// Offsets in seconds east of UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    standard: i64,
+    // the summer time's offset, and when it starts and ends
+    summer: Option<(i64, Change, Change)>,
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        Self::UTC
+    }
+}
+
+impl Change {
+    // seconds since 1970 in local time at which the clocks change in the year
+    fn local(self, year: i64) -> i64 {
+        let leap = Date {
+            year,
+            month: 2,
+            day: 1,
+        }
+        .days_in_month()
+            == 29;
+        let january = Date {
+            year,
+            month: 1,
+            day: 1,
+        }
+        .days();
+        let days = match self.day {
+            Day::Julian(day) => january + day - 1 + i64::from(leap && day > 59),
+            Day::Zero(day) => january + day,
+            Day::Weekday {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = Date {
+                    year,
+                    month,
+                    day: 1,
+                };
+                let mut day = 1 + (weekday - first.weekday()).rem_euclid(7) + (week - 1) * 7;
+                while day > first.days_in_month() {
+                    day -= 7;
+                }
+                first.days() + day - 1
+            }
+        };
+        days * 86400 + self.time
+    }
+}
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone {
+        standard: 0,
+        summer: None,
+    };
+
+    /// "UTC", a name of the tz database or a POSIX rule.
+    pub fn load(name: &str) -> Result<Self, ZoneError> {
+        if name.is_empty() || name == "UTC" {
+            return Ok(Self::UTC);
+        }
+        let named = name
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+        let path = Path::new(ZONEINFO).join(name);
+        if named && path.is_file() {
+            return Self::read(&path, name);
+        }
+        match Self::parse(name) {
+            Err(_) if name.contains('/') => Err(ZoneError::Unknown(name.to_owned())),
+            parsed => parsed,
+        }
+    }
+
+    // the rule at the end of a zoneinfo file of version 2 or later
+    fn read(path: &Path, name: &str) -> Result<Self, ZoneError> {
+        let unknown = || ZoneError::Unknown(name.to_owned());
+        let data = fs::read(path).map_err(|_| unknown())?;
+        if !data.starts_with(b"TZif") || data.get(4).is_none_or(|&version| version < b'2') {
+            return Err(unknown());
+        }
+        let footer = data.strip_suffix(b"\n").ok_or_else(unknown)?;
+        let start = footer
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .ok_or_else(unknown)?;
+        let rule = std::str::from_utf8(&footer[start + 1..]).map_err(|_| unknown())?;
+        Self::parse(rule).map_err(|_| unknown())
+    }
+
+    /// A POSIX rule like "EST5EDT,M3.2.0,M11.1.0", the offsets count west of UTC.
+    pub fn parse(rule: &str) -> Result<Self, ZoneError> {
+        let invalid = || ZoneError::Rule(rule.to_owned());
+        let mut rest = rule;
+        name(&mut rest).ok_or_else(invalid)?;
+        let standard = -offset(&mut rest).ok_or_else(invalid)?;
+        if rest.is_empty() {
+            return Ok(Self {
+                standard,
+                summer: None,
+            });
+        }
+        name(&mut rest).ok_or_else(invalid)?;
+        let summer = match rest.starts_with(',') {
+            true => standard + 3600,
+            false => -offset(&mut rest).ok_or_else(invalid)?,
+        };
+        // without the days the US rules were meant, but zoneinfo always has them
+        let mut changes = rest.strip_prefix(',').ok_or_else(invalid)?.split(',');
+        let (Some(start), Some(end), None) = (changes.next(), changes.next(), changes.next())
+        else {
+            return Err(invalid());
+        };
+        let start = change(start).ok_or_else(invalid)?;
+        let end = change(end).ok_or_else(invalid)?;
+        Ok(Self {
+            standard,
+            summer: Some((summer, start, end)),
+        })
+    }
+
+    /// The offset at the time, in seconds since 1970.
+    pub fn offset(&self, utc: i64) -> i64 {
+        let Some((summer, start, end)) = self.summer else {
+            return self.standard;
+        };
+        let year = Date::from_days((utc + self.standard).div_euclid(86400)).year;
+        // the start is given in standard time, the end in summer time
+        let start = start.local(year) - self.standard;
+        let end = end.local(year) - summer;
+        let in_summer = match start < end {
+            true => start <= utc && utc < end,
+            // on the southern half the summer spans the new year
+            false => !(end <= utc && utc < start),
+        };
+        match in_summer {
+            true => summer,
+            false => self.standard,
+        }
+    }
+
+    /// The local time, in seconds since 1970 as if it were UTC.
+    pub fn local(&self, utc: i64) -> i64 {
+        utc + self.offset(utc)
+    }
+
+    /// The first time the clocks show the local time, None when they skip it.
+    pub fn utc(&self, local: i64) -> Option<i64> {
+        let mut offsets = vec![self.standard];
+        if let Some((summer, ..)) = self.summer {
+            offsets.push(summer);
+        }
+        offsets
+            .into_iter()
+            .map(|offset| local - offset)
+            .filter(|&utc| self.local(utc) == local)
+            .min()
+    }
+}
+
+// a name like "CET" or "<+03>"
+fn name(rest: &mut &str) -> Option<()> {
+    let end = match rest.strip_prefix('<') {
+        Some(quoted) => quoted.find('>')? + 2,
+        None => rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len()),
+    };
+    if end < 3 {
+        return None;
+    }
+    *rest = &rest[end..];
+    Some(())
+}
+
+// "[+-]hh[:mm[:ss]]" as seconds
+fn offset(rest: &mut &str) -> Option<i64> {
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '+' || c == '-'))
+        .unwrap_or(rest.len());
+    let seconds = time(&rest[..end])?;
+    *rest = &rest[end..];
+    Some(seconds)
+}
+
+fn time(text: &str) -> Option<i64> {
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1, text),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let mut parts = text.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    let seconds: i64 = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    if parts.next().is_some() || hours > 167 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+// "M3.5.0/3", "J60" or "59", at 2:00 without a time
+fn change(text: &str) -> Option<Change> {
+    let (day, time) = match text.split_once('/') {
+        Some((day, time)) => (day, self::time(time)?),
+        None => (text, 7200),
+    };
+    let day = if let Some(fields) = day.strip_prefix('M') {
+        let mut fields = fields.split('.').map(str::parse::<i64>);
+        let (Some(Ok(month)), Some(Ok(week)), Some(Ok(weekday)), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return None;
+        };
+        let valid =
+            (1..=12).contains(&month) && (1..=5).contains(&week) && (0..=6).contains(&weekday);
+        valid.then_some(Day::Weekday {
+            month,
+            week,
+            weekday,
+        })?
+    } else if let Some(day) = day.strip_prefix('J') {
+        let day = day.parse().ok()?;
+        (1..=365).contains(&day).then_some(Day::Julian(day))?
+    } else {
+        let day = day.parse().ok()?;
+        (0..=365).contains(&day).then_some(Day::Zero(day))?
+    };
+    Some(Change { day, time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // seconds since 1970 of the time in UTC
+    fn utc(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> i64 {
+        Date { year, month, day }.days() * 86400 + hour * 3600 + minute * 60
+    }
+
+    #[test]
+    fn summer_time_follows_the_rule() {
+        let berlin = TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        // 2026 changes on March 29 at 1:00 UTC and on October 25 at 1:00 UTC
+        assert_eq!(berlin.offset(utc(2026, 3, 29, 0, 59)), 3600);
+        assert_eq!(berlin.offset(utc(2026, 3, 29, 1, 0)), 7200);
+        assert_eq!(berlin.offset(utc(2026, 10, 25, 0, 59)), 7200);
+        assert_eq!(berlin.offset(utc(2026, 10, 25, 1, 0)), 3600);
+        // 2:30 doesn't happen in march and happens twice in october
+        assert_eq!(berlin.utc(utc(2026, 3, 29, 2, 30)), None);
+        assert_eq!(
+            berlin.utc(utc(2026, 10, 25, 2, 30)),
+            Some(utc(2026, 10, 25, 0, 30))
+        );
+        let new_york = TimeZone::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(new_york.offset(utc(2026, 3, 8, 6, 59)), -5 * 3600);
+        assert_eq!(new_york.offset(utc(2026, 3, 8, 7, 0)), -4 * 3600);
+        let sydney = TimeZone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.offset(utc(2026, 1, 1, 0, 0)), 11 * 3600);
+        assert_eq!(sydney.offset(utc(2026, 7, 1, 0, 0)), 10 * 3600);
+        let india = TimeZone::parse("IST-5:30").unwrap();
+        assert_eq!(india.local(0), 5 * 3600 + 1800);
+        assert_eq!(TimeZone::parse("<+03>-3").unwrap().offset(0), 3 * 3600);
+    }
+
+    #[test]
+    fn broken_rules_are_refused() {
+        for rule in [
+            "",
+            "C-1",
+            "CET",
+            "CET-1CEST",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M13.5.0,M10.5.0",
+        ] {
+            assert_eq!(TimeZone::parse(rule), Err(ZoneError::Rule(rule.to_owned())));
+        }
+        assert_eq!(
+            TimeZone::load("Europe/../../etc/passwd"),
+            Err(ZoneError::Unknown("Europe/../../etc/passwd".to_owned()))
+        );
+        assert_eq!(TimeZone::load("UTC"), Ok(TimeZone::UTC));
+        // only where the system has the files
+        if Path::new(ZONEINFO).join("Europe/Berlin").exists() {
+            assert_eq!(
+                TimeZone::load("Europe/Berlin"),
+                TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3")
+            );
+        }
+    }
+}
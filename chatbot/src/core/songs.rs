@@ -0,0 +1,441 @@
+use super::{
+    commands::{Args, Command, Context},
+    ChatBotCommand,
+};
+use crate::{
+    config::SongsConfig,
+    connect::UserLevel,
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    rc::Rc,
+};
+
+// read by the player or overlay that plays the songs
+const STORAGE_NAME: &str = "songs";
+// the songs `!queue` lists after the current one
+const LISTED: usize = 5;
+
+/// What a user asked for, the player looks up a search itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Song {
+    // the 11 characters of the video id
+    Video(String),
+    Search(String),
+}
+
+impl fmt::Display for Song {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Song::Video(id) => write!(f, "https://youtu.be/{}", id),
+            Song::Search(terms) => write!(f, "\"{}\"", terms),
+        }
+    }
+}
+
+fn is_video_id(text: &str) -> bool {
+    text.len() == 11
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The video id of a link to youtube.com or youtu.be, with or without the scheme.
+fn video_id(link: &str) -> Option<&str> {
+    let link = ["https://", "http://"]
+        .into_iter()
+        .find_map(|scheme| link.strip_prefix(scheme))
+        .unwrap_or(link);
+    let link = ["www.", "m.", "music."]
+        .into_iter()
+        .find_map(|host| link.strip_prefix(host))
+        .unwrap_or(link);
+    let rest = match link.strip_prefix("youtu.be/") {
+        Some(rest) => rest,
+        None => {
+            let path = link.strip_prefix("youtube.com/")?;
+            match path.strip_prefix("watch?") {
+                Some(query) => query
+                    .split('&')
+                    .find_map(|parameter| parameter.strip_prefix("v="))?,
+                None => ["shorts/", "embed/", "live/"]
+                    .into_iter()
+                    .find_map(|kind| path.strip_prefix(kind))?,
+            }
+        }
+    };
+    let id = rest.split(['?', '&', '#', '/']).next()?;
+    is_video_id(id).then_some(id)
+}
+
+/// A link must be one to a video, a single word is taken as a video id when it looks like one.
+/// Anything else is searched for. None for a link that isn't to a video.
+fn song(text: &str) -> Option<Song> {
+    let text = text.trim();
+    let link =
+        !text.contains(char::is_whitespace) && (text.contains("://") || text.contains("youtu"));
+    if link {
+        return video_id(text).map(|id| Song::Video(id.to_owned()));
+    }
+    // "Thunderstru" is a word, real ids mix in digits, dashes or capitals
+    let looks_like_id = is_video_id(text) && text.chars().skip(1).any(|c| !c.is_ascii_lowercase());
+    Some(match looks_like_id {
+        true => Song::Video(text.to_owned()),
+        false => Song::Search(text.to_owned()),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub song: Song,
+    // lowercase
+    pub login: String,
+    pub name: String,
+}
+
+/// Why a song isn't queued.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SongRefusal {
+    // the user's songs waiting already
+    Limit(usize),
+    Duplicate,
+}
+
+/// The song requests of every channel, first come first played. The first song of a
+/// channel is the one playing. The queues are kept in `songs.json` in the storage,
+/// where a player takes them from.
+#[derive(Debug, Default)]
+pub struct Songs {
+    storage: Storage,
+    per_user: usize,
+    // by channel
+    queues: BTreeMap<String, VecDeque<Request>>,
+}
+
+pub type SharedSongs = Rc<RefCell<Songs>>;
+
+impl Songs {
+    pub fn load(config: &SongsConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            queues: storage.load(STORAGE_NAME)?,
+            storage,
+            per_user: config.per_user,
+        })
+    }
+
+    fn save(&mut self) {
+        self.queues.retain(|_, queue| !queue.is_empty());
+        if let Err(error) = self.storage.save(STORAGE_NAME, &self.queues) {
+            tracing::warn!(%error, "could not save the song requests");
+        }
+    }
+
+    /// Queues the song at the end, the position counts the one playing as 1.
+    pub fn request(&mut self, channel: &str, request: Request) -> Result<usize, SongRefusal> {
+        let queue = self.queues.entry(channel.to_owned()).or_default();
+        if queue.iter().any(|queued| queued.song == request.song) {
+            return Err(SongRefusal::Duplicate);
+        }
+        let waiting = queue
+            .iter()
+            .skip(1)
+            .filter(|queued| queued.login == request.login)
+            .count();
+        if waiting >= self.per_user {
+            return Err(SongRefusal::Limit(waiting));
+        }
+        queue.push_back(request);
+        let position = queue.len();
+        self.save();
+        Ok(position)
+    }
+
+    /// The song playing.
+    pub fn current(&self, channel: &str) -> Option<&Request> {
+        self.queues.get(channel)?.front()
+    }
+
+    /// The songs after the one playing, at most count of them.
+    pub fn next(&self, channel: &str, count: usize) -> Vec<&Request> {
+        self.queues
+            .get(channel)
+            .into_iter()
+            .flatten()
+            .skip(1)
+            .take(count)
+            .collect()
+    }
+
+    /// Ends the song playing, the next one plays then.
+    pub fn skip(&mut self, channel: &str) -> Option<Request> {
+        let skipped = self.queues.get_mut(channel)?.pop_front();
+        self.save();
+        skipped
+    }
+
+    /// Takes back the latest song the user requested.
+    pub fn withdraw(&mut self, channel: &str, login: &str) -> Option<Request> {
+        let queue = self.queues.get_mut(channel)?;
+        let index = queue.iter().rposition(|queued| queued.login == login)?;
+        let removed = queue.remove(index);
+        self.save();
+        removed
+    }
+}
+
+/// `!sr <youtube link or search terms>` requests a song.
+pub struct SongRequest(pub SharedSongs);
+
+impl Command for SongRequest {
+    fn name(&self) -> &'static str {
+        "sr"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<youtube link or search terms>"
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["songrequest"]
+    }
+
+    fn execute(&mut self, ctx: &Context, args: Args) -> Option<ChatBotCommand> {
+        let Some(text) = args.rest() else {
+            return ctx.send(format!(
+                "Usage: {}sr <youtube link or search terms>",
+                ctx.prefix
+            ));
+        };
+        let user = &ctx.message.user;
+        let Some(song) = song(text) else {
+            return ctx.send(format!(
+                "{}, that isn't a link to a YouTube video.",
+                user.display_name()
+            ));
+        };
+        let request = Request {
+            song: song.clone(),
+            login: user.name.to_lowercase(),
+            name: user.display_name().to_owned(),
+        };
+        let result = self.0.borrow_mut().request(&ctx.message.channel, request);
+        ctx.send(match result {
+            Ok(1) => format!("Added {}, it plays now.", song),
+            Ok(position) => format!("Added {}, it is number {} in the queue.", song, position),
+            Err(SongRefusal::Duplicate) => format!("{} is in the queue already.", song),
+            Err(SongRefusal::Limit(waiting)) => format!(
+                "{}, you have {} songs waiting already, {}wrongsong takes back the last one.",
+                user.display_name(),
+                waiting,
+                ctx.prefix
+            ),
+        })
+    }
+}
+
+/// `!song` tells which song plays.
+pub struct CurrentSong(pub SharedSongs);
+
+impl Command for CurrentSong {
+    fn name(&self) -> &'static str {
+        "song"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let songs = self.0.borrow();
+        ctx.send(match songs.current(&ctx.message.channel) {
+            Some(request) => format!(
+                "Now playing: {}, requested by {}.",
+                request.song, request.name
+            ),
+            None => format!("No song is playing, request one with {}sr.", ctx.prefix),
+        })
+    }
+}
+
+/// `!queue` lists the next songs, it is called by the viewer queue's `!queue`.
+pub struct SongQueue(pub SharedSongs);
+
+impl Command for SongQueue {
+    fn name(&self) -> &'static str {
+        "queue"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let songs = self.0.borrow();
+        let next = songs.next(&ctx.message.channel, LISTED);
+        if next.is_empty() {
+            return ctx.send("No songs are queued after this one.".to_owned());
+        }
+        let list: Vec<_> = next
+            .iter()
+            .enumerate()
+            .map(|(index, request)| format!("{}. {} ({})", index + 1, request.song, request.name))
+            .collect();
+        ctx.send(format!("Next up: {}", list.join(", ")))
+    }
+}
+
+/// `!skip` ends the song playing.
+pub struct Skip(pub SharedSongs);
+
+impl Command for Skip {
+    fn name(&self) -> &'static str {
+        "skip"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let mut songs = self.0.borrow_mut();
+        let channel = &ctx.message.channel;
+        let Some(skipped) = songs.skip(channel) else {
+            return ctx.send("No song is playing.".to_owned());
+        };
+        ctx.send(match songs.current(channel) {
+            Some(request) => format!(
+                "Skipped {}. Now playing: {}, requested by {}.",
+                skipped.song, request.song, request.name
+            ),
+            None => format!("Skipped {}, the queue is empty now.", skipped.song),
+        })
+    }
+}
+
+/// `!wrongsong` takes back the latest song the user requested.
+pub struct WrongSong(pub SharedSongs);
+
+impl Command for WrongSong {
+    fn name(&self) -> &'static str {
+        "wrongsong"
+    }
+
+    fn execute(&mut self, ctx: &Context, _args: Args) -> Option<ChatBotCommand> {
+        let user = &ctx.message.user;
+        let removed = self
+            .0
+            .borrow_mut()
+            .withdraw(&ctx.message.channel, &user.name.to_lowercase());
+        ctx.send(match removed {
+            Some(request) => format!("Removed {} from the queue.", request.song),
+            None => format!("{}, you have no song in the queue.", user.display_name()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(song: &str, login: &str) -> Request {
+        Request {
+            song: self::song(song).unwrap(),
+            login: login.to_owned(),
+            name: login.to_owned(),
+        }
+    }
+
+    #[test]
+    fn links_are_checked() {
+        let video = |id: &str| Some(Song::Video(id.to_owned()));
+        for link in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?list=PL1&v=dQw4w9WgXcQ&t=42s",
+            "youtu.be/dQw4w9WgXcQ?t=1",
+            "http://m.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+            "dQw4w9WgXcQ",
+        ] {
+            assert_eq!(song(link), video("dQw4w9WgXcQ"), "{}", link);
+        }
+        for link in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXc",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQQ",
+            "https://youtube.com/channel/UC38IQsAvIsxxjztdMZQtwHA",
+            "https://vimeo.com/76979871",
+            "youtu.be/dQw4w9!gXcQ",
+        ] {
+            assert_eq!(song(link), None, "{}", link);
+        }
+        assert_eq!(
+            song(" never gonna give you up "),
+            Some(Song::Search("never gonna give you up".to_owned()))
+        );
+        assert_eq!(
+            song("Thunderstru"),
+            Some(Song::Search("Thunderstru".to_owned()))
+        );
+    }
+
+    #[test]
+    fn users_have_a_limit_and_take_back_their_last_song() {
+        let storage = Storage::memory();
+        let config = SongsConfig {
+            per_user: 2,
+            ..Default::default()
+        };
+        let mut songs = Songs::load(&config, storage.clone()).unwrap();
+        // the song playing doesn't count against the limit
+        assert_eq!(
+            songs.request("carkhy", request("first song", "viewer")),
+            Ok(1)
+        );
+        assert_eq!(
+            songs.request("carkhy", request("second song", "viewer")),
+            Ok(2)
+        );
+        assert_eq!(
+            songs.request("carkhy", request("other song", "other")),
+            Ok(3)
+        );
+        assert_eq!(
+            songs.request("carkhy", request("third song", "viewer")),
+            Ok(4)
+        );
+        assert_eq!(
+            songs.request("carkhy", request("fourth song", "viewer")),
+            Err(SongRefusal::Limit(2))
+        );
+        assert_eq!(
+            songs.request("carkhy", request("other song", "someone")),
+            Err(SongRefusal::Duplicate)
+        );
+        assert_eq!(
+            songs.request("captaincallback", request("fourth song", "viewer")),
+            Ok(1)
+        );
+
+        let removed = songs.withdraw("carkhy", "viewer").unwrap();
+        assert_eq!(removed.song, Song::Search("third song".to_owned()));
+        assert_eq!(
+            songs.request("carkhy", request("fourth song", "viewer")),
+            Ok(4)
+        );
+        assert!(songs.withdraw("carkhy", "nobody").is_none());
+
+        // a restart keeps the queues
+        let mut songs = Songs::load(&config, storage.clone()).unwrap();
+        let next: Vec<_> = songs
+            .next("carkhy", 5)
+            .into_iter()
+            .map(|request| request.song.to_string())
+            .collect();
+        assert_eq!(
+            next,
+            ["\"second song\"", "\"other song\"", "\"fourth song\""]
+        );
+        assert_eq!(songs.skip("carkhy").unwrap().login, "viewer");
+        assert_eq!(
+            songs.current("carkhy").unwrap(),
+            &request("second song", "viewer")
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use super::{
+    chat_stats::ChatStats,
+    commands::render_event,
+    tasks::{announce, uptime, HelixTask},
+    ChatBotCommand,
+};
+use crate::{
+    config::{AnnouncementColor, Config},
+    connect::{ChatBotEvent, InternalEvent, Overflow},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, SystemTime},
+};
+
+/// How often twitch is asked whether the streams are live, without EventSub.
+pub const STATUS_POLL: Duration = Duration::from_secs(120);
+// a stream back online within this long is the same stream, e.g. after the connection dropped
+const FLAP_WINDOW: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+enum State {
+    Offline,
+    // the all-time messages of each chatter when the stream started, for the top chatter
+    Live {
+        since: SystemTime,
+        messages: BTreeMap<String, u64>,
+    },
+    // offline, but back within the flap window counts as the same stream
+    Ending {
+        since: SystemTime,
+        offline: SystemTime,
+        messages: BTreeMap<String, u64>,
+        id: u64,
+    },
+}
+
+/// The stream went live or offline for good, the message is the configured announcement.
+/// The first status of a channel is a change without a message, it was announced before.
+#[derive(Debug)]
+pub struct Change {
+    pub channel: String,
+    pub live: bool,
+    pub message: Option<ChatBotCommand>,
+}
+
+/// Whether the configured channels are live. EventSub tells when the streams go live or
+/// offline, without it twitch is asked every couple of minutes.
+#[derive(Debug, Default)]
+pub struct StreamStatus {
+    channels: Vec<String>,
+    poll: bool,
+    live_message: String,
+    offline_message: String,
+    announce: Option<AnnouncementColor>,
+    // channels twitch didn't tell about yet are left out
+    states: HashMap<String, State>,
+    next_id: u64,
+}
+
+impl StreamStatus {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            channels: config.channel_names(),
+            poll: !config.events.eventsub,
+            live_message: config.events.live_message.clone(),
+            offline_message: config.events.offline_message.clone(),
+            announce: config.events.announce_stream,
+            ..Default::default()
+        }
+    }
+
+    /// None until twitch told, streams that went offline moments ago still count as live.
+    pub fn is_live(&self, channel: &str) -> Option<bool> {
+        self.states
+            .get(channel)
+            .map(|state| !matches!(state, State::Offline))
+    }
+
+    fn ask(&self) -> Vec<ChatBotCommand> {
+        self.channels
+            .iter()
+            .map(|channel| {
+                ChatBotCommand::Helix(HelixTask::LiveStatus {
+                    channel: channel.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Asks twitch about each channel, and schedules the next time without EventSub.
+    pub fn poll(&self) -> Vec<ChatBotCommand> {
+        let mut commands = self.ask();
+        if self.poll && !commands.is_empty() {
+            commands.push(ChatBotCommand::TimedCallback {
+                duration: STATUS_POLL,
+                event: ChatBotEvent::Internal(InternalEvent::StreamStatusTick),
+            });
+        }
+        commands
+    }
+
+    /// EventSub only tells about changes, so twitch is asked once after each welcome. Without
+    /// EventSub for the streams the polls are running already.
+    pub fn welcomed(&self) -> Vec<ChatBotCommand> {
+        match self.poll {
+            true => Vec::new(),
+            false => self.ask(),
+        }
+    }
+
+    fn announce(
+        &self,
+        text: &str,
+        channel: &str,
+        event: &[(&str, String)],
+    ) -> Option<ChatBotCommand> {
+        if text.is_empty() {
+            return None;
+        }
+        let text = render_event(text, "stream announcement", channel, channel, event);
+        Some(match self.announce {
+            Some(color) => announce(channel, text, color, Overflow::Truncate),
+            None => ChatBotCommand::SendMessage {
+                channel: channel.to_owned(),
+                text,
+                overflow: Overflow::Truncate,
+            },
+        })
+    }
+
+    /// Going offline waits for the flap window before it is a change, the command schedules
+    /// the check then.
+    pub fn live_status(
+        &mut self,
+        channel: &str,
+        live: bool,
+        now: SystemTime,
+        stats: &ChatStats,
+    ) -> (Option<Change>, Option<ChatBotCommand>) {
+        let known = self.states.contains_key(channel);
+        let state = self.states.remove(channel).unwrap_or(State::Offline);
+        let (state, change, command) = match (state, live) {
+            (State::Offline, true) => {
+                let live = State::Live {
+                    since: now,
+                    messages: stats.messages(channel),
+                };
+                let change = Change {
+                    channel: channel.to_owned(),
+                    live: true,
+                    message: known
+                        .then(|| self.announce(&self.live_message, channel, &[]))
+                        .flatten(),
+                };
+                (live, Some(change), None)
+            }
+            (
+                State::Ending {
+                    since, messages, ..
+                },
+                true,
+            ) => {
+                tracing::info!(%channel, "the stream is back");
+                (State::Live { since, messages }, None, None)
+            }
+            (State::Live { since, messages }, false) => {
+                self.next_id += 1;
+                let check = ChatBotCommand::TimedCallback {
+                    duration: FLAP_WINDOW,
+                    event: ChatBotEvent::Internal(InternalEvent::StreamEnded {
+                        channel: channel.to_owned(),
+                        id: self.next_id,
+                    }),
+                };
+                let ending = State::Ending {
+                    since,
+                    offline: now,
+                    messages,
+                    id: self.next_id,
+                };
+                (ending, None, Some(check))
+            }
+            (state, false) if !known => {
+                let change = Change {
+                    channel: channel.to_owned(),
+                    live: false,
+                    message: None,
+                };
+                (state, Some(change), None)
+            }
+            (state, _) => (state, None, None),
+        };
+        self.states.insert(channel.to_owned(), state);
+        (change, command)
+    }
+
+    /// The stream stayed offline through the flap window, None if it came back since.
+    pub fn ended(&mut self, channel: &str, id: u64, stats: &ChatStats) -> Option<Change> {
+        let Some(State::Ending {
+            since,
+            offline,
+            messages,
+            id: ending,
+        }) = self.states.get(channel)
+        else {
+            return None;
+        };
+        if *ending != id {
+            return None;
+        }
+        let duration = offline.duration_since(*since).unwrap_or_default();
+        let mut top = ("nobody".to_owned(), 0);
+        for (login, total) in stats.messages(channel) {
+            let written = total.saturating_sub(messages.get(&login).copied().unwrap_or_default());
+            if written > top.1 {
+                top = (login, written);
+            }
+        }
+        let event = [
+            ("duration", uptime(duration)),
+            ("top_chatter", top.0),
+            ("top_messages", top.1.to_string()),
+        ];
+        let message = self.announce(&self.offline_message, channel, &event);
+        self.states.insert(channel.to_owned(), State::Offline);
+        Some(Change {
+            channel: channel.to_owned(),
+            live: false,
+            message,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_status(eventsub: bool) -> StreamStatus {
+        let mut config = Config::default();
+        config.events.eventsub = eventsub;
+        config.events.live_message = "$(channel) is live!".to_owned();
+        config.events.offline_message = "We streamed $(duration)".to_owned();
+        StreamStatus::new(&config)
+    }
+
+    fn minutes(start: SystemTime, minutes: u64) -> SystemTime {
+        start + Duration::from_secs(minutes * 60)
+    }
+
+    fn text(change: Option<Change>) -> Option<String> {
+        match change?.message? {
+            ChatBotCommand::SendMessage { text, .. } => Some(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn flaps_are_debounced() {
+        let mut status = stream_status(true);
+        let stats = ChatStats::default();
+        let start = SystemTime::now();
+        let channel = "captaincallback";
+        // the status when the bot starts isn't announced
+        let (change, _) = status.live_status(channel, false, start, &stats);
+        assert!(change.is_some_and(|change| !change.live && change.message.is_none()));
+        let (change, _) = status.live_status(channel, true, minutes(start, 1), &stats);
+        assert_eq!(text(change).as_deref(), Some("captaincallback is live!"));
+        // the connection drops for a minute
+        let (change, check) = status.live_status(channel, false, minutes(start, 30), &stats);
+        assert!(change.is_none() && status.is_live(channel) == Some(true));
+        let flap = match check {
+            Some(ChatBotCommand::TimedCallback {
+                event: ChatBotEvent::Internal(InternalEvent::StreamEnded { id, .. }),
+                ..
+            }) => id,
+            command => panic!("{:?}", command),
+        };
+        let (change, _) = status.live_status(channel, true, minutes(start, 31), &stats);
+        assert!(change.is_none());
+        assert!(status.ended(channel, flap, &stats).is_none());
+        let (_, check) = status.live_status(channel, false, minutes(start, 61), &stats);
+        let Some(ChatBotCommand::TimedCallback {
+            event: ChatBotEvent::Internal(InternalEvent::StreamEnded { id, .. }),
+            ..
+        }) = check
+        else {
+            panic!("{:?}", check);
+        };
+        let change = status.ended(channel, id, &stats);
+        assert_eq!(text(change).as_deref(), Some("We streamed 1 hour"));
+        assert_eq!(status.is_live(channel), Some(false));
+    }
+
+    #[test]
+    fn twitch_is_polled_without_eventsub() {
+        let polled = stream_status(false).poll();
+        assert!(matches!(
+            &polled[..],
+            [
+                ChatBotCommand::Helix(HelixTask::LiveStatus { channel }),
+                ChatBotCommand::TimedCallback {
+                    event: ChatBotEvent::Internal(InternalEvent::StreamStatusTick),
+                    ..
+                },
+            ] if channel == "captaincallback"
+        ));
+        // EventSub tells about the changes, twitch is only asked for the start
+        assert!(matches!(
+            &stream_status(true).poll()[..],
+            [ChatBotCommand::Helix(HelixTask::LiveStatus { .. })]
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use super::{commands::render_event, ChatBotCommand};
+use crate::{
+    config::EventsConfig,
+    connect::{Overflow, SubTier, UserNotice, UserNoticeKind},
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// twitch sends a gift bomb's single gifts right after it
+const GIFT_BOMB_WINDOW: Duration = Duration::from_secs(60);
+
+pub fn tier(tier: SubTier) -> &'static str {
+    match tier {
+        SubTier::Prime => "Prime",
+        SubTier::Tier1 => "Tier 1",
+        SubTier::Tier2 => "Tier 2",
+        SubTier::Tier3 => "Tier 3",
+    }
+}
+
+/// Thanks for subscriptions and gifted subs, a gift bomb is thanked once.
+#[derive(Debug)]
+pub struct Subs {
+    sub: String,
+    resub: String,
+    gift: String,
+    gift_bomb: String,
+    quiet_in_subs_only: bool,
+    // gifts of a bomb still to come, by channel and gifter's login, with when it was announced
+    bombs: HashMap<(String, String), (u32, Instant)>,
+}
+
+impl Default for Subs {
+    fn default() -> Self {
+        Self::new(&EventsConfig::default())
+    }
+}
+
+impl Subs {
+    pub fn new(config: &EventsConfig) -> Self {
+        Self {
+            sub: config.sub_message.clone(),
+            resub: config.resub_message.clone(),
+            gift: config.gift_message.clone(),
+            gift_bomb: config.gift_bomb_message.clone(),
+            quiet_in_subs_only: config.quiet_in_subs_only,
+            bombs: HashMap::new(),
+        }
+    }
+
+    // false for a gift announced by a bomb before, it was thanked already
+    fn single_gift(&mut self, key: (String, String), now: Instant) -> bool {
+        self.bombs
+            .retain(|_, (_, announced)| now < *announced + GIFT_BOMB_WINDOW);
+        match self.bombs.get_mut(&key) {
+            Some((remaining, _)) => {
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.bombs.remove(&key);
+                }
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// The thank-you for the notice, None for other notices. Subs-only is the channel's mode.
+    pub fn thank(
+        &mut self,
+        notice: &UserNotice,
+        subs_only: bool,
+        now: Instant,
+    ) -> Option<ChatBotCommand> {
+        let key = (notice.channel.clone(), notice.user.name.to_lowercase());
+        let (template, name, event) = match &notice.kind {
+            UserNoticeKind::Sub { tier: plan, .. } => (
+                &self.sub,
+                "sub message",
+                vec![("tier", tier(*plan).to_owned())],
+            ),
+            UserNoticeKind::Resub {
+                tier: plan,
+                cumulative_months,
+                ..
+            } => (
+                &self.resub,
+                "resub message",
+                vec![
+                    ("tier", tier(*plan).to_owned()),
+                    ("months", cumulative_months.to_string()),
+                ],
+            ),
+            UserNoticeKind::SubGift { recipient } => {
+                if !self.single_gift(key, now) {
+                    return None;
+                }
+                (
+                    &self.gift,
+                    "gift message",
+                    vec![("recipient", recipient.clone())],
+                )
+            }
+            UserNoticeKind::SubMysteryGift { count } => {
+                if *count > 0 {
+                    self.bombs.insert(key, (*count, now));
+                }
+                (
+                    &self.gift_bomb,
+                    "gift bomb message",
+                    vec![("gifts", count.to_string())],
+                )
+            }
+            _ => return None,
+        };
+        if template.is_empty() || (subs_only && self.quiet_in_subs_only) {
+            return None;
+        }
+        Some(ChatBotCommand::SendMessage {
+            channel: notice.channel.clone(),
+            text: render_event(
+                template,
+                name,
+                notice.user.display_name(),
+                &notice.channel,
+                &event,
+            ),
+            overflow: Overflow::Truncate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::UserInfo;
+
+    fn notice(login: &str, kind: UserNoticeKind) -> UserNotice {
+        UserNotice {
+            kind,
+            user: UserInfo {
+                name: login.to_owned(),
+                display_name: Some(login.to_uppercase()),
+                ..Default::default()
+            },
+            channel: "captaincallback".to_owned(),
+            system_message: String::new(),
+            text: None,
+        }
+    }
+
+    fn text(command: Option<ChatBotCommand>) -> Option<String> {
+        match command? {
+            ChatBotCommand::SendMessage { text, .. } => Some(text),
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn each_kind_has_its_message() {
+        let mut subs = Subs::new(&EventsConfig {
+            sub_message: "$(user) subscribed with $(tier)!".to_owned(),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        let mut thank = |kind| text(subs.thank(&notice("carkhy", kind), false, now));
+        assert_eq!(
+            thank(UserNoticeKind::Sub {
+                tier: SubTier::Prime,
+                cumulative_months: 1,
+                streak_months: None,
+            })
+            .as_deref(),
+            Some("CARKHY subscribed with Prime!")
+        );
+        assert_eq!(
+            thank(UserNoticeKind::Resub {
+                tier: SubTier::Tier2,
+                cumulative_months: 14,
+                streak_months: Some(3),
+            })
+            .as_deref(),
+            Some("Thank you for 14 months, CARKHY!")
+        );
+        assert_eq!(
+            thank(UserNoticeKind::SubGift {
+                recipient: "viewer".to_owned(),
+            })
+            .as_deref(),
+            Some("Thank you for gifting a sub to viewer, CARKHY!")
+        );
+        assert_eq!(
+            thank(UserNoticeKind::Unknown("bitsbadgetier".to_owned())),
+            None
+        );
+
+        // subs-only mode keeps the bot quiet if configured
+        let mut subs = Subs::new(&EventsConfig {
+            quiet_in_subs_only: true,
+            ..Default::default()
+        });
+        let sub = notice(
+            "carkhy",
+            UserNoticeKind::Sub {
+                tier: SubTier::Tier1,
+                cumulative_months: 1,
+                streak_months: None,
+            },
+        );
+        assert!(subs.thank(&sub, true, now).is_none());
+        assert!(subs.thank(&sub, false, now).is_some());
+    }
+
+    #[test]
+    fn a_gift_bomb_is_thanked_once() {
+        let mut subs = Subs::default();
+        let now = Instant::now();
+        let gift = |login: &str, recipient: usize| {
+            notice(
+                login,
+                UserNoticeKind::SubGift {
+                    recipient: format!("viewer{}", recipient),
+                },
+            )
+        };
+        let bomb = notice("carkhy", UserNoticeKind::SubMysteryGift { count: 20 });
+        let mut sent: Vec<_> = text(subs.thank(&bomb, false, now)).into_iter().collect();
+        for recipient in 0..20 {
+            sent.extend(text(subs.thank(&gift("carkhy", recipient), false, now)));
+        }
+        assert_eq!(sent, ["Thank you for gifting 20 subs, CARKHY!"]);
+
+        // another gifter meanwhile, and the gifter's next single gift are thanked
+        assert!(subs.thank(&gift("viewer", 1), false, now).is_some());
+        assert!(subs.thank(&gift("carkhy", 21), false, now).is_some());
+
+        // gifts that never came don't swallow later ones
+        subs.thank(&bomb, false, now);
+        let later = now + GIFT_BOMB_WINDOW;
+        assert!(subs.thank(&gift("carkhy", 22), false, later).is_some());
+    }
+}
@@ -0,0 +1,2258 @@
+use super::{calendar::Date, polls::PollResults, ChatBotCommand};
+use crate::{
+    config::AnnouncementColor,
+    connect::{fit_message, ChatBotEvent, InternalEvent, Overflow, RoomState},
+    helix::{
+        parse_time, ChannelChange, ChatSetting, Helix, HelixError, Prediction, PredictionEnd, Role,
+        Subscription, User,
+    },
+    prometheus::MODERATION_ACTIONS,
+};
+use reqwest::StatusCode;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const HELIX_FAILED_MESSAGE: &str = "Sorry, I couldn't ask twitch, please try again later.";
+// the streamer finds the reason in the log
+const NOT_ALLOWED_MESSAGE: &str = "Sorry, twitch doesn't allow me to do that yet.";
+// twitch makes a clip within a few seconds, until then it has no url to watch it at
+const CLIP_POLL_DELAY: Duration = Duration::from_secs(5);
+const CLIP_POLLS: u32 = 3;
+// twitch may take a moment to close a poll, its results are asked for after its end
+const POLL_CHECK_DELAY: Duration = Duration::from_secs(5);
+const POLL_CHECKS: u32 = 3;
+// twitch limits whispers per second, minute and day, rate limited ones wait longer each time
+const WHISPER_RETRY_DELAY: Duration = Duration::from_secs(10);
+const WHISPER_ATTEMPTS: u32 = 5;
+// twitch's predictions take points in a window of these seconds
+pub const PREDICTION_WINDOW: std::ops::RangeInclusive<u64> = 30..=1800;
+
+/// What a command needs twitch's API for. Main runs the task after the event was handled,
+/// so commands stay synchronous.
+#[derive(Debug)]
+pub enum HelixTask {
+    // twitch's prediction of the channel, without its id
+    Prediction {
+        channel: String,
+        action: PredictionAction,
+    },
+    // channels without the leading '#'
+    Uptime {
+        channel: String,
+    },
+    // login is the user to shout out
+    Shoutout {
+        channel: String,
+        login: String,
+    },
+    // whether login follows the channel, own when the user asked about themself
+    Followage {
+        channel: String,
+        login: String,
+        own: bool,
+    },
+    SetTitle {
+        channel: String,
+        title: String,
+    },
+    // game is the name, which is looked up first
+    SetGame {
+        channel: String,
+        game: String,
+    },
+    Clip {
+        channel: String,
+    },
+    // length in seconds, one twitch allows
+    Commercial {
+        channel: String,
+        length: u32,
+    },
+    // empty without a description
+    Marker {
+        channel: String,
+        description: String,
+    },
+    // moderation by the filters, failures are only logged
+    DeleteMessage {
+        channel: String,
+        message_id: String,
+    },
+    // a timeout with a duration, only the commands' bans are answered
+    Ban {
+        channel: String,
+        login: String,
+        duration: Option<Duration>,
+        reason: String,
+        answer: bool,
+    },
+    Unban {
+        channel: String,
+        login: String,
+    },
+    // gives login the role, or takes it away
+    Role {
+        channel: String,
+        login: String,
+        role: Role,
+        add: bool,
+    },
+    // seconds between two messages, None turns slow mode off. Failures are only logged
+    SlowMode {
+        channel: String,
+        wait: Option<u32>,
+    },
+    // `!slow` and the like, the bot's copy of the ROOMSTATE follows without waiting for twitch's
+    ChatMode {
+        channel: String,
+        setting: ChatSetting,
+    },
+    // e.g. a greeting only meant for a live stream, twitch is asked at most every 30 seconds
+    SendIfLive {
+        channel: String,
+        text: String,
+    },
+    // whether the channel is live, answered with ChatBotEvent::LiveStatus. Failures are only
+    // logged, the cache is the same as for SendIfLive
+    LiveStatus {
+        channel: String,
+    },
+    // enters the user into the raffle if they follow the channel, see InternalEvent::RaffleEntry.
+    // Failures are only logged
+    RaffleFollower {
+        channel: String,
+        login: String,
+        name: String,
+        tickets: u32,
+        raffle: Uuid,
+    },
+    // asks whether the clip can be watched yet, see InternalEvent::ClipPending
+    CheckClip {
+        channel: String,
+        id: String,
+        edit_url: String,
+        attempt: u32,
+    },
+    // a poll twitch shows on stream, duration in seconds one twitch allows. The results are
+    // announced after its end, see InternalEvent::TwitchPollPending
+    CreatePoll {
+        channel: String,
+        title: String,
+        choices: Vec<String>,
+        duration: u32,
+    },
+    // announces the results of the twitch poll once it ended. Failures are only logged
+    CheckPoll {
+        channel: String,
+        id: String,
+        attempt: u32,
+    },
+    // has the EventSub session tell about the channel's events. Failures are only logged,
+    // twitch refuses unless the bot logged in as the broadcaster
+    Subscribe {
+        channel: String,
+        session_id: String,
+        subscription: Subscription,
+    },
+    // after the actions of the reward ran. Failures are only logged
+    FulfillRedemption {
+        channel: String,
+        reward_id: String,
+        id: String,
+    },
+    // a highlighted message, a chat line once twitch refuses it for the scope
+    Announce {
+        channel: String,
+        text: String,
+        color: AnnouncementColor,
+        overflow: Overflow,
+    },
+    // to login, the fallback is sent to the channel when the whisper can't be.
+    // attempt starts at 1, see whisper
+    Whisper {
+        channel: String,
+        login: String,
+        text: String,
+        fallback: Option<String>,
+        attempt: u32,
+    },
+    // turns shield mode on or off, None asks whether it's on. See ChatBotEvent::ShieldMode
+    Shield {
+        channel: String,
+        active: Option<bool>,
+    },
+}
+
+/// Announces the text, long texts are split or truncated like chat messages.
+pub fn announce(
+    channel: &str,
+    text: String,
+    color: AnnouncementColor,
+    overflow: Overflow,
+) -> ChatBotCommand {
+    ChatBotCommand::Helix(HelixTask::Announce {
+        channel: channel.to_owned(),
+        text,
+        color,
+        overflow,
+    })
+}
+
+/// Whispers the text to the user, answering in the channel when it can't be.
+pub fn whisper(
+    channel: &str,
+    login: &str,
+    text: String,
+    fallback: Option<String>,
+) -> ChatBotCommand {
+    ChatBotCommand::Helix(HelixTask::Whisper {
+        channel: channel.to_owned(),
+        login: login.to_owned(),
+        text,
+        fallback,
+        attempt: 1,
+    })
+}
+
+/// What `!prediction` does, the outcome by its number from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PredictionAction {
+    Start {
+        title: String,
+        outcomes: Vec<String>,
+        // seconds
+        window: u32,
+    },
+    Lock,
+    Resolve(usize),
+    Cancel,
+}
+
+// only the broadcaster's token may, a moderator's is refused as well
+fn setup_message(what: &str, scope: &str) -> String {
+    format!(
+        "Twitch {} need the broadcaster's token with the scope {}, authorize the bot again as the broadcaster.",
+        what, scope
+    )
+}
+
+fn send(channel: &str, text: String) -> ChatBotCommand {
+    ChatBotCommand::SendMessage {
+        channel: channel.to_owned(),
+        text,
+        overflow: Overflow::Split,
+    }
+}
+
+// "1 hour", "2 hours"
+fn count(amount: u64, unit: &str) -> String {
+    match amount {
+        1 => format!("1 {}", unit),
+        _ => format!("{} {}s", amount, unit),
+    }
+}
+
+// "1:23:45" into the VOD
+fn position(seconds: u64) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// "2 hours 13 minutes", seconds are left out
+pub(super) fn uptime(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "less than a minute".to_owned(),
+        (0, minutes) => count(minutes, "minute"),
+        (hours, 0) => count(hours, "hour"),
+        (hours, minutes) => format!("{} {}", count(hours, "hour"), count(minutes, "minute")),
+    }
+}
+
+// "2 years 3 months 4 days", parts that are zero are left out
+fn followage(since: Date, today: Date) -> String {
+    let (years, months, days) = since.until(today);
+    let parts: Vec<_> = [(years, "year"), (months, "month"), (days, "day")]
+        .into_iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| count(amount as u64, unit))
+        .collect();
+    match parts.is_empty() {
+        true => "less than a day".to_owned(),
+        false => parts.join(" "),
+    }
+}
+
+// both asked for in one request
+async fn broadcaster_and_user(
+    helix: &mut Helix,
+    channel: &str,
+    login: &str,
+) -> Result<(Option<User>, Option<User>), HelixError> {
+    let mut users = helix.users(&[channel, login]).await?.into_iter();
+    Ok((users.next().flatten(), users.next().flatten()))
+}
+
+// false for users and channels twitch doesn't know
+async fn follows(helix: &mut Helix, channel: &str, login: &str) -> Result<bool, HelixError> {
+    let (Some(broadcaster), Some(user)) = broadcaster_and_user(helix, channel, login).await? else {
+        return Ok(false);
+    };
+    Ok(helix.followed_at(&broadcaster, &user).await?.is_some())
+}
+
+async fn follow_text(
+    helix: &mut Helix,
+    channel: &str,
+    login: &str,
+    own: bool,
+) -> Result<String, HelixError> {
+    let (broadcaster, user) = broadcaster_and_user(helix, channel, login).await?;
+    let Some(user) = user else {
+        return Ok(format!("There is no twitch user named {}.", login));
+    };
+    let Some(broadcaster) = broadcaster else {
+        return Ok(format!("There is no twitch user named {}.", channel));
+    };
+    if user.id == broadcaster.id {
+        return Ok(format!(
+            "{} can't follow their own channel.",
+            user.display_name
+        ));
+    }
+    let followed_at = helix.followed_at(&broadcaster, &user).await?;
+    let since = followed_at.as_deref().and_then(parse_time).map(Date::of);
+    let today = Date::of(SystemTime::now());
+    Ok(match (since, own) {
+        (Some(since), true) => format!(
+            "You have followed {} for {}.",
+            broadcaster.display_name,
+            followage(since, today)
+        ),
+        (Some(since), false) => format!(
+            "{} has followed {} for {}.",
+            user.display_name,
+            broadcaster.display_name,
+            followage(since, today)
+        ),
+        (None, true) => format!("You don't follow {}.", broadcaster.display_name),
+        (None, false) => format!(
+            "{} doesn't follow {}.",
+            user.display_name, broadcaster.display_name
+        ),
+    })
+}
+
+// the official shoutout only works while live, the chat message always
+async fn shoutout_text(
+    helix: &mut Helix,
+    channel: &str,
+    login: &str,
+) -> Result<String, HelixError> {
+    let (broadcaster, user) = broadcaster_and_user(helix, channel, login).await?;
+    let Some(user) = user else {
+        return Ok(format!("Sorry, I couldn't find the user {}.", login));
+    };
+    let game = helix
+        .channel(&user)
+        .await?
+        .map(|info| info.game_name)
+        .filter(|game| !game.is_empty());
+    if let Some(broadcaster) = broadcaster {
+        if let Err(error) = helix.shoutout(&broadcaster, &user).await {
+            tracing::warn!(user = %user.login, %error, "not sending twitch's shoutout");
+        }
+    }
+    Ok(match game {
+        Some(game) => format!(
+            "Go check out {} at https://twitch.tv/{} — they were last playing {}!",
+            user.display_name, user.login, game
+        ),
+        None => format!(
+            "Go check out {} at https://twitch.tv/{}!",
+            user.display_name, user.login
+        ),
+    })
+}
+
+async fn uptime_text(helix: &mut Helix, channel: &str) -> Result<String, HelixError> {
+    Ok(match helix.stream(channel).await? {
+        Some(stream) => match stream.uptime(SystemTime::now()) {
+            Some(duration) => format!("Stream has been live for {}", uptime(duration)),
+            None => "Stream has just started.".to_owned(),
+        },
+        None => format!("{} is offline right now.", channel),
+    })
+}
+
+async fn title_text(helix: &mut Helix, channel: &str, title: &str) -> Result<String, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(format!("There is no twitch user named {}.", channel));
+    };
+    helix
+        .modify_channel(&broadcaster, ChannelChange::Title(title))
+        .await?;
+    Ok(format!("The title is now: {}", title))
+}
+
+async fn game_text(helix: &mut Helix, channel: &str, name: &str) -> Result<String, HelixError> {
+    let Some(game) = helix.game(name).await? else {
+        let similar = helix.similar_games(name).await?;
+        return Ok(match similar.is_empty() {
+            true => format!("There is no game called {}.", name),
+            false => format!(
+                "There is no game called {}. Did you mean: {}?",
+                name,
+                similar.join(", ")
+            ),
+        });
+    };
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(format!("There is no twitch user named {}.", channel));
+    };
+    helix
+        .modify_channel(&broadcaster, ChannelChange::Game(&game))
+        .await?;
+    Ok(format!("The game is now {}.", game.name))
+}
+
+async fn commercial_text(
+    helix: &mut Helix,
+    channel: &str,
+    length: u32,
+) -> Result<String, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(format!("There is no twitch user named {}.", channel));
+    };
+    match helix.start_commercial(&broadcaster, length).await {
+        Ok(Some(commercial)) => Ok(format!(
+            "Running a {} second commercial. The next one can run in {}.",
+            commercial.length,
+            uptime(Duration::from_secs(commercial.retry_after))
+        )),
+        Ok(None) => Ok("Twitch didn't start the commercial.".to_owned()),
+        Err(HelixError::Status {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message,
+        }) => Ok(format!("The last commercial was too recent: {}", message)),
+        // e.g. neither partner nor affiliate, or offline
+        Err(HelixError::Status {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }) => Ok(format!("Couldn't run the commercial: {}", message)),
+        Err(error) => Err(error),
+    }
+}
+
+async fn marker_text(
+    helix: &mut Helix,
+    channel: &str,
+    description: &str,
+) -> Result<String, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(format!("There is no twitch user named {}.", channel));
+    };
+    match helix.create_marker(&broadcaster, description).await {
+        Ok(Some(marker)) => Ok(format!(
+            "Marker created at {}",
+            position(marker.position_seconds)
+        )),
+        Ok(None) => Ok("Twitch didn't create the marker.".to_owned()),
+        // offline, or VODs are disabled; twitch's reason tells the streamer which
+        Err(HelixError::Status { status, message })
+            if status == StatusCode::NOT_FOUND || status == StatusCode::FORBIDDEN =>
+        {
+            Ok(format!("Couldn't create the marker: {}", message))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+async fn clip(helix: &mut Helix, channel: &str) -> Result<ChatBotCommand, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(send(
+            channel,
+            format!("There is no twitch user named {}.", channel),
+        ));
+    };
+    let Some(clip) = helix.create_clip(&broadcaster).await? else {
+        return Ok(send(channel, "Can't clip an offline stream.".to_owned()));
+    };
+    check_clip(helix, channel, clip.id, clip.edit_url, 1).await
+}
+
+// while twitch is still making the clip, asks again later instead of holding up the bot
+async fn check_clip(
+    helix: &mut Helix,
+    channel: &str,
+    id: String,
+    edit_url: String,
+    attempt: u32,
+) -> Result<ChatBotCommand, HelixError> {
+    if let Some(url) = helix.clip_url(&id).await? {
+        return Ok(send(channel, format!("Here is the clip: {}", url)));
+    }
+    if attempt >= CLIP_POLLS {
+        return Ok(send(
+            channel,
+            format!("The clip is still being made, find it here: {}", edit_url),
+        ));
+    }
+    Ok(ChatBotCommand::TimedCallback {
+        duration: CLIP_POLL_DELAY,
+        event: ChatBotEvent::Internal(InternalEvent::ClipPending {
+            channel: channel.to_owned(),
+            id,
+            edit_url,
+            attempt: attempt + 1,
+        }),
+    })
+}
+
+async fn create_poll(
+    helix: &mut Helix,
+    channel: &str,
+    title: &str,
+    choices: &[String],
+    duration: u32,
+) -> Result<ChatBotCommand, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(send(
+            channel,
+            format!("There is no twitch user named {}.", channel),
+        ));
+    };
+    match helix
+        .create_poll(&broadcaster, title, choices, duration)
+        .await
+    {
+        Ok(Some(poll)) => Ok(ChatBotCommand::MultipleCommands(vec![
+            send(
+                channel,
+                format!(
+                    "The poll is on stream: {} It ends in {} seconds.",
+                    poll.title, poll.duration
+                ),
+            ),
+            ChatBotCommand::TimedCallback {
+                duration: Duration::from_secs(poll.duration) + POLL_CHECK_DELAY,
+                event: ChatBotEvent::Internal(InternalEvent::TwitchPollPending {
+                    channel: channel.to_owned(),
+                    id: poll.id,
+                    attempt: 1,
+                }),
+            },
+        ])),
+        Ok(None) => Ok(send(channel, "Twitch didn't start the poll.".to_owned())),
+        Err(HelixError::MissingScope(scope)) => Ok(send(channel, setup_message("polls", scope))),
+        // e.g. a poll is running already, or the channel is neither partner nor affiliate
+        Err(HelixError::Status {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }) => Ok(send(
+            channel,
+            format!("Couldn't start the poll: {}", message),
+        )),
+        Err(error) => Err(error),
+    }
+}
+
+// without the scope, or as no moderator, the text goes to chat. The hint for the scope
+// is logged once
+async fn send_announcement(
+    helix: &mut Helix,
+    channel: &str,
+    text: &str,
+    color: AnnouncementColor,
+    overflow: Overflow,
+) -> Result<Option<ChatBotCommand>, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(None);
+    };
+    let parts = fit_message(text, overflow);
+    for (index, part) in parts.iter().enumerate() {
+        match helix.announce(&broadcaster, part, color).await {
+            Ok(()) => {}
+            Err(HelixError::MissingScope(_)) => {
+                let lines = parts[index..]
+                    .iter()
+                    .map(|part| ChatBotCommand::SendMessage {
+                        channel: channel.to_owned(),
+                        text: part.clone(),
+                        overflow: Overflow::Truncate,
+                    })
+                    .collect();
+                return Ok(Some(ChatBotCommand::MultipleCommands(lines)));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(None)
+}
+
+// rate limited whispers are sent again later, those the user blocks go to chat
+async fn send_whisper(
+    helix: &mut Helix,
+    channel: &str,
+    login: &str,
+    text: &str,
+    fallback: &Option<String>,
+    attempt: u32,
+) -> Result<Option<ChatBotCommand>, HelixError> {
+    let undelivered = |reason: &str| match fallback {
+        // the answer to an admin's whisper has no channel
+        _ if channel.is_empty() => {
+            ChatBotCommand::LogTextMessage(format!("Couldn't whisper {}: {}", login, reason))
+        }
+        Some(fallback) => send(channel, fallback.clone()),
+        None => send(
+            channel,
+            format!("@{}, I couldn't whisper you: {}", login, reason),
+        ),
+    };
+    match helix.send_whisper(login, text).await {
+        Ok(()) => Ok(None),
+        Err(HelixError::WhispersBlocked(_)) => {
+            Ok(Some(undelivered("your settings don't allow my whispers.")))
+        }
+        Err(HelixError::RateLimited) if attempt < WHISPER_ATTEMPTS => {
+            Ok(Some(ChatBotCommand::TimedCallback {
+                duration: WHISPER_RETRY_DELAY * 2u32.pow(attempt - 1),
+                event: ChatBotEvent::Internal(InternalEvent::WhisperRetry {
+                    channel: channel.to_owned(),
+                    login: login.to_owned(),
+                    text: text.to_owned(),
+                    fallback: fallback.clone(),
+                    attempt: attempt + 1,
+                }),
+            }))
+        }
+        Err(HelixError::RateLimited) => Ok(Some(undelivered(
+            "twitch doesn't let me whisper that much right now.",
+        ))),
+        Err(error) => Err(error),
+    }
+}
+
+// while twitch still takes votes, asks again a little later
+async fn check_poll(
+    helix: &mut Helix,
+    channel: &str,
+    id: &str,
+    attempt: u32,
+) -> Result<Option<ChatBotCommand>, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(None);
+    };
+    let Some(poll) = helix.poll(&broadcaster, id).await? else {
+        return Ok(None);
+    };
+    match poll.status.as_str() {
+        "ACTIVE" if attempt < POLL_CHECKS => Ok(Some(ChatBotCommand::TimedCallback {
+            duration: POLL_CHECK_DELAY,
+            event: ChatBotEvent::Internal(InternalEvent::TwitchPollPending {
+                channel: channel.to_owned(),
+                id: id.to_owned(),
+                attempt: attempt + 1,
+            }),
+        })),
+        "COMPLETED" | "TERMINATED" => {
+            let results = PollResults {
+                question: poll.title,
+                voters: poll
+                    .choices
+                    .iter()
+                    .map(|choice| choice.votes as usize)
+                    .sum(),
+                options: poll
+                    .choices
+                    .into_iter()
+                    .map(|choice| (choice.title, choice.votes))
+                    .collect(),
+            };
+            Ok(Some(send(
+                channel,
+                format!("The poll is over: {}", results),
+            )))
+        }
+        // removed by twitch's moderation, or still running after all attempts
+        status => {
+            tracing::info!(%channel, poll = %id, %status, "not announcing the poll");
+            Ok(None)
+        }
+    }
+}
+
+// "Yes 1200 points from 5 users, No 300 points from 1 user"
+fn points_by_outcome(prediction: &Prediction) -> String {
+    let outcomes: Vec<_> = prediction
+        .outcomes
+        .iter()
+        .map(|outcome| {
+            format!(
+                "{} {} points from {}",
+                outcome.title,
+                outcome.channel_points,
+                count(outcome.users, "user")
+            )
+        })
+        .collect();
+    outcomes.join(", ")
+}
+
+// what twitch paid the winners, the points of all outcomes are shared among them
+fn payout(prediction: &Prediction) -> String {
+    let Some(winner) = prediction
+        .outcomes
+        .iter()
+        .find(|outcome| prediction.winning_outcome_id.as_ref() == Some(&outcome.id))
+    else {
+        return "The prediction is resolved.".to_owned();
+    };
+    let total: u64 = prediction
+        .outcomes
+        .iter()
+        .map(|outcome| outcome.channel_points)
+        .sum();
+    if winner.users == 0 {
+        return format!(
+            "{} wins, but nobody predicted it. {} points are lost.",
+            winner.title, total
+        );
+    }
+    let biggest = winner
+        .top_predictors
+        .iter()
+        .flatten()
+        .max_by_key(|predictor| predictor.channel_points_won);
+    match biggest {
+        Some(predictor) => format!(
+            "{} wins! {} share {} points, the biggest winner is {} (+{}).",
+            winner.title,
+            count(winner.users, "user"),
+            total,
+            predictor.user_name,
+            predictor.channel_points_won
+        ),
+        None => format!(
+            "{} wins! {} share {} points.",
+            winner.title,
+            count(winner.users, "user"),
+            total
+        ),
+    }
+}
+
+async fn end_prediction(
+    helix: &mut Helix,
+    broadcaster: &User,
+    action: &PredictionAction,
+) -> Result<String, HelixError> {
+    let Some(prediction) = helix.open_prediction(broadcaster).await? else {
+        return Ok("No prediction is running.".to_owned());
+    };
+    let end = match action {
+        PredictionAction::Resolve(number) => {
+            match prediction.outcomes.get(number.wrapping_sub(1)) {
+                Some(outcome) => PredictionEnd::Resolve(&outcome.id),
+                None => {
+                    return Ok(format!(
+                        "There is no outcome {}, it has {}.",
+                        number,
+                        prediction.outcomes.len()
+                    ))
+                }
+            }
+        }
+        PredictionAction::Lock => PredictionEnd::Lock,
+        _ => PredictionEnd::Cancel,
+    };
+    let Some(ended) = helix
+        .end_prediction(broadcaster, &prediction.id, end)
+        .await?
+    else {
+        return Ok("Twitch didn't change the prediction.".to_owned());
+    };
+    Ok(match end {
+        PredictionEnd::Lock => format!("Predictions are locked: {}.", points_by_outcome(&ended)),
+        PredictionEnd::Resolve(_) => payout(&ended),
+        PredictionEnd::Cancel => {
+            "The prediction is cancelled, everyone got their points back.".to_owned()
+        }
+    })
+}
+
+async fn prediction_text(
+    helix: &mut Helix,
+    channel: &str,
+    action: &PredictionAction,
+) -> Result<String, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(format!("There is no twitch user named {}.", channel));
+    };
+    let result = match action {
+        PredictionAction::Start {
+            title,
+            outcomes,
+            window,
+        } => helix
+            .create_prediction(&broadcaster, title, outcomes, *window)
+            .await
+            .map(|prediction| match prediction {
+                Some(prediction) => {
+                    let outcomes: Vec<_> = prediction
+                        .outcomes
+                        .iter()
+                        .enumerate()
+                        .map(|(index, outcome)| format!("{}. {}", index + 1, outcome.title))
+                        .collect();
+                    format!(
+                        "Prediction: {} {}. Predict within {} seconds!",
+                        prediction.title,
+                        outcomes.join(", "),
+                        prediction.prediction_window
+                    )
+                }
+                None => "Twitch didn't start the prediction.".to_owned(),
+            }),
+        _ => end_prediction(helix, &broadcaster, action).await,
+    };
+    match result {
+        Err(HelixError::MissingScope(scope)) => Ok(setup_message("predictions", scope)),
+        // e.g. one is running already, or the channel is neither partner nor affiliate
+        Err(HelixError::Status {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }) => Ok(format!("Twitch refused: {}", message)),
+        result => result,
+    }
+}
+
+async fn shield(
+    helix: &mut Helix,
+    channel: &str,
+    active: Option<bool>,
+) -> Result<ChatBotCommand, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(send(
+            channel,
+            format!("There is no twitch user named {}.", channel),
+        ));
+    };
+    let result = match active {
+        Some(active) => helix.set_shield_mode(&broadcaster, active).await,
+        None => helix.shield_mode(&broadcaster).await,
+    };
+    let mode = match result {
+        Ok(mode) => mode,
+        Err(HelixError::NotModerator) => {
+            return Ok(send(
+                channel,
+                "I can't use shield mode here, make me a moderator first.".to_owned(),
+            ))
+        }
+        Err(HelixError::MissingScope(scope)) => {
+            return Ok(send(
+                channel,
+                format!(
+                    "Shield mode needs a token with the scope {}, authorize the bot again.",
+                    scope
+                ),
+            ))
+        }
+        Err(error) => return Err(error),
+    };
+    // the filters follow what twitch says, whoever asked
+    let changed = ChatBotCommand::TimedCallback {
+        duration: Duration::ZERO,
+        event: ChatBotEvent::ShieldMode {
+            channel: channel.to_owned(),
+            active: mode.is_active,
+            asked: active.is_some(),
+        },
+    };
+    if active.is_some() {
+        return Ok(changed);
+    }
+    let since = parse_time(&mode.last_activated_at)
+        .and_then(|time| SystemTime::now().duration_since(time).ok());
+    let text = match (mode.is_active, since) {
+        (true, Some(since)) => format!(
+            "Shield mode is on, {} turned it on {} ago.",
+            mode.moderator_name,
+            uptime(since)
+        ),
+        (true, None) => "Shield mode is on.".to_owned(),
+        (false, Some(since)) => format!(
+            "Shield mode is off, it was last turned on {} ago. {} changed it last.",
+            uptime(since),
+            mode.moderator_name
+        ),
+        (false, None) => "Shield mode is off, it was never turned on.".to_owned(),
+    };
+    Ok(ChatBotCommand::MultipleCommands(vec![
+        send(channel, text),
+        changed,
+    ]))
+}
+
+async fn delete_message(
+    helix: &mut Helix,
+    channel: &str,
+    message_id: &str,
+) -> Result<(), HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        tracing::warn!(channel, "not deleting a message, there is no such user");
+        return Ok(());
+    };
+    helix.delete_message(&broadcaster, message_id).await
+}
+
+async fn slow_mode(helix: &mut Helix, channel: &str, wait: Option<u32>) -> Result<(), HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        tracing::warn!(channel, "not changing slow mode, there is no such user");
+        return Ok(());
+    };
+    helix
+        .update_chat_settings(&broadcaster, ChatSetting::Slow(wait))
+        .await?;
+    match wait {
+        Some(seconds) => tracing::info!(channel, seconds, "slow mode is back"),
+        None => tracing::info!(channel, "slow mode is off"),
+    }
+    Ok(())
+}
+
+// twitch's documented refusals in words, the others with twitch's reason
+fn role_refusal(name: &str, role: Role, add: bool, status: StatusCode, message: &str) -> String {
+    let already = message.to_lowercase().contains("already");
+    match (role, add, status.as_u16()) {
+        (_, _, 429) => {
+            "Twitch is still busy with the last change, try again in a moment.".to_owned()
+        }
+        (_, true, 400 | 422) if already => format!("{} is {} already.", name, role),
+        (Role::Vip, true, 409) => format!(
+            "Couldn't make {} a VIP, the channel has reached its limit of VIPs.",
+            name
+        ),
+        (Role::Vip, true, 425) => {
+            "The channel needs the Build a Community achievement before it can have VIPs."
+                .to_owned()
+        }
+        (Role::Vip, true, 422) => format!(
+            "{} is a moderator, they can't be a VIP until they are unmodded.",
+            name
+        ),
+        (Role::Moderator, true, 422) => format!(
+            "{} is a VIP, they can't be a moderator until they are unvipped.",
+            name
+        ),
+        (Role::Moderator, true, 400) if message.to_lowercase().contains("banned") => {
+            format!("{} is banned, they can't be a moderator.", name)
+        }
+        (_, false, 400 | 404 | 422) => format!("{} isn't {}.", name, role),
+        _ => format!("Twitch refused: {}", message),
+    }
+}
+
+async fn role_text(
+    helix: &mut Helix,
+    channel: &str,
+    login: &str,
+    role: Role,
+    add: bool,
+) -> Result<String, HelixError> {
+    let (Some(broadcaster), Some(user)) = broadcaster_and_user(helix, channel, login).await? else {
+        return Ok(format!("There is no twitch user named {}.", login));
+    };
+    let name = &user.display_name;
+    match helix.set_role(&broadcaster, &user, role, add).await {
+        Ok(()) => Ok(match add {
+            true => format!("{} is {} now.", name, role),
+            false => format!("{} isn't {} anymore.", name, role),
+        }),
+        Err(HelixError::MissingScope(scope)) => Ok(setup_message(
+            match role {
+                Role::Vip => "VIPs",
+                Role::Moderator => "moderators",
+            },
+            scope,
+        )),
+        Err(HelixError::Status { status, message }) if status.is_client_error() => {
+            Ok(role_refusal(name, role, add, status, &message))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// what twitch tells in chat after the change
+fn chat_mode_text(setting: ChatSetting) -> String {
+    match setting {
+        ChatSetting::Slow(Some(seconds)) => format!(
+            "Slow mode is on, {} between messages.",
+            count(seconds.into(), "second")
+        ),
+        ChatSetting::Slow(None) => "Slow mode is off.".to_owned(),
+        ChatSetting::EmoteOnly(true) => "Emote-only mode is on.".to_owned(),
+        ChatSetting::EmoteOnly(false) => "Emote-only mode is off.".to_owned(),
+        ChatSetting::Followers(Some(0)) => "Followers-only mode is on.".to_owned(),
+        ChatSetting::Followers(Some(minutes)) => format!(
+            "Followers-only mode is on, for followers of {} or longer.",
+            count(minutes.into(), "minute")
+        ),
+        ChatSetting::Followers(None) => "Followers-only mode is off.".to_owned(),
+        ChatSetting::Subscribers(true) => "Subscribers-only mode is on.".to_owned(),
+        ChatSetting::Subscribers(false) => "Subscribers-only mode is off.".to_owned(),
+        ChatSetting::UniqueChat(true) => "Unique chat is on.".to_owned(),
+        ChatSetting::UniqueChat(false) => "Unique chat is off.".to_owned(),
+    }
+}
+
+// the ROOMSTATE twitch sends after the change
+fn room_state(channel: &str, setting: ChatSetting) -> RoomState {
+    let mut state = RoomState {
+        channel: channel.to_owned(),
+        ..Default::default()
+    };
+    match setting {
+        ChatSetting::Slow(wait) => state.slow = Some(wait.unwrap_or(0)),
+        ChatSetting::EmoteOnly(on) => state.emote_only = Some(on),
+        ChatSetting::Followers(minutes) => {
+            state.followers_only = Some(minutes.map_or(-1, |minutes| minutes as i32))
+        }
+        ChatSetting::Subscribers(on) => state.subs_only = Some(on),
+        ChatSetting::UniqueChat(on) => state.r9k = Some(on),
+    }
+    state
+}
+
+async fn chat_mode(
+    helix: &mut Helix,
+    channel: &str,
+    setting: ChatSetting,
+) -> Result<ChatBotCommand, HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(send(
+            channel,
+            format!("There is no twitch user named {}.", channel),
+        ));
+    };
+    match helix.update_chat_settings(&broadcaster, setting).await {
+        Ok(()) => Ok(ChatBotCommand::MultipleCommands(vec![
+            send(channel, chat_mode_text(setting)),
+            ChatBotCommand::TimedCallback {
+                duration: Duration::ZERO,
+                event: ChatBotEvent::RoomState(room_state(channel, setting)),
+            },
+        ])),
+        Err(HelixError::Status {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }) => Ok(send(
+            channel,
+            format!("Couldn't change the chat settings: {}", message),
+        )),
+        Err(error) => Err(error),
+    }
+}
+
+async fn subscribe(
+    helix: &mut Helix,
+    channel: &str,
+    session_id: &str,
+    subscription: Subscription,
+) -> Result<(), HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        tracing::warn!(channel, %subscription, "not listening, there is no such user");
+        return Ok(());
+    };
+    helix
+        .subscribe(&broadcaster, subscription, session_id)
+        .await?;
+    tracing::info!(channel, %subscription, "listening to EventSub");
+    Ok(())
+}
+
+async fn fulfill_redemption(
+    helix: &mut Helix,
+    channel: &str,
+    reward_id: &str,
+    id: &str,
+) -> Result<(), HelixError> {
+    let Some(broadcaster) = helix.user(channel).await? else {
+        return Ok(());
+    };
+    helix.fulfill_redemption(&broadcaster, reward_id, id).await
+}
+
+async fn ban_text(
+    helix: &mut Helix,
+    channel: &str,
+    login: &str,
+    duration: Option<Duration>,
+    reason: &str,
+) -> Result<String, HelixError> {
+    let (Some(broadcaster), Some(user)) = broadcaster_and_user(helix, channel, login).await? else {
+        return Ok(format!("There is no twitch user named {}.", login));
+    };
+    match helix.ban(&broadcaster, &user, duration, reason).await {
+        Ok(()) => Ok(match duration {
+            Some(duration) => format!(
+                "Timed out {} for {} seconds.",
+                user.display_name,
+                duration.as_secs()
+            ),
+            None => format!("Banned {}.", user.display_name),
+        }),
+        // "The user specified in the user_id field is already banned."
+        Err(HelixError::Status {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }) => Ok(format!("Couldn't ban {}: {}", user.display_name, message)),
+        Err(error) => Err(error),
+    }
+}
+
+async fn unban_text(helix: &mut Helix, channel: &str, login: &str) -> Result<String, HelixError> {
+    let (Some(broadcaster), Some(user)) = broadcaster_and_user(helix, channel, login).await? else {
+        return Ok(format!("There is no twitch user named {}.", login));
+    };
+    match helix.unban(&broadcaster, &user).await {
+        Ok(()) => Ok(format!("Unbanned {}.", user.display_name)),
+        // "The user specified in the user_id field is not banned."
+        Err(HelixError::Status {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }) => Ok(format!("Couldn't unban {}: {}", user.display_name, message)),
+        Err(error) => Err(error),
+    }
+}
+
+impl HelixTask {
+    fn channel(&self) -> &str {
+        match self {
+            HelixTask::Prediction { channel, .. }
+            | HelixTask::Uptime { channel }
+            | HelixTask::Shoutout { channel, .. }
+            | HelixTask::Followage { channel, .. }
+            | HelixTask::SetTitle { channel, .. }
+            | HelixTask::SetGame { channel, .. }
+            | HelixTask::Clip { channel }
+            | HelixTask::Marker { channel, .. }
+            | HelixTask::Commercial { channel, .. }
+            | HelixTask::DeleteMessage { channel, .. }
+            | HelixTask::Ban { channel, .. }
+            | HelixTask::Unban { channel, .. }
+            | HelixTask::Role { channel, .. }
+            | HelixTask::SendIfLive { channel, .. }
+            | HelixTask::LiveStatus { channel }
+            | HelixTask::SlowMode { channel, .. }
+            | HelixTask::ChatMode { channel, .. }
+            | HelixTask::RaffleFollower { channel, .. }
+            | HelixTask::CheckClip { channel, .. }
+            | HelixTask::CreatePoll { channel, .. }
+            | HelixTask::CheckPoll { channel, .. }
+            | HelixTask::Subscribe { channel, .. }
+            | HelixTask::FulfillRedemption { channel, .. }
+            | HelixTask::Announce { channel, .. }
+            | HelixTask::Whisper { channel, .. }
+            | HelixTask::Shield { channel, .. } => channel,
+        }
+    }
+
+    /// The logins the task asks twitch about, the channel first.
+    pub fn logins(&self) -> Vec<&str> {
+        let login = match self {
+            HelixTask::Shoutout { login, .. }
+            | HelixTask::Followage { login, .. }
+            | HelixTask::Ban { login, .. }
+            | HelixTask::Unban { login, .. }
+            | HelixTask::Role { login, .. }
+            | HelixTask::Whisper { login, .. } => Some(login.as_str()),
+            _ => None,
+        };
+        [Some(self.channel()), login]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// What the bot answers, failures are logged and apologized for.
+    pub async fn run(self, helix: &mut Helix) -> Option<ChatBotCommand> {
+        let channel = self.channel().to_owned();
+        let answer = |text| Some(send(&channel, text));
+        let command = match &self {
+            HelixTask::Prediction { channel, action } => {
+                prediction_text(helix, channel, action).await.map(answer)
+            }
+            HelixTask::Uptime { channel } => uptime_text(helix, channel).await.map(answer),
+            HelixTask::Shoutout { channel, login } => {
+                shoutout_text(helix, channel, login).await.map(answer)
+            }
+            HelixTask::Followage {
+                channel,
+                login,
+                own,
+            } => follow_text(helix, channel, login, *own).await.map(answer),
+            HelixTask::SetTitle { channel, title } => {
+                title_text(helix, channel, title).await.map(answer)
+            }
+            HelixTask::SetGame { channel, game } => {
+                game_text(helix, channel, game).await.map(answer)
+            }
+            HelixTask::Clip { channel } => clip(helix, channel).await.map(Some),
+            HelixTask::Commercial { channel, length } => {
+                commercial_text(helix, channel, *length).await.map(answer)
+            }
+            HelixTask::Marker {
+                channel,
+                description,
+            } => marker_text(helix, channel, description).await.map(answer),
+            HelixTask::CheckClip {
+                channel,
+                id,
+                edit_url,
+                attempt,
+            } => check_clip(helix, channel, id.clone(), edit_url.clone(), *attempt)
+                .await
+                .map(Some),
+            HelixTask::CreatePoll {
+                channel,
+                title,
+                choices,
+                duration,
+            } => create_poll(helix, channel, title, choices, *duration)
+                .await
+                .map(Some),
+            HelixTask::CheckPoll {
+                channel,
+                id,
+                attempt,
+            } => check_poll(helix, channel, id, *attempt).await,
+            HelixTask::Subscribe {
+                channel,
+                session_id,
+                subscription,
+            } => subscribe(helix, channel, session_id, *subscription)
+                .await
+                .map(|_| None),
+            HelixTask::FulfillRedemption {
+                channel,
+                reward_id,
+                id,
+            } => fulfill_redemption(helix, channel, reward_id, id)
+                .await
+                .map(|_| None),
+            HelixTask::Announce {
+                channel,
+                text,
+                color,
+                overflow,
+            } => send_announcement(helix, channel, text, *color, *overflow).await,
+            HelixTask::Whisper {
+                channel,
+                login,
+                text,
+                fallback,
+                attempt,
+            } => send_whisper(helix, channel, login, text, fallback, *attempt).await,
+            HelixTask::Shield { channel, active } => {
+                shield(helix, channel, *active).await.map(Some)
+            }
+            HelixTask::DeleteMessage {
+                channel,
+                message_id,
+            } => {
+                tracing::info!(channel, message_id, "deleting a message");
+                MODERATION_ACTIONS.inc(&["delete"]);
+                delete_message(helix, channel, message_id)
+                    .await
+                    .map(|_| None)
+            }
+            HelixTask::Ban {
+                channel,
+                login,
+                duration,
+                reason,
+                answer,
+            } => {
+                let seconds = duration.map(|duration| duration.as_secs());
+                tracing::info!(channel, login, seconds, reason, "banning");
+                let action = match duration {
+                    Some(_) => "timeout",
+                    None => "ban",
+                };
+                MODERATION_ACTIONS.inc(&[action]);
+                ban_text(helix, channel, login, *duration, reason)
+                    .await
+                    .map(|text| match answer {
+                        true => Some(send(channel, text)),
+                        false => {
+                            tracing::info!(channel, login, text, "not answering in chat");
+                            None
+                        }
+                    })
+            }
+            HelixTask::Unban { channel, login } => {
+                unban_text(helix, channel, login).await.map(answer)
+            }
+            HelixTask::Role {
+                channel,
+                login,
+                role,
+                add,
+            } => role_text(helix, channel, login, *role, *add)
+                .await
+                .map(answer),
+            HelixTask::SlowMode { channel, wait } => {
+                slow_mode(helix, channel, *wait).await.map(|_| None)
+            }
+            HelixTask::ChatMode { channel, setting } => {
+                chat_mode(helix, channel, *setting).await.map(Some)
+            }
+            HelixTask::RaffleFollower {
+                channel,
+                login,
+                name,
+                tickets,
+                raffle,
+            } => follows(helix, channel, login).await.map(|follows| {
+                follows.then(|| ChatBotCommand::TimedCallback {
+                    duration: Duration::ZERO,
+                    event: ChatBotEvent::Internal(InternalEvent::RaffleEntry {
+                        channel: channel.clone(),
+                        id: *raffle,
+                        login: login.clone(),
+                        name: name.clone(),
+                        tickets: *tickets,
+                    }),
+                })
+            }),
+            HelixTask::SendIfLive { channel, text } => helix
+                .stream(channel)
+                .await
+                .map(|stream| stream.and_then(|_| answer(text.clone()))),
+            HelixTask::LiveStatus { channel } => helix.stream(channel).await.map(|stream| {
+                Some(ChatBotCommand::TimedCallback {
+                    duration: Duration::ZERO,
+                    event: ChatBotEvent::LiveStatus {
+                        channel: channel.clone(),
+                        live: stream.is_some(),
+                    },
+                })
+            }),
+        };
+        match command {
+            Ok(command) => command,
+            Err(error) => {
+                tracing::warn!(task = ?self, %error, "helix task failed");
+                // chat doesn't need to know about what nobody asked for
+                if let HelixTask::DeleteMessage { .. }
+                | HelixTask::Ban { answer: false, .. }
+                | HelixTask::SlowMode { .. }
+                | HelixTask::RaffleFollower { .. }
+                | HelixTask::SendIfLive { .. }
+                | HelixTask::LiveStatus { .. }
+                | HelixTask::CheckPoll { .. }
+                | HelixTask::Subscribe { .. }
+                | HelixTask::FulfillRedemption { .. } = self
+                {
+                    return None;
+                }
+                let apology = match error {
+                    HelixError::MissingScope(_) => NOT_ALLOWED_MESSAGE,
+                    _ => HELIX_FAILED_MESSAGE,
+                };
+                Some(send(&channel, apology.to_owned()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+
+    fn text(command: Option<ChatBotCommand>) -> String {
+        match command {
+            Some(ChatBotCommand::SendMessage { text, .. }) => text,
+            _ => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn uptime_is_in_hours_and_minutes() {
+        let minutes = |minutes: u64| uptime(Duration::from_secs(minutes * 60 + 59));
+        assert_eq!(minutes(0), "less than a minute");
+        assert_eq!(minutes(1), "1 minute");
+        assert_eq!(minutes(60), "1 hour");
+        assert_eq!(minutes(133), "2 hours 13 minutes");
+        assert_eq!(minutes(61), "1 hour 1 minute");
+    }
+
+    #[test]
+    fn followage_is_in_years_months_and_days() {
+        let date = |year, month, day| Date { year, month, day };
+        let today = date(2026, 10, 14);
+        assert_eq!(followage(date(2024, 9, 13), today), "2 years 1 month 1 day");
+        assert_eq!(followage(date(2026, 8, 14), today), "2 months");
+        assert_eq!(followage(today, today), "less than a day");
+    }
+
+    const USERS: [(&str, u16, &str); 2] = [
+        (
+            "/users?login=carkhy",
+            200,
+            r#"{"data":[{"id":"2","login":"carkhy","display_name":"Carkhy"}]}"#,
+        ),
+        (
+            "/users?login=captaincallback",
+            200,
+            r#"{"data":[{"id":"1","login":"captaincallback","display_name":"CaptainCallback"}]}"#,
+        ),
+    ];
+    // the channel and the user asked for together
+    const BOTH: (&str, u16, &str) = (
+        "/users?login=captaincallback&login=carkhy",
+        200,
+        r#"{"data":[{"id":"1","login":"captaincallback","display_name":"CaptainCallback"},{"id":"2","login":"carkhy","display_name":"Carkhy"}]}"#,
+    );
+
+    #[tokio::test]
+    async fn announcements_without_the_scope_go_to_chat() {
+        let announcement = "/chat/announcements?broadcaster_id=1&moderator_id=3";
+        let mut helix = server(vec![
+            USERS[1],
+            (
+                "/users?login=botanist",
+                200,
+                r#"{"data":[{"id":"3","login":"botanist","display_name":"Botanist"}]}"#,
+            ),
+            (announcement, 204, ""),
+            (
+                announcement,
+                403,
+                r#"{"message":"The user is not a moderator"}"#,
+            ),
+        ]);
+        // the first part is announced, the rest is sent as chat lines
+        let text = "word ".repeat(150);
+        let task = announce(
+            "captaincallback",
+            text,
+            AnnouncementColor::Green,
+            Overflow::Split,
+        );
+        let ChatBotCommand::Helix(task) = task else {
+            panic!("{:?}", task);
+        };
+        match task.run(&mut helix).await {
+            Some(ChatBotCommand::MultipleCommands(lines)) => match &lines[..] {
+                [ChatBotCommand::SendMessage { text, .. }] => assert!(text.ends_with("(2/2)")),
+                lines => panic!("{:?}", lines),
+            },
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[tokio::test]
+    async fn limited_whispers_wait_and_blocked_ones_go_to_chat() {
+        let whisper = "/whispers?from_user_id=3&to_user_id=2";
+        let mut helix = server(vec![
+            (
+                "/users?login=botanist",
+                200,
+                r#"{"data":[{"id":"3","login":"botanist","display_name":"Botanist"}]}"#,
+            ),
+            USERS[0],
+            (whisper, 429, r#"{"message":"Whisper rate limit exceeded"}"#),
+            (
+                whisper,
+                403,
+                r#"{"message":"The recipient's settings prevent this sender from whispering them."}"#,
+            ),
+        ]);
+        let task = |fallback: Option<&str>, attempt| HelixTask::Whisper {
+            channel: "captaincallback".to_owned(),
+            login: "carkhy".to_owned(),
+            text: "You won!".to_owned(),
+            fallback: fallback.map(str::to_owned),
+            attempt,
+        };
+        match task(None, 2).run(&mut helix).await {
+            Some(ChatBotCommand::TimedCallback {
+                duration,
+                event: ChatBotEvent::Internal(InternalEvent::WhisperRetry { attempt, .. }),
+            }) => assert_eq!((duration, attempt), (WHISPER_RETRY_DELAY * 2, 3)),
+            command => panic!("{:?}", command),
+        }
+        assert_eq!(
+            text(task(Some("@Carkhy, you won!"), 3).run(&mut helix).await),
+            "@Carkhy, you won!"
+        );
+    }
+
+    #[test]
+    fn tasks_name_the_users_they_look_up() {
+        let unban = HelixTask::Unban {
+            channel: "captaincallback".to_owned(),
+            login: "carkhy".to_owned(),
+        };
+        assert_eq!(unban.logins(), ["captaincallback", "carkhy"]);
+        let uptime = HelixTask::Uptime {
+            channel: "captaincallback".to_owned(),
+        };
+        assert_eq!(uptime.logins(), ["captaincallback"]);
+    }
+
+    fn followage_of(login: &str, own: bool) -> HelixTask {
+        HelixTask::Followage {
+            channel: "captaincallback".to_owned(),
+            login: login.to_owned(),
+            own,
+        }
+    }
+
+    #[tokio::test]
+    async fn followage_tells_who_follows() {
+        let mut answers = vec![BOTH];
+        answers.push((
+            "/channels/followers?broadcaster_id=1&user_id=2",
+            200,
+            r#"{"total":0,"data":[],"pagination":{}}"#,
+        ));
+        answers.push(("/users?login=nobody", 200, r#"{"data":[]}"#));
+        let mut helix = server(answers);
+        assert_eq!(
+            text(followage_of("carkhy", true).run(&mut helix).await),
+            "You don't follow CaptainCallback."
+        );
+        assert_eq!(
+            text(followage_of("Carkhy", false).run(&mut helix).await),
+            "Carkhy doesn't follow CaptainCallback."
+        );
+        assert_eq!(
+            text(followage_of("nobody", false).run(&mut helix).await),
+            "There is no twitch user named nobody."
+        );
+    }
+
+    #[tokio::test]
+    async fn followage_needs_a_moderator_token() {
+        let mut answers = vec![BOTH];
+        answers.push((
+            "/channels/followers",
+            403,
+            r#"{"error":"Forbidden","status":403,"message":"The user is not a moderator"}"#,
+        ));
+        let mut helix = server(answers);
+        assert_eq!(
+            text(followage_of("carkhy", true).run(&mut helix).await),
+            NOT_ALLOWED_MESSAGE
+        );
+    }
+
+    #[tokio::test]
+    async fn shoutouts_name_the_last_game() {
+        let mut answers = vec![
+            BOTH,
+            (
+                "/channels?broadcaster_id=2",
+                200,
+                r#"{"data":[{"broadcaster_login":"carkhy","broadcaster_name":"Carkhy","game_name":"Celeste","title":"Any%"}]}"#,
+            ),
+        ];
+        answers.push((
+            "/users?login=botanist",
+            200,
+            r#"{"data":[{"id":"3","login":"botanist","display_name":"Botanist"}]}"#,
+        ));
+        // without the scope the chat message is sent anyway
+        answers.push((
+            "/chat/shoutouts?from_broadcaster_id=1&to_broadcaster_id=2&moderator_id=3",
+            403,
+            r#"{"message":"The user is not a moderator"}"#,
+        ));
+        answers.push(("/users?login=nobody", 200, r#"{"data":[]}"#));
+        let mut helix = server(answers);
+        let shoutout = |login: &str| HelixTask::Shoutout {
+            channel: "captaincallback".to_owned(),
+            login: login.to_owned(),
+        };
+        assert_eq!(
+            text(shoutout("carkhy").run(&mut helix).await),
+            "Go check out Carkhy at https://twitch.tv/carkhy — they were last playing Celeste!"
+        );
+        assert_eq!(
+            text(shoutout("nobody").run(&mut helix).await),
+            "Sorry, I couldn't find the user nobody."
+        );
+    }
+
+    fn set_game(game: &str) -> HelixTask {
+        HelixTask::SetGame {
+            channel: "captaincallback".to_owned(),
+            game: game.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn games_are_looked_up_before_changing() {
+        let mut helix = server(vec![
+            (
+                "/games?name=celeste",
+                200,
+                r#"{"data":[{"id":"504461","name":"Celeste","box_art_url":""}]}"#,
+            ),
+            USERS[1],
+            ("PATCH /channels?broadcaster_id=1", 204, ""),
+            ("/games?name=Celest", 200, r#"{"data":[]}"#),
+            (
+                "/search/categories?query=Celest&first=5",
+                200,
+                r#"{"data":[{"id":"504461","name":"Celeste","box_art_url":""},{"id":"1","name":"Celeste Classic","box_art_url":""}]}"#,
+            ),
+            ("/games?name=Nothing", 200, r#"{"data":[]}"#),
+            ("/search/categories", 200, r#"{"data":[]}"#),
+        ]);
+        assert_eq!(
+            text(set_game("celeste").run(&mut helix).await),
+            "The game is now Celeste."
+        );
+        assert_eq!(
+            text(set_game("Celest").run(&mut helix).await),
+            "There is no game called Celest. Did you mean: Celeste, Celeste Classic?"
+        );
+        assert_eq!(
+            text(set_game("Nothing").run(&mut helix).await),
+            "There is no game called Nothing."
+        );
+    }
+
+    #[tokio::test]
+    async fn titles_need_the_broadcasters_token() {
+        let mut helix = server(vec![
+            USERS[1],
+            ("PATCH /channels", 204, ""),
+            (
+                "PATCH /channels",
+                401,
+                r#"{"error":"Unauthorized","status":401,"message":"Missing scope: channel:manage:broadcast"}"#,
+            ),
+        ]);
+        let set_title = |title: &str| HelixTask::SetTitle {
+            channel: "captaincallback".to_owned(),
+            title: title.to_owned(),
+        };
+        assert_eq!(
+            text(set_title("New speedrun attempts").run(&mut helix).await),
+            "The title is now: New speedrun attempts"
+        );
+        assert_eq!(
+            text(set_title("Chatting").run(&mut helix).await),
+            NOT_ALLOWED_MESSAGE
+        );
+    }
+
+    const CLIP_CREATED: (&str, u16, &str) = (
+        "POST /clips?broadcaster_id=1",
+        202,
+        r#"{"data":[{"id":"FiveWordsForClip","edit_url":"https://clips.twitch.tv/FiveWordsForClip/edit"}]}"#,
+    );
+    const CLIP_MADE: (&str, u16, &str) = (
+        "/clips?id=FiveWordsForClip",
+        200,
+        r#"{"data":[{"id":"FiveWordsForClip","url":"https://clips.twitch.tv/FiveWordsForClip"}]}"#,
+    );
+    const CLIP_PENDING: (&str, u16, &str) = ("/clips?id=FiveWordsForClip", 200, r#"{"data":[]}"#);
+
+    fn clip() -> HelixTask {
+        HelixTask::Clip {
+            channel: "captaincallback".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn clips_are_linked_once_made() {
+        let mut helix = server(vec![USERS[1], CLIP_CREATED, CLIP_MADE]);
+        assert_eq!(
+            text(clip().run(&mut helix).await),
+            "Here is the clip: https://clips.twitch.tv/FiveWordsForClip"
+        );
+    }
+
+    #[tokio::test]
+    async fn clips_are_asked_for_again_until_made() {
+        let mut helix = server(vec![
+            USERS[1],
+            CLIP_CREATED,
+            CLIP_PENDING,
+            CLIP_PENDING,
+            CLIP_MADE,
+        ]);
+        let mut task = clip();
+        for attempt in 2..=3 {
+            let Some(ChatBotCommand::TimedCallback { duration, event }) =
+                task.run(&mut helix).await
+            else {
+                panic!("no callback for attempt {}", attempt);
+            };
+            assert_eq!(duration, CLIP_POLL_DELAY);
+            let ChatBotEvent::Internal(InternalEvent::ClipPending {
+                channel,
+                id,
+                edit_url,
+                attempt: next,
+            }) = event
+            else {
+                panic!("{:?}", event);
+            };
+            assert_eq!(next, attempt);
+            task = HelixTask::CheckClip {
+                channel,
+                id,
+                edit_url,
+                attempt,
+            };
+        }
+        assert_eq!(
+            text(task.run(&mut helix).await),
+            "Here is the clip: https://clips.twitch.tv/FiveWordsForClip"
+        );
+    }
+
+    #[tokio::test]
+    async fn unfinished_clips_are_linked_to_their_editor() {
+        let mut helix = server(vec![CLIP_PENDING]);
+        let task = HelixTask::CheckClip {
+            channel: "captaincallback".to_owned(),
+            id: "FiveWordsForClip".to_owned(),
+            edit_url: "https://clips.twitch.tv/FiveWordsForClip/edit".to_owned(),
+            attempt: CLIP_POLLS,
+        };
+        assert_eq!(
+            text(task.run(&mut helix).await),
+            "The clip is still being made, find it here: https://clips.twitch.tv/FiveWordsForClip/edit"
+        );
+    }
+
+    #[tokio::test]
+    async fn offline_streams_cant_be_clipped() {
+        let mut helix = server(vec![
+            USERS[1],
+            (
+                "POST /clips?broadcaster_id=1",
+                404,
+                r#"{"error":"Not Found","status":404,"message":"Clipping is not possible for an offline channel."}"#,
+            ),
+        ]);
+        assert_eq!(
+            text(clip().run(&mut helix).await),
+            "Can't clip an offline stream."
+        );
+    }
+
+    #[tokio::test]
+    async fn shield_mode_tells_who_changed_it() {
+        let botanist = (
+            "/users?login=botanist",
+            200,
+            r#"{"data":[{"id":"3","login":"botanist","display_name":"Botanist"}]}"#,
+        );
+        let mut helix = server(vec![
+            USERS[1],
+            botanist,
+            (
+                "PUT /moderation/shield_mode?broadcaster_id=1&moderator_id=3",
+                200,
+                r#"{"data":[{"is_active":true,"moderator_id":"2","moderator_login":"carkhy","moderator_name":"Carkhy","last_activated_at":"2022-07-26T17:16:03.123Z"}]}"#,
+            ),
+            (
+                "GET /moderation/shield_mode?broadcaster_id=1&moderator_id=3",
+                200,
+                r#"{"data":[{"is_active":false,"moderator_id":"","moderator_login":"","moderator_name":"","last_activated_at":""}]}"#,
+            ),
+            (
+                "PUT /moderation/shield_mode",
+                403,
+                r#"{"error":"Forbidden","status":403,"message":"The user in moderator_id is not one of the broadcaster's moderators."}"#,
+            ),
+        ]);
+        let shield = |active| HelixTask::Shield {
+            channel: "captaincallback".to_owned(),
+            active,
+        };
+        assert!(matches!(
+            shield(Some(true)).run(&mut helix).await,
+            Some(ChatBotCommand::TimedCallback {
+                event: ChatBotEvent::ShieldMode {
+                    active: true,
+                    asked: true,
+                    ..
+                },
+                ..
+            })
+        ));
+        match shield(None).run(&mut helix).await {
+            Some(ChatBotCommand::MultipleCommands(commands)) => {
+                assert_eq!(
+                    text(commands.into_iter().next()),
+                    "Shield mode is off, it was never turned on."
+                )
+            }
+            command => panic!("{:?}", command),
+        }
+        assert_eq!(
+            text(shield(Some(false)).run(&mut helix).await),
+            "I can't use shield mode here, make me a moderator first."
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_modes_are_confirmed_and_mirrored() {
+        const SETTINGS: &str = "PATCH /chat/settings?broadcaster_id=1&moderator_id=3";
+        let settings = [
+            (
+                ChatSetting::Slow(Some(1)),
+                "Slow mode is on, 1 second between messages.",
+            ),
+            (ChatSetting::Slow(None), "Slow mode is off."),
+            (ChatSetting::EmoteOnly(true), "Emote-only mode is on."),
+            (
+                ChatSetting::Followers(Some(10)),
+                "Followers-only mode is on, for followers of 10 minutes or longer.",
+            ),
+            (ChatSetting::Followers(None), "Followers-only mode is off."),
+            (
+                ChatSetting::Subscribers(true),
+                "Subscribers-only mode is on.",
+            ),
+            (ChatSetting::UniqueChat(false), "Unique chat is off."),
+        ];
+        let mut answers = vec![
+            USERS[1],
+            (
+                "/users?login=botanist",
+                200,
+                r#"{"data":[{"id":"3","login":"botanist","display_name":"Botanist"}]}"#,
+            ),
+        ];
+        answers.extend(settings.iter().map(|_| (SETTINGS, 204, "")));
+        let mut helix = server(answers);
+        // what the bot knew before, twitch's ROOMSTATE echoes the same later
+        let mut mirror = RoomState {
+            channel: "captaincallback".to_owned(),
+            emote_only: Some(false),
+            followers_only: Some(-1),
+            r9k: Some(true),
+            slow: Some(30),
+            subs_only: Some(false),
+        };
+        for (setting, confirmation) in settings {
+            let task = HelixTask::ChatMode {
+                channel: "captaincallback".to_owned(),
+                setting,
+            };
+            let Some(ChatBotCommand::MultipleCommands(mut commands)) = task.run(&mut helix).await
+            else {
+                panic!("{:?}", setting);
+            };
+            match commands.pop() {
+                Some(ChatBotCommand::TimedCallback {
+                    duration: Duration::ZERO,
+                    event: ChatBotEvent::RoomState(delta),
+                }) => mirror.update(delta),
+                command => panic!("{:?}", command),
+            }
+            assert_eq!(text(commands.pop()), confirmation);
+        }
+        let expected = RoomState {
+            channel: "captaincallback".to_owned(),
+            emote_only: Some(true),
+            followers_only: Some(-1),
+            r9k: Some(false),
+            slow: Some(0),
+            subs_only: Some(true),
+        };
+        assert_eq!(mirror, expected);
+    }
+
+    #[tokio::test]
+    async fn roles_answer_twitchs_refusals_in_words() {
+        const VIPS: &str = "/channels/vips?broadcaster_id=1&user_id=2";
+        const MODS: &str = "/moderation/moderators?broadcaster_id=1&user_id=2";
+        let refused = |path, status, message| (path, status, message);
+        let mut helix = server(vec![
+            BOTH,
+            ("POST /channels/vips", 204, ""),
+            refused(
+                VIPS,
+                409,
+                r#"{"error":"Conflict","status":409,"message":"The broadcaster doesn't have available VIP slots"}"#,
+            ),
+            refused(
+                VIPS,
+                422,
+                r#"{"error":"Unprocessable Entity","status":422,"message":"The user in user_id is already a VIP."}"#,
+            ),
+            refused(
+                VIPS,
+                422,
+                r#"{"error":"Unprocessable Entity","status":422,"message":"The user in user_id is a moderator. To make them a VIP, you must first remove them as a moderator."}"#,
+            ),
+            refused(
+                VIPS,
+                425,
+                r#"{"error":"Too Early","status":425,"message":"The broadcaster must complete the Build a Community requirement before they may assign VIPs."}"#,
+            ),
+            refused(
+                VIPS,
+                429,
+                r#"{"error":"Too Many Requests","status":429,"message":"The broadcaster exceeded the number of VIP that they may add within a 10-second window."}"#,
+            ),
+            refused(
+                VIPS,
+                422,
+                r#"{"error":"Unprocessable Entity","status":422,"message":"The user in user_id is not a VIP."}"#,
+            ),
+            ("POST /moderation/moderators", 204, ""),
+            refused(
+                MODS,
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"The user in user_id is already a moderator."}"#,
+            ),
+            refused(
+                MODS,
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"The user in user_id is banned."}"#,
+            ),
+            refused(
+                MODS,
+                422,
+                r#"{"error":"Unprocessable Entity","status":422,"message":"The user in user_id is a VIP. To make them a moderator, you must first remove them as a VIP."}"#,
+            ),
+            refused(
+                MODS,
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"The user in user_id is not one of the broadcaster's moderators."}"#,
+            ),
+            refused(
+                MODS,
+                401,
+                r#"{"error":"Unauthorized","status":401,"message":"Missing scope: channel:manage:moderators"}"#,
+            ),
+        ]);
+        let answers = [
+            (Role::Vip, true, "Carkhy is a VIP now."),
+            (Role::Vip, true, "Couldn't make Carkhy a VIP, the channel has reached its limit of VIPs."),
+            (Role::Vip, true, "Carkhy is a VIP already."),
+            (Role::Vip, true, "Carkhy is a moderator, they can't be a VIP until they are unmodded."),
+            (Role::Vip, true, "The channel needs the Build a Community achievement before it can have VIPs."),
+            (Role::Vip, true, "Twitch is still busy with the last change, try again in a moment."),
+            (Role::Vip, false, "Carkhy isn't a VIP."),
+            (Role::Moderator, true, "Carkhy is a moderator now."),
+            (Role::Moderator, true, "Carkhy is a moderator already."),
+            (Role::Moderator, true, "Carkhy is banned, they can't be a moderator."),
+            (Role::Moderator, true, "Carkhy is a VIP, they can't be a moderator until they are unvipped."),
+            (Role::Moderator, false, "Carkhy isn't a moderator."),
+            (Role::Moderator, false, "Twitch moderators need the broadcaster's token with the scope channel:manage:moderators, authorize the bot again as the broadcaster."),
+        ];
+        for (role, add, answer) in answers {
+            let task = HelixTask::Role {
+                channel: "captaincallback".to_owned(),
+                login: "Carkhy".to_owned(),
+                role,
+                add,
+            };
+            assert_eq!(text(task.run(&mut helix).await), answer);
+        }
+    }
+
+    #[tokio::test]
+    async fn commercials_tell_when_the_next_can_run() {
+        let mut helix = server(vec![
+            USERS[1],
+            (
+                "POST /channels/commercial",
+                200,
+                r#"{"data":[{"length":60,"message":"","retry_after":480}]}"#,
+            ),
+            (
+                "POST /channels/commercial",
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"To start a commercial, the broadcaster must be a partner or affiliate"}"#,
+            ),
+            (
+                "POST /channels/commercial",
+                429,
+                r#"{"error":"Too Many Requests","status":429,"message":"The broadcaster may not run another commercial until the cooldown period expires"}"#,
+            ),
+        ]);
+        let commercial = || HelixTask::Commercial {
+            channel: "captaincallback".to_owned(),
+            length: 60,
+        };
+        assert_eq!(
+            text(commercial().run(&mut helix).await),
+            "Running a 60 second commercial. The next one can run in 8 minutes."
+        );
+        assert_eq!(
+            text(commercial().run(&mut helix).await),
+            "Couldn't run the commercial: To start a commercial, the broadcaster must be a partner or affiliate"
+        );
+        assert_eq!(
+            text(commercial().run(&mut helix).await),
+            "The last commercial was too recent: The broadcaster may not run another commercial until the cooldown period expires"
+        );
+    }
+
+    #[tokio::test]
+    async fn markers_tell_their_position_or_twitchs_reason() {
+        let mut helix = server(vec![
+            USERS[1],
+            (
+                "POST /streams/markers",
+                200,
+                r#"{"data":[{"id":"123","created_at":"2021-11-22T22:10:27Z","description":"Clutch","position_seconds":5025}]}"#,
+            ),
+            (
+                "POST /streams/markers",
+                404,
+                r#"{"error":"Not Found","status":404,"message":"The user is not streaming live"}"#,
+            ),
+        ]);
+        let marker = || HelixTask::Marker {
+            channel: "captaincallback".to_owned(),
+            description: "Clutch".to_owned(),
+        };
+        assert_eq!(
+            text(marker().run(&mut helix).await),
+            "Marker created at 1:23:45"
+        );
+        assert_eq!(
+            text(marker().run(&mut helix).await),
+            "Couldn't create the marker: The user is not streaming live"
+        );
+    }
+
+    #[tokio::test]
+    async fn uptime_is_answered_live_or_offline() {
+        let mut helix = server(vec![
+            (
+                "/streams?user_login=captaincallback",
+                200,
+                r#"{"data":[{"user_login":"captaincallback","game_name":"Celeste","title":"Speedruns","started_at":"2021-11-22T20:46:42Z"}]}"#,
+            ),
+            ("/streams?user_login=carkhy", 200, r#"{"data":[]}"#),
+            ("/streams?user_login=viewer", 500, r#"{"message":"Oops"}"#),
+        ]);
+        let uptime = |channel: &str| HelixTask::Uptime {
+            channel: channel.to_owned(),
+        };
+        let live = text(uptime("captaincallback").run(&mut helix).await);
+        assert!(live.starts_with("Stream has been live for "), "{}", live);
+        assert_eq!(
+            text(uptime("carkhy").run(&mut helix).await),
+            "carkhy is offline right now."
+        );
+        assert_eq!(
+            text(uptime("viewer").run(&mut helix).await),
+            HELIX_FAILED_MESSAGE
+        );
+
+        // the answers are cached, twitch isn't asked again
+        let greeting = |channel: &str| HelixTask::SendIfLive {
+            channel: channel.to_owned(),
+            text: "Welcome!".to_owned(),
+        };
+        assert_eq!(
+            text(greeting("captaincallback").run(&mut helix).await),
+            "Welcome!"
+        );
+        assert!(greeting("carkhy").run(&mut helix).await.is_none());
+        assert!(greeting("viewer").run(&mut helix).await.is_none());
+        let live = |channel: &str| HelixTask::LiveStatus {
+            channel: channel.to_owned(),
+        };
+        for (channel, expected) in [("captaincallback", true), ("carkhy", false)] {
+            match live(channel).run(&mut helix).await {
+                Some(ChatBotCommand::TimedCallback {
+                    event: ChatBotEvent::LiveStatus { live, .. },
+                    ..
+                }) => assert_eq!(live, expected, "{}", channel),
+                other => panic!("{:?}", other),
+            }
+        }
+        assert!(live("viewer").run(&mut helix).await.is_none());
+    }
+
+    fn create_poll() -> HelixTask {
+        HelixTask::CreatePoll {
+            channel: "captaincallback".to_owned(),
+            title: "Which boss next?".to_owned(),
+            choices: vec!["Gael".to_owned(), "Nameless King".to_owned()],
+            duration: 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn twitch_polls_are_announced_after_their_end() {
+        let poll = |status| {
+            format!(
+                r#"{{"data":[{{"id":"ed961efd","title":"Which boss next?","choices":[{{"id":"1","title":"Gael","votes":3}},{{"id":"2","title":"Nameless King","votes":1}}],"status":"{}","duration":60}}]}}"#,
+                status
+            )
+        };
+        let (active, completed) = (poll("ACTIVE").leak(), poll("COMPLETED").leak());
+        let mut helix = server(vec![
+            USERS[1],
+            ("POST /polls", 200, &*active),
+            ("/polls?broadcaster_id=1&id=ed961efd", 200, &*active),
+            ("/polls?broadcaster_id=1&id=ed961efd", 200, &*completed),
+        ]);
+        let Some(ChatBotCommand::MultipleCommands(commands)) = create_poll().run(&mut helix).await
+        else {
+            panic!("the poll wasn't started");
+        };
+        let mut commands = commands.into_iter();
+        assert_eq!(
+            text(commands.next()),
+            "The poll is on stream: Which boss next? It ends in 60 seconds."
+        );
+        let mut pending = commands.next();
+        for attempt in 1..=2 {
+            let Some(ChatBotCommand::TimedCallback {
+                event: ChatBotEvent::Internal(InternalEvent::TwitchPollPending { channel, id, .. }),
+                ..
+            }) = pending
+            else {
+                panic!("no callback for attempt {}: {:?}", attempt, pending);
+            };
+            let task = HelixTask::CheckPoll {
+                channel,
+                id,
+                attempt,
+            };
+            pending = task.run(&mut helix).await;
+        }
+        assert_eq!(
+            text(pending),
+            "The poll is over: Which boss next? Gael: 3 (75%), Nameless King: 1 (25%) - 4 voters"
+        );
+    }
+
+    #[tokio::test]
+    async fn twitch_polls_need_the_broadcasters_token() {
+        let mut helix = server(vec![
+            USERS[1],
+            (
+                "POST /polls",
+                401,
+                r#"{"error":"Unauthorized","status":401,"message":"Missing scope: channel:manage:polls"}"#,
+            ),
+            (
+                "POST /polls",
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"The broadcaster already has a poll that's running"}"#,
+            ),
+        ]);
+        assert_eq!(
+            text(create_poll().run(&mut helix).await),
+            setup_message("polls", "channel:manage:polls")
+        );
+        assert_eq!(
+            text(create_poll().run(&mut helix).await),
+            "Couldn't start the poll: The broadcaster already has a poll that's running"
+        );
+    }
+
+    // the prediction with the points and users of Yes and No
+    fn prediction(status: &str, yes: (u64, u64), no: (u64, u64)) -> &'static str {
+        let winner = match status {
+            "RESOLVED" => r#""o1""#,
+            _ => "null",
+        };
+        let predictors = match status {
+            "RESOLVED" => {
+                r#"[{"user_name":"Carkhy","channel_points_used":500,"channel_points_won":625}]"#
+            }
+            _ => "null",
+        };
+        format!(
+            r#"{{"data":[{{"id":"p1","title":"Will we win?","winning_outcome_id":{},"outcomes":[{{"id":"o1","title":"Yes","users":{},"channel_points":{},"top_predictors":{}}},{{"id":"o2","title":"No","users":{},"channel_points":{},"top_predictors":null}}],"prediction_window":120,"status":"{}"}}]}}"#,
+            winner, yes.1, yes.0, predictors, no.1, no.0, status
+        )
+        .leak()
+    }
+
+    #[tokio::test]
+    async fn predictions_are_remembered_until_they_end() {
+        let remembered = "/predictions?broadcaster_id=1&id=p1";
+        let locked = prediction("LOCKED", (1200, 5), (300, 1));
+        let mut helix = server(vec![
+            USERS[1],
+            (
+                "POST /predictions",
+                200,
+                prediction("ACTIVE", (0, 0), (0, 0)),
+            ),
+            (remembered, 200, prediction("ACTIVE", (1200, 5), (300, 1))),
+            ("PATCH /predictions", 200, locked),
+            (remembered, 200, locked),
+            (remembered, 200, locked),
+            (
+                "PATCH /predictions",
+                200,
+                prediction("RESOLVED", (1200, 5), (300, 1)),
+            ),
+            (
+                "/predictions?broadcaster_id=1&first=1",
+                200,
+                prediction("RESOLVED", (1200, 5), (300, 1)),
+            ),
+            (
+                "POST /predictions",
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"prediction event already active"}"#,
+            ),
+        ]);
+        let task = |action| HelixTask::Prediction {
+            channel: "captaincallback".to_owned(),
+            action,
+        };
+        let start = || PredictionAction::Start {
+            title: "Will we win?".to_owned(),
+            outcomes: vec!["Yes".to_owned(), "No".to_owned()],
+            window: 120,
+        };
+        assert_eq!(
+            text(task(start()).run(&mut helix).await),
+            "Prediction: Will we win? 1. Yes, 2. No. Predict within 120 seconds!"
+        );
+        assert_eq!(
+            text(task(PredictionAction::Lock).run(&mut helix).await),
+            "Predictions are locked: Yes 1200 points from 5 users, No 300 points from 1 user."
+        );
+        assert_eq!(
+            text(task(PredictionAction::Resolve(3)).run(&mut helix).await),
+            "There is no outcome 3, it has 2."
+        );
+        assert_eq!(
+            text(task(PredictionAction::Resolve(1)).run(&mut helix).await),
+            "Yes wins! 5 users share 1500 points, the biggest winner is Carkhy (+625)."
+        );
+        assert_eq!(
+            text(task(PredictionAction::Cancel).run(&mut helix).await),
+            "No prediction is running."
+        );
+        assert_eq!(
+            text(task(start()).run(&mut helix).await),
+            "Twitch refused: prediction event already active"
+        );
+    }
+}
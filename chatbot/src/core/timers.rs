@@ -0,0 +1,216 @@
+use crate::config::{AnnouncementColor, TimerConfig};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How often the timers check whether one is due. At most one timer of a channel fires
+/// per tick, so timers with the same interval are sent a tick apart.
+pub const TIMER_TICK: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Timer {
+    name: String,
+    text: String,
+    interval: Duration,
+    min_messages: usize,
+    announce: Option<AnnouncementColor>,
+    // chat lines received since the timer fired, or since the start
+    messages: usize,
+    last_fired: Instant,
+}
+
+impl Timer {
+    fn is_due(&self, now: Instant) -> bool {
+        now >= self.last_fired + self.interval && self.messages >= self.min_messages
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChannelTimers {
+    timers: Vec<Timer>,
+    // the timer checked first on the next tick, the one after the last that fired
+    next: usize,
+    paused: bool,
+    // only with timers waiting for the stream
+    offline: bool,
+}
+
+/// The configured timers of every channel. Time is passed in, so that tests don't wait.
+#[derive(Debug, Default)]
+pub struct Timers {
+    // by channel name without '#'
+    channels: HashMap<String, ChannelTimers>,
+    only_live: bool,
+}
+
+pub type SharedTimers = Rc<RefCell<Timers>>;
+
+impl Timers {
+    /// None of them fires before its first interval passed.
+    pub fn new(configs: &[TimerConfig], now: Instant) -> Self {
+        let mut timers = Self::default();
+        for config in configs {
+            let channel = config.channel.trim_start_matches('#').to_lowercase();
+            timers
+                .channels
+                .entry(channel)
+                .or_default()
+                .timers
+                .push(Timer {
+                    name: config.name.clone(),
+                    text: config.text.clone(),
+                    interval: Duration::from_secs(config.interval),
+                    min_messages: config.min_messages,
+                    announce: config.announce,
+                    messages: 0,
+                    last_fired: now,
+                });
+        }
+        timers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// A chat line was received in the channel.
+    pub fn message(&mut self, channel: &str) {
+        if let Some(channel) = self.channels.get_mut(channel) {
+            for timer in &mut channel.timers {
+                timer.messages += 1;
+            }
+        }
+    }
+
+    /// False if the channel has no timers.
+    pub fn pause(&mut self, channel: &str, paused: bool) -> bool {
+        match self.channels.get_mut(channel) {
+            Some(channel) => {
+                channel.paused = paused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The timers wait until the channel is known to be live.
+    pub fn wait_for_live(&mut self) {
+        self.only_live = true;
+        for channel in self.channels.values_mut() {
+            channel.offline = true;
+        }
+    }
+
+    /// Whether the channel is live, only counts for timers waiting for it.
+    pub fn live_status(&mut self, channel: &str, live: bool) {
+        if !self.only_live {
+            return;
+        }
+        if let Some(channel) = self.channels.get_mut(channel) {
+            channel.offline = !live;
+        }
+    }
+
+    /// The channel, text and announcement color of each timer firing now, at most one per
+    /// channel.
+    pub fn tick(&mut self, now: Instant) -> Vec<(String, String, Option<AnnouncementColor>)> {
+        let mut fired = Vec::new();
+        for (name, channel) in &mut self.channels {
+            if channel.paused || channel.offline {
+                continue;
+            }
+            let count = channel.timers.len();
+            let due = (0..count)
+                .map(|offset| (channel.next + offset) % count)
+                .find(|&index| channel.timers[index].is_due(now));
+            if let Some(index) = due {
+                let timer = &mut channel.timers[index];
+                tracing::debug!(channel = %name, timer = %timer.name, "timer fires");
+                timer.messages = 0;
+                timer.last_fired = now;
+                channel.next = (index + 1) % count;
+                fired.push((name.clone(), timer.text.clone(), timer.announce));
+            }
+        }
+        fired.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(name: &str, interval: u64, min_messages: usize) -> TimerConfig {
+        TimerConfig {
+            channel: "#captaincallback".to_owned(),
+            name: name.to_owned(),
+            text: format!("{} text", name),
+            interval,
+            min_messages,
+            announce: None,
+        }
+    }
+
+    fn minutes(start: Instant, minutes: u64) -> Instant {
+        start + Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn timers_wait_for_chat() {
+        let start = Instant::now();
+        let mut timers = Timers::new(&[timer("rules", 600, 3)], start);
+        assert!(timers.tick(minutes(start, 9)).is_empty());
+        // a dead chat gets no message
+        assert!(timers.tick(minutes(start, 10)).is_empty());
+        for _ in 0..3 {
+            timers.message("captaincallback");
+        }
+        timers.message("carkhy");
+        assert_eq!(
+            timers.tick(minutes(start, 11)),
+            vec![("captaincallback".to_owned(), "rules text".to_owned(), None)]
+        );
+        // the count starts again
+        timers.message("captaincallback");
+        assert!(timers.tick(minutes(start, 30)).is_empty());
+    }
+
+    #[test]
+    fn timers_take_turns() {
+        let start = Instant::now();
+        let configs = [timer("rules", 600, 0), timer("socials", 600, 0)];
+        let mut timers = Timers::new(&configs, start);
+        let texts = |fired: Vec<(String, String, Option<AnnouncementColor>)>| -> Vec<String> {
+            fired.into_iter().map(|(_, text, _)| text).collect()
+        };
+        assert_eq!(texts(timers.tick(minutes(start, 10))), vec!["rules text"]);
+        assert_eq!(texts(timers.tick(minutes(start, 11))), vec!["socials text"]);
+        assert!(timers.tick(minutes(start, 12)).is_empty());
+        assert_eq!(texts(timers.tick(minutes(start, 20))), vec!["rules text"]);
+        // socials is due as well, but waits its turn
+        assert_eq!(texts(timers.tick(minutes(start, 40))), vec!["socials text"]);
+        assert_eq!(texts(timers.tick(minutes(start, 41))), vec!["rules text"]);
+    }
+
+    #[test]
+    fn paused_timers_stay_quiet() {
+        let start = Instant::now();
+        let mut timers = Timers::new(&[timer("rules", 60, 0)], start);
+        assert!(timers.pause("captaincallback", true));
+        assert!(!timers.pause("carkhy", true));
+        assert!(timers.tick(minutes(start, 5)).is_empty());
+        timers.pause("captaincallback", false);
+        assert_eq!(timers.tick(minutes(start, 6)).len(), 1);
+        // and so do those of offline streams, when they wait for them
+        timers.live_status("captaincallback", false);
+        assert_eq!(timers.tick(minutes(start, 7)).len(), 1);
+        timers.wait_for_live();
+        assert!(timers.tick(minutes(start, 8)).is_empty());
+        timers.live_status("captaincallback", true);
+        assert_eq!(timers.tick(minutes(start, 9)).len(), 1);
+    }
+}
@@ -0,0 +1,531 @@
+use super::{
+    commands::{Args, Command, Context},
+    points::SharedPoints,
+    ChatBotCommand,
+};
+use crate::{
+    config::TriviaConfig,
+    connect::{ChatBotEvent, InternalEvent, Overflow, TextMessage, UserLevel},
+};
+use fastrand::Rng;
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The pause between an answered question and the next one.
+const TRIVIA_PAUSE: Duration = Duration::from_secs(5);
+const DEFAULT_ROUNDS: usize = 5;
+const MAX_ROUNDS: usize = 50;
+
+#[derive(Debug, Error)]
+pub enum TriviaError {
+    #[error("Could not read the trivia questions {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Invalid trivia questions in {path:?}: {reason}")]
+    Parse { path: PathBuf, reason: String },
+}
+
+/// One question of the file, any of the answers is right.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Question {
+    pub question: String,
+    pub answers: Vec<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub difficulty: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuestionFile {
+    questions: Vec<Question>,
+}
+
+/// The questions in a `.json` file, any other one is read as TOML.
+pub fn load_questions(path: &Path) -> Result<Vec<Question>, TriviaError> {
+    let text = fs::read_to_string(path).map_err(|source| TriviaError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    let parsed = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("json") => {
+            serde_json::from_str::<QuestionFile>(&text).map_err(|error| error.to_string())
+        }
+        _ => toml::from_str::<QuestionFile>(&text).map_err(|error| error.to_string()),
+    };
+    let questions = parsed
+        .map_err(|reason| TriviaError::Parse {
+            path: path.to_owned(),
+            reason,
+        })?
+        .questions;
+    match questions.iter().position(|question| {
+        !question
+            .answers
+            .iter()
+            .any(|answer| !normalize(answer).is_empty())
+    }) {
+        Some(index) => Err(TriviaError::Parse {
+            path: path.to_owned(),
+            reason: format!("question {} has no answer", index + 1),
+        }),
+        None => Ok(questions),
+    }
+}
+
+// "The U.S.A.!" and "the usa" are the same answer
+fn normalize(text: &str) -> String {
+    let kept: String = text
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect();
+    kept.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// the first letter of each word, "United States" is "U_____ S_____"
+fn hint(answer: &str) -> String {
+    let mut first = true;
+    answer
+        .chars()
+        .map(|c| {
+            let shown = match c.is_alphanumeric() {
+                true if first => c,
+                true => '_',
+                false => c,
+            };
+            first = c.is_whitespace();
+            shown
+        })
+        .collect()
+}
+
+fn send(channel: &str, text: String) -> ChatBotCommand {
+    ChatBotCommand::SendMessage {
+        channel: channel.to_owned(),
+        text,
+        overflow: Overflow::Split,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Hint,
+    Timeout,
+    Next,
+}
+
+#[derive(Debug)]
+struct Score {
+    // lowercase
+    login: String,
+    name: String,
+    correct: u32,
+}
+
+#[derive(Debug)]
+struct Game {
+    rounds: usize,
+    // indices of the questions asked so far, none is asked twice
+    asked: Vec<usize>,
+    // waiting for its answer, None between questions
+    current: Option<usize>,
+    // only the timer with this id is in effect, answers make the older ones stale
+    timer: Uuid,
+    step: Step,
+    // in the order of the first right answer
+    scores: Vec<Score>,
+}
+
+/// The trivia game of every channel, chat answers the questions of the file.
+#[derive(Debug, Default)]
+pub struct Trivia {
+    config: TriviaConfig,
+    points: SharedPoints,
+    rng: Rng,
+    questions: Vec<Question>,
+    // by channel
+    games: HashMap<String, Game>,
+}
+
+pub type SharedTrivia = Rc<RefCell<Trivia>>;
+
+impl Trivia {
+    pub fn new(
+        config: &TriviaConfig,
+        questions: Vec<Question>,
+        points: SharedPoints,
+        rng: Rng,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            points,
+            rng,
+            questions,
+            games: HashMap::new(),
+        }
+    }
+
+    fn answer_time(&self) -> Duration {
+        Duration::from_secs(self.config.answer_time)
+    }
+
+    fn schedule(&mut self, channel: &str, step: Step, duration: Duration) -> ChatBotCommand {
+        let id = Uuid::new_v4();
+        if let Some(game) = self.games.get_mut(channel) {
+            game.timer = id;
+            game.step = step;
+        }
+        ChatBotCommand::TimedCallback {
+            duration,
+            event: ChatBotEvent::Internal(InternalEvent::TriviaTimer {
+                channel: channel.to_owned(),
+                id,
+            }),
+        }
+    }
+
+    /// A new game of at most the rounds, refused while another one runs.
+    pub fn start(&mut self, channel: &str, rounds: usize) -> Result<ChatBotCommand, String> {
+        if self.questions.is_empty() {
+            return Err("There are no trivia questions.".to_owned());
+        }
+        if self.games.contains_key(channel) {
+            return Err("A trivia game is running already.".to_owned());
+        }
+        self.games.insert(
+            channel.to_owned(),
+            Game {
+                rounds,
+                asked: Vec::new(),
+                current: None,
+                timer: Uuid::nil(),
+                step: Step::Next,
+                scores: Vec::new(),
+            },
+        );
+        self.ask(channel)
+            .ok_or_else(|| "There are no trivia questions.".to_owned())
+    }
+
+    // the next question unless the game is over
+    fn ask(&mut self, channel: &str) -> Option<ChatBotCommand> {
+        let (questions, rng) = (&self.questions, &mut self.rng);
+        let game = self.games.get_mut(channel)?;
+        let left: Vec<usize> = (0..questions.len())
+            .filter(|index| !game.asked.contains(index))
+            .collect();
+        if game.asked.len() == game.rounds || left.is_empty() {
+            let text = self.end(channel)?;
+            return Some(send(channel, text));
+        }
+        let index = left[rng.usize(..left.len())];
+        game.asked.push(index);
+        game.current = Some(index);
+        let question = &questions[index];
+        let about: Vec<_> = [&question.category, &question.difficulty]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        let text = match about.is_empty() {
+            true => format!(
+                "Trivia {}/{}: {}",
+                game.asked.len(),
+                game.rounds,
+                question.question
+            ),
+            false => format!(
+                "Trivia {}/{} ({}): {}",
+                game.asked.len(),
+                game.rounds,
+                about.join(", "),
+                question.question
+            ),
+        };
+        let timer = match self.config.hint {
+            true => self.schedule(channel, Step::Hint, self.answer_time() / 2),
+            false => self.schedule(channel, Step::Timeout, self.answer_time()),
+        };
+        Some(ChatBotCommand::MultipleCommands(vec![
+            send(channel, text),
+            timer,
+        ]))
+    }
+
+    fn current(&self, channel: &str) -> Option<&Question> {
+        let index = self.games.get(channel)?.current?;
+        self.questions.get(index)
+    }
+
+    /// The first right answer to the question wins its points.
+    pub fn answer(&mut self, message: &TextMessage) -> Option<ChatBotCommand> {
+        let channel = &message.channel;
+        let question = self.current(channel)?;
+        let given = normalize(&message.text);
+        if !question
+            .answers
+            .iter()
+            .any(|answer| normalize(answer) == given)
+        {
+            return None;
+        }
+        let answer = question.answers[0].clone();
+        let login = message.user.name.to_lowercase();
+        let name = message.user.display_name().to_owned();
+        let game = self.games.get_mut(channel)?;
+        game.current = None;
+        match game.scores.iter_mut().find(|score| score.login == login) {
+            Some(score) => score.correct += 1,
+            None => game.scores.push(Score {
+                login: login.clone(),
+                name: name.clone(),
+                correct: 1,
+            }),
+        }
+        let awarded = self.config.points > 0 && self.points.borrow().is_enabled_in(channel);
+        let text = match awarded {
+            true => {
+                self.points
+                    .borrow_mut()
+                    .credit(channel, &login, self.config.points);
+                format!(
+                    "{} got it, the answer was {}! +{} points.",
+                    name, answer, self.config.points
+                )
+            }
+            false => format!("{} got it, the answer was {}!", name, answer),
+        };
+        let next = self.schedule(channel, Step::Next, TRIVIA_PAUSE);
+        Some(ChatBotCommand::MultipleCommands(vec![
+            send(channel, text),
+            next,
+        ]))
+    }
+
+    /// What is due when the timer with the id is over, nothing if it's stale.
+    pub fn timer(&mut self, channel: &str, id: Uuid) -> Option<ChatBotCommand> {
+        let game = self.games.get(channel).filter(|game| game.timer == id)?;
+        match game.step {
+            Step::Hint => {
+                let text = format!("Hint: {}", hint(&self.current(channel)?.answers[0]));
+                let rest = self.answer_time() - self.answer_time() / 2;
+                let timeout = self.schedule(channel, Step::Timeout, rest);
+                Some(ChatBotCommand::MultipleCommands(vec![
+                    send(channel, text),
+                    timeout,
+                ]))
+            }
+            Step::Timeout => {
+                let text = format!(
+                    "Time's up, the answer was {}.",
+                    self.current(channel)?.answers[0]
+                );
+                self.games.get_mut(channel)?.current = None;
+                let next = self.schedule(channel, Step::Next, TRIVIA_PAUSE);
+                Some(ChatBotCommand::MultipleCommands(vec![
+                    send(channel, text),
+                    next,
+                ]))
+            }
+            Step::Next => self.ask(channel),
+        }
+    }
+
+    /// Ends the game with its scoreboard, None without one.
+    pub fn end(&mut self, channel: &str) -> Option<String> {
+        let mut game = self.games.remove(channel)?;
+        if game.scores.is_empty() {
+            return Some("Trivia is over, nobody got an answer right.".to_owned());
+        }
+        // the stable sort keeps whoever scored first ahead on a tie
+        game.scores
+            .sort_by_key(|score| std::cmp::Reverse(score.correct));
+        let scores: Vec<_> = game
+            .scores
+            .iter()
+            .map(|score| format!("{} {}", score.name, score.correct))
+            .collect();
+        Some(format!(
+            "Trivia is over! Congratulations to {}. Scores: {}",
+            game.scores[0].name,
+            scores.join(", ")
+        ))
+    }
+
+    /// The bot left the channel, its game is gone.
+    pub fn leave(&mut self, channel: &str) {
+        self.games.remove(channel);
+    }
+}
+
+/// `!trivia start [rounds]` and `!trivia stop`.
+pub struct TriviaCommand(pub SharedTrivia);
+
+impl Command for TriviaCommand {
+    fn name(&self) -> &'static str {
+        "trivia"
+    }
+
+    fn usage(&self) -> &'static str {
+        "start [rounds], stop"
+    }
+
+    fn level(&self) -> UserLevel {
+        UserLevel::Moderator
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let channel = &ctx.message.channel;
+        let usage = format!(
+            "Usage: {0}trivia start [rounds] or {0}trivia stop, at most {1} rounds",
+            ctx.prefix, MAX_ROUNDS
+        );
+        match args.next().map(str::to_lowercase).as_deref() {
+            Some("start") => {
+                let rounds = match args.next().map(str::parse) {
+                    None => DEFAULT_ROUNDS,
+                    Some(Ok(rounds)) if (1..=MAX_ROUNDS).contains(&rounds) => rounds,
+                    Some(_) => return ctx.send(usage),
+                };
+                match self.0.borrow_mut().start(channel, rounds) {
+                    Ok(command) => Some(command),
+                    Err(text) => ctx.send(text),
+                }
+            }
+            Some("stop") => {
+                let text = self.0.borrow_mut().end(channel);
+                ctx.send(text.unwrap_or_else(|| "No trivia game is running.".to_owned()))
+            }
+            _ => ctx.send(usage),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::message;
+    use std::collections::HashSet;
+
+    fn question(question: &str, answers: &[&str]) -> Question {
+        Question {
+            question: question.to_owned(),
+            answers: answers.iter().map(|&answer| answer.to_owned()).collect(),
+            category: None,
+            difficulty: None,
+        }
+    }
+
+    fn trivia(questions: Vec<Question>, seed: u64) -> Trivia {
+        let config = TriviaConfig {
+            answer_time: 30,
+            ..Default::default()
+        };
+        Trivia::new(
+            &config,
+            questions,
+            SharedPoints::default(),
+            Rng::with_seed(seed),
+        )
+    }
+
+    // the texts sent and the timer scheduled by the command
+    fn unpack(command: ChatBotCommand) -> (Vec<String>, Option<(Duration, Uuid)>) {
+        let mut texts = Vec::new();
+        let mut timer = None;
+        let commands = match command {
+            ChatBotCommand::MultipleCommands(commands) => commands,
+            command => vec![command],
+        };
+        for command in commands {
+            match command {
+                ChatBotCommand::SendMessage { text, .. } => texts.push(text),
+                ChatBotCommand::TimedCallback {
+                    duration,
+                    event: ChatBotEvent::Internal(InternalEvent::TriviaTimer { id, .. }),
+                } => timer = Some((duration, id)),
+                command => panic!("unexpected {:?}", command),
+            }
+        }
+        (texts, timer)
+    }
+
+    #[test]
+    fn answers_ignore_case_and_punctuation() {
+        assert_eq!(normalize("  The U.S.A.!  "), "the usa");
+        assert_eq!(normalize("Rock 'n'   Roll"), "rock n roll");
+        assert_eq!(normalize("Pokémon"), "pokémon");
+        let mut trivia = trivia(vec![question("Where?", &["USA", "United States"])], 0);
+        trivia.start("carkhy", 1).unwrap();
+        assert!(trivia.answer(&message("Viewer", "Canada", false)).is_none());
+        assert!(trivia
+            .answer(&message("Viewer", "united   states.", false))
+            .is_some());
+        // answered already
+        assert!(trivia.answer(&message("Viewer", "usa", false)).is_none());
+        let mut trivia = self::trivia(vec![question("Where?", &["USA", "United States"])], 0);
+        trivia.start("carkhy", 1).unwrap();
+        let (texts, _) = unpack(trivia.answer(&message("Viewer", "u.s.a", false)).unwrap());
+        assert_eq!(texts, ["Viewer got it, the answer was USA!"]);
+    }
+
+    #[test]
+    fn the_hint_comes_halfway() {
+        assert_eq!(hint("United States"), "U_____ S_____");
+        assert_eq!(hint("R2-D2"), "R_-__");
+        let mut trivia = trivia(vec![question("Where?", &["United States"])], 0);
+        let (texts, timer) = unpack(trivia.start("carkhy", 1).unwrap());
+        assert_eq!(texts, ["Trivia 1/1: Where?"]);
+        let (duration, hint_id) = timer.unwrap();
+        assert_eq!(duration, Duration::from_secs(15));
+        let (texts, timer) = unpack(trivia.timer("carkhy", hint_id).unwrap());
+        assert_eq!(texts, ["Hint: U_____ S_____"]);
+        let (duration, timeout) = timer.unwrap();
+        assert_eq!(duration, Duration::from_secs(15));
+        // a timer fires only once
+        assert!(trivia.timer("carkhy", hint_id).is_none());
+        let (texts, timer) = unpack(trivia.timer("carkhy", timeout).unwrap());
+        assert_eq!(texts, ["Time's up, the answer was United States."]);
+        let (texts, timer) = unpack(trivia.timer("carkhy", timer.unwrap().1).unwrap());
+        assert_eq!(texts, ["Trivia is over, nobody got an answer right."]);
+        assert!(timer.is_none());
+
+        // without hints the whole time passes at once
+        trivia.config.hint = false;
+        let (_, timer) = unpack(trivia.start("carkhy", 1).unwrap());
+        assert_eq!(timer.unwrap().0, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn questions_do_not_repeat() {
+        let questions: Vec<_> = (0..5)
+            .map(|index| question(&index.to_string(), &[&format!("answer {}", index)]))
+            .collect();
+        for seed in 0..20 {
+            let mut trivia = trivia(questions.clone(), seed);
+            let mut asked = HashSet::new();
+            let mut command = trivia.start("carkhy", 10).unwrap();
+            // each question is answered right away, there are fewer than the rounds
+            while let (texts, Some(_)) = unpack(command) {
+                let number = texts[0].rsplit(' ').next().unwrap().to_owned();
+                assert!(asked.insert(number.clone()), "{} again", number);
+                let answer =
+                    trivia.answer(&message("Viewer", &format!("Answer {}!", number), false));
+                let (_, next) = unpack(answer.unwrap());
+                command = trivia.timer("carkhy", next.unwrap().1).unwrap();
+            }
+            assert_eq!(asked.len(), 5);
+        }
+    }
+}
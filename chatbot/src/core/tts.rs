@@ -0,0 +1,245 @@
+//! What a local text to speech program reads out: the input of some rewards, the messages of
+//! some users and those matching a pattern. A text with a banned term is not read, links and
+//! the emotes after `max_emotes` are left out.
+use super::{
+    moderation::{linked_hosts, SharedModeration},
+    ChatBotCommand,
+};
+use crate::{
+    config::TtsConfig,
+    connect::{EmoteSpan, Redemption, TextMessage},
+};
+use regex::Regex;
+use std::collections::HashMap;
+
+// a word repeated this often is taken for an emote of BTTV or FFZ, like the emote filter does
+const REPEATS: usize = 3;
+
+/// Picks the events to read out, without a sink nothing is.
+#[derive(Debug, Default)]
+pub struct Tts {
+    enabled: bool,
+    config: TtsConfig,
+    pattern: Option<Regex>,
+    moderation: SharedModeration,
+}
+
+/// The text without links and with at most `max_emotes` emotes, single spaces between words.
+pub fn clean(text: &str, emotes: &[EmoteSpan], max_emotes: usize) -> String {
+    let mut words: Vec<(usize, &str)> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        let word = &rest[start..];
+        let end = word.find(char::is_whitespace).unwrap_or(word.len());
+        words.push((text.len() - word.len(), &word[..end]));
+        rest = &word[end..];
+    }
+    let mut repeated: HashMap<&str, usize> = HashMap::new();
+    for &(_, word) in &words {
+        *repeated.entry(word).or_default() += 1;
+    }
+    let is_emote = |start: usize, word: &str| {
+        emotes.iter().any(|emote| emote.start == start) || repeated[word] >= REPEATS
+    };
+    let mut kept_emotes = 0;
+    let mut kept = Vec::new();
+    for (start, word) in words {
+        if !linked_hosts(word).is_empty() {
+            continue;
+        }
+        if is_emote(start, word) {
+            if kept_emotes == max_emotes {
+                continue;
+            }
+            kept_emotes += 1;
+        }
+        kept.push(word);
+    }
+    kept.join(" ")
+}
+
+impl Tts {
+    pub fn new(config: &TtsConfig, moderation: SharedModeration) -> Self {
+        Self {
+            enabled: config.sink.is_some(),
+            config: config.clone(),
+            // checked with the config
+            pattern: config
+                .pattern
+                .as_deref()
+                .and_then(|pattern| Regex::new(pattern).ok()),
+            moderation,
+        }
+    }
+
+    fn speak(
+        &self,
+        channel: &str,
+        speaker: &str,
+        text: &str,
+        emotes: &[EmoteSpan],
+        voice: Option<&String>,
+    ) -> Option<ChatBotCommand> {
+        if self
+            .moderation
+            .borrow()
+            .banned
+            .punishment(channel, text)
+            .is_some()
+        {
+            return Some(ChatBotCommand::LogTextMessage(format!(
+                "Not reading out the message of {}, it has a banned term",
+                speaker
+            )));
+        }
+        let text = clean(text, emotes, self.config.max_emotes);
+        (!text.is_empty()).then(|| ChatBotCommand::Speak {
+            speaker: speaker.to_owned(),
+            text,
+            voice: voice.or(self.config.voice.as_ref()).cloned(),
+        })
+    }
+
+    /// The input of the rewards of `tts.rewards`, found by title ignoring case or by id.
+    pub fn redemption(&self, redemption: &Redemption) -> Option<ChatBotCommand> {
+        let reward = self.config.rewards.iter().find(|reward| {
+            **reward == redemption.reward_id || reward.eq_ignore_ascii_case(&redemption.reward)
+        })?;
+        if !self.enabled {
+            return None;
+        }
+        let voice = self
+            .config
+            .voices
+            .get(reward)
+            .or_else(|| self.config.voices.get(&redemption.login));
+        self.speak(
+            &redemption.channel,
+            &redemption.display_name,
+            &redemption.input,
+            &[],
+            voice,
+        )
+    }
+
+    /// A message of a user of `tts.users` or matching `tts.pattern`, commands aren't read.
+    pub fn message(&self, message: &TextMessage) -> Option<ChatBotCommand> {
+        let login = message.user.name.to_lowercase();
+        let chosen = self
+            .config
+            .users
+            .iter()
+            .any(|user| user.eq_ignore_ascii_case(&login))
+            || self
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| pattern.is_match(&message.text));
+        if !self.enabled || !chosen {
+            return None;
+        }
+        let voice = self.config.voices.get(&login);
+        self.speak(
+            &message.channel,
+            message.user.display_name(),
+            &message.text,
+            &message.emotes,
+            voice,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BannedTermConfig, ModerationConfig, Punishment},
+        connect::testing::message,
+        core::moderation::Moderation,
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    fn tts(config: TtsConfig) -> Tts {
+        let moderation = ModerationConfig {
+            banned_terms: vec![BannedTermConfig {
+                term: "buy followers".to_owned(),
+                regex: false,
+                punishment: Punishment::Delete,
+            }],
+            ..Default::default()
+        };
+        let moderation = Moderation::load("CarkhyBot", &moderation, Default::default()).unwrap();
+        Tts::new(
+            &TtsConfig {
+                sink: Some("tcp:127.0.0.1:5555".to_owned()),
+                ..config
+            },
+            Rc::new(RefCell::new(moderation)),
+        )
+    }
+
+    fn spoken(command: Option<ChatBotCommand>) -> Option<(String, Option<String>)> {
+        match command {
+            Some(ChatBotCommand::Speak { text, voice, .. }) => Some((text, voice)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn links_and_too_many_emotes_are_left_out() {
+        let text = "Kappa look at example.com Kappa PogChamp https://x.y/z LUL LUL LUL";
+        let emotes: Vec<_> = [(0, 5), (26, 31), (32, 40)]
+            .into_iter()
+            .map(|(start, end)| EmoteSpan {
+                id: "25".to_owned(),
+                start,
+                end,
+            })
+            .collect();
+        assert_eq!(clean(text, &emotes, 3), "Kappa look at Kappa PogChamp");
+        assert_eq!(clean(text, &emotes, 0), "look at");
+        assert_eq!(clean("  hello \t chat ", &[], 3), "hello chat");
+    }
+
+    #[test]
+    fn chosen_events_are_read_unless_banned() {
+        let mut voices = HashMap::new();
+        voices.insert("carkhy".to_owned(), "robot".to_owned());
+        let tts = tts(TtsConfig {
+            rewards: vec!["Read my message".to_owned()],
+            users: vec!["Carkhy".to_owned()],
+            pattern: Some("(?i)^tts ".to_owned()),
+            voice: Some("default".to_owned()),
+            voices,
+            ..Default::default()
+        });
+        assert_eq!(
+            spoken(tts.message(&message("carkhy", "hello chat", false))),
+            Some(("hello chat".to_owned(), Some("robot".to_owned())))
+        );
+        assert_eq!(
+            spoken(tts.message(&message("tenaciousbyte", "TTS hi", false))),
+            Some(("TTS hi".to_owned(), Some("default".to_owned())))
+        );
+        assert_eq!(
+            spoken(tts.message(&message("tenaciousbyte", "hi", false))),
+            None
+        );
+        assert!(matches!(
+            tts.message(&message("carkhy", "buy followers here", false)),
+            Some(ChatBotCommand::LogTextMessage(_))
+        ));
+        let redemption = Redemption {
+            reward: "read my MESSAGE".to_owned(),
+            input: "first stream PogChamp".to_owned(),
+            display_name: "TenaciousByte".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            spoken(tts.redemption(&redemption)),
+            Some((
+                "first stream PogChamp".to_owned(),
+                Some("default".to_owned())
+            ))
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use super::{
+    commands::{Args, Command, Context},
+    tasks::uptime,
+    ChatBotCommand, HelixTask,
+};
+use crate::{
+    config::WatchTimeConfig,
+    connect::{ChatBotEvent, InternalEvent},
+    storage::{Storage, StorageError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const STORAGE_NAME: &str = "watch_time";
+/// How often the viewers in chat of a live stream are credited the time since the last tick.
+pub const WATCH_TICK: Duration = Duration::from_secs(60);
+// a late tick credits at most this much, e.g. the first one after the bot was offline
+const MAX_TICK_CREDIT: Duration = Duration::from_secs(2 * 60);
+// writing counts as watching this long, twitch doesn't tell about everyone joining a big channel
+const ACTIVE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    // by channel, seconds since 1970 of the last tick, so a restart credits nothing twice
+    credited: BTreeMap<String, u64>,
+    // by channel and lowercase login, the seconds watched
+    watched: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+// seconds since 1970
+#[derive(Debug, Default, Clone, Copy)]
+struct Presence {
+    // when the user joined or last wrote since, the JOINs of a reconnect change nothing
+    joined: Option<u64>,
+    // when the user last wrote
+    seen: Option<u64>,
+}
+
+/// How long every viewer watched each stream while it was live, kept in the storage for
+/// anything else that wants to reward watching.
+#[derive(Debug, Default)]
+pub struct WatchTime {
+    storage: Storage,
+    config: WatchTimeConfig,
+    saved: Saved,
+    // by channel and lowercase login, who is in chat
+    present: HashMap<String, HashMap<String, Presence>>,
+    // by channel, what twitch answered last
+    live: HashMap<String, bool>,
+    // the time credited since the last save that failed, the storage doesn't have it
+    unsaved: bool,
+}
+
+pub type SharedWatchTime = Rc<RefCell<WatchTime>>;
+
+impl WatchTime {
+    pub fn load(config: &WatchTimeConfig, storage: Storage) -> Result<Self, StorageError> {
+        Ok(Self {
+            saved: storage.load(STORAGE_NAME)?,
+            storage,
+            config: config.clone(),
+            ..Default::default()
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn presence(&mut self, channel: &str, login: &str) -> &mut Presence {
+        self.present
+            .entry(channel.to_owned())
+            .or_default()
+            .entry(login.to_lowercase())
+            .or_default()
+    }
+
+    pub fn join(&mut self, channel: &str, login: &str, now: SystemTime) {
+        let presence = self.presence(channel, login);
+        presence.joined.get_or_insert(seconds(now));
+    }
+
+    pub fn part(&mut self, channel: &str, login: &str) {
+        if let Some(present) = self.present.get_mut(channel) {
+            present.remove(&login.to_lowercase());
+        }
+    }
+
+    /// The user wrote, they are watching for a while even without a join.
+    pub fn seen(&mut self, channel: &str, login: &str, now: SystemTime) {
+        let presence = self.presence(channel, login);
+        presence.seen = Some(seconds(now));
+        if let Some(joined) = &mut presence.joined {
+            *joined = seconds(now);
+        }
+    }
+
+    /// The channel was left, its users aren't in chat anymore.
+    pub fn leave(&mut self, channel: &str) {
+        self.present.remove(channel);
+    }
+
+    pub fn live_status(&mut self, channel: &str, live: bool) {
+        self.live.insert(channel.to_owned(), live);
+    }
+
+    /// Credits the time since the last tick to everyone watching a live stream and asks
+    /// twitch whether the streams are live, with the next tick.
+    pub fn tick(&mut self, now: SystemTime) -> Vec<ChatBotCommand> {
+        let now = seconds(now);
+        let max_presence = self.config.max_presence_minutes * 60;
+        let mut credited = false;
+        let mut commands = Vec::new();
+        for (channel, present) in &mut self.present {
+            present.retain(|_, presence| {
+                presence
+                    .joined
+                    .is_some_and(|joined| now.saturating_sub(joined) <= max_presence)
+                    || presence
+                        .seen
+                        .is_some_and(|seen| now.saturating_sub(seen) <= ACTIVE_WINDOW.as_secs())
+            });
+            let last = self.saved.credited.insert(channel.clone(), now);
+            let live = self.live.get(channel) == Some(&true);
+            if let (Some(last), true) = (last, live) {
+                let credit = now.saturating_sub(last).min(MAX_TICK_CREDIT.as_secs());
+                let watched = self.saved.watched.entry(channel.clone()).or_default();
+                for login in present.keys() {
+                    *watched.entry(login.clone()).or_default() += credit;
+                }
+                credited |= credit > 0 && !present.is_empty();
+            }
+            commands.push(ChatBotCommand::Helix(HelixTask::LiveStatus {
+                channel: channel.clone(),
+            }));
+        }
+        self.present.retain(|_, present| !present.is_empty());
+        if credited {
+            let saved = self.storage.save(STORAGE_NAME, &self.saved);
+            self.unsaved = saved.is_err();
+            if let Err(error) = saved {
+                tracing::warn!(%error, "could not save the watch time");
+            }
+        }
+        commands.push(ChatBotCommand::TimedCallback {
+            duration: WATCH_TICK,
+            event: ChatBotEvent::Internal(InternalEvent::WatchTick),
+        });
+        commands
+    }
+
+    /// How long the user watched the channel's live streams.
+    pub fn watched(&self, channel: &str, login: &str) -> Duration {
+        let seconds = self
+            .saved
+            .watched
+            .get(channel)
+            .and_then(|watched| watched.get(&login.to_lowercase()))
+            .copied()
+            .unwrap_or_default();
+        Duration::from_secs(seconds)
+    }
+
+    /// The users who watched the longest, longest first. The storage ranks them, e.g. the
+    /// database, unless the last save failed.
+    pub fn top(&self, channel: &str, count: usize) -> Vec<(String, Duration)> {
+        if !self.unsaved {
+            match self.storage.top(STORAGE_NAME, &["watched", channel], count) {
+                Ok(Some(top)) => {
+                    return top
+                        .into_iter()
+                        .map(|(login, seconds)| (login, Duration::from_secs(seconds)))
+                        .collect()
+                }
+                Ok(None) => {}
+                Err(error) => tracing::warn!(%error, "could not rank the watch time"),
+            }
+        }
+        let mut top: Vec<_> = self
+            .saved
+            .watched
+            .get(channel)
+            .into_iter()
+            .flatten()
+            .filter(|(_, &seconds)| seconds > 0)
+            .map(|(login, &seconds)| (login.clone(), Duration::from_secs(seconds)))
+            .collect();
+        top.sort_by(|(a, a_watched), (b, b_watched)| b_watched.cmp(a_watched).then(a.cmp(b)));
+        top.truncate(count);
+        top
+    }
+}
+
+/// `!watchtime [@user]` tells how long the user calling it or the given user watched.
+pub struct WatchTimeCommand(pub SharedWatchTime);
+
+impl Command for WatchTimeCommand {
+    fn name(&self) -> &'static str {
+        "watchtime"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[@user]"
+    }
+
+    fn execute(&mut self, ctx: &Context, mut args: Args) -> Option<ChatBotCommand> {
+        let watch_time = self.0.borrow();
+        let channel = &ctx.message.channel;
+        match args.next().map(|user| user.trim_start_matches('@')) {
+            Some(user) => ctx.send(format!(
+                "{} has watched for {}.",
+                user,
+                uptime(watch_time.watched(channel, user))
+            )),
+            None => ctx.send(format!(
+                "{}, you have watched for {}.",
+                ctx.message.user.display_name(),
+                uptime(watch_time.watched(channel, &ctx.message.user.name))
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    fn ticks(watch_time: &mut WatchTime, from: SystemTime, count: u64) -> SystemTime {
+        for tick in 1..=count {
+            watch_time.tick(from + minutes(tick));
+        }
+        from + minutes(count)
+    }
+
+    #[test]
+    fn only_live_streams_count() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut watch_time = WatchTime::load(&Default::default(), Storage::memory()).unwrap();
+        watch_time.join("carkhy", "Viewer", start);
+        let now = ticks(&mut watch_time, start, 10);
+        assert_eq!(watch_time.watched("carkhy", "viewer"), Duration::ZERO);
+
+        watch_time.live_status("carkhy", true);
+        let now = ticks(&mut watch_time, now, 10);
+        // the JOINs of a reconnect count once
+        watch_time.join("carkhy", "viewer", now);
+        watch_time.join("carkhy", "Other", now);
+        let now = ticks(&mut watch_time, now, 5);
+        watch_time.part("carkhy", "viewer");
+        let now = ticks(&mut watch_time, now, 5);
+        assert_eq!(watch_time.watched("carkhy", "viewer"), minutes(15));
+        assert_eq!(watch_time.watched("carkhy", "other"), minutes(10));
+
+        watch_time.live_status("carkhy", false);
+        ticks(&mut watch_time, now, 10);
+        assert_eq!(
+            watch_time.top("carkhy", 5),
+            [
+                ("viewer".to_owned(), minutes(15)),
+                ("other".to_owned(), minutes(10))
+            ]
+        );
+    }
+
+    #[test]
+    fn presence_without_a_part_is_capped() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let config = WatchTimeConfig {
+            enabled: true,
+            max_presence_minutes: 60,
+        };
+        let mut watch_time = WatchTime::load(&config, Storage::default()).unwrap();
+        watch_time.live_status("carkhy", true);
+        watch_time.join("carkhy", "stale", start);
+        watch_time.join("carkhy", "writer", start);
+        // the first tick in a channel only starts counting
+        watch_time.tick(start);
+        // writing keeps a user watching, and counts without a join for a while
+        for minute in (0..180).step_by(30) {
+            watch_time.seen("carkhy", "writer", start + minutes(minute));
+            watch_time.seen("carkhy", "lurker", start + minutes(minute));
+            ticks(&mut watch_time, start + minutes(minute), 30);
+        }
+        assert_eq!(watch_time.watched("carkhy", "stale"), minutes(60));
+        assert_eq!(watch_time.watched("carkhy", "writer"), minutes(180));
+        assert_eq!(watch_time.watched("carkhy", "lurker"), minutes(60));
+    }
+
+    #[test]
+    fn a_restart_credits_nothing_twice() {
+        let storage = Storage::memory();
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let config = WatchTimeConfig::default();
+        let mut watch_time = WatchTime::load(&config, storage.clone()).unwrap();
+        watch_time.live_status("carkhy", true);
+        watch_time.join("carkhy", "viewer", start);
+        let now = ticks(&mut watch_time, start, 3);
+        assert_eq!(watch_time.watched("carkhy", "viewer"), minutes(2));
+
+        // back after half a minute, then after an hour offline
+        let mut watch_time = WatchTime::load(&config, storage.clone()).unwrap();
+        watch_time.live_status("carkhy", true);
+        watch_time.join("carkhy", "viewer", now);
+        watch_time.tick(now + Duration::from_secs(30));
+        assert_eq!(
+            watch_time.watched("carkhy", "viewer"),
+            minutes(2) + Duration::from_secs(30)
+        );
+        watch_time.tick(now + minutes(60));
+        assert_eq!(
+            watch_time.watched("carkhy", "viewer"),
+            minutes(4) + Duration::from_secs(30)
+        );
+    }
+}
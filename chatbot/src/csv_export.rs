@@ -0,0 +1,550 @@
+//! CSV files of chat for spreadsheets: the messages of a range of days, what each user wrote
+//! and the calls of the commands, e.g. `all-2026-10-01-to-2026-10-14-messages.csv`.
+//!
+//! The chat logs are read a line at a time and each message is written right away, so a
+//! month of chat is never in memory at once.
+use crate::{
+    config::Config,
+    core::{timestamp, CommandStats, Date},
+};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+// lines of the text logs that aren't chat messages start with their kind
+const KINDS: [&str; 4] = ["usernotice", "clearchat", "clearmsg", "notice"];
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("could not read the chat log {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("could not write {0}: {1}")]
+    Write(PathBuf, io::Error),
+}
+
+/// The days exported, both included, all of them without either end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Range {
+    pub since: Option<Date>,
+    pub until: Option<Date>,
+}
+
+impl Range {
+    pub fn contains(&self, date: Date) -> bool {
+        self.since.is_none_or(|since| since <= date) && self.until.is_none_or(|until| date <= until)
+    }
+}
+
+// "2026-10-01-to-2026-10-14", "start-to-2026-10-14"
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let end =
+            |date: Option<Date>, name: &str| date.map_or(name.to_owned(), |date| date.to_string());
+        write!(
+            f,
+            "{}-to-{}",
+            end(self.since, "start"),
+            end(self.until, "end")
+        )
+    }
+}
+
+/// A field quoted when it has a comma, a quote or a line break, its quotes doubled.
+pub fn field(text: &str) -> Cow<'_, str> {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => Cow::Owned(format!("\"{}\"", text.replace('"', "\"\""))),
+        false => Cow::Borrowed(text),
+    }
+}
+
+fn row(out: &mut impl Write, fields: &[&str]) -> io::Result<()> {
+    let fields: Vec<_> = fields.iter().map(|text| field(text)).collect();
+    writeln!(out, "{}", fields.join(","))
+}
+
+// a chat message of the logs
+#[derive(Debug, PartialEq, Eq)]
+struct Logged {
+    time: String,
+    user: String,
+    text: String,
+    bits: Option<u64>,
+}
+
+// a line of the jsonl logs, None for the other kinds
+fn from_json(line: &str) -> Option<Logged> {
+    let json: Value = serde_json::from_str(line).ok()?;
+    if json["type"] != "privmsg" {
+        return None;
+    }
+    let millis = json["timestamp"].as_u64()?;
+    Some(Logged {
+        time: timestamp(UNIX_EPOCH + Duration::from_millis(millis)),
+        user: json["user"]["login"].as_str()?.to_owned(),
+        text: json["text"].as_str()?.to_owned(),
+        bits: json["bits"].as_u64(),
+    })
+}
+
+// "[2026-10-14 20:46:42] [sub/27,vip] Carkhy: hello" or "... * Carkhy waves", the text logs
+// have the display name and no bits
+fn from_text(line: &str) -> Option<Logged> {
+    let (time, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let (badges, rest) = match rest.strip_prefix('[') {
+        Some(badged) => (true, badged.split_once("] ")?.1),
+        None => (false, rest),
+    };
+    let (user, text) = match rest.strip_prefix("* ") {
+        Some(action) => action.split_once(' ')?,
+        None => rest.split_once(": ")?,
+    };
+    if !badges && KINDS.contains(&user) {
+        return None;
+    }
+    Some(Logged {
+        time: time.to_owned(),
+        user: user.to_owned(),
+        text: text.to_owned(),
+        bits: None,
+    })
+}
+
+// a file of the chat logs, see chat_logs.rs for the names
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct LogFile {
+    date: Date,
+    channel: String,
+    index: u32,
+    json: bool,
+    path: PathBuf,
+}
+
+// "carkhy-2026-10-14.log" or "carkhy-2026-10-14.2.jsonl", None for other files
+fn log_file(path: PathBuf) -> Option<LogFile> {
+    let name = path.file_name()?.to_str()?;
+    let (stem, json) = match name.strip_suffix(".jsonl") {
+        Some(stem) => (stem, true),
+        None => (name.strip_suffix(".log")?, false),
+    };
+    let (stem, index) = match stem.split_once('.') {
+        Some((stem, index)) => (stem, index.parse().ok()?),
+        None => (stem, 0),
+    };
+    let (channel, date) = stem.split_at(stem.len().checked_sub(11)?);
+    let date = Date::parse(date.strip_prefix('-')?)?;
+    Some(LogFile {
+        date,
+        channel: channel.to_owned(),
+        index,
+        json,
+        path: path.clone(),
+    })
+}
+
+// what a user wrote in a channel
+#[derive(Debug, Default)]
+struct Totals {
+    messages: u64,
+    commands: u64,
+    bits: u64,
+    first: String,
+    last: String,
+}
+
+/// What was exported, told to whoever asked.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Exported {
+    pub messages: u64,
+    pub files: Vec<PathBuf>,
+}
+
+impl fmt::Display for Exported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect();
+        write!(
+            f,
+            "Exported {} messages to {}",
+            self.messages,
+            files.join(", ")
+        )
+    }
+}
+
+/// Writes the CSV files from the chat logs and the command stats.
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    logs: PathBuf,
+    directory: PathBuf,
+    prefix: String,
+    // the channels with a prefix of their own
+    prefixes: HashMap<String, String>,
+}
+
+impl Exporter {
+    pub fn new(config: &Config) -> Self {
+        let channels = (config.twitch.channels.iter())
+            .chain(config.commands.channel_prefixes.keys())
+            .chain(config.channels.keys());
+        let prefixes = channels
+            .map(|channel| {
+                let channel = channel.trim_start_matches('#').to_lowercase();
+                let prefix = config.for_channel(&channel).commands.prefix.clone();
+                (channel, prefix)
+            })
+            .collect();
+        Self {
+            logs: config.chat_logs.directory.clone(),
+            directory: config.export.directory.clone(),
+            prefix: config.commands.prefix.clone(),
+            prefixes,
+        }
+    }
+
+    // a message starting with the channel's prefix or the `!` of the older commands
+    fn is_command(&self, channel: &str, text: &str) -> bool {
+        let prefix = self.prefixes.get(channel).unwrap_or(&self.prefix);
+        text.starts_with(prefix.as_str()) || text.starts_with('!')
+    }
+
+    // the files of the range, in the order they were written
+    fn log_files(&self, channel: Option<&str>, range: &Range) -> Result<Vec<LogFile>, ExportError> {
+        let entries = match fs::read_dir(&self.logs) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(ExportError::Read(self.logs.clone(), error)),
+        };
+        let mut files: Vec<LogFile> = entries
+            .flatten()
+            .filter_map(|entry| log_file(entry.path()))
+            .filter(|file| range.contains(file.date))
+            .filter(|file| channel.is_none_or(|channel| file.channel == channel))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    /// The messages of the channel, or of all of them, the users and the command calls.
+    pub fn export(
+        &self,
+        channel: Option<&str>,
+        range: &Range,
+        stats: &CommandStats,
+    ) -> Result<Exported, ExportError> {
+        let channel = channel.map(|channel| channel.trim_start_matches('#').to_lowercase());
+        let channel = channel.as_deref();
+        let path = |kind: &str| {
+            let name = format!("{}-{}-{}.csv", channel.unwrap_or("all"), range, kind);
+            self.directory.join(name)
+        };
+        let create = |path: &Path| {
+            fs::create_dir_all(&self.directory)
+                .and_then(|()| File::create(path))
+                .map(BufWriter::new)
+                .map_err(|error| ExportError::Write(path.to_owned(), error))
+        };
+        let written = |path: &Path| {
+            let path = path.to_owned();
+            move |error| ExportError::Write(path, error)
+        };
+
+        let messages_path = path("messages");
+        let mut messages = create(&messages_path)?;
+        let header = ["timestamp", "channel", "user", "text", "bits", "command"];
+        row(&mut messages, &header).map_err(written(&messages_path))?;
+        let mut users: BTreeMap<(String, String), Totals> = BTreeMap::new();
+        let mut count = 0;
+        for file in self.log_files(channel, range)? {
+            let read = BufReader::new(
+                File::open(&file.path)
+                    .map_err(|error| ExportError::Read(file.path.clone(), error))?,
+            );
+            for line in read.lines() {
+                let line = line.map_err(|error| ExportError::Read(file.path.clone(), error))?;
+                let logged = match file.json {
+                    true => from_json(&line),
+                    false => from_text(&line),
+                };
+                let Some(logged) = logged else {
+                    continue;
+                };
+                let command = self.is_command(&file.channel, &logged.text);
+                let bits = logged.bits.map(|bits| bits.to_string()).unwrap_or_default();
+                let fields = [
+                    logged.time.as_str(),
+                    &file.channel,
+                    &logged.user,
+                    &logged.text,
+                    &bits,
+                    if command { "true" } else { "false" },
+                ];
+                row(&mut messages, &fields).map_err(written(&messages_path))?;
+                count += 1;
+                let key = (file.channel.clone(), logged.user.to_lowercase());
+                let totals = users.entry(key).or_default();
+                if totals.messages == 0 {
+                    totals.first = logged.time.clone();
+                }
+                totals.messages += 1;
+                totals.commands += u64::from(command);
+                totals.bits += logged.bits.unwrap_or_default();
+                totals.last = logged.time;
+            }
+        }
+        messages.flush().map_err(written(&messages_path))?;
+
+        let users_path = path("users");
+        let mut out = create(&users_path)?;
+        let header = [
+            "channel",
+            "user",
+            "messages",
+            "commands",
+            "bits",
+            "first_message",
+            "last_message",
+        ];
+        row(&mut out, &header).map_err(written(&users_path))?;
+        for ((channel, user), totals) in &users {
+            let fields = [
+                channel.as_str(),
+                user,
+                &totals.messages.to_string(),
+                &totals.commands.to_string(),
+                &totals.bits.to_string(),
+                &totals.first,
+                &totals.last,
+            ];
+            row(&mut out, &fields).map_err(written(&users_path))?;
+        }
+        out.flush().map_err(written(&users_path))?;
+
+        let commands_path = path("commands");
+        let mut out = create(&commands_path)?;
+        let header = [
+            "date",
+            "channel",
+            "command",
+            "calls",
+            "succeeded",
+            "cooldown",
+            "denied",
+            "users",
+        ];
+        row(&mut out, &header).map_err(written(&commands_path))?;
+        let calls = stats.each().filter(|(day, called, _, _)| {
+            Date::parse(day).is_some_and(|day| range.contains(day))
+                && channel.is_none_or(|channel| channel == *called)
+        });
+        for (day, called, command, tally) in calls {
+            let fields = [
+                day,
+                called,
+                command,
+                &tally.calls().to_string(),
+                &tally.succeeded.to_string(),
+                &tally.cooldown.to_string(),
+                &tally.denied.to_string(),
+                &tally.users.len().to_string(),
+            ];
+            row(&mut out, &fields).map_err(written(&commands_path))?;
+        }
+        out.flush().map_err(written(&commands_path))?;
+
+        Ok(Exported {
+            messages: count,
+            files: vec![messages_path, users_path, commands_path],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ChatLogsConfig, ExportConfig},
+        storage::testing::TempDir,
+    };
+
+    // the fields of each record, quoted ones may span lines
+    fn parse(csv: &str) -> Vec<Vec<String>> {
+        let (mut records, mut record, mut field) = (Vec::new(), Vec::new(), String::new());
+        let (mut quoted, mut chars) = (false, csv.chars().peekable());
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (false, '"') => quoted = true,
+                (false, ',') => record.push(std::mem::take(&mut field)),
+                (false, '\n') => {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                (_, c) => field.push(c),
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn fields_are_quoted_when_needed() {
+        assert_eq!(field("hello there"), "hello there");
+        assert_eq!(field("one, two"), "\"one, two\"");
+        assert_eq!(field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(field("two\nlines"), "\"two\nlines\"");
+        let line = "[2026-10-14 20:46:42] [sub/27,vip] Carkhy: hello: there";
+        assert_eq!(
+            from_text(line),
+            Some(Logged {
+                time: "2026-10-14 20:46:42".to_owned(),
+                user: "Carkhy".to_owned(),
+                text: "hello: there".to_owned(),
+                bits: None,
+            })
+        );
+        assert_eq!(
+            from_text("[2026-10-14 20:46:42] clearchat: carkhy was banned"),
+            None
+        );
+        assert_eq!(
+            from_text("[2026-10-14 20:46:42] * Carkhy waves").map(|logged| logged.text),
+            Some("waves".to_owned())
+        );
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let root = TempDir::new("chatbot-csv-export");
+        let logs = root.join("logs");
+        fs::create_dir_all(&logs).unwrap();
+        let message = |millis: u64, login: &str, text: &str, bits: Option<u64>| {
+            let mut json = serde_json::json!({
+                "type": "privmsg",
+                "channel": "carkhy",
+                "user": { "login": login, "display_name": login, "color": null },
+                "text": text,
+                "badges": [],
+                "emotes": [],
+                "timestamp": millis,
+            });
+            if let Some(bits) = bits {
+                json["bits"] = bits.into();
+            }
+            json.to_string()
+        };
+        // 2026-10-14 20:46:42 and a day later
+        let day = 1_792_010_802_000;
+        let tricky = "she said \"gg, well played\"\nand left";
+        let lines = [
+            message(day, "viewer", tricky, None),
+            message(day + 1000, "viewer", "?points", None),
+            message(day + 2000, "cheerer", "cheer100 go", Some(100)),
+            r#"{"type":"clearchat","channel":"carkhy","user":"viewer","duration":60,"timestamp":1792010804000}"#.to_owned(),
+        ];
+        fs::write(
+            logs.join("carkhy-2026-10-14.jsonl"),
+            lines.join("\n") + "\n",
+        )
+        .unwrap();
+        let later = message(day + 86_400_000, "viewer", "!lurk", None);
+        fs::write(logs.join("carkhy-2026-10-15.jsonl"), later + "\n").unwrap();
+        let config = Config {
+            chat_logs: ChatLogsConfig {
+                directory: logs,
+                ..Default::default()
+            },
+            export: ExportConfig {
+                directory: root.join("exports"),
+            },
+            ..Default::default()
+        };
+        let config = Config {
+            commands: crate::config::CommandsConfig {
+                channel_prefixes: [("carkhy".to_owned(), "?".to_owned())].into(),
+                ..Default::default()
+            },
+            ..config
+        };
+        let range = Range {
+            since: None,
+            until: Date::parse("2026-10-14"),
+        };
+        let exported = Exporter::new(&config)
+            .export(Some("#Carkhy"), &range, &CommandStats::default())
+            .unwrap();
+        assert_eq!(exported.messages, 3);
+        let names: Vec<_> = exported
+            .files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "carkhy-start-to-2026-10-14-messages.csv",
+                "carkhy-start-to-2026-10-14-users.csv",
+                "carkhy-start-to-2026-10-14-commands.csv"
+            ]
+        );
+        let messages = parse(&fs::read_to_string(&exported.files[0]).unwrap());
+        assert_eq!(
+            messages,
+            [
+                vec!["timestamp", "channel", "user", "text", "bits", "command"],
+                vec![
+                    "2026-10-14 20:46:42",
+                    "carkhy",
+                    "viewer",
+                    tricky,
+                    "",
+                    "false"
+                ],
+                vec![
+                    "2026-10-14 20:46:43",
+                    "carkhy",
+                    "viewer",
+                    "?points",
+                    "",
+                    "true"
+                ],
+                vec![
+                    "2026-10-14 20:46:44",
+                    "carkhy",
+                    "cheerer",
+                    "cheer100 go",
+                    "100",
+                    "false"
+                ],
+            ]
+        );
+        let users = parse(&fs::read_to_string(&exported.files[1]).unwrap());
+        assert_eq!(
+            users[2],
+            [
+                "carkhy",
+                "viewer",
+                "2",
+                "1",
+                "0",
+                "2026-10-14 20:46:42",
+                "2026-10-14 20:46:43"
+            ]
+        );
+        let commands = parse(&fs::read_to_string(&exported.files[2]).unwrap());
+        assert_eq!(commands.len(), 1);
+    }
+}
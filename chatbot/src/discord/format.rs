@@ -0,0 +1,127 @@
+//! What the bot posts to Discord. Chat's texts are escaped so they can't format the post or
+//! mention anyone.
+use crate::connect::{TextMessage, UserNotice};
+
+/// The longest message Discord takes.
+pub const MAX_CHARS: usize = 2000;
+
+/// Markdown is escaped, '@' is followed by a zero width space so `@everyone` mentions nobody.
+pub fn sanitize(text: &str) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '<' | '#' | '[' | ']' | '-' | ':' => {
+                sanitized.push('\\');
+                sanitized.push(c);
+            }
+            '@' => sanitized.push_str("@\u{200B}"),
+            c if c.is_control() => sanitized.push(' '),
+            c => sanitized.push(c),
+        }
+    }
+    sanitized
+}
+
+/// Cut to what Discord takes, at a char boundary.
+pub fn truncate(mut text: String) -> String {
+    if let Some((end, _)) = text.char_indices().nth(MAX_CHARS) {
+        text.truncate(end);
+    }
+    text
+}
+
+pub fn live(channel: &str) -> String {
+    format!(
+        "**{}** is live: https://www.twitch.tv/{}",
+        sanitize(channel),
+        channel
+    )
+}
+
+// twitch's own text, e.g. "Carkhy subscribed at Tier 1. They've subscribed for 12 months!"
+pub fn notice(notice: &UserNotice) -> String {
+    format!(
+        "**{}**: {}",
+        sanitize(&notice.channel),
+        sanitize(&notice.system_message)
+    )
+}
+
+pub fn chat(message: &TextMessage) -> String {
+    format!(
+        "**{}**: {}",
+        sanitize(message.user.display_name()),
+        sanitize(&message.text)
+    )
+}
+
+/// The moderation of a channel since the last summary.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub timeouts: u32,
+    pub bans: u32,
+    pub clears: u32,
+    pub deletions: u32,
+}
+
+fn count(count: u32, one: &str, many: &str) -> Option<String> {
+    match count {
+        0 => None,
+        1 => Some(format!("1 {}", one)),
+        _ => Some(format!("{} {}", count, many)),
+    }
+}
+
+pub fn summary(channel: &str, summary: &Summary) -> String {
+    let counts: Vec<String> = [
+        count(summary.timeouts, "timeout", "timeouts"),
+        count(summary.bans, "ban", "bans"),
+        count(summary.clears, "cleared chat", "cleared chats"),
+        count(summary.deletions, "deleted message", "deleted messages"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    format!(
+        "Moderation in **{}**: {}",
+        sanitize(channel),
+        counts.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_can_neither_format_nor_mention() {
+        assert_eq!(
+            sanitize("@everyone **free** nitro at [this](https://x.y) ||now||"),
+            "@\u{200B}everyone \\*\\*free\\*\\* nitro at \\[this\\](https\\://x.y) \\|\\|now\\|\\|"
+        );
+        assert_eq!(
+            sanitize("<@&1234> > quote"),
+            "\\<@\u{200B}&1234\\> \\> quote"
+        );
+        assert_eq!(sanitize("carkhy_\\_"), "carkhy\\_\\\\\\_");
+        assert_eq!(
+            live("captain_callback"),
+            "**captain\\_callback** is live: https://www.twitch.tv/captain_callback"
+        );
+        assert_eq!(truncate("ä".repeat(2001)).chars().count(), 2000);
+    }
+
+    #[test]
+    fn summaries_count_what_happened() {
+        let summary = Summary {
+            timeouts: 2,
+            bans: 1,
+            clears: 0,
+            deletions: 3,
+        };
+        assert_eq!(
+            super::summary("captaincallback", &summary),
+            "Moderation in **captaincallback**: 2 timeouts, 1 ban, 3 deleted messages"
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Announcements and chat posted to Discord, from a task of their own: Discord being slow or
+//! down only loses posts, chat never waits for it.
+//!
+//! With `discord.webhook_url` the bot posts when a stream goes live, raids, subs and a
+//! summary of the moderation every `summary_interval` seconds. With `bot_token` and
+//! `channel_id` it relays chat to the channel, a few lines of each chatter a minute, gathered
+//! into one post every couple of seconds.
+mod format;
+
+use crate::{
+    config::DiscordConfig,
+    connect::{ChatBotEvent, Command, TextMessage, UserNotice, UserNoticeKind},
+    handlers::{BotContext, EventHandler, HandlerFuture},
+};
+use format::Summary;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+const API: &str = "https://discord.com/api/v10";
+// the posts waiting for the task, more are dropped
+const QUEUE: usize = 1000;
+// Discord allows 5 posts in 5 seconds to a channel
+const RELAY_EVERY: Duration = Duration::from_secs(2);
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Timeout,
+    Ban,
+    Clear,
+    Delete,
+}
+
+#[derive(Debug)]
+enum Post {
+    Announcement(String),
+    Relay(String),
+    Moderation { channel: String, action: Action },
+}
+
+/// The lines each chatter had relayed in the last minute.
+#[derive(Debug)]
+struct Limiter {
+    per_minute: usize,
+    lines: HashMap<String, VecDeque<Instant>>,
+}
+
+impl Limiter {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: per_minute as usize,
+            lines: HashMap::new(),
+        }
+    }
+
+    fn allow(&mut self, login: &str, now: Instant) -> bool {
+        // the chatters who were quiet for a minute are forgotten
+        if self.lines.len() > QUEUE {
+            self.lines.retain(|_, lines| {
+                lines
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < MINUTE)
+            });
+        }
+        let lines = self.lines.entry(login.to_owned()).or_default();
+        while lines
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= MINUTE)
+        {
+            lines.pop_front();
+        }
+        if lines.len() >= self.per_minute {
+            return false;
+        }
+        lines.push_back(now);
+        true
+    }
+}
+
+// where the posts go
+struct Poster {
+    client: reqwest::Client,
+    webhook: Option<String>,
+    // the URL of the channel's messages and the bot's token
+    relay: Option<(String, String)>,
+}
+
+impl Poster {
+    async fn post(&self, target: Option<(&str, Option<&str>)>, content: String) {
+        let Some((url, token)) = target else {
+            return;
+        };
+        // the text is escaped, this also keeps Discord from notifying anyone
+        let body =
+            json!({ "content": format::truncate(content), "allowed_mentions": { "parse": [] } });
+        let mut request = self.client.post(url).json(&body);
+        if let Some(token) = token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Bot {}", token));
+        }
+        let result = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(error) = result {
+            tracing::warn!(%error, "could not post to discord");
+        }
+    }
+
+    async fn announce(&self, content: String) {
+        let target = match (&self.webhook, &self.relay) {
+            (Some(webhook), _) => Some((webhook.as_str(), None)),
+            (None, Some((url, token))) => Some((url.as_str(), Some(token.as_str()))),
+            (None, None) => None,
+        };
+        self.post(target, content).await
+    }
+
+    async fn relay(&self, content: String) {
+        let target = self
+            .relay
+            .as_ref()
+            .map(|(url, token)| (url.as_str(), Some(token.as_str())));
+        self.post(target, content).await
+    }
+}
+
+// the lines joined into posts Discord takes
+fn batch(lines: Vec<String>) -> Vec<String> {
+    let mut posts: Vec<String> = Vec::new();
+    for line in lines.into_iter().map(format::truncate) {
+        match posts.last_mut() {
+            Some(post) if post.chars().count() + 1 + line.chars().count() <= format::MAX_CHARS => {
+                post.push('\n');
+                post.push_str(&line);
+            }
+            _ => posts.push(line),
+        }
+    }
+    posts
+}
+
+async fn run(
+    poster: Poster,
+    mut posts: mpsc::Receiver<Post>,
+    relay_every: Duration,
+    summary_every: Duration,
+) {
+    // the first ticks an interval from now, not right away while lines are still coming in
+    let start = tokio::time::Instant::now();
+    let mut relay = tokio::time::interval_at(start + relay_every, relay_every);
+    let mut summary = tokio::time::interval_at(start + summary_every, summary_every);
+    let mut lines = Vec::new();
+    let mut summaries: BTreeMap<String, Summary> = BTreeMap::new();
+    loop {
+        tokio::select! {
+            post = posts.recv() => match post {
+                None => break,
+                Some(Post::Announcement(text)) => poster.announce(text).await,
+                Some(Post::Relay(line)) => lines.push(line),
+                Some(Post::Moderation { channel, action }) => {
+                    let summary = summaries.entry(channel).or_default();
+                    match action {
+                        Action::Timeout => summary.timeouts += 1,
+                        Action::Ban => summary.bans += 1,
+                        Action::Clear => summary.clears += 1,
+                        Action::Delete => summary.deletions += 1,
+                    }
+                }
+            },
+            _ = relay.tick() => {
+                for post in batch(std::mem::take(&mut lines)) {
+                    poster.relay(post).await;
+                }
+            }
+            _ = summary.tick() => {
+                for (channel, summary) in std::mem::take(&mut summaries) {
+                    poster.announce(format::summary(&channel, &summary)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Picks what is posted from the events and hands it to the posting task.
+pub struct Discord {
+    posts: mpsc::Sender<Post>,
+    relay: bool,
+    summaries: bool,
+    limiter: Limiter,
+}
+
+impl Discord {
+    /// None when Discord isn't configured, the task is started on the current runtime.
+    pub fn new(config: &DiscordConfig) -> Option<Self> {
+        Self::start(config, API, RELAY_EVERY)
+    }
+
+    fn start(config: &DiscordConfig, api: &str, relay_every: Duration) -> Option<Self> {
+        let relay = match (&config.bot_token, &config.channel_id) {
+            (Some(token), Some(channel)) => Some((
+                format!("{}/channels/{}/messages", api, channel),
+                token.clone(),
+            )),
+            _ => None,
+        };
+        if config.webhook_url.is_none() && relay.is_none() {
+            return None;
+        }
+        let (posts, queue) = mpsc::channel(QUEUE);
+        let this = Self {
+            posts,
+            relay: relay.is_some(),
+            summaries: config.summary_interval > 0,
+            limiter: Limiter::new(config.relay_per_minute),
+        };
+        let poster = Poster {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("the client has no settings that can fail"),
+            webhook: config.webhook_url.clone(),
+            relay,
+        };
+        let summary_every = Duration::from_secs(config.summary_interval.max(1));
+        tokio::spawn(run(poster, queue, relay_every, summary_every));
+        Some(this)
+    }
+
+    fn post(&self, post: Post) {
+        send(&self.posts, post);
+    }
+
+    fn relay(&mut self, message: &TextMessage, now: Instant) {
+        if self.relay && self.limiter.allow(&message.user.name, now) {
+            self.post(Post::Relay(format::chat(message)));
+        }
+    }
+
+    /// What the moderation summaries are counted from, None without them.
+    pub fn moderation(&self) -> Option<Moderation> {
+        self.summaries.then(|| Moderation(self.posts.clone()))
+    }
+}
+
+fn send(posts: &mpsc::Sender<Post>, post: Post) {
+    if posts.try_send(post).is_err() {
+        tracing::debug!("discord is behind, a post is dropped");
+    }
+}
+
+/// Counts the clearing of chat for the summaries. No event handler sees it, so it is called
+/// with each event before the bot handles it, and never waits.
+pub struct Moderation(mpsc::Sender<Post>);
+
+impl Moderation {
+    pub fn observe(&self, event: &ChatBotEvent) {
+        let (channel, action) = match event {
+            ChatBotEvent::ClearChat(clear) => {
+                let action = match (&clear.target_user, clear.duration) {
+                    (None, _) => Action::Clear,
+                    (Some(_), Some(_)) => Action::Timeout,
+                    (Some(_), None) => Action::Ban,
+                };
+                (&clear.channel, action)
+            }
+            ChatBotEvent::ClearMessage(clear) => (&clear.channel, Action::Delete),
+            _ => return,
+        };
+        send(
+            &self.0,
+            Post::Moderation {
+                channel: channel.clone(),
+                action,
+            },
+        );
+    }
+}
+
+// posting never waits, so the futures are ready at once
+impl EventHandler for Discord {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    fn message<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        message: &'a TextMessage,
+    ) -> HandlerFuture<'a> {
+        self.relay(message, Instant::now());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn command<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        command: &'a Command,
+    ) -> HandlerFuture<'a> {
+        self.relay(&command.message, Instant::now());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn user_notice<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        notice: &'a UserNotice,
+    ) -> HandlerFuture<'a> {
+        match notice.kind {
+            UserNoticeKind::Sub { .. }
+            | UserNoticeKind::Resub { .. }
+            | UserNoticeKind::SubGift { .. }
+            | UserNoticeKind::SubMysteryGift { .. }
+            | UserNoticeKind::Raid { .. } => self.post(Post::Announcement(format::notice(notice))),
+            UserNoticeKind::Unknown(_) => {}
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn stream_status<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        channel: &'a str,
+        live: bool,
+    ) -> HandlerFuture<'a> {
+        if live {
+            self.post(Post::Announcement(format::live(channel)));
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect::{testing, UserInfo},
+        handlers::{testing::handle, Handlers},
+        http::testing::{receiver, wait},
+        storage::Storage,
+    };
+    use serde_json::Value;
+
+    fn message(login: &str, text: &str) -> ChatBotEvent {
+        ChatBotEvent::TextMessage(TextMessage {
+            channel: "captaincallback".to_owned(),
+            ..testing::message(login, text, false)
+        })
+    }
+
+    #[test]
+    fn chatters_are_relayed_a_few_lines_a_minute() {
+        let mut limiter = Limiter::new(2);
+        let now = Instant::now();
+        assert!(limiter.allow("carkhy", now));
+        assert!(limiter.allow("carkhy", now + Duration::from_secs(10)));
+        assert!(!limiter.allow("carkhy", now + Duration::from_secs(20)));
+        // others have their own lines
+        assert!(limiter.allow("captaincallback", now + Duration::from_secs(20)));
+        assert!(limiter.allow("carkhy", now + Duration::from_secs(60)));
+        assert!(!limiter.allow("carkhy", now + Duration::from_secs(61)));
+        assert_eq!(
+            batch(vec!["a".repeat(1500), "b".repeat(600), "c".to_owned()]).len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn announcements_go_to_the_webhook_and_chat_to_the_channel() {
+        let (url, received) = receiver(vec![204, 200]);
+        let config = DiscordConfig {
+            webhook_url: Some(format!("{}/webhook", url)),
+            bot_token: Some("bot token".to_owned()),
+            channel_id: Some("123".to_owned()),
+            relay_per_minute: 2,
+            ..DiscordConfig::default()
+        };
+        let discord = Discord::start(&config, &url, Duration::from_millis(200)).unwrap();
+        let mut handlers = Handlers::new(Storage::memory());
+        handlers.register(Box::new(discord));
+        handle(
+            &mut handlers,
+            &ChatBotEvent::UserNotice(UserNotice {
+                kind: UserNoticeKind::Raid {
+                    from: "Carkhy".to_owned(),
+                    viewers: 42,
+                },
+                user: UserInfo::default(),
+                channel: "captaincallback".to_owned(),
+                system_message: "42 raiders from Carkhy have joined!".to_owned(),
+                text: None,
+            }),
+        )
+        .await;
+        for text in ["hi @everyone", "second", "third is left out"] {
+            handle(&mut handlers, &message("carkhy", text)).await;
+        }
+        let mut posts = tokio::task::spawn_blocking(move || [wait(&received), wait(&received)])
+            .await
+            .unwrap();
+        posts.sort_by(|a, b| a.path.cmp(&b.path));
+        let [relayed, announced] = posts;
+        assert_eq!(announced.path, "/webhook");
+        let body: Value = serde_json::from_str(&announced.body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "content": "**captaincallback**: 42 raiders from Carkhy have joined!",
+                "allowed_mentions": { "parse": [] },
+            })
+        );
+        assert_eq!(relayed.path, "/channels/123/messages");
+        assert_eq!(relayed.headers["authorization"], "Bot bot token");
+        let body: Value = serde_json::from_str(&relayed.body).unwrap();
+        assert_eq!(
+            body["content"],
+            "**carkhy**: hi @\u{200B}everyone\n**carkhy**: second"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_streams_going_live_are_announced() {
+        let (posts, mut queue) = mpsc::channel(10);
+        let mut handlers = Handlers::new(Storage::memory());
+        handlers.register(Box::new(Discord {
+            posts,
+            relay: false,
+            summaries: true,
+            limiter: Limiter::new(5),
+        }));
+        let live = |live| ChatBotEvent::LiveStatus {
+            channel: "captaincallback".to_owned(),
+            live,
+        };
+        // live since before the start, and live on each poll
+        handle(&mut handlers, &live(true)).await;
+        handle(&mut handlers, &live(true)).await;
+        handle(&mut handlers, &message("carkhy", "not relayed")).await;
+        assert!(queue.try_recv().is_err());
+        handle(&mut handlers, &live(false)).await;
+        handle(&mut handlers, &live(true)).await;
+        assert!(matches!(
+            queue.try_recv(),
+            Ok(Post::Announcement(text)) if text.starts_with("**captaincallback** is live")
+        ));
+    }
+}
@@ -0,0 +1,85 @@
+use super::{BotContext, EventHandler, HandlerFuture};
+use crate::connect::TextMessage;
+
+// hype chats from this level on are thanked with their amount
+const BIG_HYPE_CHAT_LEVEL: u8 = 6;
+
+/// Thanks for each Hype Chat, the big ones with their amount.
+pub struct HypeChat;
+
+impl EventHandler for HypeChat {
+    fn name(&self) -> &'static str {
+        "hype_chat"
+    }
+
+    fn message<'a>(
+        &'a mut self,
+        ctx: &'a mut BotContext<'_>,
+        message: &'a TextMessage,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let Some(paid) = &message.paid else {
+                return Ok(());
+            };
+            let name = message.user.display_name();
+            let text = match paid.level >= BIG_HYPE_CHAT_LEVEL {
+                true => format!(
+                    "Wow, thank you so much for the {} Hype Chat, {}!",
+                    paid, name
+                ),
+                false => format!("Thank you for the Hype Chat, {}!", name),
+            };
+            ctx.send(&message.channel, text);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect::{ChatBotEvent, PaidMessage, UserInfo},
+        core::ChatBotCommand,
+        handlers::{testing::handle, Handlers},
+        storage::Storage,
+    };
+
+    async fn thanks(handlers: &mut Handlers, level: Option<u8>) -> Option<String> {
+        let event = ChatBotEvent::TextMessage(TextMessage {
+            channel: "carkhy".to_owned(),
+            text: "Hype!".to_owned(),
+            user: UserInfo {
+                name: "carkhy".to_owned(),
+                display_name: Some("Carkhy".to_owned()),
+                ..Default::default()
+            },
+            paid: level.map(|level| PaidMessage {
+                amount: 10000,
+                exponent: 2,
+                currency: "USD".to_owned(),
+                level,
+            }),
+            ..Default::default()
+        });
+        match handle(handlers, &event).await {
+            Some(ChatBotCommand::SendMessage { text, .. }) => Some(text),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn hype_chats_are_thanked_by_level() {
+        let mut handlers = Handlers::new(Storage::memory());
+        handlers.register(Box::new(HypeChat));
+        assert_eq!(
+            thanks(&mut handlers, Some(1)).await.as_deref(),
+            Some("Thank you for the Hype Chat, Carkhy!")
+        );
+        assert_eq!(
+            thanks(&mut handlers, Some(8)).await.as_deref(),
+            Some("Wow, thank you so much for the 100.00 USD Hype Chat, Carkhy!")
+        );
+        assert_eq!(thanks(&mut handlers, None).await, None);
+    }
+}
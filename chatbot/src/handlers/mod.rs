@@ -0,0 +1,425 @@
+//! Event handlers, features that only need the events and what a [BotContext] offers: sending,
+//! the storage, helix and the config. They don't reach into the core, so one can be written
+//! without knowing it, and registered in `main`.
+//!
+//! What is guaranteed:
+//! - the handlers run in the order they were registered, after the core answered the event,
+//!   each one once the one before it finished
+//! - what a handler sends goes after the core's answer and after what the handlers before it
+//!   sent
+//! - a handler that returns an error or panics is logged and skipped for that event: what it
+//!   sent meanwhile is dropped, the handlers after it still run, and it sees the next event
+//! - messages and commands of ignored users, and those the filters acted on, reach no handler
+//!
+//! Each callback is timed in `chatbot_handler_duration_seconds{handler}`, the failures are
+//! counted in `chatbot_handler_failures_total{handler,kind}`.
+use crate::{
+    config::Config,
+    connect::{ChatBotEvent, Command, InternalEvent, Overflow, TextMessage, UserNotice},
+    core::ChatBotCommand,
+    helix::Helix,
+    prometheus::{HANDLER_DURATION, HANDLER_FAILURES},
+    storage::Storage,
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+    time::Instant,
+};
+
+mod hype_chat;
+
+pub use hype_chat::HypeChat;
+
+pub type HandlerResult = Result<(), Box<dyn Error>>;
+/// What the callbacks return, boxed so handlers of any type can be kept together.
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = HandlerResult> + 'a>>;
+
+fn done<'a>() -> HandlerFuture<'a> {
+    Box::pin(async { Ok(()) })
+}
+
+/// A feature driven by the bot's events, each callback does nothing unless implemented.
+pub trait EventHandler {
+    /// For the log and the metrics.
+    fn name(&self) -> &'static str;
+
+    /// A chat message that isn't a command.
+    fn message<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        _message: &'a TextMessage,
+    ) -> HandlerFuture<'a> {
+        done()
+    }
+
+    /// A message starting with the prefix, whether the core knows the command or not.
+    fn command<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        _command: &'a Command,
+    ) -> HandlerFuture<'a> {
+        done()
+    }
+
+    /// Subs, gifts, raids and the other notices twitch announces in chat.
+    fn user_notice<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        _notice: &'a UserNotice,
+    ) -> HandlerFuture<'a> {
+        done()
+    }
+
+    /// A stream went live or offline. The first status twitch tells about a channel is no
+    /// change, the stream may have been live for hours.
+    fn stream_status<'a>(
+        &'a mut self,
+        _ctx: &'a mut BotContext<'_>,
+        _channel: &'a str,
+        _live: bool,
+    ) -> HandlerFuture<'a> {
+        done()
+    }
+
+    /// Once a minute, with the repeating messages.
+    fn tick<'a>(&'a mut self, _ctx: &'a mut BotContext<'_>) -> HandlerFuture<'a> {
+        done()
+    }
+}
+
+/// What a handler can use. What it sends waits until the callback returned, so a handler that
+/// fails sends nothing.
+// the built-in handlers don't need all of it
+#[allow(dead_code)]
+pub struct BotContext<'a> {
+    pub storage: &'a Storage,
+    pub helix: &'a mut Helix,
+    // as it is right now, it may have been reloaded since the start
+    pub config: &'a Config,
+    commands: Vec<ChatBotCommand>,
+}
+
+impl BotContext<'_> {
+    /// A message to the channel, split when it is too long.
+    pub fn send(&mut self, channel: &str, text: String) {
+        self.commands.push(ChatBotCommand::SendMessage {
+            channel: channel.to_owned(),
+            text,
+            overflow: Overflow::Split,
+        });
+    }
+
+    /// Whatever else the core could answer, e.g. a [crate::core::HelixTask] or a timed event.
+    #[allow(dead_code)]
+    pub fn run(&mut self, command: ChatBotCommand) {
+        self.commands.push(command);
+    }
+}
+
+// the callback an event is for
+#[derive(Clone, Copy)]
+enum Call<'e> {
+    Message(&'e TextMessage),
+    Command(&'e Command),
+    UserNotice(&'e UserNotice),
+    StreamStatus(&'e str, bool),
+    Tick,
+}
+
+impl<'e> Call<'e> {
+    fn on<'a>(
+        self,
+        handler: &'a mut dyn EventHandler,
+        ctx: &'a mut BotContext<'_>,
+    ) -> HandlerFuture<'a>
+    where
+        'e: 'a,
+    {
+        match self {
+            Call::Message(message) => handler.message(ctx, message),
+            Call::Command(command) => handler.command(ctx, command),
+            Call::UserNotice(notice) => handler.user_notice(ctx, notice),
+            Call::StreamStatus(channel, live) => handler.stream_status(ctx, channel, live),
+            Call::Tick => handler.tick(ctx),
+        }
+    }
+
+    // the channel and the user of the event, for the log
+    fn about(self) -> (Option<&'e str>, Option<&'e str>) {
+        match self {
+            Call::Message(message) => (Some(&message.channel), Some(&message.user.name)),
+            Call::Command(command) => (
+                Some(&command.message.channel),
+                Some(&command.message.user.name),
+            ),
+            Call::UserNotice(notice) => (Some(&notice.channel), Some(&notice.user.name)),
+            Call::StreamStatus(channel, _) => (Some(channel), None),
+            Call::Tick => (None, None),
+        }
+    }
+}
+
+// a panic while polling ends the future instead of the bot
+struct CatchUnwind<'a>(HandlerFuture<'a>);
+
+impl Future for CatchUnwind<'_> {
+    type Output = thread::Result<HandlerResult>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = &mut self.0;
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(result)) => Poll::Ready(Ok(result)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+/// The registered handlers, in their order.
+#[derive(Default)]
+pub struct Handlers {
+    handlers: Vec<Box<dyn EventHandler>>,
+    storage: Storage,
+    // the latest status of each stream
+    live: HashMap<String, bool>,
+}
+
+impl Handlers {
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            ..Default::default()
+        }
+    }
+
+    /// Runs after the handlers registered before.
+    pub fn register(&mut self, handler: Box<dyn EventHandler>) {
+        self.handlers.push(handler);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    fn call<'e>(&mut self, event: &'e ChatBotEvent) -> Option<Call<'e>> {
+        match event {
+            ChatBotEvent::TextMessage(message) => Some(Call::Message(message)),
+            ChatBotEvent::Command(command) => Some(Call::Command(command)),
+            ChatBotEvent::UserNotice(notice) => Some(Call::UserNotice(notice)),
+            ChatBotEvent::LiveStatus { channel, live } => {
+                let before = self.live.insert(channel.clone(), *live);
+                before
+                    .is_some_and(|before| before != *live)
+                    .then_some(Call::StreamStatus(channel, *live))
+            }
+            ChatBotEvent::Internal(InternalEvent::TimerTick) => Some(Call::Tick),
+            _ => None,
+        }
+    }
+
+    /// Runs each handler for the event, what they sent in their order.
+    pub async fn handle(
+        &mut self,
+        event: &ChatBotEvent,
+        helix: &mut Helix,
+        config: &Config,
+    ) -> Option<ChatBotCommand> {
+        let call = self.call(event)?;
+        let (channel, user) = call.about();
+        let mut commands = Vec::new();
+        for handler in &mut self.handlers {
+            let name = handler.name();
+            let mut ctx = BotContext {
+                storage: &self.storage,
+                helix,
+                config,
+                commands: Vec::new(),
+            };
+            let started = Instant::now();
+            let result = CatchUnwind(call.on(handler.as_mut(), &mut ctx)).await;
+            HANDLER_DURATION.observe(&[name], started.elapsed());
+            match result {
+                Ok(Ok(())) => commands.append(&mut ctx.commands),
+                Ok(Err(error)) => {
+                    tracing::warn!(
+                        handler = name,
+                        channel,
+                        user,
+                        %error,
+                        "handler failed, skipping it"
+                    );
+                    HANDLER_FAILURES.inc(&[name, "error"]);
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        handler = name,
+                        channel,
+                        user,
+                        "handler panicked, skipping it"
+                    );
+                    HANDLER_FAILURES.inc(&[name, "panic"]);
+                }
+            }
+        }
+        match commands.len() {
+            0 | 1 => commands.pop(),
+            _ => Some(ChatBotCommand::MultipleCommands(commands)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    /// The handlers' answer to the event, without helix and with the default config.
+    pub async fn handle(handlers: &mut Handlers, event: &ChatBotEvent) -> Option<ChatBotCommand> {
+        let config = Config::default();
+        handlers.handle(event, &mut Helix::default(), &config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::handle;
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    // what each handler was called with, in the order of the calls
+    type Calls = Rc<RefCell<Vec<String>>>;
+
+    enum Behavior {
+        Answer,
+        Fail,
+        Panic,
+    }
+
+    struct Recorder {
+        name: &'static str,
+        behavior: Behavior,
+        calls: Calls,
+    }
+
+    impl EventHandler for Recorder {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn message<'a>(
+            &'a mut self,
+            ctx: &'a mut BotContext<'_>,
+            message: &'a TextMessage,
+        ) -> HandlerFuture<'a> {
+            Box::pin(async move {
+                self.calls.borrow_mut().push(self.name.to_owned());
+                ctx.storage.append("handled", self.name)?;
+                ctx.send(&message.channel, format!("{} answers", self.name));
+                // what was sent before failing is dropped
+                tokio::task::yield_now().await;
+                match self.behavior {
+                    Behavior::Answer => Ok(()),
+                    Behavior::Fail => Err(format!("{} fails", self.name).into()),
+                    Behavior::Panic => panic!("{} panics", self.name),
+                }
+            })
+        }
+
+        fn stream_status<'a>(
+            &'a mut self,
+            _ctx: &'a mut BotContext<'_>,
+            channel: &'a str,
+            live: bool,
+        ) -> HandlerFuture<'a> {
+            self.calls
+                .borrow_mut()
+                .push(format!("{} {} {}", self.name, channel, live));
+            done()
+        }
+    }
+
+    fn handlers(storage: Storage, behaviors: Vec<(&'static str, Behavior)>) -> (Handlers, Calls) {
+        let calls = Calls::default();
+        let mut handlers = Handlers::new(storage);
+        for (name, behavior) in behaviors {
+            handlers.register(Box::new(Recorder {
+                name,
+                behavior,
+                calls: calls.clone(),
+            }));
+        }
+        (handlers, calls)
+    }
+
+    fn texts(command: Option<ChatBotCommand>) -> Vec<String> {
+        match command {
+            Some(ChatBotCommand::MultipleCommands(commands)) => {
+                commands.into_iter().flat_map(|c| texts(Some(c))).collect()
+            }
+            Some(ChatBotCommand::SendMessage { text, .. }) => vec![text],
+            _ => Vec::new(),
+        }
+    }
+
+    fn message() -> ChatBotEvent {
+        ChatBotEvent::TextMessage(TextMessage {
+            channel: "carkhy".to_owned(),
+            text: "hi".to_owned(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn failing_handlers_are_skipped_in_order() {
+        let storage = Storage::memory();
+        let (mut handlers, calls) = handlers(
+            storage.clone(),
+            vec![
+                ("first", Behavior::Answer),
+                ("failing", Behavior::Fail),
+                ("panicking", Behavior::Panic),
+                ("last", Behavior::Answer),
+            ],
+        );
+        for _ in 0..2 {
+            let answer = handle(&mut handlers, &message()).await;
+            assert_eq!(texts(answer), vec!["first answers", "last answers"]);
+        }
+        // the failing handlers see the next event again
+        assert_eq!(
+            *calls.borrow(),
+            ["first", "failing", "panicking", "last"].repeat(2)
+        );
+        // what they did besides sending stays done
+        for name in ["first", "failing", "panicking", "last"] {
+            assert!(storage.has_line("handled", name).unwrap());
+        }
+        let failures = crate::prometheus::render();
+        assert!(failures
+            .contains("chatbot_handler_failures_total{handler=\"panicking\",kind=\"panic\"} 2\n"));
+        assert!(failures
+            .contains("chatbot_handler_failures_total{handler=\"failing\",kind=\"error\"} 2\n"));
+        assert!(failures.contains("chatbot_handler_duration_seconds_count{handler=\"last\"} 2\n"));
+    }
+
+    #[tokio::test]
+    async fn only_changes_of_the_stream_status_are_told() {
+        let (mut handlers, calls) = handlers(Storage::memory(), vec![("status", Behavior::Answer)]);
+        for live in [true, true, false, true] {
+            let event = ChatBotEvent::LiveStatus {
+                channel: "carkhy".to_owned(),
+                live,
+            };
+            assert!(handle(&mut handlers, &event).await.is_none());
+        }
+        assert_eq!(
+            *calls.borrow(),
+            vec!["status carkhy false", "status carkhy true"]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use super::{features::ANNOUNCEMENTS, Helix, HelixError, User};
+use crate::config::AnnouncementColor;
+use serde_json::json;
+
+impl Helix {
+    /// Highlights the text in the broadcaster's chat, sent by the bot's user as a moderator.
+    /// Twitch takes up to 500 characters.
+    // https://dev.twitch.tv/docs/api/reference/#send-chat-announcement
+    pub async fn announce(
+        &mut self,
+        broadcaster: &User,
+        text: &str,
+        color: AnnouncementColor,
+    ) -> Result<(), HelixError> {
+        self.enabled(&ANNOUNCEMENTS)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+        ];
+        let body = json!({ "message": text, "color": color.as_str() });
+        self.post_json(ANNOUNCEMENTS.identity, "chat/announcements", &query, &body)
+            .await
+            .map_err(|error| self.scope_needed(error, ANNOUNCEMENTS.scope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+
+    #[tokio::test]
+    async fn every_color_is_announced() {
+        let announcement = "/chat/announcements?broadcaster_id=1&moderator_id=3";
+        let colors = [
+            AnnouncementColor::Primary,
+            AnnouncementColor::Blue,
+            AnnouncementColor::Green,
+            AnnouncementColor::Orange,
+            AnnouncementColor::Purple,
+        ];
+        let mut answers = vec![(
+            "/users?login=botanist",
+            200,
+            r#"{"data":[{"id":"3","login":"botanist","display_name":"Botanist"}]}"#,
+        )];
+        answers.extend(colors.iter().map(|_| (announcement, 204, "")));
+        answers.push((
+            announcement,
+            401,
+            r#"{"error":"Unauthorized","status":401,"message":"Missing scope: moderator:manage:announcements"}"#,
+        ));
+        let mut helix = server(answers);
+        let broadcaster = User {
+            id: "1".to_owned(),
+            login: "captaincallback".to_owned(),
+            display_name: "CaptainCallback".to_owned(),
+        };
+        for color in colors {
+            helix.announce(&broadcaster, "Hi", color).await.unwrap();
+        }
+        assert!(matches!(
+            helix
+                .announce(&broadcaster, "Hi", AnnouncementColor::Blue)
+                .await,
+            Err(HelixError::MissingScope("moderator:manage:announcements"))
+        ));
+    }
+}
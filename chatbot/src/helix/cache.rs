@@ -0,0 +1,66 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Answers kept for a while, so the same question doesn't reach twitch again and again.
+#[derive(Debug)]
+pub struct Cache<K, V> {
+    ttl: Duration,
+    entries: HashMap<K, (Instant, V)>,
+}
+
+impl<K: Eq + Hash, V: Clone> Cache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// None once the value is older than the time to live.
+    pub fn get<Q: Eq + Hash + ?Sized>(&self, key: &Q, now: Instant) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let (stored, value) = self.entries.get(key)?;
+        (now < *stored + self.ttl).then(|| value.clone())
+    }
+
+    pub fn remove<Q: Eq + Hash + ?Sized>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.remove(key);
+    }
+
+    pub fn insert(&mut self, key: K, value: V, now: Instant) {
+        // expired entries would pile up with every user asked about
+        self.entries
+            .retain(|_, (stored, _)| now < *stored + self.ttl);
+        self.entries.insert(key, (now, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_expire() {
+        let now = Instant::now();
+        let mut cache = Cache::new(Duration::from_secs(30));
+        cache.insert("captaincallback".to_owned(), 1, now);
+        assert_eq!(
+            cache.get("captaincallback", now + Duration::from_secs(29)),
+            Some(1)
+        );
+        assert_eq!(
+            cache.get("captaincallback", now + Duration::from_secs(30)),
+            None
+        );
+        assert_eq!(cache.get("carkhy", now), None);
+    }
+}
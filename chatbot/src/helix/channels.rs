@@ -0,0 +1,137 @@
+use super::{
+    features::{CHANNEL, COMMERCIALS, SHOUTOUTS},
+    Game, Helix, HelixError, User,
+};
+use crate::connect::Identity;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Instant;
+
+/// What a broadcaster set for their channel, kept after the stream ended.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Channel {
+    pub broadcaster_login: String,
+    pub broadcaster_name: String,
+    // empty if none was ever set
+    pub game_name: String,
+    pub title: String,
+}
+
+/// The ad twitch started, it may be shorter than asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Commercial {
+    // seconds
+    pub length: u64,
+    // seconds until the next commercial can run
+    pub retry_after: u64,
+}
+
+/// What `!settitle` and `!setgame` change.
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelChange<'a> {
+    Title(&'a str),
+    Game(&'a Game),
+}
+
+impl Helix {
+    // https://dev.twitch.tv/docs/api/reference/#get-channel-information
+    pub async fn channel(&mut self, broadcaster: &User) -> Result<Option<Channel>, HelixError> {
+        if let Some(channel) = self.channels.get(&broadcaster.id, Instant::now()) {
+            return Ok(channel);
+        }
+        let channels: Vec<Channel> = self
+            .get(
+                Identity::Bot,
+                "channels",
+                &[("broadcaster_id", &broadcaster.id)],
+            )
+            .await?;
+        let channel = channels.into_iter().next();
+        self.channels
+            .insert(broadcaster.id.clone(), channel.clone(), Instant::now());
+        Ok(channel)
+    }
+
+    /// Changes the title or the game, only the broadcaster's token may.
+    // https://dev.twitch.tv/docs/api/reference/#modify-channel-information
+    pub async fn modify_channel(
+        &mut self,
+        broadcaster: &User,
+        change: ChannelChange<'_>,
+    ) -> Result<(), HelixError> {
+        self.enabled(&CHANNEL)?;
+        let body = match change {
+            ChannelChange::Title(title) => json!({ "title": title }),
+            ChannelChange::Game(game) => json!({ "game_id": game.id }),
+        };
+        let query = [("broadcaster_id", &*broadcaster.id)];
+        self.patch(CHANNEL.identity, "channels", &query, &body)
+            .await
+            .map_err(|error| self.scope_needed(error, CHANNEL.scope))?;
+        self.channels.remove(&broadcaster.id);
+        Ok(())
+    }
+
+    /// Runs an ad of the length in seconds, only the broadcaster's token may.
+    /// Twitch refuses with 400 when the channel can't run ads, and with 429 while the
+    /// last one is too recent.
+    // https://dev.twitch.tv/docs/api/reference/#start-commercial
+    pub async fn start_commercial(
+        &mut self,
+        broadcaster: &User,
+        length: u32,
+    ) -> Result<Option<Commercial>, HelixError> {
+        self.enabled(&COMMERCIALS)?;
+        let body = json!({ "broadcaster_id": broadcaster.id, "length": length });
+        match self
+            .post_data(
+                COMMERCIALS.identity,
+                "channels/commercial",
+                &[],
+                Some(&body),
+            )
+            .await
+        {
+            Ok(commercials) => Ok(commercials.into_iter().next()),
+            Err(error) => Err(self.scope_needed(error, COMMERCIALS.scope)),
+        }
+    }
+
+    /// The shoutout twitch shows in chat of a live stream, the bot has to moderate the channel.
+    // https://dev.twitch.tv/docs/api/reference/#send-a-shoutout
+    pub async fn shoutout(&mut self, from: &User, to: &User) -> Result<(), HelixError> {
+        self.enabled(&SHOUTOUTS)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("from_broadcaster_id", &*from.id),
+            ("to_broadcaster_id", &*to.id),
+            ("moderator_id", &*moderator.id),
+        ];
+        self.post(SHOUTOUTS.identity, "chat/shoutouts", &query)
+            .await
+            .map_err(|error| self.scope_needed(error, SHOUTOUTS.scope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+
+    #[tokio::test]
+    async fn channels_keep_their_game() {
+        let mut helix = server(vec![(
+            "/channels?broadcaster_id=141981764",
+            200,
+            r#"{"data":[{"broadcaster_id":"141981764","broadcaster_login":"carkhy","broadcaster_name":"Carkhy","broadcaster_language":"en","game_id":"504461","game_name":"Celeste","title":"Any% attempts","delay":0,"tags":[]}]}"#,
+        )]);
+        let carkhy = User {
+            id: "141981764".to_owned(),
+            login: "carkhy".to_owned(),
+            display_name: "Carkhy".to_owned(),
+        };
+        let channel = helix.channel(&carkhy).await.unwrap().unwrap();
+        assert_eq!(channel.game_name, "Celeste");
+        assert_eq!(helix.channel(&carkhy).await.unwrap(), Some(channel));
+    }
+}
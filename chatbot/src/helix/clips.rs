@@ -0,0 +1,48 @@
+use super::{features::CLIPS, Helix, HelixError, User};
+use crate::connect::Identity;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+/// A clip twitch is still making, it can be watched after a few seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PendingClip {
+    pub id: String,
+    // where the broadcaster edits the clip, available right away
+    pub edit_url: String,
+}
+
+#[derive(Deserialize)]
+struct Clip {
+    url: String,
+}
+
+impl Helix {
+    /// Clips the last seconds of the stream, None while the channel is offline.
+    // https://dev.twitch.tv/docs/api/reference/#create-clip
+    pub async fn create_clip(
+        &mut self,
+        broadcaster: &User,
+    ) -> Result<Option<PendingClip>, HelixError> {
+        self.enabled(&CLIPS)?;
+        let query = [("broadcaster_id", &*broadcaster.id)];
+        match self
+            .post_data::<PendingClip>(CLIPS.identity, "clips", &query, None)
+            .await
+        {
+            Ok(clips) => Ok(clips.into_iter().next()),
+            // "Clipping is not possible for an offline channel."
+            Err(HelixError::Status {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }) => Ok(None),
+            Err(error) => Err(self.scope_needed(error, CLIPS.scope)),
+        }
+    }
+
+    /// The url to watch the clip at, None while twitch is still making it.
+    // https://dev.twitch.tv/docs/api/reference/#get-clips
+    pub async fn clip_url(&self, id: &str) -> Result<Option<String>, HelixError> {
+        let clips: Vec<Clip> = self.get(Identity::Bot, "clips", &[("id", id)]).await?;
+        Ok(clips.into_iter().next().map(|clip| clip.url))
+    }
+}
@@ -0,0 +1,119 @@
+use super::{Helix, HelixError, User};
+use crate::connect::Identity;
+use serde_json::json;
+use std::fmt;
+
+/// What EventSub tells the bot about, each subscribed to in every channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscription {
+    Redemptions,
+    Follows,
+    Subs,
+    StreamOnline,
+    StreamOffline,
+    HypeTrainBegin,
+    HypeTrainProgress,
+    HypeTrainEnd,
+    ShieldModeBegin,
+    ShieldModeEnd,
+}
+
+impl Subscription {
+    fn kind(self) -> &'static str {
+        match self {
+            Subscription::Redemptions => "channel.channel_points_custom_reward_redemption.add",
+            Subscription::Follows => "channel.follow",
+            Subscription::Subs => "channel.subscribe",
+            Subscription::StreamOnline => "stream.online",
+            Subscription::StreamOffline => "stream.offline",
+            Subscription::HypeTrainBegin => "channel.hype_train.begin",
+            Subscription::HypeTrainProgress => "channel.hype_train.progress",
+            Subscription::HypeTrainEnd => "channel.hype_train.end",
+            Subscription::ShieldModeBegin => "channel.shield_mode.begin",
+            Subscription::ShieldModeEnd => "channel.shield_mode.end",
+        }
+    }
+
+    // the stream's status is public
+    fn scope(self) -> Option<&'static str> {
+        match self {
+            Subscription::Redemptions => Some("channel:manage:redemptions"),
+            Subscription::Follows => Some("moderator:read:followers"),
+            Subscription::Subs => Some("channel:read:subscriptions"),
+            Subscription::StreamOnline | Subscription::StreamOffline => None,
+            Subscription::HypeTrainBegin
+            | Subscription::HypeTrainProgress
+            | Subscription::HypeTrainEnd => Some("channel:read:hype_train"),
+            Subscription::ShieldModeBegin | Subscription::ShieldModeEnd => {
+                Some("moderator:manage:shield_mode")
+            }
+        }
+    }
+}
+
+// for the log
+impl fmt::Display for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Subscription::Redemptions => "the redemptions",
+            Subscription::Follows => "the follows",
+            Subscription::Subs => "the subs",
+            Subscription::StreamOnline => "the stream going live",
+            Subscription::StreamOffline => "the stream going offline",
+            Subscription::HypeTrainBegin => "the hype trains beginning",
+            Subscription::HypeTrainProgress => "the hype trains going on",
+            Subscription::HypeTrainEnd => "the hype trains ending",
+            Subscription::ShieldModeBegin => "shield mode turning on",
+            Subscription::ShieldModeEnd => "shield mode turning off",
+        })
+    }
+}
+
+impl Helix {
+    /// Has EventSub tell the session about the broadcaster's events. Twitch only allows it
+    /// with the broadcaster's own token, follows and shield mode with a moderator's as well.
+    // https://dev.twitch.tv/docs/api/reference/#create-eventsub-subscription
+    pub async fn subscribe(
+        &mut self,
+        broadcaster: &User,
+        subscription: Subscription,
+        session_id: &str,
+    ) -> Result<(), HelixError> {
+        let (version, condition) = match subscription {
+            Subscription::Follows | Subscription::ShieldModeBegin | Subscription::ShieldModeEnd => {
+                let moderator = self.moderator().await?;
+                let condition = json!({
+                    "broadcaster_user_id": broadcaster.id,
+                    "moderator_user_id": moderator.id,
+                });
+                let version = match subscription {
+                    Subscription::Follows => "2",
+                    _ => "1",
+                };
+                (version, condition)
+            }
+            _ => ("1", json!({ "broadcaster_user_id": broadcaster.id })),
+        };
+        let body = json!({
+            "type": subscription.kind(),
+            "version": version,
+            "condition": condition,
+            "transport": { "method": "websocket", "session_id": session_id },
+        });
+        match self
+            .post_data::<serde_json::Value>(
+                Identity::Bot,
+                "eventsub/subscriptions",
+                &[],
+                Some(&body),
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => match subscription.scope() {
+                Some(scope) => Err(self.scope_needed(error, scope)),
+                None => Err(error),
+            },
+        }
+    }
+}
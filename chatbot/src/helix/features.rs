@@ -0,0 +1,237 @@
+//! What the bot does with Helix that needs a scope, and whose token it needs for it. At the
+//! start the tokens are checked against this list, so that a missing token or scope is
+//! reported once instead of failing at the first use.
+use crate::{
+    config::Config,
+    connect::{required_scopes, Identity, TokenInfo},
+};
+
+/// A use of Helix, e.g. starting polls for `!twitchpoll`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feature {
+    pub name: &'static str,
+    pub identity: Identity,
+    pub scope: &'static str,
+}
+
+const fn feature(name: &'static str, identity: Identity, scope: &'static str) -> Feature {
+    Feature {
+        name,
+        identity,
+        scope,
+    }
+}
+
+pub const CHANNEL: Feature = feature(
+    "!settitle and !setgame",
+    Identity::Broadcaster,
+    "channel:manage:broadcast",
+);
+pub const MARKERS: Feature = feature("!marker", Identity::Broadcaster, "channel:manage:broadcast");
+pub const COMMERCIALS: Feature = feature(
+    "!commercial",
+    Identity::Broadcaster,
+    "channel:edit:commercial",
+);
+pub const POLLS: Feature = feature("!twitchpoll", Identity::Broadcaster, "channel:manage:polls");
+pub const PREDICTIONS: Feature = feature(
+    "!prediction",
+    Identity::Broadcaster,
+    "channel:manage:predictions",
+);
+pub const VIPS: Feature = feature("!vip", Identity::Broadcaster, "channel:manage:vips");
+pub const MODERATORS: Feature = feature("!mod", Identity::Broadcaster, "channel:manage:moderators");
+pub const CLIPS: Feature = feature("!clip", Identity::Bot, "clips:edit");
+pub const FOLLOWERS: Feature = feature("!followage", Identity::Bot, "moderator:read:followers");
+pub const SHOUTOUTS: Feature = feature("!so", Identity::Bot, "moderator:manage:shoutouts");
+pub const ANNOUNCEMENTS: Feature = feature(
+    "announcements",
+    Identity::Bot,
+    "moderator:manage:announcements",
+);
+pub const DELETIONS: Feature = feature(
+    "deleting messages",
+    Identity::Bot,
+    "moderator:manage:chat_messages",
+);
+pub const BANS: Feature = feature(
+    "timeouts and bans",
+    Identity::Bot,
+    "moderator:manage:banned_users",
+);
+pub const CHAT_SETTINGS: Feature = feature(
+    "chat settings",
+    Identity::Bot,
+    "moderator:manage:chat_settings",
+);
+pub const SHIELD_MODE: Feature = feature("!shield", Identity::Bot, "moderator:manage:shield_mode");
+pub const WHISPERS: Feature = feature("whispers", Identity::Bot, "user:manage:whispers");
+pub const REDEMPTIONS: Feature = feature(
+    "fulfilling redemptions",
+    Identity::Bot,
+    "channel:manage:redemptions",
+);
+
+pub const ALL: [Feature; 17] = [
+    CHANNEL,
+    MARKERS,
+    COMMERCIALS,
+    POLLS,
+    PREDICTIONS,
+    VIPS,
+    MODERATORS,
+    CLIPS,
+    FOLLOWERS,
+    SHOUTOUTS,
+    ANNOUNCEMENTS,
+    DELETIONS,
+    BANS,
+    CHAT_SETTINGS,
+    SHIELD_MODE,
+    WHISPERS,
+    REDEMPTIONS,
+];
+
+/// The features the config uses that the tokens can't do, with the reason. A token is None
+/// when there is none or twitch says it is invalid.
+pub fn disabled(
+    config: &Config,
+    bot: Option<&TokenInfo>,
+    broadcaster: Option<&TokenInfo>,
+) -> Vec<(Feature, String)> {
+    let mut wanted = required_scopes(config, Identity::Bot);
+    if config.broadcaster.login.is_some() {
+        wanted.extend(required_scopes(config, Identity::Broadcaster));
+    }
+    ALL.into_iter()
+        .filter(|feature| wanted.contains(&feature.scope))
+        .filter_map(|feature| Some((feature, reason(config, feature, bot, broadcaster)?)))
+        .collect()
+}
+
+// why the feature can't be used, None if it can
+fn reason(
+    config: &Config,
+    feature: Feature,
+    bot: Option<&TokenInfo>,
+    broadcaster: Option<&TokenInfo>,
+) -> Option<String> {
+    let lacks = |token: &TokenInfo| !token.scopes.iter().any(|scope| scope == feature.scope);
+    let login = config.broadcaster.login.as_deref();
+    match (feature.identity, login) {
+        (Identity::Bot, _) | (Identity::Broadcaster, None) => match bot {
+            _ if config.twitch.anonymous => Some("the bot reads chat anonymously".to_owned()),
+            None => Some("the bot's token is invalid".to_owned()),
+            Some(token) if lacks(token) => Some(format!("the bot's token lacks {}", feature.scope)),
+            // the bot's token may only do it for the bot's own channel
+            Some(token)
+                if feature.identity == Identity::Broadcaster
+                    && !config.channel_names().contains(&token.login) =>
+            {
+                Some("the bot isn't the broadcaster, set broadcaster.login".to_owned())
+            }
+            Some(_) => None,
+        },
+        (Identity::Broadcaster, Some(login)) => match broadcaster {
+            None => Some("the broadcaster's token is missing or invalid".to_owned()),
+            Some(token) if !token.login.eq_ignore_ascii_case(login) => Some(format!(
+                "the broadcaster's token is of {}, not of {}",
+                token.login, login
+            )),
+            Some(token) if lacks(token) => {
+                Some(format!("the broadcaster's token lacks {}", feature.scope))
+            }
+            Some(_) => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn token(login: &str, scopes: &[&str]) -> TokenInfo {
+        TokenInfo {
+            login: login.to_owned(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            expires_in: Duration::from_secs(3600),
+        }
+    }
+
+    fn config(broadcaster: Option<&str>) -> Config {
+        let mut config = Config::default();
+        config.broadcaster.login = broadcaster.map(str::to_owned);
+        config
+    }
+
+    fn names(disabled: &[(Feature, String)]) -> Vec<&'static str> {
+        disabled.iter().map(|(feature, _)| feature.name).collect()
+    }
+
+    #[test]
+    fn only_the_bot_token_disables_the_broadcasters_features() {
+        let config = config(None);
+        let granted = required_scopes(&config, Identity::Bot);
+        let bot = token("carkhybot", &granted);
+        let off = disabled(&config, Some(&bot), None);
+        assert_eq!(
+            names(&off),
+            [
+                "!settitle and !setgame",
+                "!marker",
+                "!commercial",
+                "!twitchpoll",
+                "!prediction",
+                "!vip",
+                "!mod"
+            ]
+        );
+        assert_eq!(
+            off[0].1,
+            "the bot isn't the broadcaster, set broadcaster.login"
+        );
+        // the bot logs in as the broadcaster
+        let broadcaster = token("captaincallback", &granted);
+        assert_eq!(disabled(&config, Some(&broadcaster), None), []);
+        let config = self::config(Some("captaincallback"));
+        let bot = token("carkhybot", &required_scopes(&config, Identity::Bot));
+        let off = disabled(&config, Some(&bot), None);
+        assert_eq!(off.len(), 7);
+        assert_eq!(
+            off[3],
+            (
+                POLLS,
+                "the broadcaster's token is missing or invalid".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn both_tokens_enable_everything() {
+        let config = config(Some("CaptainCallback"));
+        let bot = token("carkhybot", &required_scopes(&config, Identity::Bot));
+        let broadcaster = token(
+            "captaincallback",
+            &required_scopes(&config, Identity::Broadcaster),
+        );
+        assert_eq!(disabled(&config, Some(&bot), Some(&broadcaster)), []);
+        let wrong = token("carkhy", &required_scopes(&config, Identity::Broadcaster));
+        let off = disabled(&config, Some(&bot), Some(&wrong));
+        assert_eq!(
+            off[0].1,
+            "the broadcaster's token is of carkhy, not of CaptainCallback"
+        );
+        let partial = token("captaincallback", &["channel:manage:polls"]);
+        let off = disabled(&config, Some(&bot), Some(&partial));
+        assert!(!names(&off).contains(&"!twitchpoll"));
+        assert_eq!(
+            off[0].1,
+            "the broadcaster's token lacks channel:manage:broadcast"
+        );
+        let chat_only = token("carkhybot", &["chat:read", "chat:edit"]);
+        let off = disabled(&config, Some(&chat_only), Some(&broadcaster));
+        assert!(names(&off).contains(&"!clip"));
+        assert!(!names(&off).contains(&"!marker"));
+    }
+}
@@ -0,0 +1,35 @@
+use super::{Helix, HelixError};
+use crate::connect::Identity;
+use serde::Deserialize;
+
+// close matches suggested for a game that doesn't exist
+const SUGGESTIONS: &str = "5";
+
+/// A game, or another category like "Just Chatting".
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Game {
+    pub id: String,
+    pub name: String,
+}
+
+impl Helix {
+    /// The game named exactly like this, ignoring case.
+    // https://dev.twitch.tv/docs/api/reference/#get-games
+    pub async fn game(&self, name: &str) -> Result<Option<Game>, HelixError> {
+        let games: Vec<Game> = self.get(Identity::Bot, "games", &[("name", name)]).await?;
+        Ok(games.into_iter().next())
+    }
+
+    /// Names of the categories a search for the name finds, the best match first.
+    // https://dev.twitch.tv/docs/api/reference/#search-categories
+    pub async fn similar_games(&self, name: &str) -> Result<Vec<String>, HelixError> {
+        let games: Vec<Game> = self
+            .get(
+                Identity::Bot,
+                "search/categories",
+                &[("query", name), ("first", SUGGESTIONS)],
+            )
+            .await?;
+        Ok(games.into_iter().map(|game| game.name).collect())
+    }
+}
@@ -0,0 +1,479 @@
+mod announcements;
+mod cache;
+mod channels;
+mod clips;
+mod eventsub;
+mod features;
+mod games;
+mod moderation;
+mod polls;
+mod predictions;
+mod redemptions;
+mod roles;
+mod shield;
+mod streams;
+#[cfg(test)]
+pub mod testing;
+mod user_cache;
+mod users;
+mod whispers;
+
+pub use channels::{Channel, ChannelChange};
+pub use eventsub::Subscription;
+pub use features::Feature;
+pub use games::Game;
+pub use moderation::ChatSetting;
+pub use predictions::{Prediction, PredictionEnd};
+pub use roles::Role;
+pub use streams::Stream;
+pub use users::User;
+
+use crate::{
+    config::Config,
+    connect::{ConnectorError, Identity, SharedTokens},
+    prometheus::HELIX_REQUESTS,
+};
+use cache::Cache;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use user_cache::UserCache;
+
+const HELIX_URL: &str = "https://api.twitch.tv/helix";
+// events wait while a request runs, so it may not take long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// `!uptime` spammed in chat asks twitch only once
+const STREAM_TTL: Duration = Duration::from_secs(30);
+// logins are rarely renamed, ids never change
+const USER_TTL: Duration = Duration::from_secs(60 * 60);
+// a login that doesn't exist may be taken meanwhile
+const UNKNOWN_USER_TTL: Duration = Duration::from_secs(5 * 60);
+const USER_CACHE_SIZE: usize = 10_000;
+const FOLLOW_TTL: Duration = Duration::from_secs(5 * 60);
+const CHANNEL_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum HelixError {
+    #[error("Helix needs a token, the bot reads chat anonymously")]
+    NoToken,
+    #[error("Could not renew the token for Helix: {0}")]
+    Token(ConnectorError),
+    #[error("Request to Helix failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Helix answered {status}: {message}")]
+    Status { status: StatusCode, message: String },
+    #[error("The token lacks the scope {0}, or the bot's user lacks the rights it grants")]
+    MissingScope(&'static str),
+    #[error("{0} doesn't accept whispers from the bot")]
+    WhispersBlocked(String),
+    #[error("Twitch allows no more whispers for now")]
+    RateLimited,
+    #[error("The bot's user doesn't moderate the channel")]
+    NotModerator,
+}
+
+// what helix answers with for a failed request
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+// every answer lists what was asked for in "data"
+#[derive(Deserialize)]
+struct Data<T> {
+    data: Vec<T>,
+}
+
+/// Twitch's HTTP API, called with the token the bot logged in to chat with, or the
+/// broadcaster's for what only the broadcaster may do.
+pub struct Helix {
+    http: reqwest::Client,
+    url: String,
+    client_id: String,
+    // login of the bot's user, who moderates when asking for the broadcaster
+    login: String,
+    tokens: Option<SharedTokens>,
+    // of `broadcaster.login`, the bot's tokens are used without
+    broadcaster: Option<SharedTokens>,
+    // the names of the features the tokens can't do, found at the start
+    disabled: HashSet<&'static str>,
+    // by login, None while offline
+    streams: Cache<String, Option<Stream>>,
+    // by lowercase login
+    users: UserCache,
+    // by broadcaster and user id, None if the user doesn't follow
+    follows: Cache<(String, String), Option<String>>,
+    // by broadcaster id
+    channels: Cache<String, Option<Channel>>,
+    // by broadcaster id, the prediction the bot started last, until it ended
+    predictions: HashMap<String, String>,
+    // the setup hint for a missing scope is logged only once
+    hinted: HashSet<&'static str>,
+}
+
+impl Default for Helix {
+    // without a token every request fails with NoToken, e.g. in replays
+    fn default() -> Self {
+        Self::new(HELIX_URL, String::new(), String::new(), None, None)
+    }
+}
+
+impl Helix {
+    fn new(
+        url: &str,
+        client_id: String,
+        login: String,
+        tokens: Option<SharedTokens>,
+        broadcaster: Option<SharedTokens>,
+    ) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("the HTTP client has no unusual settings"),
+            url: url.to_owned(),
+            client_id,
+            login,
+            tokens,
+            broadcaster,
+            disabled: HashSet::new(),
+            streams: Cache::new(STREAM_TTL),
+            users: UserCache::new(USER_CACHE_SIZE, USER_TTL, UNKNOWN_USER_TTL),
+            follows: Cache::new(FOLLOW_TTL),
+            channels: Cache::new(CHANNEL_TTL),
+            predictions: HashMap::new(),
+            hinted: HashSet::new(),
+        }
+    }
+
+    pub fn connect(
+        config: &Config,
+        tokens: Option<SharedTokens>,
+        broadcaster: Option<SharedTokens>,
+    ) -> Self {
+        Self::new(
+            HELIX_URL,
+            config.twitch.client_id.clone(),
+            config.twitch.user.to_lowercase(),
+            tokens,
+            broadcaster,
+        )
+    }
+
+    /// Checks both tokens with twitch and disables the features they can't do, one line for
+    /// each. If twitch can't be asked nothing is disabled, the requests fail on their own.
+    pub async fn check(&mut self, config: &Config) -> Vec<String> {
+        let mut tokens = Vec::new();
+        for shared in [&self.tokens, &self.broadcaster] {
+            tokens.push(match shared {
+                Some(shared) => match shared.validate().await {
+                    Ok(info) => info,
+                    Err(error) => {
+                        tracing::warn!(%error, "could not check the tokens");
+                        return Vec::new();
+                    }
+                },
+                None => None,
+            });
+        }
+        features::disabled(config, tokens[0].as_ref(), tokens[1].as_ref())
+            .into_iter()
+            .map(|(feature, reason)| {
+                self.disabled.insert(feature.name);
+                format!("{} is disabled: {}", feature.name, reason)
+            })
+            .collect()
+    }
+
+    // a disabled feature fails before asking twitch
+    fn enabled(&self, feature: &Feature) -> Result<(), HelixError> {
+        match self.disabled.contains(feature.name) {
+            true => Err(HelixError::MissingScope(feature.scope)),
+            false => Ok(()),
+        }
+    }
+
+    fn tokens(&self, identity: Identity) -> Option<&SharedTokens> {
+        match identity {
+            Identity::Broadcaster => self.broadcaster.as_ref().or(self.tokens.as_ref()),
+            Identity::Bot => self.tokens.as_ref(),
+        }
+    }
+
+    // an expired token is refreshed once, then the request is sent again
+    async fn send(
+        &self,
+        identity: Identity,
+        request: impl Fn(&reqwest::Client, String) -> RequestBuilder,
+        path: &str,
+    ) -> Result<Response, HelixError> {
+        let tokens = self.tokens(identity).ok_or(HelixError::NoToken)?;
+        let url = format!("{}/{}", self.url, path);
+        let mut token = tokens.current().await;
+        let mut refreshed = false;
+        let endpoint = path.split('?').next().unwrap_or_default();
+        loop {
+            let start = Instant::now();
+            // the token is in a header, the path has no secrets
+            let response = request(&self.http, url.clone())
+                .bearer_auth(&token)
+                .header("Client-Id", &self.client_id)
+                .send()
+                .await
+                .inspect_err(|error| {
+                    tracing::warn!(path, %error, "helix request failed");
+                    HELIX_REQUESTS.inc(&[endpoint, "error"]);
+                })?;
+            let status = response.status();
+            HELIX_REQUESTS.inc(&[endpoint, status.as_str()]);
+            tracing::debug!(
+                path,
+                status = status.as_u16(),
+                latency_ms = start.elapsed().as_millis() as u64,
+                "helix request"
+            );
+            if status.is_success() {
+                return Ok(response);
+            }
+            let text = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<ErrorBody>(&text)
+                .map(|body| body.message)
+                .unwrap_or(text);
+            // a renewed token has the same scopes
+            if status == StatusCode::UNAUTHORIZED
+                && !refreshed
+                && !message.starts_with("Missing scope")
+            {
+                token = tokens.refresh().await.map_err(HelixError::Token)?;
+                refreshed = true;
+                continue;
+            }
+            return Err(HelixError::Status { status, message });
+        }
+    }
+
+    // twitch refuses with 401 or 403 when the token can't do what the scope grants
+    fn scope_needed(&mut self, error: HelixError, scope: &'static str) -> HelixError {
+        let HelixError::Status { status, message } = &error else {
+            return error;
+        };
+        if *status != StatusCode::UNAUTHORIZED && *status != StatusCode::FORBIDDEN {
+            return error;
+        }
+        if !self.hinted.insert(scope) {
+            return HelixError::MissingScope(scope);
+        }
+        let broadcasters = features::ALL
+            .iter()
+            .any(|feature| feature.scope == scope && feature.identity == Identity::Broadcaster);
+        if broadcasters && self.broadcaster.is_some() {
+            tracing::warn!(
+                %message,
+                scope,
+                store = %Identity::Broadcaster.store().display(),
+                "twitch refused a request, the broadcaster's token needs the scope: delete the \
+                 store and let the broadcaster authorize again"
+            );
+        } else {
+            tracing::warn!(
+                %message,
+                scope,
+                "twitch refused a request, the bot's token needs the scope: delete ./auth_store and \
+                 authorize the bot again, as the broadcaster or a moderator as the command needs"
+            );
+        }
+        HelixError::MissingScope(scope)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<T>, HelixError> {
+        let response = self
+            .send(identity, |http, url| http.get(url).query(query), path)
+            .await?;
+        Ok(response.json::<Data<T>>().await?.data)
+    }
+
+    // like get, for what twitch creates
+    async fn post_data<T: DeserializeOwned>(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<&serde_json::Value>,
+    ) -> Result<Vec<T>, HelixError> {
+        let request = |http: &reqwest::Client, url| {
+            let request = http.post(url).query(query);
+            match body {
+                Some(body) => request.json(body),
+                None => request,
+            }
+        };
+        let response = self.send(identity, request, path).await?;
+        Ok(response.json::<Data<T>>().await?.data)
+    }
+
+    // twitch answers with 204 No Content
+    async fn post(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<(), HelixError> {
+        self.send(identity, |http, url| http.post(url).query(query), path)
+            .await?;
+        Ok(())
+    }
+
+    // like post, with a body
+    async fn post_json(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+        body: &serde_json::Value,
+    ) -> Result<(), HelixError> {
+        self.send(
+            identity,
+            |http, url| http.post(url).query(query).json(body),
+            path,
+        )
+        .await?;
+        Ok(())
+    }
+
+    // twitch answers with 204 No Content
+    async fn delete(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<(), HelixError> {
+        self.send(identity, |http, url| http.delete(url).query(query), path)
+            .await?;
+        Ok(())
+    }
+
+    // like patch, for what twitch answers with the changed data
+    async fn patch_data<T: DeserializeOwned>(
+        &self,
+        identity: Identity,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<Vec<T>, HelixError> {
+        let response = self
+            .send(identity, |http, url| http.patch(url).json(body), path)
+            .await?;
+        Ok(response.json::<Data<T>>().await?.data)
+    }
+
+    // like patch_data, with a query
+    async fn put_data<T: DeserializeOwned>(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+        body: &serde_json::Value,
+    ) -> Result<Vec<T>, HelixError> {
+        let response = self
+            .send(
+                identity,
+                |http, url| http.put(url).query(query).json(body),
+                path,
+            )
+            .await?;
+        Ok(response.json::<Data<T>>().await?.data)
+    }
+
+    // twitch answers with 204 No Content
+    async fn patch(
+        &self,
+        identity: Identity,
+        path: &str,
+        query: &[(&str, &str)],
+        body: &serde_json::Value,
+    ) -> Result<(), HelixError> {
+        self.send(
+            identity,
+            |http, url| http.patch(url).query(query).json(body),
+            path,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// "2021-03-10T15:04:21Z" like helix writes times, fractions of a second are ignored.
+pub fn parse_time(text: &str) -> Option<SystemTime> {
+    let (date, time) = text.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hours > 23 || minutes > 59 {
+        return None;
+    }
+    // the days_from_civil algorithm by Howard Hinnant
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
+    let seconds = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+
+    #[test]
+    fn times_are_read_in_utc() {
+        let seconds = |text| {
+            parse_time(text)
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs())
+        };
+        assert_eq!(seconds("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(seconds("2000-02-29T00:00:00Z"), Some(951_782_400));
+        assert_eq!(seconds("2021-11-22T20:46:42.123Z"), Some(1_637_614_002));
+        assert_eq!(seconds("2021-11-22 20:46:42"), None);
+        assert_eq!(seconds("2021-13-22T20:46:42Z"), None);
+    }
+
+    #[tokio::test]
+    async fn broadcaster_features_use_its_token() {
+        let mut helix = server(vec![
+            (
+                "POST /streams/markers as broadcaster",
+                200,
+                r#"{"data":[]}"#,
+            ),
+            ("POST /clips?broadcaster_id=1", 202, r#"{"data":[]}"#),
+        ]);
+        helix.broadcaster = Some(SharedTokens::fixed("broadcaster"));
+        let channel = User {
+            id: "1".to_owned(),
+            login: "captaincallback".to_owned(),
+            display_name: "CaptainCallback".to_owned(),
+        };
+        assert!(helix.create_marker(&channel, "").await.is_ok());
+        assert!(helix.create_clip(&channel).await.is_ok());
+        // refused without asking twitch, the server has no more answers
+        helix.disabled.insert(features::POLLS.name);
+        assert!(matches!(
+            helix.enabled(&features::POLLS),
+            Err(HelixError::MissingScope("channel:manage:polls"))
+        ));
+    }
+}
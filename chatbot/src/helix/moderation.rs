@@ -0,0 +1,115 @@
+use super::{
+    features::{BANS, CHAT_SETTINGS, DELETIONS},
+    Helix, HelixError, User,
+};
+use serde_json::json;
+use std::time::Duration;
+
+/// A chat setting `!slow` and the like change, the raids turn slow mode off for a while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatSetting {
+    // seconds between two messages of a user, None turns slow mode off
+    Slow(Option<u32>),
+    EmoteOnly(bool),
+    // minutes a user has to follow before chatting, None turns followers-only mode off
+    Followers(Option<u32>),
+    Subscribers(bool),
+    UniqueChat(bool),
+}
+
+impl Helix {
+    /// Deletes a chat message by its id, the bot has to moderate the channel.
+    // https://dev.twitch.tv/docs/api/reference/#delete-chat-messages
+    pub async fn delete_message(
+        &mut self,
+        broadcaster: &User,
+        message_id: &str,
+    ) -> Result<(), HelixError> {
+        self.enabled(&DELETIONS)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+            ("message_id", message_id),
+        ];
+        self.delete(DELETIONS.identity, "moderation/chat", &query)
+            .await
+            .map_err(|error| self.scope_needed(error, DELETIONS.scope))
+    }
+
+    /// Times the user out for the duration, or bans them without one.
+    // https://dev.twitch.tv/docs/api/reference/#ban-user
+    pub async fn ban(
+        &mut self,
+        broadcaster: &User,
+        user: &User,
+        duration: Option<Duration>,
+        reason: &str,
+    ) -> Result<(), HelixError> {
+        self.enabled(&BANS)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+        ];
+        let mut data = json!({ "user_id": user.id, "reason": reason });
+        if let Some(duration) = duration {
+            data["duration"] = json!(duration.as_secs().max(1));
+        }
+        self.post_data::<serde_json::Value>(
+            BANS.identity,
+            "moderation/bans",
+            &query,
+            Some(&json!({ "data": data })),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|error| self.scope_needed(error, BANS.scope))
+    }
+
+    /// Lifts a ban or timeout of the user.
+    // https://dev.twitch.tv/docs/api/reference/#unban-user
+    pub async fn unban(&mut self, broadcaster: &User, user: &User) -> Result<(), HelixError> {
+        self.enabled(&BANS)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+            ("user_id", &*user.id),
+        ];
+        self.delete(BANS.identity, "moderation/bans", &query)
+            .await
+            .map_err(|error| self.scope_needed(error, BANS.scope))
+    }
+
+    /// Changes the one setting, the others stay as they are.
+    // https://dev.twitch.tv/docs/api/reference/#update-chat-settings
+    pub async fn update_chat_settings(
+        &mut self,
+        broadcaster: &User,
+        setting: ChatSetting,
+    ) -> Result<(), HelixError> {
+        self.enabled(&CHAT_SETTINGS)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+        ];
+        let body = match setting {
+            ChatSetting::Slow(Some(seconds)) => {
+                json!({ "slow_mode": true, "slow_mode_wait_time": seconds })
+            }
+            ChatSetting::Slow(None) => json!({ "slow_mode": false }),
+            ChatSetting::EmoteOnly(on) => json!({ "emote_mode": on }),
+            ChatSetting::Followers(Some(minutes)) => {
+                json!({ "follower_mode": true, "follower_mode_duration": minutes })
+            }
+            ChatSetting::Followers(None) => json!({ "follower_mode": false }),
+            ChatSetting::Subscribers(on) => json!({ "subscriber_mode": on }),
+            ChatSetting::UniqueChat(on) => json!({ "unique_chat_mode": on }),
+        };
+        self.patch(CHAT_SETTINGS.identity, "chat/settings", &query, &body)
+            .await
+            .map_err(|error| self.scope_needed(error, CHAT_SETTINGS.scope))
+    }
+}
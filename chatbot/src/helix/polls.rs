@@ -0,0 +1,63 @@
+use super::{features::POLLS, Helix, HelixError, User};
+use serde::Deserialize;
+use serde_json::json;
+
+/// A poll twitch shows on stream.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Poll {
+    pub id: String,
+    pub title: String,
+    pub choices: Vec<PollChoice>,
+    // ACTIVE while it takes votes, then COMPLETED, TERMINATED, ARCHIVED, MODERATED or INVALID
+    pub status: String,
+    // seconds
+    pub duration: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PollChoice {
+    pub title: String,
+    // with those bought with channel points
+    pub votes: u64,
+}
+
+impl Helix {
+    /// Starts a poll of the choices for the seconds, only the broadcaster's token may.
+    // https://dev.twitch.tv/docs/api/reference/#create-poll
+    pub async fn create_poll(
+        &mut self,
+        broadcaster: &User,
+        title: &str,
+        choices: &[String],
+        duration: u32,
+    ) -> Result<Option<Poll>, HelixError> {
+        self.enabled(&POLLS)?;
+        let choices: Vec<_> = choices
+            .iter()
+            .map(|choice| json!({ "title": choice }))
+            .collect();
+        let body = json!({
+            "broadcaster_id": broadcaster.id,
+            "title": title,
+            "choices": choices,
+            "duration": duration,
+        });
+        match self
+            .post_data(POLLS.identity, "polls", &[], Some(&body))
+            .await
+        {
+            Ok(polls) => Ok(polls.into_iter().next()),
+            Err(error) => Err(self.scope_needed(error, POLLS.scope)),
+        }
+    }
+
+    // https://dev.twitch.tv/docs/api/reference/#get-polls
+    pub async fn poll(&mut self, broadcaster: &User, id: &str) -> Result<Option<Poll>, HelixError> {
+        self.enabled(&POLLS)?;
+        let query = [("broadcaster_id", &*broadcaster.id), ("id", id)];
+        match self.get(POLLS.identity, "polls", &query).await {
+            Ok(polls) => Ok(polls.into_iter().next()),
+            Err(error) => Err(self.scope_needed(error, POLLS.scope)),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use super::{features::PREDICTIONS, Helix, HelixError, User};
+use serde::Deserialize;
+use serde_json::json;
+
+/// A prediction of channel points twitch shows on stream.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Prediction {
+    pub id: String,
+    pub title: String,
+    pub outcomes: Vec<Outcome>,
+    // ACTIVE while it takes predictions, then LOCKED, RESOLVED or CANCELED
+    pub status: String,
+    pub winning_outcome_id: Option<String>,
+    // seconds
+    pub prediction_window: u64,
+}
+
+impl Prediction {
+    /// Still waiting to be resolved or cancelled.
+    pub fn is_open(&self) -> bool {
+        self.status == "ACTIVE" || self.status == "LOCKED"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Outcome {
+    pub id: String,
+    pub title: String,
+    // who predicted it, 0 until someone did
+    #[serde(default)]
+    pub users: u64,
+    #[serde(default)]
+    pub channel_points: u64,
+    // the users who used the most points on it, twitch sends null before anyone predicted
+    #[serde(default)]
+    pub top_predictors: Option<Vec<Predictor>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Predictor {
+    pub user_name: String,
+    pub channel_points_used: u64,
+    // 0 until the outcome won
+    #[serde(default)]
+    pub channel_points_won: u64,
+}
+
+/// How the prediction is changed, by its outcome's id for resolving it.
+#[derive(Debug, Clone, Copy)]
+pub enum PredictionEnd<'a> {
+    Lock,
+    Resolve(&'a str),
+    Cancel,
+}
+
+impl Helix {
+    /// Starts a prediction taking points for the seconds, only the broadcaster's token may.
+    /// The bot remembers it, so it can be ended without its id.
+    // https://dev.twitch.tv/docs/api/reference/#create-prediction
+    pub async fn create_prediction(
+        &mut self,
+        broadcaster: &User,
+        title: &str,
+        outcomes: &[String],
+        window: u32,
+    ) -> Result<Option<Prediction>, HelixError> {
+        self.enabled(&PREDICTIONS)?;
+        let outcomes: Vec<_> = outcomes
+            .iter()
+            .map(|outcome| json!({ "title": outcome }))
+            .collect();
+        let body = json!({
+            "broadcaster_id": broadcaster.id,
+            "title": title,
+            "outcomes": outcomes,
+            "prediction_window": window,
+        });
+        let prediction = match self
+            .post_data::<Prediction>(PREDICTIONS.identity, "predictions", &[], Some(&body))
+            .await
+        {
+            Ok(predictions) => predictions.into_iter().next(),
+            Err(error) => return Err(self.scope_needed(error, PREDICTIONS.scope)),
+        };
+        if let Some(prediction) = &prediction {
+            self.predictions
+                .insert(broadcaster.id.clone(), prediction.id.clone());
+        }
+        Ok(prediction)
+    }
+
+    /// The prediction the bot started, or the latest one after a restart, None unless it is
+    /// still open.
+    // https://dev.twitch.tv/docs/api/reference/#get-predictions
+    pub async fn open_prediction(
+        &mut self,
+        broadcaster: &User,
+    ) -> Result<Option<Prediction>, HelixError> {
+        self.enabled(&PREDICTIONS)?;
+        let remembered = self.predictions.get(&broadcaster.id).cloned();
+        let query = match &remembered {
+            Some(id) => [("broadcaster_id", &*broadcaster.id), ("id", id.as_str())],
+            None => [("broadcaster_id", &*broadcaster.id), ("first", "1")],
+        };
+        match self
+            .get::<Prediction>(PREDICTIONS.identity, "predictions", &query)
+            .await
+        {
+            Ok(predictions) => Ok(predictions.into_iter().next().filter(Prediction::is_open)),
+            Err(error) => Err(self.scope_needed(error, PREDICTIONS.scope)),
+        }
+    }
+
+    /// Locks, resolves or cancels the prediction, with what it ended with.
+    // https://dev.twitch.tv/docs/api/reference/#end-prediction
+    pub async fn end_prediction(
+        &mut self,
+        broadcaster: &User,
+        id: &str,
+        end: PredictionEnd<'_>,
+    ) -> Result<Option<Prediction>, HelixError> {
+        self.enabled(&PREDICTIONS)?;
+        let body = match end {
+            PredictionEnd::Lock => {
+                json!({ "broadcaster_id": broadcaster.id, "id": id, "status": "LOCKED" })
+            }
+            PredictionEnd::Resolve(outcome) => json!({
+                "broadcaster_id": broadcaster.id,
+                "id": id,
+                "status": "RESOLVED",
+                "winning_outcome_id": outcome,
+            }),
+            PredictionEnd::Cancel => {
+                json!({ "broadcaster_id": broadcaster.id, "id": id, "status": "CANCELED" })
+            }
+        };
+        let prediction = match self
+            .patch_data::<Prediction>(PREDICTIONS.identity, "predictions", &body)
+            .await
+        {
+            Ok(predictions) => predictions.into_iter().next(),
+            Err(error) => return Err(self.scope_needed(error, PREDICTIONS.scope)),
+        };
+        if !matches!(end, PredictionEnd::Lock) {
+            self.predictions.remove(&broadcaster.id);
+        }
+        Ok(prediction)
+    }
+}
@@ -0,0 +1,29 @@
+use super::{features::REDEMPTIONS, Helix, HelixError, User};
+use serde_json::json;
+
+impl Helix {
+    /// Twitch refuses with 403 unless the reward was created with the bot's client id.
+    // https://dev.twitch.tv/docs/api/reference/#update-redemption-status
+    pub async fn fulfill_redemption(
+        &mut self,
+        broadcaster: &User,
+        reward_id: &str,
+        id: &str,
+    ) -> Result<(), HelixError> {
+        self.enabled(&REDEMPTIONS)?;
+        let query = [
+            ("id", id),
+            ("broadcaster_id", &*broadcaster.id),
+            ("reward_id", reward_id),
+        ];
+        let body = json!({ "status": "FULFILLED" });
+        // twitch's message tells which it was, the scope or the client id
+        self.patch(
+            REDEMPTIONS.identity,
+            "channel_points/custom_rewards/redemptions",
+            &query,
+            &body,
+        )
+        .await
+    }
+}
@@ -0,0 +1,109 @@
+use super::{
+    features::{Feature, MODERATORS, VIPS},
+    Helix, HelixError, User,
+};
+use std::fmt;
+
+/// The roles the broadcaster gives to users and takes back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Vip,
+    Moderator,
+}
+
+impl Role {
+    fn path(self) -> &'static str {
+        match self {
+            Role::Vip => "channels/vips",
+            Role::Moderator => "moderation/moderators",
+        }
+    }
+
+    fn feature(self) -> Feature {
+        match self {
+            Role::Vip => VIPS,
+            Role::Moderator => MODERATORS,
+        }
+    }
+}
+
+// "a VIP", "a moderator"
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Vip => "a VIP",
+            Role::Moderator => "a moderator",
+        })
+    }
+}
+
+impl Helix {
+    /// Gives the user the role, or takes it away. Only the broadcaster's token may.
+    // https://dev.twitch.tv/docs/api/reference/#add-channel-vip
+    // https://dev.twitch.tv/docs/api/reference/#add-channel-moderator
+    pub async fn set_role(
+        &mut self,
+        broadcaster: &User,
+        user: &User,
+        role: Role,
+        add: bool,
+    ) -> Result<(), HelixError> {
+        let feature = role.feature();
+        self.enabled(&feature)?;
+        let query = [("broadcaster_id", &*broadcaster.id), ("user_id", &*user.id)];
+        let result = match add {
+            true => self.post(feature.identity, role.path(), &query).await,
+            false => self.delete(feature.identity, role.path(), &query).await,
+        };
+        result.map_err(|error| self.scope_needed(error, feature.scope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+    use reqwest::StatusCode;
+
+    #[tokio::test]
+    async fn roles_are_given_and_taken() {
+        let mut helix = server(vec![
+            ("POST /channels/vips?broadcaster_id=1&user_id=2", 204, ""),
+            (
+                "DELETE /moderation/moderators?broadcaster_id=1&user_id=2",
+                400,
+                r#"{"error":"Bad Request","status":400,"message":"The user in user_id is not one of the broadcaster's moderators."}"#,
+            ),
+            (
+                "POST /moderation/moderators",
+                401,
+                r#"{"error":"Unauthorized","status":401,"message":"Missing scope: channel:manage:moderators"}"#,
+            ),
+        ]);
+        let user = |id: &str, login: &str| User {
+            id: id.to_owned(),
+            login: login.to_owned(),
+            display_name: login.to_owned(),
+        };
+        let (broadcaster, carkhy) = (user("1", "captaincallback"), user("2", "carkhy"));
+        assert!(helix
+            .set_role(&broadcaster, &carkhy, Role::Vip, true)
+            .await
+            .is_ok());
+        assert!(matches!(
+            helix
+                .set_role(&broadcaster, &carkhy, Role::Moderator, false)
+                .await,
+            Err(HelixError::Status {
+                status: StatusCode::BAD_REQUEST,
+                ..
+            })
+        ));
+        assert!(matches!(
+            helix
+                .set_role(&broadcaster, &carkhy, Role::Moderator, true)
+                .await,
+            Err(HelixError::MissingScope("channel:manage:moderators"))
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use super::{features::SHIELD_MODE, Helix, HelixError, User};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Whether shield mode is on, and who turned it on or off last.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ShieldMode {
+    pub is_active: bool,
+    // empty if it was never turned on
+    pub moderator_name: String,
+    pub last_activated_at: String,
+}
+
+impl Helix {
+    // twitch refuses with 403 when the bot isn't a moderator, and with 401 for the scope
+    fn shield_error(&mut self, error: HelixError) -> HelixError {
+        match error {
+            HelixError::Status {
+                status: StatusCode::FORBIDDEN,
+                ..
+            } => HelixError::NotModerator,
+            error => self.scope_needed(error, SHIELD_MODE.scope),
+        }
+    }
+
+    // https://dev.twitch.tv/docs/api/reference/#get-shield-mode-status
+    pub async fn shield_mode(&mut self, broadcaster: &User) -> Result<ShieldMode, HelixError> {
+        self.enabled(&SHIELD_MODE)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+        ];
+        match self
+            .get(SHIELD_MODE.identity, "moderation/shield_mode", &query)
+            .await
+        {
+            Ok(modes) => modes.into_iter().next().ok_or(HelixError::Status {
+                status: StatusCode::NOT_FOUND,
+                message: "twitch told nothing about shield mode".to_owned(),
+            }),
+            Err(error) => Err(self.shield_error(error)),
+        }
+    }
+
+    /// Turns shield mode on or off, the bot has to moderate the channel.
+    // https://dev.twitch.tv/docs/api/reference/#update-shield-mode-status
+    pub async fn set_shield_mode(
+        &mut self,
+        broadcaster: &User,
+        active: bool,
+    ) -> Result<ShieldMode, HelixError> {
+        self.enabled(&SHIELD_MODE)?;
+        let moderator = self.moderator().await?;
+        let query = [
+            ("broadcaster_id", &*broadcaster.id),
+            ("moderator_id", &*moderator.id),
+        ];
+        let body = json!({ "is_active": active });
+        match self
+            .put_data(
+                SHIELD_MODE.identity,
+                "moderation/shield_mode",
+                &query,
+                &body,
+            )
+            .await
+        {
+            Ok(modes) => modes.into_iter().next().ok_or(HelixError::Status {
+                status: StatusCode::NOT_FOUND,
+                message: "twitch told nothing about shield mode".to_owned(),
+            }),
+            Err(error) => Err(self.shield_error(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+
+    const SHIELD: &str = "/moderation/shield_mode?broadcaster_id=2&moderator_id=1";
+
+    fn carkhy() -> User {
+        User {
+            id: "2".to_owned(),
+            login: "carkhy".to_owned(),
+            display_name: "Carkhy".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn shield_mode_is_toggled() {
+        let mut helix = server(vec![
+            (
+                "/users?login=botanist",
+                200,
+                r#"{"data":[{"id":"1","login":"botanist","display_name":"Botanist"}]}"#,
+            ),
+            (
+                SHIELD,
+                200,
+                r#"{"data":[{"is_active":true,"moderator_id":"1","moderator_login":"botanist","moderator_name":"Botanist","last_activated_at":"2022-07-26T17:16:03.123Z"}]}"#,
+            ),
+            (
+                SHIELD,
+                200,
+                r#"{"data":[{"is_active":false,"moderator_id":"1","moderator_login":"botanist","moderator_name":"Botanist","last_activated_at":"2022-07-26T17:16:03.123Z"}]}"#,
+            ),
+        ]);
+        let on = helix.set_shield_mode(&carkhy(), true).await.unwrap();
+        assert!(on.is_active);
+        assert_eq!(on.moderator_name, "Botanist");
+        let status = helix.shield_mode(&carkhy()).await.unwrap();
+        assert!(!status.is_active);
+    }
+
+    #[tokio::test]
+    async fn moderators_and_scopes_are_told_apart() {
+        let mut helix = server(vec![
+            (
+                "/users?login=botanist",
+                200,
+                r#"{"data":[{"id":"1","login":"botanist","display_name":"Botanist"}]}"#,
+            ),
+            (
+                SHIELD,
+                403,
+                r#"{"error":"Forbidden","status":403,"message":"The user in moderator_id is not one of the broadcaster's moderators."}"#,
+            ),
+            (
+                SHIELD,
+                401,
+                r#"{"error":"Unauthorized","status":401,"message":"Missing scope: moderator:manage:shield_mode"}"#,
+            ),
+        ]);
+        assert!(matches!(
+            helix.set_shield_mode(&carkhy(), true).await,
+            Err(HelixError::NotModerator)
+        ));
+        assert!(matches!(
+            helix.shield_mode(&carkhy()).await,
+            Err(HelixError::MissingScope("moderator:manage:shield_mode"))
+        ));
+    }
+}
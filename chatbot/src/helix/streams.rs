@@ -0,0 +1,117 @@
+use super::{features::MARKERS, parse_time, Helix, HelixError, User};
+use crate::connect::Identity;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A live stream, as helix describes it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Stream {
+    pub user_login: String,
+    pub game_name: String,
+    pub title: String,
+    // "2021-03-10T15:04:21Z"
+    pub started_at: String,
+}
+
+impl Stream {
+    /// None if the start time can't be read, or lies in the future.
+    pub fn uptime(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(parse_time(&self.started_at)?).ok()
+    }
+}
+
+/// A point in the VOD of a live stream, e.g. for a highlight.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Marker {
+    // since the stream started
+    pub position_seconds: u64,
+}
+
+impl Helix {
+    /// The channel's stream, None while it is offline.
+    // https://dev.twitch.tv/docs/api/reference/#get-streams
+    pub async fn stream(&mut self, login: &str) -> Result<Option<Stream>, HelixError> {
+        if let Some(stream) = self.streams.get(login, Instant::now()) {
+            return Ok(stream);
+        }
+        let streams: Vec<Stream> = self
+            .get(Identity::Bot, "streams", &[("user_login", login)])
+            .await?;
+        let stream = streams.into_iter().next();
+        self.streams
+            .insert(login.to_owned(), stream.clone(), Instant::now());
+        Ok(stream)
+    }
+
+    /// Marks the current moment of the stream. Twitch refuses with 404 while offline,
+    /// and with 403 when the broadcaster doesn't keep VODs.
+    // https://dev.twitch.tv/docs/api/reference/#create-stream-marker
+    pub async fn create_marker(
+        &mut self,
+        broadcaster: &User,
+        description: &str,
+    ) -> Result<Option<Marker>, HelixError> {
+        self.enabled(&MARKERS)?;
+        let body = json!({ "user_id": broadcaster.id, "description": description });
+        match self
+            .post_data(MARKERS.identity, "streams/markers", &[], Some(&body))
+            .await
+        {
+            Ok(markers) => Ok(markers.into_iter().next()),
+            Err(
+                error @ HelixError::Status {
+                    status: StatusCode::UNAUTHORIZED,
+                    ..
+                },
+            ) => Err(self.scope_needed(error, MARKERS.scope)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+    use reqwest::StatusCode;
+
+    const LIVE: &str = r#"{"data":[{"id":"40952121085","user_id":"101051819","user_login":"captaincallback","user_name":"CaptainCallback","game_id":"509670","game_name":"Science & Technology","type":"live","title":"Writing a chat bot","viewer_count":12,"started_at":"2021-11-22T20:46:42Z","language":"en","thumbnail_url":"","tag_ids":[],"is_mature":false}],"pagination":{}}"#;
+
+    #[tokio::test]
+    async fn live_streams_are_cached() {
+        let mut helix = server(vec![("/streams?user_login=captaincallback", 200, LIVE)]);
+        let stream = helix.stream("captaincallback").await.unwrap().unwrap();
+        assert_eq!(stream.game_name, "Science & Technology");
+        let later = parse_time("2021-11-22T23:00:00Z").unwrap();
+        assert_eq!(
+            stream.uptime(later),
+            Some(Duration::from_secs(2 * 3600 + 13 * 60 + 18))
+        );
+        // the server answers only once
+        assert_eq!(helix.stream("captaincallback").await.unwrap(), Some(stream));
+    }
+
+    #[tokio::test]
+    async fn offline_streams_are_none() {
+        let mut helix = server(vec![("/streams", 200, r#"{"data":[],"pagination":{}}"#)]);
+        assert_eq!(helix.stream("carkhy").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn failures_keep_the_message() {
+        let mut helix = server(vec![(
+            "/streams",
+            500,
+            r#"{"error":"Internal Server Error","status":500,"message":"Something broke"}"#,
+        )]);
+        let error = helix.stream("carkhy").await.unwrap_err();
+        assert!(
+            matches!(&error, HelixError::Status { status, message }
+                if *status == StatusCode::INTERNAL_SERVER_ERROR && message == "Something broke"),
+            "{:?}",
+            error
+        );
+    }
+}
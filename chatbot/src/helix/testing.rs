@@ -0,0 +1,60 @@
+//! A local server standing in for helix.
+
+use super::Helix;
+use crate::connect::SharedTokens;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+/// Answers the requests in order with status and body, each to the expected path. A path
+/// like "POST /polls as broadcaster" expects the token "broadcaster" instead of "token".
+/// Requests after the last answer are refused.
+pub fn server(answers: Vec<(&'static str, u16, &'static str)>) -> Helix {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for ((path, status, body), stream) in answers.into_iter().zip(listener.incoming()) {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            // the body is read as well, closing with unread data resets the connection
+            let mut length = 0;
+            let mut token = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if let Some(value) = line.strip_prefix("authorization: bearer ") {
+                    token = value.to_owned();
+                }
+            }
+            reader.read_exact(&mut vec![0; length]).unwrap();
+            let (path, expected) = path.split_once(" as ").unwrap_or((path, "token"));
+            assert!(request.contains(path), "{} instead of {}", request, path);
+            assert_eq!(token, expected, "the token of {}", path);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+    Helix::new(
+        &format!("http://127.0.0.1:{}", port),
+        "client".to_owned(),
+        "botanist".to_owned(),
+        Some(SharedTokens::fixed("token")),
+        None,
+    )
+}
@@ -0,0 +1,156 @@
+use super::User;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+/// Twitch answers at most this many logins in one request.
+pub const MAX_LOGINS: usize = 100;
+
+#[derive(Debug)]
+struct Entry {
+    stored: Instant,
+    // when it was last asked for, for the order in `used`
+    used: u64,
+    // None if there is no such user
+    user: Option<User>,
+}
+
+/// The users by lowercase login, the least recently used are forgotten when full. Unknown
+/// logins are kept as well, for a shorter while, so a typo repeated in chat isn't asked
+/// about each time.
+#[derive(Debug)]
+pub struct UserCache {
+    capacity: usize,
+    ttl: Duration,
+    unknown_ttl: Duration,
+    entries: HashMap<String, Entry>,
+    // the logins by when they were last asked for, oldest first
+    used: BTreeMap<u64, String>,
+    uses: u64,
+}
+
+impl UserCache {
+    pub fn new(capacity: usize, ttl: Duration, unknown_ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            unknown_ttl,
+            entries: HashMap::new(),
+            used: BTreeMap::new(),
+            uses: 0,
+        }
+    }
+
+    /// Some(None) for a login known not to exist, None once the entry expired, which is when
+    /// renames and new display names are noticed.
+    pub fn get(&mut self, login: &str, now: Instant) -> Option<Option<User>> {
+        let entry = self.entries.get_mut(login)?;
+        let ttl = match entry.user {
+            Some(_) => self.ttl,
+            None => self.unknown_ttl,
+        };
+        if now >= entry.stored + ttl {
+            return None;
+        }
+        self.uses += 1;
+        self.used.remove(&entry.used);
+        self.used.insert(self.uses, login.to_owned());
+        entry.used = self.uses;
+        Some(entry.user.clone())
+    }
+
+    pub fn insert(&mut self, login: String, user: Option<User>, now: Instant) {
+        // a user who was renamed isn't found under the old login either
+        if let Some(user) = &user {
+            let renamed: Vec<String> = self
+                .entries
+                .iter()
+                .filter(|(other, entry)| {
+                    **other != login && entry.user.as_ref().is_some_and(|old| old.id == user.id)
+                })
+                .map(|(other, _)| other.clone())
+                .collect();
+            for other in renamed {
+                self.remove(&other);
+            }
+        }
+        self.remove(&login);
+        while self.entries.len() >= self.capacity.max(1) {
+            let Some((_, oldest)) = self.used.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.uses += 1;
+        self.used.insert(self.uses, login.clone());
+        self.entries.insert(
+            login,
+            Entry {
+                stored: now,
+                used: self.uses,
+                user,
+            },
+        );
+    }
+
+    fn remove(&mut self, login: &str) {
+        if let Some(entry) = self.entries.remove(login) {
+            self.used.remove(&entry.used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60 * 60);
+    const UNKNOWN_TTL: Duration = Duration::from_secs(5 * 60);
+
+    fn user(id: &str, login: &str) -> Option<User> {
+        Some(User {
+            id: id.to_owned(),
+            login: login.to_owned(),
+            display_name: login.to_uppercase(),
+        })
+    }
+
+    #[test]
+    fn unknown_logins_expire_sooner() {
+        let now = Instant::now();
+        let mut cache = UserCache::new(10, TTL, UNKNOWN_TTL);
+        cache.insert("carkhy".to_owned(), user("2", "carkhy"), now);
+        cache.insert("carkhyy".to_owned(), None, now);
+        let later = now + UNKNOWN_TTL;
+        assert_eq!(
+            cache.get("carkhyy", later - Duration::from_secs(1)),
+            Some(None)
+        );
+        assert_eq!(cache.get("carkhyy", later), None);
+        assert_eq!(cache.get("carkhy", later), Some(user("2", "carkhy")));
+        assert_eq!(cache.get("carkhy", now + TTL), None);
+        assert_eq!(cache.get("nobody", now), None);
+    }
+
+    #[test]
+    fn the_least_recently_used_are_forgotten() {
+        let now = Instant::now();
+        let mut cache = UserCache::new(2, TTL, UNKNOWN_TTL);
+        cache.insert("carkhy".to_owned(), user("2", "carkhy"), now);
+        cache.insert(
+            "captaincallback".to_owned(),
+            user("1", "captaincallback"),
+            now,
+        );
+        cache.get("carkhy", now);
+        cache.insert("botanist".to_owned(), user("3", "botanist"), now);
+        assert!(cache.get("captaincallback", now).is_none());
+        assert!(cache.get("carkhy", now).is_some());
+        assert!(cache.get("botanist", now).is_some());
+        // renamed, the old login is forgotten
+        cache.insert("carkhy_".to_owned(), user("2", "carkhy_"), now);
+        assert!(cache.get("carkhy", now).is_none());
+        assert_eq!(cache.entries.len(), cache.used.len());
+    }
+}
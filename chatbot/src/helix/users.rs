@@ -0,0 +1,238 @@
+use super::{features::FOLLOWERS, user_cache::MAX_LOGINS, Helix, HelixError};
+use crate::connect::Identity;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{collections::HashMap, time::Instant};
+
+/// A twitch user, the id is what other endpoints ask for.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub login: String,
+    pub display_name: String,
+}
+
+#[derive(Deserialize)]
+struct Follower {
+    // "2022-05-24T22:22:08Z"
+    followed_at: String,
+}
+
+impl Helix {
+    /// The user with the login, None if there is none. Logins are matched ignoring case.
+    pub async fn user(&mut self, login: &str) -> Result<Option<User>, HelixError> {
+        Ok(self.users(&[login]).await?.pop().flatten())
+    }
+
+    /// The users with the logins, in the same order, None for those there are none of.
+    /// Those not cached are asked for together, a hundred in each request.
+    pub async fn users(&mut self, logins: &[&str]) -> Result<Vec<Option<User>>, HelixError> {
+        self.users_at(logins, Instant::now()).await
+    }
+
+    // https://dev.twitch.tv/docs/api/reference/#get-users
+    async fn users_at(
+        &mut self,
+        logins: &[&str],
+        now: Instant,
+    ) -> Result<Vec<Option<User>>, HelixError> {
+        let logins: Vec<String> = logins.iter().map(|login| login.to_lowercase()).collect();
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        for login in &logins {
+            if found.contains_key(login) || missing.contains(login) {
+                continue;
+            }
+            match self.users.get(login, now) {
+                Some(user) => {
+                    found.insert(login.clone(), user);
+                }
+                None => missing.push(login.clone()),
+            }
+        }
+        for chunk in missing.chunks(MAX_LOGINS) {
+            let query: Vec<_> = chunk
+                .iter()
+                .map(|login| ("login", login.as_str()))
+                .collect();
+            let users: Vec<User> = self.get(Identity::Bot, "users", &query).await?;
+            for login in chunk {
+                let user = users.iter().find(|user| user.login == *login).cloned();
+                self.users.insert(login.clone(), user.clone(), now);
+                found.insert(login.clone(), user);
+            }
+        }
+        Ok(logins
+            .iter()
+            .map(|login| found.get(login).cloned().flatten())
+            .collect())
+    }
+
+    /// The bot's user, who moderates for the broadcaster.
+    pub async fn moderator(&mut self) -> Result<User, HelixError> {
+        let login = self.login.clone();
+        self.user(&login).await?.ok_or_else(|| HelixError::Status {
+            status: StatusCode::NOT_FOUND,
+            message: format!("the bot's user {} doesn't exist", login),
+        })
+    }
+
+    /// When the user followed the broadcaster, None if they don't follow.
+    /// Needs a token of the broadcaster or one of their moderators.
+    // https://dev.twitch.tv/docs/api/reference/#get-channel-followers
+    pub async fn followed_at(
+        &mut self,
+        broadcaster: &User,
+        user: &User,
+    ) -> Result<Option<String>, HelixError> {
+        self.enabled(&FOLLOWERS)?;
+        let key = (broadcaster.id.clone(), user.id.clone());
+        if let Some(followed_at) = self.follows.get(&key, Instant::now()) {
+            return Ok(followed_at);
+        }
+        let query = [("broadcaster_id", &*broadcaster.id), ("user_id", &*user.id)];
+        let followers: Vec<Follower> = self
+            .get(FOLLOWERS.identity, "channels/followers", &query)
+            .await
+            .map_err(|error| self.scope_needed(error, FOLLOWERS.scope))?;
+        let followed_at = followers
+            .into_iter()
+            .next()
+            .map(|follower| follower.followed_at);
+        self.follows
+            .insert(key, followed_at.clone(), Instant::now());
+        Ok(followed_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::{testing::server, UNKNOWN_USER_TTL};
+    use std::time::Duration;
+
+    const CARKHY: &str = r#"{"data":[{"id":"141981764","login":"carkhy","display_name":"Carkhy","type":"","broadcaster_type":"","description":"","profile_image_url":"","offline_image_url":"","view_count":0,"created_at":"2016-12-14T20:32:28Z"}]}"#;
+
+    #[tokio::test]
+    async fn users_are_asked_for_once() {
+        let mut helix = server(vec![
+            ("/users?login=carkhy", 200, CARKHY),
+            ("/users?login=nobody", 200, r#"{"data":[]}"#),
+        ]);
+        let carkhy = helix.user("Carkhy").await.unwrap().unwrap();
+        assert_eq!(carkhy.id, "141981764");
+        assert_eq!(carkhy.display_name, "Carkhy");
+        assert_eq!(helix.user("nobody").await.unwrap(), None);
+        assert_eq!(helix.user("carkhy").await.unwrap(), Some(carkhy));
+        assert_eq!(helix.user("nobody").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn users_are_asked_for_together() {
+        let mut helix = server(vec![
+            (
+                "/users?login=carkhy&login=captaincallback&login=nobody ",
+                200,
+                r#"{"data":[{"id":"1","login":"captaincallback","display_name":"CaptainCallback"},{"id":"141981764","login":"carkhy","display_name":"Carkhy"}]}"#,
+            ),
+            (
+                "/users?login=viewer000&login=viewer001&",
+                200,
+                r#"{"data":[{"id":"5","login":"viewer000","display_name":"viewer000"}]}"#,
+            ),
+            ("/users?login=viewer100&", 200, r#"{"data":[]}"#),
+        ]);
+        let users = helix
+            .users(&["Carkhy", "captaincallback", "carkhy", "nobody"])
+            .await
+            .unwrap();
+        let logins: Vec<_> = users
+            .iter()
+            .map(|user| user.as_ref().map(|user| user.login.as_str()))
+            .collect();
+        assert_eq!(
+            logins,
+            [
+                Some("carkhy"),
+                Some("captaincallback"),
+                Some("carkhy"),
+                None
+            ]
+        );
+        // twice a hundred logins at most, the cached ones aren't asked for again
+        let mut viewers: Vec<String> = (0..150).map(|id| format!("viewer{:03}", id)).collect();
+        viewers.push("carkhy".to_owned());
+        let viewers: Vec<&str> = viewers.iter().map(String::as_str).collect();
+        let users = helix.users(&viewers).await.unwrap();
+        assert_eq!(users.iter().flatten().count(), 2);
+        assert_eq!(users[0].as_ref().unwrap().id, "5");
+    }
+
+    #[tokio::test]
+    async fn cached_users_need_no_request() {
+        // requests after the first are refused
+        let mut helix = server(vec![("/users?login=carkhy&login=nobody ", 200, CARKHY)]);
+        let users = helix.users(&["carkhy", "nobody"]).await.unwrap();
+        assert_eq!(
+            helix.users(&["nobody", "Carkhy"]).await.unwrap(),
+            [None, users[0].clone()]
+        );
+        assert_eq!(helix.user("carkhy").await.unwrap(), users[0]);
+        assert!(helix.user("captaincallback").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_users_are_asked_for_again_later() {
+        let mut helix = server(vec![
+            ("/users?login=nobody", 200, r#"{"data":[]}"#),
+            (
+                "/users?login=nobody",
+                200,
+                r#"{"data":[{"id":"9","login":"nobody","display_name":"Nobody"}]}"#,
+            ),
+        ]);
+        let now = Instant::now();
+        assert_eq!(helix.users_at(&["nobody"], now).await.unwrap(), [None]);
+        let soon = now + UNKNOWN_USER_TTL - Duration::from_secs(1);
+        assert_eq!(helix.users_at(&["nobody"], soon).await.unwrap(), [None]);
+        // meanwhile someone took the login
+        let later = now + UNKNOWN_USER_TTL;
+        let user = helix.users_at(&["nobody"], later).await.unwrap();
+        assert_eq!(user[0].as_ref().unwrap().id, "9");
+        let much_later = later + UNKNOWN_USER_TTL;
+        assert_eq!(helix.users_at(&["nobody"], much_later).await.unwrap(), user);
+    }
+
+    #[tokio::test]
+    async fn missing_scope_is_named() {
+        let mut helix = server(vec![
+            (
+                "/channels/followers?broadcaster_id=1&user_id=2",
+                200,
+                r#"{"total":1,"data":[{"user_id":"2","user_login":"carkhy","user_name":"Carkhy","followed_at":"2022-05-24T22:22:08Z"}],"pagination":{}}"#,
+            ),
+            (
+                "/channels/followers?broadcaster_id=1&user_id=3",
+                403,
+                r#"{"error":"Forbidden","status":403,"message":"The user is not a moderator"}"#,
+            ),
+        ]);
+        let user = |id: &str| User {
+            id: id.to_owned(),
+            login: String::new(),
+            display_name: String::new(),
+        };
+        assert_eq!(
+            helix
+                .followed_at(&user("1"), &user("2"))
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("2022-05-24T22:22:08Z")
+        );
+        assert!(matches!(
+            helix.followed_at(&user("1"), &user("3")).await,
+            Err(HelixError::MissingScope("moderator:read:followers"))
+        ));
+    }
+}
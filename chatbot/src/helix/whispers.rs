@@ -0,0 +1,95 @@
+use super::{features::WHISPERS, Helix, HelixError};
+use reqwest::StatusCode;
+use serde_json::json;
+
+// twitch allows longer whispers only to users whispered before
+const MAX_WHISPER_CHARS: usize = 500;
+
+impl Helix {
+    /// Whispers the text to the user from the bot's user. Twitch only lets users with a
+    /// verified phone number whisper, and only so many new recipients a day.
+    // https://dev.twitch.tv/docs/api/reference/#send-whisper
+    pub async fn send_whisper(&mut self, to_login: &str, text: &str) -> Result<(), HelixError> {
+        self.enabled(&WHISPERS)?;
+        let from = self.moderator().await?;
+        let Some(to) = self.user(to_login).await? else {
+            return Err(HelixError::Status {
+                status: StatusCode::NOT_FOUND,
+                message: format!("there is no twitch user named {}", to_login),
+            });
+        };
+        let query = [("from_user_id", &*from.id), ("to_user_id", &*to.id)];
+        let text: String = text.chars().take(MAX_WHISPER_CHARS).collect();
+        let body = json!({ "message": text });
+        match self
+            .post_json(WHISPERS.identity, "whispers", &query, &body)
+            .await
+        {
+            Ok(()) => Ok(()),
+            // "The recipient's settings prevent this sender from whispering them."
+            Err(HelixError::Status {
+                status: StatusCode::FORBIDDEN,
+                ..
+            }) => Err(HelixError::WhispersBlocked(to.display_name)),
+            Err(HelixError::Status {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                ..
+            }) => Err(HelixError::RateLimited),
+            Err(error) => Err(self.scope_needed(error, WHISPERS.scope)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helix::testing::server;
+
+    const USERS: [(&str, u16, &str); 2] = [
+        (
+            "/users?login=botanist",
+            200,
+            r#"{"data":[{"id":"1","login":"botanist","display_name":"Botanist"}]}"#,
+        ),
+        (
+            "/users?login=carkhy",
+            200,
+            r#"{"data":[{"id":"2","login":"carkhy","display_name":"Carkhy"}]}"#,
+        ),
+    ];
+    const WHISPER: &str = "/whispers?from_user_id=1&to_user_id=2";
+
+    #[tokio::test]
+    async fn whispers_are_sent_to_the_user_id() {
+        let mut answers = USERS.to_vec();
+        answers.push((WHISPER, 204, ""));
+        answers.push((WHISPER, 403, r#"{"error":"Forbidden","status":403,"message":"The recipient's settings prevent this sender from whispering them."}"#));
+        let mut helix = server(answers);
+        assert!(helix.send_whisper("Carkhy", "You won!").await.is_ok());
+        // the users are looked up once
+        assert!(matches!(
+            helix.send_whisper("carkhy", "You won!").await,
+            Err(HelixError::WhispersBlocked(name)) if name == "Carkhy"
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_told_apart() {
+        let mut answers = USERS.to_vec();
+        answers.push((
+            WHISPER,
+            429,
+            r#"{"error":"Too Many Requests","status":429,"message":"Whisper rate limit exceeded"}"#,
+        ));
+        answers.push((WHISPER, 401, r#"{"error":"Unauthorized","status":401,"message":"Missing scope: user:manage:whispers"}"#));
+        let mut helix = server(answers);
+        assert!(matches!(
+            helix.send_whisper("carkhy", "hi").await,
+            Err(HelixError::RateLimited)
+        ));
+        assert!(matches!(
+            helix.send_whisper("carkhy", "hi").await,
+            Err(HelixError::MissingScope("user:manage:whispers"))
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+//! The bot's HTTP endpoints, answered by a thread of their own so chat never waits for them:
+//! - `GET /metrics`: the metrics in Prometheus' text format, see [crate::prometheus]
+//! - `GET /healthz`: 200 while the connection to chat is read from, else 503
+//! - `GET /readyz`: 200 once logged in and in all channels with a short queue, else 503
+//!
+//! - `/api/`: the API once `http.api_token` is set, see [crate::api]
+//!
+//! Both health endpoints answer which checks failed in a JSON body.
+use crate::{
+    api::{self, Api},
+    connect::{Health, HealthLimits, Report},
+    prometheus,
+};
+use std::{error::Error, io::Read, net::SocketAddr, thread, time::Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// Prometheus' text format
+const METRICS_TYPE: &str = "text/plain; version=0.0.4";
+// the longest body read, far more than any chat message
+const MAX_BODY: u64 = 64 * 1024;
+
+/// The status, the content type and the body of the answer to a request.
+#[derive(Debug, PartialEq, Eq)]
+struct Answer {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+/// The state the health endpoints read, and what they allow.
+#[derive(Clone)]
+pub struct Probes {
+    pub health: Health,
+    pub limits: HealthLimits,
+}
+
+fn route(method: &Method, path: &str, probes: &Probes, now: Instant) -> Answer {
+    let path = path.split('?').next().unwrap_or_default();
+    let report = |report: Report| Answer {
+        status: if report.passed() { 200 } else { 503 },
+        content_type: "application/json",
+        body: format!("{}\n", report.to_json()),
+    };
+    match (method, path) {
+        (Method::Get, "/metrics") => Answer {
+            status: 200,
+            content_type: METRICS_TYPE,
+            body: prometheus::render(),
+        },
+        (Method::Get, "/healthz") => report(probes.health.live(now, &probes.limits)),
+        (Method::Get, "/readyz") => report(probes.health.ready(now, &probes.limits)),
+        (Method::Get, _) => Answer {
+            status: 404,
+            content_type: "text/plain",
+            body: "Not found\n".to_owned(),
+        },
+        _ => Answer {
+            status: 405,
+            content_type: "text/plain",
+            body: "Only GET is supported\n".to_owned(),
+        },
+    }
+}
+
+fn call_api(request: &mut Request, api: &Api) -> Answer {
+    let authorization = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .map(|header| header.value.as_str().to_owned());
+    let mut body = String::new();
+    let read = request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_string(&mut body);
+    let answer = match read {
+        Err(_) => api::error(400, "the body is not UTF-8"),
+        Ok(length) if length as u64 > MAX_BODY => api::error(413, "the body is too long"),
+        Ok(_) => api::handle(
+            api,
+            request.method(),
+            request.url(),
+            authorization.as_deref(),
+            &body,
+            |call| api.call(call),
+        ),
+    };
+    Answer {
+        status: answer.status,
+        content_type: "application/json",
+        body: format!("{}\n", answer.body),
+    }
+}
+
+fn respond(mut request: Request, probes: &Probes, api: Option<&Api>) {
+    let answer = match api {
+        Some(api) if request.url().starts_with("/api/") => call_api(&mut request, api),
+        _ => route(request.method(), request.url(), probes, Instant::now()),
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], answer.content_type.as_bytes())
+        .expect("the content types are valid headers");
+    let response = Response::from_string(answer.body)
+        .with_status_code(answer.status)
+        .with_header(content_type);
+    if let Err(error) = request.respond(response) {
+        tracing::debug!(%error, "could not answer an HTTP request");
+    }
+}
+
+/// Listens on the address until the bot stops, the address bound is returned, e.g. the port
+/// chosen for "127.0.0.1:0".
+pub fn serve(
+    listen: &str,
+    probes: Probes,
+    api: Option<Api>,
+) -> Result<SocketAddr, Box<dyn Error + Send + Sync>> {
+    let server = Server::http(listen)?;
+    let address = server.server_addr();
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            respond(request, &probes, api.as_ref());
+        }
+    });
+    tracing::info!(%address, "answering HTTP requests");
+    Ok(address)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::Probes;
+    use crate::connect::{Health, HealthLimits};
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        time::{Duration, Instant},
+    };
+    // the receiver is for the webhooks and discord
+    #[cfg(any(feature = "webhooks", feature = "discord"))]
+    use std::{
+        collections::HashMap,
+        sync::mpsc::{channel, Receiver},
+        thread,
+    };
+    #[cfg(any(feature = "webhooks", feature = "discord"))]
+    use tiny_http::{Response, Server};
+
+    /// A request the [receiver] got, the header names are in lowercase.
+    #[cfg(any(feature = "webhooks", feature = "discord"))]
+    pub struct Received {
+        pub path: String,
+        pub headers: HashMap<String, String>,
+        pub body: String,
+    }
+
+    /// A server answering with the statuses in turn, it tells what it got. The URL is without
+    /// a path, e.g. "http://127.0.0.1:41234".
+    #[cfg(any(feature = "webhooks", feature = "discord"))]
+    pub fn receiver(statuses: Vec<u16>) -> (String, Receiver<Received>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        let (sender, received) = channel();
+        thread::spawn(move || {
+            for status in statuses {
+                let mut request = server.recv().unwrap();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let headers = request
+                    .headers()
+                    .iter()
+                    .map(|header| {
+                        let name = header.field.as_str().as_str().to_ascii_lowercase();
+                        (name, header.value.to_string())
+                    })
+                    .collect();
+                let path = request.url().to_owned();
+                let _ = sender.send(Received {
+                    path,
+                    headers,
+                    body,
+                });
+                request.respond(Response::empty(status)).unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    /// The next request the receiver got, within a few seconds.
+    #[cfg(any(feature = "webhooks", feature = "discord"))]
+    pub fn wait(received: &Receiver<Received>) -> Received {
+        received.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    /// Before the login to a chat without channels.
+    pub fn probes() -> Probes {
+        Probes {
+            health: Health::new(&[], || 0, Instant::now()),
+            limits: HealthLimits {
+                stale_after: Duration::from_secs(300),
+                max_queue: 50,
+            },
+        }
+    }
+
+    /// The status line and the body of a GET request.
+    pub fn get(address: SocketAddr, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap().to_owned();
+        (status, body.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        testing::{get, probes},
+        *,
+    };
+
+    #[test]
+    fn only_the_endpoints_are_answered() {
+        let address = serve("127.0.0.1:0", probes(), None).unwrap();
+        let (status, body) = get(address, "/metrics");
+        assert_eq!(status, "HTTP/1.0 200 OK");
+        assert!(body.contains("# TYPE chatbot_connected gauge\n"));
+        let (status, _) = get(address, "/admin");
+        assert_eq!(status, "HTTP/1.0 404 Not Found");
+        let answer = route(&Method::Post, "/metrics", &probes(), Instant::now());
+        assert_eq!(answer.status, 405);
+    }
+
+    #[test]
+    fn health_is_answered_with_the_failed_checks() {
+        let probes = probes();
+        let address = serve("127.0.0.1:0", probes.clone(), None).unwrap();
+        let (status, body) = get(address, "/healthz");
+        assert_eq!(status, "HTTP/1.0 200 OK");
+        assert_eq!(
+            body,
+            "{\"checks\":{\"event_loop\":\"ok\"},\"status\":\"ok\"}\n"
+        );
+        let (status, body) = get(address, "/readyz");
+        assert_eq!(status, "HTTP/1.0 503 Service Unavailable");
+        assert!(body.contains("\"authenticated\":\"not logged in yet\""));
+        probes.health.logged_in("botname");
+        let (status, _) = get(address, "/readyz?verbose");
+        assert_eq!(status, "HTTP/1.0 200 OK");
+    }
+}
@@ -0,0 +1,412 @@
+//! The bot's log, from the `tracing` spans and events of the modules, written to stdout.
+//!
+//! Each event is written with the spans it happened in, e.g. the connection attempt and the
+//! login around twitch refusing the token. Nothing secret goes into the fields, the modules
+//! write "[redacted]" for the token where it is used.
+use crate::{
+    config::{LogFormat, LoggingConfig},
+    core::timestamp,
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread::{self, ThreadId},
+    time::SystemTime,
+};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, Event, Level, Metadata, Subscriber,
+};
+
+// a span and its fields, kept until the last handle to it is closed
+#[derive(Debug)]
+struct Span {
+    name: &'static str,
+    fields: Vec<(&'static str, Value)>,
+    parent: Option<u64>,
+    handles: usize,
+}
+
+#[derive(Debug, Default)]
+struct Spans {
+    open: HashMap<u64, Span>,
+    // the spans entered on each thread, innermost last
+    entered: HashMap<ThreadId, Vec<u64>>,
+}
+
+/// Writes the events the filter lets through, with their spans.
+pub struct Log {
+    format: LogFormat,
+    // the longest matching module decides, "" matches every module
+    directives: Vec<(String, LevelFilter)>,
+    sink: Mutex<Box<dyn Write + Send>>,
+    spans: Mutex<Spans>,
+    next_id: AtomicU64,
+}
+
+impl Log {
+    pub fn new(config: &LoggingConfig, sink: Box<dyn Write + Send>) -> Self {
+        let mut directives = config.directives().unwrap_or_default();
+        // e.g. "chatbot::helix" before "chatbot"
+        directives.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Self {
+            format: config.format,
+            directives,
+            sink: Mutex::new(sink),
+            spans: Mutex::default(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .find(|(module, _)| {
+                module.is_empty()
+                    || target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map_or(LevelFilter::INFO, |(_, level)| *level)
+    }
+
+    // the spans around the current one, outermost first
+    fn scope(&self, spans: &Spans, parent: Option<u64>) -> Vec<(&'static str, Value)> {
+        let mut scope = Vec::new();
+        let mut next = parent;
+        while let Some(span) = next.and_then(|id| spans.open.get(&id)) {
+            scope.push((span.name, Value::Object(fields(&span.fields))));
+            next = span.parent;
+        }
+        scope.reverse();
+        scope
+    }
+
+    fn current(&self, spans: &Spans) -> Option<u64> {
+        spans
+            .entered
+            .get(&thread::current().id())
+            .and_then(|entered| entered.last().copied())
+    }
+
+    fn line(
+        &self,
+        level: &Level,
+        target: &str,
+        event: Fields,
+        scope: Vec<(&str, Value)>,
+    ) -> String {
+        let time = timestamp(SystemTime::now());
+        match self.format {
+            LogFormat::Pretty => {
+                let mut line = format!("{} {:>5} ", time, level.as_str());
+                for (name, fields) in &scope {
+                    let _ = write!(line, "{}{}:", name, pretty_fields(fields, "{", "}"));
+                }
+                if !scope.is_empty() {
+                    line.push(' ');
+                }
+                let message = event.message.unwrap_or_default();
+                let _ = write!(line, "{}: {}", target, message);
+                line + &pretty_fields(&Value::Object(fields(&event.fields)), " ", "")
+            }
+            LogFormat::Json => {
+                let spans: Vec<Value> = scope
+                    .into_iter()
+                    .map(|(name, mut fields)| {
+                        fields["name"] = name.into();
+                        fields
+                    })
+                    .collect();
+                let mut line = fields(&event.fields);
+                line.insert("time".to_owned(), time.into());
+                line.insert("level".to_owned(), level.as_str().into());
+                line.insert("target".to_owned(), target.into());
+                line.insert("message".to_owned(), event.message.into());
+                line.insert("spans".to_owned(), spans.into());
+                Value::Object(line).to_string()
+            }
+        }
+    }
+}
+
+/// Makes the log of the config the one of the whole bot.
+pub fn init(config: &LoggingConfig) {
+    let log = Log::new(config, Box::new(io::stdout()));
+    if tracing::subscriber::set_global_default(log).is_err() {
+        tracing::warn!("the log was set up before, the config's is not used");
+    }
+}
+
+impl Subscriber for Log {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level(metadata.target())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.directives.iter().map(|(_, level)| *level).max()
+    }
+
+    fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+        let mut recorded = Fields::default();
+        attributes.record(&mut recorded);
+        let mut spans = self.spans.lock().unwrap();
+        let parent = match attributes.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attributes.is_root() => None,
+            None => self.current(&spans),
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let span = Span {
+            name: attributes.metadata().name(),
+            fields: recorded.fields,
+            parent,
+            handles: 1,
+        };
+        spans.open.insert(id, span);
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut recorded = Fields::default();
+        values.record(&mut recorded);
+        if let Some(span) = self.spans.lock().unwrap().open.get_mut(&span.into_u64()) {
+            span.fields.extend(recorded.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut recorded = Fields::default();
+        event.record(&mut recorded);
+        let line = {
+            let spans = self.spans.lock().unwrap();
+            let parent = match event.parent() {
+                Some(parent) => Some(parent.into_u64()),
+                None if event.is_root() => None,
+                None => self.current(&spans),
+            };
+            let metadata = event.metadata();
+            let scope = self.scope(&spans, parent);
+            self.line(metadata.level(), metadata.target(), recorded, scope)
+        };
+        let mut sink = self.sink.lock().unwrap();
+        let _ = writeln!(sink, "{}", line).and_then(|_| sink.flush());
+    }
+
+    fn enter(&self, span: &span::Id) {
+        let mut spans = self.spans.lock().unwrap();
+        let entered = spans.entered.entry(thread::current().id()).or_default();
+        entered.push(span.into_u64());
+    }
+
+    fn exit(&self, span: &span::Id) {
+        let mut spans = self.spans.lock().unwrap();
+        let thread = thread::current().id();
+        if let Some(entered) = spans.entered.get_mut(&thread) {
+            if let Some(position) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(position);
+            }
+            if entered.is_empty() {
+                spans.entered.remove(&thread);
+            }
+        }
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some(open) = self.spans.lock().unwrap().open.get_mut(&span.into_u64()) {
+            open.handles += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let id = span.into_u64();
+        let Some(open) = spans.open.get_mut(&id) else {
+            return false;
+        };
+        open.handles -= 1;
+        if open.handles > 0 {
+            return false;
+        }
+        spans.open.remove(&id);
+        true
+    }
+}
+
+// the fields of a span or an event, the message of an event apart
+#[derive(Debug, Default)]
+struct Fields {
+    message: Option<String>,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, value.into());
+    }
+}
+
+impl Fields {
+    fn record(&mut self, field: &Field, value: Value) {
+        match (field.name(), value) {
+            ("message", Value::String(message)) => self.message = Some(message),
+            (name, value) => self.fields.push((name, value)),
+        }
+    }
+}
+
+fn fields(fields: &[(&'static str, Value)]) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect()
+}
+
+// "{channel=carkhy user=tenaciousbyte}", strings without their quotes
+fn pretty_fields(fields: &Value, open: &str, close: &str) -> String {
+    let Value::Object(fields) = fields else {
+        return String::new();
+    };
+    if fields.is_empty() {
+        return String::new();
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| match value {
+            Value::String(text) => format!("{}={}", name, text),
+            value => format!("{}={}", name, value),
+        })
+        .collect();
+    format!("{}{}{}", open, fields.join(" "), close)
+}
+
+/// A log written to memory, for tests checking the spans and fields the modules log.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The JSON lines everything down to trace logged while running the function on this
+    /// thread.
+    pub fn capture(run: impl FnOnce()) -> Vec<Value> {
+        let buffer = Buffer::default();
+        let config = LoggingConfig {
+            format: LogFormat::Json,
+            filter: "trace".to_owned(),
+        };
+        let log = Log::new(&config, Box::new(buffer.clone()));
+        tracing::subscriber::with_default(log, run);
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        text.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// The fields of the span with the name around the line.
+    pub fn span<'a>(line: &'a Value, name: &str) -> Option<&'a Value> {
+        line["spans"]
+            .as_array()?
+            .iter()
+            .find(|span| span["name"] == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::*, *};
+    use serde_json::json;
+
+    #[test]
+    fn events_are_logged_with_their_spans() {
+        let lines = capture(|| {
+            let connect = tracing::info_span!("connect", host = "irc-ws.chat.twitch.tv");
+            let _connect = connect.enter();
+            let join = tracing::info_span!("join", channel = "carkhy");
+            join.in_scope(|| tracing::warn!(attempt = 2_u64, "refused"));
+            drop(join);
+            tracing::debug!(latency_ms = 12_u64, "asked");
+        });
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["message"], "refused");
+        assert_eq!(lines[0]["attempt"], 2);
+        assert_eq!(lines[0]["target"], "chatbot::logging::tests");
+        assert_eq!(
+            span(&lines[0], "connect").unwrap()["host"],
+            "irc-ws.chat.twitch.tv"
+        );
+        assert_eq!(span(&lines[0], "join").unwrap()["channel"], "carkhy");
+        // the closed span is gone
+        assert!(span(&lines[1], "join").is_none());
+        assert!(span(&lines[1], "connect").is_some());
+    }
+
+    #[test]
+    fn modules_have_levels_of_their_own() {
+        let config = LoggingConfig {
+            format: LogFormat::Pretty,
+            filter: "warn, chatbot::helix=debug,chatbot::connect=off".to_owned(),
+        };
+        let log = Log::new(&config, Box::new(io::sink()));
+        assert_eq!(log.level("chatbot::helix::users"), LevelFilter::DEBUG);
+        assert_eq!(log.level("chatbot::helix"), LevelFilter::DEBUG);
+        assert_eq!(log.level("chatbot::helixes"), LevelFilter::WARN);
+        assert_eq!(log.level("chatbot::connect::connector"), LevelFilter::OFF);
+        assert_eq!(log.level("chatbot::core"), LevelFilter::WARN);
+        let invalid = LoggingConfig {
+            filter: "chatbot=loud".to_owned(),
+            ..Default::default()
+        };
+        assert!(invalid.directives().is_err());
+
+        let event = Fields {
+            message: Some("logged in".to_owned()),
+            fields: vec![("login", "botanist".into())],
+        };
+        let scope = vec![("connect", json!({ "port": 443 }))];
+        let line = log.line(&Level::INFO, "chatbot::connect", event, scope);
+        assert!(
+            line.ends_with("  INFO connect{port=443}: chatbot::connect: logged in login=botanist")
+        );
+    }
+}
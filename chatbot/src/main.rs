@@ -1,64 +1,1136 @@
 use crate::{
-    connect::ChatBotEvent,
+    connect::{ChatBotEvent, InternalEvent, Priority, UserLevel},
     core::{
         ChatBot,
         ChatBotCommand::{self, *},
+        CommandStats, HelixTask,
     },
+    csv_export::Exporter,
 };
-use app_config::AppConfig;
-use connect::TwitchChatConnector;
-use std::sync::mpsc;
-use std::{error::Error, sync::mpsc::Sender};
-use thread_timer::ThreadTimer;
+use chat_logs::ChatLogs;
+use cli::TokenAction;
+use config::{Config, SharedConfig};
+use connect::{
+    spawn_eventsub, AccessTokenDispenser, Connection, ConnectorError, EventHandler, HealthLimits,
+    Identity, IrcLogger, JsonExporter, Overflow, ReplaySource, ReplayTiming, SharedTokens,
+    TokenInfo, TwitchChatConnector, EVENTSUB_URL,
+};
+use helix::Helix;
+use std::{
+    env,
+    error::Error,
+    fs::File,
+    io::{self, BufReader},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+use storage::Storage;
+use tracing::Instrument;
 
-pub mod app_config;
+mod api;
+mod chat_logs;
+mod cli;
+pub mod config;
 mod connect;
+#[cfg(unix)]
+mod control;
 mod core;
+mod csv_export;
+#[cfg(feature = "discord")]
+mod discord;
+mod handlers;
+mod helix;
+mod http;
+mod logging;
+mod obs;
+mod prometheus;
+mod sha256;
+mod storage;
+mod tts;
+#[cfg(feature = "webhooks")]
+mod webhooks;
 
-fn process_command(
+// helix and OBS tasks are awaited here, the next event waits until they answered
+async fn process_command<C: Connection>(
     command: ChatBotCommand,
-    connector: &TwitchChatConnector,
-    bot_event_sender: Sender<ChatBotEvent>,
+    chat: &C,
+    helix: &mut Helix,
+    obs: Option<&obs::Obs>,
+    tts: Option<&tts::Tts>,
+    priority: Priority,
 ) -> Result<(), Box<dyn Error>> {
     match command {
-        SendMessage(message) => {
-            println!("Sending this message : {}", &message);
-            connector.send_message(&message)?;
-        }
-        LogTextMessage(message) => println!("{}", message),
-        TimedCallback { duration, event } => {
-            // This timer spawns a thread per invokation, that's bad
-            // More serious timers were not a good fit (afaik)
-            // It would be a nice exercise to implement such timer with
-            // one or 2 threads for all timers
-            let timer = ThreadTimer::new();
-            let _ = timer.start(duration, move || {
-                let _ = bot_event_sender.send(event);
-            });
+        SendMessage {
+            channel,
+            text,
+            overflow,
+        } => {
+            tracing::info!(%channel, %text, "sending a message");
+            skip_invalid(chat.send_message(&channel, &text, overflow, priority))?;
+            prometheus::MESSAGES_SENT.inc(&[&channel]);
+        }
+        SendReply {
+            channel,
+            parent_msg_id,
+            text,
+        } => {
+            // the reply tag is only understood when tags were granted
+            if chat.has_capability("twitch.tv/tags") {
+                tracing::info!(%channel, %parent_msg_id, %text, "replying");
+                skip_invalid(chat.send_reply(&channel, &parent_msg_id, &text, priority))?;
+            } else {
+                tracing::info!(%channel, %text, "sending a message");
+                skip_invalid(chat.send_message(&channel, &text, Overflow::Split, priority))?;
+            }
+            prometheus::MESSAGES_SENT.inc(&[&channel]);
+        }
+        LogTextMessage(message) => tracing::info!("{}", message),
+        JoinChannel(channel) => {
+            tracing::info!(%channel, "joining");
+            chat.join(&channel)?;
+        }
+        PartChannel(channel) => {
+            tracing::info!(%channel, "leaving");
+            chat.part(&channel)?;
+        }
+        TimedCallback { duration, event } => chat.schedule(duration, event),
+        Helix(task) => {
+            if let Some(answer) = task.run(helix).await {
+                Box::pin(process_command(answer, chat, helix, obs, tts, priority)).await?;
+            }
+        }
+        Obs(task) => {
+            if let Some(answer) = task.run(obs).await {
+                Box::pin(process_command(answer, chat, helix, obs, tts, priority)).await?;
+            }
         }
+        Speak {
+            speaker,
+            text,
+            voice,
+        } => match tts {
+            Some(tts) => tts.speak(&speaker, &text, voice.as_deref()),
+            None => tracing::info!(%speaker, "not reading out the message, tts.sink is not set"),
+        },
+        Webhook { url, body, then } => match post_webhook(&url, body).await {
+            Ok(()) => {
+                if let Some(then) = then {
+                    Box::pin(process_command(*then, chat, helix, obs, tts, priority)).await?;
+                }
+            }
+            // the next redemption may reach it, the bot keeps running
+            Err(error) => tracing::warn!(%url, %error, "webhook failed"),
+        },
         MultipleCommands(new_commands) => {
+            // e.g. the bans of `!nuke`, their users are asked for in one request
+            let logins: Vec<&str> = new_commands
+                .iter()
+                .flat_map(|command| match command {
+                    Helix(task) => task.logins(),
+                    _ => Vec::new(),
+                })
+                .collect();
+            if logins.len() > 1 {
+                if let Err(error) = helix.users(&logins).await {
+                    tracing::warn!(%error, "could not look up the users");
+                }
+            }
             for command in new_commands {
-                process_command(command, connector, bot_event_sender.clone())?;
+                Box::pin(process_command(command, chat, helix, obs, tts, priority)).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the events wait for the webhook like for helix
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn post_webhook(url: &str, body: String) -> Result<(), reqwest::Error> {
+    reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .timeout(WEBHOOK_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// answers to moderators go before other answers, repeating messages and reminders
+// after everything else
+fn priority(event: &ChatBotEvent) -> Priority {
+    match event {
+        ChatBotEvent::Internal(
+            InternalEvent::TimedMessage { .. }
+            | InternalEvent::TimerTick
+            | InternalEvent::ScheduleTick
+            | InternalEvent::ReminderDue { .. },
+        ) => Priority::Timer,
+        ChatBotEvent::Command(command) if command.message.has_level(UserLevel::Moderator) => {
+            Priority::Moderation
+        }
+        _ => Priority::Response,
+    }
+}
+
+// what the filters do about a message goes before other answers, like the answers to moderators
+fn is_moderation(command: &ChatBotCommand) -> bool {
+    match command {
+        Helix(HelixTask::DeleteMessage { .. } | HelixTask::Ban { .. }) => true,
+        MultipleCommands(commands) => commands.iter().any(is_moderation),
+        _ => false,
+    }
+}
+
+// repeating messages are the first to go when twitch's rate limit holds back the bot's messages
+const BACKLOG_LIMIT: usize = 20;
+
+// the timers are kept, only the messages are dropped
+fn without_messages(command: ChatBotCommand) -> Option<ChatBotCommand> {
+    match command {
+        SendMessage { .. } | SendReply { .. } => None,
+        MultipleCommands(commands) => Some(MultipleCommands(
+            commands.into_iter().filter_map(without_messages).collect(),
+        )),
+        command => Some(command),
+    }
+}
+
+// a single message with a line break must not stop the bot, neither must a read-only connection
+fn skip_invalid(result: Result<(), ConnectorError>) -> Result<(), ConnectorError> {
+    match result {
+        Err(
+            error @ (ConnectorError::InvalidOutgoingMessage(_)
+            | ConnectorError::ReadOnlyConnection(_)),
+        ) => {
+            tracing::warn!(%error, "not sending the message");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+// queued messages get this long to make it past twitch's rate limit
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+// Ctrl-C, or SIGTERM e.g. from docker stop
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// `kill -HUP` reloads the config file, the bot keeps running with the old one if it is invalid
+#[cfg(unix)]
+async fn reload_on_hangup(config: SharedConfig, path: Option<PathBuf>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+        return;
+    };
+    while hangup.recv().await.is_some() {
+        match config.reload(path.as_deref()) {
+            Ok(reload) => tracing::info!("{}", reload),
+            Err(error) => tracing::warn!(%error, "keeping the running config"),
+        }
+    }
+}
+
+// the control socket asks the bot like the API does, and reloads like `kill -HUP`
+#[cfg(unix)]
+fn serve_control(
+    socket: &Path,
+    config: &Config,
+    shared: &SharedConfig,
+    path: Option<PathBuf>,
+    connector: &TwitchChatConnector,
+) -> io::Result<()> {
+    let chat = connector.handle();
+    let health = connector.health();
+    let limits = HealthLimits {
+        stale_after: Duration::from_secs(config.http.stale_after),
+        max_queue: config.http.max_queue,
+    };
+    let shared = shared.clone();
+    let control = control::Control::new(
+        move |event| chat.schedule(Duration::ZERO, event),
+        move || health.ready(Instant::now(), &limits).to_json(),
+        move || {
+            let reload = shared
+                .reload(path.as_deref())
+                .map_err(|error| error.to_string())?;
+            tracing::info!("{}", reload);
+            Ok(reload.to_string())
+        },
+        Duration::from_secs(config.control.timeout),
+    );
+    control::serve(socket, config.control.max_clients, control)
+}
+
+// everything the bot does with an event besides sending
+struct Bot {
+    chat_bot: ChatBot,
+    helix: Helix,
+    exporter: Option<JsonExporter>,
+    irc_logger: Option<IrcLogger>,
+    chat_logs: Option<ChatLogs>,
+    #[cfg(feature = "webhooks")]
+    webhooks: Option<webhooks::Webhooks>,
+    // the rest of discord is an event handler
+    #[cfg(feature = "discord")]
+    discord: Option<discord::Moderation>,
+    handlers: handlers::Handlers,
+    obs: Option<obs::Obs>,
+    tts: Option<tts::Tts>,
+    // the goodbye message may have been reloaded since the start
+    config: SharedConfig,
+}
+
+// the channel and the user an event is about, for the log
+fn event_fields(event: &ChatBotEvent) -> (Option<&str>, Option<&str>) {
+    match event {
+        ChatBotEvent::TextMessage(message)
+        | ChatBotEvent::Command(connect::Command { message, .. }) => {
+            (Some(&message.channel), Some(&message.user.name))
+        }
+        ChatBotEvent::UserNotice(notice) => (Some(&notice.channel), Some(&notice.user.name)),
+        ChatBotEvent::ClearChat(clear) => (Some(&clear.channel), clear.target_user.as_deref()),
+        ChatBotEvent::ClearMessage(clear) => (Some(&clear.channel), Some(&clear.login)),
+        _ => (None, None),
+    }
+}
+
+impl EventHandler for Bot {
+    async fn handle<C: Connection>(
+        &mut self,
+        event: ChatBotEvent,
+        chat: &C,
+    ) -> Result<ControlFlow<()>, Box<dyn Error>> {
+        let (channel, user) = event_fields(&event);
+        let span = tracing::info_span!("dispatch", channel, user);
+        self.dispatch(event, chat).instrument(span).await
+    }
+}
+
+impl Bot {
+    // the handlers' answers go after the core's
+    async fn run_handlers(
+        &mut self,
+        event: &ChatBotEvent,
+        bot_command: Option<ChatBotCommand>,
+    ) -> Option<ChatBotCommand> {
+        let message = matches!(
+            event,
+            ChatBotEvent::TextMessage(_) | ChatBotEvent::Command(_)
+        );
+        if message && !self.chat_bot.admitted() {
+            return bot_command;
+        }
+        let config = self.config.load();
+        let answers = self.handlers.handle(event, &mut self.helix, &config).await;
+        match (bot_command, answers) {
+            (Some(bot_command), Some(answers)) => {
+                Some(MultipleCommands(vec![bot_command, answers]))
+            }
+            (bot_command, answers) => bot_command.or(answers),
+        }
+    }
+
+    async fn dispatch<C: Connection>(
+        &mut self,
+        event: ChatBotEvent,
+        chat: &C,
+    ) -> Result<ControlFlow<()>, Box<dyn Error>> {
+        if let Some(exporter) = self.exporter.as_mut() {
+            if let Err(error) = exporter.export(&event) {
+                tracing::warn!(%error, "could not export the message");
+            }
+        }
+        if let Some(irc_logger) = self.irc_logger.as_mut() {
+            if let Err(error) = irc_logger.log(&event) {
+                tracing::warn!(%error, "could not log the message");
+            }
+        }
+        if let Some(chat_logs) = &self.chat_logs {
+            chat_logs.log(&event);
+        }
+        #[cfg(feature = "discord")]
+        if let Some(discord) = &self.discord {
+            discord.observe(&event);
+        }
+        if let ChatBotEvent::TextMessage(message)
+        | ChatBotEvent::Command(connect::Command { message, .. }) = &event
+        {
+            prometheus::MESSAGES_RECEIVED.inc(&[&message.channel]);
+        }
+        let shutdown = event == ChatBotEvent::Shutdown;
+        let mut priority = priority(&event);
+        // a reminder or the schedule is sent even late, unlike another round of the repeating
+        // messages
+        let repeating = priority == Priority::Timer
+            && !matches!(
+                event,
+                ChatBotEvent::Internal(
+                    InternalEvent::ReminderDue { .. } | InternalEvent::ScheduleTick
+                )
+            );
+        #[cfg(feature = "webhooks")]
+        let webhook = self
+            .webhooks
+            .as_ref()
+            .and_then(|webhooks| webhooks.prepare(&event, std::time::SystemTime::now()));
+        let handled = (!self.handlers.is_empty()).then(|| event.clone());
+        let mut bot_command = self.chat_bot.handle_event(event);
+        if let Some(event) = handled {
+            bot_command = self.run_handlers(&event, bot_command).await;
+        }
+        #[cfg(feature = "webhooks")]
+        if let (Some(webhooks), Some(webhook)) = (&self.webhooks, webhook) {
+            webhooks.send(webhook, bot_command.is_some());
+        }
+        if bot_command.as_ref().is_some_and(is_moderation) {
+            priority = Priority::Moderation;
+        }
+        if repeating && chat.queue_depth() > BACKLOG_LIMIT {
+            tracing::info!(
+                waiting = chat.queue_depth(),
+                dropped = chat.send_count(Priority::Timer).dropped,
+                "skipping a repeating message"
+            );
+            bot_command = bot_command.and_then(without_messages);
+        }
+        if let Some(bot_command) = bot_command {
+            process_command(
+                bot_command,
+                chat,
+                &mut self.helix,
+                self.obs.as_ref(),
+                self.tts.as_ref(),
+                priority,
+            )
+            .await?;
+        }
+        prometheus::QUEUE_DEPTH.set(chat.queue_depth() as i64);
+        if shutdown {
+            let goodbye = self.config.load().chat.goodbye_message.clone();
+            chat.shutdown(goodbye.as_deref(), SHUTDOWN_DEADLINE).await?;
+            if let Some(chat_logs) = self.chat_logs.take() {
+                chat_logs.finish();
             }
+            return Ok(ControlFlow::Break(()));
         }
+        Ok(ControlFlow::Continue(()))
     }
+}
+
+// the handlers that need no config, discord is added when it is set up
+fn built_in_handlers(storage: Storage) -> handlers::Handlers {
+    let mut handlers = handlers::Handlers::new(storage);
+    handlers.register(Box::new(handlers::HypeChat));
+    handlers
+}
+
+// the bot answers a raw IRC log instead of twitch chat, what it sends is printed
+async fn replay(path: &str, timing: ReplayTiming) -> Result<(), Box<dyn Error>> {
+    logging::init(&Config::default().logging);
+    let log = BufReader::new(File::open(path)?);
+    let mut source = ReplaySource::new(log, timing, io::stdout());
+    let mut bot = Bot {
+        chat_bot: ChatBot::new(),
+        helix: Helix::default(),
+        exporter: None,
+        irc_logger: None,
+        chat_logs: None,
+        #[cfg(feature = "webhooks")]
+        webhooks: None,
+        #[cfg(feature = "discord")]
+        discord: None,
+        handlers: built_in_handlers(Storage::default()),
+        obs: None,
+        tts: None,
+        config: SharedConfig::new(Config::default()),
+    };
+    source.run(&mut bot).await?;
+    println!("{}", source.summary());
+    Ok(())
+}
+
+// exits like the bot does when it can't start with the config
+fn load_config(path: Option<&Path>) -> Config {
+    match Config::load(path) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(2);
+        }
+    }
+}
+
+// how long `send` waits for twitch to confirm the join, and then for the message to go out
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+// logs in to join only the channel, instead of the configured ones
+async fn send(mut config: Config, channel: &str, message: &str) -> Result<(), Box<dyn Error>> {
+    if config.twitch.anonymous {
+        return Err("send needs a login, twitch.anonymous is set".into());
+    }
+    logging::init(&config.logging);
+    let channel = channel.trim_start_matches('#').to_lowercase();
+    config.twitch.channels = vec![channel.clone()];
+    let mut chat = TwitchChatConnector::new(&SharedConfig::new(config.clone())).await;
+    send_once(&mut chat, &config.twitch.user, &channel, message).await
+}
+
+// the message goes out once twitch confirmed the bot joined, then the bot quits
+async fn send_once<C: Connection>(
+    chat: &mut C,
+    user: &str,
+    channel: &str,
+    message: &str,
+) -> Result<(), Box<dyn Error>> {
+    chat.join(channel)?;
+    let joined = async {
+        while let Some(event) = chat.next_event().await {
+            if let ChatBotEvent::Join {
+                user: joined,
+                channel: to,
+            } = &event
+            {
+                if joined.eq_ignore_ascii_case(user) && to == channel {
+                    return true;
+                }
+            }
+        }
+        false
+    };
+    match tokio::time::timeout(SEND_TIMEOUT, joined).await {
+        Ok(true) => {}
+        Ok(false) => return Err(format!("The connection ended before joining #{}", channel).into()),
+        Err(_) => {
+            return Err(format!(
+                "Twitch didn't confirm joining #{} within {} seconds",
+                channel,
+                SEND_TIMEOUT.as_secs()
+            )
+            .into())
+        }
+    }
+    chat.send_message(channel, message, Overflow::Split, Priority::Response)?;
+    chat.shutdown(None, SEND_TIMEOUT).await?;
     Ok(())
 }
 
+// `token validate|refresh` with the stored tokens, nobody is asked to authorize the bot
+async fn token(
+    config: &Config,
+    action: TokenAction,
+    identity: Identity,
+) -> Result<String, Box<dyn Error>> {
+    let mut tokens = AccessTokenDispenser::stored(config, identity).await?;
+    if action == TokenAction::Refresh {
+        tokens.refresh().await?;
+    }
+    Ok(token_report(config, identity, tokens.validate().await?)?)
+}
+
+// what twitch told about the token, an error when the bot can't use it as it is
+fn token_report(
+    config: &Config,
+    identity: Identity,
+    info: Option<TokenInfo>,
+) -> Result<String, String> {
+    let Some(info) = info else {
+        return Err(
+            "The stored access token expired or was revoked, `token refresh` renews it".to_owned(),
+        );
+    };
+    let minutes = info.expires_in.as_secs() / 60;
+    match info.missing_scopes(config, identity)[..] {
+        [] => Ok(format!(
+            "The access token of {} is valid for {} more minutes, with every scope the config needs",
+            info.login, minutes
+        )),
+        ref missing => Err(format!(
+            "The access token of {} is valid for {} more minutes, but lacks {}. Delete {} and start the bot to authorize it again",
+            info.login,
+            minutes,
+            missing.join(", "),
+            identity.store().display()
+        )),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let app_config = AppConfig::new()?;
+    let cli = cli::parse(env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{}\n\n{}", error, cli::USAGE);
+        process::exit(2);
+    });
+    let path = cli.config;
+    match cli.command {
+        cli::Command::Run => run(path).await,
+        cli::Command::Replay { path, timing } => replay(&path, timing).await,
+        cli::Command::ValidateConfig => {
+            let config = load_config(path.as_deref());
+            print!("{}{}", config.redacted(), config.effective_channels());
+            Ok(())
+        }
+        cli::Command::Send { channel, message } => {
+            send(load_config(path.as_deref()), &channel, &message).await
+        }
+        cli::Command::Token(action, identity) => {
+            match token(&load_config(path.as_deref()), action, identity).await {
+                Ok(report) => {
+                    println!("{}", report);
+                    Ok(())
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            }
+        }
+        cli::Command::Export { channel, range } => {
+            let config = load_config(path.as_deref());
+            let stats = CommandStats::load(Storage::from_config(&config.storage)?)?;
+            match Exporter::new(&config).export(channel.as_deref(), &range, &stats) {
+                Ok(exported) => {
+                    println!("{}", exported);
+                    Ok(())
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            }
+        }
+        cli::Command::ExampleConfig => {
+            print!("{}", Config::example());
+            Ok(())
+        }
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    }
+}
+
+async fn run(path: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let config = load_config(path.as_deref());
+    logging::init(&config.logging);
+
+    let shared = SharedConfig::new(config.clone());
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(shared.clone(), path.clone()));
+
+    let mut connector = TwitchChatConnector::new(&shared).await;
+    if let Some(listen) = &config.http.listen {
+        let probes = http::Probes {
+            health: connector.health(),
+            limits: HealthLimits {
+                stale_after: Duration::from_secs(config.http.stale_after),
+                max_queue: config.http.max_queue,
+            },
+        };
+        // the bot answers between the events of the chat
+        let api = config.http.api_token.clone().map(|token| {
+            let chat = connector.handle();
+            let runtime = tokio::runtime::Handle::current();
+            api::Api::new(token, move |event| {
+                let _runtime = runtime.enter();
+                chat.schedule(Duration::ZERO, event);
+            })
+        });
+        http::serve(listen, probes, api)
+            .map_err(|error| format!("Could not listen on {}: {}", listen, error))?;
+    }
+    #[cfg(unix)]
+    if let Some(socket) = &config.control.socket {
+        serve_control(socket, &config, &shared, path, &connector)
+            .map_err(|error| format!("Could not listen on {}: {}", socket.display(), error))?;
+    }
+    let shutdown_chat = connector.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown_chat.schedule(Duration::ZERO, ChatBotEvent::Shutdown);
+    });
+    for channel in config.channel_names() {
+        skip_invalid(connector.send_message(
+            &channel,
+            "Hello, world!",
+            Overflow::Split,
+            Priority::Response,
+        ))?;
+    }
 
-    let (tx, rx) = mpsc::channel();
+    let storage = Storage::from_config(&config.storage)?;
+    #[allow(unused_mut)]
+    let mut handlers = built_in_handlers(storage.clone());
+    #[cfg(feature = "discord")]
+    let discord = discord::Discord::new(&config.discord).map(|discord| {
+        let moderation = discord.moderation();
+        handlers.register(Box::new(discord));
+        moderation
+    });
+    // without the broadcaster's token the bot runs on, its features are disabled below
+    let broadcaster = match config.broadcaster.login {
+        Some(_) => AccessTokenDispenser::new(&config, Identity::Broadcaster)
+            .await
+            .inspect_err(|error| tracing::warn!(%error, "could not get the broadcaster's token"))
+            .ok()
+            .map(SharedTokens::new),
+        None => None,
+    };
+    let mut helix = Helix::connect(&config, connector.tokens(), broadcaster);
+    if !config.twitch.anonymous {
+        for disabled in helix.check(&config).await {
+            tracing::warn!("{}", disabled);
+        }
+    }
+    let mut bot = Bot {
+        chat_bot: ChatBot::load(&config, storage.clone())?,
+        helix,
+        exporter: config
+            .output
+            .chat_export
+            .as_deref()
+            .map(JsonExporter::new)
+            .transpose()?,
+        irc_logger: config
+            .output
+            .chat_log
+            .as_deref()
+            .map(IrcLogger::new)
+            .transpose()?,
+        chat_logs: config
+            .chat_logs
+            .enabled
+            .then(|| ChatLogs::new(&config.chat_logs)),
+        #[cfg(feature = "webhooks")]
+        webhooks: webhooks::Webhooks::new(&config.webhooks, storage),
+        #[cfg(feature = "discord")]
+        discord: discord.flatten(),
+        handlers,
+        obs: obs::Obs::start(&config.obs),
+        tts: tts::Tts::start(&config.tts),
+        config: shared,
+    };
+    // twitch tells about follows and redemptions only over EventSub, helix needs the token
+    // for subscribing
+    if bot.chat_bot.wants_eventsub() && !config.twitch.anonymous {
+        let eventsub_chat = connector.handle();
+        let runtime = tokio::runtime::Handle::current();
+        spawn_eventsub(EVENTSUB_URL, move |event| {
+            let _runtime = runtime.enter();
+            eventsub_chat.schedule(Duration::ZERO, event);
+        });
+    }
+    if let Some(command) = bot.chat_bot.start_timers() {
+        process_command(
+            command,
+            &connector,
+            &mut bot.helix,
+            bot.obs.as_ref(),
+            bot.tts.as_ref(),
+            Priority::Timer,
+        )
+        .await?;
+    }
+    connector.run(&mut bot).await
+}
 
-    let connector = TwitchChatConnector::new(&app_config, tx.clone()).await;
-    connector.send_message("Hello, world!")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::testing::MockConnection;
 
-    let mut chat_bot = ChatBot::new();
-    while let Ok(message) = rx.recv() {
-        if let Some(bot_command) = chat_bot.handle_event(message) {
-            process_command(bot_command, &connector, tx.clone())?;
+    fn bot() -> Bot {
+        Bot {
+            chat_bot: ChatBot::new(),
+            helix: Helix::default(),
+            exporter: None,
+            irc_logger: None,
+            chat_logs: None,
+            #[cfg(feature = "webhooks")]
+            webhooks: None,
+            #[cfg(feature = "discord")]
+            discord: None,
+            handlers: built_in_handlers(Storage::default()),
+            obs: None,
+            tts: None,
+            config: SharedConfig::new(Config::default()),
         }
     }
-    Ok(())
+
+    // a template for testing commands: script what twitch sends, check what the bot answers
+    #[tokio::test]
+    async fn hello_is_answered_once_defined() {
+        let mut chat = MockConnection::new(&[
+            "@badges=broadcaster/1 :captaincallback!captaincallback@captaincallback.tmi.twitch.tv \
+             PRIVMSG #captaincallback :!newcommand hello Hello there!",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!hello",
+        ]);
+        chat.run(&mut bot()).await.unwrap();
+        assert_eq!(
+            chat.sent(),
+            vec![
+                "PRIVMSG #captaincallback :The new command has been defined successfully.\r\n",
+                "PRIVMSG #captaincallback :Hello there!\r\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn new_command_keeps_its_spacing() {
+        let mut chat = MockConnection::new(&[
+            "@badges=broadcaster/1 :captaincallback!captaincallback@captaincallback.tmi.twitch.tv \
+             PRIVMSG #captaincallback :!newcommand lenny ( ͡°  ͜ʖ ͡°)",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!lenny",
+        ]);
+        chat.run(&mut bot()).await.unwrap();
+        assert_eq!(chat.sent()[1], "PRIVMSG #captaincallback :( ͡°  ͜ʖ ͡°)\r\n");
+    }
+
+    #[tokio::test]
+    async fn channels_call_commands_with_their_prefix() {
+        let mut bot = bot();
+        let config = Config {
+            commands: config::CommandsConfig {
+                channel_prefixes: [("carkhy".to_owned(), "?".to_owned())].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        bot.chat_bot = ChatBot::load(&config, Storage::default()).unwrap();
+        let mut chat = MockConnection::new(&[
+            ":captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #carkhy :!info",
+            ":captaincallback!captaincallback@captaincallback.tmi.twitch.tv PRIVMSG #carkhy :?info",
+        ]);
+        chat.run(&mut bot).await.unwrap();
+        let sent = chat.sent();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("PRIVMSG #carkhy :Hello, my name is"));
+    }
+
+    #[tokio::test]
+    async fn hype_trains_are_announced_once_per_level() {
+        use crate::connect::eventsub_testing::{
+            eventsub_server, hype_train, session_events, welcome,
+        };
+        let mut bot = bot();
+        let config = Config {
+            events: config::EventsConfig {
+                eventsub: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        bot.chat_bot = ChatBot::load(&config, Storage::default()).unwrap();
+        let url = eventsub_server(vec![
+            welcome("AQoQ", 10),
+            hype_train("1", "begin", 1, &["Carkhy"]),
+            hype_train("2", "progress", 1, &["Carkhy"]),
+            hype_train("3", "progress", 2, &["Carkhy"]),
+            hype_train("4", "progress", 3, &["Carkhy"]),
+            hype_train("5", "progress", 3, &["Carkhy", "TenaciousByte"]),
+            hype_train("6", "end", 3, &["Carkhy", "TenaciousByte"]),
+        ]);
+        let chat = MockConnection::new(&[]);
+        // the subscriptions fail without a token, only the log tells
+        for event in session_events(&url) {
+            let flow = bot.handle(event, &chat).await.unwrap();
+            assert_eq!(flow, ControlFlow::Continue(()));
+        }
+        assert_eq!(
+            chat.sent(),
+            vec![
+                "PRIVMSG #captaincallback :Hype Train level 1 started!\r\n",
+                "PRIVMSG #captaincallback :The Hype Train reached level 2!\r\n",
+                "PRIVMSG #captaincallback :The Hype Train reached level 3!\r\n",
+                "PRIVMSG #captaincallback :The Hype Train ended at level 3! Thanks to Carkhy, TenaciousByte\r\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn redemptions_from_eventsub_are_answered() {
+        use crate::connect::eventsub_testing::{
+            eventsub_server, redemption, session_events, welcome,
+        };
+        let mut bot = bot();
+        let config = Config {
+            moderation: config::ModerationConfig {
+                banned_terms: vec![config::BannedTermConfig {
+                    term: "badword".to_owned(),
+                    regex: false,
+                    punishment: config::Punishment::Delete,
+                }],
+                ..Default::default()
+            },
+            redemptions: config::RedemptionsConfig {
+                fulfill: false,
+                rewards: vec![config::RewardConfig {
+                    reward: "Hydrate".to_owned(),
+                    message: "$(user) says drink some water: $(input)".to_owned(),
+                    command: String::new(),
+                    webhook: String::new(),
+                }],
+            },
+            ..Default::default()
+        };
+        bot.chat_bot = ChatBot::load(&config, Storage::default()).unwrap();
+        let url = eventsub_server(vec![
+            welcome("AQoQ", 10),
+            redemption("Hydrate", "badword"),
+            redemption("Song", "never gonna"),
+            redemption("hydrate", "now!"),
+        ]);
+        let chat = MockConnection::new(&[]);
+        // the subscription fails without a token, only the log tells
+        for event in session_events(&url) {
+            let flow = bot.handle(event, &chat).await.unwrap();
+            assert_eq!(flow, ControlFlow::Continue(()));
+        }
+        assert_eq!(
+            chat.sent(),
+            vec!["PRIVMSG #captaincallback :Carkhy says drink some water: now!\r\n"]
+        );
+    }
+
+    #[test]
+    fn removals_are_moderation() {
+        let delete = Helix(HelixTask::DeleteMessage {
+            channel: "carkhy".to_owned(),
+            message_id: "1".to_owned(),
+        });
+        let notice = LogTextMessage("Deleting".to_owned());
+        assert!(is_moderation(&MultipleCommands(vec![notice, delete])));
+        let uptime = Helix(HelixTask::Uptime {
+            channel: "carkhy".to_owned(),
+        });
+        assert!(!is_moderation(&uptime));
+    }
+
+    #[tokio::test]
+    async fn goodbye_is_read_when_shutting_down() {
+        let mut bot = bot();
+        let mut config = Config::default();
+        config.chat.goodbye_message = Some("Bye!".to_owned());
+        bot.config = SharedConfig::new(config);
+        let chat = MockConnection::new(&[]);
+        chat.join("captaincallback").unwrap();
+        let flow = bot.handle(ChatBotEvent::Shutdown, &chat).await.unwrap();
+        assert!(flow.is_break());
+        assert_eq!(
+            chat.sent(),
+            vec![
+                "JOIN #captaincallback\r\n",
+                "PRIVMSG #captaincallback :Bye!\r\n",
+                "QUIT\r\n",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn control_socket_drives_the_bot() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        let (events, mut forwarded) = tokio::sync::mpsc::unbounded_channel();
+        let control = control::Control::new(
+            move |event| {
+                let _ = events.send(event);
+            },
+            || serde_json::json!({ "status": "ok" }),
+            || Ok("Reloaded the config, nothing changed".to_owned()),
+            Duration::from_secs(5),
+        );
+        let path = env::temp_dir().join(format!("chatbot-control-bot-{}.sock", process::id()));
+        control::serve(&path, 1, control).unwrap();
+        let requests = [
+            r#"{"command":"join","channel":"CaptainCallback"}"#,
+            r#"{"command":"send","channel":"captaincallback","text":"Stream starts soon!"}"#,
+            r#"{"command":"run","channel":"captaincallback","text":"!newcommand hello Hi!"}"#,
+            r#"{"command":"run","channel":"captaincallback","text":"!hello"}"#,
+            r#"{"command":"part","channel":"captaincallback"}"#,
+        ];
+        let client = tokio::spawn(async move {
+            let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut answers = Vec::new();
+            for request in requests {
+                let line = format!("{}\n", request);
+                stream.get_mut().write_all(line.as_bytes()).await.unwrap();
+                let mut answer = String::new();
+                stream.read_line(&mut answer).await.unwrap();
+                answers.push(answer.trim_end().to_owned());
+            }
+            let _ = std::fs::remove_file(&path);
+            answers
+        });
+        let chat = MockConnection::new(&[]);
+        let mut bot = bot();
+        for _ in requests {
+            let event = forwarded.recv().await.unwrap();
+            let flow = bot.handle(event, &chat).await.unwrap();
+            assert_eq!(flow, ControlFlow::Continue(()));
+        }
+        assert_eq!(
+            client.await.unwrap(),
+            [
+                r#"{"joined":"captaincallback","ok":true}"#,
+                r#"{"ok":true,"sent":true}"#,
+                r#"{"answered":true,"ok":true}"#,
+                r#"{"answered":true,"ok":true}"#,
+                r#"{"left":"captaincallback","ok":true}"#,
+            ]
+        );
+        assert_eq!(
+            chat.sent(),
+            vec![
+                "JOIN #captaincallback\r\n",
+                "PRIVMSG #captaincallback :Stream starts soon!\r\n",
+                "PRIVMSG #captaincallback :The new command has been defined successfully.\r\n",
+                "PRIVMSG #captaincallback :Hi!\r\n",
+                "PART #captaincallback\r\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_waits_for_the_join() {
+        let mut chat = MockConnection::new(&[
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv JOIN #captaincallback",
+            ":carkhybot!carkhybot@carkhybot.tmi.twitch.tv JOIN #captaincallback",
+        ]);
+        send_once(
+            &mut chat,
+            "CarkhyBot",
+            "captaincallback",
+            "Stream starts soon!",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            chat.sent(),
+            vec![
+                "JOIN #captaincallback\r\n",
+                "PRIVMSG #captaincallback :Stream starts soon!\r\n",
+                "QUIT\r\n",
+            ]
+        );
+        let mut chat =
+            MockConnection::new(&[":carkhy!carkhy@carkhy.tmi.twitch.tv JOIN #captaincallback"]);
+        assert!(send_once(&mut chat, "carkhybot", "captaincallback", "hi")
+            .await
+            .is_err());
+        assert_eq!(chat.sent(), vec!["JOIN #captaincallback\r\n"]);
+    }
+
+    #[test]
+    fn validated_config_is_printed_without_secrets() {
+        let path = env::temp_dir().join(format!("chatbot-validate-{}.toml", process::id()));
+        std::fs::write(
+            &path,
+            "[twitch]\nuser = \"carkhybot\"\nclient_id = \"id\"\nclient_secret = \"hunter2\"\n",
+        )
+        .unwrap();
+        let printed = Config::load(Some(&path)).unwrap().redacted();
+        assert!(printed.contains("user = \"carkhybot\""), "{}", printed);
+        assert!(!printed.contains("hunter2"), "{}", printed);
+        std::fs::write(&path, "[connection]\nkeepalive = 0\n").unwrap();
+        assert!(Config::load(Some(&path)).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn token_report_tells_what_is_missing() {
+        let info = |scopes: &[&str]| TokenInfo {
+            login: "carkhybot".to_owned(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            expires_in: Duration::from_secs(5400),
+        };
+        let config = Config::default();
+        let all = info(&[]).missing_scopes(&config, Identity::Bot);
+        assert_eq!(
+            token_report(&config, Identity::Bot, Some(info(&all))),
+            Ok("The access token of carkhybot is valid for 90 more minutes, with every scope the config needs".to_owned())
+        );
+        let report = token_report(&config, Identity::Bot, Some(info(&["chat:read"]))).unwrap_err();
+        assert!(report.contains("lacks chat:edit"), "{}", report);
+        assert!(token_report(&config, Identity::Bot, None).is_err());
+        let report =
+            token_report(&config, Identity::Broadcaster, Some(info(&["chat:read"]))).unwrap_err();
+        assert!(
+            report.contains("Delete ./auth_store_broadcaster"),
+            "{}",
+            report
+        );
+    }
+
+    #[tokio::test]
+    async fn denial_is_a_threaded_reply_when_tags_are_granted() {
+        let mut chat = MockConnection::new(&[
+            "@id=b34ccfc7 :carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #captaincallback :!newcommand hello Hi",
+        ])
+        .grant("twitch.tv/tags");
+        chat.run(&mut bot()).await.unwrap();
+        assert_eq!(
+            chat.sent(),
+            vec![
+                "@reply-parent-msg-id=b34ccfc7 PRIVMSG #captaincallback :Denied: i ought to !slap you...\r\n"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn repeating_message_is_scheduled() {
+        let mut chat = MockConnection::new(&[
+            "@badges=broadcaster/1 :captaincallback!captaincallback@captaincallback.tmi.twitch.tv \
+             PRIVMSG #captaincallback :!newrepeating discord 600 Join the discord!",
+        ]);
+        chat.run(&mut bot()).await.unwrap();
+        let scheduled = chat.scheduled();
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].0, Duration::from_secs(600));
+        assert!(matches!(
+            &scheduled[0].1,
+            ChatBotEvent::Internal(InternalEvent::TimedMessage { channel, name, .. })
+                if channel == "captaincallback" && name == "discord"
+        ));
+    }
+
+    // the counters are shared by all tests, only this one uses the channel
+    #[tokio::test]
+    async fn metrics_are_scraped() {
+        let mut chat = MockConnection::new(&[
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #metricschannel :hi",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG #metricschannel :!info",
+            ":carkhy!carkhy@carkhy.tmi.twitch.tv PRIVMSG",
+        ]);
+        chat.run(&mut bot()).await.unwrap();
+        let address = http::serve("127.0.0.1:0", http::testing::probes(), None).unwrap();
+        let (status, body) = http::testing::get(address, "/metrics");
+        assert_eq!(status, "HTTP/1.0 200 OK");
+        assert!(body.contains("chatbot_messages_received_total{channel=\"metricschannel\"} 2\n"));
+        assert!(body.contains("chatbot_messages_sent_total{channel=\"metricschannel\"} 1\n"));
+        assert!(body.contains("chatbot_commands_total{command=\"info\"} "));
+        assert!(body.contains("chatbot_parse_errors_total{error=\"missing_channel\"} "));
+    }
 }
@@ -0,0 +1,477 @@
+//! OBS's scenes, inputs and sources controlled over obs-websocket 5, from a thread of its own
+//! that connects again when OBS goes away. The scene and input lists are fetched at each
+//! connect and again when OBS tells they changed, so that a typo in chat is refused with the
+//! names OBS has instead of doing nothing.
+mod protocol;
+
+use crate::{
+    config::ObsConfig,
+    connect::{connect_websocket, random_jitter, Backoff, ChatStream},
+};
+use protocol::Message;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use thiserror::Error;
+use uuid::Uuid;
+use websocket::{
+    sender::{Sender, Writer},
+    sync::Client,
+    OwnedMessage,
+};
+
+// how long a request waits for OBS, the event waits as long
+const TIMEOUT: Duration = Duration::from_secs(5);
+// the ids of the list requests after a change, their responses update the lists
+const SCENE_LIST: &str = "scene-list";
+const INPUT_LIST: &str = "input-list";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ObsError {
+    #[error("OBS is not connected")]
+    NotConnected,
+    #[error("OBS did not answer in time")]
+    Timeout,
+    #[error("OBS refused: {comment}")]
+    Refused { code: u64, comment: String },
+    // known are the names OBS has, for chat to pick from
+    #[error("there is no {kind} {name}, the {kind}s are {known}")]
+    Unknown {
+        kind: &'static str,
+        name: String,
+        known: String,
+    },
+    // the connection failed or OBS ended it, e.g. for a wrong password
+    #[error("{0}")]
+    Session(String),
+}
+
+/// A source made visible in a scene, to hide it again in the same scene.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shown {
+    pub scene: String,
+    pub source: String,
+    id: u64,
+}
+
+#[derive(Default)]
+struct State {
+    // None while OBS is not connected
+    writer: Option<Writer<ChatStream>>,
+    // the requests waiting for their response, by request id
+    pending: HashMap<String, mpsc::Sender<Result<Value, ObsError>>>,
+    scenes: Vec<String>,
+    inputs: Vec<String>,
+}
+
+/// The connection to OBS, clones share it. Requests block until OBS answered.
+#[derive(Clone, Default)]
+pub struct Obs(Arc<Mutex<State>>);
+
+fn session(reason: String) -> ObsError {
+    ObsError::Session(reason)
+}
+
+// the next message OBS sends that the bot understands, pings are answered with `send`
+fn receive(
+    client: &mut Client<ChatStream>,
+    send: &mut dyn FnMut(OwnedMessage) -> Result<(), ObsError>,
+) -> Result<Message, ObsError> {
+    loop {
+        match client.recv_message() {
+            Ok(OwnedMessage::Text(text)) => {
+                if let Some(message) = protocol::parse(&text) {
+                    return Ok(message);
+                }
+            }
+            Ok(OwnedMessage::Ping(data)) => send(OwnedMessage::Pong(data))?,
+            // OBS tells why, e.g. "Authentication failed."
+            Ok(OwnedMessage::Close(close)) => {
+                let reason = close.map(|close| close.reason).unwrap_or_default();
+                return Err(session(format!("OBS closed the connection: {}", reason)));
+            }
+            Ok(_) => {}
+            Err(err) => return Err(session(format!("Could not receive from OBS: {:?}", err))),
+        }
+    }
+}
+
+// by its exact name, else by one differing in case
+fn known(kind: &'static str, name: &str, names: &[String]) -> Result<String, ObsError> {
+    names
+        .iter()
+        .find(|known| *known == name)
+        .or_else(|| names.iter().find(|known| known.eq_ignore_ascii_case(name)))
+        .cloned()
+        .ok_or_else(|| ObsError::Unknown {
+            kind,
+            name: name.to_owned(),
+            known: names.join(", "),
+        })
+}
+
+impl Obs {
+    /// Connects from a thread of its own until the bot stops, None without `obs.url`.
+    pub fn start(config: &ObsConfig) -> Option<Self> {
+        let url = config.url.clone()?;
+        let password = config.password.clone();
+        let obs = Self::default();
+        let connection = obs.clone();
+        thread::spawn(move || {
+            let backoff = Backoff::default();
+            let mut attempt = 0;
+            loop {
+                let error = match connection.connect(&url, password.as_deref()) {
+                    Ok(client) => {
+                        attempt = 0;
+                        connection.listen(client)
+                    }
+                    Err(error) => error,
+                };
+                connection.disconnected();
+                attempt += 1;
+                let delay = backoff.delay(attempt, random_jitter());
+                tracing::warn!(?delay, %error, "OBS failed, reconnecting");
+                thread::sleep(delay);
+            }
+        });
+        Some(obs)
+    }
+
+    // hello, identify with the password, then the lists before any request is let through
+    fn connect(&self, url: &str, password: Option<&str>) -> Result<Client<ChatStream>, ObsError> {
+        let mut client = connect_websocket(url).map_err(|err| session(err.to_string()))?;
+        let stream = client
+            .stream_ref()
+            .try_clone()
+            .map_err(|err| session(format!("Could not write to OBS: {:?}", err)))?;
+        let mut writer = Writer {
+            stream,
+            sender: Sender::new(true),
+        };
+        let mut send = |message: OwnedMessage| {
+            writer
+                .send_message(&message)
+                .map_err(|err| session(format!("Could not send to OBS: {:?}", err)))
+        };
+        let authentication = match receive(&mut client, &mut send)? {
+            Message::Hello {
+                authentication: None,
+            } => None,
+            Message::Hello {
+                authentication: Some((challenge, salt)),
+            } => {
+                let password = password.ok_or_else(|| {
+                    session("OBS wants a password, obs.password is not set".to_owned())
+                })?;
+                Some(protocol::authentication(password, &salt, &challenge))
+            }
+            message => return Err(session(format!("OBS did not say hello: {:?}", message))),
+        };
+        send(OwnedMessage::Text(protocol::identify(authentication)))?;
+        match receive(&mut client, &mut send)? {
+            Message::Identified => {}
+            message => return Err(session(format!("OBS did not identify: {:?}", message))),
+        }
+        let mut lists = Vec::new();
+        for (id, kind) in [(SCENE_LIST, "GetSceneList"), (INPUT_LIST, "GetInputList")] {
+            send(OwnedMessage::Text(protocol::request(kind, id, json!({}))))?;
+            loop {
+                match receive(&mut client, &mut send)? {
+                    Message::Response {
+                        id: answered,
+                        result,
+                    } if answered == id => {
+                        lists.push(result?);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut state = self.0.lock().unwrap();
+        state.scenes = protocol::names(&lists[0], "scenes", "sceneName");
+        state.inputs = protocol::names(&lists[1], "inputs", "inputName");
+        tracing::info!(scenes = %state.scenes.join(", "), "connected to OBS");
+        state.writer = Some(writer);
+        Ok(client)
+    }
+
+    // hands the responses to the waiting requests until the connection ends
+    fn listen(&self, mut client: Client<ChatStream>) -> ObsError {
+        loop {
+            let message = match receive(&mut client, &mut |message| self.write(message)) {
+                Ok(message) => message,
+                Err(error) => return error,
+            };
+            let list = match message {
+                Message::Response { id, result } => {
+                    self.answered(&id, result);
+                    None
+                }
+                Message::Event { kind } => match kind.as_str() {
+                    "SceneCreated" | "SceneRemoved" | "SceneNameChanged" => {
+                        Some((SCENE_LIST, "GetSceneList"))
+                    }
+                    "InputCreated" | "InputRemoved" | "InputNameChanged" => {
+                        Some((INPUT_LIST, "GetInputList"))
+                    }
+                    _ => None,
+                },
+                Message::Hello { .. } | Message::Identified => None,
+            };
+            if let Some((id, kind)) = list {
+                let request = protocol::request(kind, id, json!({}));
+                if let Err(error) = self.write(OwnedMessage::Text(request)) {
+                    return error;
+                }
+            }
+        }
+    }
+
+    fn answered(&self, id: &str, result: Result<Value, ObsError>) {
+        let mut state = self.0.lock().unwrap();
+        match (id, result) {
+            (SCENE_LIST, Ok(data)) => state.scenes = protocol::names(&data, "scenes", "sceneName"),
+            (INPUT_LIST, Ok(data)) => state.inputs = protocol::names(&data, "inputs", "inputName"),
+            (id, result) => {
+                if let Some(waiting) = state.pending.remove(id) {
+                    let _ = waiting.send(result);
+                }
+            }
+        }
+    }
+
+    fn write(&self, message: OwnedMessage) -> Result<(), ObsError> {
+        let mut state = self.0.lock().unwrap();
+        let writer = state.writer.as_mut().ok_or(ObsError::NotConnected)?;
+        writer
+            .send_message(&message)
+            .map_err(|err| session(format!("Could not send to OBS: {:?}", err)))
+    }
+
+    // the waiting requests fail as not connected
+    fn disconnected(&self) {
+        let mut state = self.0.lock().unwrap();
+        if let Some(writer) = state.writer.take() {
+            writer.stream.shutdown();
+        }
+        state.pending.clear();
+    }
+
+    /// Sends the request and waits for OBS's response data.
+    pub fn request(&self, kind: &str, data: Value) -> Result<Value, ObsError> {
+        let id = Uuid::new_v4().to_string();
+        let (sender, response) = mpsc::channel();
+        self.0.lock().unwrap().pending.insert(id.clone(), sender);
+        if let Err(error) = self.write(OwnedMessage::Text(protocol::request(kind, &id, data))) {
+            self.0.lock().unwrap().pending.remove(&id);
+            return Err(error);
+        }
+        match response.recv_timeout(TIMEOUT) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                self.0.lock().unwrap().pending.remove(&id);
+                Err(ObsError::Timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(ObsError::NotConnected),
+        }
+    }
+
+    fn lookup(&self, kind: &'static str, name: &str) -> Result<String, ObsError> {
+        let state = self.0.lock().unwrap();
+        if state.writer.is_none() {
+            return Err(ObsError::NotConnected);
+        }
+        match kind {
+            "scene" => known(kind, name, &state.scenes),
+            _ => known(kind, name, &state.inputs),
+        }
+    }
+
+    /// Switches the program to the scene, the name as OBS has it is returned.
+    pub fn set_scene(&self, name: &str) -> Result<String, ObsError> {
+        let scene = self.lookup("scene", name)?;
+        self.request("SetCurrentProgramScene", json!({ "sceneName": scene }))?;
+        Ok(scene)
+    }
+
+    /// Mutes the input or turns it on again, true when it is muted now.
+    pub fn toggle_mute(&self, name: &str) -> Result<(String, bool), ObsError> {
+        let input = self.lookup("input", name)?;
+        let data = self.request("ToggleInputMute", json!({ "inputName": input }))?;
+        Ok((input, data["inputMuted"].as_bool().unwrap_or_default()))
+    }
+
+    /// Makes the source visible in the program's scene.
+    pub fn show(&self, name: &str) -> Result<Shown, ObsError> {
+        let current = self.request("GetCurrentProgramScene", json!({}))?;
+        let scene = current["currentProgramSceneName"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+        let items = self.request("GetSceneItemList", json!({ "sceneName": scene }))?;
+        let source = known(
+            "source",
+            name,
+            &protocol::names(&items, "sceneItems", "sourceName"),
+        )?;
+        let id = items["sceneItems"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|item| item["sourceName"] == source.as_str())
+            .and_then(|item| item["sceneItemId"].as_u64())
+            .unwrap_or_default();
+        let shown = Shown { scene, source, id };
+        self.set_enabled(&shown, true)?;
+        Ok(shown)
+    }
+
+    pub fn hide(&self, shown: &Shown) -> Result<(), ObsError> {
+        self.set_enabled(shown, false)
+    }
+
+    fn set_enabled(&self, shown: &Shown, enabled: bool) -> Result<(), ObsError> {
+        self.request(
+            "SetSceneItemEnabled",
+            json!({ "sceneName": shown.scene, "sceneItemId": shown.id, "sceneItemEnabled": enabled }),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::protocol;
+    use serde_json::{json, Value};
+    use std::{sync::mpsc, thread};
+    use websocket::{sync::Server, CloseData, OwnedMessage};
+
+    /// An OBS with the scenes Main and BRB, the inputs Mic and Desktop Audio and the sources
+    /// Camera and Confetti in Main. The requests it gets are sent to the receiver.
+    pub fn obs_server(password: Option<&'static str>) -> (String, mpsc::Receiver<Value>) {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().unwrap();
+            let (challenge, salt) = ("+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=", "lM1Gnc");
+            let mut hello =
+                json!({ "op": 0, "d": { "obsWebSocketVersion": "5.1.0", "rpcVersion": 1 } });
+            if password.is_some() {
+                hello["d"]["authentication"] = json!({ "challenge": challenge, "salt": salt });
+            }
+            client
+                .send_message(&OwnedMessage::Text(hello.to_string()))
+                .unwrap();
+            let Ok(OwnedMessage::Text(identify)) = client.recv_message() else {
+                return;
+            };
+            let identify: Value = serde_json::from_str(&identify).unwrap();
+            let expected =
+                password.map(|password| protocol::authentication(password, salt, challenge));
+            if identify["d"]["authentication"].as_str() != expected.as_deref() {
+                let close = CloseData::new(4009, "Authentication failed.".to_owned());
+                let _ = client.send_message(&OwnedMessage::Close(Some(close)));
+                return;
+            }
+            let identified = json!({ "op": 2, "d": { "negotiatedRpcVersion": 1 } });
+            client
+                .send_message(&OwnedMessage::Text(identified.to_string()))
+                .unwrap();
+            while let Ok(OwnedMessage::Text(request)) = client.recv_message() {
+                let request: Value = serde_json::from_str(&request).unwrap();
+                let data = match request["d"]["requestType"].as_str().unwrap() {
+                    "GetSceneList" => {
+                        json!({ "currentProgramSceneName": "Main", "scenes": [{ "sceneName": "Main" }, { "sceneName": "BRB" }] })
+                    }
+                    "GetInputList" => {
+                        json!({ "inputs": [{ "inputName": "Mic" }, { "inputName": "Desktop Audio" }] })
+                    }
+                    "GetCurrentProgramScene" => json!({ "currentProgramSceneName": "Main" }),
+                    "GetSceneItemList" => {
+                        json!({ "sceneItems": [{ "sourceName": "Camera", "sceneItemId": 1 }, { "sourceName": "Confetti", "sceneItemId": 7 }] })
+                    }
+                    "ToggleInputMute" => json!({ "inputMuted": true }),
+                    _ => json!({}),
+                };
+                let response = json!({ "op": 7, "d": {
+                    "requestType": request["d"]["requestType"],
+                    "requestId": request["d"]["requestId"],
+                    "requestStatus": { "result": true, "code": 100 },
+                    "responseData": data,
+                } });
+                let _ = requests.send(request["d"].clone());
+                client
+                    .send_message(&OwnedMessage::Text(response.to_string()))
+                    .unwrap();
+            }
+        });
+        (format!("ws://127.0.0.1:{}", port), received)
+    }
+
+    /// Waits until the connection to OBS is up.
+    pub fn wait_connected(obs: &super::Obs) {
+        for _ in 0..500 {
+            if obs.0.lock().unwrap().writer.is_some() {
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("OBS did not connect");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::*, *};
+
+    #[test]
+    fn a_wrong_password_is_not_let_in() {
+        let (url, _) = obs_server(Some("supersecretpassword"));
+        let Err(error) = Obs::default().connect(&url, Some("hunter2")) else {
+            panic!("identified with the wrong password");
+        };
+        assert_eq!(
+            error,
+            ObsError::Session("OBS closed the connection: Authentication failed.".to_owned())
+        );
+        let (url, _) = obs_server(Some("supersecretpassword"));
+        assert!(Obs::default().connect(&url, None).is_err());
+    }
+
+    #[test]
+    fn scenes_are_switched_by_the_name_obs_has() {
+        let (url, requests) = obs_server(Some("supersecretpassword"));
+        let config = ObsConfig {
+            url: Some(url),
+            password: Some("supersecretpassword".to_owned()),
+            ..Default::default()
+        };
+        let obs = Obs::start(&config).unwrap();
+        wait_connected(&obs);
+        assert_eq!(requests.recv().unwrap()["requestType"], "GetSceneList");
+        assert_eq!(requests.recv().unwrap()["requestType"], "GetInputList");
+        assert_eq!(obs.set_scene("brb"), Ok("BRB".to_owned()));
+        let request = requests.recv().unwrap();
+        assert_eq!(request["requestType"], "SetCurrentProgramScene");
+        assert_eq!(request["requestData"], json!({ "sceneName": "BRB" }));
+        assert_eq!(
+            obs.set_scene("Lobby"),
+            Err(ObsError::Unknown {
+                kind: "scene",
+                name: "Lobby".to_owned(),
+                known: "Main, BRB".to_owned()
+            })
+        );
+        assert_eq!(obs.toggle_mute("mic"), Ok(("Mic".to_owned(), true)));
+    }
+}
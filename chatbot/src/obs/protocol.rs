@@ -0,0 +1,156 @@
+//! The messages of obs-websocket 5, see
+//! https://github.com/obsproject/obs-websocket/blob/master/docs/generated/protocol.md
+use super::ObsError;
+use crate::sha256::sha256;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+
+const RPC_VERSION: u64 = 1;
+// the Scenes and Inputs events, the bot only follows their lists
+pub const EVENT_SUBSCRIPTIONS: u64 = (1 << 2) | (1 << 3);
+
+/// What the bot understands of OBS's messages, the rest is ignored.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    // the challenge and the salt when OBS wants a password
+    Hello {
+        authentication: Option<(String, String)>,
+    },
+    Identified,
+    Event {
+        kind: String,
+    },
+    // the response data, or why OBS refused
+    Response {
+        id: String,
+        result: Result<Value, ObsError>,
+    },
+}
+
+pub fn parse(text: &str) -> Option<Message> {
+    let message: Value = serde_json::from_str(text).ok()?;
+    let data = &message["d"];
+    match message["op"].as_u64()? {
+        0 => Some(Message::Hello {
+            authentication: data.get("authentication").and_then(|auth| {
+                Some((
+                    auth["challenge"].as_str()?.to_owned(),
+                    auth["salt"].as_str()?.to_owned(),
+                ))
+            }),
+        }),
+        2 => Some(Message::Identified),
+        5 => Some(Message::Event {
+            kind: data["eventType"].as_str()?.to_owned(),
+        }),
+        7 => {
+            let status = &data["requestStatus"];
+            let result = match status["result"].as_bool()? {
+                true => Ok(data.get("responseData").cloned().unwrap_or(Value::Null)),
+                false => Err(ObsError::Refused {
+                    code: status["code"].as_u64().unwrap_or_default(),
+                    comment: status["comment"].as_str().unwrap_or_default().to_owned(),
+                }),
+            };
+            Some(Message::Response {
+                id: data["requestId"].as_str()?.to_owned(),
+                result,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn base64_sha256(text: &str) -> String {
+    STANDARD.encode(sha256(text.as_bytes()))
+}
+
+/// The answer to OBS's challenge: base64(sha256(base64(sha256(password + salt)) + challenge)).
+pub fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = base64_sha256(&format!("{}{}", password, salt));
+    base64_sha256(&format!("{}{}", secret, challenge))
+}
+
+pub fn identify(authentication: Option<String>) -> String {
+    let mut data = json!({
+        "rpcVersion": RPC_VERSION,
+        "eventSubscriptions": EVENT_SUBSCRIPTIONS,
+    });
+    if let Some(authentication) = authentication {
+        data["authentication"] = json!(authentication);
+    }
+    json!({ "op": 1, "d": data }).to_string()
+}
+
+pub fn request(kind: &str, id: &str, data: Value) -> String {
+    json!({
+        "op": 6,
+        "d": { "requestType": kind, "requestId": id, "requestData": data },
+    })
+    .to_string()
+}
+
+/// The names in a list of GetSceneList's or GetInputList's response.
+pub fn names(data: &Value, list: &str, name: &str) -> Vec<String> {
+    data[list]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item[name].as_str().map(str::to_owned))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the example of obs-websocket's protocol documentation
+    #[test]
+    fn challenges_are_answered_with_the_password() {
+        let hello = r#"{"op":0,"d":{"obsWebSocketVersion":"5.1.0","rpcVersion":1,"authentication":{
+            "challenge":"+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=",
+            "salt":"lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI="}}}"#;
+        let Some(Message::Hello {
+            authentication: Some((challenge, salt)),
+        }) = parse(hello)
+        else {
+            panic!("no challenge in {}", hello);
+        };
+        assert_eq!(
+            authentication("supersecretpassword", &salt, &challenge),
+            "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4="
+        );
+        assert_eq!(
+            parse(r#"{"op":0,"d":{"obsWebSocketVersion":"5.1.0","rpcVersion":1}}"#),
+            Some(Message::Hello {
+                authentication: None
+            })
+        );
+    }
+
+    #[test]
+    fn responses_carry_their_request_id() {
+        let refused = r#"{"op":7,"d":{"requestType":"SetCurrentProgramScene","requestId":"f819dcf0",
+            "requestStatus":{"result":false,"code":600,"comment":"No source was found by the name of `BRB`."}}}"#;
+        assert_eq!(
+            parse(refused),
+            Some(Message::Response {
+                id: "f819dcf0".to_owned(),
+                result: Err(ObsError::Refused {
+                    code: 600,
+                    comment: "No source was found by the name of `BRB`.".to_owned()
+                })
+            })
+        );
+        let scenes = r#"{"op":7,"d":{"requestType":"GetSceneList","requestId":"1",
+            "requestStatus":{"result":true,"code":100},
+            "responseData":{"currentProgramSceneName":"Main","scenes":[{"sceneIndex":1,"sceneName":"BRB"},{"sceneIndex":0,"sceneName":"Main"}]}}}"#;
+        let Some(Message::Response {
+            result: Ok(data), ..
+        }) = parse(scenes)
+        else {
+            panic!("not a response: {}", scenes);
+        };
+        assert_eq!(names(&data, "scenes", "sceneName"), vec!["BRB", "Main"]);
+    }
+}
@@ -0,0 +1,353 @@
+//! What the bot counted since it started, in Prometheus' text format.
+//!
+//! The names are stable, series are only ever added:
+//! - `chatbot_messages_received_total{channel}`: chat messages, commands included
+//! - `chatbot_messages_sent_total{channel}`: messages and replies the bot handed to chat
+//! - `chatbot_parse_errors_total{error}`: lines from twitch that couldn't be parsed, by the
+//!   kind of error, e.g. `malformed_tags`
+//! - `chatbot_commands_total{command}`: commands run, by their name without the prefix
+//! - `chatbot_moderation_actions_total{action}`: `delete`, `timeout` or `ban`
+//! - `chatbot_helix_requests_total{endpoint,status}`: requests to twitch's API, by the path
+//!   without the query and the status code, `error` when no answer came
+//! - `chatbot_reconnects_total`: connections to chat restored after one was lost
+//! - `chatbot_duplicates_dropped_total{key}`: chat messages twitch delivered twice, e.g. while
+//!   switching connections, by what told them apart: the `id` tag or the `fallback` of channel,
+//!   user, text and time
+//! - `chatbot_queue_depth`: lines waiting to be sent to chat
+//! - `chatbot_connected`: 1 while logged in to chat, else 0
+//! - `chatbot_command_duration_seconds`: how long commands took to run, a histogram
+//! - `chatbot_handler_duration_seconds{handler}`: how long each event handler took, a histogram
+//! - `chatbot_handler_failures_total{handler,kind}`: handlers skipped for an `error` or a `panic`
+//!
+//! Counting is an atomic increment once a series exists, only a new label takes a lock.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
+
+pub static MESSAGES_RECEIVED: Counter = Counter::new(
+    "chatbot_messages_received_total",
+    "Chat messages received, commands included.",
+    &["channel"],
+);
+pub static MESSAGES_SENT: Counter = Counter::new(
+    "chatbot_messages_sent_total",
+    "Messages and replies handed to chat.",
+    &["channel"],
+);
+pub static PARSE_ERRORS: Counter = Counter::new(
+    "chatbot_parse_errors_total",
+    "Lines from twitch that couldn't be parsed.",
+    &["error"],
+);
+pub static COMMANDS: Counter = Counter::new(
+    "chatbot_commands_total",
+    "Commands run, by name.",
+    &["command"],
+);
+pub static MODERATION_ACTIONS: Counter = Counter::new(
+    "chatbot_moderation_actions_total",
+    "Messages deleted and users timed out or banned.",
+    &["action"],
+);
+pub static HELIX_REQUESTS: Counter = Counter::new(
+    "chatbot_helix_requests_total",
+    "Requests to the helix API, by endpoint and status.",
+    &["endpoint", "status"],
+);
+pub static RECONNECTS: Counter = Counter::new(
+    "chatbot_reconnects_total",
+    "Connections to chat restored after one was lost.",
+    &[],
+);
+pub static DUPLICATES_DROPPED: Counter = Counter::new(
+    "chatbot_duplicates_dropped_total",
+    "Chat messages delivered twice and handed to the bot once.",
+    &["key"],
+);
+pub static QUEUE_DEPTH: Gauge =
+    Gauge::new("chatbot_queue_depth", "Lines waiting to be sent to chat.");
+pub static CONNECTED: Gauge = Gauge::new("chatbot_connected", "1 while logged in to chat, else 0.");
+pub static COMMAND_DURATION: Histogram = Histogram::new(
+    "chatbot_command_duration_seconds",
+    "How long commands took to run.",
+    &[],
+);
+pub static HANDLER_DURATION: Histogram = Histogram::new(
+    "chatbot_handler_duration_seconds",
+    "How long event handlers took, by handler.",
+    &["handler"],
+);
+pub static HANDLER_FAILURES: Counter = Counter::new(
+    "chatbot_handler_failures_total",
+    "Event handlers that returned an error or panicked.",
+    &["handler", "kind"],
+);
+
+/// A count for each combination of label values.
+#[derive(Debug)]
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: RwLock<BTreeMap<Vec<String>, AtomicU64>>,
+}
+
+impl Counter {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// The values are in the order of the labels.
+    pub fn inc(&self, values: &[&str]) {
+        debug_assert_eq!(values.len(), self.labels.len(), "{}", self.name);
+        if let Some(count) = self.values.read().unwrap().get(&owned(values)) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut counts = self.values.write().unwrap();
+        let count = counts.entry(owned(values)).or_default();
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, text: &mut String) {
+        header(text, self.name, self.help, "counter");
+        for (values, count) in self.values.read().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "{}{} {}",
+                self.name,
+                labels(self.labels, values),
+                count.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+// the lookup allocates, but the map keeps only the first of each
+fn owned(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+/// A value that goes up and down.
+#[derive(Debug)]
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicI64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, text: &mut String) {
+        header(text, self.name, self.help, "gauge");
+        let _ = writeln!(text, "{} {}", self.name, self.get());
+    }
+}
+
+// upper bounds in seconds, a command waiting for twitch takes the longest
+const BUCKETS: [f64; 9] = [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Default)]
+struct Series {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Series {
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let micros = duration.as_micros().try_into().unwrap_or(u64::MAX);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+}
+
+/// How many observations fell into each bucket, and their sum, for each combination of label
+/// values.
+#[derive(Debug)]
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    series: RwLock<BTreeMap<Vec<String>, Series>>,
+}
+
+impl Histogram {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            series: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// The values are in the order of the labels.
+    pub fn observe(&self, values: &[&str], duration: Duration) {
+        debug_assert_eq!(values.len(), self.labels.len(), "{}", self.name);
+        if let Some(series) = self.series.read().unwrap().get(&owned(values)) {
+            series.observe(duration);
+            return;
+        }
+        let mut series = self.series.write().unwrap();
+        series.entry(owned(values)).or_default().observe(duration);
+    }
+
+    // the buckets are cumulative in the format, `le` is the last label
+    fn render(&self, text: &mut String) {
+        header(text, self.name, self.help, "histogram");
+        for (values, series) in self.series.read().unwrap().iter() {
+            let bucket = |bound: &str| {
+                let mut names = self.labels.to_vec();
+                names.push("le");
+                let mut values = values.clone();
+                values.push(bound.to_owned());
+                labels(&names, &values)
+            };
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(&series.buckets) {
+                cumulative += count.load(Ordering::Relaxed);
+                let bound = bucket(&bound.to_string());
+                let _ = writeln!(text, "{}_bucket{} {}", self.name, bound, cumulative);
+            }
+            let count = series.count.load(Ordering::Relaxed);
+            let _ = writeln!(text, "{}_bucket{} {}", self.name, bucket("+Inf"), count);
+            let sum = series.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            let values = labels(self.labels, values);
+            let _ = writeln!(text, "{}_sum{} {}", self.name, values, sum);
+            let _ = writeln!(text, "{}_count{} {}", self.name, values, count);
+        }
+    }
+}
+
+fn header(text: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+// `{channel="carkhy"}`, nothing without labels
+fn labels(names: &[&str], values: &[String]) -> String {
+    if names.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = names
+        .iter()
+        .zip(values)
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// Every metric in the text format, for `/metrics`.
+pub fn render() -> String {
+    let mut text = String::new();
+    for counter in [
+        &MESSAGES_RECEIVED,
+        &MESSAGES_SENT,
+        &PARSE_ERRORS,
+        &COMMANDS,
+        &MODERATION_ACTIONS,
+        &HELIX_REQUESTS,
+        &RECONNECTS,
+        &DUPLICATES_DROPPED,
+        &HANDLER_FAILURES,
+    ] {
+        counter.render(&mut text);
+    }
+    QUEUE_DEPTH.render(&mut text);
+    CONNECTED.render(&mut text);
+    COMMAND_DURATION.render(&mut text);
+    HANDLER_DURATION.render(&mut text);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_are_written_in_the_text_format() {
+        let counter = Counter::new("test_total", "Counted in a test.", &["channel", "user"]);
+        counter.inc(&["carkhy", "say \"hi\""]);
+        counter.inc(&["carkhy", "say \"hi\""]);
+        counter.inc(&["captaincallback", "viewer"]);
+        let mut text = String::new();
+        counter.render(&mut text);
+        assert_eq!(
+            text,
+            "# HELP test_total Counted in a test.\n\
+             # TYPE test_total counter\n\
+             test_total{channel=\"captaincallback\",user=\"viewer\"} 1\n\
+             test_total{channel=\"carkhy\",user=\"say \\\"hi\\\"\"} 2\n"
+        );
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new("test_seconds", "Timed in a test.", &[]);
+        histogram.observe(&[], Duration::from_micros(300));
+        histogram.observe(&[], Duration::from_millis(20));
+        histogram.observe(&[], Duration::from_secs(10));
+        let mut text = String::new();
+        histogram.render(&mut text);
+        assert!(text.contains("test_seconds_bucket{le=\"0.0005\"} 1\n"));
+        assert!(text.contains("test_seconds_bucket{le=\"0.01\"} 1\n"));
+        assert!(text.contains("test_seconds_bucket{le=\"0.05\"} 2\n"));
+        assert!(text.contains("test_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(text.contains("test_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("test_seconds_sum 10.0203\n"));
+        assert!(text.contains("test_seconds_count 3\n"));
+
+        let histogram = Histogram::new("handler_seconds", "Timed by handler.", &["handler"]);
+        histogram.observe(&["hype_chat"], Duration::from_millis(2));
+        let mut text = String::new();
+        histogram.render(&mut text);
+        assert!(text.contains("handler_seconds_bucket{handler=\"hype_chat\",le=\"0.001\"} 0\n"));
+        assert!(text.contains("handler_seconds_bucket{handler=\"hype_chat\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("handler_seconds_count{handler=\"hype_chat\"} 1\n"));
+    }
+}
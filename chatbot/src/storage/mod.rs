@@ -0,0 +1,714 @@
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use crate::config::{StorageBackend, StorageConfig};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Could not read {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Invalid JSON in {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Could not write {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("Could not use the database {path:?}: {message}")]
+    Database { path: PathBuf, message: String },
+}
+
+/// Where the saved values and the logs are kept, by their names.
+pub trait Backend: Debug + Send + Sync {
+    /// The text saved under the name, None before anything was.
+    fn get(&self, name: &str) -> Result<Option<String>, StorageError>;
+    fn put(&self, name: &str, text: &str) -> Result<(), StorageError>;
+    /// Forgets what was saved under the name, if anything was.
+    fn delete(&self, name: &str) -> Result<(), StorageError>;
+    fn append(&self, log: &str, line: &str) -> Result<(), StorageError>;
+    fn has_line(&self, log: &str, line: &str) -> Result<bool, StorageError>;
+
+    /// The keys with the highest counts above 0 in the JSON object at the path of the value,
+    /// highest first and ties by key, e.g. a leaderboard. Empty without such an object.
+    fn top(
+        &self,
+        name: &str,
+        path: &[&str],
+        count: usize,
+    ) -> Result<Vec<(String, u64)>, StorageError> {
+        let Some(text) = self.get(name)? else {
+            return Ok(Vec::new());
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|source| StorageError::Parse {
+                path: self.location(name),
+                source,
+            })?;
+        let counts = path
+            .iter()
+            .try_fold(&value, |value, key| value.get(key))
+            .and_then(|value| value.as_object());
+        let mut top: Vec<_> = counts
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, count)| Some((key.clone(), count.as_u64()?)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        top.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        top.truncate(count);
+        Ok(top)
+    }
+
+    /// Where the value is kept, for the errors.
+    fn location(&self, name: &str) -> PathBuf {
+        PathBuf::from(format!("{}.json", name))
+    }
+
+    /// Writes what is still waiting to be written.
+    fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// Moves an invalid value out of the way so the name starts over, None if it can't be
+    /// kept anywhere, else where it was moved to.
+    fn set_aside(&self, _name: &str) -> Result<Option<PathBuf>, StorageError> {
+        Ok(None)
+    }
+}
+
+// the changes not written yet, by name
+#[derive(Debug, Default)]
+struct Pending {
+    texts: BTreeMap<String, String>,
+    // when the oldest of them was changed
+    since: Option<Instant>,
+}
+
+/// One pretty-printed JSON file per name and one text file per log in the directory. The
+/// changes within the delay are written together.
+#[derive(Debug)]
+struct Files {
+    directory: PathBuf,
+    delay: Duration,
+    pending: Mutex<Pending>,
+    // held from taking the changes until they are written, so two writers never share the
+    // temporary file and an older change never replaces a newer one
+    writing: Mutex<()>,
+}
+
+impl Files {
+    fn new(directory: PathBuf, delay: Duration) -> Self {
+        Self {
+            directory,
+            delay,
+            pending: Mutex::default(),
+            writing: Mutex::default(),
+        }
+    }
+
+    fn log(&self, log: &str) -> PathBuf {
+        self.directory.join(format!("{}.log", log))
+    }
+
+    fn write(&self, name: &str, text: &str) -> Result<(), StorageError> {
+        let path = self.location(name);
+        write_file(&path, text).map_err(|source| StorageError::Write { path, source })
+    }
+
+    fn put_at(&self, name: &str, text: &str, now: Instant) -> Result<(), StorageError> {
+        if self.delay.is_zero() {
+            let _writing = self.writing.lock().unwrap();
+            return self.write(name, text);
+        }
+        let mut pending = self.pending.lock().unwrap();
+        pending.texts.insert(name.to_owned(), text.to_owned());
+        pending.since.get_or_insert(now);
+        drop(pending);
+        self.flush_due(now)
+    }
+
+    /// Writes the pending changes once the oldest waited for the delay.
+    fn flush_due(&self, now: Instant) -> Result<(), StorageError> {
+        let since = self.pending.lock().unwrap().since;
+        match since {
+            Some(since) if now >= since + self.delay => self.write_pending(now),
+            _ => Ok(()),
+        }
+    }
+
+    // what can't be written is kept for the next flush, the first error is returned after
+    // the others were tried
+    fn write_pending(&self, now: Instant) -> Result<(), StorageError> {
+        let _writing = self.writing.lock().unwrap();
+        let due = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.since = None;
+            std::mem::take(&mut pending.texts)
+        };
+        let mut result = Ok(());
+        let mut failed = BTreeMap::new();
+        for (name, text) in due {
+            if let Err(error) = self.write(&name, &text) {
+                result = result.and(Err(error));
+                failed.insert(name, text);
+            }
+        }
+        if !failed.is_empty() {
+            let mut pending = self.pending.lock().unwrap();
+            // a change made meanwhile is newer than the one that failed
+            for (name, text) in failed {
+                pending.texts.entry(name).or_insert(text);
+            }
+            pending.since.get_or_insert(now);
+        }
+        result
+    }
+}
+
+impl Backend for Files {
+    fn location(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", name))
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>, StorageError> {
+        if let Some(text) = self.pending.lock().unwrap().texts.get(name) {
+            return Ok(Some(text.clone()));
+        }
+        let path = self.location(name);
+        match fs::read_to_string(&path) {
+            Ok(text) => Ok(Some(text)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(StorageError::Read { path, source }),
+        }
+    }
+
+    fn put(&self, name: &str, text: &str) -> Result<(), StorageError> {
+        self.put_at(name, text, Instant::now())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), StorageError> {
+        let _writing = self.writing.lock().unwrap();
+        self.pending.lock().unwrap().texts.remove(name);
+        let path = self.location(name);
+        match fs::remove_file(&path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(StorageError::Write {
+                path,
+                source: error,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.write_pending(Instant::now())
+    }
+
+    // e.g. quotes.invalid-1700000000.json, next to the file
+    fn set_aside(&self, name: &str) -> Result<Option<PathBuf>, StorageError> {
+        let path = self.location(name);
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let aside = self
+            .directory
+            .join(format!("{}.invalid-{}.json", name, seconds));
+        match fs::rename(&path, &aside) {
+            Ok(()) => Ok(Some(aside)),
+            Err(source) => Err(StorageError::Write { path, source }),
+        }
+    }
+
+    fn append(&self, log: &str, line: &str) -> Result<(), StorageError> {
+        let path = self.log(log);
+        append_line(&path, line).map_err(|source| StorageError::Write { path, source })
+    }
+
+    // read line by line, the logs get long
+    fn has_line(&self, log: &str, line: &str) -> Result<bool, StorageError> {
+        let path = self.log(log);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(source) => return Err(StorageError::Read { path, source }),
+        };
+        for logged in BufReader::new(file).lines() {
+            match logged {
+                Ok(logged) if logged == line => return Ok(true),
+                Ok(_) => {}
+                Err(source) => return Err(StorageError::Read { path, source }),
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Kept while the bot runs, e.g. to try it out or for tests that restart a feature.
+#[derive(Debug, Default)]
+struct Memory {
+    values: Mutex<HashMap<String, String>>,
+    logs: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl Backend for Memory {
+    fn get(&self, name: &str) -> Result<Option<String>, StorageError> {
+        Ok(self.values.lock().unwrap().get(name).cloned())
+    }
+
+    fn put(&self, name: &str, text: &str) -> Result<(), StorageError> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), text.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), StorageError> {
+        self.values.lock().unwrap().remove(name);
+        Ok(())
+    }
+
+    fn append(&self, log: &str, line: &str) -> Result<(), StorageError> {
+        let mut logs = self.logs.lock().unwrap();
+        logs.entry(log.to_owned())
+            .or_default()
+            .push(line.to_owned());
+        Ok(())
+    }
+
+    fn has_line(&self, log: &str, line: &str) -> Result<bool, StorageError> {
+        let logs = self.logs.lock().unwrap();
+        Ok(logs
+            .get(log)
+            .is_some_and(|lines| lines.iter().any(|l| l == line)))
+    }
+}
+
+/// What the bot keeps between runs, as JSON by the kind of data. Clones share the backend.
+/// Without one nothing is read or written, e.g. in replays.
+#[derive(Debug, Clone, Default)]
+pub struct Storage {
+    backend: Option<Arc<dyn Backend>>,
+}
+
+impl Storage {
+    /// In files in the directory, written right away.
+    #[cfg(test)]
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self::with_backend(Files::new(directory.into(), Duration::ZERO))
+    }
+
+    /// The configured backend. The delayed changes of the files are written in a thread of
+    /// their own, and by [Storage::flush]; the database is opened, and created or upgraded.
+    pub fn from_config(config: &StorageConfig) -> Result<Self, StorageError> {
+        match config.backend {
+            StorageBackend::Memory => Ok(Self::memory()),
+            StorageBackend::Json => {
+                let delay = Duration::from_secs(config.write_delay);
+                let files = Arc::new(Files::new(config.directory.clone(), delay));
+                if !delay.is_zero() {
+                    let delayed = files.clone();
+                    thread::spawn(move || loop {
+                        thread::sleep(delay);
+                        if let Err(error) = delayed.flush_due(Instant::now()) {
+                            tracing::warn!(%error, "could not save the changes");
+                        }
+                    });
+                }
+                Ok(Self {
+                    backend: Some(files),
+                })
+            }
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => {
+                Ok(Self::with_backend(sqlite::Sqlite::open(&config.directory)?))
+            }
+            #[cfg(not(feature = "sqlite"))]
+            StorageBackend::Sqlite => Err(StorageError::Database {
+                path: config.directory.clone(),
+                message: "the bot was built without the sqlite feature".to_owned(),
+            }),
+        }
+    }
+
+    /// Forgotten once the last clone is dropped.
+    pub fn memory() -> Self {
+        Self::with_backend(Memory::default())
+    }
+
+    pub fn with_backend(backend: impl Backend + 'static) -> Self {
+        Self {
+            backend: Some(Arc::new(backend)),
+        }
+    }
+
+    /// The default value until something was saved under the name.
+    pub fn load<T: DeserializeOwned + Default>(&self, name: &str) -> Result<T, StorageError> {
+        let Some(backend) = &self.backend else {
+            return Ok(T::default());
+        };
+        let Some(text) = backend.get(name)? else {
+            return Ok(T::default());
+        };
+        let source = match serde_json::from_str(&text) {
+            Ok(value) => return Ok(value),
+            Err(source) => source,
+        };
+        // an invalid file is kept aside instead of being overwritten with the next change
+        let path = backend.location(name);
+        match backend.set_aside(name)? {
+            Some(aside) => {
+                tracing::warn!(
+                    ?path,
+                    error = %source,
+                    ?aside,
+                    name,
+                    "invalid JSON was moved aside, it starts empty"
+                );
+                Ok(T::default())
+            }
+            None => Err(StorageError::Parse { path, source }),
+        }
+    }
+
+    /// Writes the changes still waiting for the write delay, e.g. before the bot stops.
+    pub fn flush(&self) -> Result<(), StorageError> {
+        match &self.backend {
+            Some(backend) => backend.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces what was saved under the name.
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<(), StorageError> {
+        let Some(backend) = &self.backend else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(value).expect("stored values are plain data");
+        backend.put(name, &text)
+    }
+
+    /// Forgets what was saved under the name, it loads as the default again.
+    pub fn delete(&self, name: &str) -> Result<(), StorageError> {
+        match &self.backend {
+            Some(backend) => backend.delete(name),
+            None => Ok(()),
+        }
+    }
+
+    /// The keys with the highest counts in the object at the path of what was saved under
+    /// the name, e.g. `["balances", "carkhy"]`. None when nothing is kept.
+    pub fn top(
+        &self,
+        name: &str,
+        path: &[&str],
+        count: usize,
+    ) -> Result<Option<Vec<(String, u64)>>, StorageError> {
+        match &self.backend {
+            Some(backend) => backend.top(name, path, count).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Adds a line to the log kept under the name, a text file for people to read.
+    pub fn append(&self, name: &str, line: &str) -> Result<(), StorageError> {
+        match &self.backend {
+            Some(backend) => backend.append(name, line),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the log kept under the name has the line.
+    pub fn has_line(&self, name: &str, line: &str) -> Result<bool, StorageError> {
+        match &self.backend {
+            Some(backend) => backend.has_line(name, line),
+            None => Ok(false),
+        }
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+// written next to the file and synced first, so a crash never leaves half of it behind
+fn write_file(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let partial = path.with_extension("json.partial");
+    let mut file = fs::File::create(&partial)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&partial, path)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::{
+        fs,
+        ops::Deref,
+        path::{Path, PathBuf},
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// A directory of its own in the temp directory, removed when dropped, also after a
+    /// failed assertion. It is created by what is written to it.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            static CREATED: AtomicUsize = AtomicUsize::new(0);
+            let number = CREATED.fetch_add(1, Ordering::Relaxed);
+            Self(std::env::temp_dir().join(format!("{}-{}-{}", name, process::id(), number)))
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl From<&TempDir> for PathBuf {
+        fn from(directory: &TempDir) -> Self {
+            directory.0.clone()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_values_are_loaded_again() {
+        let directory = testing::TempDir::new("chatbot-storage");
+        let storage = Storage::new(&directory);
+        let empty: BTreeMap<String, u32> = storage.load("counts").unwrap();
+        assert!(empty.is_empty());
+
+        let counts = BTreeMap::from([("deaths".to_owned(), 12)]);
+        storage.save("counts", &counts).unwrap();
+        assert_eq!(
+            storage.load::<BTreeMap<String, u32>>("counts").unwrap(),
+            counts
+        );
+
+        // a file edited by hand into invalid JSON is kept aside, not overwritten
+        fs::write(directory.join("counts.json"), "{").unwrap();
+        assert!(storage
+            .load::<BTreeMap<String, u32>>("counts")
+            .unwrap()
+            .is_empty());
+        assert!(!directory.join("counts.json").exists());
+        let aside: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("counts.invalid-"))
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(fs::read_to_string(directory.join(&aside[0])).unwrap(), "{");
+    }
+
+    #[test]
+    fn a_crash_before_the_rename_keeps_the_old_value() {
+        let directory = testing::TempDir::new("chatbot-crash");
+        let storage = Storage::new(&directory);
+        storage.save("counts", &vec![1]).unwrap();
+        // the bot died after writing the temporary file
+        fs::write(directory.join("counts.json.partial"), "[1, 2").unwrap();
+        assert_eq!(storage.load::<Vec<u32>>("counts").unwrap(), [1]);
+        storage.save("counts", &vec![1, 2]).unwrap();
+        assert_eq!(
+            Storage::new(&directory).load::<Vec<u32>>("counts").unwrap(),
+            [1, 2]
+        );
+        assert!(!directory.join("counts.json.partial").exists());
+    }
+
+    #[test]
+    fn changes_within_the_delay_are_written_together() {
+        let directory = testing::TempDir::new("chatbot-delay");
+        let files = Files::new(directory.to_path_buf(), Duration::from_secs(2));
+        let start = Instant::now();
+        files.put_at("counts", "[1]", start).unwrap();
+        files
+            .put_at("quotes", "[]", start + Duration::from_secs(1))
+            .unwrap();
+        files
+            .put_at("counts", "[2]", start + Duration::from_secs(1))
+            .unwrap();
+        assert!(!directory.exists());
+        // the pending value is read before it was written
+        assert_eq!(files.get("counts").unwrap().as_deref(), Some("[2]"));
+
+        files.flush_due(start + Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("counts.json")).unwrap(),
+            "[2]"
+        );
+        assert!(directory.join("quotes.json").exists());
+        files
+            .put_at("quotes", "[3]", start + Duration::from_secs(5))
+            .unwrap();
+        files.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("quotes.json")).unwrap(),
+            "[3]"
+        );
+    }
+
+    #[test]
+    fn changes_flushed_by_several_threads_are_all_written() {
+        let directory = testing::TempDir::new("chatbot-threads");
+        let files = Arc::new(Files::new(directory.to_path_buf(), Duration::from_secs(2)));
+        // like the background flush, a stop and the delay running out at once
+        let threads: Vec<_> = (0..4)
+            .map(|thread| {
+                let files = files.clone();
+                thread::spawn(move || {
+                    for count in 0..50 {
+                        let text = format!("[{}, {}]", thread, count);
+                        files.put("counts", &text).unwrap();
+                        files.flush().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let written = fs::read_to_string(directory.join("counts.json")).unwrap();
+        assert!(written.ends_with(", 49]"), "{}", written);
+        assert!(!directory.join("counts.json.partial").exists());
+    }
+
+    #[test]
+    fn a_failed_write_is_tried_again_without_holding_up_the_others() {
+        let directory = testing::TempDir::new("chatbot-failed");
+        let files = Files::new(directory.to_path_buf(), Duration::from_secs(2));
+        // a directory where the file belongs can't be replaced
+        fs::create_dir_all(directory.join("counts.json")).unwrap();
+        let start = Instant::now();
+        files.put_at("counts", "[1]", start).unwrap();
+        files.put_at("quotes", "[2]", start).unwrap();
+        let later = start + Duration::from_secs(2);
+        assert!(matches!(
+            files.flush_due(later),
+            Err(StorageError::Write { .. })
+        ));
+        assert_eq!(
+            fs::read_to_string(directory.join("quotes.json")).unwrap(),
+            "[2]"
+        );
+        assert_eq!(files.get("counts").unwrap().as_deref(), Some("[1]"));
+
+        fs::remove_dir(directory.join("counts.json")).unwrap();
+        // waiting for the delay again
+        files.flush_due(later + Duration::from_secs(1)).unwrap();
+        assert!(!directory.join("counts.json").exists());
+        files.flush_due(later + Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("counts.json")).unwrap(),
+            "[1]"
+        );
+    }
+
+    #[test]
+    fn lines_are_appended_to_logs() {
+        let directory = testing::TempDir::new("chatbot-logs");
+        let storage = Storage::new(&directory);
+        storage.append("markers", "first").unwrap();
+        storage.append("markers", "second").unwrap();
+        assert_eq!(
+            fs::read_to_string(directory.join("markers.log")).unwrap(),
+            "first\nsecond\n"
+        );
+        assert!(storage.has_line("markers", "second").unwrap());
+        assert!(!storage.has_line("markers", "sec").unwrap());
+        assert!(!storage.has_line("quotes", "first").unwrap());
+    }
+
+    #[test]
+    fn clones_share_the_memory() {
+        let storage = Storage::memory();
+        storage.clone().save("counts", &vec![1, 2]).unwrap();
+        storage.append("markers", "kept").unwrap();
+        assert_eq!(storage.load::<Vec<u32>>("counts").unwrap(), [1, 2]);
+        assert!(storage.clone().has_line("markers", "kept").unwrap());
+        assert!(Storage::memory()
+            .load::<Vec<u32>>("counts")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn deleted_values_are_gone_and_counts_are_ranked() {
+        let directory = testing::TempDir::new("chatbot-delete");
+        let files = Files::new(directory.to_path_buf(), Duration::from_secs(2));
+        for storage in [Storage::memory(), Storage::with_backend(files)] {
+            let points = BTreeMap::from([(
+                "balances",
+                BTreeMap::from([("carkhy", BTreeMap::from([("a", 1), ("b", 3), ("c", 0)]))]),
+            )]);
+            storage.save("points", &points).unwrap();
+            storage.flush().unwrap();
+            let top = |path: &[&str]| storage.top("points", path, 5).unwrap().unwrap();
+            assert_eq!(
+                top(&["balances", "carkhy"]),
+                [("b".to_owned(), 3), ("a".to_owned(), 1)]
+            );
+            assert!(top(&["balances", "nowhere"]).is_empty());
+            storage.delete("points").unwrap();
+            storage.delete("never").unwrap();
+            assert!(top(&["balances", "carkhy"]).is_empty());
+            assert!(storage
+                .load::<BTreeMap<String, u32>>("points")
+                .unwrap()
+                .is_empty());
+        }
+        assert!(!directory.join("points.json").exists());
+        assert_eq!(Storage::default().top("points", &[], 5).unwrap(), None);
+    }
+
+    #[test]
+    fn nothing_is_kept_without_a_directory() {
+        let storage = Storage::default();
+        storage.save("counts", &vec![1, 2]).unwrap();
+        storage.append("markers", "nowhere").unwrap();
+        assert!(storage.load::<Vec<u32>>("counts").unwrap().is_empty());
+        assert!(!storage.has_line("markers", "nowhere").unwrap());
+    }
+}
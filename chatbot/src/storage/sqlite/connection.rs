@@ -0,0 +1,288 @@
+//! The few functions of the system's SQLite library the backend needs, behind a safe
+//! connection. Statements are finalized and the connection is closed when dropped.
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    marker::PhantomData,
+    path::Path,
+    ptr,
+};
+
+#[allow(non_camel_case_types)]
+enum sqlite3 {}
+#[allow(non_camel_case_types)]
+enum sqlite3_stmt {}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+const SQLITE_OPEN_FULLMUTEX: c_int = 0x10000;
+// SQLite copies the bound text before the call returns
+const SQLITE_TRANSIENT: isize = -1;
+// how long a statement waits for another process holding the database
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char;
+    fn sqlite3_busy_timeout(db: *mut sqlite3, ms: c_int) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut sqlite3,
+        sql: *const c_char,
+        bytes: c_int,
+        stmt: *mut *mut sqlite3_stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut sqlite3_stmt,
+        index: c_int,
+        text: *const c_char,
+        bytes: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut sqlite3_stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_step(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_column_text(stmt: *mut sqlite3_stmt, column: c_int) -> *const u8;
+    fn sqlite3_column_bytes(stmt: *mut sqlite3_stmt, column: c_int) -> c_int;
+    fn sqlite3_column_int64(stmt: *mut sqlite3_stmt, column: c_int) -> i64;
+    fn sqlite3_finalize(stmt: *mut sqlite3_stmt) -> c_int;
+}
+
+/// A value bound to a `?` of a statement, by position.
+#[derive(Debug, Clone, Copy)]
+pub enum Param<'a> {
+    Text(&'a str),
+    Integer(i64),
+}
+
+/// A row of a query, its columns counted from 0.
+pub struct Row<'s> {
+    stmt: *mut sqlite3_stmt,
+    _statement: PhantomData<&'s Statement<'s>>,
+}
+
+impl Row<'_> {
+    /// Empty for NULL.
+    pub fn text(&self, column: usize) -> String {
+        // SAFETY: the statement is on a row while the row exists, the text is valid until the
+        // next step and has the length SQLite tells after converting it
+        unsafe {
+            let text = sqlite3_column_text(self.stmt, column as c_int);
+            if text.is_null() {
+                return String::new();
+            }
+            let bytes = sqlite3_column_bytes(self.stmt, column as c_int) as usize;
+            String::from_utf8_lossy(std::slice::from_raw_parts(text, bytes)).into_owned()
+        }
+    }
+
+    /// 0 for NULL.
+    pub fn integer(&self, column: usize) -> i64 {
+        // SAFETY: the statement is on a row while the row exists
+        unsafe { sqlite3_column_int64(self.stmt, column as c_int) }
+    }
+}
+
+struct Statement<'c> {
+    connection: &'c Connection,
+    stmt: *mut sqlite3_stmt,
+}
+
+impl Statement<'_> {
+    // one step, true while there are rows
+    fn step(&mut self) -> Result<bool, String> {
+        // SAFETY: the statement was prepared and is finalized only when dropped
+        match unsafe { sqlite3_step(self.stmt) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(self.connection.error()),
+        }
+    }
+
+    fn row(&self) -> Row<'_> {
+        Row {
+            stmt: self.stmt,
+            _statement: PhantomData,
+        }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: prepared by the connection, which outlives the statement
+        unsafe { sqlite3_finalize(self.stmt) };
+    }
+}
+
+/// An open database file.
+pub struct Connection {
+    db: *mut sqlite3,
+}
+
+// SAFETY: the library is built thread safe and the connection is opened with its full mutex,
+// it is only used by one thread at a time anyway
+unsafe impl Send for Connection {}
+
+impl Connection {
+    /// Creates the file if there is none.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let name = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| "the path has a NUL character".to_owned())?;
+        let mut db = ptr::null_mut();
+        let flags = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX;
+        // SAFETY: the name is NUL terminated, a handle is returned even when opening fails,
+        // and it is closed by the connection either way
+        let opened = unsafe { sqlite3_open_v2(name.as_ptr(), &mut db, flags, ptr::null()) };
+        let connection = Self { db };
+        if opened != SQLITE_OK {
+            return Err(connection.error());
+        }
+        // SAFETY: the handle is open
+        unsafe { sqlite3_busy_timeout(connection.db, BUSY_TIMEOUT_MS) };
+        Ok(connection)
+    }
+
+    fn error(&self) -> String {
+        if self.db.is_null() {
+            return "out of memory".to_owned();
+        }
+        // SAFETY: the message is NUL terminated and valid until the next call on the handle
+        unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // one statement, the rest of the SQL is its tail
+    fn prepare<'c, 's>(
+        &'c self,
+        sql: &'s str,
+        params: &[Param],
+    ) -> Result<(Statement<'c>, &'s str), String> {
+        let mut stmt = ptr::null_mut();
+        let mut tail = ptr::null();
+        // SAFETY: the length is given, so the SQL needs no NUL, and the tail points into it
+        let prepared = unsafe {
+            sqlite3_prepare_v2(
+                self.db,
+                sql.as_ptr().cast(),
+                sql.len() as c_int,
+                &mut stmt,
+                &mut tail,
+            )
+        };
+        if prepared != SQLITE_OK {
+            return Err(self.error());
+        }
+        let rest = match tail.is_null() {
+            true => "",
+            false => &sql[tail as usize - sql.as_ptr() as usize..],
+        };
+        let statement = Statement {
+            connection: self,
+            stmt,
+        };
+        for (index, param) in params.iter().enumerate() {
+            let index = index as c_int + 1;
+            // SAFETY: the statement was prepared, the text is copied before the call returns
+            let bound = unsafe {
+                match param {
+                    Param::Text(text) => sqlite3_bind_text(
+                        statement.stmt,
+                        index,
+                        text.as_ptr().cast(),
+                        text.len() as c_int,
+                        SQLITE_TRANSIENT,
+                    ),
+                    Param::Integer(value) => sqlite3_bind_int64(statement.stmt, index, *value),
+                }
+            };
+            if bound != SQLITE_OK {
+                return Err(self.error());
+            }
+        }
+        Ok((statement, rest))
+    }
+
+    /// Runs the statements separated by semicolons, without parameters.
+    pub fn execute_batch(&self, mut sql: &str) -> Result<(), String> {
+        while !sql.trim().is_empty() {
+            let (mut statement, rest) = self.prepare(sql, &[])?;
+            // an empty statement, e.g. only a comment, isn't prepared
+            if !statement.stmt.is_null() {
+                while statement.step()? {}
+            }
+            sql = rest;
+        }
+        Ok(())
+    }
+
+    /// Runs one statement with the parameters.
+    pub fn execute(&self, sql: &str, params: &[Param]) -> Result<(), String> {
+        let (mut statement, _) = self.prepare(sql, params)?;
+        while statement.step()? {}
+        Ok(())
+    }
+
+    /// The rows of one statement, each as the function takes it.
+    pub fn query<T>(
+        &self,
+        sql: &str,
+        params: &[Param],
+        mut row: impl FnMut(&Row) -> T,
+    ) -> Result<Vec<T>, String> {
+        let (mut statement, _) = self.prepare(sql, params)?;
+        let mut rows = Vec::new();
+        while statement.step()? {
+            rows.push(row(&statement.row()));
+        }
+        Ok(rows)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: the statements borrowed the connection, so they were finalized before
+        unsafe { sqlite3_close(self.db) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_take_parameters_and_give_rows() {
+        let connection = Connection::open(Path::new(":memory:")).unwrap();
+        connection
+            .execute_batch("CREATE TABLE t (a TEXT, b INTEGER); -- two columns\n")
+            .unwrap();
+        for (a, b) in [("one", 1), ("zwei 🎉", 2)] {
+            connection
+                .execute(
+                    "INSERT INTO t VALUES (?1, ?2)",
+                    &[Param::Text(a), Param::Integer(b)],
+                )
+                .unwrap();
+        }
+        let rows = connection
+            .query(
+                "SELECT a, b FROM t WHERE b >= ?1 ORDER BY b",
+                &[Param::Integer(1)],
+                |row| (row.text(0), row.integer(1)),
+            )
+            .unwrap();
+        assert_eq!(rows, [("one".to_owned(), 1), ("zwei 🎉".to_owned(), 2)]);
+        assert!(connection
+            .execute("INSERT INTO missing VALUES (?1)", &[Param::Integer(1)])
+            .unwrap_err()
+            .contains("no such table: missing"));
+    }
+}
@@ -0,0 +1,339 @@
+mod connection;
+
+use super::{Backend, StorageError};
+use connection::{Connection, Param};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The database in the storage directory.
+pub const DATABASE_FILE: &str = "chatbot.sqlite3";
+
+// each brings the schema from the version before it to its own. A database is upgraded by
+// those after its `user_version` when it is opened
+const MIGRATIONS: [&str; 2] = [
+    // 1: the values by name and the lines of the logs
+    "CREATE TABLE saved (name TEXT PRIMARY KEY, text TEXT NOT NULL);
+     CREATE TABLE lines (log TEXT NOT NULL, line TEXT NOT NULL);",
+    // 2: when each value was last changed, and the lines by log for `has_line`
+    "ALTER TABLE saved ADD COLUMN changed INTEGER NOT NULL DEFAULT 0;
+     CREATE INDEX lines_by_log ON lines (log, line);",
+];
+
+// run by the database thread in order, with the first write that failed since the last flush
+type Job = Box<dyn FnOnce(&Connection, &mut Option<String>) + Send>;
+
+/// One SQLite database for the values and the logs. Its thread does the work in order, so
+/// the bot never waits for a write, and a read sees the writes before it.
+#[derive(Debug)]
+pub struct Sqlite {
+    path: PathBuf,
+    jobs: Sender<Job>,
+}
+
+fn unix_seconds() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+// the JSON path of the keys, e.g. $."balances"."carkhy"
+fn json_path(path: &[&str]) -> String {
+    path.iter().fold("$".to_owned(), |json_path, key| {
+        format!("{}.\"{}\"", json_path, key)
+    })
+}
+
+fn migrate(connection: &Connection) -> Result<(), String> {
+    let version = connection.query("PRAGMA user_version", &[], |row| row.integer(0))?[0];
+    let known = MIGRATIONS.len() as i64;
+    if version > known {
+        return Err(format!(
+            "schema version {} is newer than this bot's {}",
+            version, known
+        ));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let upgrade = format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            index + 1
+        );
+        if let Err(error) = connection.execute_batch(&upgrade) {
+            let _ = connection.execute_batch("ROLLBACK;");
+            return Err(format!("upgrading to version {}: {}", index + 1, error));
+        }
+    }
+    Ok(())
+}
+
+impl Sqlite {
+    /// Opens the database in the directory, creating or upgrading it.
+    pub fn open(directory: &Path) -> Result<Self, StorageError> {
+        let path = directory.join(DATABASE_FILE);
+        let database = |message: String| StorageError::Database {
+            path: path.clone(),
+            message,
+        };
+        fs::create_dir_all(directory).map_err(|error| database(error.to_string()))?;
+        let connection = Connection::open(&path).map_err(database)?;
+        connection
+            .execute_batch("PRAGMA journal_mode = WAL;")
+            .and_then(|()| migrate(&connection))
+            .map_err(database)?;
+        let (jobs, queued) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut failed = None;
+            for job in queued {
+                job(&connection, &mut failed);
+            }
+        });
+        Ok(Self { path, jobs })
+    }
+
+    fn error(&self, message: String) -> StorageError {
+        StorageError::Database {
+            path: self.path.clone(),
+            message,
+        }
+    }
+
+    // queued, a failure is logged and returned by the next flush
+    fn write(&self, sql: &'static str, params: Vec<String>, changed: Option<i64>) {
+        let job: Job = Box::new(move |connection, failed| {
+            let mut bound: Vec<Param> = params.iter().map(|param| Param::Text(param)).collect();
+            bound.extend(changed.map(Param::Integer));
+            if let Err(error) = connection.execute(sql, &bound) {
+                tracing::warn!(%error, "could not write to the database");
+                failed.get_or_insert(error);
+            }
+        });
+        // the thread only ends with the backend
+        let _ = self.jobs.send(job);
+    }
+
+    // waits for the writes queued before
+    fn read<T: Send + 'static>(
+        &self,
+        read: impl FnOnce(&Connection, &mut Option<String>) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, StorageError> {
+        let (answer, answered) = mpsc::channel();
+        let job: Job = Box::new(move |connection, failed| {
+            let _ = answer.send(read(connection, failed));
+        });
+        let stopped = || self.error("the database thread stopped".to_owned());
+        self.jobs.send(job).map_err(|_| stopped())?;
+        answered
+            .recv()
+            .map_err(|_| stopped())?
+            .map_err(|message| self.error(message))
+    }
+}
+
+impl Backend for Sqlite {
+    // e.g. data/chatbot.sqlite3#quotes
+    fn location(&self, name: &str) -> PathBuf {
+        PathBuf::from(format!("{}#{}", self.path.display(), name))
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>, StorageError> {
+        let name = name.to_owned();
+        self.read(move |connection, _| {
+            let texts = connection.query(
+                "SELECT text FROM saved WHERE name = ?1",
+                &[Param::Text(&name)],
+                |row| row.text(0),
+            )?;
+            Ok(texts.into_iter().next())
+        })
+    }
+
+    fn put(&self, name: &str, text: &str) -> Result<(), StorageError> {
+        self.write(
+            "INSERT INTO saved (name, text, changed) VALUES (?1, ?2, ?3)
+             ON CONFLICT (name) DO UPDATE SET text = excluded.text, changed = excluded.changed",
+            vec![name.to_owned(), text.to_owned()],
+            Some(unix_seconds()),
+        );
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), StorageError> {
+        self.write(
+            "DELETE FROM saved WHERE name = ?1",
+            vec![name.to_owned()],
+            None,
+        );
+        Ok(())
+    }
+
+    fn append(&self, log: &str, line: &str) -> Result<(), StorageError> {
+        self.write(
+            "INSERT INTO lines (log, line) VALUES (?1, ?2)",
+            vec![log.to_owned(), line.to_owned()],
+            None,
+        );
+        Ok(())
+    }
+
+    fn has_line(&self, log: &str, line: &str) -> Result<bool, StorageError> {
+        let (log, line) = (log.to_owned(), line.to_owned());
+        self.read(move |connection, _| {
+            let found = connection.query(
+                "SELECT 1 FROM lines WHERE log = ?1 AND line = ?2 LIMIT 1",
+                &[Param::Text(&log), Param::Text(&line)],
+                |_| (),
+            )?;
+            Ok(!found.is_empty())
+        })
+    }
+
+    // counted by SQLite in the saved JSON
+    fn top(
+        &self,
+        name: &str,
+        path: &[&str],
+        count: usize,
+    ) -> Result<Vec<(String, u64)>, StorageError> {
+        let (name, path) = (name.to_owned(), json_path(path));
+        let count = i64::try_from(count).unwrap_or(i64::MAX);
+        self.read(move |connection, _| {
+            connection.query(
+                "SELECT entry.key, entry.value FROM saved, json_each(saved.text, ?2) AS entry
+                 WHERE saved.name = ?1 AND entry.type = 'integer' AND entry.value > 0
+                 ORDER BY entry.value DESC, entry.key LIMIT ?3",
+                &[
+                    Param::Text(&name),
+                    Param::Text(&path),
+                    Param::Integer(count),
+                ],
+                |row| (row.text(0), row.integer(1) as u64),
+            )
+        })
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.read(|_, failed| match failed.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        })
+    }
+
+    // e.g. quotes.invalid-1700000000, next to it in the table
+    fn set_aside(&self, name: &str) -> Result<Option<PathBuf>, StorageError> {
+        let aside = format!("{}.invalid-{}", name, unix_seconds());
+        let (from, to) = (name.to_owned(), aside.clone());
+        self.read(move |connection, _| {
+            connection.execute(
+                "UPDATE saved SET name = ?2 WHERE name = ?1",
+                &[Param::Text(&from), Param::Text(&to)],
+            )
+        })?;
+        Ok(Some(self.location(&aside)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{testing::TempDir, Storage};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn values_and_logs_are_kept() {
+        let directory = TempDir::new("chatbot-sqlite");
+        let storage = Storage::with_backend(Sqlite::open(&directory).unwrap());
+        let balances = BTreeMap::from([(
+            "carkhy",
+            BTreeMap::from([("viewer", 5), ("lurker", 0), ("friend", 50), ("other", 5)]),
+        )]);
+        storage.save("points", &balances).unwrap();
+        storage.append("markers", "first").unwrap();
+        assert!(storage.has_line("markers", "first").unwrap());
+        assert!(!storage.has_line("markers", "fir").unwrap());
+        assert_eq!(
+            storage.top("points", &["carkhy"], 5).unwrap().unwrap(),
+            [
+                ("friend".to_owned(), 50),
+                ("other".to_owned(), 5),
+                ("viewer".to_owned(), 5)
+            ]
+        );
+        assert!(storage
+            .top("points", &["nowhere"], 5)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        storage.save("quotes", &vec!["so it begins"]).unwrap();
+        storage.delete("quotes").unwrap();
+        storage.flush().unwrap();
+        drop(storage);
+
+        // opened again like after a restart
+        let storage = Storage::with_backend(Sqlite::open(&directory).unwrap());
+        assert_eq!(
+            storage
+                .load::<BTreeMap<String, BTreeMap<String, u64>>>("points")
+                .unwrap()["carkhy"]["friend"],
+            50
+        );
+        assert!(storage.load::<Vec<String>>("quotes").unwrap().is_empty());
+        assert!(storage.has_line("markers", "first").unwrap());
+    }
+
+    #[test]
+    fn a_version_1_database_is_upgraded() {
+        let directory = TempDir::new("chatbot-sqlite-v1");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(DATABASE_FILE);
+        let old = Connection::open(&path).unwrap();
+        old.execute_batch(MIGRATIONS[0]).unwrap();
+        old.execute_batch("PRAGMA user_version = 1;").unwrap();
+        old.execute(
+            "INSERT INTO saved (name, text) VALUES (?1, ?2)",
+            &[Param::Text("quotes"), Param::Text("[\"kept\"]")],
+        )
+        .unwrap();
+        old.execute(
+            "INSERT INTO lines (log, line) VALUES (?1, ?2)",
+            &[Param::Text("greeted_users"), Param::Text("carkhy viewer")],
+        )
+        .unwrap();
+        drop(old);
+
+        let sqlite = Sqlite::open(&directory).unwrap();
+        assert_eq!(sqlite.get("quotes").unwrap().as_deref(), Some("[\"kept\"]"));
+        assert!(sqlite.has_line("greeted_users", "carkhy viewer").unwrap());
+        sqlite.put("quotes", "[]").unwrap();
+        sqlite.flush().unwrap();
+        drop(sqlite);
+        let upgraded = Connection::open(&path).unwrap();
+        let version = upgraded.query("PRAGMA user_version", &[], |row| row.integer(0));
+        assert_eq!(version.unwrap(), [MIGRATIONS.len() as i64]);
+        let changed = upgraded
+            .query(
+                "SELECT changed FROM saved WHERE name = 'quotes'",
+                &[],
+                |row| row.integer(0),
+            )
+            .unwrap();
+        assert!(changed[0] > 0);
+        let indexes = upgraded
+            .query("PRAGMA index_list(lines)", &[], |row| row.text(1))
+            .unwrap();
+        assert_eq!(indexes, ["lines_by_log"]);
+
+        // a database of a newer bot is left alone
+        upgraded.execute_batch("PRAGMA user_version = 99;").unwrap();
+        drop(upgraded);
+        assert!(matches!(
+            Sqlite::open(&directory),
+            Err(StorageError::Database { message, .. }) if message.contains("newer")
+        ));
+    }
+}
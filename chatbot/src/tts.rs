@@ -0,0 +1,237 @@
+//! Text to speech entries written to a local program as JSON lines, like
+//! `{"speaker":"Carkhy","text":"hello chat","voice":null}`, from a thread of its own. A slow or
+//! missing program never holds up chat: the entries wait in a queue of `tts.queue`, and when
+//! it is full the oldest one is dropped for the new one.
+use crate::{
+    config::TtsConfig,
+    connect::{random_jitter, Backoff},
+};
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    net::TcpStream,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+// the program may start after the bot, so it is looked for again soon
+const RETRY_FIRST: Duration = Duration::from_millis(250);
+const RETRY_MAX: Duration = Duration::from_secs(10);
+
+/// Where the entries go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sink {
+    Unix(PathBuf),
+    Tcp(String),
+    // a FIFO, or `\\.\pipe\name` on Windows
+    Pipe(PathBuf),
+}
+
+impl Sink {
+    /// `unix:<path>`, `tcp:<host>:<port>` or `pipe:<path>`.
+    pub fn parse(sink: &str) -> Option<Self> {
+        let (kind, address) = sink.split_once(':')?;
+        match kind {
+            "unix" => Some(Sink::Unix(PathBuf::from(address))),
+            "tcp" => Some(Sink::Tcp(address.to_owned())),
+            "pipe" => Some(Sink::Pipe(PathBuf::from(address))),
+            _ => None,
+        }
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        match self {
+            #[cfg(unix)]
+            Sink::Unix(path) => Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?)),
+            #[cfg(not(unix))]
+            Sink::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix sockets need a Unix system",
+            )),
+            Sink::Tcp(address) => Ok(Box::new(TcpStream::connect(address)?)),
+            // opening a FIFO waits for the program to open it for reading
+            Sink::Pipe(path) => Ok(Box::new(OpenOptions::new().write(true).open(path)?)),
+        }
+    }
+}
+
+impl fmt::Display for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sink::Unix(path) => write!(f, "unix:{}", path.display()),
+            Sink::Tcp(address) => write!(f, "tcp:{}", address),
+            Sink::Pipe(path) => write!(f, "pipe:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    lines: VecDeque<String>,
+    // since the program last connected
+    dropped: usize,
+}
+
+struct Queue {
+    entries: Mutex<Entries>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl Queue {
+    fn push(&self, line: String) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.lines.len() >= self.capacity {
+            entries.lines.pop_front();
+            entries.dropped += 1;
+        }
+        entries.lines.push_back(line);
+        self.ready.notify_one();
+    }
+
+    fn next(&self) -> String {
+        let mut entries = self.entries.lock().unwrap();
+        loop {
+            if let Some(line) = entries.lines.pop_front() {
+                return line;
+            }
+            entries = self.ready.wait(entries).unwrap();
+        }
+    }
+
+    // the entry the program went away during goes first next time, unless newer ones filled
+    // the queue meanwhile
+    fn put_back(&self, line: String) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.lines.len() < self.capacity {
+            entries.lines.push_front(line);
+        }
+    }
+
+    fn take_dropped(&self) -> usize {
+        std::mem::take(&mut self.entries.lock().unwrap().dropped)
+    }
+}
+
+/// The queue of the writing thread.
+pub struct Tts(Arc<Queue>);
+
+impl Tts {
+    /// Writes from a thread of its own, None without `tts.sink`.
+    pub fn start(config: &TtsConfig) -> Option<Self> {
+        let sink = Sink::parse(config.sink.as_deref()?)?;
+        let queue = Arc::new(Queue {
+            entries: Mutex::default(),
+            ready: Condvar::new(),
+            capacity: config.queue.max(1),
+        });
+        let entries = queue.clone();
+        thread::spawn(move || write(&sink, &entries));
+        Some(Self(queue))
+    }
+
+    /// Queues the entry, the voice is a hint for the program.
+    pub fn speak(&self, speaker: &str, text: &str, voice: Option<&str>) {
+        let entry = json!({ "speaker": speaker, "text": text, "voice": voice });
+        self.0.push(format!("{}\n", entry));
+    }
+}
+
+fn write(sink: &Sink, queue: &Queue) {
+    let backoff = Backoff::new(RETRY_FIRST, RETRY_MAX);
+    let mut attempt = 0;
+    loop {
+        let mut writer = match sink.open() {
+            Ok(writer) => writer,
+            Err(error) => {
+                attempt += 1;
+                if attempt == 1 {
+                    tracing::warn!(%sink, %error, "text to speech waits for its sink");
+                }
+                thread::sleep(backoff.delay(attempt, random_jitter()));
+                continue;
+            }
+        };
+        attempt = 0;
+        tracing::info!(%sink, dropped = queue.take_dropped(), "writing text to speech");
+        loop {
+            let line = queue.next();
+            if let Err(error) = writer
+                .write_all(line.as_bytes())
+                .and_then(|()| writer.flush())
+            {
+                tracing::warn!(%sink, %error, "text to speech stopped");
+                queue.put_back(line);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::{
+        env, fs,
+        io::{BufRead, BufReader},
+        os::unix::net::UnixListener,
+        path::Path,
+        process,
+    };
+
+    fn config(path: &Path, queue: usize) -> TtsConfig {
+        TtsConfig {
+            sink: Some(format!("unix:{}", path.display())),
+            queue,
+            ..Default::default()
+        }
+    }
+
+    fn socket(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("chatbot-tts-{}-{}.sock", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn texts(listener: &UnixListener, count: usize) -> Vec<String> {
+        let (stream, _) = listener.accept().unwrap();
+        BufReader::new(stream)
+            .lines()
+            .take(count)
+            .map(|line| {
+                let entry: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                entry["text"].as_str().unwrap().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn entries_arrive_in_order() {
+        let path = socket("order");
+        let listener = UnixListener::bind(&path).unwrap();
+        let tts = Tts::start(&config(&path, 20)).unwrap();
+        for text in ["one", "two", "three"] {
+            tts.speak("Carkhy", text, Some("robot"));
+        }
+        assert_eq!(texts(&listener, 3), vec!["one", "two", "three"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_missing_program_loses_the_oldest_entries() {
+        let path = socket("drop");
+        let tts = Tts::start(&config(&path, 3)).unwrap();
+        for text in ["one", "two", "three", "four", "five"] {
+            tts.speak("Carkhy", text, None);
+        }
+        let listener = UnixListener::bind(&path).unwrap();
+        assert_eq!(texts(&listener, 3), vec!["three", "four", "five"]);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,374 @@
+//! Chat events POSTed as JSON to the URLs of `[webhooks]`, from a task of their own so that
+//! chat never waits for them.
+//!
+//! The body is `{"event", "channel", "sent_at", "data"}`: the kind of event like `"raid"`,
+//! the channel without '#', milliseconds since the unix epoch and the event as the `serde`
+//! feature serializes it. `X-Chatbot-Signature: sha256={hex}` is the HMAC-SHA256 of the body
+//! with `webhooks.secret`. Server errors and timeouts are tried again, what fails for good is
+//! written to `webhooks.log` in the storage.
+
+use crate::{
+    config::WebhooksConfig,
+    connect::{ChatBotEvent, UserNoticeKind},
+    sha256::{hex, hmac_sha256},
+    storage::Storage,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+const DEAD_LETTERS: &str = "webhooks";
+// the deliveries waiting for the task, more are written to the dead letters right away
+const QUEUE: usize = 1000;
+// the wait before the first retry, doubled for each one after it
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// What a webhook is sent for, and the channel it happened in.
+fn kind(event: &ChatBotEvent) -> Option<(&'static str, &str)> {
+    match event {
+        ChatBotEvent::Follow { channel, .. } => Some(("follow", channel)),
+        ChatBotEvent::UserNotice(notice) => match notice.kind {
+            UserNoticeKind::Sub { .. }
+            | UserNoticeKind::Resub { .. }
+            | UserNoticeKind::SubGift { .. }
+            | UserNoticeKind::SubMysteryGift { .. } => Some(("sub", &notice.channel)),
+            UserNoticeKind::Raid { .. } => Some(("raid", &notice.channel)),
+            UserNoticeKind::Unknown(_) => None,
+        },
+        ChatBotEvent::TextMessage(message) if message.first_msg => {
+            Some(("first_chat", &message.channel))
+        }
+        ChatBotEvent::Command(command) => Some(("command", &command.message.channel)),
+        ChatBotEvent::ClearChat(clear) => Some(("moderation", &clear.channel)),
+        ChatBotEvent::ClearMessage(clear) => Some(("moderation", &clear.channel)),
+        _ => None,
+    }
+}
+
+/// The body for an event, sent once the bot handled it.
+#[derive(Debug)]
+pub struct Pending {
+    kind: &'static str,
+    body: String,
+}
+
+impl Pending {
+    // commands are only told about when the bot answered them
+    fn wanted(&self, answered: bool) -> bool {
+        self.kind != "command" || answered
+    }
+}
+
+struct Delivery {
+    url: String,
+    kind: &'static str,
+    body: Arc<String>,
+}
+
+#[derive(Clone)]
+struct Sender {
+    client: reqwest::Client,
+    secret: Vec<u8>,
+    retries: u32,
+    backoff: Duration,
+    storage: Storage,
+}
+
+/// Hands the events to the delivery task.
+pub struct Webhooks {
+    urls: HashMap<&'static str, Vec<String>>,
+    deliveries: mpsc::Sender<Delivery>,
+    storage: Storage,
+}
+
+impl Webhooks {
+    /// None without any URL, the task is started on the current runtime.
+    pub fn new(config: &WebhooksConfig, storage: Storage) -> Option<Self> {
+        Self::with_backoff(config, storage, BACKOFF)
+    }
+
+    fn with_backoff(config: &WebhooksConfig, storage: Storage, backoff: Duration) -> Option<Self> {
+        let urls: HashMap<_, _> = config
+            .urls()
+            .into_iter()
+            .filter(|(_, urls)| !urls.is_empty())
+            .map(|(kind, urls)| (kind, urls.to_vec()))
+            .collect();
+        if urls.is_empty() {
+            return None;
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .build()
+            .expect("the client has no settings that can fail");
+        let sender = Sender {
+            client,
+            secret: config.secret.clone().unwrap_or_default().into_bytes(),
+            retries: config.retries,
+            backoff,
+            storage: storage.clone(),
+        };
+        let (deliveries, mut queue) = mpsc::channel::<Delivery>(QUEUE);
+        tokio::spawn(async move {
+            // a slow receiver doesn't hold up the others
+            while let Some(delivery) = queue.recv().await {
+                tokio::spawn(sender.clone().deliver(delivery));
+            }
+        });
+        Some(Self {
+            urls,
+            deliveries,
+            storage,
+        })
+    }
+
+    /// The body for the event if it is sent anywhere, made before the bot takes the event.
+    pub fn prepare(&self, event: &ChatBotEvent, now: SystemTime) -> Option<Pending> {
+        let (kind, channel) = kind(event)?;
+        self.urls.contains_key(kind).then(|| Pending {
+            kind,
+            body: body(kind, channel, event, now).to_string(),
+        })
+    }
+
+    /// Queues the deliveries, `answered` is whether the bot answered the event.
+    pub fn send(&self, pending: Pending, answered: bool) {
+        if !pending.wanted(answered) {
+            return;
+        }
+        let body = Arc::new(pending.body);
+        for url in &self.urls[pending.kind] {
+            let delivery = Delivery {
+                url: url.clone(),
+                kind: pending.kind,
+                body: body.clone(),
+            };
+            if let Err(error) = self.deliveries.try_send(delivery) {
+                let delivery = match error {
+                    mpsc::error::TrySendError::Full(delivery)
+                    | mpsc::error::TrySendError::Closed(delivery) => delivery,
+                };
+                dead_letter(&self.storage, &delivery, "too many deliveries waiting", 0);
+            }
+        }
+    }
+}
+
+fn body(kind: &str, channel: &str, event: &ChatBotEvent, now: SystemTime) -> Value {
+    let sent_at = now
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64);
+    json!({
+        "event": kind,
+        "channel": channel,
+        "sent_at": sent_at,
+        "data": serde_json::to_value(event).expect("the events serialize to JSON"),
+    })
+}
+
+/// The header value for the body, for receivers to check with the same secret.
+pub fn signature(secret: &[u8], body: &str) -> String {
+    format!("sha256={}", hex(&hmac_sha256(secret, body.as_bytes())))
+}
+
+// one JSON object per line, to be sent again by hand
+fn dead_letter(storage: &Storage, delivery: &Delivery, error: &str, attempts: u32) {
+    let body: Value = serde_json::from_str(&delivery.body).unwrap_or(Value::Null);
+    let line = json!({
+        "url": delivery.url,
+        "error": error,
+        "attempts": attempts,
+        "body": body,
+    });
+    tracing::warn!(url = %delivery.url, event = delivery.kind, error, "webhook failed for good");
+    if let Err(error) = storage.append(DEAD_LETTERS, &line.to_string()) {
+        tracing::error!(
+            url = %delivery.url,
+            event = delivery.kind,
+            %error,
+            "could not keep the failed webhook"
+        );
+    }
+}
+
+enum Attempt {
+    Delivered,
+    // a server error or no answer in time
+    Retry(String),
+    Failed(String),
+}
+
+impl Sender {
+    async fn attempt(&self, delivery: &Delivery) -> Attempt {
+        let result = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Chatbot-Event", delivery.kind)
+            .header(
+                "X-Chatbot-Signature",
+                signature(&self.secret, &delivery.body),
+            )
+            .body(delivery.body.to_string())
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => Attempt::Delivered,
+            Ok(response) if response.status().is_server_error() => {
+                Attempt::Retry(format!("status {}", response.status().as_u16()))
+            }
+            Ok(response) => Attempt::Failed(format!("status {}", response.status().as_u16())),
+            Err(error) if error.is_timeout() || error.is_connect() => {
+                Attempt::Retry(error.to_string())
+            }
+            Err(error) => Attempt::Failed(error.to_string()),
+        }
+    }
+
+    async fn deliver(self, delivery: Delivery) {
+        let mut wait = self.backoff;
+        for attempt in 1.. {
+            let error = match self.attempt(&delivery).await {
+                Attempt::Delivered => return,
+                Attempt::Retry(error) if attempt <= self.retries => error,
+                Attempt::Retry(error) | Attempt::Failed(error) => {
+                    dead_letter(&self.storage, &delivery, &error, attempt);
+                    return;
+                }
+            };
+            tracing::debug!(url = %delivery.url, attempt, error, "webhook is tried again");
+            tokio::time::sleep(wait).await;
+            wait *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connect::{UserInfo, UserNotice},
+        http::testing::{receiver, wait},
+    };
+
+    const SECRET: &str = "0123456789abcdef";
+
+    fn webhooks(raid: &str, retries: u32, storage: &Storage) -> Webhooks {
+        let config = WebhooksConfig {
+            secret: Some(SECRET.to_owned()),
+            retries,
+            raid: vec![raid.to_owned()],
+            ..WebhooksConfig::default()
+        };
+        Webhooks::with_backoff(&config, storage.clone(), Duration::from_millis(10)).unwrap()
+    }
+
+    fn raid() -> ChatBotEvent {
+        ChatBotEvent::UserNotice(UserNotice {
+            kind: UserNoticeKind::Raid {
+                from: "Carkhy".to_owned(),
+                viewers: 42,
+            },
+            user: UserInfo::default(),
+            channel: "captaincallback".to_owned(),
+            system_message: "42 raiders from Carkhy have joined!".to_owned(),
+            text: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn events_are_signed_and_sent() {
+        let (url, received) = receiver(vec![204]);
+        let url = format!("{}/hook", url);
+        let storage = Storage::memory();
+        let webhooks = webhooks(&url, 3, &storage);
+        let now = UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let pending = webhooks.prepare(&raid(), now).unwrap();
+        webhooks.send(pending, false);
+        let hook = tokio::task::spawn_blocking(move || wait(&received))
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_str(&hook.body).unwrap();
+        assert_eq!(hook.path, "/hook");
+        assert_eq!(body["event"], "raid");
+        assert_eq!(body["channel"], "captaincallback");
+        assert_eq!(body["sent_at"], 1_800_000_000_000u64);
+        assert_eq!(body["data"], serde_json::to_value(raid()).unwrap());
+        assert_eq!(hook.headers["x-chatbot-event"], "raid");
+        // what a receiver checks
+        let expected = format!(
+            "sha256={}",
+            hex(&hmac_sha256(SECRET.as_bytes(), hook.body.as_bytes()))
+        );
+        assert_eq!(hook.headers["x-chatbot-signature"], expected);
+        assert_ne!(
+            signature(b"another secret!!", &hook.body),
+            hook.headers["x-chatbot-signature"]
+        );
+        // no URLs for follows
+        let follow = ChatBotEvent::Follow {
+            channel: "captaincallback".to_owned(),
+            login: "carkhy".to_owned(),
+            name: "Carkhy".to_owned(),
+        };
+        assert!(webhooks.prepare(&follow, now).is_none());
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_tried_again() {
+        let (url, received) = receiver(vec![503, 502, 200]);
+        let url = format!("{}/hook", url);
+        let storage = Storage::memory();
+        let webhooks = webhooks(&url, 3, &storage);
+        let pending = webhooks.prepare(&raid(), SystemTime::now()).unwrap();
+        webhooks.send(pending, false);
+        let bodies = tokio::task::spawn_blocking(move || {
+            (0..3).map(|_| wait(&received).body).collect::<Vec<_>>()
+        })
+        .await
+        .unwrap();
+        assert!(bodies.iter().all(|body| body == &bodies[0]));
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_kept() {
+        let (url, received) = receiver(vec![500, 500]);
+        let url = format!("{}/hook", url);
+        let storage = Storage::memory();
+        let webhooks = webhooks(&url, 1, &storage);
+        let now = UNIX_EPOCH;
+        let pending = webhooks.prepare(&raid(), now).unwrap();
+        webhooks.send(pending, false);
+        tokio::task::spawn_blocking(move || {
+            wait(&received);
+            wait(&received);
+        })
+        .await
+        .unwrap();
+        let line = json!({
+            "url": url,
+            "error": "status 500",
+            "attempts": 2,
+            "body": body("raid", "captaincallback", &raid(), now),
+        });
+        let mut kept = false;
+        for _ in 0..100 {
+            kept = storage.has_line(DEAD_LETTERS, &line.to_string()).unwrap();
+            if kept {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(kept);
+        // a command nobody answered isn't sent
+        let pending = Pending {
+            kind: "command",
+            body: String::new(),
+        };
+        assert!(!pending.wanted(false));
+    }
+}